@@ -0,0 +1,2 @@
+-- Events stored before the schema was versioned are treated as version 0
+ALTER TABLE events ADD COLUMN version integer not null default 0;
@@ -0,0 +1,12 @@
+-- Events that cannot be loaded as part of their CFD, moved out of the way by the integrity check
+create table if not exists quarantined_events
+(
+    id             integer primary key,
+    cfd_id         integer not null,
+    name           text not null,
+    data           text not null,
+    created_at     text not null,
+    version        integer not null,
+    reason         text not null,
+    quarantined_at text not null
+);
@@ -0,0 +1,7 @@
+-- CFDs of third parties watched by the maker, see `watch_only`
+create table if not exists watched_cfds
+(
+    id   integer primary key,
+    uuid text unique not null,
+    dlc  text not null
+);
@@ -0,0 +1,15 @@
+-- CFDs with events that cannot be loaded, moved out of the way by the integrity check together with all of their events
+create table if not exists quarantined_cfds
+(
+    id                              integer primary key,
+    uuid                            text not null,
+    position                        text not null,
+    initial_price                   text not null,
+    leverage                        integer not null,
+    settlement_time_interval_hours  integer not null,
+    quantity_usd                    text not null,
+    counterparty_network_identity   text not null,
+    role                            text not null,
+    reason                          text not null,
+    quarantined_at                  text not null
+);
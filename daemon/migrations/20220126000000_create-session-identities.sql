@@ -0,0 +1,7 @@
+-- The identity the taker presented to the maker when taking the order of a CFD, see `seed::IdentityPolicy`
+create table if not exists session_identities
+(
+    id       integer primary key,
+    uuid     text unique not null,
+    identity text not null
+);
@@ -44,6 +44,30 @@ where
         self.inner.retain(|_, candidate| stopping.me != *candidate);
     }
 
+    /// The keys of all actors that are still running.
+    pub fn connected_keys(&self) -> impl Iterator<Item = &K> {
+        self.inner
+            .iter()
+            .filter(|(_, addr)| addr.is_connected())
+            .map(|(key, _)| key)
+    }
+
+    /// Ensure another actor can be started without exceeding `limit` running actors.
+    pub fn ensure_capacity(&self, limit: usize) -> Result<(), AtCapacity> {
+        let running = self.connected_keys().count();
+
+        if running >= limit {
+            return Err(AtCapacity { running, limit });
+        }
+
+        Ok(())
+    }
+
+    /// Remove the actor stored with the given key, returning its address if it is still running.
+    pub fn remove_connected(&mut self, key: &K) -> Option<Address<A>> {
+        self.inner.remove(key).filter(|addr| addr.is_connected())
+    }
+
     pub fn insert(&mut self, key: K, address: Address<A>) {
         self.inner.insert(key, address);
     }
@@ -113,6 +137,13 @@ where
 #[error("The address is still connected")]
 pub struct StillConnected;
 
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("{running} protocols are already running, at most {limit} can run at the same time")]
+pub struct AtCapacity {
+    pub running: usize,
+    pub limit: usize,
+}
+
 pub struct Disconnected<'a, K, A> {
     entry: Entry<'a, K, xtra::Address<A>>,
 }
@@ -153,6 +184,56 @@ mod tests {
         assert!(map.inner.get("foo").is_some());
     }
 
+    #[test]
+    fn remove_connected_removes_address() {
+        let (addr_1, _ctx_1) = Context::<Dummy>::new(None);
+        let (addr_2, _ctx_2) = Context::<Dummy>::new(None);
+        let mut map = AddressMap::default();
+        map.insert("foo", addr_1);
+        map.insert("bar", addr_2.clone());
+
+        let removed = map.remove_connected(&"bar");
+
+        assert!(removed == Some(addr_2));
+        assert_eq!(map.connected_keys().collect::<Vec<_>>(), vec![&"foo"]);
+        assert!(map.remove_connected(&"bar").is_none());
+    }
+
+    #[test]
+    fn actors_beyond_limit_are_rejected_while_earlier_ones_run() {
+        let (addr_1, _ctx_1) = Context::<Dummy>::new(None);
+        let (addr_2, _ctx_2) = Context::<Dummy>::new(None);
+        let mut map = AddressMap::default();
+
+        map.ensure_capacity(2).unwrap();
+        map.insert("foo", addr_1);
+        map.ensure_capacity(2).unwrap();
+        map.insert("bar", addr_2.clone());
+
+        assert_eq!(
+            map.ensure_capacity(2),
+            Err(AtCapacity {
+                running: 2,
+                limit: 2
+            })
+        );
+
+        map.gc(Stopping { me: addr_2 });
+
+        assert!(map.ensure_capacity(2).is_ok());
+    }
+
+    #[test]
+    fn stopped_actors_do_not_count_towards_limit() {
+        let (addr, ctx) = Context::<Dummy>::new(None);
+        let mut map = AddressMap::default();
+        map.insert("foo", addr);
+
+        drop(ctx);
+
+        assert!(map.ensure_capacity(1).is_ok());
+    }
+
     struct Dummy;
 
     impl xtra::Actor for Dummy {}
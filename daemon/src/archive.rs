@@ -0,0 +1,742 @@
+//! Portable archive of the state of a daemon, used to move it to another host.
+//!
+//! Instead of copying the database and wallet files, which are tied to the versions of sqlite and
+//! bdk in use, the CFDs and their events as well as the watched CFDs of third parties are exported
+//! as stored together with the wallet's address index into a single, versioned JSON document. CFDs and events quarantined by the
+//! [integrity check](crate::integrity_check) are exported as well, so that they can still be
+//! inspected on the new host. The seed is not part of the archive and has to be copied separately.
+
+use crate::db;
+use crate::model::cfd::OrderId;
+use crate::wallet;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::Network;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::pool::PoolConnection;
+use sqlx::Connection;
+use sqlx::Row;
+use sqlx::Sqlite;
+use std::path::Path;
+
+/// The version of the archive format, bumped whenever its structure changes.
+pub const VERSION: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    pub network: Network,
+    /// The index of the last address handed out by the wallet.
+    pub last_address_index: Option<u32>,
+    pub cfds: Vec<ArchivedCfd>,
+    pub watched_cfds: Vec<WatchedCfd>,
+    pub quarantined_cfds: Vec<QuarantinedCfd>,
+    pub quarantined_events: Vec<QuarantinedEvent>,
+}
+
+/// A row of the `cfds` table together with its events, as stored.
+///
+/// The values are kept in their database encoding, events are upcast by the importing daemon like
+/// the ones it stored itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedCfd {
+    pub id: OrderId,
+    pub position: String,
+    pub initial_price: String,
+    pub leverage: i64,
+    pub settlement_time_interval_hours: i64,
+    pub quantity_usd: String,
+    pub counterparty_network_identity: String,
+    pub role: String,
+    /// The identity the taker presented when taking the order, see
+    /// [`IdentityPolicy`](crate::seed::IdentityPolicy).
+    pub session_identity: Option<String>,
+    pub events: Vec<ArchivedEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedEvent {
+    pub name: String,
+    pub data: String,
+    pub created_at: String,
+    pub version: i64,
+}
+
+/// A row of the `watched_cfds` table, as stored.
+///
+/// The DLC is kept in its database encoding, see [`crate::watch_only`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchedCfd {
+    pub id: OrderId,
+    pub dlc: String,
+}
+
+/// A row of the `quarantined_cfds` table, as stored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantinedCfd {
+    pub id: i64,
+    pub uuid: String,
+    pub position: String,
+    pub initial_price: String,
+    pub leverage: i64,
+    pub settlement_time_interval_hours: i64,
+    pub quantity_usd: String,
+    pub counterparty_network_identity: String,
+    pub role: String,
+    pub reason: String,
+    pub quarantined_at: String,
+}
+
+/// A row of the `quarantined_events` table, as stored.
+///
+/// The `cfd_id` refers to the `id` of a [`QuarantinedCfd`] or, for an orphaned event, to no CFD
+/// at all.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantinedEvent {
+    pub id: i64,
+    pub cfd_id: i64,
+    pub name: String,
+    pub data: String,
+    pub created_at: String,
+    pub version: i64,
+    pub reason: String,
+    pub quarantined_at: String,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum CannotImport {
+    #[error("Archive has version {found} but only version {supported} is supported")]
+    IncompatibleVersion { found: u32, supported: u32 },
+    #[error("Archive was exported on {found} but we are running on {expected}")]
+    WrongNetwork { found: Network, expected: Network },
+    #[error("Database table {table} already contains {rows} rows, refusing to import into it")]
+    NotEmpty { table: &'static str, rows: usize },
+}
+
+/// The tables [`Archive::import`] writes to, all of which have to be empty.
+const IMPORTED_TABLES: [&str; 6] = [
+    "cfds",
+    "events",
+    "session_identities",
+    "watched_cfds",
+    "quarantined_cfds",
+    "quarantined_events",
+];
+
+impl Archive {
+    /// Export all CFDs, including the quarantined and the watched ones, from the database and the
+    /// address index of the wallet.
+    pub async fn export(
+        network: Network,
+        address_index_path: &Path,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> Result<Self> {
+        let rows = sqlx::query(
+            r#"
+            select
+                id,
+                uuid,
+                position,
+                initial_price,
+                leverage,
+                settlement_time_interval_hours,
+                quantity_usd,
+                counterparty_network_identity,
+                role,
+                (select identity from session_identities where session_identities.uuid = cfds.uuid) as session_identity
+            from
+                cfds
+            order by
+                id
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut cfds = Vec::with_capacity(rows.len());
+        for row in rows {
+            let events = sqlx::query(
+                r#"
+                select
+                    name,
+                    data,
+                    created_at,
+                    version
+                from
+                    events
+                where
+                    cfd_id = $1
+                order by
+                    id
+                "#,
+            )
+            .bind(row.try_get::<i64, _>("id")?)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|event| {
+                Ok(ArchivedEvent {
+                    name: event.try_get("name")?,
+                    data: event.try_get("data")?,
+                    created_at: event.try_get("created_at")?,
+                    version: event.try_get("version")?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+            cfds.push(ArchivedCfd {
+                id: row.try_get("uuid")?,
+                position: row.try_get("position")?,
+                initial_price: row.try_get("initial_price")?,
+                leverage: row.try_get("leverage")?,
+                settlement_time_interval_hours: row.try_get("settlement_time_interval_hours")?,
+                quantity_usd: row.try_get("quantity_usd")?,
+                counterparty_network_identity: row.try_get("counterparty_network_identity")?,
+                role: row.try_get("role")?,
+                session_identity: row.try_get("session_identity")?,
+                events,
+            });
+        }
+
+        let watched_cfds = sqlx::query(
+            r#"
+            select
+                uuid,
+                dlc
+            from
+                watched_cfds
+            order by
+                id
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok(WatchedCfd {
+                id: row.try_get("uuid")?,
+                dlc: row.try_get("dlc")?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        let quarantined_cfds = sqlx::query(
+            r#"
+            select
+                id,
+                uuid,
+                position,
+                initial_price,
+                leverage,
+                settlement_time_interval_hours,
+                quantity_usd,
+                counterparty_network_identity,
+                role,
+                reason,
+                quarantined_at
+            from
+                quarantined_cfds
+            order by
+                id
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok(QuarantinedCfd {
+                id: row.try_get("id")?,
+                uuid: row.try_get("uuid")?,
+                position: row.try_get("position")?,
+                initial_price: row.try_get("initial_price")?,
+                leverage: row.try_get("leverage")?,
+                settlement_time_interval_hours: row.try_get("settlement_time_interval_hours")?,
+                quantity_usd: row.try_get("quantity_usd")?,
+                counterparty_network_identity: row.try_get("counterparty_network_identity")?,
+                role: row.try_get("role")?,
+                reason: row.try_get("reason")?,
+                quarantined_at: row.try_get("quarantined_at")?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        let quarantined_events = sqlx::query(
+            r#"
+            select
+                id,
+                cfd_id,
+                name,
+                data,
+                created_at,
+                version,
+                reason,
+                quarantined_at
+            from
+                quarantined_events
+            order by
+                id
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok(QuarantinedEvent {
+                id: row.try_get("id")?,
+                cfd_id: row.try_get("cfd_id")?,
+                name: row.try_get("name")?,
+                data: row.try_get("data")?,
+                created_at: row.try_get("created_at")?,
+                version: row.try_get("version")?,
+                reason: row.try_get("reason")?,
+                quarantined_at: row.try_get("quarantined_at")?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            version: VERSION,
+            network,
+            last_address_index: wallet::load_address_index(address_index_path)?,
+            cfds,
+            watched_cfds,
+            quarantined_cfds,
+            quarantined_events,
+        })
+    }
+
+    /// Import the archive into an empty database and restore the address index of the wallet.
+    ///
+    /// Archives of another version or network are refused. The address index is only written once
+    /// the CFDs are imported, a failed import leaves neither the database nor the address index
+    /// behind.
+    pub async fn import(
+        &self,
+        network: Network,
+        address_index_path: &Path,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> Result<()> {
+        if self.version != VERSION {
+            return Err(CannotImport::IncompatibleVersion {
+                found: self.version,
+                supported: VERSION,
+            }
+            .into());
+        }
+        if self.network != network {
+            return Err(CannotImport::WrongNetwork {
+                found: self.network,
+                expected: network,
+            }
+            .into());
+        }
+
+        for table in IMPORTED_TABLES {
+            let rows = sqlx::query(&format!("select count(*) as count from {}", table))
+                .fetch_one(&mut *conn)
+                .await?
+                .try_get::<i64, _>("count")?;
+            if rows > 0 {
+                return Err(CannotImport::NotEmpty {
+                    table,
+                    rows: rows as usize,
+                }
+                .into());
+            }
+        }
+
+        if self.last_address_index.is_some() {
+            anyhow::ensure!(
+                !address_index_path.exists(),
+                "Refusing to overwrite address index at {}",
+                address_index_path.display()
+            );
+        }
+
+        let mut tx = conn.begin().await?;
+
+        for cfd in &self.cfds {
+            let cfd_id = sqlx::query(
+                r#"
+                insert into cfds (
+                    uuid,
+                    position,
+                    initial_price,
+                    leverage,
+                    settlement_time_interval_hours,
+                    quantity_usd,
+                    counterparty_network_identity,
+                    role
+                ) values ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+            )
+            .bind(&cfd.id)
+            .bind(&cfd.position)
+            .bind(&cfd.initial_price)
+            .bind(cfd.leverage)
+            .bind(cfd.settlement_time_interval_hours)
+            .bind(&cfd.quantity_usd)
+            .bind(&cfd.counterparty_network_identity)
+            .bind(&cfd.role)
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("Failed to import CFD {}", cfd.id))?
+            .last_insert_rowid();
+
+            for event in &cfd.events {
+                sqlx::query(
+                    r#"
+                    insert into events (
+                        cfd_id,
+                        name,
+                        data,
+                        created_at,
+                        version
+                    ) values ($1, $2, $3, $4, $5)"#,
+                )
+                .bind(cfd_id)
+                .bind(&event.name)
+                .bind(&event.data)
+                .bind(&event.created_at)
+                .bind(event.version)
+                .execute(&mut tx)
+                .await
+                .with_context(|| format!("Failed to import event of CFD {}", cfd.id))?;
+            }
+
+            if let Some(identity) = &cfd.session_identity {
+                sqlx::query("insert into session_identities (uuid, identity) values ($1, $2)")
+                    .bind(&cfd.id)
+                    .bind(identity)
+                    .execute(&mut tx)
+                    .await
+                    .with_context(|| {
+                        format!("Failed to import session identity of CFD {}", cfd.id)
+                    })?;
+            }
+        }
+
+        for cfd in &self.watched_cfds {
+            sqlx::query("insert into watched_cfds (uuid, dlc) values ($1, $2)")
+                .bind(&cfd.id)
+                .bind(&cfd.dlc)
+                .execute(&mut tx)
+                .await
+                .with_context(|| format!("Failed to import watched CFD {}", cfd.id))?;
+        }
+
+        for cfd in &self.quarantined_cfds {
+            sqlx::query(
+                r#"
+                insert into quarantined_cfds (
+                    id,
+                    uuid,
+                    position,
+                    initial_price,
+                    leverage,
+                    settlement_time_interval_hours,
+                    quantity_usd,
+                    counterparty_network_identity,
+                    role,
+                    reason,
+                    quarantined_at
+                ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"#,
+            )
+            .bind(cfd.id)
+            .bind(&cfd.uuid)
+            .bind(&cfd.position)
+            .bind(&cfd.initial_price)
+            .bind(cfd.leverage)
+            .bind(cfd.settlement_time_interval_hours)
+            .bind(&cfd.quantity_usd)
+            .bind(&cfd.counterparty_network_identity)
+            .bind(&cfd.role)
+            .bind(&cfd.reason)
+            .bind(&cfd.quarantined_at)
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("Failed to import quarantined CFD {}", cfd.uuid))?;
+        }
+
+        for event in &self.quarantined_events {
+            sqlx::query(
+                r#"
+                insert into quarantined_events (
+                    id,
+                    cfd_id,
+                    name,
+                    data,
+                    created_at,
+                    version,
+                    reason,
+                    quarantined_at
+                ) values ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+            )
+            .bind(event.id)
+            .bind(event.cfd_id)
+            .bind(&event.name)
+            .bind(&event.data)
+            .bind(&event.created_at)
+            .bind(event.version)
+            .bind(&event.reason)
+            .bind(&event.quarantined_at)
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("Failed to import quarantined event {}", event.id))?;
+        }
+
+        tx.commit().await?;
+
+        if let Some(last_index) = self.last_address_index {
+            wallet::store_address_index(address_index_path, last_index)?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn read_from(path: &Path) -> Result<Self> {
+        let bytes = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Failed to read archive {}", path.display()))?;
+
+        serde_json::from_slice(&bytes).context("Failed to parse archive")
+    }
+
+    pub async fn write_to(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            anyhow::bail!("Refusing to overwrite file at {}", path.display())
+        }
+
+        tokio::fs::write(path, serde_json::to_vec_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write archive {}", path.display()))?;
+
+        Ok(())
+    }
+}
+
+/// Export the state of the daemon with the database at `db_file` into a new archive at `path`.
+pub async fn export_to_file(
+    path: &Path,
+    network: Network,
+    db_file: &Path,
+    address_index_path: &Path,
+) -> Result<()> {
+    let db = db::connect(db_file, 1, db::DEFAULT_BUSY_TIMEOUT).await?;
+    db::run_migrations(&db)
+        .await
+        .context("Db migrations failed")?;
+
+    let archive = Archive::export(network, address_index_path, &mut db.acquire().await?).await?;
+    archive.write_to(path).await?;
+
+    tracing::info!("Exported {} CFDs to {}", archive.cfds.len(), path.display());
+
+    Ok(())
+}
+
+/// Import the archive at `path` into the daemon with the database at `db_file`.
+pub async fn import_from_file(
+    path: &Path,
+    network: Network,
+    db_file: &Path,
+    address_index_path: &Path,
+) -> Result<()> {
+    let archive = Archive::read_from(path).await?;
+
+    let db = db::connect(db_file, 1, db::DEFAULT_BUSY_TIMEOUT).await?;
+    db::run_migrations(&db)
+        .await
+        .context("Db migrations failed")?;
+
+    archive
+        .import(network, address_index_path, &mut db.acquire().await?)
+        .await?;
+
+    tracing::info!(
+        "Imported {} CFDs from {}",
+        archive.cfds.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tests::setup_test_db;
+    use crate::model::cfd::Cfd;
+    use crate::model::cfd::CfdEvent;
+    use crate::model::cfd::Event;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn exported_state_is_imported_unchanged() {
+        let mut source = setup_test_db().await;
+        let cfd = Cfd::dummy().insert(&mut source).await;
+        for event in [CfdEvent::OfferRejected, CfdEvent::RevokeConfirmed] {
+            db::append_event(Event::new(cfd.id(), event), &mut source)
+                .await
+                .unwrap();
+        }
+        db::insert_session_identity(
+            cfd.id(),
+            "EEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEEE"
+                .parse()
+                .unwrap(),
+            &mut source,
+        )
+        .await
+        .unwrap();
+        let quarantined = Cfd::dummy().insert(&mut source).await;
+        db::append_event(
+            Event::new(quarantined.id(), CfdEvent::OfferRejected),
+            &mut source,
+        )
+        .await
+        .unwrap();
+        db::quarantine_cfd(quarantined.id(), "corrupt", &mut source)
+            .await
+            .unwrap();
+        // The DLC is exported as stored, it does not have to be valid
+        sqlx::query("insert into watched_cfds (uuid, dlc) values ($1, $2)")
+            .bind(&OrderId::default())
+            .bind("{}")
+            .execute(&mut *source)
+            .await
+            .unwrap();
+        let source_index = temp_path("source-address-index");
+        wallet::store_address_index(&source_index, 7).unwrap();
+
+        let archive = Archive::export(Network::Testnet, &source_index, &mut source)
+            .await
+            .unwrap();
+        let archive =
+            serde_json::from_slice::<Archive>(&serde_json::to_vec(&archive).unwrap()).unwrap();
+
+        let mut target = setup_test_db().await;
+        let target_index = temp_path("target-address-index");
+        archive
+            .import(Network::Testnet, &target_index, &mut target)
+            .await
+            .unwrap();
+
+        let reexported = Archive::export(Network::Testnet, &target_index, &mut target)
+            .await
+            .unwrap();
+        assert_eq!(reexported, archive);
+        assert_eq!(reexported.last_address_index, Some(7));
+        assert!(reexported.cfds[0].session_identity.is_some());
+        assert_eq!(reexported.watched_cfds.len(), 1);
+        assert_eq!(reexported.quarantined_cfds.len(), 1);
+        assert_eq!(reexported.quarantined_events.len(), 1);
+
+        let (_, source_events) = db::load_cfd(cfd.id(), &mut source).await.unwrap();
+        let (_, target_events) = db::load_cfd(cfd.id(), &mut target).await.unwrap();
+        assert_eq!(target_events, source_events);
+
+        for path in [source_index, target_index] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[tokio::test]
+    async fn archive_of_other_version_is_refused() {
+        let mut conn = setup_test_db().await;
+        let archive = Archive {
+            version: VERSION + 1,
+            network: Network::Testnet,
+            last_address_index: None,
+            cfds: Vec::new(),
+            watched_cfds: Vec::new(),
+            quarantined_cfds: Vec::new(),
+            quarantined_events: Vec::new(),
+        };
+
+        let error = archive
+            .import(Network::Testnet, &temp_path("address-index"), &mut conn)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<CannotImport>(),
+            Some(&CannotImport::IncompatibleVersion {
+                found: VERSION + 1,
+                supported: VERSION
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_import_does_not_write_address_index() {
+        let mut source = setup_test_db().await;
+        Cfd::dummy().insert(&mut source).await;
+        let mut archive =
+            Archive::export(Network::Testnet, &temp_path("address-index"), &mut source)
+                .await
+                .unwrap();
+        archive.last_address_index = Some(7);
+        archive.cfds.push(archive.cfds[0].clone());
+
+        let mut target = setup_test_db().await;
+        let target_index = temp_path("target-address-index");
+        let result = archive
+            .import(Network::Testnet, &target_index, &mut target)
+            .await;
+
+        assert!(result.is_err());
+        assert!(!target_index.exists());
+    }
+
+    #[tokio::test]
+    async fn archive_is_not_imported_into_database_with_cfds() {
+        let mut conn = setup_test_db().await;
+        Cfd::dummy().insert(&mut conn).await;
+        let archive = Archive::export(Network::Testnet, &temp_path("address-index"), &mut conn)
+            .await
+            .unwrap();
+
+        let error = archive
+            .import(Network::Testnet, &temp_path("address-index"), &mut conn)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<CannotImport>(),
+            Some(&CannotImport::NotEmpty {
+                table: "cfds",
+                rows: 1
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn archive_is_not_imported_into_database_with_quarantined_cfds() {
+        let mut conn = setup_test_db().await;
+        let archive = Archive::export(Network::Testnet, &temp_path("address-index"), &mut conn)
+            .await
+            .unwrap();
+        let cfd = Cfd::dummy().insert(&mut conn).await;
+        db::quarantine_cfd(cfd.id(), "corrupt", &mut conn)
+            .await
+            .unwrap();
+
+        let error = archive
+            .import(Network::Testnet, &temp_path("address-index"), &mut conn)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<CannotImport>(),
+            Some(&CannotImport::NotEmpty {
+                table: "quarantined_cfds",
+                rows: 1
+            })
+        );
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}-{}", name, OrderId::default()))
+    }
+}
@@ -10,10 +10,23 @@ use rocket_basicauth::BasicAuth;
 use rocket_basicauth::BasicAuthError;
 use std::fmt;
 use std::str::FromStr;
+use subtle::ConstantTimeEq;
 
 /// A request guard that can be included in handler definitions to enforce authentication.
 pub struct Authenticated {}
 
+/// A request guard that enforces bearer token authentication if an [`ApiToken`] is managed by
+/// Rocket.
+///
+/// Requests are let through unconditionally if no [`ApiToken`] was configured.
+pub struct TokenAuthenticated {}
+
+/// A request guard that enforces bearer token authentication and rejects requests with `403
+/// Forbidden` if no [`ApiToken`] is managed by Rocket.
+///
+/// Meant for routes that must never be open, like exporting the seed.
+pub struct TokenRequired {}
+
 pub const MAKER_USERNAME: &str = "maker";
 
 #[derive(Debug)]
@@ -25,6 +38,10 @@ pub enum Error {
     /// The auth password was not configured in Rocket's state.
     MissingPassword,
     NoAuthHeader,
+    BadAuthHeader,
+    BadToken,
+    /// The route requires an API token, but none was configured.
+    NoApiToken,
 }
 
 #[derive(PartialEq)]
@@ -53,6 +70,22 @@ impl FromStr for Password {
     }
 }
 
+/// The token clients have to present as `Authorization: Bearer <token>`.
+#[derive(Clone, PartialEq)]
+pub struct ApiToken(String);
+
+impl FromStr for ApiToken {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            anyhow::bail!("API token must not be empty");
+        }
+
+        Ok(Self(s.to_owned()))
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for Authenticated {
     type Error = Error;
@@ -88,3 +121,47 @@ impl<'r> FromRequest<'r> for Authenticated {
         Outcome::Success(Authenticated {})
     }
 }
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TokenAuthenticated {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let api_token = match req.rocket().state::<ApiToken>() {
+            Some(api_token) => api_token,
+            None => return Outcome::Success(TokenAuthenticated {}),
+        };
+
+        let header = match req.headers().get_one("Authorization") {
+            Some(header) => header,
+            None => return Outcome::Failure((Status::Unauthorized, Error::NoAuthHeader)),
+        };
+
+        let token = match header.strip_prefix("Bearer ") {
+            Some(token) => token,
+            None => return Outcome::Failure((Status::Unauthorized, Error::BadAuthHeader)),
+        };
+
+        // Compare in constant time to not leak how much of the token was guessed correctly
+        if !bool::from(token.as_bytes().ct_eq(api_token.0.as_bytes())) {
+            return Outcome::Failure((Status::Unauthorized, Error::BadToken));
+        }
+
+        Outcome::Success(TokenAuthenticated {})
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TokenRequired {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if req.rocket().state::<ApiToken>().is_none() {
+            return Outcome::Failure((Status::Forbidden, Error::NoApiToken));
+        }
+
+        try_outcome!(req.guard::<TokenAuthenticated>().await);
+
+        Outcome::Success(TokenRequired {})
+    }
+}
@@ -0,0 +1,197 @@
+use crate::cfd_actors::load_cfd;
+use crate::clock::Clock;
+use crate::db;
+use crate::model::cfd::OrderId;
+use crate::model::Price;
+use crate::process_manager;
+use crate::taker_cfd;
+use crate::try_continue;
+use crate::Tasks;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use xtra::message_channel::MessageChannel;
+use xtra::Address;
+use xtra_productivity::xtra_productivity;
+
+/// How often CFDs are checked for a delayed commit being due.
+const AUTO_COMMIT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// When to publish the commit transaction after the oracle attested prior to the CET timelock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommitPolicy {
+    /// Publish the commit transaction as soon as the oracle attested.
+    Immediate,
+    /// Give the counterparty the given time to publish the commit transaction, saving us the
+    /// fees, before publishing it ourselves.
+    ///
+    /// The taker proposes to settle collaboratively at the attested price in the meantime, which
+    /// saves the fees of both the commit transaction and the CET. This only works if the maker
+    /// delays its commit transaction as well, a maker committing immediately closes the CFD through
+    /// the CET before the settlement can complete.
+    ///
+    /// A completed settlement postpones the commit transaction until the settlement transaction
+    /// confirms or [`crate::model::cfd::UNCONFIRMED_SETTLEMENT_TIMEOUT`] passed.
+    Delayed(time::Duration),
+}
+
+impl CommitPolicy {
+    /// Without a positive delay the commit transaction is published immediately.
+    pub fn from_delay(delay: Option<time::Duration>) -> Self {
+        match delay {
+            Some(delay) if delay.is_positive() => Self::Delayed(delay),
+            _ => Self::Immediate,
+        }
+    }
+
+    pub fn publishes_commit_on_attestation(&self) -> bool {
+        matches!(self, Self::Immediate)
+    }
+}
+
+/// Publishes commit transactions whose delay according to [`CommitPolicy::Delayed`] passed.
+pub struct Actor {
+    db: sqlx::SqlitePool,
+    process_manager: Address<process_manager::Actor>,
+    /// Only set if a settlement is to be proposed while the commit transaction is delayed.
+    propose_settlement: Option<Box<dyn MessageChannel<taker_cfd::ProposeSettlement>>>,
+    /// The CFDs we proposed to settle, each one is only proposed once.
+    settlement_proposed: HashSet<OrderId>,
+    delay: time::Duration,
+    clock: Arc<dyn Clock>,
+    tasks: Tasks,
+}
+
+impl Actor {
+    pub fn new(
+        db: sqlx::SqlitePool,
+        process_manager: Address<process_manager::Actor>,
+        propose_settlement: Option<Box<dyn MessageChannel<taker_cfd::ProposeSettlement>>>,
+        delay: time::Duration,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            db,
+            process_manager,
+            propose_settlement,
+            settlement_proposed: HashSet::new(),
+            delay,
+            clock,
+            tasks: Tasks::default(),
+        }
+    }
+
+    /// Propose to settle at the attested `price`, unless we did so already.
+    async fn propose_settlement(
+        &mut self,
+        order_id: OrderId,
+        price: Price,
+    ) -> Result<(), xtra::Disconnected> {
+        let propose_settlement = match self.propose_settlement.as_ref() {
+            Some(propose_settlement) => propose_settlement,
+            None => return Ok(()),
+        };
+
+        if !self.settlement_proposed.insert(order_id) {
+            return Ok(());
+        }
+
+        tracing::info!(%order_id, %price, "Oracle attested, proposing settlement before committing");
+
+        if let Err(e) = propose_settlement
+            .send(taker_cfd::ProposeSettlement {
+                order_id,
+                current_price: price,
+            })
+            .await?
+        {
+            tracing::warn!(%order_id, "Failed to propose settlement before committing: {:#}", e)
+        }
+
+        Ok(())
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _msg: AutoCommit, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        tracing::trace!("Checking all CFDs for delayed commits");
+
+        let mut conn = self.db.acquire().await?;
+        let now = self.clock.timestamp();
+
+        for id in db::load_all_cfd_ids(&mut conn).await? {
+            let cfd = try_continue!(load_cfd(id, &mut conn).await);
+
+            if cfd.is_monitoring_paused() {
+                continue;
+            }
+
+            if let Some(price) = cfd.settle_before_commit(self.delay, now) {
+                if let Err(e) = self.propose_settlement(id, price).await {
+                    tracing::error!(order_id = %id, "Cannot propose settlement, stopping auto commit: {:#}", e);
+                    ctx.stop();
+                    return Ok(());
+                }
+
+                continue;
+            }
+
+            let event = match try_continue!(cfd.commit_after_attestation(self.delay, now)) {
+                Some(event) => event,
+                None => continue,
+            };
+
+            tracing::info!(order_id = %id, "Counterparty did not commit in time, publishing commit transaction");
+
+            if let Err(e) = self
+                .process_manager
+                .send(process_manager::Event::new(event))
+                .await?
+            {
+                tracing::error!("Sending event to process manager failed: {:#}", e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let fut = ctx
+            .notify_interval(AUTO_COMMIT_INTERVAL, || AutoCommit)
+            .expect("we are alive");
+
+        self.tasks.add(fut);
+    }
+}
+
+/// Message to trigger publishing delayed commits on a regular interval
+pub struct AutoCommit;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_delay_commits_immediately() {
+        assert_eq!(CommitPolicy::from_delay(None), CommitPolicy::Immediate);
+        assert_eq!(
+            CommitPolicy::from_delay(Some(time::Duration::ZERO)),
+            CommitPolicy::Immediate
+        );
+        assert!(CommitPolicy::Immediate.publishes_commit_on_attestation());
+    }
+
+    #[test]
+    fn delayed_policy_leaves_commit_to_actor() {
+        let policy = CommitPolicy::from_delay(Some(time::Duration::hours(1)));
+
+        assert_eq!(policy, CommitPolicy::Delayed(time::Duration::hours(1)));
+        assert!(!policy.publishes_commit_on_attestation());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::cfd_actors::load_cfd;
+use crate::clock::Clock;
+use crate::db;
+use crate::model::cfd::MissedAttestation;
+use crate::model::cfd::OrderId;
+use crate::model::cfd::Role;
+use crate::model::Price;
+use crate::process_manager;
+use crate::taker_cfd;
+use crate::try_continue;
+use crate::Tasks;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use xtra::message_channel::MessageChannel;
+use xtra_productivity::xtra_productivity;
+
+/// How often CFDs are checked for a missed attestation.
+const AUTO_REFUND_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long after the expiry of a CFD the oracle has to attest before the CFD is closed without
+/// an attestation.
+const ATTESTATION_GRACE_PERIOD: time::Duration = time::Duration::hours(1);
+
+/// How long the counterparty has to accept a settlement proposal before we fall back to the
+/// refund.
+///
+/// The maker waits this long on top of the [`ATTESTATION_GRACE_PERIOD`] so that it does not
+/// commit while the taker's settlement proposal is pending.
+const SETTLEMENT_GRACE_PERIOD: time::Duration = time::Duration::hours(1);
+
+/// What to do about CFDs the oracle did not attest to by their expiry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefundStrategy {
+    /// Leave it to the user to commit and refund the CFD.
+    Manual,
+    /// Publish the commit transaction and the refund transaction once the refund timelock
+    /// expired.
+    Refund,
+    /// Propose to settle collaboratively at the initial price first, falling back to the refund if
+    /// the counterparty does not accept.
+    ///
+    /// Only the taker can propose a settlement, the maker refunds right away.
+    SettleThenRefund,
+}
+
+impl RefundStrategy {
+    /// The strategy used by each role unless configured otherwise.
+    pub fn default_for(role: Role) -> Self {
+        match role {
+            Role::Maker => RefundStrategy::Refund,
+            Role::Taker => RefundStrategy::SettleThenRefund,
+        }
+    }
+}
+
+impl FromStr for RefundStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "manual" => Ok(RefundStrategy::Manual),
+            "refund" => Ok(RefundStrategy::Refund),
+            "settle-then-refund" => Ok(RefundStrategy::SettleThenRefund),
+            other => anyhow::bail!(
+                "Unknown refund strategy {}, expected manual, refund or settle-then-refund",
+                other
+            ),
+        }
+    }
+}
+
+/// Closes CFDs the oracle did not attest to according to [`RefundStrategy`].
+pub struct Actor {
+    db: sqlx::SqlitePool,
+    process_manager: Box<dyn MessageChannel<process_manager::Event>>,
+    /// Only set if a settlement is to be proposed before refunding.
+    propose_settlement: Option<Box<dyn MessageChannel<taker_cfd::ProposeSettlement>>>,
+    /// How long after the expiry of a CFD to wait for the attestation.
+    grace_period: time::Duration,
+    /// When we proposed to settle each CFD.
+    settlement_proposed_at: HashMap<OrderId, OffsetDateTime>,
+    clock: Arc<dyn Clock>,
+    tasks: Tasks,
+}
+
+impl Actor {
+    pub fn new(
+        db: sqlx::SqlitePool,
+        role: Role,
+        process_manager: &(impl MessageChannel<process_manager::Event> + 'static),
+        propose_settlement: Option<Box<dyn MessageChannel<taker_cfd::ProposeSettlement>>>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let grace_period = match role {
+            Role::Maker => ATTESTATION_GRACE_PERIOD + SETTLEMENT_GRACE_PERIOD,
+            Role::Taker => ATTESTATION_GRACE_PERIOD,
+        };
+
+        Self {
+            db,
+            process_manager: process_manager.clone_channel(),
+            propose_settlement,
+            grace_period,
+            settlement_proposed_at: HashMap::new(),
+            clock,
+            tasks: Tasks::default(),
+        }
+    }
+
+    /// Whether to wait for the counterparty to accept our settlement proposal, proposing it first
+    /// if we did not do so yet.
+    ///
+    /// Fails if the actor proposing the settlement is gone.
+    async fn settle_first(
+        &mut self,
+        order_id: OrderId,
+        price: Price,
+    ) -> Result<bool, xtra::Disconnected> {
+        let propose_settlement = match self.propose_settlement.as_ref() {
+            Some(propose_settlement) => propose_settlement,
+            None => return Ok(false),
+        };
+
+        let now = self.clock.now();
+
+        if let Some(proposed_at) = self.settlement_proposed_at.get(&order_id) {
+            return Ok(now < *proposed_at + SETTLEMENT_GRACE_PERIOD);
+        }
+
+        tracing::info!(%order_id, "Oracle did not attest, proposing settlement at initial price");
+
+        self.settlement_proposed_at.insert(order_id, now);
+
+        match propose_settlement
+            .send(taker_cfd::ProposeSettlement {
+                order_id,
+                current_price: price,
+            })
+            .await?
+        {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                tracing::warn!(%order_id, "Failed to propose settlement, refunding instead: {:#}", e);
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _msg: AutoRefund, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        tracing::trace!("Checking all CFDs for missed attestations");
+
+        let mut conn = self.db.acquire().await?;
+        let now = self.clock.now();
+
+        for id in db::load_all_cfd_ids(&mut conn).await? {
+            let cfd = try_continue!(load_cfd(id, &mut conn).await);
+
+            if cfd.is_monitoring_paused() {
+                continue;
+            }
+
+            let event = match cfd.missed_attestation(now, self.grace_period) {
+                None => continue,
+                Some(MissedAttestation::Uncommitted) => {
+                    // Settling at the initial price pays out what the refund would, without the
+                    // fees of the commit and refund transactions and waiting for the refund
+                    // timelock.
+                    match self.settle_first(id, cfd.initial_price()).await {
+                        Ok(true) => continue,
+                        Ok(false) => {}
+                        Err(e) => {
+                            tracing::error!(order_id = %id, "Cannot propose settlement, stopping auto refund: {:#}", e);
+                            ctx.stop();
+                            return Ok(());
+                        }
+                    }
+
+                    tracing::info!(order_id = %id, "Oracle did not attest, publishing commit transaction to refund");
+
+                    try_continue!(cfd.manual_commit_and_settle())
+                }
+                Some(MissedAttestation::Refundable) => {
+                    tracing::info!(order_id = %id, "Oracle did not attest, publishing refund transaction");
+
+                    try_continue!(cfd.manual_refund_to_blockchain())
+                }
+            };
+
+            if let Err(e) = self
+                .process_manager
+                .send(process_manager::Event::new(event))
+                .await?
+            {
+                tracing::error!("Sending event to process manager failed: {:#}", e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let fut = ctx
+            .notify_interval(AUTO_REFUND_INTERVAL, || AutoRefund)
+            .expect("we are alive");
+
+        self.tasks.add(fut);
+    }
+}
+
+/// Message to trigger closing CFDs without attestation on a regular interval
+pub struct AutoRefund;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::model::cfd::Cfd;
+    use crate::model::cfd::CfdEvent;
+    use crate::model::cfd::Event;
+    use sqlx::SqlitePool;
+    use std::sync::Mutex;
+    use xtra::prelude::*;
+
+    #[tokio::test]
+    async fn taker_refunds_once_settlement_proposal_was_not_accepted() {
+        let (db, order_id, expiry) = db_with_expired_cfd().await;
+        let clock = ManualClock::new(expiry + ATTESTATION_GRACE_PERIOD);
+        let counterparts = Counterparts::default();
+        let counterparts_addr = counterparts.clone().create(None).spawn_global();
+        let auto_refund = Actor::new(
+            db,
+            Role::Taker,
+            &counterparts_addr,
+            Some(counterparts_addr.clone_channel()),
+            Arc::new(clock.clone()),
+        )
+        .create(None)
+        .spawn_global();
+
+        auto_refund.send(AutoRefund).await.unwrap().unwrap();
+        assert_eq!(counterparts.proposed(), vec![order_id]);
+        assert_eq!(counterparts.events(), 0);
+
+        clock.advance(SETTLEMENT_GRACE_PERIOD - time::Duration::seconds(1));
+        auto_refund.send(AutoRefund).await.unwrap().unwrap();
+        assert_eq!(counterparts.events(), 0);
+
+        clock.advance(time::Duration::seconds(1));
+        auto_refund.send(AutoRefund).await.unwrap().unwrap();
+        assert_eq!(counterparts.proposed(), vec![order_id]);
+        assert_eq!(counterparts.events(), 1);
+    }
+
+    #[tokio::test]
+    async fn maker_gives_taker_time_to_propose_settlement() {
+        let (db, _, expiry) = db_with_expired_cfd().await;
+        let clock = ManualClock::new(expiry + ATTESTATION_GRACE_PERIOD);
+        let counterparts = Counterparts::default();
+        let counterparts_addr = counterparts.clone().create(None).spawn_global();
+        let auto_refund = Actor::new(
+            db,
+            Role::Maker,
+            &counterparts_addr,
+            None,
+            Arc::new(clock.clone()),
+        )
+        .create(None)
+        .spawn_global();
+
+        auto_refund.send(AutoRefund).await.unwrap().unwrap();
+        assert_eq!(counterparts.events(), 0);
+
+        clock.advance(SETTLEMENT_GRACE_PERIOD);
+        auto_refund.send(AutoRefund).await.unwrap().unwrap();
+        assert_eq!(counterparts.events(), 1);
+    }
+
+    #[tokio::test]
+    async fn stops_instead_of_refunding_if_settlement_cannot_be_proposed() {
+        let (db, _, expiry) = db_with_expired_cfd().await;
+        let clock = ManualClock::new(expiry + ATTESTATION_GRACE_PERIOD);
+        let counterparts = Counterparts::default();
+        let counterparts_addr = counterparts.clone().create(None).spawn_global();
+        let (gone, gone_ctx) = Context::<Counterparts>::new(None);
+        drop(gone_ctx);
+        let auto_refund = Actor::new(
+            db,
+            Role::Taker,
+            &counterparts_addr,
+            Some(gone.clone_channel()),
+            Arc::new(clock),
+        )
+        .create(None)
+        .spawn_global();
+
+        auto_refund.send(AutoRefund).await.unwrap().unwrap();
+
+        assert!(auto_refund.send(AutoRefund).await.is_err());
+        assert_eq!(counterparts.events(), 0);
+    }
+
+    /// Records the settlement proposals and the events sent by the auto refund actor.
+    #[derive(Clone, Default)]
+    struct Counterparts {
+        proposed: Arc<Mutex<Vec<OrderId>>>,
+        events: Arc<Mutex<usize>>,
+    }
+
+    impl Counterparts {
+        fn proposed(&self) -> Vec<OrderId> {
+            self.proposed.lock().unwrap().clone()
+        }
+
+        fn events(&self) -> usize {
+            *self.events.lock().unwrap()
+        }
+    }
+
+    impl xtra::Actor for Counterparts {}
+
+    #[async_trait]
+    impl Handler<taker_cfd::ProposeSettlement> for Counterparts {
+        async fn handle(
+            &mut self,
+            msg: taker_cfd::ProposeSettlement,
+            _ctx: &mut Context<Self>,
+        ) -> Result<()> {
+            self.proposed.lock().unwrap().push(msg.order_id);
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Handler<process_manager::Event> for Counterparts {
+        async fn handle(
+            &mut self,
+            _: process_manager::Event,
+            _ctx: &mut Context<Self>,
+        ) -> Result<()> {
+            *self.events.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    /// A database with an open CFD whose settlement event expired, returning its id and expiry.
+    async fn db_with_expired_cfd() -> (SqlitePool, OrderId, OffsetDateTime) {
+        let db = crate::db::tests::setup_test_pool().await;
+        let mut conn = db.acquire().await.unwrap();
+
+        let cfd = Cfd::dummy().insert(&mut conn).await;
+
+        let (_, dlc) = crate::self_test::set_up_contract().await.unwrap();
+        let expiry = dlc.settlement_event_id.timestamp();
+        for event in [
+            CfdEvent::ContractSetupCompleted { dlc },
+            CfdEvent::LockConfirmed,
+        ] {
+            crate::db::append_event(Event::new(cfd.id(), event), &mut conn)
+                .await
+                .unwrap();
+        }
+
+        (db, cfd.id(), expiry)
+    }
+
+    #[test]
+    fn parses_refund_strategies() {
+        assert_eq!(
+            "manual".parse::<RefundStrategy>().unwrap(),
+            RefundStrategy::Manual
+        );
+        assert_eq!(
+            "refund".parse::<RefundStrategy>().unwrap(),
+            RefundStrategy::Refund
+        );
+        assert_eq!(
+            "settle-then-refund".parse::<RefundStrategy>().unwrap(),
+            RefundStrategy::SettleThenRefund
+        );
+        assert!("later".parse::<RefundStrategy>().is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use crate::address_map::AddressMap;
 use crate::address_map::Stopping;
+use crate::cfd_actors;
 use crate::cfd_actors::load_cfd;
+use crate::clock::Clock;
 use crate::connection;
 use crate::db;
 use crate::model::cfd::OrderId;
@@ -13,7 +15,9 @@ use crate::Tasks;
 use anyhow::Result;
 use async_trait::async_trait;
 use maia::secp256k1_zkp::schnorrsig;
+use std::sync::Arc;
 use std::time::Duration;
+use tracing::Instrument;
 use xtra::Actor as _;
 use xtra::Address;
 use xtra_productivity::xtra_productivity;
@@ -26,13 +30,18 @@ pub struct Actor<O, M> {
     _monitor_actor: Address<M>,
     oracle_actor: Address<O>,
     n_payouts: usize,
+    /// CFDs open for longer than this are no longer rolled over
+    max_position_age: Option<time::Duration>,
+    max_concurrent_rollovers: usize,
 
     rollover_actors: AddressMap<OrderId, rollover_taker::Actor>,
 
+    clock: Arc<dyn Clock>,
     tasks: Tasks,
 }
 
 impl<O, M> Actor<O, M> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         db: sqlx::SqlitePool,
         oracle_pk: schnorrsig::PublicKey,
@@ -41,6 +50,9 @@ impl<O, M> Actor<O, M> {
         monitor_actor: Address<M>,
         oracle_actor: Address<O>,
         n_payouts: usize,
+        max_position_age: Option<time::Duration>,
+        max_concurrent_rollovers: usize,
+        clock: Arc<dyn Clock>,
     ) -> Self {
         Self {
             db,
@@ -50,7 +62,10 @@ impl<O, M> Actor<O, M> {
             _monitor_actor: monitor_actor,
             oracle_actor,
             n_payouts,
+            max_position_age,
+            max_concurrent_rollovers,
             rollover_actors: AddressMap::default(),
+            clock,
             tasks: Tasks::default(),
         }
     }
@@ -62,7 +77,7 @@ where
     M: xtra::Handler<monitor::StartMonitoring>,
     O: xtra::Handler<oracle::MonitorAttestation> + xtra::Handler<oracle::GetAnnouncement>,
 {
-    async fn handle(&mut self, _msg: AutoRollover, ctx: &mut xtra::Context<Self>) -> Result<()> {
+    async fn handle(&mut self, _msg: AutoRollover, ctx: &mut xtra::Context<Self>) -> Result<usize> {
         tracing::trace!("Checking all CFDs for rollover eligibility");
 
         let mut conn = self.db.acquire().await?;
@@ -72,7 +87,18 @@ where
             .address()
             .expect("actor to be able to give address to itself");
 
+        let mut started = 0;
         for id in cfd_ids {
+            // The remaining CFDs are rolled over once others completed, at the latest on the next
+            // check
+            if let Err(e) = self
+                .rollover_actors
+                .ensure_capacity(self.max_concurrent_rollovers)
+            {
+                tracing::debug!("Not starting more rollovers: {:#}", e);
+                break;
+            }
+
             let disconnected = match self.rollover_actors.get_disconnected(id) {
                 Ok(disconnected) => disconnected,
                 Err(_) => {
@@ -84,6 +110,19 @@ where
             // TODO: Shall this have a try_continue?
             let cfd = load_cfd(id, &mut conn).await?;
 
+            // Only CFDs that are eligible are counted as started
+            if let Err(e) = cfd.is_rollover_possible(self.clock.now()) {
+                tracing::trace!(order_id=%id, "Not rolling over: {:#}", e);
+                continue;
+            }
+
+            if let Some(max_age) = self.max_position_age {
+                if let Err(e) = cfd.ensure_within_max_age(self.clock.now(), max_age) {
+                    tracing::debug!(order_id=%id, "Not rolling over: {:#}", e);
+                    continue;
+                }
+            }
+
             let (addr, fut) = rollover_taker::Actor::new(
                 (cfd, self.n_payouts),
                 self.oracle_pk,
@@ -92,15 +131,17 @@ where
                 self.projection_actor.clone(),
                 &this,
                 (&this, &self.conn_actor),
+                self.clock.clone(),
             )
             .create(None)
             .run();
 
             disconnected.insert(addr);
-            self.tasks.add(fut);
+            self.tasks.add(fut.instrument(cfd_actors::cfd_span(id)));
+            started += 1;
         }
 
-        Ok(())
+        Ok(started)
     }
 }
 
@@ -130,6 +171,35 @@ where
     }
 }
 
+#[xtra_productivity]
+impl<O, M> Actor<O, M>
+where
+    M: xtra::Handler<monitor::StartMonitoring>,
+    O: xtra::Handler<oracle::MonitorAttestation> + xtra::Handler<oracle::GetAnnouncement>,
+{
+    async fn handle_get_running_rollovers(&mut self, _: GetRunningRollovers) -> Vec<OrderId> {
+        self.rollover_actors.connected_keys().copied().collect()
+    }
+
+    async fn handle_cancel_rollover(&mut self, msg: CancelRollover) -> bool {
+        match self.rollover_actors.remove_connected(&msg.order_id) {
+            Some(addr) => {
+                let _ = addr.send(rollover_taker::Cancel).await;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Lists the CFDs that are currently being rolled over.
+pub struct GetRunningRollovers;
+
+/// Stops the rollover of the given CFD, returning whether one was running.
+pub struct CancelRollover {
+    pub order_id: OrderId,
+}
+
 #[async_trait]
 impl<O, M> xtra::Actor for Actor<O, M>
 where
@@ -149,5 +219,100 @@ where
     }
 }
 
-/// Message to trigger roll-over on a regular interval
+/// Message to trigger roll-over on a regular interval, or right away through
+/// [`crate::TakerActorSystem::trigger_rollover_check`].
+///
+/// Responds with the number of CFDs a rollover was started for.
 pub struct AutoRollover;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::model::cfd::Cfd;
+    use crate::model::cfd::CfdEvent;
+    use crate::model::cfd::Dlc;
+    use crate::model::cfd::Event;
+    use crate::model::Timestamp;
+    use sqlx::SqlitePool;
+    use std::str::FromStr;
+    use time::OffsetDateTime;
+
+    #[tokio::test]
+    async fn cfd_past_max_age_is_not_rolled_over() {
+        let max_age = time::Duration::days(7);
+        let dlc = Dlc::dummy(0);
+        let expiry = dlc.settlement_event_id.timestamp();
+        let (db, order_id) =
+            db_with_open_cfd(dlc, expiry - max_age - time::Duration::hours(1)).await;
+
+        // Within the rollover window, but open for longer than the maximum age
+        let clock = ManualClock::new(expiry - time::Duration::hours(2));
+        clock.advance(time::Duration::minutes(90));
+        let cfd = load_cfd(order_id, &mut db.acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert!(cfd.is_rollover_possible(clock.now()).is_ok());
+        assert!(cfd.ensure_within_max_age(clock.now(), max_age).is_err());
+
+        let auto_rollover = actor(db, Some(max_age), clock).create(None).spawn_global();
+
+        let started = auto_rollover.send(AutoRollover).await.unwrap().unwrap();
+        let running = auto_rollover.send(GetRunningRollovers).await.unwrap();
+
+        assert_eq!(started, 0);
+        assert!(running.is_empty());
+    }
+
+    fn actor(
+        db: SqlitePool,
+        max_position_age: Option<time::Duration>,
+        clock: ManualClock,
+    ) -> Actor<oracle::Actor, monitor::Actor> {
+        // None of the counterparts are needed unless a rollover is started
+        let (projection_actor, _) = xtra::Context::<projection::Actor>::new(None);
+        let (conn_actor, _) = xtra::Context::<connection::Actor>::new(None);
+        let (monitor_actor, _) = xtra::Context::<monitor::Actor>::new(None);
+        let (oracle_actor, _) = xtra::Context::<oracle::Actor>::new(None);
+
+        Actor::new(
+            db,
+            schnorrsig::PublicKey::from_str(
+                "ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7",
+            )
+            .unwrap(),
+            projection_actor,
+            conn_actor,
+            monitor_actor,
+            oracle_actor,
+            crate::N_PAYOUTS,
+            max_position_age,
+            crate::MAX_CONCURRENT_PROTOCOLS,
+            Arc::new(clock),
+        )
+    }
+
+    /// A database with a CFD whose contract was set up at `opened_at`.
+    async fn db_with_open_cfd(dlc: Dlc, opened_at: OffsetDateTime) -> (SqlitePool, OrderId) {
+        let db = db::tests::setup_test_pool().await;
+        let mut conn = db.acquire().await.unwrap();
+
+        let cfd = Cfd::dummy().insert(&mut conn).await;
+
+        db::append_event(
+            Event {
+                timestamp: Timestamp::new(opened_at.unix_timestamp()),
+                id: cfd.id(),
+                event: CfdEvent::ContractSetupCompleted { dlc },
+            },
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        db::append_event(Event::new(cfd.id(), CfdEvent::LockConfirmed), &mut conn)
+            .await
+            .unwrap();
+
+        (db, cfd.id())
+    }
+}
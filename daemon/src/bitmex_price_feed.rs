@@ -1,8 +1,8 @@
 use crate::model::Price;
 use crate::model::Timestamp;
-use crate::projection;
-use crate::supervisor;
-use crate::Tasks;
+use crate::price_feed::PriceSource;
+use crate::price_feed::Quote;
+use crate::price_feed::Quotes;
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::SinkExt;
@@ -11,158 +11,75 @@ use rust_decimal::Decimal;
 use std::convert::TryFrom;
 use std::time::Duration;
 use tokio_tungstenite::tungstenite;
-use xtra::prelude::MessageChannel;
-use xtra_productivity::xtra_productivity;
 
 const URL: &str = "wss://www.bitmex.com/realtime?subscribe=quoteBin1m:XBTUSD";
 
-pub struct Actor {
-    tasks: Tasks,
-    receiver: Box<dyn MessageChannel<projection::Update<Quote>>>,
-    supervisor: xtra::Address<supervisor::Actor<Self, StopReason>>,
-}
-
-impl Actor {
-    pub fn new(
-        receiver: impl MessageChannel<projection::Update<Quote>> + 'static,
-        supervisor: xtra::Address<supervisor::Actor<Self, StopReason>>,
-    ) -> Self {
-        Self {
-            tasks: Tasks::default(),
-            receiver: Box::new(receiver),
-            supervisor,
-        }
-    }
-}
+/// Quotes of the XBTUSD perpetual swap from the BitMex realtime API.
+pub struct BitMex;
 
 #[async_trait]
-impl xtra::Actor for Actor {
-    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
-        self.tasks.add({
-            let this = ctx.address().expect("we are alive");
-            let receiver = self.receiver.clone_channel();
-
-            async move {
-                tracing::debug!("Connecting to BitMex realtime API");
-
-                let mut connection = match tokio_tungstenite::connect_async(URL).await {
-                    Ok((connection, _)) => connection,
-                    Err(e) => {
-                        let _ = this.send(StopReason::FailedToConnect { source: e }).await;
-                        return
-                    }
-                };
-
-                tracing::info!("Connected to BitMex realtime API");
+impl PriceSource for BitMex {
+    fn name(&self) -> &'static str {
+        "BitMex"
+    }
 
-                loop {
-                    tokio::select! {
-                        _ = tokio::time::sleep(Duration::from_secs(5)) => {
-                            tracing::trace!("No message from BitMex in the last 5 seconds, pinging");
-                            let _ = connection.send(tungstenite::Message::Ping([0u8; 32].to_vec())).await;
-                        },
-                        msg = connection.try_next() => {
-                            match msg {
-                                Ok(Some(tungstenite::Message::Pong(_))) => {
-                                    tracing::trace!("Received pong");
-                                    continue;
-                                }
-                                Ok(Some(tungstenite::Message::Text(text))) => {
-                                    match Quote::from_str(&text) {
-                                        Ok(None) => {
-                                            continue;
-                                        }
-                                        Ok(Some(quote)) => {
-                                            if receiver.send(projection::Update(quote)).await.is_err() {
-                                                return; // if the receiver dies, our job is done
-                                            }
-                                        }
-                                        Err(e) => {
-                                            tracing::warn!("Failed to parse quote: {:#}", e);
-                                            return;
-                                        }
+    async fn connect(&self) -> Result<Quotes> {
+        let (connection, _) = tokio_tungstenite::connect_async(URL).await?;
+
+        let quotes = futures::stream::unfold(connection, |mut connection| async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(5)) => {
+                        tracing::trace!("No message from BitMex in the last 5 seconds, pinging");
+                        let _ = connection.send(tungstenite::Message::Ping([0u8; 32].to_vec())).await;
+                    },
+                    msg = connection.try_next() => {
+                        match msg {
+                            Ok(Some(tungstenite::Message::Pong(_))) => {
+                                tracing::trace!("Received pong");
+                                continue;
+                            }
+                            Ok(Some(tungstenite::Message::Text(text))) => {
+                                match parse_quote(&text) {
+                                    Ok(None) => continue,
+                                    Ok(Some(quote)) => return Some((Ok(quote), connection)),
+                                    Err(e) => {
+                                        return Some((Err(e.context("Failed to parse quote")), connection))
                                     }
                                 }
-                                Ok(Some(other)) => {
-                                    tracing::trace!("Unsupported message: {:?}", other);
-                                    continue;
-                                }
-                                Ok(None) => {
-                                    let _ = this.send(StopReason::StreamEnded).await;
-                                    return;
-                                }
-                                Err(e) => {
-                                    let _ = this.send(StopReason::Failed { source: e }).await;
-                                    return;
-                                }
                             }
-                        },
-                    }
+                            Ok(Some(other)) => {
+                                tracing::trace!("Unsupported message: {:?}", other);
+                                continue;
+                            }
+                            Ok(None) => return None,
+                            Err(e) => return Some((Err(e.into()), connection)),
+                        }
+                    },
                 }
             }
         });
-    }
-}
 
-#[xtra_productivity]
-impl Actor {
-    async fn handle(&mut self, msg: StopReason, ctx: &mut xtra::Context<Self>) {
-        let _ = self
-            .supervisor
-            .send(supervisor::Stopped { reason: msg })
-            .await;
-        ctx.stop();
+        Ok(Box::pin(quotes))
     }
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum StopReason {
-    #[error("Connection to BitMex API failed")]
-    Failed { source: tungstenite::Error },
-    #[error("Failed to connect to BitMex API")]
-    FailedToConnect { source: tungstenite::Error },
-    #[error("Websocket stream to BitMex API closed")]
-    StreamEnded,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct Quote {
-    pub timestamp: Timestamp,
-    pub bid: Price,
-    pub ask: Price,
-}
-
-impl Quote {
-    fn from_str(text: &str) -> Result<Option<Self>> {
-        let table_message = match serde_json::from_str::<wire::TableMessage>(text) {
-            Ok(table_message) => table_message,
-            Err(_) => {
-                tracing::trace!(%text, "Not a 'table' message, skipping...");
-                return Ok(None);
-            }
-        };
-
-        let [quote] = table_message.data;
-
-        Ok(Some(Self {
-            timestamp: Timestamp::parse_from_rfc3339(&quote.timestamp)?,
-            bid: Price::new(Decimal::try_from(quote.bid_price)?)?,
-            ask: Price::new(Decimal::try_from(quote.ask_price)?)?,
-        }))
-    }
-
-    pub fn for_maker(&self) -> Price {
-        self.ask
-    }
+fn parse_quote(text: &str) -> Result<Option<Quote>> {
+    let table_message = match serde_json::from_str::<wire::TableMessage>(text) {
+        Ok(table_message) => table_message,
+        Err(_) => {
+            tracing::trace!(%text, "Not a 'table' message, skipping...");
+            return Ok(None);
+        }
+    };
 
-    pub fn for_taker(&self) -> Price {
-        // TODO: verify whether this is correct
-        self.mid_range()
-    }
+    let [quote] = table_message.data;
 
-    fn mid_range(&self) -> Price {
-        (self.bid + self.ask) / 2
-    }
+    Ok(Some(Quote {
+        timestamp: Timestamp::parse_from_rfc3339(&quote.timestamp)?,
+        bid: Price::new(Decimal::try_from(quote.bid_price)?)?,
+        ask: Price::new(Decimal::try_from(quote.ask_price)?)?,
+    }))
 }
 
 mod wire {
@@ -194,7 +111,7 @@ mod tests {
 
     #[test]
     fn can_deserialize_quote_message() {
-        let quote = Quote::from_str(r#"{"table":"quoteBin1m","action":"insert","data":[{"timestamp":"2021-09-21T02:40:00.000Z","symbol":"XBTUSD","bidSize":50200,"bidPrice":42640.5,"askPrice":42641,"askSize":363600}]}"#).unwrap().unwrap();
+        let quote = parse_quote(r#"{"table":"quoteBin1m","action":"insert","data":[{"timestamp":"2021-09-21T02:40:00.000Z","symbol":"XBTUSD","bidSize":50200,"bidPrice":42640.5,"askPrice":42641,"askSize":363600}]}"#).unwrap().unwrap();
 
         assert_eq!(quote.bid, Price::new(dec!(42640.5)).unwrap());
         assert_eq!(quote.ask, Price::new(dec!(42641)).unwrap());
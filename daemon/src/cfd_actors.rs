@@ -1,6 +1,15 @@
 use crate::db;
+use crate::model::cfd::ActionCosts;
 use crate::model::cfd::Cfd;
+use crate::model::cfd::CfdEvent;
+use crate::model::cfd::CloseOption;
+use crate::model::cfd::DlcSummary;
+use crate::model::cfd::ExitLevels;
 use crate::model::cfd::OrderId;
+use crate::model::cfd::SignedCommitTx;
+use crate::model::Identity;
+use crate::model::Price;
+use crate::model::Usd;
 use crate::monitor;
 use crate::oracle;
 use crate::process_manager;
@@ -11,6 +20,7 @@ use anyhow::Result;
 use sqlx::pool::PoolConnection;
 use sqlx::Sqlite;
 use sqlx::SqlitePool;
+use std::collections::HashMap;
 
 pub async fn insert_cfd_and_update_feed(
     cfd: &Cfd,
@@ -22,10 +32,18 @@ pub async fn insert_cfd_and_update_feed(
     Ok(())
 }
 
+/// Monitoring events that arrived while monitoring of their CFD was paused.
+///
+/// They are replayed once monitoring is resumed. The queue is not persisted, but after a restart
+/// a paused CFD is only monitored again once it is resumed, which delivers its events anew.
+#[derive(Default)]
+pub struct PausedEvents(HashMap<OrderId, Vec<monitor::Event>>);
+
 pub async fn handle_monitoring_event(
     event: monitor::Event,
     db: &SqlitePool,
     process_manager: &xtra::Address<process_manager::Actor>,
+    paused_events: &mut PausedEvents,
 ) -> Result<()> {
     let mut conn = db.acquire().await?;
 
@@ -33,6 +51,12 @@ pub async fn handle_monitoring_event(
 
     let cfd = load_cfd(order_id, &mut conn).await?;
 
+    if cfd.is_monitoring_paused() {
+        tracing::info!(%order_id, ?event, "Monitoring paused, deferring monitoring event");
+        paused_events.0.entry(order_id).or_default().push(event);
+        return Ok(());
+    }
+
     let event = match event {
         monitor::Event::LockFinality(_) => cfd.handle_lock_confirmed(),
         monitor::Event::CommitFinality(_) => cfd.handle_commit_confirmed(),
@@ -45,11 +69,20 @@ pub async fn handle_monitoring_event(
             }
         }
         monitor::Event::CetFinality(_) => cfd.handle_cet_confirmed(),
-        monitor::Event::RefundTimelockExpired(_) => cfd.handle_refund_timelock_expired(),
+        monitor::Event::RefundTimelockExpired(_) => {
+            if cfd.is_final() {
+                return Ok(()); // Early return from a no-op
+            }
+
+            cfd.handle_refund_timelock_expired()
+                .context("Failed to handle expiry of the refund timelock")?
+        }
         monitor::Event::RefundFinality(_) => cfd.handle_refund_confirmed(),
         monitor::Event::RevokedTransactionFound(_) => cfd.handle_revoke_confirmed(),
     };
 
+    let is_refund_timelock_expiry = matches!(event.event, CfdEvent::RefundTimelockConfirmed { .. });
+
     if let Err(e) = process_manager
         .send(process_manager::Event::new(event.clone()))
         .await?
@@ -57,9 +90,33 @@ pub async fn handle_monitoring_event(
         tracing::error!("Sending event to process manager failed: {:#}", e);
     }
 
+    if is_refund_timelock_expiry {
+        let cfd = load_cfd(order_id, &mut conn).await?;
+
+        if let Some(event) = cfd.refund_after_commit()? {
+            tracing::info!(%order_id, "Refund timelock expired, refunding as requested on commit");
+
+            if let Err(e) = process_manager
+                .send(process_manager::Event::new(event))
+                .await?
+            {
+                tracing::error!("Sending event to process manager failed: {:#}", e);
+            }
+        }
+    }
+
     Ok(())
 }
 
+/// The span all actors working on a single CFD run in.
+///
+/// The span is named `cfd` and carries the `order_id` of the CFD as a field. Contract setup,
+/// rollover and collaborative settlement actors are instrumented with it, so all their logs can be
+/// filtered by `order_id`.
+pub fn cfd_span(order_id: OrderId) -> tracing::Span {
+    tracing::info_span!("cfd", %order_id)
+}
+
 /// Load a CFD from the database and rehydrate as the [`model::cfd::Cfd`] aggregate.
 pub async fn load_cfd(order_id: OrderId, conn: &mut PoolConnection<Sqlite>) -> Result<Cfd> {
     let (
@@ -107,6 +164,182 @@ pub async fn handle_commit(
     Ok(())
 }
 
+pub async fn handle_pause_monitoring(
+    order_id: OrderId,
+    conn: &mut PoolConnection<Sqlite>,
+    process_manager: &xtra::Address<process_manager::Actor>,
+) -> Result<()> {
+    let cfd = load_cfd(order_id, conn).await?;
+
+    let event = cfd.pause_monitoring()?;
+    if let Err(e) = process_manager
+        .send(process_manager::Event::new(event.clone()))
+        .await?
+    {
+        tracing::error!("Sending event to process manager failed: {:#}", e);
+    }
+
+    Ok(())
+}
+
+pub async fn handle_archive(
+    order_id: OrderId,
+    conn: &mut PoolConnection<Sqlite>,
+    process_manager: &xtra::Address<process_manager::Actor>,
+) -> Result<()> {
+    let cfd = load_cfd(order_id, conn).await?;
+
+    let event = cfd.archive()?;
+    if let Err(e) = process_manager
+        .send(process_manager::Event::new(event.clone()))
+        .await?
+    {
+        tracing::error!("Sending event to process manager failed: {:#}", e);
+    }
+
+    Ok(())
+}
+
+pub async fn handle_resume_monitoring(
+    order_id: OrderId,
+    db: &SqlitePool,
+    process_manager: &xtra::Address<process_manager::Actor>,
+    paused_events: &mut PausedEvents,
+) -> Result<()> {
+    let mut conn = db.acquire().await?;
+    let cfd = load_cfd(order_id, &mut conn).await?;
+
+    let event = cfd.resume_monitoring()?;
+    if let Err(e) = process_manager
+        .send(process_manager::Event::new(event.clone()))
+        .await?
+    {
+        tracing::error!("Sending event to process manager failed: {:#}", e);
+    }
+
+    for event in paused_events.0.remove(&order_id).unwrap_or_default() {
+        tracing::info!(%order_id, ?event, "Replaying monitoring event deferred while paused");
+
+        try_continue!(handle_monitoring_event(event, db, process_manager, paused_events).await);
+    }
+
+    Ok(())
+}
+
+pub async fn handle_set_exit_levels(
+    order_id: OrderId,
+    levels: ExitLevels,
+    conn: &mut PoolConnection<Sqlite>,
+    process_manager: &xtra::Address<process_manager::Actor>,
+) -> Result<()> {
+    let cfd = load_cfd(order_id, conn).await?;
+
+    let event = cfd.set_exit_levels(levels)?;
+    if let Err(e) = process_manager
+        .send(process_manager::Event::new(event.clone()))
+        .await?
+    {
+        tracing::error!("Sending event to process manager failed: {:#}", e);
+    }
+
+    Ok(())
+}
+
+pub async fn handle_commit_and_settle(
+    order_id: OrderId,
+    conn: &mut PoolConnection<Sqlite>,
+    process_manager: &xtra::Address<process_manager::Actor>,
+) -> Result<()> {
+    let cfd = load_cfd(order_id, conn).await?;
+
+    let event = cfd.manual_commit_and_settle()?;
+    if let Err(e) = process_manager
+        .send(process_manager::Event::new(event.clone()))
+        .await?
+    {
+        tracing::error!("Sending event to process manager failed: {:#}", e);
+    }
+
+    Ok(())
+}
+
+pub async fn handle_refund(
+    order_id: OrderId,
+    conn: &mut PoolConnection<Sqlite>,
+    process_manager: &xtra::Address<process_manager::Actor>,
+) -> Result<()> {
+    let cfd = load_cfd(order_id, conn).await?;
+
+    let event = cfd.manual_refund_to_blockchain()?;
+    if let Err(e) = process_manager
+        .send(process_manager::Event::new(event.clone()))
+        .await?
+    {
+        tracing::error!("Sending event to process manager failed: {:#}", e);
+    }
+
+    Ok(())
+}
+
+pub async fn load_signed_commit_tx(
+    order_id: OrderId,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<SignedCommitTx> {
+    let cfd = load_cfd(order_id, conn).await?;
+
+    cfd.signed_commit_tx()
+}
+
+pub async fn load_dlc_summary(
+    order_id: OrderId,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<DlcSummary> {
+    let cfd = load_cfd(order_id, conn).await?;
+
+    cfd.dlc_summary()
+}
+
+/// The quantity of all CFDs with `counterparty` that still expose us to it, see
+/// [`Cfd::is_exposed`].
+pub async fn load_exposure(
+    counterparty: Identity,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<Usd> {
+    let mut exposure = Usd::ZERO;
+
+    for order_id in db::load_all_cfd_ids(conn).await? {
+        let cfd = load_cfd(order_id, conn).await?;
+
+        if cfd.counterparty_network_identity() == counterparty && cfd.is_exposed() {
+            exposure = exposure + cfd.quantity();
+        }
+    }
+
+    Ok(exposure)
+}
+
+pub async fn load_close_options(
+    order_id: OrderId,
+    current_price: Option<Price>,
+    n_payouts: usize,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<Vec<CloseOption>> {
+    let cfd = load_cfd(order_id, conn).await?;
+
+    Ok(cfd.close_options(current_price, n_payouts))
+}
+
+pub async fn load_action_costs(
+    order_id: OrderId,
+    current_price: Option<Price>,
+    n_payouts: usize,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<ActionCosts> {
+    let cfd = load_cfd(order_id, conn).await?;
+
+    Ok(cfd.action_costs(current_price, n_payouts))
+}
+
 pub async fn handle_oracle_attestation(
     attestation: oracle::Attestation,
     db: &SqlitePool,
@@ -121,6 +354,12 @@ pub async fn handle_oracle_attestation(
 
     for id in db::load_all_cfd_ids(&mut conn).await? {
         let cfd = try_continue!(load_cfd(id, &mut conn).await);
+
+        if cfd.is_monitoring_paused() {
+            tracing::info!(order_id=%id, "Monitoring paused, ignoring attestation");
+            continue;
+        }
+
         let event = try_continue!(cfd
             .decrypt_cet(&attestation)
             .context("Failed to decrypt CET using attestation"));
@@ -0,0 +1,81 @@
+use crate::model::Timestamp;
+use std::sync::Arc;
+use std::sync::Mutex;
+use time::Duration;
+use time::OffsetDateTime;
+
+/// Source of the current time.
+///
+/// Actors that make decisions based on the current time take a clock instead of asking the
+/// system directly so that tests can control time.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> OffsetDateTime;
+
+    fn timestamp(&self) -> Timestamp {
+        Timestamp::new(self.now().unix_timestamp())
+    }
+}
+
+/// The real clock of the system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+/// A clock that only moves when told to.
+///
+/// Clones share the same time, so a test can keep a handle and advance the clock that was handed
+/// to an actor.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<OffsetDateTime>>,
+}
+
+impl ManualClock {
+    pub fn new(now: OffsetDateTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: OffsetDateTime) {
+        *self.now.lock().expect("clock mutex not to be poisoned") = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().expect("clock mutex not to be poisoned") += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> OffsetDateTime {
+        *self.now.lock().expect("clock mutex not to be poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn manual_clock_is_shared_between_clones() {
+        let clock = ManualClock::new(datetime!(2021-11-19 10:00:00).assume_utc());
+        let handed_out: Arc<dyn Clock> = Arc::new(clock.clone());
+
+        clock.advance(Duration::hours(1));
+
+        assert_eq!(
+            handed_out.now(),
+            datetime!(2021-11-19 11:00:00).assume_utc()
+        );
+        assert_eq!(
+            handed_out.timestamp(),
+            Timestamp::new(datetime!(2021-11-19 11:00:00).assume_utc().unix_timestamp())
+        );
+    }
+}
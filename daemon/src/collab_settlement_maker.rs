@@ -1,5 +1,6 @@
 use crate::address_map::ActorName;
 use crate::address_map::Stopping;
+use crate::clock::Clock;
 use crate::maker_inc_connections;
 use crate::model::cfd::Cfd;
 use crate::model::cfd::CollaborativeSettlement;
@@ -7,11 +8,15 @@ use crate::model::cfd::Completed;
 use crate::model::cfd::SettlementKind;
 use crate::model::cfd::SettlementProposal;
 use crate::model::Identity;
+use crate::model::Price;
+use crate::model::Timestamp;
 use crate::projection;
 use crate::xtra_ext::LogFailure;
 use anyhow::Context;
+use anyhow::Result;
 use async_trait::async_trait;
 use maia::secp256k1_zkp::Signature;
+use std::sync::Arc;
 use xtra::prelude::MessageChannel;
 use xtra_productivity::xtra_productivity;
 
@@ -20,6 +25,11 @@ pub struct Actor {
     projection: xtra::Address<projection::Actor>,
     on_completed: Box<dyn MessageChannel<Completed<CollaborativeSettlement>>>,
     proposal: SettlementProposal,
+    /// When we received the current proposal, or sent our counter-proposal.
+    proposed_at: Timestamp,
+    n_payouts: usize,
+    max_proposal_age: time::Duration,
+    clock: Arc<dyn Clock>,
     taker_id: Identity,
     connections: Box<dyn MessageChannel<maker_inc_connections::settlement::Response>>,
     on_stopping: Vec<Box<dyn MessageChannel<Stopping<Self>>>>,
@@ -27,6 +37,11 @@ pub struct Actor {
 
 pub struct Accepted;
 pub struct Rejected;
+/// Propose to settle at `price` instead of the price proposed by the taker.
+pub struct Countered {
+    pub price: Price,
+}
+pub struct CounterRejected;
 pub struct Initiated {
     pub sig_taker: Signature,
 }
@@ -51,6 +66,33 @@ impl Actor {
         self.update_proposal(None).await;
     }
 
+    async fn handle(&mut self, msg: Countered, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        let order_id = self.cfd.id();
+
+        let proposal = self
+            .cfd
+            .counter_collaborative_settlement(msg.price, self.n_payouts)?;
+
+        tracing::info!(%order_id, price = %proposal.price, "Countering settlement proposal");
+
+        self.proposal = proposal.clone();
+        self.proposed_at = self.clock.timestamp();
+        self.counter(ctx).await;
+        self.update_proposal(Some((proposal, SettlementKind::Outgoing)))
+            .await;
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, _: CounterRejected, ctx: &mut xtra::Context<Self>) {
+        let order_id = self.cfd.id();
+
+        tracing::info!(%order_id, "Settlement counter-proposal rejected");
+
+        self.update_proposal(None).await;
+        ctx.stop();
+    }
+
     async fn handle(&mut self, msg: Initiated, ctx: &mut xtra::Context<Self>) {
         let completed = async {
             tracing::info!(
@@ -59,9 +101,13 @@ impl Actor {
                 "Received signature for collaborative settlement"
             );
 
-            let settlement = self
-                .cfd
-                .start_collaborative_settlement_maker(self.proposal.clone(), msg.sig_taker)?;
+            let settlement = self.cfd.start_collaborative_settlement_maker(
+                self.proposal.clone(),
+                msg.sig_taker,
+                self.proposed_at,
+                self.max_proposal_age,
+                self.clock.timestamp(),
+            )?;
 
             self.update_proposal(None).await;
 
@@ -114,6 +160,9 @@ impl Actor {
     pub fn new(
         cfd: Cfd,
         proposal: SettlementProposal,
+        n_payouts: usize,
+        max_proposal_age: time::Duration,
+        clock: Arc<dyn Clock>,
         projection: xtra::Address<projection::Actor>,
         on_completed: &(impl MessageChannel<Completed<CollaborativeSettlement>> + 'static),
         taker_id: Identity,
@@ -128,6 +177,10 @@ impl Actor {
             projection,
             on_completed: on_completed.clone_channel(),
             proposal,
+            proposed_at: clock.timestamp(),
+            n_payouts,
+            max_proposal_age,
+            clock,
             taker_id,
             connections: connections.clone_channel(),
             on_stopping: vec![on_stopping0.clone_channel(), on_stopping1.clone_channel()],
@@ -173,6 +226,18 @@ impl Actor {
         .await
     }
 
+    async fn counter(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("self to be alive");
+        self.inform_taker(
+            maker_inc_connections::settlement::Decision::Counter {
+                proposal: self.proposal.clone(),
+                address: this,
+            },
+            ctx,
+        )
+        .await
+    }
+
     async fn reject(&mut self, ctx: &mut xtra::Context<Self>) {
         self.inform_taker(maker_inc_connections::settlement::Decision::Reject, ctx)
             .await
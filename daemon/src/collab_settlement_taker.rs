@@ -7,11 +7,14 @@ use crate::model::cfd::Completed;
 use crate::model::cfd::SettlementKind;
 use crate::model::cfd::SettlementProposal;
 use crate::model::Price;
+use crate::model::Role;
 use crate::projection;
 use crate::send_async_safe::SendAsyncSafe;
 use crate::wire;
+use anyhow::ensure;
 use anyhow::Result;
 use async_trait::async_trait;
+use bdk::bitcoin::Amount;
 use xtra::prelude::MessageChannel;
 use xtra_productivity::xtra_productivity;
 
@@ -21,6 +24,9 @@ pub struct Actor {
     on_completed: Box<dyn MessageChannel<Completed<CollaborativeSettlement>>>,
     connection: xtra::Address<connection::Actor>,
     proposal: SettlementProposal,
+    n_payouts: usize,
+    /// Whether the maker countered our proposal with the current `proposal`.
+    countered: bool,
 }
 
 impl Actor {
@@ -40,6 +46,8 @@ impl Actor {
             on_completed: Box::new(on_completed),
             connection,
             proposal,
+            n_payouts,
+            countered: false,
         })
     }
 
@@ -70,9 +78,10 @@ impl Actor {
 
         // TODO: This should happen within a dedicated state machine returned from
         // start_collaborative_settlement
-        let (tx, sig, payout_script_pubkey) = self
+        let (tx, sig, dlc) = self
             .cfd
             .sign_collaborative_close_transaction_taker(&self.proposal)?;
+        let settlement = CollaborativeSettlement::new(tx, dlc, Role::Taker, &self.proposal)?;
 
         self.connection
             .send_async_safe(wire::TakerToMaker::Settlement {
@@ -81,11 +90,47 @@ impl Actor {
             })
             .await?;
 
-        Ok(CollaborativeSettlement::new(
-            tx,
-            payout_script_pubkey,
-            self.proposal.price,
-        )?)
+        Ok(settlement)
+    }
+
+    async fn handle_countered(&mut self, taker: Amount, maker: Amount, price: Price) -> Result<()> {
+        let order_id = self.cfd.id();
+
+        tracing::info!(%order_id, %price, "Settlement proposal got countered");
+
+        let proposal = self
+            .cfd
+            .counter_collaborative_settlement(price, self.n_payouts)?;
+        ensure!(
+            proposal.taker == taker && proposal.maker == maker,
+            "Payouts of counter-proposal do not match its price {}",
+            price
+        );
+
+        self.proposal = proposal.clone();
+        self.countered = true;
+        self.update_proposal(Some((proposal, SettlementKind::Incoming)))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn reject_counter(&mut self, ctx: &mut xtra::Context<Self>) {
+        let order_id = self.cfd.id();
+
+        if let Err(e) = self
+            .connection
+            .send_async_safe(wire::TakerToMaker::Settlement {
+                order_id,
+                msg: wire::taker_to_maker::Settlement::RejectCounter,
+            })
+            .await
+        {
+            tracing::warn!(%order_id, "Failed to reject settlement counter-proposal: {:#}", e);
+        }
+
+        // The CFD stays open, there is nothing to complete
+        ctx.stop();
     }
 
     async fn handle_rejected(&mut self) -> Result<()> {
@@ -178,12 +223,76 @@ impl Actor {
                     Completed::rejected(order_id)
                 }
             }
+            wire::maker_to_taker::Settlement::Counter {
+                taker,
+                maker,
+                price,
+            } => {
+                if let Err(e) = self.handle_countered(taker, maker, price).await {
+                    tracing::warn!(%order_id, "Rejecting settlement counter-proposal: {:#}", e);
+                    self.reject_counter(ctx).await;
+                }
+
+                // Wait for the user to accept or reject the counter-proposal
+                return;
+            }
         };
 
         self.complete(completed, ctx).await;
     }
+
+    async fn handle(&mut self, _: AcceptCounter, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        ensure!(self.countered, "No settlement counter-proposal to accept");
+
+        let order_id = self.cfd.id();
+        let completed = match self.handle_confirmed().await {
+            Ok(settlement) => Completed::Succeeded {
+                order_id,
+                payload: settlement,
+            },
+            Err(e) => Completed::Failed { error: e, order_id },
+        };
+
+        self.complete(completed, ctx).await;
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, _: RejectCounter, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        ensure!(self.countered, "No settlement counter-proposal to reject");
+
+        tracing::info!(order_id = %self.cfd.id(), "Rejecting settlement counter-proposal");
+
+        self.reject_counter(ctx).await;
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, _: Cancel, ctx: &mut xtra::Context<Self>) {
+        let order_id = self.cfd.id();
+        tracing::info!(%order_id, "Settlement proposal got cancelled");
+
+        self.complete(
+            Completed::Failed {
+                order_id,
+                error: anyhow::anyhow!("Collaborative settlement was cancelled"),
+            },
+            ctx,
+        )
+        .await;
+    }
 }
 
+/// Message to abort a collaborative settlement that is stuck, e.g. because the maker stopped
+/// responding.
+pub struct Cancel;
+
+/// Message to settle at the price the maker countered our proposal with.
+pub struct AcceptCounter;
+
+/// Message to keep the CFD open instead of settling at the price the maker countered with.
+pub struct RejectCounter;
+
 impl ActorName for Actor {
     fn actor_name() -> String {
         "Taker collab settlement".to_string()
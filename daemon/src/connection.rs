@@ -1,8 +1,11 @@
 use crate::address_map::AddressMap;
 use crate::address_map::Stopping;
+use crate::clock::Clock;
 use crate::collab_settlement_taker;
 use crate::model::cfd::OrderId;
+use crate::model::DisplayName;
 use crate::model::Identity;
+use crate::model::Leverage;
 use crate::model::Price;
 use crate::model::Timestamp;
 use crate::model::Usd;
@@ -24,9 +27,11 @@ use bdk::bitcoin::Amount;
 use futures::SinkExt;
 use futures::StreamExt;
 use futures::TryStreamExt;
+use serde::Serialize;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
-use std::time::SystemTime;
+use time::OffsetDateTime;
 use tokio::net::TcpStream;
 use tokio::sync::watch;
 use tokio_util::codec::Framed;
@@ -41,7 +46,7 @@ const CONNECT_TO_MAKER_INTERVAL: Duration = Duration::from_secs(5);
 #[allow(clippy::large_enum_variant)]
 enum State {
     Connected {
-        last_heartbeat: SystemTime,
+        last_heartbeat: OffsetDateTime,
         write: wire::Write<wire::MakerToTaker, wire::TakerToMaker>,
         _tasks: Tasks,
     },
@@ -69,10 +74,10 @@ impl State {
         Ok(())
     }
 
-    fn handle_incoming_heartbeat(&mut self) {
+    fn handle_incoming_heartbeat(&mut self, now: OffsetDateTime) {
         match self {
             State::Connected { last_heartbeat, .. } => {
-                *last_heartbeat = SystemTime::now();
+                *last_heartbeat = now;
             }
             State::Disconnected => {
                 debug_assert!(false, "Received heartbeat in disconnected state")
@@ -80,15 +85,17 @@ impl State {
         }
     }
 
-    fn disconnect_if_last_heartbeat_older_than(&mut self, timeout: Duration) -> bool {
-        let duration_since_last_heartbeat = match self {
-            State::Connected { last_heartbeat, .. } => SystemTime::now()
-                .duration_since(*last_heartbeat)
-                .expect("clock is monotonic"),
+    fn disconnect_if_last_heartbeat_older_than(
+        &mut self,
+        timeout: Duration,
+        now: OffsetDateTime,
+    ) -> bool {
+        let last_heartbeat = match self {
+            State::Connected { last_heartbeat, .. } => *last_heartbeat,
             State::Disconnected => return false,
         };
 
-        if duration_since_last_heartbeat < timeout {
+        if !is_heartbeat_stale(last_heartbeat, timeout, now) {
             return false;
         }
 
@@ -98,6 +105,15 @@ impl State {
     }
 }
 
+/// Whether the last heartbeat was received longer than `timeout` ago.
+fn is_heartbeat_stale(
+    last_heartbeat: OffsetDateTime,
+    timeout: Duration,
+    now: OffsetDateTime,
+) -> bool {
+    now - last_heartbeat >= timeout
+}
+
 pub struct Actor {
     status_sender: watch::Sender<ConnectionStatus>,
     identity_sk: x25519_dalek::StaticSecret,
@@ -105,10 +121,13 @@ pub struct Actor {
     /// Max duration since the last heartbeat until we die.
     heartbeat_timeout: Duration,
     connect_timeout: Duration,
+    display_name: Option<DisplayName>,
+    min_compatible_version: Version,
     state: State,
     setup_actors: AddressMap<OrderId, setup_taker::Actor>,
     collab_settlement_actors: AddressMap<OrderId, collab_settlement_taker::Actor>,
     rollover_actors: AddressMap<OrderId, rollover_taker::Actor>,
+    clock: Arc<dyn Clock>,
 }
 
 pub struct Connect {
@@ -123,9 +142,15 @@ pub struct MakerStreamMessage {
 /// Private message to measure the current pulse (i.e. check when we received the last heartbeat).
 struct MeasurePulse;
 
+/// Private message to signal that the maker closed the connection.
+struct MakerClosedConnection;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum ConnectionStatus {
-    Online,
+    Online {
+        /// The name the maker introduced itself with, if any.
+        maker_name: Option<DisplayName>,
+    },
     Offline {
         reason: Option<ConnectionCloseReason>,
     },
@@ -137,6 +162,12 @@ pub enum ConnectionCloseReason {
         taker_version: Version,
         maker_version: Version,
     },
+    /// The maker did not send a heartbeat in time.
+    Timeout,
+    /// Reading from the connection failed.
+    NetworkError,
+    /// The maker closed the connection.
+    RemoteClosed,
 }
 
 /// Message sent from the `setup_taker::Actor` to the
@@ -149,6 +180,8 @@ pub enum ConnectionCloseReason {
 pub struct TakeOrder {
     pub order_id: OrderId,
     pub quantity: Usd,
+    pub fee_rate: u32,
+    pub leverage: Leverage,
     pub address: xtra::Address<setup_taker::Actor>,
 }
 
@@ -174,6 +207,9 @@ impl Actor {
         identity_sk: x25519_dalek::StaticSecret,
         hearthbeat_timeout: Duration,
         connect_timeout: Duration,
+        display_name: Option<DisplayName>,
+        min_compatible_version: Version,
+        clock: Arc<dyn Clock>,
     ) -> Self {
         Self {
             status_sender,
@@ -183,10 +219,22 @@ impl Actor {
             state: State::Disconnected,
             setup_actors: AddressMap::default(),
             connect_timeout,
+            display_name,
+            min_compatible_version,
             collab_settlement_actors: AddressMap::default(),
             rollover_actors: AddressMap::default(),
+            clock,
         }
     }
+
+    fn disconnect(&mut self, reason: ConnectionCloseReason) {
+        self.state = State::Disconnected;
+        self.status_sender
+            .send(ConnectionStatus::Offline {
+                reason: Some(reason),
+            })
+            .expect("watch receiver to outlive the actor");
+    }
 }
 
 #[xtra_productivity(message_impl = false)]
@@ -216,6 +264,8 @@ impl Actor {
             .send(wire::TakerToMaker::TakeOrder {
                 order_id: msg.order_id,
                 quantity: msg.quantity,
+                fee_rate: Some(msg.fee_rate),
+                leverage: Some(msg.leverage),
             })
             .await?;
 
@@ -319,7 +369,7 @@ impl Actor {
                 )
             })? {
             Ok(Some(wire::MakerToTaker::Hello(maker_version))) => {
-                if our_version != maker_version {
+                if !maker_version.is_compatible(&self.min_compatible_version) {
                     self.status_sender
                         .send(ConnectionStatus::Offline {
                             reason: Some(ConnectionCloseReason::VersionMismatch {
@@ -335,6 +385,14 @@ impl Actor {
                         maker_version,
                     )
                 }
+
+                if our_version != maker_version {
+                    tracing::warn!(
+                        "Maker is on version {} but we are on version {}, staying connected because the versions are compatible",
+                        maker_version,
+                        our_version,
+                    );
+                }
             }
             unexpected_message => {
                 bail!(
@@ -345,24 +403,59 @@ impl Actor {
             }
         }
 
-        tracing::info!(address = %maker_addr, "Established connection to maker");
+        write
+            .send(TakerToMaker::Introduction {
+                display_name: self.display_name.as_ref().map(|name| name.to_string()),
+            })
+            .await?;
+
+        let maker_name = match read
+            .try_next()
+            .timeout(Duration::from_secs(10))
+            .await
+            .with_context(|| {
+                format!(
+                    "Maker {} did not introduce itself within 10 seconds, dropping connection",
+                    maker_identity
+                )
+            })? {
+            Ok(Some(wire::MakerToTaker::Introduction { display_name })) => {
+                display_name.and_then(|name| DisplayName::sanitize(&name))
+            }
+            unexpected_message => {
+                bail!(
+                    "Unexpected message {:?} from maker {}",
+                    unexpected_message,
+                    maker_identity
+                )
+            }
+        };
+
+        tracing::info!(address = %maker_addr, maker_name = ?maker_name, "Established connection to maker");
 
         let this = ctx.address().expect("self to be alive");
 
         let mut tasks = Tasks::default();
-        tasks.add(this.attach_stream(read.map(move |item| MakerStreamMessage { item })));
+        tasks.add(async move {
+            this.clone()
+                .attach_stream(read.map(move |item| MakerStreamMessage { item }))
+                .await;
+
+            // The stream ends once the maker closes the connection
+            let _ = this.send(MakerClosedConnection).await;
+        });
         tasks.add(
             ctx.notify_interval(self.heartbeat_timeout, || MeasurePulse)
                 .expect("we just started"),
         );
 
         self.state = State::Connected {
-            last_heartbeat: SystemTime::now(),
+            last_heartbeat: self.clock.now(),
             write,
             _tasks: tasks,
         };
         self.status_sender
-            .send(ConnectionStatus::Online)
+            .send(ConnectionStatus::Online { maker_name })
             .expect("receiver to outlive the actor");
 
         Ok(())
@@ -375,6 +468,11 @@ impl Actor {
     ) -> KeepRunning {
         let msg = match message.item {
             Ok(msg) => msg,
+            Err(e) if e.is::<std::io::Error>() => {
+                tracing::warn!("Connection to maker failed: {:#}", e);
+                self.disconnect(ConnectionCloseReason::NetworkError);
+                return KeepRunning::Yes;
+            }
             Err(e) => {
                 tracing::warn!("Error while receiving message from maker: {:#}", e);
                 return KeepRunning::Yes;
@@ -385,7 +483,7 @@ impl Actor {
 
         match msg {
             wire::MakerToTaker::Heartbeat => {
-                self.state.handle_incoming_heartbeat();
+                self.state.handle_incoming_heartbeat(self.clock.now());
             }
             wire::MakerToTaker::ConfirmOrder(order_id) => {
                 if self
@@ -478,6 +576,9 @@ impl Actor {
             wire::MakerToTaker::Hello(_) => {
                 tracing::warn!("Ignoring unexpected Hello message from maker. Hello is only expected when opening a new connection.")
             }
+            wire::MakerToTaker::Introduction { .. } => {
+                tracing::warn!("Ignoring unexpected Introduction message from maker. Introduction is only expected when opening a new connection.")
+            }
         }
         KeepRunning::Yes
     }
@@ -485,41 +586,130 @@ impl Actor {
     fn handle_measure_pulse(&mut self, _: MeasurePulse) {
         if self
             .state
-            .disconnect_if_last_heartbeat_older_than(self.heartbeat_timeout)
+            .disconnect_if_last_heartbeat_older_than(self.heartbeat_timeout, self.clock.now())
         {
             self.status_sender
-                .send(ConnectionStatus::Offline { reason: None })
+                .send(ConnectionStatus::Offline {
+                    reason: Some(ConnectionCloseReason::Timeout),
+                })
                 .expect("watch receiver to outlive the actor");
         }
     }
+
+    fn handle_maker_closed_connection(&mut self, _: MakerClosedConnection) {
+        if matches!(self.state, State::Connected { .. }) {
+            tracing::info!("Maker closed the connection");
+            self.disconnect(ConnectionCloseReason::RemoteClosed);
+        }
+    }
 }
 
 impl xtra::Actor for Actor {}
 
+/// How reliably the maker could be reached at one of its addresses.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AddressHealth {
+    pub address: SocketAddr,
+    /// Number of failed connection attempts since the last successful one.
+    pub consecutive_failures: u32,
+    pub last_success: Option<Timestamp>,
+}
+
+/// The addresses the maker resolved to, together with their health.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct MakerAddresses(Vec<AddressHealth>);
+
+impl MakerAddresses {
+    pub fn new(addresses: Vec<SocketAddr>) -> Self {
+        Self(
+            addresses
+                .into_iter()
+                .map(|address| AddressHealth {
+                    address,
+                    consecutive_failures: 0,
+                    last_success: None,
+                })
+                .collect(),
+        )
+    }
+
+    /// The addresses in the order they should be tried in.
+    ///
+    /// Addresses that failed least are preferred, among those the most recently successful ones.
+    /// Otherwise, the order of resolution is kept.
+    pub fn by_priority(&self) -> Vec<SocketAddr> {
+        let mut addresses = self.0.iter().collect::<Vec<_>>();
+        addresses.sort_by_key(|health| {
+            (
+                health.consecutive_failures,
+                std::cmp::Reverse(health.last_success),
+            )
+        });
+
+        addresses.into_iter().map(|health| health.address).collect()
+    }
+
+    pub fn record_success(&mut self, address: SocketAddr, timestamp: Timestamp) {
+        if let Some(health) = self.get_mut(address) {
+            health.consecutive_failures = 0;
+            health.last_success = Some(timestamp);
+        }
+    }
+
+    pub fn record_failure(&mut self, address: SocketAddr) {
+        if let Some(health) = self.get_mut(address) {
+            health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn get_mut(&mut self, address: SocketAddr) -> Option<&mut AddressHealth> {
+        self.0.iter_mut().find(|health| health.address == address)
+    }
+}
+
 // TODO: Move the reconnection logic inside the connection::Actor instead of
 // depending on a watch channel
 pub async fn connect(
     mut maker_online_status_feed_receiver: watch::Receiver<ConnectionStatus>,
     connection_actor_addr: xtra::Address<Actor>,
     maker_identity: Identity,
-    maker_addresses: Vec<SocketAddr>,
+    maker_addresses_sender: watch::Sender<MakerAddresses>,
+    clock: Arc<dyn Clock>,
 ) {
+    let mut maker_addresses = maker_addresses_sender.borrow().clone();
+
     loop {
         let connection_status = maker_online_status_feed_receiver.borrow().clone();
         if matches!(connection_status, ConnectionStatus::Offline { .. }) {
             tracing::debug!("No connection to the maker");
             'connect: loop {
-                for address in &maker_addresses {
+                for address in maker_addresses.by_priority() {
                     let connect_msg = Connect {
                         maker_identity,
-                        maker_addr: *address,
+                        maker_addr: address,
                     };
 
-                    if let Err(e) = connection_actor_addr
+                    let result = connection_actor_addr
                         .send(connect_msg)
                         .await
-                        .expect("Taker actor to be present")
-                    {
+                        .expect("Taker actor to be present");
+
+                    match &result {
+                        Ok(()) => maker_addresses.record_success(address, clock.timestamp()),
+                        Err(_) => maker_addresses.record_failure(address),
+                    }
+                    let _ = maker_addresses_sender.send(maker_addresses.clone());
+
+                    if let Err(e) = result {
                         tracing::warn!(%address, "Failed to establish connection: {:#}", e);
                         continue;
                     }
@@ -541,3 +731,49 @@ pub async fn connect(
             .expect("watch channel should outlive the future");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use time::macros::datetime;
+
+    #[test]
+    fn failing_address_is_tried_after_healthy_ones() {
+        let failing = "127.0.0.1:9999".parse().unwrap();
+        let healthy = "127.0.0.2:9999".parse().unwrap();
+        let untried = "127.0.0.3:9999".parse().unwrap();
+        let mut addresses = MakerAddresses::new(vec![failing, healthy, untried]);
+
+        addresses.record_failure(failing);
+        addresses.record_success(healthy, Timestamp::new(1));
+
+        assert_eq!(addresses.by_priority(), vec![healthy, untried, failing]);
+    }
+
+    #[test]
+    fn address_recovers_after_successful_connection() {
+        let first = "127.0.0.1:9999".parse().unwrap();
+        let second = "127.0.0.2:9999".parse().unwrap();
+        let mut addresses = MakerAddresses::new(vec![first, second]);
+
+        addresses.record_failure(first);
+        addresses.record_success(second, Timestamp::new(1));
+        addresses.record_success(first, Timestamp::new(2));
+
+        assert_eq!(addresses.by_priority(), vec![first, second]);
+    }
+
+    #[test]
+    fn heartbeat_becomes_stale_after_timeout() {
+        let clock = ManualClock::new(datetime!(2021-11-19 10:00:00).assume_utc());
+        let last_heartbeat = clock.now();
+        let timeout = Duration::from_secs(5);
+
+        clock.advance(time::Duration::seconds(4));
+        assert!(!is_heartbeat_stale(last_heartbeat, timeout, clock.now()));
+
+        clock.advance(time::Duration::seconds(1));
+        assert!(is_heartbeat_stale(last_heartbeat, timeout, clock.now()));
+    }
+}
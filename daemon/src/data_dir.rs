@@ -0,0 +1,105 @@
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The `configured` path if one was given and we can write to it, `default` otherwise.
+pub async fn configured_or_default(
+    configured: Option<PathBuf>,
+    default: PathBuf,
+) -> Result<PathBuf> {
+    match configured {
+        Some(path) => {
+            ensure_writable(&path).await?;
+            Ok(path)
+        }
+        None => Ok(default),
+    }
+}
+
+/// Ensures that we can write to `path`, creating its parent directory if necessary.
+///
+/// Permissions don't tell whether the directory is writable, e.g. on a read-only mount, so we
+/// write a file next to `path` and remove it again.
+pub async fn ensure_writable(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+
+    if path.exists() {
+        tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("{} is not writable", path.display()))?;
+
+        return Ok(());
+    }
+
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => bail!("{} is not a file", path.display()),
+    };
+    let probe = dir.join(format!(".{}.write-probe", file_name));
+
+    tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .await
+        .with_context(|| format!("Directory {} is not writable", dir.display()))?;
+    tokio::fs::remove_file(&probe)
+        .await
+        .with_context(|| format!("Failed to remove {}", probe.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::cfd::OrderId;
+
+    #[tokio::test]
+    async fn override_below_a_file_is_rejected() {
+        let file = std::env::temp_dir().join(format!("data-dir-{}", OrderId::default()));
+        std::fs::write(&file, "").unwrap();
+
+        let result = configured_or_default(Some(file.join("taker_seed")), file.clone()).await;
+
+        assert!(result.is_err());
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writable_override_is_used_without_leaving_files_behind() {
+        let dir = std::env::temp_dir().join(format!("data-dir-{}", OrderId::default()));
+        let path = dir.join("taker_seed");
+
+        let resolved = configured_or_default(Some(path.clone()), PathBuf::from("taker_seed"))
+            .await
+            .unwrap();
+
+        assert_eq!(resolved, path);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn derived_default_is_used_without_override() {
+        let default = std::env::temp_dir()
+            .join(format!("data-dir-{}", OrderId::default()))
+            .join("taker_seed");
+
+        let resolved = configured_or_default(None, default.clone()).await.unwrap();
+
+        assert_eq!(resolved, default);
+        assert!(!default.parent().unwrap().exists());
+    }
+}
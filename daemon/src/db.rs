@@ -3,17 +3,62 @@ use crate::model::cfd::CfdEvent;
 use crate::model::cfd::Event;
 use crate::model::cfd::OrderId;
 use crate::model::cfd::Role;
+use crate::model::cfd::WatchOnlyDlc;
 use crate::model::Identity;
 use crate::model::Leverage;
 use crate::model::Position;
 use crate::model::Price;
+use crate::model::Timestamp;
 use crate::model::Usd;
+use anyhow::ensure;
+use anyhow::Context;
 use anyhow::Result;
 use sqlx::pool::PoolConnection;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::sqlite::SqliteJournalMode;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::Connection;
+use sqlx::Row;
 use sqlx::Sqlite;
 use sqlx::SqlitePool;
+use std::fmt;
+use std::path::Path;
 use time::Duration;
 
+/// How long a connection waits by default for another connection to release its lock on the
+/// database before failing with "database is locked".
+pub const DEFAULT_BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Open the database at `path` with up to `max_connections` connections, creating it if missing.
+///
+/// The database is journaled with a write-ahead log so that reads don't have to wait for writes
+/// of other actors to complete and vice versa. A connection waits up to `busy_timeout` for the
+/// lock of another connection.
+pub async fn connect(
+    path: &Path,
+    max_connections: u32,
+    busy_timeout: std::time::Duration,
+) -> Result<SqlitePool> {
+    ensure!(
+        max_connections > 0,
+        "The database needs at least one connection"
+    );
+
+    let options = SqliteConnectOptions::new()
+        .create_if_missing(true)
+        .filename(path)
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(busy_timeout);
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(max_connections)
+        .connect_with(options)
+        .await
+        .with_context(|| format!("Failed to open database {}", path.display()))?;
+
+    Ok(pool)
+}
+
 pub async fn run_migrations(pool: &SqlitePool) -> anyhow::Result<()> {
     sqlx::migrate!("./migrations").run(pool).await?;
     Ok(())
@@ -72,16 +117,18 @@ pub async fn append_event(
             cfd_id,
             name,
             data,
-            created_at
+            created_at,
+            version
         ) values (
             (select id from cfds where cfds.uuid = $1),
-            $2, $3, $4
+            $2, $3, $4, $5
         )"##,
     )
     .bind(&event.id)
     .bind(&event_name)
     .bind(&event_data)
     .bind(&event.timestamp)
+    .bind(CfdEvent::VERSION)
     .execute(conn)
     .await?;
 
@@ -145,7 +192,8 @@ pub async fn load_cfd(id: OrderId, conn: &mut PoolConnection<Sqlite>) -> Result<
         select
             name,
             data,
-            created_at as "created_at: crate::model::Timestamp"
+            created_at as "created_at: crate::model::Timestamp",
+            version
         from
             events
         where
@@ -160,7 +208,8 @@ pub async fn load_cfd(id: OrderId, conn: &mut PoolConnection<Sqlite>) -> Result<
         Ok(Event {
             timestamp: row.created_at,
             id,
-            event: CfdEvent::from_json(row.name, row.data)?,
+            event: CfdEvent::from_json(row.name, row.data, u32::try_from(row.version)?)
+                .with_context(|| format!("Failed to load event of CFD {}", id))?,
         })
     })
     .collect::<Result<Vec<_>>>()?;
@@ -186,8 +235,334 @@ pub async fn load_all_cfd_ids(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<O
     Ok(ids)
 }
 
+/// An event that cannot be loaded as part of its CFD.
+#[derive(Debug)]
+pub struct CorruptEvent {
+    /// The primary key of the event in the `events` table.
+    pub row_id: i64,
+    pub corruption: Corruption,
+}
+
+#[derive(Debug)]
+pub enum Corruption {
+    /// The event references a CFD that does not exist.
+    Orphaned { cfd_id: i64 },
+    /// The event belongs to an existing CFD but cannot be deserialized.
+    Undecodable {
+        order_id: OrderId,
+        error: anyhow::Error,
+    },
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Corruption::Orphaned { cfd_id } => {
+                write!(
+                    f,
+                    "Orphaned event of non-existent CFD with row id {}",
+                    cfd_id
+                )
+            }
+            Corruption::Undecodable { order_id, error } => {
+                write!(f, "Undecodable event of CFD {}: {:#}", order_id, error)
+            }
+        }
+    }
+}
+
+/// Persist the CFD of a third party to watch, see [`crate::watch_only`].
+pub async fn insert_watched_cfd(
+    order_id: OrderId,
+    dlc: &WatchOnlyDlc,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        insert into watched_cfds (
+            uuid,
+            dlc
+        ) values ($1, $2)"#,
+    )
+    .bind(&order_id)
+    .bind(serde_json::to_string(dlc)?)
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("Failed to persist watched CFD {}", order_id))?;
+
+    Ok(())
+}
+
+pub async fn load_watched_cfds(
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<Vec<(OrderId, WatchOnlyDlc)>> {
+    sqlx::query(
+        r#"
+        select
+            uuid,
+            dlc
+        from
+            watched_cfds
+        order by
+            id
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let order_id = row.try_get::<OrderId, _>("uuid")?;
+        let dlc = serde_json::from_str(row.try_get("dlc")?)
+            .with_context(|| format!("Failed to parse DLC of watched CFD {}", order_id))?;
+
+        Ok((order_id, dlc))
+    })
+    .collect()
+}
+
+/// Record the identity we presented to the maker when taking the order of the CFD.
+pub async fn insert_session_identity(
+    order_id: OrderId,
+    identity: Identity,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        insert into session_identities (
+            uuid,
+            identity
+        ) values ($1, $2)"#,
+    )
+    .bind(&order_id)
+    .bind(&identity)
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("Failed to persist session identity of CFD {}", order_id))?;
+
+    Ok(())
+}
+
+/// The identity we presented to the maker when taking the order of the CFD, `None` for CFDs
+/// taken before session identities were recorded.
+pub async fn load_session_identity(
+    order_id: OrderId,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<Option<Identity>> {
+    let row = sqlx::query(
+        r#"
+        select
+            identity
+        from
+            session_identities
+        where
+            uuid = $1
+        "#,
+    )
+    .bind(&order_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    row.map(|row| row.try_get("identity"))
+        .transpose()
+        .map_err(anyhow::Error::from)
+}
+
+/// Find all events that would be skipped or would fail [`load_cfd`].
+pub async fn find_corrupt_events(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<CorruptEvent>> {
+    let rows = sqlx::query(
+        r#"
+        select
+            events.id as row_id,
+            events.cfd_id as cfd_id,
+            cfds.uuid as uuid,
+            events.name as name,
+            events.data as data,
+            events.version as version
+        from
+            events
+        left join
+            cfds on events.cfd_id = cfds.id
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut corrupt_events = Vec::new();
+
+    for row in rows {
+        let row_id = row.try_get::<i64, _>("row_id")?;
+        let cfd_id = row.try_get::<i64, _>("cfd_id")?;
+
+        let order_id = match row.try_get::<Option<OrderId>, _>("uuid")? {
+            Some(order_id) => order_id,
+            None => {
+                corrupt_events.push(CorruptEvent {
+                    row_id,
+                    corruption: Corruption::Orphaned { cfd_id },
+                });
+                continue;
+            }
+        };
+
+        let decoded = u32::try_from(row.try_get::<i64, _>("version")?)
+            .context("Invalid event version")
+            .and_then(|version| {
+                CfdEvent::from_json(row.try_get("name")?, row.try_get("data")?, version)
+            });
+
+        if let Err(error) = decoded {
+            corrupt_events.push(CorruptEvent {
+                row_id,
+                corruption: Corruption::Undecodable { order_id, error },
+            });
+        }
+    }
+
+    Ok(corrupt_events)
+}
+
+/// Move `event` from the `events` into the `quarantined_events` table.
+///
+/// Quarantined events are kept for manual inspection but no longer loaded with their CFD.
+pub async fn quarantine_event(
+    event: &CorruptEvent,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<()> {
+    let mut tx = conn.begin().await?;
+
+    let query_result = sqlx::query(
+        r#"
+        insert into quarantined_events (
+            id,
+            cfd_id,
+            name,
+            data,
+            created_at,
+            version,
+            reason,
+            quarantined_at
+        )
+        select
+            id, cfd_id, name, data, created_at, version, $2, $3
+        from
+            events
+        where
+            id = $1
+        "#,
+    )
+    .bind(event.row_id)
+    .bind(event.corruption.to_string())
+    .bind(Timestamp::now())
+    .execute(&mut tx)
+    .await?;
+
+    if query_result.rows_affected() != 1 {
+        anyhow::bail!("failed to quarantine event {}", event.row_id);
+    }
+
+    sqlx::query("delete from events where id = $1")
+        .bind(event.row_id)
+        .execute(&mut tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Move the CFD with `order_id` and all of its events into the `quarantined_cfds` and
+/// `quarantined_events` tables.
+///
+/// A quarantined CFD is kept for manual inspection but no longer loaded at all. Its session
+/// identity is deleted, nothing refers to it once the CFD is gone.
+pub async fn quarantine_cfd(
+    order_id: OrderId,
+    reason: &str,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<()> {
+    let mut tx = conn.begin().await?;
+    let quarantined_at = Timestamp::now();
+
+    let query_result = sqlx::query(
+        r#"
+        insert into quarantined_cfds (
+            id,
+            uuid,
+            position,
+            initial_price,
+            leverage,
+            settlement_time_interval_hours,
+            quantity_usd,
+            counterparty_network_identity,
+            role,
+            reason,
+            quarantined_at
+        )
+        select
+            id, uuid, position, initial_price, leverage, settlement_time_interval_hours,
+            quantity_usd, counterparty_network_identity, role, $2, $3
+        from
+            cfds
+        where
+            uuid = $1
+        "#,
+    )
+    .bind(&order_id)
+    .bind(reason)
+    .bind(&quarantined_at)
+    .execute(&mut tx)
+    .await?;
+
+    if query_result.rows_affected() != 1 {
+        anyhow::bail!("failed to quarantine CFD {}", order_id);
+    }
+
+    sqlx::query(
+        r#"
+        insert into quarantined_events (
+            id,
+            cfd_id,
+            name,
+            data,
+            created_at,
+            version,
+            reason,
+            quarantined_at
+        )
+        select
+            id, cfd_id, name, data, created_at, version, $2, $3
+        from
+            events
+        where
+            cfd_id = (select id from cfds where uuid = $1)
+        "#,
+    )
+    .bind(&order_id)
+    .bind(reason)
+    .bind(&quarantined_at)
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query("delete from events where cfd_id = (select id from cfds where uuid = $1)")
+        .bind(&order_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("delete from session_identities where uuid = $1")
+        .bind(&order_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("delete from cfds where uuid = $1")
+        .bind(&order_id)
+        .execute(&mut tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::model::cfd::Cfd;
     use crate::model::cfd::Role;
@@ -261,16 +636,272 @@ mod tests {
         assert_eq!(events, vec![event1, event2])
     }
 
-    async fn setup_test_db() -> PoolConnection<Sqlite> {
+    #[tokio::test]
+    async fn test_load_events_stored_without_version() {
+        let mut conn = setup_test_db().await;
+
+        let cfd = Cfd::dummy().insert(&mut conn).await;
+        let timestamp = Timestamp::now();
+
+        // simulate an event that was stored before event versioning was introduced
+        sqlx::query(
+            r#"
+            insert into events (
+                cfd_id,
+                name,
+                data,
+                created_at
+            ) values (
+                (select id from cfds where cfds.uuid = $1),
+                $2, $3, $4
+            )"#,
+        )
+        .bind(&cfd.id())
+        .bind("OfferRejected")
+        .bind("null")
+        .bind(&timestamp)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+        let (_, events) = load_cfd(cfd.id(), &mut conn).await.unwrap();
+
+        assert_eq!(
+            events,
+            vec![Event {
+                timestamp,
+                id: cfd.id(),
+                event: CfdEvent::OfferRejected,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn orphaned_event_is_detected() {
+        let mut conn = setup_test_db().await;
+
+        let cfd = Cfd::dummy().insert(&mut conn).await;
+        append_event(Event::new(cfd.id(), CfdEvent::OfferRejected), &mut conn)
+            .await
+            .unwrap();
+        insert_raw_event(42, "OfferRejected", &mut conn).await;
+
+        let corrupt_events = find_corrupt_events(&mut conn).await.unwrap();
+
+        assert_eq!(corrupt_events.len(), 1);
+        assert!(matches!(
+            corrupt_events[0].corruption,
+            Corruption::Orphaned { cfd_id: 42 }
+        ));
+    }
+
+    #[tokio::test]
+    async fn undecodable_event_is_detected() {
+        let mut conn = setup_test_db().await;
+
+        let cfd = Cfd::dummy().insert(&mut conn).await;
+        let cfd_id = cfd_row_id(&cfd, &mut conn).await;
+        insert_raw_event(cfd_id, "NotAnEvent", &mut conn).await;
+
+        let corrupt_events = find_corrupt_events(&mut conn).await.unwrap();
+
+        assert_eq!(corrupt_events.len(), 1);
+        assert!(matches!(
+            corrupt_events[0].corruption,
+            Corruption::Undecodable { order_id, .. } if order_id == cfd.id()
+        ));
+        assert!(load_cfd(cfd.id(), &mut conn).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn quarantined_event_is_no_longer_found() {
+        let mut conn = setup_test_db().await;
+
+        let cfd = Cfd::dummy().insert(&mut conn).await;
+        let event = Event::new(cfd.id(), CfdEvent::OfferRejected);
+        append_event(event.clone(), &mut conn).await.unwrap();
+        insert_raw_event(42, "OfferRejected", &mut conn).await;
+
+        for corrupt_event in find_corrupt_events(&mut conn).await.unwrap() {
+            quarantine_event(&corrupt_event, &mut conn).await.unwrap();
+        }
+
+        let (_, events) = load_cfd(cfd.id(), &mut conn).await.unwrap();
+        assert_eq!(events, vec![event]);
+        assert!(find_corrupt_events(&mut conn).await.unwrap().is_empty());
+
+        let quarantined = sqlx::query("select cfd_id from quarantined_events")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].get::<i64, _>("cfd_id"), 42);
+    }
+
+    #[tokio::test]
+    async fn quarantined_cfd_is_no_longer_loaded() {
+        let mut conn = setup_test_db().await;
+
+        let cfd = Cfd::dummy().insert(&mut conn).await;
+        let other_cfd = Cfd::dummy().insert(&mut conn).await;
+        append_event(Event::new(cfd.id(), CfdEvent::OfferRejected), &mut conn)
+            .await
+            .unwrap();
+        let cfd_id = cfd_row_id(&cfd, &mut conn).await;
+        insert_raw_event(cfd_id, "NotAnEvent", &mut conn).await;
+        let identity = Identity::new(x25519_dalek::PublicKey::from([7u8; 32]));
+        insert_session_identity(cfd.id(), identity, &mut conn)
+            .await
+            .unwrap();
+
+        quarantine_cfd(cfd.id(), "Undecodable event", &mut conn)
+            .await
+            .unwrap();
+
+        assert!(load_cfd(cfd.id(), &mut conn).await.is_err());
+        assert_eq!(
+            load_session_identity(cfd.id(), &mut conn).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            load_all_cfd_ids(&mut conn).await.unwrap(),
+            vec![other_cfd.id()]
+        );
+        assert!(find_corrupt_events(&mut conn).await.unwrap().is_empty());
+
+        let quarantined_cfds = sqlx::query("select uuid from quarantined_cfds")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(quarantined_cfds.len(), 1);
+        assert_eq!(quarantined_cfds[0].get::<OrderId, _>("uuid"), cfd.id());
+
+        let quarantined_events =
+            sqlx::query("select name from quarantined_events where cfd_id = $1")
+                .bind(cfd_id)
+                .fetch_all(&mut conn)
+                .await
+                .unwrap();
+        assert_eq!(quarantined_events.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn session_identity_is_recorded_per_cfd() {
+        let mut conn = setup_test_db().await;
+
+        let cfd = Cfd::dummy().insert(&mut conn).await;
+        let other_cfd = Cfd::dummy().insert(&mut conn).await;
+        let identity = Identity::new(x25519_dalek::PublicKey::from([7u8; 32]));
+
+        insert_session_identity(cfd.id(), identity, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            load_session_identity(cfd.id(), &mut conn).await.unwrap(),
+            Some(identity)
+        );
+        assert_eq!(
+            load_session_identity(other_cfd.id(), &mut conn)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_appends_and_reads_do_not_fail() {
+        let path = std::env::temp_dir().join(format!("db-test-{}.sqlite", OrderId::default()));
+        let pool = connect(&path, 5, DEFAULT_BUSY_TIMEOUT).await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let cfd = Cfd::dummy()
+            .insert(&mut pool.acquire().await.unwrap())
+            .await;
+
+        let mut tasks = Vec::new();
+        for _ in 0..20 {
+            let (pool, id) = (pool.clone(), cfd.id());
+            tasks.push(tokio::spawn(async move {
+                let mut conn = pool.acquire().await?;
+                append_event(Event::new(id, CfdEvent::OfferRejected), &mut conn).await
+            }));
+
+            let (pool, id) = (pool.clone(), cfd.id());
+            tasks.push(tokio::spawn(async move {
+                let mut conn = pool.acquire().await?;
+                load_cfd(id, &mut conn).await.map(|_| ())
+            }));
+        }
+        let all_done = async {
+            for task in tasks {
+                task.await.unwrap().unwrap();
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(60), all_done)
+            .await
+            .expect("concurrent database access not to deadlock");
+
+        let (_, events) = load_cfd(cfd.id(), &mut pool.acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 20);
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    /// Insert an event with `name` for the CFD with the row id `cfd_id`, bypassing any checks.
+    pub(crate) async fn insert_raw_event(
+        cfd_id: i64,
+        name: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) {
+        sqlx::query(
+            r#"
+            insert into events (
+                cfd_id,
+                name,
+                data,
+                created_at,
+                version
+            ) values ($1, $2, $3, $4, $5)"#,
+        )
+        .bind(cfd_id)
+        .bind(name)
+        .bind("null")
+        .bind(&Timestamp::now())
+        .bind(CfdEvent::VERSION)
+        .execute(conn)
+        .await
+        .unwrap();
+    }
+
+    async fn cfd_row_id(cfd: &Cfd, conn: &mut PoolConnection<Sqlite>) -> i64 {
+        sqlx::query("select id from cfds where uuid = $1")
+            .bind(&cfd.id())
+            .fetch_one(conn)
+            .await
+            .unwrap()
+            .get("id")
+    }
+
+    pub(crate) async fn setup_test_db() -> PoolConnection<Sqlite> {
+        setup_test_pool().await.acquire().await.unwrap()
+    }
+
+    /// An in-memory database for tests that need the pool itself, e.g. to hand it to an actor.
+    pub(crate) async fn setup_test_pool() -> SqlitePool {
         let pool = SqlitePool::connect(":memory:").await.unwrap();
 
         run_migrations(&pool).await.unwrap();
 
-        pool.acquire().await.unwrap()
+        pool
     }
 
     impl Cfd {
-        fn dummy() -> Self {
+        pub(crate) fn dummy() -> Self {
             Self::new(
                 OrderId::default(),
                 Position::Long,
@@ -286,7 +917,7 @@ mod tests {
         }
 
         /// Insert this [`Cfd`] into the database, returning the instance for further chaining.
-        async fn insert(self, conn: &mut PoolConnection<Sqlite>) -> Self {
+        pub(crate) async fn insert(self, conn: &mut PoolConnection<Sqlite>) -> Self {
             insert_cfd(&self, conn).await.unwrap();
 
             self
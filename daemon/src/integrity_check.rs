@@ -0,0 +1,218 @@
+use crate::db;
+use crate::model::cfd::Cfd;
+use crate::model::cfd::OrderId;
+use crate::try_continue;
+use crate::Tasks;
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::pool::PoolConnection;
+use sqlx::Sqlite;
+use std::collections::HashSet;
+use std::time::Duration;
+use xtra_productivity::xtra_productivity;
+
+/// How often the database is checked for corruption while the daemon is running.
+const INTEGRITY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// What to do about CFDs and events found by the integrity check.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Repair {
+    /// Only log the corrupt CFDs and events, leaving them in place for manual inspection.
+    LogOnly,
+    /// Move orphaned events into the `quarantined_events` table and CFDs that cannot be loaded,
+    /// together with all of their events, into the `quarantined_cfds` table.
+    Quarantine,
+}
+
+impl Repair {
+    pub fn from_quarantine(quarantine: bool) -> Self {
+        if quarantine {
+            Self::Quarantine
+        } else {
+            Self::LogOnly
+        }
+    }
+}
+
+/// Check the database for orphaned events and CFDs that cannot be loaded, returning how many were
+/// found.
+///
+/// Only quarantine at startup, before any actor loads a CFD: a CFD is quarantined as a whole
+/// because loading it without some of its events would give a wrong picture of the contract.
+pub async fn check(db: &sqlx::SqlitePool, repair: Repair) -> Result<usize> {
+    tracing::debug!("Checking database integrity");
+
+    let mut conn = db.acquire().await?;
+
+    let mut corrupt_cfds = HashSet::new();
+    let mut orphaned_events = 0;
+
+    for event in db::find_corrupt_events(&mut conn).await? {
+        tracing::error!(row_id = %event.row_id, "{}", event.corruption);
+
+        match &event.corruption {
+            db::Corruption::Orphaned { .. } => {
+                orphaned_events += 1;
+
+                if repair == Repair::Quarantine {
+                    try_continue!(db::quarantine_event(&event, &mut conn).await);
+
+                    tracing::warn!(row_id = %event.row_id, "Quarantined orphaned event");
+                }
+            }
+            db::Corruption::Undecodable { order_id, .. } => {
+                if corrupt_cfds.insert(*order_id) {
+                    quarantine_cfd(*order_id, &event.corruption.to_string(), repair, &mut conn)
+                        .await;
+                }
+            }
+        }
+    }
+
+    for order_id in db::load_all_cfd_ids(&mut conn).await? {
+        if corrupt_cfds.contains(&order_id) {
+            continue;
+        }
+
+        if let Err(e) = rehydrate(order_id, &mut conn).await {
+            let reason = format!("Inapplicable event of CFD {}: {:#}", order_id, e);
+            tracing::error!("{}", reason);
+
+            corrupt_cfds.insert(order_id);
+            quarantine_cfd(order_id, &reason, repair, &mut conn).await;
+        }
+    }
+
+    Ok(orphaned_events + corrupt_cfds.len())
+}
+
+/// Periodically checks the database for corruption that appeared while the daemon is running.
+///
+/// Only logs what it finds, the CFDs are in use by other actors and quarantining them is left to
+/// the check at the next startup.
+pub struct Actor {
+    db: sqlx::SqlitePool,
+    tasks: Tasks,
+}
+
+impl Actor {
+    pub fn new(db: sqlx::SqlitePool) -> Self {
+        Self {
+            db,
+            tasks: Tasks::default(),
+        }
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _msg: CheckIntegrity) -> Result<usize> {
+        check(&self.db, Repair::LogOnly).await
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let fut = ctx
+            .notify_interval(INTEGRITY_CHECK_INTERVAL, || CheckIntegrity)
+            .expect("we are alive");
+
+        self.tasks.add(fut);
+    }
+}
+
+/// Message to trigger the integrity check on a regular interval
+pub struct CheckIntegrity;
+
+/// Rehydrate the CFD with `order_id`, failing if any of its events cannot be applied.
+async fn rehydrate(order_id: OrderId, conn: &mut PoolConnection<Sqlite>) -> Result<Cfd> {
+    let (
+        db::Cfd {
+            id,
+            position,
+            initial_price,
+            leverage,
+            settlement_interval,
+            counterparty_network_identity,
+            role,
+            quantity_usd,
+        },
+        events,
+    ) = db::load_cfd(order_id, conn).await?;
+
+    Cfd::try_rehydrate(
+        id,
+        position,
+        initial_price,
+        leverage,
+        settlement_interval,
+        quantity_usd,
+        counterparty_network_identity,
+        role,
+        events,
+    )
+}
+
+async fn quarantine_cfd(
+    order_id: OrderId,
+    reason: &str,
+    repair: Repair,
+    conn: &mut PoolConnection<Sqlite>,
+) {
+    if repair != Repair::Quarantine {
+        return;
+    }
+
+    match db::quarantine_cfd(order_id, reason, conn).await {
+        Ok(()) => tracing::warn!(%order_id, "Quarantined corrupt CFD"),
+        Err(e) => tracing::error!(%order_id, "Failed to quarantine CFD: {:#}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::cfd::CfdEvent;
+    use crate::model::cfd::Event;
+    use xtra::Actor as _;
+
+    #[tokio::test]
+    async fn cfd_with_inapplicable_event_is_quarantined() {
+        let pool = db::tests::setup_test_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let cfd = Cfd::dummy().insert(&mut conn).await.id();
+        let healthy_cfd = Cfd::dummy().insert(&mut conn).await.id();
+        db::append_event(Event::new(cfd, CfdEvent::MonitoringPaused), &mut conn)
+            .await
+            .unwrap();
+        db::append_event(Event::new(healthy_cfd, CfdEvent::OfferRejected), &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(check(&pool, Repair::LogOnly).await.unwrap(), 1);
+        assert_eq!(db::load_all_cfd_ids(&mut conn).await.unwrap().len(), 2);
+
+        assert_eq!(check(&pool, Repair::Quarantine).await.unwrap(), 1);
+        assert_eq!(
+            db::load_all_cfd_ids(&mut conn).await.unwrap(),
+            vec![healthy_cfd]
+        );
+    }
+
+    #[tokio::test]
+    async fn orphan_inserted_after_startup_is_reported_periodically() {
+        let pool = db::tests::setup_test_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        assert_eq!(check(&pool, Repair::Quarantine).await.unwrap(), 0);
+
+        let integrity_check = Actor::new(pool.clone()).create(None).spawn_global();
+        db::tests::insert_raw_event(42, "OfferRejected", &mut conn).await;
+
+        let found = integrity_check.send(CheckIntegrity).await.unwrap().unwrap();
+
+        assert_eq!(found, 1);
+        assert_eq!(db::find_corrupt_events(&mut conn).await.unwrap().len(), 1);
+    }
+}
@@ -1,18 +1,40 @@
 #![cfg_attr(not(test), warn(clippy::unwrap_used))]
 #![warn(clippy::disallowed_method)]
+use crate::auto_commit::CommitPolicy;
+use crate::auto_refund::RefundStrategy;
 use crate::bitcoin::Txid;
+use crate::clock::Clock;
 use crate::maker_cfd::FromTaker;
 use crate::maker_cfd::TakerConnected;
+use crate::model::cfd::ActionCosts;
 use crate::model::cfd::Cfd;
+use crate::model::cfd::CloseOption;
+use crate::model::cfd::DlcSummary;
+use crate::model::cfd::ExitLevels;
+use crate::model::cfd::Markup;
+use crate::model::cfd::MinimumSize;
 use crate::model::cfd::Order;
 use crate::model::cfd::OrderId;
 use crate::model::cfd::Role;
+use crate::model::cfd::SettlementPriceTolerance;
+use crate::model::cfd::SettlementRejectionPolicy;
+use crate::model::cfd::SignedCommitTx;
+use crate::model::cfd::SlippageBound;
+use crate::model::cfd::WatchOnlyDlc;
+use crate::model::DisplayName;
 use crate::model::Identity;
+use crate::model::Leverage;
 use crate::model::Price;
 use crate::model::Usd;
 use crate::oracle::Attestation;
+use crate::oracle::SettlementEventSelection;
+use crate::oracle::UnknownEventPolicy;
+use crate::price_feed::PricingMode;
+use crate::process_manager::CetPolicy;
 use crate::tokio_ext::FutureExt;
+use crate::wire::Version;
 use address_map::Stopping;
+use anyhow::bail;
 use anyhow::Result;
 use bdk::bitcoin;
 use bdk::bitcoin::Amount;
@@ -23,6 +45,7 @@ use maia::secp256k1_zkp::schnorrsig;
 use maker_cfd::TakerDisconnected;
 use sqlx::SqlitePool;
 use std::future::Future;
+use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
 use tokio::net::TcpListener;
@@ -35,32 +58,40 @@ use xtra::Address;
 pub mod sqlx_ext; // Must come first because it is a macro.
 
 pub mod address_map;
+pub mod archive;
 pub mod auth;
+pub mod auto_commit;
+pub mod auto_refund;
 pub mod auto_rollover;
 pub mod bdk_ext;
 pub mod bitmex_price_feed;
 pub mod cfd_actors;
+pub mod clock;
 pub mod collab_settlement_maker;
 pub mod collab_settlement_taker;
 pub mod connection;
+pub mod data_dir;
 pub mod db;
 pub mod fan_out;
+pub mod integrity_check;
 pub mod keypair;
 pub mod logger;
 pub mod maker_cfd;
 pub mod maker_inc_connections;
 pub mod model;
 pub mod monitor;
-mod noise;
+pub mod noise;
 pub mod olivia;
 pub mod oracle;
 pub mod payout_curve;
+pub mod price_feed;
 pub mod process_manager;
 pub mod projection;
 pub mod rollover_maker;
 pub mod rollover_taker;
 pub mod routes;
 pub mod seed;
+pub mod self_test;
 pub mod send_async_safe;
 pub mod setup_contract;
 pub mod setup_maker;
@@ -71,6 +102,7 @@ pub mod to_sse_event;
 pub mod tokio_ext;
 pub mod try_continue;
 pub mod wallet;
+pub mod watch_only;
 pub mod wire;
 pub mod xtra_ext;
 
@@ -83,6 +115,15 @@ pub const HEARTBEAT_INTERVAL: std::time::Duration =
 
 pub const N_PAYOUTS: usize = 200;
 
+/// The maximum number of CETs per oracle event we are willing to create during contract setup.
+///
+/// With [`N_PAYOUTS`] contracts stay well below this limit.
+pub const MAX_CETS: usize = 10_000;
+
+/// The maximum number of contract setups, collaborative settlements and rollovers each running at
+/// the same time.
+pub const MAX_CONCURRENT_PROTOCOLS: usize = 10;
+
 /// The interval until the cfd gets settled, i.e. the attestation happens
 ///
 /// This variable defines at what point in time the oracle event id will be chose to settle the cfd.
@@ -115,6 +156,8 @@ pub struct MakerActorSystem<O, T, W> {
     pub cfd_actor_addr: Address<maker_cfd::Actor<O, T, W>>,
     wallet_actor_addr: Address<W>,
     inc_conn_addr: Address<T>,
+    projection_actor: Address<projection::Actor>,
+    watch_only_addr: Address<watch_only::Actor>,
     _tasks: Tasks,
 }
 
@@ -135,7 +178,9 @@ where
     W: xtra::Handler<wallet::BuildPartyParams>
         + xtra::Handler<wallet::Sign>
         + xtra::Handler<wallet::TryBroadcastTransaction>
-        + xtra::Handler<wallet::Withdraw>,
+        + xtra::Handler<wallet::Withdraw>
+        + xtra::Handler<wallet::NewAddress>
+        + xtra::Handler<wallet::ReleaseUtxos>,
 {
     #[allow(clippy::too_many_arguments)]
     pub async fn new<FO, FM, M>(
@@ -150,8 +195,25 @@ where
             Box<dyn MessageChannel<FromTaker>>,
         ) -> T,
         settlement_interval: time::Duration,
+        settlement_event: SettlementEventSelection,
         n_payouts: usize,
+        max_quantity_per_taker: Option<Usd>,
+        minimum_size: MinimumSize,
+        max_cets: usize,
+        settlement_price_tolerance: SettlementPriceTolerance,
+        markup: Markup,
+        leverage_choices: Vec<Leverage>,
+        pricing_mode: PricingMode,
+        max_settlement_proposal_age: time::Duration,
+        max_position_age: Option<time::Duration>,
         projection_actor: Address<projection::Actor>,
+        commit_policy: CommitPolicy,
+        cet_policy: CetPolicy,
+        settlement_rejection_policy: SettlementRejectionPolicy,
+        refund_strategy: RefundStrategy,
+        unknown_event_policy: UnknownEventPolicy,
+        rebroadcast_interval: Duration,
+        clock: Arc<dyn Clock>,
     ) -> Result<Self>
     where
         M: xtra::Handler<monitor::StartMonitoring>
@@ -173,21 +235,81 @@ where
             Role::Maker,
             &projection_actor,
             &wallet_addr,
+            &wallet_addr,
             &monitor_addr,
             &monitor_addr,
             &oracle_addr,
+            commit_policy,
+            cet_policy,
+            rebroadcast_interval,
         )));
 
+        // Only the taker proposes to settle while the commit transaction is delayed
+        if let CommitPolicy::Delayed(delay) = commit_policy {
+            let (auto_commit_address, auto_commit_fut) = auto_commit::Actor::new(
+                db.clone(),
+                process_manager_addr.clone(),
+                None,
+                delay,
+                clock.clone(),
+            )
+            .create(None)
+            .run();
+            std::mem::forget(auto_commit_address); // leak this address to avoid shutdown
+            tasks.add(auto_commit_fut);
+        }
+
+        // The maker cannot propose settlements, it refunds once the taker had the time to propose
+        // one
+        if refund_strategy != RefundStrategy::Manual {
+            let (auto_refund_address, auto_refund_fut) = auto_refund::Actor::new(
+                db.clone(),
+                Role::Maker,
+                &process_manager_addr,
+                None,
+                clock.clone(),
+            )
+            .create(None)
+            .run();
+            std::mem::forget(auto_refund_address); // leak this address to avoid shutdown
+            tasks.add(auto_refund_fut);
+        }
+
+        if let UnknownEventPolicy::Warn {
+            oracle_url,
+            check_interval,
+        } = unknown_event_policy
+        {
+            tasks.add(oracle::check_events_periodically(
+                db.clone(),
+                oracle_url,
+                projection_actor.clone(),
+                check_interval,
+            ));
+        }
+
         let (cfd_actor_addr, cfd_actor_fut) = maker_cfd::Actor::new(
             db,
             wallet_addr.clone(),
             settlement_interval,
+            settlement_event,
             oracle_pk,
-            projection_actor,
+            projection_actor.clone(),
             process_manager_addr.clone(),
             inc_conn_addr.clone(),
             oracle_addr.clone(),
             n_payouts,
+            max_quantity_per_taker,
+            minimum_size,
+            max_cets,
+            settlement_price_tolerance,
+            markup,
+            leverage_choices,
+            pricing_mode,
+            max_settlement_proposal_age,
+            max_position_age,
+            settlement_rejection_policy,
+            clock,
         )
         .create(None)
         .run();
@@ -200,17 +322,25 @@ where
             Box::new(cfd_actor_addr.clone()),
         )));
 
-        tasks.add(monitor_ctx.run(monitor_constructor(Box::new(cfd_actor_addr.clone())).await?));
+        let (watch_only_addr, watch_only_fut) =
+            watch_only::Actor::new(db.clone(), &monitor_addr, &oracle_addr, &cfd_actor_addr)
+                .create(None)
+                .run();
+        tasks.add(watch_only_fut);
+
+        tasks.add(monitor_ctx.run(monitor_constructor(Box::new(watch_only_addr.clone())).await?));
 
         let (fan_out_actor, fan_out_actor_fut) =
-            fan_out::Actor::new(&[&cfd_actor_addr, &monitor_addr])
+            fan_out::Actor::new(&[&cfd_actor_addr, &monitor_addr, &watch_only_addr])
                 .create(None)
                 .run();
         tasks.add(fan_out_actor_fut);
 
         tasks.add(oracle_ctx.run(oracle_constructor(Box::new(fan_out_actor)).await?));
-
-        oracle_addr.send(oracle::Sync).await?;
+        tasks.add(oracle::sync_periodically(
+            oracle_addr.clone(),
+            oracle::SYNC_INTERVAL,
+        ));
 
         tracing::debug!("Maker actor system ready");
 
@@ -218,6 +348,8 @@ where
             cfd_actor_addr,
             wallet_actor_addr: wallet_addr,
             inc_conn_addr,
+            projection_actor,
+            watch_only_addr,
             _tasks: tasks,
         })
     }
@@ -240,18 +372,64 @@ where
 
     pub async fn new_order(
         &self,
-        price: Price,
+        price: Option<Price>,
         min_quantity: Usd,
         max_quantity: Usd,
         fee_rate: Option<u32>,
     ) -> Result<()> {
         self.cfd_actor_addr
-            .send(maker_cfd::NewOrder {
+            .send(new_order_params(
                 price,
                 min_quantity,
                 max_quantity,
-                fee_rate: fee_rate.unwrap_or(1),
-            })
+                fee_rate,
+            ))
+            .await??;
+
+        Ok(())
+    }
+
+    /// Offer the taker with `taker_id` an order with the given parameters instead of the current
+    /// order, see [`MakerActorSystem::new_order`].
+    pub async fn set_order_override(
+        &self,
+        taker_id: Identity,
+        price: Option<Price>,
+        min_quantity: Usd,
+        max_quantity: Usd,
+        fee_rate: Option<u32>,
+    ) -> Result<()> {
+        self.override_order(
+            taker_id,
+            Some(new_order_params(
+                price,
+                min_quantity,
+                max_quantity,
+                fee_rate,
+            )),
+        )
+        .await
+    }
+
+    pub async fn withdraw_order(&self) -> Result<()> {
+        self.cfd_actor_addr.send(maker_cfd::WithdrawOrder).await??;
+
+        Ok(())
+    }
+
+    pub async fn rebuild_projection(&self) -> Result<()> {
+        self.projection_actor.send(projection::Rebuild).await?;
+
+        Ok(())
+    }
+
+    pub async fn override_order(
+        &self,
+        taker_id: Identity,
+        order: Option<maker_cfd::NewOrder>,
+    ) -> Result<()> {
+        self.cfd_actor_addr
+            .send(maker_cfd::OverrideOrder { taker_id, order })
             .await??;
 
         Ok(())
@@ -285,6 +463,13 @@ where
         Ok(())
     }
 
+    pub async fn counter_settlement(&self, order_id: OrderId, price: Price) -> Result<()> {
+        self.cfd_actor_addr
+            .send(maker_cfd::CounterSettlement { order_id, price })
+            .await??;
+        Ok(())
+    }
+
     pub async fn accept_rollover(&self, order_id: OrderId) -> Result<()> {
         self.cfd_actor_addr
             .send(maker_cfd::AcceptRollOver { order_id })
@@ -305,6 +490,96 @@ where
         Ok(())
     }
 
+    pub async fn commit_and_settle(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(maker_cfd::CommitAndSettle { order_id })
+            .await??;
+        Ok(())
+    }
+
+    pub async fn refund(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(maker_cfd::Refund { order_id })
+            .await??;
+        Ok(())
+    }
+
+    pub async fn pause_monitoring(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(maker_cfd::PauseMonitoring { order_id })
+            .await??;
+        Ok(())
+    }
+
+    pub async fn resume_monitoring(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(maker_cfd::ResumeMonitoring { order_id })
+            .await??;
+        Ok(())
+    }
+
+    pub async fn archive(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(maker_cfd::Archive { order_id })
+            .await??;
+        Ok(())
+    }
+
+    pub async fn commit_tx(&self, order_id: OrderId) -> Result<SignedCommitTx> {
+        self.cfd_actor_addr
+            .send(maker_cfd::GetCommitTx { order_id })
+            .await?
+    }
+
+    pub async fn dlc_summary(&self, order_id: OrderId) -> Result<DlcSummary> {
+        self.cfd_actor_addr
+            .send(maker_cfd::GetDlcSummary { order_id })
+            .await?
+    }
+
+    pub async fn watch(&self, order_id: OrderId, dlc: WatchOnlyDlc) -> Result<()> {
+        self.watch_only_addr
+            .send(watch_only::Import { order_id, dlc })
+            .await?
+    }
+
+    pub async fn watched_cfds(&self) -> Result<Vec<watch_only::WatchedCfd>> {
+        let cfds = self.watch_only_addr.send(watch_only::GetWatched).await?;
+        Ok(cfds)
+    }
+
+    pub async fn ensure_can_sign(&self, order_id: OrderId) -> Result<()> {
+        self.watch_only_addr
+            .send(watch_only::EnsureCanSign { order_id })
+            .await?
+    }
+
+    pub async fn close_options(
+        &self,
+        order_id: OrderId,
+        current_price: Option<Price>,
+    ) -> Result<Vec<CloseOption>> {
+        self.cfd_actor_addr
+            .send(maker_cfd::GetCloseOptions {
+                order_id,
+                current_price,
+            })
+            .await?
+    }
+
+    pub async fn action_costs(
+        &self,
+        order_id: OrderId,
+        current_price: Option<Price>,
+    ) -> Result<ActionCosts> {
+        self.cfd_actor_addr
+            .send(maker_cfd::GetActionCosts {
+                order_id,
+                current_price,
+            })
+            .await?
+    }
+
     pub async fn withdraw(
         &self,
         amount: Option<Amount>,
@@ -319,6 +594,25 @@ where
             })
             .await?
     }
+
+    pub async fn new_address(&self) -> Result<wallet::ReceiveAddress> {
+        self.wallet_actor_addr.send(wallet::NewAddress).await?
+    }
+}
+
+/// The parameters of a new order, defaulting to a fee rate of 1 sat/vbyte.
+fn new_order_params(
+    price: Option<Price>,
+    min_quantity: Usd,
+    max_quantity: Usd,
+    fee_rate: Option<u32>,
+) -> maker_cfd::NewOrder {
+    maker_cfd::NewOrder {
+        price,
+        min_quantity,
+        max_quantity,
+        fee_rate: fee_rate.unwrap_or(1),
+    }
 }
 
 pub struct TakerActorSystem<O, W> {
@@ -326,6 +620,10 @@ pub struct TakerActorSystem<O, W> {
     pub connection_actor_addr: Address<connection::Actor>,
     pub maker_online_status_feed_receiver: watch::Receiver<ConnectionStatus>,
     wallet_actor_addr: Address<W>,
+    oracle_actor_addr: Address<O>,
+    get_running_rollovers: Box<dyn MessageChannel<auto_rollover::GetRunningRollovers>>,
+    cancel_rollover: Box<dyn MessageChannel<auto_rollover::CancelRollover>>,
+    trigger_rollover_check: Box<dyn MessageChannel<auto_rollover::AutoRollover>>,
     _tasks: Tasks,
 }
 
@@ -333,12 +631,16 @@ impl<O, W> TakerActorSystem<O, W>
 where
     O: xtra::Handler<oracle::MonitorAttestation>
         + xtra::Handler<oracle::GetAnnouncement>
+        + xtra::Handler<oracle::GetHealth>
         + xtra::Handler<oracle::Sync>,
     W: xtra::Handler<wallet::BuildPartyParams>
         + xtra::Handler<wallet::Sign>
         + xtra::Handler<wallet::TryBroadcastTransaction>
         + xtra::Handler<wallet::Withdraw>
-        + xtra::Handler<wallet::Reinitialise>,
+        + xtra::Handler<wallet::Reinitialise>
+        + xtra::Handler<wallet::Rescan>
+        + xtra::Handler<wallet::NewAddress>
+        + xtra::Handler<wallet::ReleaseUtxos>,
 {
     #[allow(clippy::too_many_arguments)]
     pub async fn new<FM, FO, M>(
@@ -349,10 +651,25 @@ where
         oracle_constructor: impl FnOnce(Box<dyn StrongMessageChannel<Attestation>>) -> FO,
         monitor_constructor: impl FnOnce(Box<dyn StrongMessageChannel<monitor::Event>>) -> FM,
         n_payouts: usize,
+        minimum_size: MinimumSize,
+        max_cets: usize,
         maker_heartbeat_interval: Duration,
         connect_timeout: Duration,
         projection_actor: Address<projection::Actor>,
         maker_identity: Identity,
+        display_name: Option<DisplayName>,
+        min_compatible_version: Version,
+        commit_policy: CommitPolicy,
+        cet_policy: CetPolicy,
+        settlement_rejection_policy: SettlementRejectionPolicy,
+        pricing_mode: PricingMode,
+        refund_strategy: RefundStrategy,
+        unknown_event_policy: UnknownEventPolicy,
+        max_position_age: Option<time::Duration>,
+        max_concurrent_protocols: usize,
+        rebroadcast_interval: Duration,
+        oracle_sync_interval: Duration,
+        clock: Arc<dyn Clock>,
     ) -> Result<Self>
     where
         M: xtra::Handler<monitor::StartMonitoring>
@@ -362,6 +679,10 @@ where
         FO: Future<Output = Result<O>>,
         FM: Future<Output = Result<M>>,
     {
+        if max_concurrent_protocols == 0 {
+            bail!("At least one protocol has to be allowed to run at a time")
+        }
+
         let (maker_online_status_feed_sender, maker_online_status_feed_receiver) =
             watch::channel(ConnectionStatus::Offline { reason: None });
 
@@ -376,9 +697,13 @@ where
             Role::Taker,
             &projection_actor,
             &wallet_actor_addr,
+            &wallet_actor_addr,
             &monitor_addr,
             &monitor_addr,
             &oracle_addr,
+            commit_policy,
+            cet_policy,
+            rebroadcast_interval,
         )));
 
         let (connection_actor_addr, connection_actor_ctx) = xtra::Context::new(None);
@@ -387,26 +712,88 @@ where
             wallet_actor_addr.clone(),
             oracle_pk,
             projection_actor.clone(),
-            process_manager_addr,
+            process_manager_addr.clone(),
             connection_actor_addr.clone(),
             oracle_addr.clone(),
             n_payouts,
+            minimum_size,
+            max_cets,
+            settlement_rejection_policy,
+            pricing_mode,
             maker_identity,
+            Identity::new(x25519_dalek::PublicKey::from(&identity_sk)),
+            max_concurrent_protocols,
         )
         .create(None)
         .run();
 
+        if let CommitPolicy::Delayed(delay) = commit_policy {
+            let (auto_commit_address, auto_commit_fut) = auto_commit::Actor::new(
+                db.clone(),
+                process_manager_addr.clone(),
+                Some(cfd_actor_addr.clone_channel()),
+                delay,
+                clock.clone(),
+            )
+            .create(None)
+            .run();
+            std::mem::forget(auto_commit_address); // leak this address to avoid shutdown
+            tasks.add(auto_commit_fut);
+        }
+
+        if refund_strategy != RefundStrategy::Manual {
+            let propose_settlement: Option<Box<dyn MessageChannel<taker_cfd::ProposeSettlement>>> =
+                match refund_strategy {
+                    RefundStrategy::SettleThenRefund => Some(cfd_actor_addr.clone_channel()),
+                    RefundStrategy::Refund | RefundStrategy::Manual => None,
+                };
+
+            let (auto_refund_address, auto_refund_fut) = auto_refund::Actor::new(
+                db.clone(),
+                Role::Taker,
+                &process_manager_addr,
+                propose_settlement,
+                clock.clone(),
+            )
+            .create(None)
+            .run();
+            std::mem::forget(auto_refund_address); // leak this address to avoid shutdown
+            tasks.add(auto_refund_fut);
+        }
+
+        if let UnknownEventPolicy::Warn {
+            oracle_url,
+            check_interval,
+        } = unknown_event_policy
+        {
+            tasks.add(oracle::check_events_periodically(
+                db.clone(),
+                oracle_url,
+                projection_actor.clone(),
+                check_interval,
+            ));
+        }
+
         let (auto_rollover_address, auto_rollover_fut) = auto_rollover::Actor::new(
             db,
             oracle_pk,
             projection_actor,
             connection_actor_addr.clone(),
             monitor_addr.clone(),
-            oracle_addr,
+            oracle_addr.clone(),
             n_payouts,
+            max_position_age,
+            max_concurrent_protocols,
+            clock.clone(),
         )
         .create(None)
         .run();
+        let get_running_rollovers: Box<dyn MessageChannel<auto_rollover::GetRunningRollovers>> =
+            auto_rollover_address.clone_channel();
+        let cancel_rollover: Box<dyn MessageChannel<auto_rollover::CancelRollover>> =
+            auto_rollover_address.clone_channel();
+        let trigger_rollover_check: Box<dyn MessageChannel<auto_rollover::AutoRollover>> =
+            auto_rollover_address.clone_channel();
         std::mem::forget(auto_rollover_address); // leak this address to avoid shutdown
 
         tasks.add(cfd_actor_fut);
@@ -418,6 +805,9 @@ where
             identity_sk,
             maker_heartbeat_interval,
             connect_timeout,
+            display_name,
+            min_compatible_version,
+            clock,
         )));
 
         tasks.add(monitor_ctx.run(monitor_constructor(Box::new(cfd_actor_addr.clone())).await?));
@@ -430,6 +820,10 @@ where
         tasks.add(fan_out_actor_fut);
 
         tasks.add(oracle_ctx.run(oracle_constructor(Box::new(fan_out_actor)).await?));
+        tasks.add(oracle::sync_periodically(
+            oracle_addr.clone(),
+            oracle_sync_interval,
+        ));
 
         tracing::debug!("Taker actor system ready");
 
@@ -438,23 +832,142 @@ where
             connection_actor_addr,
             maker_online_status_feed_receiver,
             wallet_actor_addr,
+            oracle_actor_addr: oracle_addr,
+            get_running_rollovers,
+            cancel_rollover,
+            trigger_rollover_check,
             _tasks: tasks,
         })
     }
 
-    pub async fn take_offer(&self, order_id: OrderId, quantity: Usd) -> Result<()> {
+    pub async fn oracle_health(&self) -> Result<oracle::Health> {
+        let health = self.oracle_actor_addr.send(oracle::GetHealth).await??;
+        Ok(health)
+    }
+
+    pub async fn take_offer(
+        &self,
+        order_id: OrderId,
+        quantity: Usd,
+        fee_rate: Option<u32>,
+        slippage_bound: SlippageBound,
+        leverage: Option<Leverage>,
+    ) -> Result<()> {
         self.cfd_actor_addr
-            .send(taker_cfd::TakeOffer { order_id, quantity })
+            .send(taker_cfd::TakeOffer {
+                order_id,
+                quantity,
+                fee_rate,
+                slippage_bound,
+                leverage,
+            })
             .await??;
         Ok(())
     }
 
+    /// Propose to settle every CFD that can be settled collaboratively, reporting the outcome per
+    /// CFD.
+    pub async fn settle_all(
+        &self,
+        current_price: Price,
+    ) -> Result<Vec<taker_cfd::SettlementAttempt>> {
+        self.cfd_actor_addr
+            .send(taker_cfd::SettleAll { current_price })
+            .await?
+    }
+
+    pub async fn accept_settlement(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(taker_cfd::AcceptSettlement { order_id })
+            .await?
+    }
+
+    pub async fn reject_settlement(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(taker_cfd::RejectSettlement { order_id })
+            .await?
+    }
+
     pub async fn commit(&self, order_id: OrderId) -> Result<()> {
         self.cfd_actor_addr
             .send(taker_cfd::Commit { order_id })
             .await?
     }
 
+    pub async fn commit_and_settle(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(taker_cfd::CommitAndSettle { order_id })
+            .await?
+    }
+
+    pub async fn refund(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(taker_cfd::Refund { order_id })
+            .await?
+    }
+
+    pub async fn pause_monitoring(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(taker_cfd::PauseMonitoring { order_id })
+            .await?
+    }
+
+    pub async fn resume_monitoring(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(taker_cfd::ResumeMonitoring { order_id })
+            .await?
+    }
+
+    pub async fn archive(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor_addr
+            .send(taker_cfd::Archive { order_id })
+            .await?
+    }
+
+    pub async fn set_exit_levels(&self, order_id: OrderId, levels: ExitLevels) -> Result<()> {
+        self.cfd_actor_addr
+            .send(taker_cfd::SetExitLevels { order_id, levels })
+            .await?
+    }
+
+    pub async fn commit_tx(&self, order_id: OrderId) -> Result<SignedCommitTx> {
+        self.cfd_actor_addr
+            .send(taker_cfd::GetCommitTx { order_id })
+            .await?
+    }
+
+    pub async fn dlc_summary(&self, order_id: OrderId) -> Result<DlcSummary> {
+        self.cfd_actor_addr
+            .send(taker_cfd::GetDlcSummary { order_id })
+            .await?
+    }
+
+    pub async fn close_options(
+        &self,
+        order_id: OrderId,
+        current_price: Option<Price>,
+    ) -> Result<Vec<CloseOption>> {
+        self.cfd_actor_addr
+            .send(taker_cfd::GetCloseOptions {
+                order_id,
+                current_price,
+            })
+            .await?
+    }
+
+    pub async fn action_costs(
+        &self,
+        order_id: OrderId,
+        current_price: Option<Price>,
+    ) -> Result<ActionCosts> {
+        self.cfd_actor_addr
+            .send(taker_cfd::GetActionCosts {
+                order_id,
+                current_price,
+            })
+            .await?
+    }
+
     pub async fn propose_settlement(&self, order_id: OrderId, current_price: Price) -> Result<()> {
         self.cfd_actor_addr
             .send(taker_cfd::ProposeSettlement {
@@ -486,4 +999,69 @@ where
             })
             .await?
     }
+
+    pub async fn running_protocols(&self) -> Result<Vec<taker_cfd::RunningProtocol>> {
+        let mut running = self
+            .cfd_actor_addr
+            .send(taker_cfd::GetRunningProtocols)
+            .await?;
+
+        let rollovers = self
+            .get_running_rollovers
+            .send(auto_rollover::GetRunningRollovers)
+            .await?;
+        running.extend(
+            rollovers
+                .into_iter()
+                .map(|order_id| taker_cfd::RunningProtocol {
+                    order_id,
+                    protocol: taker_cfd::Protocol::Rollover,
+                }),
+        );
+
+        Ok(running)
+    }
+
+    pub async fn cancel_protocol(&self, order_id: OrderId) -> Result<taker_cfd::Protocol> {
+        if let Some(protocol) = self
+            .cfd_actor_addr
+            .send(taker_cfd::CancelProtocol { order_id })
+            .await?
+        {
+            return Ok(protocol);
+        }
+
+        if self
+            .cancel_rollover
+            .send(auto_rollover::CancelRollover { order_id })
+            .await?
+        {
+            return Ok(taker_cfd::Protocol::Rollover);
+        }
+
+        bail!("No protocol is running for order {}", order_id)
+    }
+
+    /// Check all CFDs for rollover right away instead of waiting for the next periodic check.
+    ///
+    /// Returns the number of CFDs a rollover was started for, CFDs that are not eligible for a
+    /// rollover are not counted.
+    pub async fn trigger_rollover_check(&self) -> Result<usize> {
+        self.trigger_rollover_check
+            .send(auto_rollover::AutoRollover)
+            .await?
+    }
+
+    /// Start a rescan of the wallet without waiting for it to finish.
+    ///
+    /// A rescan that fails after it started is logged.
+    pub async fn rescan_wallet(&self, from_height: Option<u32>) -> Result<()> {
+        self.wallet_actor_addr
+            .send(wallet::Rescan { from_height })
+            .await?
+    }
+
+    pub async fn new_address(&self) -> Result<wallet::ReceiveAddress> {
+        self.wallet_actor_addr.send(wallet::NewAddress).await?
+    }
 }
@@ -1,12 +1,123 @@
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
 use anyhow::Result;
+use std::path::PathBuf;
+use std::str;
+use std::time::Duration;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::RollingFileAppender;
+use tracing_appender::rolling::Rotation;
 use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::fmt::time::ChronoLocal;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::FmtSubscriber;
 
-pub fn init(level: LevelFilter, json_format: bool) -> Result<()> {
+/// How often to delete log files beyond [`LogFile::max_files`].
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Log to files in `dir` instead of stderr.
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    pub dir: PathBuf,
+    /// The name of the log file, suffixed with the date unless the log is never rotated.
+    pub prefix: &'static str,
+    pub rotation: LogRotation,
+    /// How many log files to keep, deleting the oldest ones. Keeps all log files if `None`.
+    pub max_files: Option<usize>,
+}
+
+impl LogFile {
+    /// Delete the oldest log files, keeping the newest [`LogFile::max_files`].
+    pub fn prune(&self) -> Result<()> {
+        let max_files = match self.max_files {
+            Some(max_files) => max_files,
+            None => return Ok(()),
+        };
+
+        let mut files = std::fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read log directory {}", self.dir.display()))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .map_or(false, |name| name.starts_with(self.prefix))
+            })
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+
+        // The date suffix of rotated files sorts them from oldest to newest
+        files.sort();
+
+        let excess = files.len().saturating_sub(max_files);
+        for file in &files[..excess] {
+            std::fs::remove_file(file)
+                .with_context(|| format!("Failed to delete log file {}", file.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Prune the log files every [`PRUNE_INTERVAL`], to also catch files rotated while running.
+    pub async fn prune_periodically(self) {
+        loop {
+            if let Err(e) = self.prune() {
+                tracing::warn!("Failed to prune log files: {:#}", e);
+            }
+
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    }
+}
+
+/// How often to start a new log file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl str::FromStr for LogRotation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rotation = match s {
+            "hourly" => LogRotation::Hourly,
+            "daily" => LogRotation::Daily,
+            "never" => LogRotation::Never,
+            other => bail!(
+                "Unknown log rotation {}, expected one of hourly, daily or never",
+                other
+            ),
+        };
+
+        Ok(rotation)
+    }
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Initialise the global logger.
+///
+/// If logging to a file, the returned guard needs to be held until the program exits, otherwise
+/// buffered log lines are lost.
+pub fn init(
+    level: LevelFilter,
+    json_format: bool,
+    file: Option<LogFile>,
+) -> Result<Option<WorkerGuard>> {
     if level == LevelFilter::OFF {
-        return Ok(());
+        return Ok(None);
     }
 
     let is_terminal = atty::is(atty::Stream::Stderr);
@@ -19,17 +130,87 @@ pub fn init(level: LevelFilter, json_format: bool) -> Result<()> {
 
     let builder = FmtSubscriber::builder()
         .with_env_filter(filter)
-        .with_writer(std::io::stderr)
-        .with_ansi(is_terminal)
         .with_timer(ChronoLocal::with_format("%F %T".to_owned()));
 
-    if json_format {
-        builder.json().init();
-    } else {
-        builder.init();
-    }
+    let guard = match file {
+        None => {
+            let builder = builder.with_writer(std::io::stderr).with_ansi(is_terminal);
+
+            if json_format {
+                builder.json().init();
+            } else {
+                builder.init();
+            }
+
+            None
+        }
+        Some(LogFile {
+            dir,
+            prefix,
+            rotation,
+            max_files,
+        }) => {
+            if let Some(max_files) = max_files {
+                ensure!(max_files > 0, "Must keep at least one log file");
+            }
+
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create log directory {}", dir.display()))?;
+
+            let appender = RollingFileAppender::new(rotation.into(), dir, prefix);
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let builder = builder.with_writer(writer).with_ansi(false);
+
+            if json_format {
+                builder.json().init();
+            } else {
+                builder.init();
+            }
+
+            Some(guard)
+        }
+    };
 
     tracing::info!("Initialized logger");
 
-    Ok(())
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_keeps_newest_log_files() {
+        let dir = std::env::temp_dir().join(format!("logs-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "maker.log.2022-01-01",
+            "maker.log.2022-01-02",
+            "maker.log.2022-01-03",
+            "other.txt",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let log_file = LogFile {
+            dir: dir.clone(),
+            prefix: "maker.log",
+            rotation: LogRotation::Daily,
+            max_files: Some(2),
+        };
+        log_file.prune().unwrap();
+
+        let mut remaining = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            vec!["maker.log.2022-01-02", "maker.log.2022-01-03", "other.txt"]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -6,29 +6,59 @@ use bdk::bitcoin::Amount;
 use bdk::FeeRate;
 use clap::Parser;
 use clap::Subcommand;
+use daemon::archive;
 use daemon::auth;
 use daemon::auth::MAKER_USERNAME;
-use daemon::bitmex_price_feed;
+use daemon::auto_commit::CommitPolicy;
+use daemon::auto_refund::RefundStrategy;
+use daemon::clock::SystemClock;
+use daemon::data_dir;
 use daemon::db;
+use daemon::fan_out;
+use daemon::integrity_check;
+use daemon::integrity_check::Repair;
 use daemon::logger;
+use daemon::logger::LogFile;
+use daemon::logger::LogRotation;
 use daemon::maker_inc_connections;
+use daemon::model::cfd::Markup;
+use daemon::model::cfd::MinimumSize;
 use daemon::model::cfd::Role;
+use daemon::model::cfd::SettlementPriceTolerance;
+use daemon::model::cfd::SettlementRejectionPolicy;
+use daemon::model::DisplayName;
+use daemon::model::Leverage;
+use daemon::model::Usd;
 use daemon::monitor;
 use daemon::oracle;
+use daemon::oracle::SettlementEventSelection;
+use daemon::oracle::UnknownEventPolicy;
+use daemon::price_feed;
+use daemon::price_feed::PricingMode;
+use daemon::price_feed::SourceConfig;
+use daemon::process_manager;
+use daemon::process_manager::CetPolicy;
 use daemon::projection;
+use daemon::projection::Explorer;
+use daemon::routes::RequestLog;
 use daemon::seed::Seed;
 use daemon::supervisor;
 use daemon::wallet;
+use daemon::wallet::ElectrumTls;
+use daemon::wire::Version;
 use daemon::MakerActorSystem;
 use daemon::Tasks;
 use daemon::HEARTBEAT_INTERVAL;
+use daemon::MAX_CETS;
 use daemon::N_PAYOUTS;
 use daemon::SETTLEMENT_INTERVAL;
-use sqlx::sqlite::SqliteConnectOptions;
-use sqlx::SqlitePool;
+use rust_decimal::Decimal;
 use std::net::SocketAddr;
+use std::num::NonZeroU32;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing_subscriber::filter::LevelFilter;
 use xtra::Actor;
 
@@ -48,6 +78,11 @@ struct Opts {
     #[clap(long)]
     data_dir: Option<PathBuf>,
 
+    /// Path to the file persisting the index of the last address handed out by the wallet,
+    /// defaults to `maker_wallet_address_index` in the network's data directory.
+    #[clap(long)]
+    wallet_address_index_file: Option<PathBuf>,
+
     /// If enabled logs will be in json format
     #[clap(short, long)]
     json: bool,
@@ -56,6 +91,157 @@ struct Opts {
     #[clap(short, long, default_value = "Debug")]
     log_level: LevelFilter,
 
+    /// Write logs to `maker.log` files in this directory instead of stderr.
+    #[clap(long)]
+    log_dir: Option<PathBuf>,
+
+    /// How often to start a new log file: hourly, daily or never.
+    #[clap(long, default_value = "daily")]
+    log_rotation: LogRotation,
+
+    /// How many log files to keep, deleting the oldest ones. Keeps all log files if not
+    /// specified.
+    #[clap(long)]
+    log_max_files: Option<usize>,
+
+    /// The maximum quantity in USD a single taker can take in total. If not specified, takers are
+    /// only limited by the quantity range of the order.
+    #[clap(long)]
+    max_quantity_per_taker: Option<Usd>,
+
+    /// The minimum quantity in USD of a CFD. Orders whose minimum quantity is below this are
+    /// rejected.
+    #[clap(long)]
+    min_notional: Option<Usd>,
+
+    /// The minimum margin of the taker side of a CFD. Orders whose minimum quantity would require
+    /// less margin are rejected. Amount is to be specified with denomination, e.g. "0.0001 BTC"
+    #[clap(long)]
+    min_margin: Option<Amount>,
+
+    /// The maximum number of CETs of a contract. Contracts that would need more are refused.
+    /// Defaults to 10000.
+    #[clap(long)]
+    max_cets: Option<usize>,
+
+    /// Seconds after which a request to the electrum backend fails instead of waiting for a slow
+    /// or unresponsive server, between 1 and 255.
+    #[clap(long, default_value = "30", parse(try_from_str = wallet::parse_electrum_timeout))]
+    electrum_timeout_secs: Duration,
+
+    /// PEM file with the certificate of the electrum backend, e.g. the self-signed certificate of
+    /// a private server. The backend has to present exactly this certificate.
+    #[clap(long)]
+    electrum_certificate: Option<PathBuf>,
+
+    /// Accept any TLS certificate of the electrum backend. This allows anyone between you and the
+    /// backend to intercept the connection, prefer `--electrum-certificate`.
+    #[clap(long)]
+    electrum_accept_invalid_certs: bool,
+
+    /// Number of confirmations of the lock transaction after which a CFD is considered open.
+    #[clap(long, default_value = "1")]
+    lock_confirmations: NonZeroU32,
+
+    /// How far in percent the price of a settlement proposed by a taker may deviate from the
+    /// current market price. Proposals outside of this range are rejected.
+    #[clap(long, default_value = "1")]
+    settlement_price_tolerance: Decimal,
+
+    /// The spread to add to the market price when quoting an order without a price: a number of
+    /// USD, e.g. 50, or a percentage of the market price, e.g. 0.5%.
+    #[clap(long, default_value = "0")]
+    markup: Markup,
+
+    /// Leverages takers can choose from in addition to the default leverage of 2, e.g.
+    /// "1,3,5". Orders are only published if they are viable at every offered leverage.
+    #[clap(long, use_delimiter = true)]
+    leverage_choices: Vec<u8>,
+
+    /// Maximum age in seconds of a settlement proposal, counted from when we received it, when the
+    /// taker's signature arrives. Older proposals are rejected instead of settling at an outdated
+    /// price.
+    #[clap(long, default_value = "600")]
+    max_settlement_proposal_age_secs: NonZeroU32,
+
+    /// Maximum number of connections to the database the actors share.
+    #[clap(long, default_value = "10")]
+    db_max_connections: NonZeroU32,
+
+    /// Seconds a database connection waits for another one to release its lock before failing.
+    #[clap(long, default_value = "30")]
+    db_busy_timeout_secs: u64,
+
+    /// Minutes to wait for the counterparty to publish the commit transaction or settle
+    /// collaboratively after the oracle attested before the CET timelock expired. If not specified,
+    /// the commit transaction is published immediately, which leaves takers that delay their
+    /// commit transaction no time to settle collaboratively.
+    #[clap(long)]
+    commit_delay_mins: Option<u32>,
+
+    /// Keep a CFD open when a collaborative settlement is rejected or fails instead of publishing
+    /// the commit transaction, allowing for another settlement attempt.
+    #[clap(long)]
+    keep_open_on_rejected_settlement: bool,
+
+    /// Do not publish the CET when the oracle attested after the CET timelock expired, leaving
+    /// it to the counterparty instead.
+    #[clap(long)]
+    no_auto_publish_cet: bool,
+
+    /// What to do about CFDs the oracle did not attest to by their expiry: manual, refund or
+    /// settle-then-refund. The maker cannot propose settlements, so settle-then-refund refunds
+    /// right away.
+    ///
+    /// Defaults to refund.
+    #[clap(long)]
+    refund_strategy: Option<RefundStrategy>,
+
+    /// Do not check whether the oracle still serves the events of open CFDs. By default CFDs
+    /// whose event is unavailable are flagged because they can only be refunded.
+    #[clap(long)]
+    ignore_unknown_oracle_events: bool,
+
+    /// Move CFDs that cannot be loaded and events of non-existent CFDs into separate tables when
+    /// the database integrity check at startup finds them, instead of only logging them. The
+    /// hourly check while running only ever logs them.
+    #[clap(long)]
+    quarantine_corrupt_events: bool,
+
+    /// Reject rollovers of CFDs that have been open for longer than this many hours, letting them
+    /// settle at their expiry instead.
+    #[clap(long)]
+    max_position_age_hours: Option<u32>,
+
+    /// Settle CFDs only on oracle events every this many hours from midnight UTC, picking the one
+    /// closest to the end of the settlement interval. Must divide a day into equal parts. If not
+    /// specified, CFDs settle on the first event after the settlement interval.
+    #[clap(long)]
+    settlement_cadence_hours: Option<u8>,
+
+    /// Which price of the quote to mark CFDs, quote orders and check settlement prices at: bid,
+    /// ask or mid.
+    ///
+    /// Defaults to ask.
+    #[clap(long)]
+    pricing_mode: Option<PricingMode>,
+
+    /// Where to get quotes from: bitmex or simulated:<price>, the latter quoting a constant price
+    /// for testing.
+    #[clap(long, default_value = "bitmex")]
+    price_source: SourceConfig,
+
+    /// The name to introduce ourselves to takers with.
+    #[clap(long)]
+    display_name: Option<DisplayName>,
+
+    /// The oldest protocol version of takers to stay connected to, e.g. 2.3.0. Only for versions
+    /// known to be compatible with ours, a mismatch is logged as warning.
+    ///
+    /// Defaults to our own version.
+    #[clap(long)]
+    min_compatible_version: Option<Version>,
+
     #[clap(subcommand)]
     network: Network,
 }
@@ -69,7 +255,7 @@ enum Network {
         electrum: String,
 
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        command: Option<Command>,
     },
     /// Run on testnet.
     Testnet {
@@ -78,7 +264,7 @@ enum Network {
         electrum: String,
 
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        command: Option<Command>,
     },
     /// Run on signet
     Signet {
@@ -87,12 +273,12 @@ enum Network {
         electrum: String,
 
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        command: Option<Command>,
     },
 }
 
 #[derive(Subcommand)]
-enum Withdraw {
+enum Command {
     Withdraw {
         /// Optionally specify the amount of Bitcoin to be withdrawn. If not specified the wallet
         /// will be drained. Amount is to be specified with denomination, e.g. "0.1 BTC"
@@ -106,6 +292,19 @@ enum Withdraw {
         #[clap(long)]
         address: bdk::bitcoin::Address,
     },
+    /// Export the CFDs and the wallet's address index into a portable archive for moving the
+    /// daemon to another host, then exit. The seed is not part of the archive.
+    Export {
+        /// Where to write the archive to.
+        #[clap(long)]
+        archive: PathBuf,
+    },
+    /// Import an archive created with `export` into a data directory without CFDs, then exit.
+    Import {
+        /// The archive to import.
+        #[clap(long)]
+        archive: PathBuf,
+    },
 }
 
 impl Network {
@@ -133,11 +332,11 @@ impl Network {
         }
     }
 
-    fn withdraw(&self) -> &Option<Withdraw> {
+    fn command(&self) -> &Option<Command> {
         match self {
-            Network::Mainnet { withdraw, .. } => withdraw,
-            Network::Testnet { withdraw, .. } => withdraw,
-            Network::Signet { withdraw, .. } => withdraw,
+            Network::Mainnet { command, .. } => command,
+            Network::Testnet { command, .. } => command,
+            Network::Signet { command, .. } => command,
         }
     }
 }
@@ -146,7 +345,14 @@ impl Network {
 async fn main() -> Result<()> {
     let opts = Opts::parse();
 
-    logger::init(opts.log_level, opts.json).context("initialize logger")?;
+    let log_file = opts.log_dir.clone().map(|dir| LogFile {
+        dir,
+        prefix: "maker.log",
+        rotation: opts.log_rotation,
+        max_files: opts.log_max_files,
+    });
+    let _log_guard =
+        logger::init(opts.log_level, opts.json, log_file.clone()).context("initialize logger")?;
     tracing::info!("Running version: {}", env!("VERGEN_GIT_SEMVER_LIGHTWEIGHT"));
     tracing::info!(
         "CFDs created with this release will settle after {} hours",
@@ -169,18 +375,64 @@ async fn main() -> Result<()> {
     let bitcoin_network = opts.network.bitcoin_network();
     let ext_priv_key = seed.derive_extended_priv_key(bitcoin_network)?;
 
+    let db_file = data_dir.join("maker.sqlite");
+    let address_index_file = data_dir::configured_or_default(
+        opts.wallet_address_index_file.clone(),
+        data_dir.join("maker_wallet_address_index"),
+    )
+    .await?;
+
+    match opts.network.command() {
+        Some(Command::Export {
+            archive: archive_file,
+        }) => {
+            archive::export_to_file(archive_file, bitcoin_network, &db_file, &address_index_file)
+                .await
+                .context("Export failed")?;
+
+            return Ok(());
+        }
+        Some(Command::Import {
+            archive: archive_file,
+        }) => {
+            archive::import_from_file(archive_file, bitcoin_network, &db_file, &address_index_file)
+                .await
+                .context("Import failed")?;
+
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let mut tasks = Tasks::default();
 
-    let (wallet, wallet_feed_receiver) = wallet::Actor::new(opts.network.electrum(), ext_priv_key)?;
+    if let Some(log_file) = log_file {
+        tasks.add(log_file.prune_periodically());
+    }
+
+    let min_compatible_version = Version::min_compatible(opts.min_compatible_version)?;
+    let electrum_timeout = opts.electrum_timeout_secs;
+    let electrum_tls = ElectrumTls::new(
+        opts.network.electrum(),
+        opts.electrum_certificate.as_deref(),
+        opts.electrum_accept_invalid_certs,
+    )?;
+    let (wallet, wallet_feed_receiver) = wallet::Actor::new(
+        opts.network.electrum(),
+        electrum_timeout,
+        electrum_tls.clone(),
+        ext_priv_key,
+        address_index_file,
+    )?;
 
     let (wallet, wallet_fut) = wallet.create(None).run();
     tasks.add(wallet_fut);
 
-    if let Some(Withdraw::Withdraw {
+    if let Some(Command::Withdraw {
         amount,
         address,
         fee,
-    }) = opts.network.withdraw()
+    }) = opts.network.command()
     {
         wallet
             .send(wallet::Withdraw {
@@ -209,6 +461,8 @@ async fn main() -> Result<()> {
         "ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7",
     )?;
 
+    let oracle_url = oracle::OLIVIA_URL.parse()?;
+
     let figment = rocket::Config::figment()
         .merge(("address", opts.http_address.ip()))
         .merge(("port", opts.http_address.port()));
@@ -223,10 +477,10 @@ async fn main() -> Result<()> {
 
     tracing::info!("Listening on {}", local_addr);
 
-    let db = SqlitePool::connect_with(
-        SqliteConnectOptions::new()
-            .create_if_missing(true)
-            .filename(data_dir.join("maker.sqlite")),
+    let db = db::connect(
+        &db_file,
+        opts.db_max_connections.get(),
+        Duration::from_secs(opts.db_busy_timeout_secs),
     )
     .await?;
 
@@ -234,19 +488,38 @@ async fn main() -> Result<()> {
         .await
         .context("Db migrations failed")?;
 
+    integrity_check::check(&db, Repair::from_quarantine(opts.quarantine_corrupt_events))
+        .await
+        .context("Database integrity check failed")?;
+
     // Create actors
 
     let (projection_actor, projection_context) = xtra::Context::new(None);
 
+    let max_position_age = opts
+        .max_position_age_hours
+        .map(|hours| time::Duration::hours(hours.into()));
+
+    let pricing_mode = opts
+        .pricing_mode
+        .unwrap_or_else(|| PricingMode::default_for(Role::Maker));
+
     let mut maker = MakerActorSystem::new(
         db.clone(),
         wallet.clone(),
         oracle,
-        |channel| oracle::Actor::new(db.clone(), channel, SETTLEMENT_INTERVAL),
+        |channel| oracle::Actor::new(db.clone(), channel, SETTLEMENT_INTERVAL, oracle_url),
         {
             |channel| {
                 let electrum = opts.network.electrum().to_string();
-                monitor::Actor::new(db.clone(), electrum, channel)
+                monitor::Actor::new(
+                    db.clone(),
+                    electrum,
+                    electrum_timeout,
+                    &electrum_tls,
+                    opts.lock_confirmations,
+                    channel,
+                )
             }
         },
         |channel0, channel1, channel2| {
@@ -256,44 +529,120 @@ async fn main() -> Result<()> {
                 channel2,
                 identity_sk,
                 HEARTBEAT_INTERVAL,
+                opts.display_name.clone(),
+                min_compatible_version,
             )
         },
         SETTLEMENT_INTERVAL,
+        SettlementEventSelection::from_cadence(opts.settlement_cadence_hours)?,
         N_PAYOUTS,
+        opts.max_quantity_per_taker,
+        MinimumSize {
+            notional: opts.min_notional,
+            margin: opts.min_margin,
+        },
+        opts.max_cets.unwrap_or(MAX_CETS),
+        SettlementPriceTolerance::from_percent(opts.settlement_price_tolerance)?,
+        opts.markup,
+        opts.leverage_choices
+            .iter()
+            .map(|leverage| Leverage::new(*leverage))
+            .collect::<Result<_>>()
+            .context("Invalid leverage choice")?,
+        pricing_mode,
+        time::Duration::seconds(opts.max_settlement_proposal_age_secs.get().into()),
+        max_position_age,
         projection_actor.clone(),
+        CommitPolicy::from_delay(
+            opts.commit_delay_mins
+                .map(|mins| time::Duration::minutes(mins.into())),
+        ),
+        CetPolicy::from_auto_publish(!opts.no_auto_publish_cet),
+        SettlementRejectionPolicy::from_keep_open(opts.keep_open_on_rejected_settlement),
+        opts.refund_strategy
+            .unwrap_or_else(|| RefundStrategy::default_for(Role::Maker)),
+        UnknownEventPolicy::from_ignore(
+            opts.ignore_unknown_oracle_events,
+            oracle::OLIVIA_URL.parse()?,
+        ),
+        process_manager::REBROADCAST_INTERVAL,
+        Arc::new(SystemClock),
     )
     .await?;
 
+    // The maker validates settlement proposals against the latest quote
+    let (quote_fan_out, task) = fan_out::Actor::new(&[&projection_actor, &maker.cfd_actor_addr])
+        .create(None)
+        .run();
+    tasks.add(task);
+
+    let price_source = opts.price_source.into_source();
     let (supervisor, _price_feed) = supervisor::Actor::new(
-        move |supervisor| bitmex_price_feed::Actor::new(projection_actor.clone(), supervisor),
+        move |supervisor| {
+            price_feed::Actor::new(
+                price_source.clone(),
+                quote_fan_out.clone(),
+                projection_actor.clone(),
+                supervisor,
+            )
+        },
         |_| true, // always restart price feed actor
     );
 
     let (_supervisor_address, task) = supervisor.create(None).run();
     tasks.add(task);
 
-    let (proj_actor, projection_feeds) =
-        projection::Actor::new(db.clone(), Role::Maker, bitcoin_network);
+    let (_integrity_check_address, task) =
+        integrity_check::Actor::new(db.clone()).create(None).run();
+    tasks.add(task);
+
+    let explorer = Explorer::mempool_space(bitcoin_network);
+    let (proj_actor, projection_feeds) = projection::Actor::new(
+        db.clone(),
+        Role::Maker,
+        explorer.clone(),
+        pricing_mode,
+        max_position_age,
+    );
     tasks.add(projection_context.run(proj_actor));
 
     maker.listen_on(listener);
 
     rocket::custom(figment)
+        .attach(RequestLog)
         .manage(projection_feeds)
         .manage(wallet_feed_receiver)
         .manage(maker)
         .manage(auth_password)
-        .manage(bitcoin_network)
+        .manage(explorer)
+        .manage(pricing_mode)
         .mount(
             "/api",
             rocket::routes![
                 routes_maker::maker_feed,
                 routes_maker::post_sell_order,
+                routes_maker::delete_order,
+                routes_maker::put_order_override,
+                routes_maker::delete_order_override,
                 routes_maker::post_cfd_action,
+                routes_maker::post_cfd_actions,
+                routes_maker::get_commit_tx,
+                routes_maker::get_dlc_summary,
+                routes_maker::post_watch_only,
+                routes_maker::get_watch_only,
+                routes_maker::get_close_options,
+                routes_maker::get_action_costs,
+                routes_maker::get_break_even,
+                routes_maker::post_counter_settlement,
+                routes_maker::get_settlement_receipt,
                 routes_maker::get_health_check,
                 routes_maker::post_withdraw_request,
+                routes_maker::get_wallet_address,
                 routes_maker::get_cfds,
+                routes_maker::get_confirmation_times,
+                routes_maker::get_orders,
                 routes_maker::get_takers,
+                routes_maker::post_rebuild_projection,
             ],
         )
         .register("/api", rocket::catchers![routes_maker::unauthorized])
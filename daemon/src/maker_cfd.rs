@@ -3,24 +3,40 @@ use crate::address_map::Stopping;
 use crate::cfd_actors;
 use crate::cfd_actors::insert_cfd_and_update_feed;
 use crate::cfd_actors::load_cfd;
+use crate::cfd_actors::PausedEvents;
+use crate::clock::Clock;
 use crate::collab_settlement_maker;
 use crate::maker_inc_connections;
 use crate::model;
+use crate::model::cfd::ActionCosts;
 use crate::model::cfd::Cfd;
+use crate::model::cfd::CloseOption;
 use crate::model::cfd::CollaborativeSettlement;
+use crate::model::cfd::DlcSummary;
+use crate::model::cfd::Markup;
+use crate::model::cfd::MinimumSize;
 use crate::model::cfd::Order;
 use crate::model::cfd::OrderId;
 use crate::model::cfd::Origin;
 use crate::model::cfd::Role;
 use crate::model::cfd::RolloverProposal;
+use crate::model::cfd::SettlementPriceTolerance;
 use crate::model::cfd::SettlementProposal;
+use crate::model::cfd::SettlementRejectionPolicy;
 use crate::model::cfd::SetupCompleted;
+use crate::model::cfd::SignedCommitTx;
+use crate::model::DisplayName;
 use crate::model::Identity;
+use crate::model::Leverage;
 use crate::model::Position;
 use crate::model::Price;
 use crate::model::Usd;
 use crate::monitor;
 use crate::oracle;
+use crate::oracle::SettlementEventSelection;
+use crate::payout_curve;
+use crate::price_feed;
+use crate::price_feed::PricingMode;
 use crate::process_manager;
 use crate::projection;
 use crate::projection::Update;
@@ -32,13 +48,15 @@ use crate::wallet;
 use crate::wire;
 use crate::wire::TakerToMaker;
 use crate::Tasks;
+use anyhow::bail;
 use anyhow::Context as _;
 use anyhow::Result;
 use async_trait::async_trait;
 use bdk::bitcoin::secp256k1::schnorrsig;
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::sync::Arc;
 use time::Duration;
-use time::OffsetDateTime;
+use tracing::Instrument;
 use xtra::prelude::*;
 use xtra::Actor as _;
 use xtra_productivity::xtra_productivity;
@@ -55,6 +73,11 @@ pub struct AcceptSettlement {
 pub struct RejectSettlement {
     pub order_id: OrderId,
 }
+/// Counter a settlement proposal of a taker with a proposal to settle at `price`.
+pub struct CounterSettlement {
+    pub order_id: OrderId,
+    pub price: Price,
+}
 pub struct AcceptRollOver {
     pub order_id: OrderId,
 }
@@ -68,15 +91,65 @@ pub struct RollOverProposed {
 pub struct Commit {
     pub order_id: OrderId,
 }
+pub struct CommitAndSettle {
+    pub order_id: OrderId,
+}
+pub struct Refund {
+    pub order_id: OrderId,
+}
+pub struct PauseMonitoring {
+    pub order_id: OrderId,
+}
+pub struct ResumeMonitoring {
+    pub order_id: OrderId,
+}
+/// Hides a CFD whose contract setup failed from the default list.
+pub struct Archive {
+    pub order_id: OrderId,
+}
+pub struct GetCommitTx {
+    pub order_id: OrderId,
+}
+
+/// Request a read-only view of the contract of a CFD, see [`DlcSummary`].
+pub struct GetDlcSummary {
+    pub order_id: OrderId,
+}
+pub struct GetCloseOptions {
+    pub order_id: OrderId,
+    pub current_price: Option<Price>,
+}
+
+/// Compute the cost of rolling over and settling a CFD, see [`ActionCosts`].
+pub struct GetActionCosts {
+    pub order_id: OrderId,
+    pub current_price: Option<Price>,
+}
 pub struct NewOrder {
-    pub price: Price,
+    /// Without a price the order is quoted from the latest quote, marked up by the configured
+    /// [`Markup`].
+    pub price: Option<Price>,
     pub min_quantity: Usd,
     pub max_quantity: Usd,
     pub fee_rate: u32,
 }
 
+/// Withdraw the current order so that it can no longer be taken.
+pub struct WithdrawOrder;
+
+/// Offer a specific taker a different order than the one broadcast to all takers.
+///
+/// Without an `order` the override is removed and the taker falls back to the current order.
+///
+/// Overrides are only kept in memory, like the current order, and are gone after a restart.
+pub struct OverrideOrder {
+    pub taker_id: Identity,
+    pub order: Option<NewOrder>,
+}
+
 pub struct TakerConnected {
     pub id: Identity,
+    pub display_name: Option<DisplayName>,
 }
 
 pub struct TakerDisconnected {
@@ -92,17 +165,34 @@ pub struct Actor<O, T, W> {
     db: sqlx::SqlitePool,
     wallet: Address<W>,
     settlement_interval: Duration,
+    settlement_event: SettlementEventSelection,
     oracle_pk: schnorrsig::PublicKey,
     projection_actor: Address<projection::Actor>,
     process_manager_actor: Address<process_manager::Actor>,
     rollover_actors: AddressMap<OrderId, rollover_maker::Actor>,
     takers: Address<T>,
     current_order: Option<Order>,
+    order_overrides: HashMap<Identity, Order>,
     setup_actors: AddressMap<OrderId, setup_maker::Actor>,
     settlement_actors: AddressMap<OrderId, collab_settlement_maker::Actor>,
     oracle_actor: Address<O>,
-    connected_takers: HashSet<Identity>,
+    connected_takers: HashMap<Identity, Option<DisplayName>>,
     n_payouts: usize,
+    max_quantity_per_taker: Option<Usd>,
+    minimum_size: MinimumSize,
+    max_cets: usize,
+    settlement_price_tolerance: SettlementPriceTolerance,
+    markup: Markup,
+    leverage_choices: Vec<Leverage>,
+    /// Which price of a quote orders are quoted at and settlement prices are compared to
+    pricing_mode: PricingMode,
+    max_settlement_proposal_age: Duration,
+    /// CFDs open for longer than this are no longer rolled over
+    max_position_age: Option<Duration>,
+    settlement_rejection_policy: SettlementRejectionPolicy,
+    latest_quote: Option<price_feed::Quote>,
+    clock: Arc<dyn Clock>,
+    paused_events: PausedEvents,
     tasks: Tasks,
 }
 
@@ -112,39 +202,169 @@ impl<O, T, W> Actor<O, T, W> {
         db: sqlx::SqlitePool,
         wallet: Address<W>,
         settlement_interval: Duration,
+        settlement_event: SettlementEventSelection,
         oracle_pk: schnorrsig::PublicKey,
         projection_actor: Address<projection::Actor>,
         process_manager_actor: Address<process_manager::Actor>,
         takers: Address<T>,
         oracle_actor: Address<O>,
         n_payouts: usize,
+        max_quantity_per_taker: Option<Usd>,
+        minimum_size: MinimumSize,
+        max_cets: usize,
+        settlement_price_tolerance: SettlementPriceTolerance,
+        markup: Markup,
+        leverage_choices: Vec<Leverage>,
+        pricing_mode: PricingMode,
+        max_settlement_proposal_age: Duration,
+        max_position_age: Option<Duration>,
+        settlement_rejection_policy: SettlementRejectionPolicy,
+        clock: Arc<dyn Clock>,
     ) -> Self {
         Self {
             db,
             wallet,
             settlement_interval,
+            settlement_event,
             oracle_pk,
             projection_actor,
             process_manager_actor,
             rollover_actors: AddressMap::default(),
             takers,
             current_order: None,
+            order_overrides: HashMap::new(),
             setup_actors: AddressMap::default(),
             oracle_actor,
             n_payouts,
-            connected_takers: HashSet::new(),
+            max_quantity_per_taker,
+            minimum_size,
+            max_cets,
+            settlement_price_tolerance,
+            markup,
+            leverage_choices,
+            pricing_mode,
+            max_settlement_proposal_age,
+            max_position_age,
+            settlement_rejection_policy,
+            latest_quote: None,
+            connected_takers: HashMap::new(),
             settlement_actors: AddressMap::default(),
+            clock,
+            paused_events: PausedEvents::default(),
             tasks: Tasks::default(),
         }
     }
 
+    /// Ensure a settlement `price` proposed by a taker is close to our own view of the market.
+    fn ensure_settlement_price_near_market(&self, price: Price) -> Result<()> {
+        let quote = self
+            .latest_quote
+            .context("No current price to validate the settlement price against")?;
+
+        if quote.is_stale(self.clock.timestamp()) {
+            bail!(
+                "Latest price is older than {} seconds, cannot validate the settlement price against it",
+                price_feed::MAX_QUOTE_AGE.as_secs()
+            )
+        }
+
+        self.settlement_price_tolerance
+            .ensure_within(price, quote.price(self.pricing_mode))
+    }
+
+    /// Build an order from the maker's parameters, ensuring it can be taken.
+    fn build_order(&self, new_order: NewOrder) -> Result<Order> {
+        let NewOrder {
+            price,
+            min_quantity,
+            max_quantity,
+            fee_rate,
+        } = new_order;
+
+        let price = match price {
+            Some(price) => price,
+            None => {
+                let quote = self
+                    .latest_quote
+                    .context("No current price to quote the order at")?;
+
+                if quote.is_stale(self.clock.timestamp()) {
+                    bail!(
+                        "Latest price is older than {} seconds, cannot quote the order at it",
+                        price_feed::MAX_QUOTE_AGE.as_secs()
+                    )
+                }
+
+                self.markup.apply(quote.price(self.pricing_mode))?
+            }
+        };
+
+        // The oracle actor fetches announcements up to the settlement interval ahead
+        let oracle_event_id = self.settlement_event.select(
+            self.clock.now(),
+            self.settlement_interval,
+            self.settlement_interval,
+        )?;
+
+        let order = Order::new_short(
+            price,
+            min_quantity,
+            max_quantity,
+            Origin::Ours,
+            oracle_event_id,
+            self.settlement_interval,
+            fee_rate,
+        )?
+        .with_leverage_choices(&self.leverage_choices);
+
+        // Every CFD taken from this order must be worth its on-chain fees, whichever leverage the
+        // taker chooses
+        for leverage in order.leverage_choices() {
+            self.minimum_size
+                .ensure_satisfied_by(order.price, order.min_quantity, leverage)
+                .with_context(|| {
+                    format!(
+                        "Minimum quantity of order is too small at leverage {}",
+                        leverage
+                    )
+                })?;
+            payout_curve::ensure_within_cet_limit(
+                order.price,
+                order.max_quantity,
+                leverage,
+                self.n_payouts,
+                self.max_cets,
+            )?;
+        }
+
+        Ok(order)
+    }
+
+    /// The order offered to the given taker, preferring its override over the current order.
+    fn order_for(&self, taker_id: &Identity) -> Option<Order> {
+        self.order_overrides
+            .get(taker_id)
+            .cloned()
+            .or_else(|| self.current_order.clone())
+    }
+
+    fn broadcast_order(&self) -> maker_inc_connections::BroadcastOrder {
+        maker_inc_connections::BroadcastOrder {
+            order: self.current_order.clone(),
+            overrides: self.order_overrides.clone(),
+        }
+    }
+
     async fn update_connected_takers(&mut self) -> Result<()> {
         self.projection_actor
             .send(Update(
                 self.connected_takers
-                    .clone()
-                    .into_iter()
-                    .collect::<Vec<Identity>>(),
+                    .iter()
+                    .map(|(id, display_name)| projection::ConnectedTaker {
+                        id: *id,
+                        display_name: display_name.clone(),
+                    })
+                    .collect::<Vec<_>>(),
             ))
             .await?;
         Ok(())
@@ -155,15 +375,23 @@ impl<O, T, W> Actor<O, T, W>
 where
     T: xtra::Handler<maker_inc_connections::TakerMessage>,
 {
-    async fn handle_taker_connected(&mut self, taker_id: Identity) -> Result<()> {
+    async fn handle_taker_connected(
+        &mut self,
+        taker_id: Identity,
+        display_name: Option<DisplayName>,
+    ) -> Result<()> {
         self.takers
             .send_async_safe(maker_inc_connections::TakerMessage {
                 taker_id,
-                msg: wire::MakerToTaker::CurrentOrder(self.current_order.clone()),
+                msg: wire::MakerToTaker::CurrentOrder(self.order_for(&taker_id)),
             })
             .await?;
 
-        if !self.connected_takers.insert(taker_id) {
+        if self
+            .connected_takers
+            .insert(taker_id, display_name)
+            .is_some()
+        {
             tracing::warn!("Taker already connected: {:?}", &taker_id);
         }
         self.update_connected_takers().await?;
@@ -171,7 +399,7 @@ where
     }
 
     async fn handle_taker_disconnected(&mut self, taker_id: Identity) -> Result<()> {
-        if !self.connected_takers.remove(&taker_id) {
+        if self.connected_takers.remove(&taker_id).is_none() {
             tracing::warn!("Removed unknown taker: {:?}", &taker_id);
         }
         self.update_connected_takers().await?;
@@ -204,7 +432,22 @@ where
         let mut conn = self.db.acquire().await?;
         let cfd = load_cfd(proposal.order_id, &mut conn).await?;
 
-        cfd.is_rollover_possible(OffsetDateTime::now_utc())?;
+        cfd.is_rollover_possible(self.clock.now())?;
+
+        if let Some(max_age) = self.max_position_age {
+            if let Err(e) = cfd.ensure_within_max_age(self.clock.now(), max_age) {
+                tracing::info!(order_id = %proposal.order_id, "Rejecting rollover: {:#}", e);
+
+                self.takers
+                    .send(maker_inc_connections::TakerMessage {
+                        taker_id,
+                        msg: wire::MakerToTaker::RejectRollOver(proposal.order_id),
+                    })
+                    .await??;
+
+                return Ok(());
+            }
+        }
 
         let this = ctx.address().expect("acquired own address");
 
@@ -223,7 +466,8 @@ where
         .create(None)
         .run();
 
-        self.tasks.add(rollover_actor_future);
+        self.tasks
+            .add(rollover_actor_future.instrument(cfd_actors::cfd_span(proposal.order_id)));
 
         self.takers
             .send(RollOverProposed {
@@ -255,6 +499,8 @@ where
         taker_id: Identity,
         order_id: OrderId,
         quantity: Usd,
+        fee_rate: Option<u32>,
+        leverage: Option<Leverage>,
         ctx: &mut Context<Self>,
     ) -> Result<()> {
         tracing::debug!(%taker_id, %quantity, %order_id, "Taker wants to take an order");
@@ -272,8 +518,8 @@ where
         let mut conn = self.db.acquire().await?;
 
         // 1. Validate if order is still valid
-        let current_order = match &self.current_order {
-            Some(current_order) if current_order.id == order_id => current_order.clone(),
+        let current_order = match self.order_for(&taker_id) {
+            Some(current_order) if current_order.id == order_id => current_order,
             _ => {
                 // An outdated order on the taker side does not require any state change on the
                 // maker. notifying the taker with a specific message should be sufficient.
@@ -292,41 +538,119 @@ where
             }
         };
 
+        // 2. Validate that the taker does not exceed the maker's exposure limit
+        if let Some(max_quantity_per_taker) = self.max_quantity_per_taker {
+            let taken_quantity = cfd_actors::load_exposure(taker_id, &mut conn).await?;
+
+            if taken_quantity + quantity > max_quantity_per_taker {
+                tracing::info!(
+                    %taker_id,
+                    %order_id,
+                    %taken_quantity,
+                    "Order rejected: quantity {} exceeds the limit of {} per taker",
+                    quantity,
+                    max_quantity_per_taker
+                );
+
+                self.takers
+                    .send(maker_inc_connections::TakerMessage {
+                        taker_id,
+                        msg: wire::MakerToTaker::RejectOrder(order_id),
+                    })
+                    .await??;
+
+                return Ok(());
+            }
+        }
+
+        // 3. Validate the fee rate the taker asked for
+        let fee_rate = match current_order.fee_rate_for_setup(fee_rate) {
+            Ok(fee_rate) => fee_rate,
+            Err(e) => {
+                tracing::info!(%taker_id, %order_id, "Order rejected: {:#}", e);
+
+                self.takers
+                    .send(maker_inc_connections::TakerMessage {
+                        taker_id,
+                        msg: wire::MakerToTaker::RejectOrder(order_id),
+                    })
+                    .await??;
+
+                return Ok(());
+            }
+        };
+
+        // 4. Validate the leverage the taker chose
+        let leverage = match current_order.leverage_for_take(leverage) {
+            Ok(leverage) => leverage,
+            Err(e) => {
+                tracing::info!(%taker_id, %order_id, "Order rejected: {:#}", e);
+
+                self.takers
+                    .send(maker_inc_connections::TakerMessage {
+                        taker_id,
+                        msg: wire::MakerToTaker::RejectOrder(order_id),
+                    })
+                    .await??;
+
+                return Ok(());
+            }
+        };
+
         let cfd = Cfd::from_order(
             current_order.clone(),
             Position::Short,
             quantity,
+            leverage,
             taker_id,
             Role::Maker,
         );
 
-        // 2. Remove current order
+        // 5. Remove current order
         // The order is removed before we update the state, because the maker might react on the
         // state change. Once we know that we go for either an accept/reject scenario we
         // have to remove the current order.
-        self.current_order = None;
-
-        self.takers
-            .send_async_safe(maker_inc_connections::BroadcastOrder(None))
-            .await?;
+        if self.order_overrides.remove(&taker_id).is_some() {
+            // Only the taker's own order was taken, it falls back to the current order
+            self.takers
+                .send_async_safe(maker_inc_connections::TakerMessage {
+                    taker_id,
+                    msg: wire::MakerToTaker::CurrentOrder(self.current_order.clone()),
+                })
+                .await?;
+        } else {
+            self.current_order = None;
+
+            self.takers.send_async_safe(self.broadcast_order()).await?;
+
+            self.projection_actor.send(projection::Update(None)).await?;
+        }
 
-        self.projection_actor.send(projection::Update(None)).await?;
         insert_cfd_and_update_feed(&cfd, &mut conn, &self.projection_actor).await?;
 
-        // 4. Try to get the oracle announcement, if that fails we should exit prior to changing any
+        tracing::info!(%taker_id, %order_id, %quantity, "Order taken");
+        self.projection_actor
+            .send(projection::Update(projection::OrderTaken {
+                order_id,
+                taker_id,
+                quantity,
+            }))
+            .await?;
+
+        // 6. Try to get the oracle announcement, if that fails we should exit prior to changing any
         // state
         let announcement = self
             .oracle_actor
             .send(oracle::GetAnnouncement(current_order.oracle_event_id))
             .await??;
 
-        // 5. Start up contract setup actor
+        // 7. Start up contract setup actor
         let this = ctx
             .address()
             .expect("actor to be able to give address to itself");
 
         let (addr, fut) = setup_maker::Actor::new(
-            (cfd, current_order, self.n_payouts),
+            (cfd, current_order, fee_rate, self.n_payouts),
             (self.oracle_pk, announcement),
             &self.wallet,
             &self.wallet,
@@ -339,7 +663,8 @@ where
 
         disconnected.insert(addr);
 
-        self.tasks.add(fut);
+        self.tasks
+            .add(fut.instrument(cfd_actors::cfd_span(order_id)));
 
         Ok(())
     }
@@ -395,6 +720,17 @@ impl<O, T, W> Actor<O, T, W> {
         Ok(())
     }
 
+    async fn handle_counter_settlement(&mut self, msg: CounterSettlement) -> Result<()> {
+        let CounterSettlement { order_id, price } = msg;
+
+        self.settlement_actors
+            .send_fallible(&order_id, collab_settlement_maker::Countered { price })
+            .await
+            .with_context(|| format!("No settlement in progress for order {}", order_id))??;
+
+        Ok(())
+    }
+
     async fn handle_accept_rollover(&mut self, msg: AcceptRollOver) -> Result<()> {
         if self
             .rollover_actors
@@ -429,6 +765,80 @@ impl<O, T, W> Actor<O, T, W> {
 
         Ok(())
     }
+
+    async fn handle_commit_and_settle(&mut self, msg: CommitAndSettle) -> Result<()> {
+        let CommitAndSettle { order_id } = msg;
+
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::handle_commit_and_settle(order_id, &mut conn, &self.process_manager_actor)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn handle_refund(&mut self, msg: Refund) -> Result<()> {
+        let Refund { order_id } = msg;
+
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::handle_refund(order_id, &mut conn, &self.process_manager_actor).await?;
+
+        Ok(())
+    }
+
+    async fn handle_pause_monitoring(&mut self, msg: PauseMonitoring) -> Result<()> {
+        let PauseMonitoring { order_id } = msg;
+
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::handle_pause_monitoring(order_id, &mut conn, &self.process_manager_actor)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn handle_resume_monitoring(&mut self, msg: ResumeMonitoring) -> Result<()> {
+        let ResumeMonitoring { order_id } = msg;
+
+        cfd_actors::handle_resume_monitoring(
+            order_id,
+            &self.db,
+            &self.process_manager_actor,
+            &mut self.paused_events,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn handle_archive(&mut self, msg: Archive) -> Result<()> {
+        let Archive { order_id } = msg;
+
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::handle_archive(order_id, &mut conn, &self.process_manager_actor).await?;
+
+        Ok(())
+    }
+
+    async fn handle_get_commit_tx(&mut self, msg: GetCommitTx) -> Result<SignedCommitTx> {
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::load_signed_commit_tx(msg.order_id, &mut conn).await
+    }
+
+    async fn handle_get_dlc_summary(&mut self, msg: GetDlcSummary) -> Result<DlcSummary> {
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::load_dlc_summary(msg.order_id, &mut conn).await
+    }
+
+    async fn handle_get_close_options(&mut self, msg: GetCloseOptions) -> Result<Vec<CloseOption>> {
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::load_close_options(msg.order_id, msg.current_price, self.n_payouts, &mut conn)
+            .await
+    }
+
+    async fn handle_get_action_costs(&mut self, msg: GetActionCosts) -> Result<ActionCosts> {
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::load_action_costs(msg.order_id, msg.current_price, self.n_payouts, &mut conn)
+            .await
+    }
 }
 
 #[xtra_productivity(message_impl = false)]
@@ -438,6 +848,7 @@ impl<O, T, W> Actor<O, T, W> {
         let mut conn = self.db.acquire().await?;
 
         let cfd = load_cfd(order_id, &mut conn).await?;
+
         let event = cfd.setup_contract(msg)?;
         if let Err(e) = self
             .process_manager_actor
@@ -454,6 +865,10 @@ impl<O, T, W> Actor<O, T, W> {
         self.setup_actors.gc(message);
     }
 
+    async fn handle_quote(&mut self, msg: Update<price_feed::Quote>) {
+        self.latest_quote = Some(msg.0);
+    }
+
     async fn handle_settlement_completed(
         &mut self,
         msg: model::cfd::Completed<CollaborativeSettlement>,
@@ -462,10 +877,13 @@ impl<O, T, W> Actor<O, T, W> {
         let mut conn = self.db.acquire().await?;
         let cfd = load_cfd(order_id, &mut conn).await?;
 
-        let event = cfd.settle_collaboratively(msg)?;
+        let event = match cfd.settle_collaboratively(msg, self.settlement_rejection_policy)? {
+            Some(event) => event,
+            None => return Ok(()),
+        };
         if let Err(e) = self
             .process_manager_actor
-            .send(process_manager::Event::new(event.clone()))
+            .send(process_manager::Event::new(event))
             .await?
         {
             tracing::error!("Sending event to process manager failed: {:#}", e);
@@ -482,8 +900,13 @@ impl<O, T, W> Actor<O, T, W> {
     }
 
     async fn handle_monitor(&mut self, msg: monitor::Event) {
-        if let Err(e) =
-            cfd_actors::handle_monitoring_event(msg, &self.db, &self.process_manager_actor).await
+        if let Err(e) = cfd_actors::handle_monitoring_event(
+            msg,
+            &self.db,
+            &self.process_manager_actor,
+            &mut self.paused_events,
+        )
+        .await
         {
             tracing::error!("Unable to handle monotoring event: {:#}", e)
         }
@@ -536,13 +959,32 @@ where
                 )
             })?;
 
+        let order_id = proposal.order_id;
+
+        if let Err(reason) = self.ensure_settlement_price_near_market(proposal.price) {
+            tracing::info!(%order_id, "Rejecting settlement proposal: {:#}", reason);
+
+            self.takers
+                .send(maker_inc_connections::settlement::Response {
+                    taker_id,
+                    order_id,
+                    decision: maker_inc_connections::settlement::Decision::Reject,
+                })
+                .await??;
+
+            return Ok(());
+        }
+
         let mut conn = self.db.acquire().await?;
-        let cfd = load_cfd(proposal.order_id, &mut conn).await?;
+        let cfd = load_cfd(order_id, &mut conn).await?;
 
         let this = ctx.address().expect("self to be alive");
         let (addr, task) = collab_settlement_maker::Actor::new(
             cfd,
             proposal,
+            self.n_payouts,
+            self.max_settlement_proposal_age,
+            self.clock.clone(),
             self.projection_actor.clone(),
             &ctx.address().expect("we are alive"),
             taker_id,
@@ -552,7 +994,8 @@ where
         .create(None)
         .run();
 
-        self.tasks.add(task);
+        self.tasks
+            .add(task.instrument(cfd_actors::cfd_span(order_id)));
         disconnected.insert(addr);
 
         Ok(())
@@ -562,29 +1005,11 @@ where
 #[xtra_productivity]
 impl<O, T, W> Actor<O, T, W>
 where
-    T: xtra::Handler<maker_inc_connections::BroadcastOrder>,
+    T: xtra::Handler<maker_inc_connections::BroadcastOrder>
+        + xtra::Handler<maker_inc_connections::TakerMessage>,
 {
     async fn handle_new_order(&mut self, msg: NewOrder) -> Result<()> {
-        let NewOrder {
-            price,
-            min_quantity,
-            max_quantity,
-            fee_rate,
-        } = msg;
-
-        let oracle_event_id = oracle::next_announcement_after(
-            time::OffsetDateTime::now_utc() + self.settlement_interval,
-        )?;
-
-        let order = Order::new_short(
-            price,
-            min_quantity,
-            max_quantity,
-            Origin::Ours,
-            oracle_event_id,
-            self.settlement_interval,
-            fee_rate,
-        )?;
+        let order = self.build_order(msg)?;
 
         // 1. Update actor state to current order
         self.current_order.replace(order.clone());
@@ -595,9 +1020,50 @@ where
             .await?;
 
         // 3. Inform connected takers
-        self.takers
-            .send(maker_inc_connections::BroadcastOrder(Some(order)))
-            .await?;
+        self.takers.send(self.broadcast_order()).await?;
+
+        Ok(())
+    }
+
+    async fn handle_override_order(&mut self, msg: OverrideOrder) -> Result<()> {
+        let OverrideOrder { taker_id, order } = msg;
+
+        match order {
+            Some(new_order) => {
+                let order = self.build_order(new_order)?;
+
+                tracing::info!(%taker_id, order_id = %order.id, "Overriding order for taker");
+                self.order_overrides.insert(taker_id, order);
+            }
+            None => {
+                self.order_overrides
+                    .remove(&taker_id)
+                    .with_context(|| format!("No order override for taker {}", taker_id))?;
+            }
+        }
+
+        if self.connected_takers.contains_key(&taker_id) {
+            self.takers
+                .send(maker_inc_connections::TakerMessage {
+                    taker_id,
+                    msg: wire::MakerToTaker::CurrentOrder(self.order_for(&taker_id)),
+                })
+                .await??;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_withdraw_order(&mut self, _: WithdrawOrder) -> Result<()> {
+        let order = self
+            .current_order
+            .take()
+            .context("No current order to withdraw")?;
+
+        tracing::info!(order_id = %order.id, "Withdrawing current order");
+
+        self.projection_actor.send(projection::Update(None)).await?;
+        self.takers.send(self.broadcast_order()).await?;
 
         Ok(())
     }
@@ -609,7 +1075,7 @@ where
     T: xtra::Handler<maker_inc_connections::TakerMessage>,
 {
     async fn handle(&mut self, msg: TakerConnected, _ctx: &mut Context<Self>) -> Result<()> {
-        self.handle_taker_connected(msg.id).await
+        self.handle_taker_connected(msg.id, msg.display_name).await
     }
 }
 
@@ -651,9 +1117,14 @@ where
 {
     async fn handle(&mut self, FromTaker { taker_id, msg }: FromTaker, ctx: &mut Context<Self>) {
         match msg {
-            wire::TakerToMaker::TakeOrder { order_id, quantity } => {
+            wire::TakerToMaker::TakeOrder {
+                order_id,
+                quantity,
+                fee_rate,
+                leverage,
+            } => {
                 if let Err(e) = self
-                    .handle_take_order(taker_id, order_id, quantity, ctx)
+                    .handle_take_order(taker_id, order_id, quantity, fee_rate, leverage, ctx)
                     .await
                 {
                     tracing::error!("Error when handling order take request: {:#}", e)
@@ -687,7 +1158,9 @@ where
                 }
             }
             wire::TakerToMaker::Settlement {
-                msg: wire::taker_to_maker::Settlement::Initiate { .. },
+                msg:
+                    wire::taker_to_maker::Settlement::Initiate { .. }
+                    | wire::taker_to_maker::Settlement::RejectCounter,
                 ..
             } => {
                 unreachable!("Handled within `collab_settlement_maker::Actor");
@@ -719,6 +1192,9 @@ where
             TakerToMaker::Hello(_) => {
                 unreachable!("The Hello message is not sent to the cfd actor")
             }
+            TakerToMaker::Introduction { .. } => {
+                tracing::warn!(%taker_id, "Ignoring unexpected Introduction message from taker. Introduction is only expected when opening a new connection.")
+            }
         }
     }
 }
@@ -7,6 +7,8 @@ use crate::maker_cfd::TakerConnected;
 use crate::maker_cfd::TakerDisconnected;
 use crate::model::cfd::Order;
 use crate::model::cfd::OrderId;
+use crate::model::cfd::SettlementProposal;
+use crate::model::DisplayName;
 use crate::model::Identity;
 use crate::noise;
 use crate::noise::TransportStateExt;
@@ -37,7 +39,13 @@ use xtra::prelude::*;
 use xtra::KeepRunning;
 use xtra_productivity::xtra_productivity;
 
-pub struct BroadcastOrder(pub Option<Order>);
+/// Send the current order to all connected takers.
+///
+/// Takers with an entry in `overrides` are sent their own order instead.
+pub struct BroadcastOrder {
+    pub order: Option<Order>,
+    pub overrides: HashMap<Identity, Order>,
+}
 
 /// Message sent from the `setup_maker::Actor` to the
 /// `maker_inc_connections::Actor` so that it can forward it to the
@@ -76,6 +84,10 @@ pub mod settlement {
             address: xtra::Address<collab_settlement_maker::Actor>,
         },
         Reject,
+        Counter {
+            proposal: SettlementProposal,
+            address: xtra::Address<collab_settlement_maker::Actor>,
+        },
     }
 }
 
@@ -102,6 +114,8 @@ pub struct Actor {
     taker_msg_channel: Box<dyn MessageChannel<FromTaker>>,
     noise_priv_key: x25519_dalek::StaticSecret,
     heartbeat_interval: Duration,
+    display_name: Option<DisplayName>,
+    min_compatible_version: Version,
     setup_actors: AddressMap<OrderId, setup_maker::Actor>,
     settlement_actors: AddressMap<OrderId, collab_settlement_maker::Actor>,
     rollover_actors: AddressMap<OrderId, rollover_maker::Actor>,
@@ -136,6 +150,8 @@ impl Actor {
         taker_msg_channel: Box<dyn MessageChannel<FromTaker>>,
         noise_priv_key: x25519_dalek::StaticSecret,
         heartbeat_interval: Duration,
+        display_name: Option<DisplayName>,
+        min_compatible_version: Version,
     ) -> Self {
         Self {
             connections: HashMap::new(),
@@ -144,6 +160,8 @@ impl Actor {
             taker_msg_channel: taker_msg_channel.clone_channel(),
             noise_priv_key,
             heartbeat_interval,
+            display_name,
+            min_compatible_version,
             setup_actors: AddressMap::default(),
             settlement_actors: AddressMap::default(),
             rollover_actors: AddressMap::default(),
@@ -205,16 +223,25 @@ impl Actor {
                 let our_version = Version::current();
                 write.send(MakerToTaker::Hello(our_version.clone())).await?;
 
-                if our_version != taker_version {
+                if !taker_version.is_compatible(&self.min_compatible_version) {
                     tracing::debug!(
                         "Network version mismatch, we are on version {} but taker is on version {}",
                         our_version,
                         taker_version
                     );
 
-                    // A taker running a different version is not treated as error for the maker
+                    // A taker running an incompatible version is not treated as error for the maker
                     return Ok(());
                 }
+
+                if our_version != taker_version {
+                    tracing::warn!(
+                        %taker_id,
+                        "Taker is on version {} but we are on version {}, accepting the connection because the versions are compatible",
+                        taker_version,
+                        our_version
+                    );
+                }
             }
             unexpected_message => {
                 bail!(
@@ -225,7 +252,35 @@ impl Actor {
             }
         }
 
-        tracing::info!(%taker_id, address = %taker_address, "New taker connected");
+        write
+            .send(MakerToTaker::Introduction {
+                display_name: self.display_name.as_ref().map(|name| name.to_string()),
+            })
+            .await?;
+
+        let display_name = match read
+            .try_next()
+            .timeout(Duration::from_secs(10))
+            .await
+            .with_context(|| {
+                format!(
+                    "Taker {} did not introduce itself within 10 seconds, dropping connection",
+                    taker_id
+                )
+            })? {
+            Ok(Some(TakerToMaker::Introduction { display_name })) => {
+                display_name.and_then(|name| DisplayName::sanitize(&name))
+            }
+            unexpected_message => {
+                bail!(
+                    "Unexpected message {:?} from taker {}",
+                    unexpected_message,
+                    taker_id
+                );
+            }
+        };
+
+        tracing::info!(%taker_id, address = %taker_address, display_name = ?display_name, "New taker connected");
 
         let this = ctx.address().expect("self to be alive");
         let read_fut = async move {
@@ -259,7 +314,10 @@ impl Actor {
 
         let _ = self
             .taker_connected_channel
-            .send(maker_cfd::TakerConnected { id: taker_id })
+            .send(maker_cfd::TakerConnected {
+                id: taker_id,
+                display_name,
+            })
             .log_failure("Failed to report new taker connection")
             .await;
 
@@ -276,11 +334,13 @@ pub struct NoConnection(Identity);
 #[xtra_productivity]
 impl Actor {
     async fn handle_broadcast_order(&mut self, msg: BroadcastOrder) {
-        let order = msg.0;
+        let BroadcastOrder { order, overrides } = msg;
 
         let mut broken_connections = Vec::with_capacity(self.connections.len());
 
         for (id, conn) in &mut self.connections {
+            let order = overrides.get(id).cloned().or_else(|| order.clone());
+
             if let Err(e) = conn
                 .send(wire::MakerToTaker::CurrentOrder(order.clone()))
                 .await
@@ -328,6 +388,15 @@ impl Actor {
                 wire::maker_to_taker::Settlement::Confirm
             }
             settlement::Decision::Reject => wire::maker_to_taker::Settlement::Reject,
+            settlement::Decision::Counter { proposal, address } => {
+                self.settlement_actors.insert(msg.order_id, address);
+
+                wire::maker_to_taker::Settlement::Counter {
+                    taker: proposal.taker,
+                    maker: proposal.maker,
+                    price: proposal.price,
+                }
+            }
         };
 
         self.send_to_taker(
@@ -419,6 +488,19 @@ impl Actor {
                     tracing::warn!(%order_id, "No active settlement");
                 }
             }
+            Settlement {
+                order_id,
+                msg: taker_to_maker::Settlement::RejectCounter,
+            } => {
+                if self
+                    .settlement_actors
+                    .send(&order_id, collab_settlement_maker::CounterRejected)
+                    .await
+                    .is_err()
+                {
+                    tracing::warn!(%order_id, "No active settlement");
+                }
+            }
             _ => {
                 let _ = self.taker_msg_channel.send(msg);
             }
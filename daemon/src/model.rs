@@ -1,5 +1,6 @@
 use crate::impl_sqlx_type_display_from_str;
 use crate::olivia;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use bdk::bitcoin::Address;
@@ -8,6 +9,7 @@ use bdk::bitcoin::Denomination;
 use chrono::DateTime;
 use derive_more::Display;
 use reqwest::Url;
+use rocket::request::FromParam;
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
 use serde::de::Error as _;
@@ -15,6 +17,7 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_with::DeserializeFromStr;
 use serde_with::SerializeDisplay;
+use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::fmt;
 use std::num::NonZeroU8;
@@ -39,10 +42,15 @@ pub enum Error {
     NegativePrice,
 }
 
+/// An amount of US dollars.
+///
+/// Unlike [`Price`] this is not validated, it is signed as it also represents profits and losses.
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Usd(Decimal);
 
 impl Usd {
+    pub const ZERO: Self = Self(Decimal::ZERO);
+
     pub fn new(value: Decimal) -> Self {
         Self(value)
     }
@@ -78,12 +86,19 @@ impl str::FromStr for Usd {
 
 impl_sqlx_type_display_from_str!(Usd);
 
+/// A strictly positive price.
+///
+/// Deserializing and parsing validate the price like [`Price::new`].
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "Decimal")]
 pub struct Price(Decimal);
 
 impl_sqlx_type_display_from_str!(Price);
 
 impl Price {
+    /// Rejects zero and negative prices.
+    ///
+    /// `Decimal` cannot represent NaN or infinity, so there is nothing else to reject.
     pub fn new(value: Decimal) -> Result<Self, Error> {
         if value == Decimal::ZERO {
             return Result::Err(Error::ZeroPrice);
@@ -121,7 +136,17 @@ impl str::FromStr for Price {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let dec = Decimal::from_str(s)?;
-        Ok(Price(dec))
+        let price = Price::new(dec)?;
+
+        Ok(price)
+    }
+}
+
+impl TryFrom<Decimal> for Price {
+    type Error = Error;
+
+    fn try_from(value: Decimal) -> Result<Self, Self::Error> {
+        Price::new(value)
     }
 }
 
@@ -472,8 +497,72 @@ impl str::FromStr for Identity {
     }
 }
 
+impl<'v> FromParam<'v> for Identity {
+    type Error = hex::FromHexError;
+
+    fn from_param(param: &'v str) -> Result<Self, Self::Error> {
+        param.parse()
+    }
+}
+
 impl_sqlx_type_display_from_str!(Identity);
 
+/// A human-readable name a maker or taker introduces itself with.
+///
+/// The name is only used for display, a party is still identified by its [`Identity`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct DisplayName(String);
+
+impl DisplayName {
+    /// The maximum number of characters of a display name.
+    pub const MAX_LEN: usize = 32;
+
+    /// Turn a name received from a peer into a display name.
+    ///
+    /// Control characters are dropped and the name is truncated to [`DisplayName::MAX_LEN`]
+    /// characters. Returns `None` if nothing but whitespace is left.
+    pub fn sanitize(name: &str) -> Option<Self> {
+        let name = name.chars().filter(|c| !c.is_control()).collect::<String>();
+        let name = name.trim().chars().take(Self::MAX_LEN).collect::<String>();
+        let name = name.trim_end();
+
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(Self(name.to_owned()))
+    }
+}
+
+impl fmt::Display for DisplayName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl str::FromStr for DisplayName {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim();
+
+        if name.is_empty() {
+            bail!("Display name must not be empty");
+        }
+        if name.chars().count() > Self::MAX_LEN {
+            bail!(
+                "Display name must not be longer than {} characters",
+                Self::MAX_LEN
+            );
+        }
+        if name.chars().any(char::is_control) {
+            bail!("Display name must not contain control characters");
+        }
+
+        Ok(Self(name.to_owned()))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct WalletInfo {
     pub balance: Amount,
@@ -517,10 +606,8 @@ impl BitMexPriceEventId {
         now > self.timestamp
     }
 
-    pub fn to_olivia_url(self) -> Url {
-        "https://h00.ooo"
-            .parse::<Url>()
-            .expect("valid URL from constant")
+    pub fn to_olivia_url(self, oracle: &Url) -> Url {
+        oracle
             .join(&self.to_string())
             .expect("Event id can be joined")
     }
@@ -562,7 +649,9 @@ impl str::FromStr for BitMexPriceEventId {
 
 impl_sqlx_type_display_from_str!(BitMexPriceEventId);
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, sqlx::Type)]
+#[derive(
+    Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, sqlx::Type,
+)]
 #[sqlx(transparent)]
 pub struct Timestamp(i64);
 
@@ -607,10 +696,34 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn price_rejects_non_positive_values() {
+        assert!(matches!(Price::new(dec!(0)), Err(Error::ZeroPrice)));
+        assert!(matches!(Price::new(dec!(-0.01)), Err(Error::NegativePrice)));
+        assert!(Price::new(dec!(0.01)).is_ok());
+
+        assert!("0".parse::<Price>().is_err());
+        assert!("-40000".parse::<Price>().is_err());
+        assert_eq!(
+            "40000".parse::<Price>().unwrap(),
+            Price::new(dec!(40000)).unwrap()
+        );
+    }
+
+    #[test]
+    fn deserializing_price_rejects_non_positive_values() {
+        assert!(serde_json::from_str::<Price>("\"0\"").is_err());
+        assert!(serde_json::from_str::<Price>("\"-1.5\"").is_err());
+
+        let price = Price::new(dec!(40000.5)).unwrap();
+        let json = serde_json::to_string(&price).unwrap();
+        assert_eq!(serde_json::from_str::<Price>(&json).unwrap(), price);
+    }
+
     #[test]
     fn to_olivia_url() {
         let url = BitMexPriceEventId::with_20_digits(datetime!(2021-09-23 10:00:00).assume_utc())
-            .to_olivia_url();
+            .to_olivia_url(&crate::oracle::OLIVIA_URL.parse().unwrap());
 
         assert_eq!(
             url,
@@ -723,4 +836,24 @@ mod tests {
             )],
         );
     }
+
+    #[test]
+    fn display_name_from_peer_is_sanitized() {
+        let name = DisplayName::sanitize("  Alice\n\u{7}'s maker  ").unwrap();
+        assert_eq!(name.to_string(), "Alice's maker");
+
+        let name = DisplayName::sanitize(&"a".repeat(100)).unwrap();
+        assert_eq!(name.to_string().len(), DisplayName::MAX_LEN);
+
+        assert_eq!(DisplayName::sanitize(" \t\n "), None);
+    }
+
+    #[test]
+    fn invalid_display_name_is_rejected() {
+        assert!("Alice".parse::<DisplayName>().is_ok());
+
+        assert!("".parse::<DisplayName>().is_err());
+        assert!("a".repeat(33).parse::<DisplayName>().is_err());
+        assert!("Alice\u{7}".parse::<DisplayName>().is_err());
+    }
 }
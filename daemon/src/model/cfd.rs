@@ -16,12 +16,16 @@ use crate::SETTLEMENT_INTERVAL;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::hashes::Hash as _;
+use bdk::bitcoin::hashes::HashEngine;
 use bdk::bitcoin::secp256k1::SecretKey;
 use bdk::bitcoin::secp256k1::Signature;
 use bdk::bitcoin::Address;
 use bdk::bitcoin::Amount;
 use bdk::bitcoin::PublicKey;
 use bdk::bitcoin::Script;
+use bdk::bitcoin::SigHashType;
 use bdk::bitcoin::SignedAmount;
 use bdk::bitcoin::Transaction;
 use bdk::bitcoin::Txid;
@@ -39,6 +43,8 @@ use rust_decimal::Decimal;
 use serde::de::Error as _;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_with::DeserializeFromStr;
+use serde_with::SerializeDisplay;
 use std::collections::HashMap;
 use std::fmt;
 use std::ops::RangeInclusive;
@@ -50,6 +56,11 @@ use uuid::Uuid;
 
 pub const CET_TIMELOCK: u32 = 12;
 
+/// How long a delayed commit transaction waits for the collaborative settlement transaction to
+/// confirm. Beyond that the settlement transaction is assumed to be stuck and the commit
+/// transaction is published anyway.
+pub const UNCONFIRMED_SETTLEMENT_TIMEOUT: Duration = Duration::hours(12);
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, sqlx::Type)]
 #[sqlx(transparent)]
 pub struct OrderId(Hyphenated);
@@ -105,7 +116,7 @@ pub enum Origin {
 }
 
 /// Role in the Cfd
-#[derive(Debug, Copy, Clone, PartialEq, sqlx::Type)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, sqlx::Type)]
 pub enum Role {
     Maker,
     Taker,
@@ -153,6 +164,10 @@ pub struct Order {
     pub oracle_event_id: BitMexPriceEventId,
 
     pub fee_rate: u32,
+
+    /// The leverages the taker can choose from in addition to [`Order::leverage`].
+    #[serde(default)]
+    pub leverage_choices: Vec<Leverage>,
 }
 
 impl Order {
@@ -165,6 +180,18 @@ impl Order {
         settlement_interval: Duration,
         fee_rate: u32,
     ) -> Result<Self> {
+        anyhow::ensure!(
+            min_quantity > Usd::ZERO,
+            "Minimum quantity must be positive, got {}",
+            min_quantity
+        );
+        anyhow::ensure!(
+            min_quantity <= max_quantity,
+            "Minimum quantity {} exceeds maximum quantity {}",
+            min_quantity,
+            max_quantity
+        );
+
         let leverage = Leverage::new(2)?;
         let liquidation_price = calculate_long_liquidation_price(leverage, price);
 
@@ -182,8 +209,118 @@ impl Order {
             origin,
             oracle_event_id,
             fee_rate,
+            leverage_choices: Vec::new(),
         })
     }
+
+    /// Offer the taker to choose from `leverage_choices` besides [`Order::leverage`].
+    pub fn with_leverage_choices(mut self, leverage_choices: &[Leverage]) -> Self {
+        self.leverage_choices.clear();
+
+        for leverage in leverage_choices {
+            if *leverage != self.leverage && !self.leverage_choices.contains(leverage) {
+                self.leverage_choices.push(*leverage);
+            }
+        }
+
+        self
+    }
+
+    /// The position of the taker, the opposite of the maker's position in the order.
+    pub fn taker_position(&self) -> Position {
+        self.position.counter_position()
+    }
+
+    /// The fee rate to set up a contract for this order with.
+    ///
+    /// The taker may override the fee rate of the order, e.g. to avoid stuck transactions during
+    /// fee spikes, as long as it is within [`FEE_RATE_BAND`].
+    pub fn fee_rate_for_setup(&self, fee_rate_override: Option<u32>) -> Result<u32> {
+        let fee_rate = match fee_rate_override {
+            Some(fee_rate) => fee_rate,
+            None => return Ok(self.fee_rate),
+        };
+
+        if !FEE_RATE_BAND.contains(&fee_rate) {
+            bail!(
+                "Fee rate {} sat/vbyte is outside of the allowed range of {} to {} sat/vbyte",
+                fee_rate,
+                FEE_RATE_BAND.start(),
+                FEE_RATE_BAND.end()
+            )
+        }
+
+        Ok(fee_rate)
+    }
+
+    /// The leverages a taker can choose from when taking this order, [`Order::leverage`] first.
+    pub fn leverage_choices(&self) -> Vec<Leverage> {
+        std::iter::once(self.leverage)
+            .chain(self.leverage_choices.iter().copied())
+            .collect()
+    }
+
+    /// The leverage to take this order with.
+    ///
+    /// Defaults to [`Order::leverage`] if the taker did not choose one.
+    pub fn leverage_for_take(
+        &self,
+        leverage: Option<Leverage>,
+    ) -> Result<Leverage, LeverageNotOffered> {
+        let leverage = match leverage {
+            Some(leverage) => leverage,
+            None => return Ok(self.leverage),
+        };
+
+        let offered = self.leverage_choices();
+        if !offered.contains(&leverage) {
+            return Err(LeverageNotOffered {
+                requested: leverage,
+                offered,
+            });
+        }
+
+        Ok(leverage)
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Leverage {requested} is not offered by the order")]
+pub struct LeverageNotOffered {
+    pub requested: Leverage,
+    pub offered: Vec<Leverage>,
+}
+
+/// The fee rates in sat/vbyte a contract can be set up with.
+pub const FEE_RATE_BAND: RangeInclusive<u32> = 1..=500;
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum CannotTakeOrder {
+    #[error("The maker has no order to take")]
+    NoCurrentOrder,
+    #[error("Order {requested} is no longer available, the maker's current order is {current}")]
+    NoLongerCurrent {
+        requested: OrderId,
+        current: OrderId,
+    },
+}
+
+/// The order with `order_id` if it is the maker's `current_order`, the only order that can be
+/// taken.
+pub fn order_to_take(
+    current_order: Option<&Order>,
+    order_id: OrderId,
+) -> Result<&Order, CannotTakeOrder> {
+    let current_order = current_order.ok_or(CannotTakeOrder::NoCurrentOrder)?;
+
+    if current_order.id != order_id {
+        return Err(CannotTakeOrder::NoLongerCurrent {
+            requested: order_id,
+            current: current_order.id,
+        });
+    }
+
+    Ok(current_order)
 }
 
 /// Proposed collaborative settlement
@@ -219,6 +356,8 @@ pub enum CannotRollover {
     WasJustRolledOver,
     #[error("Cannot roll over in state {state}")]
     WrongState { state: String },
+    #[error("The Cfd exceeded the maximum position age")]
+    MaxAgeExceeded,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -246,7 +385,10 @@ pub enum CfdEvent {
         dlc: Dlc,
     },
 
-    ContractSetupFailed,
+    ContractSetupFailed {
+        /// Why the contract setup failed, not recorded for events of version 1 and before.
+        reason: Option<String>,
+    },
     OfferRejected,
 
     RolloverCompleted {
@@ -260,6 +402,14 @@ pub enum CfdEvent {
         spend_tx: Transaction,
         script: Script,
         price: Price,
+        /// The payout of the maker agreed on in the settlement proposal, not recorded for events
+        /// of version 2 and before.
+        #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat::opt")]
+        maker: Option<Amount>,
+        /// The payout of the taker agreed on in the settlement proposal, not recorded for events
+        /// of version 2 and before.
+        #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat::opt")]
+        taker: Option<Amount>,
     },
     CollaborativeSettlementRejected {
         #[serde(with = "hex_transaction")]
@@ -307,13 +457,82 @@ pub enum CfdEvent {
         cet: Transaction,
         price: Price,
     },
+    /// We published the CET, it is only recorded when first published and not for the
+    /// rebroadcasts that follow.
+    CetPublished,
     ManualCommit {
         #[serde(with = "hex_transaction")]
         tx: Transaction,
     },
+    /// The user committed and asked for the CFD to be settled without further interaction.
+    ManualCommitAndSettle {
+        #[serde(with = "hex_transaction")]
+        tx: Transaction,
+    },
+    ManualRefund {
+        #[serde(with = "hex_transaction")]
+        tx: Transaction,
+    },
+
+    MonitoringPaused,
+    MonitoringResumed,
+
+    ExitLevelsSet {
+        levels: ExitLevels,
+    },
+
+    /// The user archived the CFD after its contract setup failed, hiding it from the default list.
+    Archived,
 }
 
 impl CfdEvent {
+    /// The schema version of the events produced by [`CfdEvent::to_json`].
+    ///
+    /// Whenever a variant is renamed or the shape of its data changes, this has to be bumped and a
+    /// corresponding step has to be added to [`CfdEvent::upcast`], otherwise events that are
+    /// already stored in the database can no longer be loaded.
+    pub const VERSION: u32 = 3;
+
+    /// The name the event is stored under, without serializing its data.
+    pub fn name(&self) -> &'static str {
+        use CfdEvent::*;
+
+        match self {
+            ContractSetupCompleted { .. } => "ContractSetupCompleted",
+            ContractSetupFailed { .. } => "ContractSetupFailed",
+            OfferRejected => "OfferRejected",
+            RolloverCompleted { .. } => "RolloverCompleted",
+            RolloverRejected => "RolloverRejected",
+            RolloverFailed => "RolloverFailed",
+            CollaborativeSettlementCompleted { .. } => "CollaborativeSettlementCompleted",
+            CollaborativeSettlementRejected { .. } => "CollaborativeSettlementRejected",
+            CollaborativeSettlementFailed { .. } => "CollaborativeSettlementFailed",
+            LockConfirmed => "LockConfirmed",
+            CommitConfirmed => "CommitConfirmed",
+            CetConfirmed => "CetConfirmed",
+            RefundConfirmed => "RefundConfirmed",
+            RevokeConfirmed => "RevokeConfirmed",
+            CollaborativeSettlementConfirmed => "CollaborativeSettlementConfirmed",
+            CetTimelockConfirmedPriorOracleAttestation => {
+                "CetTimelockConfirmedPriorOracleAttestation"
+            }
+            CetTimelockConfirmedPostOracleAttestation { .. } => {
+                "CetTimelockConfirmedPostOracleAttestation"
+            }
+            RefundTimelockConfirmed { .. } => "RefundTimelockConfirmed",
+            OracleAttestedPriorCetTimelock { .. } => "OracleAttestedPriorCetTimelock",
+            OracleAttestedPostCetTimelock { .. } => "OracleAttestedPostCetTimelock",
+            CetPublished => "CetPublished",
+            ManualCommit { .. } => "ManualCommit",
+            ManualCommitAndSettle { .. } => "ManualCommitAndSettle",
+            ManualRefund { .. } => "ManualRefund",
+            MonitoringPaused => "MonitoringPaused",
+            MonitoringResumed => "MonitoringResumed",
+            ExitLevelsSet { .. } => "ExitLevelsSet",
+            Archived => "Archived",
+        }
+    }
+
     pub fn to_json(&self) -> (String, String) {
         let value = serde_json::to_value(self).expect("serialization to always work");
         let object = value.as_object().expect("always an object");
@@ -329,18 +548,103 @@ impl CfdEvent {
         (name, data)
     }
 
-    pub fn from_json(name: String, data: String) -> Result<Self> {
+    /// Deserialize an event that was stored with the given schema `version`.
+    ///
+    /// Events stored in an older shape are upcast to the current one before being deserialized.
+    pub fn from_json(name: String, data: String, version: u32) -> Result<Self> {
         use serde_json::json;
 
-        let data = serde_json::from_str::<serde_json::Value>(&data)?;
+        let data = serde_json::from_str::<serde_json::Value>(&data)
+            .map_err(|source| InvalidEvent::new(&name, &data, source))?;
+        let (name, data) = Self::upcast(name, data, version)?;
 
         let event = serde_json::from_value::<Self>(json!({
             "name": name,
             "data": data
-        }))?;
+        }))
+        .map_err(|source| InvalidEvent::new(&name, &data.to_string(), source))?;
 
         Ok(event)
     }
+
+    /// Transform the `name` and `data` of an event stored with schema `version` into the shape of
+    /// the current [`CfdEvent::VERSION`], one version at a time.
+    fn upcast(
+        mut name: String,
+        mut data: serde_json::Value,
+        version: u32,
+    ) -> Result<(String, serde_json::Value)> {
+        use serde_json::json;
+
+        if version > Self::VERSION {
+            bail!(
+                "Cannot load event {} with version {}, latest known version is {}",
+                name,
+                version,
+                Self::VERSION
+            )
+        }
+
+        for from in version..Self::VERSION {
+            let (upcast_name, upcast_data) = match from {
+                // Events stored before versioning was introduced share the shape of version 1
+                0 => (name, data),
+                // `ContractSetupFailed` gained a reason
+                1 if name == "ContractSetupFailed" => (name, json!({ "reason": null })),
+                1 => (name, data),
+                // `CollaborativeSettlementCompleted` gained the amounts paid to maker and taker
+                2 if name == "CollaborativeSettlementCompleted" => {
+                    data["maker"] = json!(null);
+                    data["taker"] = json!(null);
+
+                    (name, data)
+                }
+                2 => (name, data),
+                _ => bail!("No upcast defined from event version {}", from),
+            };
+
+            name = upcast_name;
+            data = upcast_data;
+        }
+
+        Ok((name, data))
+    }
+}
+
+/// A stored event that could not be parsed into a [`CfdEvent`].
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to parse event {name} from data {data}")]
+pub struct InvalidEvent {
+    pub name: String,
+    /// The beginning of the offending data, events can be large.
+    pub data: String,
+    #[source]
+    source: serde_json::Error,
+}
+
+impl InvalidEvent {
+    const MAX_DATA_LEN: usize = 100;
+
+    fn new(name: &str, data: &str, source: serde_json::Error) -> Self {
+        let data = match data.char_indices().nth(Self::MAX_DATA_LEN) {
+            Some((end, _)) => format!("{}...", &data[..end]),
+            None => data.to_owned(),
+        };
+
+        Self {
+            name: name.to_owned(),
+            data,
+            source,
+        }
+    }
+}
+
+/// The commit transaction of a CFD, signed by both parties and ready to be broadcast.
+#[derive(Debug, Clone, Serialize)]
+pub struct SignedCommitTx {
+    pub txid: Txid,
+    #[serde(with = "hex_transaction")]
+    pub tx: Transaction,
 }
 
 /// Models the cfd state of the taker
@@ -367,6 +671,9 @@ pub struct Cfd {
     // dynamic (based on events)
     dlc: Option<Dlc>,
 
+    /// When the contract setup completed, rollovers do not change this
+    opened_at: Option<Timestamp>,
+
     /// Holds the decrypted CET transaction once it is available in the CFD lifecycle
     ///
     /// Only `Some` in case we receive the attestation after the CET timelock expiry.
@@ -381,6 +688,9 @@ pub struct Cfd {
 
     collaborative_settlement_spend_tx: Option<Transaction>,
 
+    /// When we completed the collaborative settlement, its transaction may not confirm yet
+    settled_collaboratively_at: Option<Timestamp>,
+
     refund_tx: Option<Transaction>,
 
     lock_finality: bool,
@@ -388,9 +698,54 @@ pub struct Cfd {
     refund_finality: bool,
     cet_finality: bool,
     collaborative_settlement_finality: bool,
+    /// The counterparty published a revoked commit transaction, which spent the lock output.
+    ///
+    /// None of the transactions of the current DLC can be published anymore. Punishing the
+    /// counterparty is not supported yet, which leaves the CFD at the mercy of the counterparty.
+    revoke_finality: bool,
 
     cet_timelock_expired: bool,
     refund_timelock_expired: bool,
+
+    /// Whether the user paused monitoring for this CFD
+    ///
+    /// While paused, neither blockchain monitoring nor oracle attestations lead to any
+    /// transactions being published automatically.
+    monitoring_paused: bool,
+
+    /// Whether the user committed and asked us to publish whatever settles the CFD next
+    ///
+    /// CETs are published automatically anyway, this additionally publishes the refund
+    /// transaction once the refund timelock expired without an attestation.
+    settle_after_commit: bool,
+
+    /// When the oracle attested prior to the CET timelock expiry
+    attested_prior_cet_timelock_at: Option<Timestamp>,
+
+    /// The price the oracle attested to
+    attested_price: Option<Price>,
+
+    /// Whether we published the commit transaction, not counting the one published upon an
+    /// attestation, see [`Cfd::commit_after_attestation`]
+    commit_published: bool,
+
+    /// Whether we published the refund transaction
+    refund_published: bool,
+
+    /// Whether we published the CET
+    cet_published: bool,
+
+    /// The prices at which the user wants the CFD to be settled automatically
+    exit_levels: ExitLevels,
+
+    /// Whether the contract setup failed
+    setup_failed: bool,
+
+    /// Whether the offer was rejected
+    offer_rejected: bool,
+
+    /// Whether the user archived the CFD
+    archived: bool,
 }
 
 impl Cfd {
@@ -418,17 +773,31 @@ impl Cfd {
             counterparty_network_identity,
             role,
             dlc: None,
+            opened_at: None,
             cet: None,
             commit_tx: None,
             collaborative_settlement_spend_tx: None,
+            settled_collaboratively_at: None,
             refund_tx: None,
             lock_finality: false,
             commit_finality: false,
             refund_finality: false,
             cet_finality: false,
+            revoke_finality: false,
             collaborative_settlement_finality: false,
             cet_timelock_expired: false,
             refund_timelock_expired: false,
+            monitoring_paused: false,
+            settle_after_commit: false,
+            attested_prior_cet_timelock_at: None,
+            attested_price: None,
+            commit_published: false,
+            refund_published: false,
+            cet_published: false,
+            exit_levels: ExitLevels::default(),
+            setup_failed: false,
+            offer_rejected: false,
+            archived: false,
         }
     }
 
@@ -437,6 +806,7 @@ impl Cfd {
         order: Order,
         position: Position,
         quantity: Usd,
+        leverage: Leverage,
         counterparty_network_identity: Identity,
         role: Role,
     ) -> Self {
@@ -444,7 +814,7 @@ impl Cfd {
             order.id,
             position,
             order.price,
-            order.leverage,
+            leverage,
             order.settlement_interval,
             role,
             quantity,
@@ -478,6 +848,35 @@ impl Cfd {
         events.into_iter().fold(cfd, Cfd::apply)
     }
 
+    /// Like [`Cfd::rehydrate`] but fails on the first event that cannot be applied.
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_rehydrate(
+        id: OrderId,
+        position: Position,
+        initial_price: Price,
+        leverage: Leverage,
+        settlement_interval: Duration,
+        quantity: Usd,
+        counterparty_network_identity: Identity,
+        role: Role,
+        events: Vec<Event>,
+    ) -> Result<Self> {
+        let cfd = Self::new(
+            id,
+            position,
+            initial_price,
+            leverage,
+            settlement_interval,
+            role,
+            quantity,
+            counterparty_network_identity,
+        );
+        events.into_iter().try_fold(cfd, |cfd, event| {
+            cfd.ensure_applicable(&event.event)?;
+            Ok(cfd.apply(event))
+        })
+    }
+
     fn expiry_timestamp(&self) -> Option<OffsetDateTime> {
         self.dlc
             .as_ref()
@@ -529,23 +928,63 @@ impl Cfd {
         Ok(())
     }
 
+    /// Ensure the CFD has not been open for longer than `max_age`, counting from its contract
+    /// setup.
+    ///
+    /// CFDs exceeding the maximum age are no longer rolled over and settle at their expiry.
+    pub fn ensure_within_max_age(
+        &self,
+        now: OffsetDateTime,
+        max_age: Duration,
+    ) -> Result<(), CannotRollover> {
+        let opened_at = match self.opened_at {
+            Some(opened_at) => opened_at,
+            None => return Ok(()),
+        };
+
+        if now.unix_timestamp() - opened_at.seconds() > max_age.whole_seconds() {
+            return Err(CannotRollover::MaxAgeExceeded);
+        }
+
+        Ok(())
+    }
+
     fn can_roll_over(&self) -> bool {
         self.lock_finality && !self.commit_finality && !self.is_final() && !self.is_attested()
     }
 
-    fn can_settle_collaboratively(&self) -> bool {
-        self.lock_finality && !self.commit_finality && !self.is_final() && !self.is_attested()
+    /// Whether the CFD can still be settled without publishing the commit transaction.
+    ///
+    /// This includes CFDs the oracle attested to prior to the CET timelock until the commit
+    /// transaction is confirmed, see [`Cfd::settle_before_commit`].
+    pub fn can_settle_collaboratively(&self) -> bool {
+        let attested_post_cet_timelock =
+            self.is_attested() && self.attested_prior_cet_timelock_at.is_none();
+
+        self.lock_finality
+            && !self.commit_finality
+            && !self.is_final()
+            && !attested_post_cet_timelock
     }
 
     fn is_attested(&self) -> bool {
         self.cet.is_some()
     }
 
-    fn is_final(&self) -> bool {
-        self.collaborative_settlement_finality || self.cet_finality || self.refund_finality
+    pub fn is_final(&self) -> bool {
+        self.collaborative_settlement_finality
+            || self.cet_finality
+            || self.refund_finality
+            || self.revoke_finality
     }
 
-    pub fn start_contract_setup(&self) -> Result<(SetupParams, Identity)> {
+    /// Whether the CFD counts towards our exposure to the counterparty, i.e. it was neither
+    /// rejected nor did its contract setup fail and it is not closed yet.
+    pub fn is_exposed(&self) -> bool {
+        !self.offer_rejected && !self.setup_failed && !self.is_final()
+    }
+
+    pub fn start_contract_setup(&self, fee_rate: u32) -> Result<(SetupParams, Identity)> {
         if self.version > 0 {
             bail!("Start contract not allowed in version {}", self.version)
         }
@@ -566,13 +1005,14 @@ impl Cfd {
 
         Ok((
             SetupParams::new(
+                self.id,
                 margin,
                 counterparty_margin,
                 self.initial_price,
                 self.quantity,
                 self.leverage,
                 self.refund_timelock_in_blocks(),
-                1, // TODO: Where should I get the fee rate from?
+                fee_rate,
             ),
             self.counterparty_network_identity,
         ))
@@ -599,11 +1039,28 @@ impl Cfd {
         ))
     }
 
+    /// Finalize the settlement `proposal` with the signature of the taker.
+    ///
+    /// Fails if more than `max_age` passed between `received_at` and `now`, to not settle at an
+    /// outdated price. Both are taken from the maker's clock, the timestamp of the proposal is set
+    /// by the taker and cannot be trusted.
     pub fn start_collaborative_settlement_maker(
         &self,
         proposal: SettlementProposal,
         sig_taker: Signature,
+        received_at: Timestamp,
+        max_age: Duration,
+        now: Timestamp,
     ) -> Result<CollaborativeSettlement> {
+        let age = now.seconds() - received_at.seconds();
+        if age > max_age.whole_seconds() {
+            bail!(
+                "Settlement proposal is {} seconds old, exceeding the maximum age of {} seconds",
+                age,
+                max_age.whole_seconds()
+            )
+        }
+
         let dlc = self
             .dlc
             .as_ref()
@@ -612,9 +1069,8 @@ impl Cfd {
 
         let (tx, sig_maker) = dlc.close_transaction(&proposal)?;
         let spend_tx = dlc.finalize_spend_transaction((tx, sig_maker), sig_taker)?;
-        let script_pk = dlc.script_pubkey_for(Role::Maker);
 
-        let settlement = CollaborativeSettlement::new(spend_tx, script_pk, proposal.price)?;
+        let settlement = CollaborativeSettlement::new(spend_tx, &dlc, Role::Maker, &proposal)?;
         Ok(settlement)
     }
 
@@ -627,6 +1083,40 @@ impl Cfd {
             bail!("Start collaborative settlement only allowed when open")
         }
 
+        self.settlement_proposal(current_price, n_payouts)
+    }
+
+    /// Counter a settlement proposal of the taker with a proposal to settle at `price` instead.
+    pub fn counter_collaborative_settlement(
+        &self,
+        price: Price,
+        n_payouts: usize,
+    ) -> Result<SettlementProposal> {
+        if !self.can_settle_collaboratively() {
+            bail!("Counter collaborative settlement only allowed when open")
+        }
+
+        self.settlement_proposal(price, n_payouts)
+    }
+
+    fn settlement_proposal(&self, price: Price, n_payouts: usize) -> Result<SettlementProposal> {
+        let (maker, taker) = self.settlement_payouts(price, n_payouts)?;
+
+        Ok(SettlementProposal {
+            order_id: self.id,
+            timestamp: Timestamp::now(),
+            taker,
+            maker,
+            price,
+        })
+    }
+
+    /// The payouts of maker and taker when settling at `current_price`.
+    fn settlement_payouts(
+        &self,
+        current_price: Price,
+        n_payouts: usize,
+    ) -> Result<(Amount, Amount)> {
         let payout_curve = payout_curve::calculate(
             // TODO: Is this correct? Does rollover change the price? (I think currently not)
             self.initial_price,
@@ -643,15 +1133,7 @@ impl Cfd {
                 .context("find current price on the payout curve")?
         };
 
-        let settlement_proposal = SettlementProposal {
-            order_id: self.id,
-            timestamp: Timestamp::now(),
-            taker: *payout.taker_amount(),
-            maker: *payout.maker_amount(),
-            price: current_price,
-        };
-
-        Ok(settlement_proposal)
+        Ok((*payout.maker_amount(), *payout.taker_amount()))
     }
 
     pub fn setup_contract(self, completed: SetupCompleted) -> Result<Event> {
@@ -670,7 +1152,9 @@ impl Cfd {
             SetupCompleted::Failed { error, .. } => {
                 tracing::error!("Contract setup failed: {:#}", error);
 
-                CfdEvent::ContractSetupFailed
+                CfdEvent::ContractSetupFailed {
+                    reason: Some(format!("{:#}", error)),
+                }
             }
         };
 
@@ -698,24 +1182,44 @@ impl Cfd {
         Ok(self.event(event))
     }
 
+    /// Record the outcome of a collaborative settlement.
+    ///
+    /// A rejected or failed settlement only publishes the commit transaction if the `policy` says
+    /// so, otherwise nothing is recorded and the CFD stays open.
     pub fn settle_collaboratively(
         mut self,
         settlement: Completed<CollaborativeSettlement>,
-    ) -> Result<Event> {
+        policy: SettlementRejectionPolicy,
+    ) -> Result<Option<Event>> {
         if !self.can_settle_collaboratively() {
             bail!("Cannot collaboratively settle anymore")
         }
 
-        let event = match settlement {
-            Completed::Succeeded {
-                payload: settlement,
-                ..
-            } => CfdEvent::CollaborativeSettlementCompleted {
+        let event = match (settlement, policy) {
+            (
+                Completed::Succeeded {
+                    payload: settlement,
+                    ..
+                },
+                _,
+            ) => CfdEvent::CollaborativeSettlementCompleted {
                 spend_tx: settlement.tx,
                 script: settlement.script_pubkey,
                 price: settlement.price,
+                maker: Some(settlement.maker),
+                taker: Some(settlement.taker),
             },
-            Completed::Rejected { reason, .. } => {
+            (Completed::Rejected { reason, .. }, SettlementRejectionPolicy::KeepOpen) => {
+                tracing::info!(order_id=%self.id(), "Collaborative close rejected, keeping CFD open: {:#}", reason);
+
+                return Ok(None);
+            }
+            (Completed::Failed { error, .. }, SettlementRejectionPolicy::KeepOpen) => {
+                tracing::warn!(order_id=%self.id(), "Collaborative close failed, keeping CFD open: {:#}", error);
+
+                return Ok(None);
+            }
+            (Completed::Rejected { reason, .. }, SettlementRejectionPolicy::Commit) => {
                 tracing::info!(order_id=%self.id(), "Collaborative close rejected: {:#}", reason);
 
                 let dlc = self
@@ -726,7 +1230,7 @@ impl Cfd {
 
                 CfdEvent::CollaborativeSettlementRejected { commit_tx }
             }
-            Completed::Failed { error, .. } => {
+            (Completed::Failed { error, .. }, SettlementRejectionPolicy::Commit) => {
                 tracing::warn!(order_id=%self.id(), "Collaborative close failed: {:#}", error);
 
                 let dlc = self
@@ -739,7 +1243,7 @@ impl Cfd {
             }
         };
 
-        Ok(self.event(event))
+        Ok(Some(self.event(event)))
     }
 
     /// Given an attestation, find and decrypt the relevant CET.
@@ -756,13 +1260,27 @@ impl Cfd {
 
         let cet = match dlc.signed_cet(attestation)? {
             Ok(cet) => cet,
-            Err(e @ IrrelevantAttestation { .. }) => {
+            Err(e @ IgnoredAttestation::Irrelevant { .. }) => {
                 tracing::debug!("{}", e);
                 return Ok(None);
             }
+            Err(e @ IgnoredAttestation::WrongOracle { .. }) => {
+                tracing::warn!(order_id = %self.id(), "{}", e);
+                return Ok(None);
+            }
         };
 
-        let price = Price(Decimal::from(attestation.price));
+        // Attestations are delivered again, e.g. after a restart, which decrypts the same CET. Once
+        // a CET was decrypted, the attestation of another event must not settle the CFD again.
+        if let Some(decrypted) = self.cet.as_ref() {
+            if decrypted.txid() != cet.txid() {
+                tracing::debug!(order_id = %self.id(), event_id = %attestation.id, "Ignoring attestation, already decrypted the CET of another event");
+                return Ok(None);
+            }
+        }
+
+        let price = Price::new(Decimal::from(attestation.price))
+            .context("Oracle attested to an invalid price")?;
 
         if self.cet_timelock_expired {
             return Ok(Some(
@@ -790,8 +1308,17 @@ impl Cfd {
         Ok(self.event(cfd_event))
     }
 
-    pub fn handle_refund_timelock_expired(self) -> Event {
-        todo!()
+    pub fn handle_refund_timelock_expired(self) -> Result<Event> {
+        anyhow::ensure!(!self.is_final());
+
+        let dlc = self
+            .dlc
+            .as_ref()
+            .context("Cannot handle refund timelock without a DLC")?;
+
+        Ok(self.event(CfdEvent::RefundTimelockConfirmed {
+            refund_tx: dlc.signed_refund_tx()?,
+        }))
     }
 
     pub fn handle_lock_confirmed(self) -> Event {
@@ -819,67 +1346,408 @@ impl Cfd {
     }
 
     pub fn manual_commit_to_blockchain(&self) -> Result<Event> {
-        anyhow::ensure!(!self.is_final());
-
-        let dlc = self.dlc.as_ref().context("Cannot commit without a DLC")?;
-
         Ok(self.event(CfdEvent::ManualCommit {
-            tx: dlc.signed_commit_tx()?,
+            tx: self.signed_commit_tx()?.tx,
         }))
     }
 
-    fn event(&self, event: CfdEvent) -> Event {
-        Event::new(self.id, event)
+    pub fn manual_commit_and_settle(&self) -> Result<Event> {
+        Ok(self.event(CfdEvent::ManualCommitAndSettle {
+            tx: self.signed_commit_tx()?.tx,
+        }))
     }
 
-    /// A factor to be added to the CFD order settlement_interval for calculating the
-    /// refund timelock.
+    /// Publish the refund transaction on behalf of a user who committed and asked us to settle.
     ///
-    /// The refund timelock is important in case the oracle disappears or never publishes a
-    /// signature. Ideally, both users collaboratively settle in the refund scenario. This
-    /// factor is important if the users do not settle collaboratively.
-    /// `1.5` times the settlement_interval as defined in CFD order should be safe in the
-    /// extreme case where a user publishes the commit transaction right after the contract was
-    /// initialized. In this case, the oracle still has `1.0 *
-    /// cfdorder.settlement_interval` time to attest and no one can publish the refund
-    /// transaction.
-    /// The downside is that if the oracle disappears: the users would only notice at the end
-    /// of the cfd settlement_interval. In this case the users has to wait for another
-    /// `1.5` times of the settlement_interval to get his funds back.
-    const REFUND_THRESHOLD: f32 = 1.5;
+    /// Returns `None` if the user did not ask for it or if a CET can be published instead.
+    pub fn refund_after_commit(&self) -> Result<Option<Event>> {
+        if !self.settle_after_commit || self.cet.is_some() {
+            return Ok(None);
+        }
 
-    fn refund_timelock_in_blocks(&self) -> u32 {
-        (self.settlement_interval * Self::REFUND_THRESHOLD)
-            .as_blocks()
-            .ceil() as u32
+        self.manual_refund_to_blockchain().map(Some)
     }
 
-    pub fn id(&self) -> OrderId {
-        self.id
-    }
+    /// Publish the commit transaction once `delay` passed since the oracle attested.
+    ///
+    /// Only used if commits are delayed, see [`crate::auto_commit::CommitPolicy`]. Returns `None`
+    /// if the commit transaction is not due yet or was already published by either party.
+    pub fn commit_after_attestation(
+        &self,
+        delay: Duration,
+        now: Timestamp,
+    ) -> Result<Option<Event>> {
+        if !self.is_commit_due(delay, now) {
+            return Ok(None);
+        }
 
-    pub fn position(&self) -> Position {
-        self.position
+        self.manual_commit_to_blockchain().map(Some)
     }
 
-    pub fn initial_price(&self) -> Price {
-        self.initial_price
-    }
+    /// The attested price to propose a collaborative settlement at while the commit transaction is
+    /// delayed after the oracle attested, settling cheaper than through the CET.
+    ///
+    /// Returns `None` once the commit transaction is due according to `delay` or if the CFD cannot
+    /// be settled collaboratively.
+    pub fn settle_before_commit(&self, delay: Duration, now: Timestamp) -> Option<Price> {
+        if !self.can_settle_collaboratively()
+            || self.collaborative_settlement_spend_tx.is_some()
+            || self.is_commit_due(delay, now)
+        {
+            return None;
+        }
 
-    pub fn leverage(&self) -> Leverage {
-        self.leverage
-    }
+        self.attested_prior_cet_timelock_at?;
 
-    pub fn settlement_time_interval_hours(&self) -> Duration {
-        self.settlement_interval
+        self.attested_price
     }
 
-    pub fn quantity(&self) -> Usd {
-        self.quantity
-    }
+    /// What is left to do to close the CFD if the oracle did not attest to its settlement event
+    /// within `grace_period` after its expiry.
+    ///
+    /// Returns `None` while the oracle may still attest and while waiting for the refund timelock
+    /// to expire after the commit transaction was published.
+    pub fn missed_attestation(
+        &self,
+        now: OffsetDateTime,
+        grace_period: Duration,
+    ) -> Option<MissedAttestation> {
+        if !self.lock_finality || self.is_final() || self.is_attested() || self.refund_published {
+            return None;
+        }
 
-    pub fn counterparty_network_identity(&self) -> Identity {
-        self.counterparty_network_identity
+        if now < self.expiry_timestamp()? + grace_period {
+            return None;
+        }
+
+        if self.refund_timelock_expired {
+            return Some(MissedAttestation::Refundable);
+        }
+
+        if self.commit_published || self.commit_finality {
+            return None;
+        }
+
+        Some(MissedAttestation::Uncommitted)
+    }
+
+    fn is_commit_due(&self, delay: Duration, now: Timestamp) -> bool {
+        let attested_at = match self.attested_prior_cet_timelock_at {
+            Some(attested_at) => attested_at,
+            None => return false,
+        };
+
+        if self.is_final() || self.commit_published || self.commit_finality {
+            return false;
+        }
+
+        // The settlement transaction awaits confirmation, committing would compete with it unless
+        // it is stuck
+        if let Some(settled_at) = self.settled_collaboratively_at {
+            if now.seconds() < settled_at.seconds() + UNCONFIRMED_SETTLEMENT_TIMEOUT.whole_seconds()
+            {
+                return false;
+            }
+        }
+
+        now.seconds() >= attested_at.seconds() + delay.whole_seconds()
+    }
+
+    /// The transactions we published that are not confirmed yet.
+    ///
+    /// `commit_on_attestation` tells whether we publish the commit transaction as soon as the
+    /// oracle attested prior to the CET timelock expiry.
+    pub fn unconfirmed_transactions(&self, commit_on_attestation: bool) -> UnconfirmedTransactions {
+        let dlc = match &self.dlc {
+            Some(dlc) if !self.is_final() => dlc,
+            _ => return UnconfirmedTransactions::default(),
+        };
+
+        let lock = (!self.lock_finality && !self.commit_finality).then(|| dlc.lock.0.clone());
+
+        let commit_published = self.commit_published
+            || (commit_on_attestation && self.attested_prior_cet_timelock_at.is_some());
+        let commit = if commit_published && !self.commit_finality {
+            self.commit_tx
+                .clone()
+                .or_else(|| dlc.signed_commit_tx().ok())
+        } else {
+            None
+        };
+
+        let cet = if self.cet_published {
+            self.cet.clone()
+        } else {
+            None
+        };
+
+        UnconfirmedTransactions { lock, commit, cet }
+    }
+
+    pub fn signed_commit_tx(&self) -> Result<SignedCommitTx> {
+        anyhow::ensure!(!self.is_final(), "CFD is already final");
+
+        let dlc = self.dlc.as_ref().context("Cannot commit without a DLC")?;
+        let tx = dlc.signed_commit_tx()?;
+
+        Ok(SignedCommitTx {
+            txid: tx.txid(),
+            tx,
+        })
+    }
+
+    pub fn manual_refund_to_blockchain(&self) -> Result<Event> {
+        anyhow::ensure!(!self.is_final());
+        anyhow::ensure!(
+            self.refund_timelock_expired,
+            "Cannot refund before the refund timelock expired"
+        );
+
+        let dlc = self.dlc.as_ref().context("Cannot refund without a DLC")?;
+
+        Ok(self.event(CfdEvent::ManualRefund {
+            tx: dlc.signed_refund_tx()?,
+        }))
+    }
+
+    pub fn pause_monitoring(&self) -> Result<Event> {
+        anyhow::ensure!(!self.is_final(), "CFD is already final");
+        anyhow::ensure!(!self.monitoring_paused, "Monitoring is already paused");
+        anyhow::ensure!(self.dlc.is_some(), "Cannot pause monitoring without a DLC");
+
+        Ok(self.event(CfdEvent::MonitoringPaused))
+    }
+
+    pub fn resume_monitoring(&self) -> Result<Event> {
+        anyhow::ensure!(self.monitoring_paused, "Monitoring is not paused");
+
+        Ok(self.event(CfdEvent::MonitoringResumed))
+    }
+
+    /// Archive a CFD whose contract setup failed, it is kept for audit but not listed by default.
+    pub fn archive(&self) -> Result<Event> {
+        anyhow::ensure!(
+            self.setup_failed,
+            "Only CFDs whose contract setup failed can be archived"
+        );
+        anyhow::ensure!(!self.archived, "CFD is already archived");
+
+        Ok(self.event(CfdEvent::Archived))
+    }
+
+    pub fn set_exit_levels(&self, levels: ExitLevels) -> Result<Event> {
+        anyhow::ensure!(!self.is_final(), "CFD is already final");
+        anyhow::ensure!(self.dlc.is_some(), "Cannot set exit levels without a DLC");
+        levels.ensure_valid_for(self.position)?;
+
+        Ok(self.event(CfdEvent::ExitLevelsSet { levels }))
+    }
+
+    /// Whether the CFD can still be settled once one of its exit levels is crossed.
+    pub fn can_settle_at_exit_level(&self) -> bool {
+        self.can_settle_collaboratively()
+            && self.collaborative_settlement_spend_tx.is_none()
+            && !self.is_attested()
+    }
+
+    pub fn is_monitoring_paused(&self) -> bool {
+        self.monitoring_paused
+    }
+
+    pub fn dlc(&self) -> Option<&Dlc> {
+        self.dlc.as_ref()
+    }
+
+    /// The event the CFD is settled by as long as the oracle did not attest to it.
+    ///
+    /// `None` if the contract was not set up or the CFD is closed.
+    pub fn pending_settlement_event_id(&self) -> Option<BitMexPriceEventId> {
+        if self.is_final() || self.is_attested() {
+            return None;
+        }
+
+        self.dlc.as_ref().map(|dlc| dlc.settlement_event_id)
+    }
+
+    pub fn dlc_summary(&self) -> Result<DlcSummary> {
+        let dlc = self
+            .dlc
+            .as_ref()
+            .context("The contract of this CFD was not set up")?;
+
+        Ok(dlc.summary())
+    }
+
+    /// All the ways this CFD can currently be closed, together with our payout for each of them.
+    ///
+    /// Only the taker can propose a collaborative settlement, so it is never an option of the
+    /// maker. Its payout is estimated based on `current_price`; it is unknown without a price.
+    /// The payout of committing is only known once the oracle attested.
+    pub fn close_options(
+        &self,
+        current_price: Option<Price>,
+        n_payouts: usize,
+    ) -> Vec<CloseOption> {
+        let dlc = match self.dlc.as_ref() {
+            Some(dlc) if !self.is_final() => dlc,
+            _ => return Vec::new(),
+        };
+        let own_script_pubkey = dlc.script_pubkey_for(self.role);
+        let payout_in = |tx: &Transaction| {
+            // Our output is missing in case we were liquidated
+            tx.output
+                .iter()
+                .find(|output| output.script_pubkey == own_script_pubkey)
+                .map(|output| Amount::from_sat(output.value))
+                .unwrap_or(Amount::ZERO)
+        };
+
+        let mut options = Vec::new();
+
+        if self.role == Role::Taker && self.can_settle_collaboratively() {
+            let payout = current_price.and_then(|price| {
+                let (_, taker) = self.settlement_payouts(price, n_payouts).ok()?;
+
+                Some(taker)
+            });
+
+            options.push(CloseOption {
+                kind: CloseKind::CollaborativeSettlement,
+                payout,
+            });
+        }
+
+        if !self.commit_finality {
+            options.push(CloseOption {
+                kind: CloseKind::Commit,
+                payout: self.cet.as_ref().map(payout_in),
+            });
+        }
+
+        if let Some(cet) = self.cet.as_ref() {
+            if self.cet_timelock_expired {
+                options.push(CloseOption {
+                    kind: CloseKind::Cet,
+                    payout: Some(payout_in(cet)),
+                });
+            }
+        }
+
+        if self.refund_timelock_expired {
+            options.push(CloseOption {
+                kind: CloseKind::Refund,
+                payout: Some(payout_in(&dlc.refund.0)),
+            });
+        }
+
+        options
+    }
+
+    /// What rolling over and settling the CFD would cost us right now, see [`ActionCost`].
+    pub fn action_costs(&self, current_price: Option<Price>, n_payouts: usize) -> ActionCosts {
+        ActionCosts {
+            rollover: self.rollover_cost().ok(),
+            settlement: current_price.and_then(|price| self.settlement_cost(price, n_payouts).ok()),
+        }
+    }
+
+    /// What rolling over costs us, based on the parameters of [`Cfd::start_rollover`].
+    ///
+    /// A rollover does not publish any transaction, so there is no fee.
+    pub fn rollover_cost(&self) -> Result<ActionCost> {
+        let (params, dlc, _) = self.start_rollover()?;
+
+        ActionCost::new(
+            params.margin(self.position),
+            dlc.lock_amount(self.role),
+            Amount::ZERO,
+            None,
+        )
+    }
+
+    /// What settling collaboratively at `price` costs us, based on the close transaction.
+    pub fn settlement_cost(&self, price: Price, n_payouts: usize) -> Result<ActionCost> {
+        if !self.can_settle_collaboratively() {
+            bail!("Collaborative settlement only allowed when open")
+        }
+
+        let dlc = self
+            .dlc
+            .as_ref()
+            .context("dlc has to be available for collab settlement")?;
+        let proposal = self.settlement_proposal(price, n_payouts)?;
+        let (close_tx, _) = dlc.close_transaction(&proposal)?;
+
+        let fee = dlc
+            .fee(&close_tx)
+            .context("Close transaction does not spend the lock transaction")?;
+        let own_script_pubkey = dlc.script_pubkey_for(self.role);
+        let payout = close_tx
+            .output
+            .iter()
+            .find(|output| output.script_pubkey == own_script_pubkey)
+            .map(|output| Amount::from_sat(output.value))
+            .unwrap_or(Amount::ZERO);
+
+        ActionCost::new(Amount::ZERO, dlc.lock_amount(self.role), fee, Some(payout))
+    }
+
+    fn event(&self, event: CfdEvent) -> Event {
+        Event::new(self.id, event)
+    }
+
+    /// A factor to be added to the CFD order settlement_interval for calculating the
+    /// refund timelock.
+    ///
+    /// The refund timelock is important in case the oracle disappears or never publishes a
+    /// signature. Ideally, both users collaboratively settle in the refund scenario. This
+    /// factor is important if the users do not settle collaboratively.
+    /// `1.5` times the settlement_interval as defined in CFD order should be safe in the
+    /// extreme case where a user publishes the commit transaction right after the contract was
+    /// initialized. In this case, the oracle still has `1.0 *
+    /// cfdorder.settlement_interval` time to attest and no one can publish the refund
+    /// transaction.
+    /// The downside is that if the oracle disappears: the users would only notice at the end
+    /// of the cfd settlement_interval. In this case the users has to wait for another
+    /// `1.5` times of the settlement_interval to get his funds back.
+    const REFUND_THRESHOLD: f32 = 1.5;
+
+    fn refund_timelock_in_blocks(&self) -> u32 {
+        (self.settlement_interval * Self::REFUND_THRESHOLD)
+            .as_blocks()
+            .ceil() as u32
+    }
+
+    pub fn id(&self) -> OrderId {
+        self.id
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn exit_levels(&self) -> ExitLevels {
+        self.exit_levels
+    }
+
+    pub fn initial_price(&self) -> Price {
+        self.initial_price
+    }
+
+    pub fn leverage(&self) -> Leverage {
+        self.leverage
+    }
+
+    pub fn settlement_time_interval_hours(&self) -> Duration {
+        self.settlement_interval
+    }
+
+    pub fn quantity(&self) -> Usd {
+        self.quantity
+    }
+
+    pub fn counterparty_network_identity(&self) -> Identity {
+        self.counterparty_network_identity
     }
 
     pub fn role(&self) -> Role {
@@ -887,62 +1755,158 @@ impl Cfd {
     }
 
     pub fn sign_collaborative_close_transaction_taker(
-        &mut self,
+        &self,
         proposal: &SettlementProposal,
-    ) -> Result<(Transaction, Signature, Script)> {
-        let dlc = self.dlc.take().context("Collaborative close without DLC")?;
+    ) -> Result<(Transaction, Signature, &Dlc)> {
+        let dlc = self
+            .dlc
+            .as_ref()
+            .context("Collaborative close without DLC")?;
 
         let (tx, sig) = dlc.close_transaction(proposal)?;
-        let script_pk = dlc.script_pubkey_for(Role::Taker);
 
-        Ok((tx, sig, script_pk))
+        Ok((tx, sig, dlc))
     }
 
     pub fn version(&self) -> u64 {
         self.version
     }
 
+    /// Ensure `event` can follow the events this CFD was built from.
+    ///
+    /// [`Cfd::apply`] never fails and therefore silently accepts events that cannot occur.
+    pub fn ensure_applicable(&self, event: &CfdEvent) -> Result<()> {
+        use CfdEvent::*;
+
+        let name = event.name();
+
+        let can_occur_when_final = matches!(
+            event,
+            LockConfirmed
+                | CommitConfirmed
+                | CetConfirmed
+                | RefundConfirmed
+                | RevokeConfirmed
+                | CollaborativeSettlementConfirmed
+                | MonitoringResumed
+        );
+        if self.is_final() && !can_occur_when_final {
+            bail!("{} cannot occur once the CFD is final", name);
+        }
+
+        let requires_dlc = matches!(
+            event,
+            RolloverCompleted { .. }
+                | OracleAttestedPostCetTimelock { .. }
+                | OracleAttestedPriorCetTimelock { .. }
+                | CetPublished
+                | CollaborativeSettlementCompleted { .. }
+                | MonitoringPaused
+                | ExitLevelsSet { .. }
+        );
+        match (event, &self.dlc) {
+            (ContractSetupCompleted { .. }, Some(_)) => {
+                bail!("{} cannot occur twice", name)
+            }
+            (ContractSetupCompleted { .. }, None) => {}
+            (_, None) if requires_dlc => bail!("{} cannot occur without a DLC", name),
+            _ => {}
+        }
+
+        if matches!(event, CetPublished) && self.cet_published {
+            bail!("{} cannot occur twice", name)
+        }
+
+        Ok(())
+    }
+
     pub fn apply(mut self, evt: Event) -> Cfd {
         use CfdEvent::*;
 
         self.version += 1;
 
         match evt.event {
-            ContractSetupCompleted { dlc } => self.dlc = Some(dlc),
-            OracleAttestedPostCetTimelock { cet, .. } => self.cet = Some(cet),
-            OracleAttestedPriorCetTimelock { timelocked_cet, .. } => {
+            ContractSetupCompleted { dlc } => {
+                // A contract is only set up once, a second completion must not replace its DLC
+                if self.dlc.is_some() {
+                    tracing::error!(order_id=%self.id, "Ignoring duplicate contract setup completion, keeping the existing DLC");
+                } else {
+                    self.dlc = Some(dlc);
+                    self.opened_at.get_or_insert(evt.timestamp);
+                }
+            }
+            OracleAttestedPostCetTimelock { cet, price } => {
+                self.cet = Some(cet);
+                self.attested_price = Some(price);
+            }
+            OracleAttestedPriorCetTimelock {
+                timelocked_cet,
+                price,
+                ..
+            } => {
                 self.cet = Some(timelocked_cet);
+                self.attested_prior_cet_timelock_at = Some(evt.timestamp);
+                self.attested_price = Some(price);
             }
             ContractSetupFailed { .. } => {
-                // TODO: Deal with failed contract setup
+                // the UTXOs reserved for the lock transaction are released by the process manager
+                self.setup_failed = true;
             }
             RolloverCompleted { dlc } => {
                 self.dlc = Some(dlc);
             }
-            RolloverFailed { .. } => todo!(),
-            RolloverRejected => todo!(),
+            RolloverFailed { .. } | RolloverRejected => {
+                // nothing to do here, the contract continues with the current DLC
+            }
             CollaborativeSettlementCompleted { spend_tx, .. } => {
-                self.collaborative_settlement_spend_tx = Some(spend_tx)
+                self.collaborative_settlement_spend_tx = Some(spend_tx);
+                self.settled_collaboratively_at = Some(evt.timestamp);
+            }
+            CollaborativeSettlementRejected { commit_tx } => {
+                self.commit_tx = Some(commit_tx);
+                self.commit_published = true;
+            }
+            CollaborativeSettlementFailed { commit_tx } => {
+                self.commit_tx = Some(commit_tx);
+                self.commit_published = true;
             }
-            CollaborativeSettlementRejected { commit_tx } => self.commit_tx = Some(commit_tx),
-            CollaborativeSettlementFailed { commit_tx } => self.commit_tx = Some(commit_tx),
 
             CetConfirmed => self.cet_finality = true,
             RefundConfirmed => self.refund_finality = true,
             CollaborativeSettlementConfirmed => self.collaborative_settlement_finality = true,
-            RefundTimelockConfirmed { .. } => self.refund_timelock_expired = true,
+            RefundTimelockConfirmed { refund_tx } => {
+                self.refund_tx = Some(refund_tx);
+                self.refund_timelock_expired = true;
+            }
             LockConfirmed => self.lock_finality = true,
             CommitConfirmed => self.commit_finality = true,
             CetTimelockConfirmedPriorOracleAttestation
             | CetTimelockConfirmedPostOracleAttestation { .. } => {
                 self.cet_timelock_expired = true;
             }
-            OfferRejected => {
-                // nothing to do here? A rejection means it should be impossible to issue any
-                // commands
+            OfferRejected => self.offer_rejected = true,
+            ManualCommit { tx } => {
+                self.commit_tx = Some(tx);
+                self.commit_published = true;
             }
-            ManualCommit { tx } => self.commit_tx = Some(tx),
-            RevokeConfirmed => todo!("Deal with revoke"),
+            ManualCommitAndSettle { tx } => {
+                self.commit_tx = Some(tx);
+                self.commit_published = true;
+                self.settle_after_commit = true;
+            }
+            ManualRefund { tx } => {
+                self.refund_tx = Some(tx);
+                self.refund_published = true;
+            }
+            RevokeConfirmed => {
+                tracing::error!(order_id = %self.id, "Counterparty published a revoked commit transaction, punishing it is not supported");
+                self.revoke_finality = true;
+            }
+            CetPublished => self.cet_published = true,
+            MonitoringPaused => self.monitoring_paused = true,
+            MonitoringResumed => self.monitoring_paused = false,
+            ExitLevelsSet { levels } => self.exit_levels = levels,
+            Archived => self.archived = true,
         }
 
         self
@@ -973,6 +1937,15 @@ pub fn calculate_long_margin(price: Price, quantity: Usd, leverage: Leverage) ->
     quantity / (price * leverage)
 }
 
+/// Transactions of a CFD that are broadcast until they confirm, see
+/// [`Cfd::unconfirmed_transactions`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UnconfirmedTransactions {
+    pub lock: Option<Transaction>,
+    pub commit: Option<Transaction>,
+    pub cet: Option<Transaction>,
+}
+
 /// Calculates the shorts's margin in BTC
 ///
 /// The short margin is represented as the quantity of the contract given the
@@ -986,49 +1959,319 @@ pub fn calculate_long_liquidation_price(leverage: Leverage, price: Price) -> Pri
     price * leverage / (leverage + 1)
 }
 
-/// Returns the Profit/Loss (P/L) as Bitcoin. Losses are capped by the provided margin
-pub fn calculate_profit(
-    initial_price: Price,
-    closing_price: Price,
-    quantity: Usd,
-    leverage: Leverage,
-    position: Position,
-) -> Result<(SignedAmount, Percent)> {
-    let inv_initial_price =
-        InversePrice::new(initial_price).context("cannot invert invalid price")?;
-    let inv_closing_price =
-        InversePrice::new(closing_price).context("cannot invert invalid price")?;
-    let long_liquidation_price = calculate_long_liquidation_price(leverage, initial_price);
-    let long_is_liquidated = closing_price <= long_liquidation_price;
+/// The smallest CFD we are willing to enter.
+///
+/// Contracts below this floor are not worth it because the on-chain fees would exceed their value.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MinimumSize {
+    pub notional: Option<Usd>,
+    pub margin: Option<Amount>,
+}
 
-    let long_margin = calculate_long_margin(initial_price, quantity, leverage)
-        .to_signed()
-        .context("Unable to compute long margin")?;
-    let short_margin = calculate_short_margin(initial_price, quantity)
-        .to_signed()
-        .context("Unable to compute short margin")?;
-    let amount_changed = (quantity * inv_initial_price)
-        .to_signed()
-        .context("Unable to convert to SignedAmount")?
-        - (quantity * inv_closing_price)
-            .to_signed()
-            .context("Unable to convert to SignedAmount")?;
+impl MinimumSize {
+    /// Ensure a CFD of `quantity` at `price` and `leverage` is not below the floor.
+    ///
+    /// The margin is checked against the long margin because that is the margin of the taker,
+    /// which is always the smaller one.
+    pub fn ensure_satisfied_by(
+        &self,
+        price: Price,
+        quantity: Usd,
+        leverage: Leverage,
+    ) -> Result<()> {
+        if let Some(min_notional) = self.notional {
+            if quantity < min_notional {
+                bail!(
+                    "Quantity {} is below the minimum notional of {}",
+                    quantity,
+                    min_notional
+                );
+            }
+        }
 
-    // calculate profit/loss (P and L) in BTC
-    let (margin, payout) = match position {
-        // TODO:
-        // At this point, long_leverage == leverage, short_leverage == 1
-        // which has the effect that the right boundary `b` below is
-        // infinite and not used.
-        //
-        // The general case is:
-        //   let:
-        //     P = payout
-        //     Q = quantity
-        //     Ll = long_leverage
-        //     Ls = short_leverage
-        //     xi = initial_price
-        //     xc = closing_price
+        if let Some(min_margin) = self.margin {
+            let margin = calculate_long_margin(price, quantity, leverage);
+
+            if margin < min_margin {
+                bail!(
+                    "Margin {} for quantity {} is below the minimum margin of {}",
+                    margin,
+                    quantity,
+                    min_margin
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How far the price of a settlement proposal may deviate from our own view of the market.
+///
+/// Without this check a taker could settle at an arbitrary price and walk away with (part of) our
+/// margin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SettlementPriceTolerance(Decimal);
+
+impl SettlementPriceTolerance {
+    pub fn from_percent(percent: Decimal) -> Result<Self> {
+        if percent.is_sign_negative() {
+            bail!("Settlement price tolerance cannot be negative")
+        }
+
+        Ok(Self(percent / Decimal::ONE_HUNDRED))
+    }
+
+    /// Ensure the `proposed` price is not further from the `market` price than we tolerate.
+    pub fn ensure_within(&self, proposed: Price, market: Price) -> Result<()> {
+        let market_price = market.into_decimal();
+        let deviation = (proposed.into_decimal() - market_price).abs() / market_price;
+
+        if deviation > self.0 {
+            bail!(
+                "Proposed settlement price {} deviates more than {}% from the market price {}",
+                proposed,
+                self.0 * Decimal::ONE_HUNDRED,
+                market
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The spread the maker adds to the market price when quoting an order from the latest quote.
+///
+/// Parsed from a number of USD, e.g. `50`, or a percentage of the market price, e.g. `0.5%`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Markup {
+    Absolute(Decimal),
+    Percent(Decimal),
+}
+
+impl Markup {
+    /// The price to advertise if the market is at `market`.
+    pub fn apply(&self, market: Price) -> Result<Price> {
+        let market = market.into_decimal();
+        let markup = match self {
+            Markup::Absolute(amount) => *amount,
+            Markup::Percent(percent) => market * *percent / Decimal::ONE_HUNDRED,
+        };
+
+        let price = Price::new(market + markup).context("Marked up price is invalid")?;
+
+        Ok(price)
+    }
+}
+
+impl Default for Markup {
+    fn default() -> Self {
+        Markup::Absolute(Decimal::ZERO)
+    }
+}
+
+impl str::FromStr for Markup {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let markup = match s.strip_suffix('%') {
+            Some(percent) => Markup::Percent(percent.trim().parse()?),
+            None => Markup::Absolute(s.parse()?),
+        };
+
+        let (Markup::Absolute(value) | Markup::Percent(value)) = markup;
+        if value.is_sign_negative() {
+            bail!("Markup cannot be negative")
+        }
+
+        Ok(markup)
+    }
+}
+
+/// What is left to do to close a CFD the oracle did not attest to, see
+/// [`Cfd::missed_attestation`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissedAttestation {
+    /// The commit transaction was not published yet, the CFD can still be settled collaboratively.
+    Uncommitted,
+    /// The refund timelock expired, the refund transaction can be published.
+    Refundable,
+}
+
+/// What happens to a CFD once a collaborative settlement was rejected or failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettlementRejectionPolicy {
+    /// Publish the commit transaction, closing the CFD on-chain.
+    Commit,
+    /// Keep the CFD open, allowing for another settlement attempt.
+    KeepOpen,
+}
+
+impl SettlementRejectionPolicy {
+    pub fn from_keep_open(keep_open: bool) -> Self {
+        if keep_open {
+            Self::KeepOpen
+        } else {
+            Self::Commit
+        }
+    }
+}
+
+/// The worst price a taker accepts to enter a CFD at.
+///
+/// Protects the taker from the price of the order moving against them between seeing the order
+/// and the maker processing the take request.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SlippageBound {
+    /// The highest price a long position is entered at.
+    pub max_price: Option<Price>,
+    /// The lowest price a short position is entered at.
+    pub min_price: Option<Price>,
+}
+
+impl SlippageBound {
+    /// Ensure a `position` entered at `price` is within the bound.
+    ///
+    /// Only an upper bound protects a long position and only a lower bound protects a short
+    /// position, a bound in the other direction is refused.
+    pub fn ensure_satisfied_by(&self, position: Position, price: Price) -> Result<()> {
+        match (position, self.max_price, self.min_price) {
+            (Position::Long, _, Some(_)) => {
+                bail!("A minimum price does not protect a long position, specify a maximum price")
+            }
+            (Position::Short, Some(_), _) => {
+                bail!("A maximum price does not protect a short position, specify a minimum price")
+            }
+            (Position::Long, Some(max_price), None) if price > max_price => {
+                bail!(
+                    "Price {} of the order exceeds the maximum price of {}",
+                    price,
+                    max_price
+                )
+            }
+            (Position::Short, None, Some(min_price)) if price < min_price => {
+                bail!(
+                    "Price {} of the order is below the minimum price of {}",
+                    price,
+                    min_price
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Prices at which a CFD is settled automatically once the market reaches them.
+///
+/// Not setting any level disables the automatic settlement.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExitLevels {
+    /// The price at which the profit is taken.
+    pub take_profit: Option<Price>,
+    /// The price at which the loss is limited.
+    pub stop_loss: Option<Price>,
+}
+
+/// An exit level reached by the market.
+#[derive(Debug, Clone, Copy, PartialEq, derive_more::Display)]
+pub enum ExitLevel {
+    #[display(fmt = "Take profit")]
+    TakeProfit,
+    #[display(fmt = "Stop loss")]
+    StopLoss,
+}
+
+impl ExitLevels {
+    /// Ensure the take profit is on the profitable side of the stop loss for the `position`.
+    pub fn ensure_valid_for(&self, position: Position) -> Result<()> {
+        let (take_profit, stop_loss) = match (self.take_profit, self.stop_loss) {
+            (Some(take_profit), Some(stop_loss)) => (take_profit, stop_loss),
+            _ => return Ok(()),
+        };
+
+        match position {
+            Position::Long if take_profit <= stop_loss => bail!(
+                "Take profit {} of a long position must be above the stop loss {}",
+                take_profit,
+                stop_loss
+            ),
+            Position::Short if take_profit >= stop_loss => bail!(
+                "Take profit {} of a short position must be below the stop loss {}",
+                take_profit,
+                stop_loss
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// The level a `position` reached with the market at `price`, if any.
+    pub fn crossed_by(&self, position: Position, price: Price) -> Option<ExitLevel> {
+        let (take_profit_reached, stop_loss_reached) = match position {
+            Position::Long => (
+                self.take_profit
+                    .map_or(false, |take_profit| price >= take_profit),
+                self.stop_loss.map_or(false, |stop_loss| price <= stop_loss),
+            ),
+            Position::Short => (
+                self.take_profit
+                    .map_or(false, |take_profit| price <= take_profit),
+                self.stop_loss.map_or(false, |stop_loss| price >= stop_loss),
+            ),
+        };
+
+        if take_profit_reached {
+            Some(ExitLevel::TakeProfit)
+        } else if stop_loss_reached {
+            Some(ExitLevel::StopLoss)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the Profit/Loss (P/L) as Bitcoin. Losses are capped by the provided margin
+pub fn calculate_profit(
+    initial_price: Price,
+    closing_price: Price,
+    quantity: Usd,
+    leverage: Leverage,
+    position: Position,
+) -> Result<(SignedAmount, Percent)> {
+    let inv_initial_price =
+        InversePrice::new(initial_price).context("cannot invert invalid price")?;
+    let inv_closing_price =
+        InversePrice::new(closing_price).context("cannot invert invalid price")?;
+    let long_liquidation_price = calculate_long_liquidation_price(leverage, initial_price);
+    let long_is_liquidated = closing_price <= long_liquidation_price;
+
+    let long_margin = calculate_long_margin(initial_price, quantity, leverage)
+        .to_signed()
+        .context("Unable to compute long margin")?;
+    let short_margin = calculate_short_margin(initial_price, quantity)
+        .to_signed()
+        .context("Unable to compute short margin")?;
+    let amount_changed = (quantity * inv_initial_price)
+        .to_signed()
+        .context("Unable to convert to SignedAmount")?
+        - (quantity * inv_closing_price)
+            .to_signed()
+            .context("Unable to convert to SignedAmount")?;
+
+    // calculate profit/loss (P and L) in BTC
+    let (margin, payout) = match position {
+        // TODO:
+        // At this point, long_leverage == leverage, short_leverage == 1
+        // which has the effect that the right boundary `b` below is
+        // infinite and not used.
+        //
+        // The general case is:
+        //   let:
+        //     P = payout
+        //     Q = quantity
+        //     Ll = long_leverage
+        //     Ls = short_leverage
+        //     xi = initial_price
+        //     xc = closing_price
         //
         //     a = xi * Ll / (Ll + 1)
         //     b = xi * Ls / (Ls - 1)
@@ -1067,6 +2310,44 @@ pub fn calculate_profit(
     Ok((profit, Percent(percent)))
 }
 
+/// Returns the closing price at which the profit of `position` covers `fees`.
+///
+/// This is the inverse of [`calculate_profit`]: without fees it is the initial price, the long
+/// breaks even above it and the short below it.
+pub fn calculate_break_even_price(
+    initial_price: Price,
+    quantity: Usd,
+    leverage: Leverage,
+    position: Position,
+    fees: Amount,
+) -> Result<Price> {
+    let fees_btc = Decimal::from(fees.as_sat()) / Decimal::from(Amount::ONE_BTC.as_sat());
+    let quantity_btc_at_initial_price = quantity.into_decimal() / initial_price.into_decimal();
+
+    // Solve `calculate_profit` for the closing price, i.e. the closing price at which the
+    // quantity is worth the fees less (long) or more (short) in BTC than at the initial price
+    let quantity_btc_at_break_even = match position {
+        Position::Long => quantity_btc_at_initial_price - fees_btc,
+        Position::Short => {
+            // The short cannot win more than the long's margin
+            let long_margin = calculate_long_margin(initial_price, quantity, leverage);
+            if fees >= long_margin {
+                bail!("Fees exceed the maximum profit of the short position");
+            }
+
+            quantity_btc_at_initial_price + fees_btc
+        }
+    };
+
+    if quantity_btc_at_break_even <= Decimal::ZERO {
+        bail!("Fees exceed the maximum profit of the long position");
+    }
+
+    let break_even_price = Price::new(quantity.into_decimal() / quantity_btc_at_break_even)?;
+
+    Ok(break_even_price)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Cet {
     pub tx: Transaction,
@@ -1078,6 +2359,68 @@ pub struct Cet {
     pub n_bits: usize,
 }
 
+/// A way of closing a CFD, see [`Cfd::close_options`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CloseOption {
+    pub kind: CloseKind,
+    /// Our payout when closing this way, `None` if it cannot be estimated yet.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub payout: Option<Amount>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CloseKind {
+    CollaborativeSettlement,
+    Commit,
+    Cet,
+    Refund,
+}
+
+/// The funds involved in an action on a CFD, computed before the action is taken so that
+/// insufficient funds can be pointed out up front.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ActionCost {
+    /// Our margin once the action is taken, zero after a settlement.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub margin: Amount,
+    /// How much our margin changes compared to what we locked in the current contract.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub margin_delta: SignedAmount,
+    /// What we have to pay from our wallet, i.e. the margin we have to add.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub required_funds: Amount,
+    /// The miner fee of the transaction published by the action, shared by maker and taker.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub fee: Amount,
+    /// Our payout, only available for settlements.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub payout: Option<Amount>,
+}
+
+impl ActionCost {
+    fn new(margin: Amount, locked: Amount, fee: Amount, payout: Option<Amount>) -> Result<Self> {
+        let margin_delta = margin.to_signed()? - locked.to_signed()?;
+        let required_funds = margin.checked_sub(locked).unwrap_or(Amount::ZERO);
+
+        Ok(Self {
+            margin,
+            margin_delta,
+            required_funds,
+            fee,
+            payout,
+        })
+    }
+}
+
+/// The cost of each action that can currently be taken on a CFD, see [`Cfd::action_costs`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ActionCosts {
+    /// `None` if the CFD cannot be rolled over.
+    pub rollover: Option<ActionCost>,
+    /// `None` if the CFD cannot be settled collaboratively or there is no current price.
+    pub settlement: Option<ActionCost>,
+}
+
 /// Contains all data we've assembled about the CFD through the setup protocol.
 ///
 /// All contained signatures are the signatures of THE OTHER PARTY.
@@ -1103,6 +2446,11 @@ pub struct Dlc {
     pub maker_lock_amount: Amount,
     #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
     pub taker_lock_amount: Amount,
+    /// The miner fee of the lock transaction, computed from the UTXOs both wallets spent on it.
+    ///
+    /// Not recorded for contracts set up before the fee was tracked.
+    #[serde(default, with = "::bdk::bitcoin::util::amount::serde::as_sat::opt")]
+    pub lock_fee: Option<Amount>,
 
     pub revoked_commit: Vec<RevokedCommit>,
 
@@ -1114,7 +2462,177 @@ pub struct Dlc {
     pub refund_timelock: u32,
 }
 
+/// A read-only view of a [`Dlc`] to audit the contract.
+///
+/// Only contains what is published on chain anyway, never any secret keys or signatures.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DlcSummary {
+    pub lock_txid: Txid,
+    pub commit_txid: Txid,
+    pub refund_txid: Txid,
+    /// The number of blocks after the commit transaction from which the refund can be published.
+    pub refund_timelock: u32,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub maker_lock_amount: Amount,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub taker_lock_amount: Amount,
+    pub settlement_event_id: BitMexPriceEventId,
+    pub cet_count: usize,
+    /// The CETs ordered by oracle event and price.
+    pub cets: Vec<CetSummary>,
+    /// What another daemon needs to watch this contract, see [`WatchOnlyDlc`].
+    pub watch_only: WatchOnlyDlc,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CetSummary {
+    pub event_id: BitMexPriceEventId,
+    pub txid: Txid,
+    /// The lowest price attested to for which this CET can be published.
+    pub from_price: u64,
+    /// The highest price attested to for which this CET can be published.
+    pub to_price: u64,
+}
+
+/// The public part of a [`Dlc`], enough to watch the contract on chain.
+///
+/// Without any keys or signatures none of the transactions can be signed or published, see
+/// [`crate::watch_only`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchOnlyDlc {
+    pub lock: (Txid, Descriptor<PublicKey>),
+    pub commit: (Txid, Descriptor<PublicKey>),
+    pub cets: HashMap<BitMexPriceEventId, Vec<WatchOnlyCet>>,
+    /// The refund transaction and the script paying out to the maker.
+    pub refund: (Txid, Script),
+    pub refund_timelock: u32,
+    pub revoked_commits: Vec<(Txid, Script)>,
+    pub event_ids: Vec<BitMexPriceEventId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchOnlyCet {
+    pub txid: Txid,
+    pub script_pubkey: Script,
+    pub range: RangeInclusive<u64>,
+}
+
 impl Dlc {
+    pub fn summary(&self) -> DlcSummary {
+        let mut cets = self
+            .cets
+            .iter()
+            .flat_map(|(event_id, cets)| {
+                cets.iter().map(move |cet| CetSummary {
+                    event_id: *event_id,
+                    txid: cet.tx.txid(),
+                    from_price: *cet.range.start(),
+                    to_price: *cet.range.end(),
+                })
+            })
+            .collect::<Vec<_>>();
+        cets.sort_by_key(|cet| (cet.event_id, cet.from_price));
+
+        DlcSummary {
+            lock_txid: self.lock.0.txid(),
+            commit_txid: self.commit.0.txid(),
+            refund_txid: self.refund.0.txid(),
+            refund_timelock: self.refund_timelock,
+            maker_lock_amount: self.maker_lock_amount,
+            taker_lock_amount: self.taker_lock_amount,
+            settlement_event_id: self.settlement_event_id,
+            cet_count: cets.len(),
+            cets,
+            watch_only: self.watch_only(),
+        }
+    }
+
+    pub fn watch_only(&self) -> WatchOnlyDlc {
+        let cets = self
+            .cets
+            .iter()
+            .map(|(event_id, cets)| {
+                let cets = cets
+                    .iter()
+                    .map(|cet| WatchOnlyCet {
+                        txid: cet.tx.txid(),
+                        script_pubkey: cet.tx.output[0].script_pubkey.clone(),
+                        range: cet.range.clone(),
+                    })
+                    .collect();
+
+                (*event_id, cets)
+            })
+            .collect();
+
+        WatchOnlyDlc {
+            lock: (self.lock.0.txid(), self.lock.1.clone()),
+            commit: (self.commit.0.txid(), self.commit.2.clone()),
+            cets,
+            refund: (self.refund.0.txid(), self.maker_address.script_pubkey()),
+            refund_timelock: self.refund_timelock,
+            revoked_commits: self
+                .revoked_commit
+                .iter()
+                .map(|revoked| (revoked.txid, revoked.script_pubkey.clone()))
+                .collect(),
+            event_ids: self.event_ids(),
+        }
+    }
+
+    /// The amount the party with `role` locked in this contract.
+    pub fn lock_amount(&self, role: Role) -> Amount {
+        match role {
+            Role::Maker => self.maker_lock_amount,
+            Role::Taker => self.taker_lock_amount,
+        }
+    }
+
+    /// The miner fee paid by `tx`, if it is the lock transaction or only spends outputs of the
+    /// lock or commit transaction.
+    ///
+    /// The inputs of the lock transaction come from the wallets of both parties, so its fee is
+    /// taken from [`Dlc::lock_fee`].
+    pub fn fee(&self, tx: &Transaction) -> Option<Amount> {
+        if tx.txid() == self.lock.0.txid() {
+            return self.lock_fee;
+        }
+
+        let spent = tx
+            .input
+            .iter()
+            .map(|input| {
+                let prevout = input.previous_output;
+
+                [&self.lock.0, &self.commit.0]
+                    .into_iter()
+                    .find(|spent_tx| spent_tx.txid() == prevout.txid)?
+                    .output
+                    .get(prevout.vout as usize)
+                    .map(|output| output.value)
+            })
+            .sum::<Option<u64>>()?;
+        let paid = tx.output.iter().map(|output| output.value).sum::<u64>();
+
+        spent.checked_sub(paid).map(Amount::from_sat)
+    }
+
+    /// All oracle events whose attestation settles this DLC, i.e. the settlement event and any
+    /// other event we have CETs for.
+    pub fn event_ids(&self) -> Vec<BitMexPriceEventId> {
+        let mut other_event_ids = self
+            .cets
+            .keys()
+            .filter(|event_id| **event_id != self.settlement_event_id)
+            .copied()
+            .collect::<Vec<_>>();
+        other_event_ids.sort();
+
+        std::iter::once(self.settlement_event_id)
+            .chain(other_event_ids)
+            .collect()
+    }
+
     /// Create a close transaction based on the current contract and a settlement proposals
     pub fn close_transaction(
         &self,
@@ -1234,238 +2752,1307 @@ impl Dlc {
         Ok(signed_commit_tx)
     }
 
-    pub fn signed_cet(
-        &self,
-        attestation: &oracle::Attestation,
-    ) -> Result<Result<Transaction, IrrelevantAttestation>> {
-        let cets = match self.cets.get(&attestation.id) {
-            Some(cets) => cets,
-            None => {
-                return Ok(Err(IrrelevantAttestation {
-                    id: attestation.id,
-                    tx_id: self.lock.0.txid(),
-                }))
-            }
+    pub fn signed_cet(
+        &self,
+        attestation: &oracle::Attestation,
+    ) -> Result<Result<Transaction, IgnoredAttestation>> {
+        let Cet {
+            tx: cet,
+            adaptor_sig: encsig,
+            n_bits,
+            ..
+        } = match select_cet(&self.cets, attestation)? {
+            Some(cet) => cet,
+            None => {
+                return Ok(Err(IgnoredAttestation::Irrelevant {
+                    id: attestation.id,
+                    tx_id: self.lock.0.txid(),
+                }))
+            }
+        };
+
+        anyhow::ensure!(
+            attestation.scalars.len() >= *n_bits,
+            "Attestation {} has {} scalars but CET requires {}",
+            attestation.id,
+            attestation.scalars.len(),
+            n_bits
+        );
+
+        let mut decryption_sk = attestation.scalars[0];
+        for oracle_attestation in attestation.scalars[1..*n_bits].iter() {
+            decryption_sk.add_assign(oracle_attestation.as_ref())?;
+        }
+
+        let sig_hash = spending_tx_sighash(
+            cet,
+            &self.commit.2,
+            Amount::from_sat(self.commit.0.output[0].value),
+        );
+        let our_sig = SECP256K1.sign(&sig_hash, &self.identity);
+        let our_pubkey = PublicKey::new(bdk::bitcoin::secp256k1::PublicKey::from_secret_key(
+            SECP256K1,
+            &self.identity,
+        ));
+
+        let counterparty_pubkey = self.identity_counterparty;
+        let counterparty_sig =
+            match decrypt_counterparty_sig(encsig, &decryption_sk, &sig_hash, &counterparty_pubkey)
+            {
+                Some(sig) => sig,
+                None => {
+                    return Ok(Err(IgnoredAttestation::WrongOracle {
+                        id: attestation.id,
+                        tx_id: self.lock.0.txid(),
+                    }))
+                }
+            };
+
+        let signed_cet = finalize_spend_transaction(
+            cet.clone(),
+            &self.commit.2,
+            (our_pubkey, our_sig),
+            (counterparty_pubkey, counterparty_sig),
+        )?;
+
+        Ok(Ok(signed_cet))
+    }
+
+    /// Check that `tx` validates against the descriptor of the output it spends, carrying valid
+    /// signatures of both parties.
+    ///
+    /// This applies to all finalized transactions of the DLC but the lock transaction, i.e. the
+    /// commit transaction, the CETs, the refund transaction and the close transaction. Verifying
+    /// them before broadcasting catches a malformed finalization early instead of relying on the
+    /// network to reject it.
+    pub fn verify_finalized(&self, tx: &Transaction) -> Result<()> {
+        let txid = tx.txid();
+
+        let input = match tx.input.as_slice() {
+            [input] => input,
+            _ => bail!("Transaction {} does not have exactly one input", txid),
+        };
+
+        let prevout = input.previous_output;
+        let (spent_tx, descriptor) = if prevout.txid == self.lock.0.txid() {
+            (&self.lock.0, &self.lock.1)
+        } else if prevout.txid == self.commit.0.txid() {
+            (&self.commit.0, &self.commit.2)
+        } else {
+            bail!("Transaction {} does not spend from the DLC", txid)
+        };
+        let spent_output = spent_tx
+            .output
+            .get(prevout.vout as usize)
+            .with_context(|| format!("Transaction {} spends a non-existent output", txid))?;
+        anyhow::ensure!(
+            spent_output.script_pubkey == descriptor.script_pubkey(),
+            "Transaction {} does not spend the output of the descriptor",
+            txid
+        );
+
+        let sighash = spending_tx_sighash(tx, descriptor, Amount::from_sat(spent_output.value));
+        let own_pk = PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(
+            SECP256K1,
+            &self.identity,
+        ));
+        let mut sigs = HashMap::new();
+        for pk in [own_pk, self.identity_counterparty] {
+            let sig = input
+                .witness
+                .iter()
+                .filter_map(|element| parse_witness_signature(element).ok())
+                .find(|sig| SECP256K1.verify(&sighash, sig, &pk.key).is_ok())
+                .with_context(|| {
+                    format!("Transaction {} lacks a valid signature of {}", txid, pk)
+                })?;
+            sigs.insert(pk, (sig, SigHashType::All));
+        }
+
+        // The shape of the witness depends on the descriptor: the lock output is a plain
+        // CHECKMULTISIG requiring a dummy element, the commit output is not
+        let (expected_witness, _) = descriptor.get_satisfaction(sigs).with_context(|| {
+            format!(
+                "Signatures of transaction {} do not satisfy its descriptor",
+                txid
+            )
+        })?;
+        anyhow::ensure!(
+            input.witness == expected_witness,
+            "Transaction {} carries a witness not satisfying the descriptor of the spent output",
+            txid
+        );
+
+        Ok(())
+    }
+
+    pub fn fingerprint(&self) -> DlcFingerprint {
+        DlcFingerprint::new(&self.lock.0, &self.commit.0, &self.refund.0, &self.cets)
+    }
+}
+
+/// Parse a signature of a witness, which is DER-encoded and followed by the sighash type.
+///
+/// All transactions of the DLC are signed with `SIGHASH_ALL`.
+fn parse_witness_signature(sig: &[u8]) -> Result<Signature> {
+    let (sighash_type, der) = sig.split_last().context("Empty signature")?;
+    anyhow::ensure!(
+        u32::from(*sighash_type) == SigHashType::All.as_u32(),
+        "Unexpected sighash type {}",
+        sighash_type
+    );
+
+    Ok(Signature::from_der(der)?)
+}
+
+/// Find the CET to publish for an attestation.
+///
+/// CETs are grouped by the event they were built for, so the attestation's event id determines
+/// the set of CETs and its price the CET within that set. Returns `None` if we have no CETs for
+/// the attested event.
+fn select_cet<'a>(
+    cets: &'a HashMap<BitMexPriceEventId, Vec<Cet>>,
+    attestation: &oracle::Attestation,
+) -> Result<Option<&'a Cet>> {
+    let cets = match cets.get(&attestation.id) {
+        Some(cets) => cets,
+        None => return Ok(None),
+    };
+
+    let cet = cets
+        .iter()
+        .find(|Cet { range, .. }| range.contains(&attestation.price))
+        .context("Price out of range of cets")?;
+
+    Ok(Some(cet))
+}
+
+/// Decrypt the counterparty's adaptor signature on a CET using the scalars of an attestation.
+///
+/// The adaptor signature is encrypted to a point derived from the oracle's public key the DLC was
+/// set up with. Scalars of any other oracle decrypt to a signature that does not verify, in which
+/// case `None` is returned.
+fn decrypt_counterparty_sig(
+    encsig: &EcdsaAdaptorSignature,
+    decryption_sk: &SecretKey,
+    sig_hash: &secp256k1_zkp::Message,
+    counterparty_pk: &PublicKey,
+) -> Option<Signature> {
+    let sig = encsig.decrypt(decryption_sk).ok()?;
+    SECP256K1
+        .verify(sig_hash, &sig, &counterparty_pk.key)
+        .ok()?;
+
+    Some(sig)
+}
+
+/// A digest over the transactions of a [`Dlc`].
+///
+/// It only depends on data both parties have to agree on, which allows them to confirm at the end
+/// of the contract setup that they built identical contracts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SerializeDisplay, DeserializeFromStr)]
+pub struct DlcFingerprint(sha256::Hash);
+
+impl DlcFingerprint {
+    fn new(
+        lock: &Transaction,
+        commit: &Transaction,
+        refund: &Transaction,
+        cets: &HashMap<BitMexPriceEventId, Vec<Cet>>,
+    ) -> Self {
+        let mut engine = sha256::Hash::engine();
+
+        engine.input(&lock.txid()[..]);
+        engine.input(&commit.txid()[..]);
+        engine.input(&refund.txid()[..]);
+
+        let mut cets = cets.iter().collect::<Vec<_>>();
+        cets.sort_by_key(|(event_id, _)| **event_id);
+
+        for (event_id, cets) in cets {
+            engine.input(event_id.to_string().as_bytes());
+
+            let mut cets = cets.iter().collect::<Vec<_>>();
+            cets.sort_by_key(|cet| *cet.range.start());
+
+            for cet in cets {
+                engine.input(&cet.range.start().to_be_bytes());
+                engine.input(&cet.range.end().to_be_bytes());
+                engine.input(&(cet.n_bits as u64).to_be_bytes());
+                engine.input(&cet.tx.txid()[..]);
+            }
+        }
+
+        Self(sha256::Hash::from_engine(engine))
+    }
+}
+
+impl fmt::Display for DlcFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl str::FromStr for DlcFingerprint {
+    type Err = bdk::bitcoin::hashes::hex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.parse()?))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IgnoredAttestation {
+    #[error("Attestation {id} is irrelevant for DLC {tx_id}")]
+    Irrelevant { id: BitMexPriceEventId, tx_id: Txid },
+    #[error("Attestation {id} was not produced by the oracle of DLC {tx_id}")]
+    WrongOracle { id: BitMexPriceEventId, tx_id: Txid },
+}
+
+/// Information which we need to remember in order to construct a
+/// punishment transaction in case the counterparty publishes a
+/// revoked commit transaction.
+///
+/// It also includes the information needed to monitor for the
+/// publication of the revoked commit transaction.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RevokedCommit {
+    // To build punish transaction
+    pub encsig_ours: EcdsaAdaptorSignature,
+    pub revocation_sk_theirs: SecretKey,
+    pub publication_pk_theirs: PublicKey,
+    // To monitor revoked commit transaction
+    pub txid: Txid,
+    pub script_pubkey: Script,
+}
+
+/// Used when transactions (e.g. collaborative close) are recorded as a part of
+/// CfdState in the cases when we can't solely rely on state transition
+/// timestamp as it could have occured for different reasons (like a new
+/// attestation in Open state)
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CollaborativeSettlement {
+    pub tx: Transaction,
+    pub script_pubkey: Script,
+    pub timestamp: Timestamp,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
+    payout: Amount,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
+    maker: Amount,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
+    taker: Amount,
+    price: Price,
+}
+
+impl CollaborativeSettlement {
+    /// Record the settlement of `proposal` by `tx`.
+    ///
+    /// The amounts are taken from the agreed upon `proposal`, `tx` is only checked to pay out to
+    /// us.
+    pub fn new(
+        tx: Transaction,
+        dlc: &Dlc,
+        own_role: Role,
+        proposal: &SettlementProposal,
+    ) -> Result<Self> {
+        let payout = match own_role {
+            Role::Maker => proposal.maker,
+            Role::Taker => proposal.taker,
+        };
+
+        let script_pubkey = dlc.script_pubkey_for(own_role);
+        if output_amount(&tx, &script_pubkey) == Amount::ZERO {
+            match MissingPayout::for_payout(payout) {
+                MissingPayout::Reject => bail!(
+                    "Collaborative settlement transaction {} does not pay out to us",
+                    tx.txid()
+                ),
+                MissingPayout::AssumeLiquidated => {
+                    tracing::error!(
+                        "Collaborative settlement with a zero amount, this should really not happen!"
+                    );
+                }
+            }
+        }
+
+        Ok(Self {
+            tx,
+            script_pubkey,
+            timestamp: Timestamp::now(),
+            payout,
+            maker: proposal.maker,
+            taker: proposal.taker,
+            price: proposal.price,
+        })
+    }
+
+    pub fn payout(&self) -> Amount {
+        self.payout
+    }
+}
+
+/// How to treat a collaborative settlement transaction without an output paying out to us.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingPayout {
+    /// Fail the settlement, our output must have gone missing when constructing the transaction.
+    Reject,
+    /// Record a zero payout because we were liquidated.
+    AssumeLiquidated,
+}
+
+impl MissingPayout {
+    /// Only accept a missing output if the agreed upon `payout` was nothing in the first place.
+    pub fn for_payout(payout: Amount) -> Self {
+        if payout == Amount::ZERO {
+            MissingPayout::AssumeLiquidated
+        } else {
+            MissingPayout::Reject
+        }
+    }
+}
+
+/// The amount paid to `script_pubkey` by `tx`.
+///
+/// Falls back to [`Amount::ZERO`] in case no output matches the script pubkey.
+fn output_amount(tx: &Transaction, script_pubkey: &Script) -> Amount {
+    tx.output
+        .iter()
+        .find(|output| &output.script_pubkey == script_pubkey)
+        .map(|output| Amount::from_sat(output.value))
+        .unwrap_or(Amount::ZERO)
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum Completed<P> {
+    Succeeded {
+        order_id: OrderId,
+        payload: P,
+    },
+    Rejected {
+        order_id: OrderId,
+        reason: anyhow::Error,
+    },
+    Failed {
+        order_id: OrderId,
+        error: anyhow::Error,
+    },
+}
+
+impl<P> xtra::Message for Completed<P>
+where
+    P: Send + 'static,
+{
+    type Result = Result<()>;
+}
+
+impl<P> Completed<P> {
+    pub fn order_id(&self) -> OrderId {
+        *match self {
+            Completed::Succeeded { order_id, .. } => order_id,
+            Completed::Rejected { order_id, .. } => order_id,
+            Completed::Failed { order_id, .. } => order_id,
+        }
+    }
+
+    pub fn rejected(order_id: OrderId) -> Self {
+        Self::Rejected {
+            order_id,
+            reason: anyhow::format_err!("unknown"),
+        }
+    }
+    pub fn rejected_due_to(order_id: OrderId, reason: anyhow::Error) -> Self {
+        Self::Rejected { order_id, reason }
+    }
+}
+
+pub mod marker {
+    /// Marker type for contract setup completion
+    #[derive(Debug)]
+    pub struct Setup;
+    /// Marker type for rollover  completion
+    #[derive(Debug)]
+    pub struct Rollover;
+}
+
+/// Message sent from a setup actor to the
+/// cfd actor to notify that the contract setup has finished.
+pub type SetupCompleted = Completed<(Dlc, marker::Setup)>;
+
+/// Message sent from a rollover actor to the
+/// cfd actor to notify that the rollover has finished (contract got updated).
+/// TODO: Roll it out in the maker rollover actor
+pub type RolloverCompleted = Completed<(Dlc, marker::Rollover)>;
+
+impl Completed<(Dlc, marker::Setup)> {
+    pub fn succeeded(order_id: OrderId, dlc: Dlc) -> Self {
+        Self::Succeeded {
+            order_id,
+            payload: (dlc, marker::Setup),
+        }
+    }
+}
+
+impl Completed<(Dlc, marker::Rollover)> {
+    pub fn succeeded(order_id: OrderId, dlc: Dlc) -> Self {
+        Self::Succeeded {
+            order_id,
+            payload: (dlc, marker::Rollover),
+        }
+    }
+}
+
+mod hex_transaction {
+    use super::*;
+    use bdk::bitcoin;
+    use serde::Deserializer;
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(value: &Transaction, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes = bitcoin::consensus::serialize(value);
+        let hex_str = hex::encode(bytes);
+        serializer.serialize_str(hex_str.as_str())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Transaction, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer).map_err(D::Error::custom)?;
+        let bytes = hex::decode(hex).map_err(D::Error::custom)?;
+        let tx = bitcoin::consensus::deserialize(&bytes).map_err(D::Error::custom)?;
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::OutPoint;
+    use bdk::bitcoin::TxIn;
+    use bdk::bitcoin::TxOut;
+    use proptest::prelude::*;
+    use rust_decimal_macros::dec;
+    use time::macros::datetime;
+
+    #[test]
+    fn quantity_at_minimum_notional_is_accepted() {
+        let minimum = MinimumSize {
+            notional: Some(Usd::new(dec!(100))),
+            margin: None,
+        };
+
+        let result = minimum.ensure_satisfied_by(
+            Price::new(dec!(40000)).unwrap(),
+            Usd::new(dec!(100)),
+            Leverage::new(2).unwrap(),
+        );
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn quantity_below_minimum_notional_is_rejected() {
+        let minimum = MinimumSize {
+            notional: Some(Usd::new(dec!(100))),
+            margin: None,
+        };
+
+        let result = minimum.ensure_satisfied_by(
+            Price::new(dec!(40000)).unwrap(),
+            Usd::new(dec!(99)),
+            Leverage::new(2).unwrap(),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn margin_at_minimum_margin_is_accepted() {
+        // 100 / (40000 * 2) = 0.00125 BTC
+        let minimum = MinimumSize {
+            notional: None,
+            margin: Some(Amount::from_sat(125_000)),
+        };
+
+        let result = minimum.ensure_satisfied_by(
+            Price::new(dec!(40000)).unwrap(),
+            Usd::new(dec!(100)),
+            Leverage::new(2).unwrap(),
+        );
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn margin_below_minimum_margin_is_rejected() {
+        let minimum = MinimumSize {
+            notional: None,
+            margin: Some(Amount::from_sat(125_001)),
+        };
+
+        let result = minimum.ensure_satisfied_by(
+            Price::new(dec!(40000)).unwrap(),
+            Usd::new(dec!(100)),
+            Leverage::new(2).unwrap(),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn no_minimum_accepts_any_size() {
+        let result = MinimumSize::default().ensure_satisfied_by(
+            Price::new(dec!(40000)).unwrap(),
+            Usd::new(dec!(1)),
+            Leverage::new(2).unwrap(),
+        );
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn dlc_fingerprint_is_deterministic() {
+        let fingerprint =
+            DlcFingerprint::new(&dummy_tx(1), &dummy_tx(2), &dummy_tx(3), &HashMap::new());
+        let same_fingerprint =
+            DlcFingerprint::new(&dummy_tx(1), &dummy_tx(2), &dummy_tx(3), &HashMap::new());
+
+        assert_eq!(fingerprint, same_fingerprint);
+        assert_eq!(
+            fingerprint.to_string().parse::<DlcFingerprint>().unwrap(),
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn dlcs_with_different_commit_txs_have_different_fingerprints() {
+        let fingerprint =
+            DlcFingerprint::new(&dummy_tx(1), &dummy_tx(2), &dummy_tx(3), &HashMap::new());
+        let other_fingerprint =
+            DlcFingerprint::new(&dummy_tx(1), &dummy_tx(4), &dummy_tx(3), &HashMap::new());
+
+        assert_ne!(fingerprint, other_fingerprint);
+    }
+
+    #[test]
+    fn settlement_price_within_tolerance_is_accepted() {
+        let tolerance = SettlementPriceTolerance::from_percent(dec!(1)).unwrap();
+        let market = Price::new(dec!(40_000)).unwrap();
+
+        tolerance
+            .ensure_within(Price::new(dec!(40_400)).unwrap(), market)
+            .unwrap();
+        tolerance
+            .ensure_within(Price::new(dec!(39_600)).unwrap(), market)
+            .unwrap();
+    }
+
+    #[test]
+    fn settlement_price_outside_tolerance_is_rejected() {
+        let tolerance = SettlementPriceTolerance::from_percent(dec!(1)).unwrap();
+        let market = Price::new(dec!(40_000)).unwrap();
+
+        let too_high = tolerance.ensure_within(Price::new(dec!(40_401)).unwrap(), market);
+        let too_low = tolerance.ensure_within(Price::new(dec!(39_599)).unwrap(), market);
+
+        assert!(too_high.is_err());
+        assert!(too_low.is_err());
+    }
+
+    #[test]
+    fn negative_settlement_price_tolerance_is_refused() {
+        assert!(SettlementPriceTolerance::from_percent(dec!(-1)).is_err());
+    }
+
+    #[test]
+    fn markup_is_added_to_market_price() {
+        let market = Price::new(dec!(40_000)).unwrap();
+
+        let absolute = "50".parse::<Markup>().unwrap().apply(market).unwrap();
+        let percent = "0.5%".parse::<Markup>().unwrap().apply(market).unwrap();
+        let none = Markup::default().apply(market).unwrap();
+
+        assert_eq!(absolute, Price::new(dec!(40_050)).unwrap());
+        assert_eq!(percent, Price::new(dec!(40_200)).unwrap());
+        assert_eq!(none, market);
+    }
+
+    #[test]
+    fn negative_markup_is_refused() {
+        assert!("-50".parse::<Markup>().is_err());
+        assert!("-0.5%".parse::<Markup>().is_err());
+    }
+
+    #[test]
+    fn price_within_slippage_bound_is_accepted() {
+        let price = Price::new(dec!(40_000)).unwrap();
+
+        SlippageBound {
+            max_price: Some(Price::new(dec!(40_000)).unwrap()),
+            min_price: None,
+        }
+        .ensure_satisfied_by(Position::Long, price)
+        .unwrap();
+        SlippageBound {
+            max_price: None,
+            min_price: Some(Price::new(dec!(39_900)).unwrap()),
+        }
+        .ensure_satisfied_by(Position::Short, price)
+        .unwrap();
+        SlippageBound::default()
+            .ensure_satisfied_by(Position::Long, price)
+            .unwrap();
+    }
+
+    #[test]
+    fn price_exceeding_slippage_bound_is_rejected() {
+        let price = Price::new(dec!(40_000)).unwrap();
+
+        let above_max = SlippageBound {
+            max_price: Some(Price::new(dec!(39_999)).unwrap()),
+            min_price: None,
+        }
+        .ensure_satisfied_by(Position::Long, price);
+        let below_min = SlippageBound {
+            max_price: None,
+            min_price: Some(Price::new(dec!(40_001)).unwrap()),
+        }
+        .ensure_satisfied_by(Position::Short, price);
+
+        assert!(above_max.is_err());
+        assert!(below_min.is_err());
+    }
+
+    #[test]
+    fn exit_levels_are_crossed_in_direction_of_position() {
+        let levels = ExitLevels {
+            take_profit: Some(Price::new(dec!(45_000)).unwrap()),
+            stop_loss: Some(Price::new(dec!(35_000)).unwrap()),
+        };
+
+        let crossed = |price| levels.crossed_by(Position::Long, Price::new(price).unwrap());
+
+        assert_eq!(crossed(dec!(45_000)), Some(ExitLevel::TakeProfit));
+        assert_eq!(crossed(dec!(46_000)), Some(ExitLevel::TakeProfit));
+        assert_eq!(crossed(dec!(35_000)), Some(ExitLevel::StopLoss));
+        assert_eq!(crossed(dec!(34_000)), Some(ExitLevel::StopLoss));
+        assert_eq!(crossed(dec!(40_000)), None);
+    }
+
+    #[test]
+    fn exit_levels_of_short_position_are_mirrored() {
+        let levels = ExitLevels {
+            take_profit: Some(Price::new(dec!(35_000)).unwrap()),
+            stop_loss: Some(Price::new(dec!(45_000)).unwrap()),
+        };
+
+        let crossed = |price| levels.crossed_by(Position::Short, Price::new(price).unwrap());
+
+        assert_eq!(crossed(dec!(34_000)), Some(ExitLevel::TakeProfit));
+        assert_eq!(crossed(dec!(46_000)), Some(ExitLevel::StopLoss));
+        assert_eq!(crossed(dec!(40_000)), None);
+    }
+
+    #[test]
+    fn no_exit_level_is_crossed_without_levels() {
+        let price = Price::new(dec!(40_000)).unwrap();
+
+        assert_eq!(
+            ExitLevels::default().crossed_by(Position::Long, price),
+            None
+        );
+        assert_eq!(
+            ExitLevels::default().crossed_by(Position::Short, price),
+            None
+        );
+    }
+
+    #[test]
+    fn take_profit_on_losing_side_of_stop_loss_is_refused() {
+        let levels = ExitLevels {
+            take_profit: Some(Price::new(dec!(35_000)).unwrap()),
+            stop_loss: Some(Price::new(dec!(45_000)).unwrap()),
+        };
+
+        assert!(levels.ensure_valid_for(Position::Long).is_err());
+        assert!(levels.ensure_valid_for(Position::Short).is_ok());
+    }
+
+    #[test]
+    fn slippage_bound_against_position_direction_is_refused() {
+        let price = Price::new(dec!(40_000)).unwrap();
+
+        let min_price_for_long = SlippageBound {
+            max_price: None,
+            min_price: Some(Price::new(dec!(30_000)).unwrap()),
+        }
+        .ensure_satisfied_by(Position::Long, price);
+        let max_price_for_short = SlippageBound {
+            max_price: Some(Price::new(dec!(50_000)).unwrap()),
+            min_price: None,
+        }
+        .ensure_satisfied_by(Position::Short, price);
+
+        assert!(min_price_for_long.is_err());
+        assert!(max_price_for_short.is_err());
+    }
+
+    #[test]
+    fn fee_is_difference_of_spent_and_paid_amounts() {
+        let mut dlc = Dlc::dummy(0);
+        dlc.lock.0.output.push(TxOut {
+            value: 100_000,
+            script_pubkey: Script::new(),
+        });
+        dlc.commit.0 = spending_tx(&dlc.lock.0, 99_000);
+        let cet = spending_tx(&dlc.commit.0, 98_500);
+
+        assert_eq!(dlc.fee(&dlc.lock.0), dlc.lock_fee);
+        assert_eq!(dlc.fee(&dlc.commit.0), Some(Amount::from_sat(1_000)));
+        assert_eq!(dlc.fee(&cet), Some(Amount::from_sat(500)));
+    }
+
+    #[test]
+    fn no_fee_for_transaction_spending_unknown_outputs() {
+        let mut dlc = Dlc::dummy(0);
+        dlc.lock.0.output.push(TxOut {
+            value: 100_000,
+            script_pubkey: Script::new(),
+        });
+
+        let unrelated = spending_tx(&dummy_tx(42), 1_000);
+
+        assert_eq!(dlc.fee(&unrelated), None);
+    }
+
+    /// A transaction paying `value` from the first output of `spent_tx`.
+    fn spending_tx(spent_tx: &Transaction, value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(spent_tx.txid(), 0),
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: Script::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn taker_takes_opposite_position_of_order() {
+        let mut order = dummy_order();
+        assert_eq!(order.taker_position(), Position::Long);
+
+        order.position = Position::Long;
+        let cfd = Cfd::from_order(
+            order.clone(),
+            order.taker_position(),
+            Usd::new(dec!(100)),
+            order.leverage,
+            Identity::new(x25519_dalek::PublicKey::from([42u8; 32])),
+            Role::Taker,
+        );
+
+        assert_eq!(cfd.position(), Position::Short);
+    }
+
+    #[test]
+    fn leverage_not_offered_by_order_is_rejected() {
+        let order = dummy_order();
+        let leverage = Leverage::new(5).unwrap();
+
+        let result = order.leverage_for_take(Some(leverage));
+
+        assert_eq!(
+            result,
+            Err(LeverageNotOffered {
+                requested: leverage,
+                offered: vec![order.leverage],
+            })
+        );
+    }
+
+    #[test]
+    fn leverage_offered_by_order_can_be_chosen() {
+        let order = dummy_order().with_leverage_choices(&[
+            Leverage::new(5).unwrap(),
+            Leverage::new(2).unwrap(),
+            Leverage::new(5).unwrap(),
+        ]);
+
+        assert_eq!(
+            order.leverage_choices(),
+            vec![Leverage::new(2).unwrap(), Leverage::new(5).unwrap()]
+        );
+        assert_eq!(
+            order.leverage_for_take(Some(Leverage::new(5).unwrap())),
+            Ok(Leverage::new(5).unwrap())
+        );
+        assert!(order
+            .leverage_for_take(Some(Leverage::new(3).unwrap()))
+            .is_err());
+    }
+
+    #[test]
+    fn chosen_leverage_is_honored() {
+        let chosen = Leverage::new(5).unwrap();
+        let order = dummy_order().with_leverage_choices(&[chosen]);
+        assert_ne!(order.leverage, chosen);
+
+        let leverage = order.leverage_for_take(Some(chosen)).unwrap();
+        let quantity = Usd::new(dec!(100));
+        let identity = Identity::new(x25519_dalek::PublicKey::from([42u8; 32]));
+        let taker_cfd = Cfd::from_order(
+            order.clone(),
+            order.taker_position(),
+            quantity,
+            leverage,
+            identity,
+            Role::Taker,
+        );
+        let maker_cfd = Cfd::from_order(
+            order.clone(),
+            order.position,
+            quantity,
+            leverage,
+            identity,
+            Role::Maker,
+        );
+
+        let (taker_params, _) = taker_cfd.start_contract_setup(1).unwrap();
+        let (maker_params, _) = maker_cfd.start_contract_setup(1).unwrap();
+        let long_margin = calculate_long_margin(order.price, quantity, chosen);
+
+        assert_eq!(taker_cfd.leverage(), chosen);
+        assert_eq!(maker_cfd.leverage(), chosen);
+        assert_eq!(taker_params.leverage(), chosen);
+        assert_eq!(maker_params.leverage(), chosen);
+        assert_eq!(taker_params.margin(), long_margin);
+        assert_eq!(maker_params.counterparty_margin(), long_margin);
+        assert_ne!(
+            long_margin,
+            calculate_long_margin(order.price, quantity, order.leverage)
+        );
+        assert_eq!(order.leverage_for_take(None), Ok(order.leverage));
+    }
+
+    #[test]
+    fn taking_without_current_order_fails() {
+        let result = order_to_take(None, OrderId::default());
+
+        assert_eq!(result, Err(CannotTakeOrder::NoCurrentOrder));
+    }
+
+    #[test]
+    fn taking_order_other_than_current_order_fails() {
+        let current_order = dummy_order();
+        let withdrawn = OrderId::default();
+
+        let result = order_to_take(Some(&current_order), withdrawn);
+
+        assert_eq!(
+            result,
+            Err(CannotTakeOrder::NoLongerCurrent {
+                requested: withdrawn,
+                current: current_order.id,
+            })
+        );
+    }
+
+    #[test]
+    fn current_order_can_be_taken() {
+        let current_order = dummy_order();
+
+        let result = order_to_take(Some(&current_order), current_order.id);
+
+        assert_eq!(result, Ok(&current_order));
+    }
+
+    #[test]
+    fn order_rejects_invalid_quantities() {
+        let new_order = |min_quantity, max_quantity| {
+            Order::new_short(
+                Price::new(dec!(40_000)).unwrap(),
+                Usd::new(min_quantity),
+                Usd::new(max_quantity),
+                Origin::Ours,
+                BitMexPriceEventId::with_20_digits(OffsetDateTime::now_utc()),
+                Duration::hours(24),
+                1,
+            )
+        };
+
+        assert!(new_order(dec!(0), dec!(100)).is_err());
+        assert!(new_order(dec!(-10), dec!(100)).is_err());
+        assert!(new_order(dec!(100), dec!(10)).is_err());
+        assert!(new_order(dec!(100), dec!(100)).is_ok());
+    }
+
+    fn dummy_order() -> Order {
+        Order::new_short(
+            Price::new(dec!(40_000)).unwrap(),
+            Usd::new(dec!(100)),
+            Usd::new(dec!(1000)),
+            Origin::Theirs,
+            BitMexPriceEventId::with_20_digits(OffsetDateTime::now_utc()),
+            Duration::hours(24),
+            1,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn fee_rate_override_is_reflected_in_setup_params() {
+        let order = dummy_order();
+        let cfd = Cfd::from_order(
+            order.clone(),
+            Position::Long,
+            Usd::new(dec!(100)),
+            order.leverage,
+            Identity::new(x25519_dalek::PublicKey::from([42u8; 32])),
+            Role::Taker,
+        );
+
+        let fee_rate = order.fee_rate_for_setup(Some(5)).unwrap();
+        let (setup_params, _) = cfd.start_contract_setup(fee_rate).unwrap();
+
+        assert_eq!(setup_params.fee_rate(), 5);
+    }
+
+    #[test]
+    fn fee_rate_of_order_is_used_without_override() {
+        let order = Order {
+            fee_rate: 3,
+            ..dummy_order()
+        };
+
+        assert_eq!(order.fee_rate_for_setup(None).unwrap(), 3);
+        assert!(order.fee_rate_for_setup(Some(0)).is_err());
+        assert!(order
+            .fee_rate_for_setup(Some(FEE_RATE_BAND.end() + 1))
+            .is_err());
+    }
+
+    #[test]
+    fn cfd_within_max_age_can_be_rolled_over() {
+        let opened_at = datetime!(2021-11-19 10:00:00).assume_utc();
+        let cfd = Cfd {
+            opened_at: Some(Timestamp::new(opened_at.unix_timestamp())),
+            ..dummy_cfd()
+        };
+
+        let result = cfd.ensure_within_max_age(opened_at + Duration::days(6), Duration::days(7));
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn cfd_past_max_age_is_no_longer_rolled_over() {
+        let opened_at = datetime!(2021-11-19 10:00:00).assume_utc();
+        let cfd = Cfd {
+            opened_at: Some(Timestamp::new(opened_at.unix_timestamp())),
+            ..dummy_cfd()
         };
 
-        let Cet {
-            tx: cet,
-            adaptor_sig: encsig,
-            n_bits,
-            ..
-        } = cets
-            .iter()
-            .find(|Cet { range, .. }| range.contains(&attestation.price))
-            .context("Price out of range of cets")?;
+        let result = cfd.ensure_within_max_age(opened_at + Duration::days(8), Duration::days(7));
 
-        let mut decryption_sk = attestation.scalars[0];
-        for oracle_attestation in attestation.scalars[1..*n_bits].iter() {
-            decryption_sk.add_assign(oracle_attestation.as_ref())?;
-        }
+        assert_eq!(result, Err(CannotRollover::MaxAgeExceeded));
+    }
 
-        let sig_hash = spending_tx_sighash(
-            cet,
-            &self.commit.2,
-            Amount::from_sat(self.commit.0.output[0].value),
-        );
-        let our_sig = SECP256K1.sign(&sig_hash, &self.identity);
-        let our_pubkey = PublicKey::new(bdk::bitcoin::secp256k1::PublicKey::from_secret_key(
-            SECP256K1,
-            &self.identity,
-        ));
+    #[test]
+    fn cfd_without_contract_setup_has_no_age() {
+        let result = dummy_cfd().ensure_within_max_age(OffsetDateTime::now_utc(), Duration::ZERO);
 
-        let counterparty_sig = encsig.decrypt(&decryption_sk)?;
-        let counterparty_pubkey = self.identity_counterparty;
+        assert!(result.is_ok());
+    }
 
-        let signed_cet = finalize_spend_transaction(
-            cet.clone(),
-            &self.commit.2,
-            (our_pubkey, our_sig),
-            (counterparty_pubkey, counterparty_sig),
-        )?;
+    #[test]
+    fn only_cfds_not_rejected_failed_or_closed_are_exposed() {
+        let apply = |event| dummy_cfd().apply(Event::new(OrderId::default(), event));
 
-        Ok(Ok(signed_cet))
+        assert!(dummy_cfd().is_exposed());
+        assert!(!apply(CfdEvent::OfferRejected).is_exposed());
+        assert!(!apply(CfdEvent::ContractSetupFailed { reason: None }).is_exposed());
+        assert!(!apply(CfdEvent::CollaborativeSettlementConfirmed).is_exposed());
     }
-}
 
-#[derive(Debug, thiserror::Error)]
-#[error("Attestation {id} is irrelevant for DLC {tx_id}")]
-pub struct IrrelevantAttestation {
-    id: BitMexPriceEventId,
-    tx_id: Txid,
-}
+    #[test]
+    fn events_that_cannot_occur_are_not_applicable() {
+        let setup_completed = || CfdEvent::ContractSetupCompleted { dlc: Dlc::dummy(0) };
+        let open = dummy_cfd().apply(Event::new(OrderId::default(), setup_completed()));
+        let closed = dummy_cfd()
+            .apply(Event::new(OrderId::default(), setup_completed()))
+            .apply(Event::new(
+                OrderId::default(),
+                CfdEvent::CollaborativeSettlementConfirmed,
+            ));
 
-/// Information which we need to remember in order to construct a
-/// punishment transaction in case the counterparty publishes a
-/// revoked commit transaction.
-///
-/// It also includes the information needed to monitor for the
-/// publication of the revoked commit transaction.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct RevokedCommit {
-    // To build punish transaction
-    pub encsig_ours: EcdsaAdaptorSignature,
-    pub revocation_sk_theirs: SecretKey,
-    pub publication_pk_theirs: PublicKey,
-    // To monitor revoked commit transaction
-    pub txid: Txid,
-    pub script_pubkey: Script,
-}
+        assert!(dummy_cfd().ensure_applicable(&setup_completed()).is_ok());
+        assert!(open.ensure_applicable(&CfdEvent::MonitoringPaused).is_ok());
+        assert!(closed.ensure_applicable(&CfdEvent::CetConfirmed).is_ok());
 
-/// Used when transactions (e.g. collaborative close) are recorded as a part of
-/// CfdState in the cases when we can't solely rely on state transition
-/// timestamp as it could have occured for different reasons (like a new
-/// attestation in Open state)
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-pub struct CollaborativeSettlement {
-    pub tx: Transaction,
-    pub script_pubkey: Script,
-    pub timestamp: Timestamp,
-    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
-    payout: Amount,
-    price: Price,
-}
+        assert!(open.ensure_applicable(&setup_completed()).is_err());
+        assert!(dummy_cfd()
+            .ensure_applicable(&CfdEvent::MonitoringPaused)
+            .is_err());
+        assert!(closed.ensure_applicable(&CfdEvent::OfferRejected).is_err());
+    }
 
-impl CollaborativeSettlement {
-    pub fn new(tx: Transaction, own_script_pubkey: Script, price: Price) -> Result<Self> {
-        // Falls back to Amount::ZERO in case we don't find an output that matches out script pubkey
-        // The assumption is, that this can happen for cases where we were liquidated
-        let payout = match tx
-            .output
-            .iter()
-            .find(|output| output.script_pubkey == own_script_pubkey)
-            .map(|output| Amount::from_sat(output.value))
-        {
-            Some(payout) => payout,
-            None => {
-                tracing::error!(
-                    "Collaborative settlement with a zero amount, this should really not happen!"
-                );
-                Amount::ZERO
-            }
+    #[test]
+    fn cet_is_only_published_once() {
+        let cfd = dummy_cfd()
+            .apply(Event::new(
+                OrderId::default(),
+                CfdEvent::ContractSetupCompleted { dlc: Dlc::dummy(0) },
+            ))
+            .apply(Event::new(OrderId::default(), CfdEvent::CetPublished));
+
+        assert!(cfd.ensure_applicable(&CfdEvent::CetPublished).is_err());
+    }
+
+    #[test]
+    fn rollover_cost_matches_rollover_params() {
+        let dlc = Dlc {
+            taker_lock_amount: Amount::from_sat(100_000),
+            ..Dlc::dummy(0)
         };
+        let cfd = dummy_cfd()
+            .apply(Event::new(
+                OrderId::default(),
+                CfdEvent::ContractSetupCompleted { dlc },
+            ))
+            .apply(Event::new(OrderId::default(), CfdEvent::LockConfirmed));
+        let (params, _, _) = cfd.start_rollover().unwrap();
+
+        let cost = cfd.rollover_cost().unwrap();
+
+        // 100 USD at 40,000 with leverage 2
+        assert_eq!(params.margin(Position::Long), Amount::from_sat(125_000));
+        assert_eq!(
+            cost,
+            ActionCost {
+                margin: Amount::from_sat(125_000),
+                margin_delta: SignedAmount::from_sat(25_000),
+                required_funds: Amount::from_sat(25_000),
+                fee: Amount::ZERO,
+                payout: None,
+            }
+        );
+    }
 
-        Ok(Self {
-            tx,
-            script_pubkey: own_script_pubkey,
-            timestamp: Timestamp::now(),
-            payout,
-            price,
-        })
+    #[test]
+    fn settlement_cost_releases_margin_and_pays_out_close_transaction() {
+        let mut dlc = Dlc {
+            taker_lock_amount: Amount::from_sat(125_000),
+            maker_lock_amount: Amount::from_sat(250_000),
+            ..Dlc::dummy(0)
+        };
+        let taker_pk = PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(
+            SECP256K1,
+            &SecretKey::from_slice(&[5; 32]).unwrap(),
+        ));
+        dlc.taker_address = Address::p2wpkh(&taker_pk, bdk::bitcoin::Network::Testnet).unwrap();
+        dlc.lock.0.output.push(TxOut {
+            value: 375_000,
+            script_pubkey: dlc.lock.1.script_pubkey(),
+        });
+        let cfd = dummy_cfd()
+            .apply(Event::new(
+                OrderId::default(),
+                CfdEvent::ContractSetupCompleted { dlc: dlc.clone() },
+            ))
+            .apply(Event::new(OrderId::default(), CfdEvent::LockConfirmed));
+        let price = Price::new(dec!(44_000)).unwrap();
+
+        let cost = cfd.settlement_cost(price, 200).unwrap();
+
+        let proposal = cfd.settlement_proposal(price, 200).unwrap();
+        let (close_tx, _) = dlc.close_transaction(&proposal).unwrap();
+        let taker_output = close_tx
+            .output
+            .iter()
+            .find(|output| output.script_pubkey == dlc.taker_address.script_pubkey())
+            .unwrap();
+        let paid = close_tx
+            .output
+            .iter()
+            .map(|output| output.value)
+            .sum::<u64>();
+        assert_eq!(cost.margin, Amount::ZERO);
+        assert_eq!(cost.margin_delta, SignedAmount::from_sat(-125_000));
+        assert_eq!(cost.required_funds, Amount::ZERO);
+        assert_eq!(cost.fee, Amount::from_sat(375_000 - paid));
+        assert_eq!(cost.payout, Some(Amount::from_sat(taker_output.value)));
+        // The long taker profits from the price rising above 40,000
+        assert!(cost.payout.unwrap() > Amount::from_sat(125_000));
+        assert!(cost.fee > Amount::ZERO);
     }
 
-    pub fn payout(&self) -> Amount {
-        self.payout
+    #[test]
+    fn cfd_without_dlc_has_no_action_costs() {
+        let costs = dummy_cfd().action_costs(Some(Price::new(dec!(41_000)).unwrap()), 200);
+
+        assert_eq!(
+            costs,
+            ActionCosts {
+                rollover: None,
+                settlement: None
+            }
+        );
     }
-}
 
-#[allow(clippy::large_enum_variant)]
-#[derive(Debug)]
-pub enum Completed<P> {
-    Succeeded {
-        order_id: OrderId,
-        payload: P,
-    },
-    Rejected {
-        order_id: OrderId,
-        reason: anyhow::Error,
-    },
-    Failed {
-        order_id: OrderId,
-        error: anyhow::Error,
-    },
-}
+    #[test]
+    fn cfd_without_dlc_has_no_close_options() {
+        let cfd = dummy_cfd();
 
-impl<P> xtra::Message for Completed<P>
-where
-    P: Send + 'static,
-{
-    type Result = Result<()>;
-}
+        let options = cfd.close_options(Some(Price::new(dec!(41_000)).unwrap()), 200);
 
-impl<P> Completed<P> {
-    pub fn order_id(&self) -> OrderId {
-        *match self {
-            Completed::Succeeded { order_id, .. } => order_id,
-            Completed::Rejected { order_id, .. } => order_id,
-            Completed::Failed { order_id, .. } => order_id,
-        }
+        assert!(options.is_empty());
     }
 
-    pub fn rejected(order_id: OrderId) -> Self {
-        Self::Rejected {
-            order_id,
-            reason: anyhow::format_err!("unknown"),
-        }
+    #[test]
+    fn maker_cannot_close_by_collaborative_settlement() {
+        let cfd = Cfd {
+            role: Role::Maker,
+            ..open_cfd()
+        };
+
+        let options = cfd.close_options(Some(Price::new(dec!(41_000)).unwrap()), 200);
+
+        assert_eq!(
+            options.iter().map(|option| option.kind).collect::<Vec<_>>(),
+            vec![CloseKind::Commit]
+        );
     }
-    pub fn rejected_due_to(order_id: OrderId, reason: anyhow::Error) -> Self {
-        Self::Rejected { order_id, reason }
+
+    #[test]
+    fn collaborative_settlement_without_our_output_is_rejected() {
+        let proposal =
+            dummy_settlement_proposal(Amount::from_sat(100_000), Amount::from_sat(100_000));
+
+        let result =
+            CollaborativeSettlement::new(dummy_tx(0), &Dlc::dummy(0), Role::Taker, &proposal);
+
+        assert!(result.is_err());
     }
-}
 
-pub mod marker {
-    /// Marker type for contract setup completion
-    #[derive(Debug)]
-    pub struct Setup;
-    /// Marker type for rollover  completion
-    #[derive(Debug)]
-    pub struct Rollover;
-}
+    #[test]
+    fn collaborative_settlement_without_our_output_pays_zero_if_liquidated() {
+        let proposal = dummy_settlement_proposal(Amount::from_sat(200_000), Amount::ZERO);
 
-/// Message sent from a setup actor to the
-/// cfd actor to notify that the contract setup has finished.
-pub type SetupCompleted = Completed<(Dlc, marker::Setup)>;
+        let settlement =
+            CollaborativeSettlement::new(dummy_tx(0), &Dlc::dummy(0), Role::Taker, &proposal)
+                .unwrap();
 
-/// Message sent from a rollover actor to the
-/// cfd actor to notify that the rollover has finished (contract got updated).
-/// TODO: Roll it out in the maker rollover actor
-pub type RolloverCompleted = Completed<(Dlc, marker::Rollover)>;
+        assert_eq!(settlement.payout(), Amount::ZERO);
+    }
 
-impl Completed<(Dlc, marker::Setup)> {
-    pub fn succeeded(order_id: OrderId, dlc: Dlc) -> Self {
-        Self::Succeeded {
-            order_id,
-            payload: (dlc, marker::Setup),
-        }
+    #[test]
+    fn collaborative_settlement_records_amounts_of_proposal() {
+        let dlc = Dlc::dummy(0);
+        let mut tx = dummy_tx(0);
+        // The output is less than the agreed upon payout because it pays its share of the fee
+        tx.output.push(TxOut {
+            value: 99_500,
+            script_pubkey: dlc.script_pubkey_for(Role::Taker),
+        });
+        let proposal =
+            dummy_settlement_proposal(Amount::from_sat(50_000), Amount::from_sat(100_000));
+
+        let settlement = CollaborativeSettlement::new(tx, &dlc, Role::Taker, &proposal).unwrap();
+
+        assert_eq!(settlement.payout(), Amount::from_sat(100_000));
+        assert_eq!(settlement.maker, Amount::from_sat(50_000));
+        assert_eq!(settlement.taker, Amount::from_sat(100_000));
+        assert_eq!(settlement.price, proposal.price);
     }
-}
 
-impl Completed<(Dlc, marker::Rollover)> {
-    pub fn succeeded(order_id: OrderId, dlc: Dlc) -> Self {
-        Self::Succeeded {
-            order_id,
-            payload: (dlc, marker::Rollover),
+    fn dummy_settlement_proposal(maker: Amount, taker: Amount) -> SettlementProposal {
+        SettlementProposal {
+            order_id: OrderId::default(),
+            timestamp: Timestamp::now(),
+            taker,
+            maker,
+            price: Price::new(dec!(60_000)).unwrap(),
         }
     }
-}
 
-mod hex_transaction {
-    use super::*;
-    use bdk::bitcoin;
-    use serde::Deserializer;
-    use serde::Serializer;
+    #[test]
+    fn missing_payout_is_only_expected_for_zero_payout() {
+        assert_eq!(
+            MissingPayout::for_payout(Amount::ZERO),
+            MissingPayout::AssumeLiquidated
+        );
+        assert_eq!(
+            MissingPayout::for_payout(Amount::from_sat(1)),
+            MissingPayout::Reject
+        );
+    }
 
-    pub fn serialize<S: Serializer>(value: &Transaction, serializer: S) -> Result<S::Ok, S::Error> {
-        let bytes = bitcoin::consensus::serialize(value);
-        let hex_str = hex::encode(bytes);
-        serializer.serialize_str(hex_str.as_str())
+    #[test]
+    fn tampered_close_transaction_fails_verification() {
+        let mut dlc = Dlc::dummy(0);
+        dlc.lock.0.output.push(TxOut {
+            value: Amount::ONE_BTC.as_sat(),
+            script_pubkey: dlc.lock.1.script_pubkey(),
+        });
+        let proposal = SettlementProposal {
+            order_id: OrderId::default(),
+            timestamp: Timestamp::now(),
+            taker: Amount::from_sat(49_000_000),
+            maker: Amount::from_sat(49_000_000),
+            price: Price::new(dec!(40_000)).unwrap(),
+        };
+
+        let (close_tx, own_sig) = dlc.close_transaction(&proposal).unwrap();
+        let sighash = spending_tx_sighash(&close_tx, &dlc.lock.1, Amount::ONE_BTC);
+        let counterparty_sig = SECP256K1.sign(&sighash, &SecretKey::from_slice(&[2; 32]).unwrap());
+        let spend_tx = dlc
+            .finalize_spend_transaction((close_tx, own_sig), counterparty_sig)
+            .unwrap();
+
+        dlc.verify_finalized(&spend_tx).unwrap();
+
+        let mut tampered = spend_tx;
+        tampered.output[0].value -= 1;
+
+        assert!(dlc.verify_finalized(&tampered).is_err());
     }
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Transaction, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let hex = String::deserialize(deserializer).map_err(D::Error::custom)?;
-        let bytes = hex::decode(hex).map_err(D::Error::custom)?;
-        let tx = bitcoin::consensus::deserialize(&bytes).map_err(D::Error::custom)?;
-        Ok(tx)
+    #[test]
+    fn dlc_summary_does_not_contain_secrets() {
+        let (settlement, liquidation) = event_ids();
+        let mut dlc = Dlc::dummy(0);
+        dlc.cets = cets_for(settlement, liquidation);
+
+        let summary = dlc.summary();
+        let json = serde_json::to_string(&summary).unwrap();
+
+        assert_eq!(summary.cet_count, 2);
+        assert_eq!(summary.cets[0].event_id, liquidation);
+        assert_eq!(summary.cets[0].to_price, 50_000);
+        for secret in [dlc.identity, dlc.revocation, dlc.publish] {
+            let secret = serde_json::to_value(secret).unwrap();
+            let secret = secret.as_str().unwrap();
+
+            assert!(!json.contains(secret), "{} leaks a secret key", json);
+        }
+        let refund_sig = serde_json::to_value(dlc.refund.1).unwrap();
+        assert!(!json.contains(refund_sig.as_str().unwrap()));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rust_decimal_macros::dec;
+    fn dummy_tx(lock_time: u32) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time,
+            input: vec![],
+            output: vec![],
+        }
+    }
 
     #[test]
     fn given_default_values_then_expected_liquidation_price() {
@@ -1630,8 +4217,64 @@ mod tests {
         let (profit, in_percent) =
             calculate_profit(initial_price, current_price, quantity, leverage, position).unwrap();
 
-        assert_eq!(profit, should_profit, "{}", msg);
-        assert_eq!(in_percent, should_profit_in_percent, "{}", msg);
+        assert_eq!(profit, should_profit, "{}", msg);
+        assert_eq!(in_percent, should_profit_in_percent, "{}", msg);
+    }
+
+    #[test]
+    fn long_profit_at_break_even_price_covers_fees() {
+        assert_break_even(Position::Long, Amount::ZERO);
+        assert_break_even(Position::Long, Amount::from_sat(50_000));
+    }
+
+    #[test]
+    fn short_profit_at_break_even_price_covers_fees() {
+        assert_break_even(Position::Short, Amount::ZERO);
+        assert_break_even(Position::Short, Amount::from_sat(50_000));
+    }
+
+    #[test]
+    fn no_break_even_price_if_fees_exceed_maximum_profit() {
+        let initial_price = Price::new(dec!(40_000)).unwrap();
+        let quantity = Usd::new(dec!(100));
+        let leverage = Leverage::new(2).unwrap();
+        let long_margin = calculate_long_margin(initial_price, quantity, leverage);
+
+        let result = calculate_break_even_price(
+            initial_price,
+            quantity,
+            leverage,
+            Position::Short,
+            long_margin,
+        );
+
+        assert!(result.is_err());
+    }
+
+    fn assert_break_even(position: Position, fees: Amount) {
+        let initial_price = Price::new(dec!(40_000)).unwrap();
+        let quantity = Usd::new(dec!(10_000));
+        let leverage = Leverage::new(2).unwrap();
+
+        let break_even_price =
+            calculate_break_even_price(initial_price, quantity, leverage, position, fees).unwrap();
+        let (profit, _) = calculate_profit(
+            initial_price,
+            break_even_price,
+            quantity,
+            leverage,
+            position,
+        )
+        .unwrap();
+
+        let fees = fees.to_signed().unwrap();
+        assert!(
+            (profit - fees).as_sat().abs() <= 2,
+            "profit of {} at break-even price {} should cover fees of {}",
+            profit,
+            break_even_price,
+            fees
+        );
     }
 
     #[test]
@@ -1715,22 +4358,76 @@ mod tests {
 
     #[test]
     fn cfd_event_to_json() {
-        let event = CfdEvent::ContractSetupFailed;
+        let event = CfdEvent::ContractSetupFailed {
+            reason: Some("Failed to sign".to_owned()),
+        };
 
         let (name, data) = event.to_json();
 
         assert_eq!(name, "ContractSetupFailed");
-        assert_eq!(data, r#"null"#);
+        assert_eq!(data, r#"{"reason":"Failed to sign"}"#);
     }
 
     #[test]
     fn cfd_event_from_json() {
+        let name = "ContractSetupFailed".to_owned();
+        let data = r#"{"reason":"Failed to sign"}"#.to_owned();
+
+        let event = CfdEvent::from_json(name, data, CfdEvent::VERSION).unwrap();
+
+        assert_eq!(
+            event,
+            CfdEvent::ContractSetupFailed {
+                reason: Some("Failed to sign".to_owned())
+            }
+        );
+    }
+
+    #[test]
+    fn contract_setup_failed_without_reason_from_version_1_json() {
         let name = "ContractSetupFailed".to_owned();
         let data = r#"null"#.to_owned();
 
-        let event = CfdEvent::from_json(name, data).unwrap();
+        let event = CfdEvent::from_json(name, data, 1).unwrap();
+
+        assert_eq!(event, CfdEvent::ContractSetupFailed { reason: None });
+    }
+
+    #[test]
+    fn collaborative_settlement_completed_without_amounts_from_version_2_json() {
+        let spend_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: vec![TxOut::default()],
+        };
+        let price = Price::new(dec!(40_000)).unwrap();
+        let (name, data) = CfdEvent::CollaborativeSettlementCompleted {
+            spend_tx: spend_tx.clone(),
+            script: Script::new(),
+            price,
+            maker: None,
+            taker: None,
+        }
+        .to_json();
+
+        let mut data = serde_json::from_str::<serde_json::Value>(&data).unwrap();
+        let object = data.as_object_mut().unwrap();
+        object.remove("maker");
+        object.remove("taker");
+
+        let event = CfdEvent::from_json(name, data.to_string(), 2).unwrap();
 
-        assert_eq!(event, CfdEvent::ContractSetupFailed);
+        assert_eq!(
+            event,
+            CfdEvent::CollaborativeSettlementCompleted {
+                spend_tx,
+                script: Script::new(),
+                price,
+                maker: None,
+                taker: None,
+            }
+        );
     }
 
     #[test]
@@ -1738,8 +4435,703 @@ mod tests {
         let name = "OfferRejected".to_owned();
         let data = r#"null"#.to_owned();
 
-        let event = CfdEvent::from_json(name, data).unwrap();
+        let event = CfdEvent::from_json(name, data, CfdEvent::VERSION).unwrap();
 
         assert_eq!(event, CfdEvent::OfferRejected);
     }
+
+    #[test]
+    fn contract_setup_failed_from_unversioned_json() {
+        // the shape `ContractSetupFailed` was stored in before versioning was introduced, which
+        // is loaded as version 0 and upcast through every later version
+        let name = "ContractSetupFailed".to_owned();
+        let data = r#"null"#.to_owned();
+
+        let event = CfdEvent::from_json(name, data, 0).unwrap();
+
+        assert_eq!(event, CfdEvent::ContractSetupFailed { reason: None });
+    }
+
+    #[test]
+    fn malformed_cfd_event_error_names_the_event() {
+        let name = "CetTimelockConfirmedPostOracleAttestation".to_owned();
+        let data = r#"{"cet":"not-a-transaction"}"#.to_owned();
+
+        let error = CfdEvent::from_json(name, data, CfdEvent::VERSION).unwrap_err();
+        let error = error.downcast_ref::<InvalidEvent>().unwrap();
+
+        assert_eq!(error.name, "CetTimelockConfirmedPostOracleAttestation");
+        assert_eq!(error.data, r#"{"cet":"not-a-transaction"}"#);
+    }
+
+    #[test]
+    fn cfd_event_from_json_with_unknown_version_fails() {
+        let name = "OfferRejected".to_owned();
+        let data = r#"null"#.to_owned();
+
+        let result = CfdEvent::from_json(name, data, CfdEvent::VERSION + 1);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn only_cfd_with_failed_setup_can_be_archived_once() {
+        let cfd = dummy_cfd();
+        assert!(cfd.archive().is_err());
+
+        let cfd = cfd.apply(Event::new(
+            OrderId::default(),
+            CfdEvent::ContractSetupFailed { reason: None },
+        ));
+        let archived = cfd.archive().unwrap();
+        assert_eq!(archived.event, CfdEvent::Archived);
+
+        let cfd = cfd.apply(archived);
+        assert!(cfd.archive().is_err());
+    }
+
+    fn dummy_cfd() -> Cfd {
+        Cfd::new(
+            OrderId::default(),
+            Position::Long,
+            Price::new(dec!(40_000)).unwrap(),
+            Leverage::new(2).unwrap(),
+            Duration::hours(24),
+            Role::Taker,
+            Usd::new(dec!(100)),
+            Identity::new(x25519_dalek::PublicKey::from([42u8; 32])),
+        )
+    }
+
+    fn finality_flags(cfd: &Cfd) -> [bool; 8] {
+        [
+            cfd.lock_finality,
+            cfd.commit_finality,
+            cfd.refund_finality,
+            cfd.cet_finality,
+            cfd.collaborative_settlement_finality,
+            cfd.revoke_finality,
+            cfd.cet_timelock_expired,
+            cfd.refund_timelock_expired,
+        ]
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn transactions(
+        cfd: &Cfd,
+    ) -> (
+        Option<Transaction>,
+        Option<Transaction>,
+        Option<Transaction>,
+        Option<Transaction>,
+    ) {
+        (
+            cfd.cet.clone(),
+            cfd.commit_tx.clone(),
+            cfd.collaborative_settlement_spend_tx.clone(),
+            cfd.refund_tx.clone(),
+        )
+    }
+
+    fn arbitrary_event() -> impl Strategy<Value = CfdEvent> {
+        let price = Price::new(dec!(40_000)).unwrap();
+
+        prop_oneof![
+            any::<u32>().prop_map(|marker| CfdEvent::ContractSetupCompleted {
+                dlc: Dlc::dummy(marker)
+            }),
+            Just(CfdEvent::ContractSetupFailed { reason: None }),
+            Just(CfdEvent::OfferRejected),
+            any::<u32>().prop_map(|marker| CfdEvent::RolloverCompleted {
+                dlc: Dlc::dummy(marker)
+            }),
+            Just(CfdEvent::RolloverRejected),
+            Just(CfdEvent::RolloverFailed),
+            (any::<u32>(), any::<u64>(), any::<u64>()).prop_map(
+                move |(lock_time, maker, taker)| {
+                    CfdEvent::CollaborativeSettlementCompleted {
+                        spend_tx: dummy_tx(lock_time),
+                        script: Script::new(),
+                        price,
+                        maker: Some(Amount::from_sat(maker)),
+                        taker: Some(Amount::from_sat(taker)),
+                    }
+                }
+            ),
+            any::<u32>().prop_map(|lock_time| CfdEvent::CollaborativeSettlementRejected {
+                commit_tx: dummy_tx(lock_time)
+            }),
+            any::<u32>().prop_map(|lock_time| CfdEvent::CollaborativeSettlementFailed {
+                commit_tx: dummy_tx(lock_time)
+            }),
+            Just(CfdEvent::LockConfirmed),
+            Just(CfdEvent::CommitConfirmed),
+            Just(CfdEvent::CetConfirmed),
+            Just(CfdEvent::RefundConfirmed),
+            Just(CfdEvent::RevokeConfirmed),
+            Just(CfdEvent::CollaborativeSettlementConfirmed),
+            Just(CfdEvent::CetTimelockConfirmedPriorOracleAttestation),
+            any::<u32>().prop_map(|lock_time| {
+                CfdEvent::CetTimelockConfirmedPostOracleAttestation {
+                    cet: dummy_tx(lock_time),
+                }
+            }),
+            any::<u32>().prop_map(|lock_time| CfdEvent::RefundTimelockConfirmed {
+                refund_tx: dummy_tx(lock_time)
+            }),
+            any::<u32>().prop_map(move |lock_time| CfdEvent::OracleAttestedPriorCetTimelock {
+                timelocked_cet: dummy_tx(lock_time),
+                commit_tx: dummy_tx(lock_time),
+                price,
+            }),
+            any::<u32>().prop_map(move |lock_time| CfdEvent::OracleAttestedPostCetTimelock {
+                cet: dummy_tx(lock_time),
+                price,
+            }),
+            Just(CfdEvent::CetPublished),
+            any::<u32>().prop_map(|lock_time| CfdEvent::ManualCommit {
+                tx: dummy_tx(lock_time)
+            }),
+            any::<u32>().prop_map(|lock_time| CfdEvent::ManualCommitAndSettle {
+                tx: dummy_tx(lock_time)
+            }),
+            any::<u32>().prop_map(|lock_time| CfdEvent::ManualRefund {
+                tx: dummy_tx(lock_time)
+            }),
+            Just(CfdEvent::MonitoringPaused),
+            Just(CfdEvent::MonitoringResumed),
+            Just(CfdEvent::ExitLevelsSet {
+                levels: ExitLevels {
+                    take_profit: Some(price),
+                    stop_loss: None,
+                },
+            }),
+            Just(CfdEvent::Archived),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn applying_events_preserves_invariants(
+            events in proptest::collection::vec(arbitrary_event(), 0..30)
+        ) {
+            let mut cfd = dummy_cfd();
+            let mut applied = 0;
+
+            for event in events {
+                prop_assert_eq!(event.name(), event.to_json().0);
+
+                if cfd.ensure_applicable(&event).is_err() {
+                    continue;
+                }
+
+                let was_final = cfd.is_final();
+
+                let flags_before = finality_flags(&cfd);
+                let transactions_before = transactions(&cfd);
+
+                cfd = cfd.apply(Event::new(cfd.id, event.clone()));
+                applied += 1;
+
+                prop_assert_eq!(cfd.version, applied);
+
+                let flags_after = finality_flags(&cfd);
+                prop_assert!(
+                    flags_before
+                        .iter()
+                        .zip(flags_after.iter())
+                        .all(|(before, after)| !before || *after),
+                    "{:?} reset a finality flag",
+                    event
+                );
+                prop_assert!(
+                    !was_final || transactions(&cfd) == transactions_before,
+                    "{:?} changed a final CFD",
+                    event
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn settlement_attestation_selects_settlement_cets() {
+        let (settlement, liquidation) = event_ids();
+        let cets = cets_for(settlement, liquidation);
+
+        let cet = select_cet(&cets, &attestation(settlement, 45_000))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(cet.tx.lock_time, SETTLEMENT_CET_MARKER);
+    }
+
+    #[test]
+    fn liquidation_attestation_selects_liquidation_cets() {
+        let (settlement, liquidation) = event_ids();
+        let cets = cets_for(settlement, liquidation);
+
+        let cet = select_cet(&cets, &attestation(liquidation, 45_000))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(cet.tx.lock_time, LIQUIDATION_CET_MARKER);
+    }
+
+    #[test]
+    fn attestation_of_unknown_event_selects_no_cet() {
+        let (settlement, liquidation) = event_ids();
+        let cets = cets_for(settlement, liquidation);
+        let unknown =
+            BitMexPriceEventId::with_20_digits(datetime!(2021-11-20 10:00:00).assume_utc());
+
+        let cet = select_cet(&cets, &attestation(unknown, 45_000)).unwrap();
+
+        assert!(cet.is_none());
+    }
+
+    #[test]
+    fn attested_price_outside_of_liquidation_cets_fails() {
+        let (settlement, liquidation) = event_ids();
+        let cets = cets_for(settlement, liquidation);
+
+        let result = select_cet(&cets, &attestation(liquidation, 60_000));
+
+        assert!(result.is_err());
+    }
+
+    const SETTLEMENT_CET_MARKER: u32 = 1;
+    const LIQUIDATION_CET_MARKER: u32 = 2;
+
+    fn event_ids() -> (BitMexPriceEventId, BitMexPriceEventId) {
+        let settlement =
+            BitMexPriceEventId::with_20_digits(datetime!(2021-11-19 10:00:00).assume_utc());
+        let liquidation =
+            BitMexPriceEventId::with_20_digits(datetime!(2021-11-18 12:00:00).assume_utc());
+
+        (settlement, liquidation)
+    }
+
+    /// Settlement CETs cover all prices, liquidation CETs only prices up to 50k.
+    fn cets_for(
+        settlement: BitMexPriceEventId,
+        liquidation: BitMexPriceEventId,
+    ) -> HashMap<BitMexPriceEventId, Vec<Cet>> {
+        HashMap::from([
+            (
+                settlement,
+                vec![dummy_cet(0..=u64::MAX, SETTLEMENT_CET_MARKER)],
+            ),
+            (
+                liquidation,
+                vec![dummy_cet(0..=50_000, LIQUIDATION_CET_MARKER)],
+            ),
+        ])
+    }
+
+    #[test]
+    fn duplicate_contract_setup_completion_keeps_first_dlc() {
+        let first = Dlc::dummy(1);
+        let second = Dlc::dummy(2);
+
+        let cfd = dummy_cfd()
+            .apply(Event::new(
+                OrderId::default(),
+                CfdEvent::ContractSetupCompleted { dlc: first.clone() },
+            ))
+            .apply(Event::new(
+                OrderId::default(),
+                CfdEvent::ContractSetupCompleted { dlc: second },
+            ));
+
+        assert_eq!(cfd.dlc, Some(first));
+    }
+
+    impl Dlc {
+        /// A DLC whose transactions are marked with `marker`, not usable for anything but
+        /// comparison.
+        pub(crate) fn dummy(marker: u32) -> Self {
+            let identity = SecretKey::from_slice(&[1; 32]).unwrap();
+            let identity_counterparty = SecretKey::from_slice(&[2; 32]).unwrap();
+            let pk = PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(
+                SECP256K1, &identity,
+            ));
+            let pk_counterparty = PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(
+                SECP256K1,
+                &identity_counterparty,
+            ));
+            let descriptor = format!("wsh(multi(2,{},{}))", pk, pk_counterparty)
+                .parse::<Descriptor<PublicKey>>()
+                .unwrap();
+            let address = Address::p2wpkh(&pk, bdk::bitcoin::Network::Testnet).unwrap();
+            let msg = secp256k1_zkp::Message::from_slice(&[3; 32]).unwrap();
+            let (settlement, _) = event_ids();
+
+            Dlc {
+                identity,
+                identity_counterparty: pk_counterparty,
+                revocation: identity,
+                revocation_pk_counterparty: pk_counterparty,
+                publish: identity,
+                publish_pk_counterparty: pk_counterparty,
+                maker_address: address.clone(),
+                taker_address: address,
+                lock: (dummy_tx(marker), descriptor.clone()),
+                commit: (
+                    dummy_tx(marker),
+                    EcdsaAdaptorSignature::encrypt(SECP256K1, &msg, &identity, &pk.key),
+                    descriptor,
+                ),
+                cets: HashMap::new(),
+                refund: (dummy_tx(marker), SECP256K1.sign(&msg, &identity)),
+                maker_lock_amount: Amount::ONE_BTC,
+                taker_lock_amount: Amount::ONE_BTC,
+                lock_fee: Some(Amount::from_sat(500)),
+                revoked_commit: Vec::new(),
+                settlement_event_id: settlement,
+                refund_timelock: 0,
+            }
+        }
+    }
+
+    fn dummy_cet(range: RangeInclusive<u64>, marker: u32) -> Cet {
+        let sk = SecretKey::from_slice(&[1; 32]).unwrap();
+        let encryption_pk = secp256k1_zkp::PublicKey::from_secret_key(SECP256K1, &sk);
+        let msg = secp256k1_zkp::Message::from_slice(&[2; 32]).unwrap();
+
+        let mut tx = dummy_tx(marker);
+        tx.output.push(bdk::bitcoin::TxOut {
+            value: 0,
+            script_pubkey: Script::new(),
+        });
+
+        Cet {
+            tx,
+            adaptor_sig: EcdsaAdaptorSignature::encrypt(SECP256K1, &msg, &sk, &encryption_pk),
+            range,
+            n_bits: 20,
+        }
+    }
+
+    fn attestation(id: BitMexPriceEventId, price: u64) -> oracle::Attestation {
+        oracle::Attestation {
+            id,
+            price,
+            scalars: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn attestation_of_other_oracle_does_not_decrypt_counterparty_sig() {
+        let counterparty_sk = SecretKey::from_slice(&[3; 32]).unwrap();
+        let counterparty_pk = PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(
+            SECP256K1,
+            &counterparty_sk,
+        ));
+        let oracle_scalar = SecretKey::from_slice(&[4; 32]).unwrap();
+        let other_oracle_scalar = SecretKey::from_slice(&[5; 32]).unwrap();
+        let sig_hash = secp256k1_zkp::Message::from_slice(&[6; 32]).unwrap();
+        let encsig = EcdsaAdaptorSignature::encrypt(
+            SECP256K1,
+            &sig_hash,
+            &counterparty_sk,
+            &secp256k1_zkp::PublicKey::from_secret_key(SECP256K1, &oracle_scalar),
+        );
+
+        let expected_oracle =
+            decrypt_counterparty_sig(&encsig, &oracle_scalar, &sig_hash, &counterparty_pk);
+        let other_oracle =
+            decrypt_counterparty_sig(&encsig, &other_oracle_scalar, &sig_hash, &counterparty_pk);
+
+        assert!(expected_oracle.is_some());
+        assert!(other_oracle.is_none());
+    }
+
+    #[test]
+    fn delayed_commit_is_due_once_delay_passed() {
+        let attested = attested_prior_cet_timelock(Timestamp::new(1_000));
+        let delay = Duration::hours(1);
+
+        assert!(!attested.is_commit_due(delay, Timestamp::new(1_000 + 3_599)));
+        assert!(attested.is_commit_due(delay, Timestamp::new(1_000 + 3_600)));
+    }
+
+    #[test]
+    fn delayed_commit_is_not_due_once_commit_published_by_either_party() {
+        let delay = Duration::hours(1);
+        let now = Timestamp::new(1_000 + 3_600);
+
+        let published = attested_prior_cet_timelock(Timestamp::new(1_000)).apply(Event::new(
+            OrderId::default(),
+            CfdEvent::ManualCommit { tx: dummy_tx(0) },
+        ));
+        let confirmed = attested_prior_cet_timelock(Timestamp::new(1_000))
+            .apply(Event::new(OrderId::default(), CfdEvent::CommitConfirmed));
+
+        assert!(!published.is_commit_due(delay, now));
+        assert!(!confirmed.is_commit_due(delay, now));
+    }
+
+    #[test]
+    fn settlement_is_proposed_at_attested_price_until_commit_is_due() {
+        let cfd = attested_open_cfd(Timestamp::new(1_000));
+        let delay = Duration::hours(1);
+
+        assert_eq!(
+            cfd.settle_before_commit(delay, Timestamp::new(1_000 + 60)),
+            Some(Price::new(dec!(41_000)).unwrap())
+        );
+        assert_eq!(
+            cfd.settle_before_commit(delay, Timestamp::new(1_000 + 3_600)),
+            None
+        );
+    }
+
+    #[test]
+    fn settlement_within_commit_delay_avoids_commit() {
+        let delay = Duration::hours(1);
+        let now = Timestamp::new(1_000 + 3_600);
+        let cfd = attested_open_cfd(Timestamp::new(1_000));
+        assert!(cfd.is_commit_due(delay, now));
+
+        let settled = cfd.apply(Event::new(
+            OrderId::default(),
+            CfdEvent::CollaborativeSettlementCompleted {
+                spend_tx: dummy_tx(2),
+                script: Script::new(),
+                price: Price::new(dec!(41_000)).unwrap(),
+                maker: Some(Amount::from_sat(50_000)),
+                taker: Some(Amount::from_sat(50_000)),
+            },
+        ));
+
+        assert!(settled
+            .commit_after_attestation(delay, now)
+            .unwrap()
+            .is_none());
+        assert_eq!(settled.settle_before_commit(delay, now), None);
+    }
+
+    #[test]
+    fn commit_is_due_if_settlement_does_not_confirm_in_time() {
+        let delay = Duration::hours(1);
+        let settled_at = Timestamp::new(1_000 + 60);
+        let settled = attested_open_cfd(Timestamp::new(1_000)).apply(Event {
+            timestamp: settled_at,
+            id: OrderId::default(),
+            event: CfdEvent::CollaborativeSettlementCompleted {
+                spend_tx: dummy_tx(2),
+                script: Script::new(),
+                price: Price::new(dec!(41_000)).unwrap(),
+                maker: Some(Amount::from_sat(50_000)),
+                taker: Some(Amount::from_sat(50_000)),
+            },
+        });
+        let timeout = UNCONFIRMED_SETTLEMENT_TIMEOUT.whole_seconds();
+
+        assert!(!settled.is_commit_due(delay, Timestamp::new(settled_at.seconds() + timeout - 1)));
+        assert!(settled.is_commit_due(delay, Timestamp::new(settled_at.seconds() + timeout)));
+
+        let confirmed = settled.apply(Event::new(
+            OrderId::default(),
+            CfdEvent::CollaborativeSettlementConfirmed,
+        ));
+        assert!(!confirmed.is_commit_due(delay, Timestamp::new(settled_at.seconds() + timeout)));
+    }
+
+    #[test]
+    fn published_transactions_are_unconfirmed_until_confirmed() {
+        let setup = dummy_cfd().apply(Event::new(
+            OrderId::default(),
+            CfdEvent::ContractSetupCompleted { dlc: Dlc::dummy(0) },
+        ));
+        assert_eq!(
+            setup.unconfirmed_transactions(true),
+            UnconfirmedTransactions {
+                lock: Some(dummy_tx(0)),
+                ..Default::default()
+            }
+        );
+
+        let committed = open_cfd().apply(Event::new(
+            OrderId::default(),
+            CfdEvent::ManualCommit { tx: dummy_tx(2) },
+        ));
+        assert_eq!(
+            committed.unconfirmed_transactions(true),
+            UnconfirmedTransactions {
+                commit: Some(dummy_tx(2)),
+                ..Default::default()
+            }
+        );
+
+        let cet_published = committed
+            .apply(Event::new(OrderId::default(), CfdEvent::CommitConfirmed))
+            .apply(Event::new(
+                OrderId::default(),
+                CfdEvent::OracleAttestedPostCetTimelock {
+                    cet: dummy_tx(3),
+                    price: Price::new(dec!(41_000)).unwrap(),
+                },
+            ))
+            .apply(Event::new(OrderId::default(), CfdEvent::CetPublished));
+        assert_eq!(
+            cet_published.unconfirmed_transactions(true),
+            UnconfirmedTransactions {
+                cet: Some(dummy_tx(3)),
+                ..Default::default()
+            }
+        );
+
+        let closed = cet_published.apply(Event::new(OrderId::default(), CfdEvent::CetConfirmed));
+        assert_eq!(
+            closed.unconfirmed_transactions(true),
+            UnconfirmedTransactions::default()
+        );
+    }
+
+    #[test]
+    fn commit_is_not_due_without_attestation() {
+        assert!(!dummy_cfd().is_commit_due(Duration::ZERO, Timestamp::new(1_000)));
+    }
+
+    #[test]
+    fn cfd_without_attestation_is_committed_then_refunded() {
+        let expiry = Dlc::dummy(0).settlement_event_id.timestamp;
+        let grace_period = Duration::hours(1);
+        let open = open_cfd();
+
+        assert_eq!(open.missed_attestation(expiry, grace_period), None);
+
+        let now = expiry + grace_period;
+        assert_eq!(
+            open.missed_attestation(now, grace_period),
+            Some(MissedAttestation::Uncommitted)
+        );
+
+        let committed = open.apply(Event::new(
+            OrderId::default(),
+            CfdEvent::ManualCommitAndSettle { tx: dummy_tx(0) },
+        ));
+        assert_eq!(committed.missed_attestation(now, grace_period), None);
+
+        let refundable = committed.apply(Event::new(
+            OrderId::default(),
+            CfdEvent::RefundTimelockConfirmed {
+                refund_tx: dummy_tx(2),
+            },
+        ));
+        assert_eq!(
+            refundable.missed_attestation(now, grace_period),
+            Some(MissedAttestation::Refundable)
+        );
+
+        let refunded = refundable.apply(Event::new(
+            OrderId::default(),
+            CfdEvent::ManualRefund { tx: dummy_tx(2) },
+        ));
+        assert_eq!(refunded.missed_attestation(now, grace_period), None);
+    }
+
+    #[test]
+    fn cfd_committed_by_counterparty_is_refunded_once_refund_timelock_expired() {
+        let expiry = Dlc::dummy(0).settlement_event_id.timestamp;
+        let grace_period = Duration::hours(1);
+        let now = expiry + grace_period;
+
+        let committed = open_cfd().apply(Event::new(OrderId::default(), CfdEvent::CommitConfirmed));
+        assert_eq!(committed.missed_attestation(now, grace_period), None);
+
+        let refundable = committed.apply(Event::new(
+            OrderId::default(),
+            CfdEvent::RefundTimelockConfirmed {
+                refund_tx: dummy_tx(2),
+            },
+        ));
+        assert_eq!(
+            refundable.missed_attestation(now, grace_period),
+            Some(MissedAttestation::Refundable)
+        );
+    }
+
+    #[test]
+    fn attested_cfd_did_not_miss_attestation() {
+        let expiry = Dlc::dummy(0).settlement_event_id.timestamp;
+        let grace_period = Duration::hours(1);
+
+        let attested = open_cfd().apply(Event::new(
+            OrderId::default(),
+            CfdEvent::OracleAttestedPriorCetTimelock {
+                timelocked_cet: dummy_tx(1),
+                commit_tx: dummy_tx(0),
+                price: Price::new(dec!(40_000)).unwrap(),
+            },
+        ));
+
+        assert_eq!(
+            attested.missed_attestation(expiry + Duration::days(7), grace_period),
+            None
+        );
+    }
+
+    #[test]
+    fn settlement_proposal_older_than_max_age_is_rejected_at_finalization() {
+        let cfd = open_cfd();
+        let received_at = Timestamp::new(1_000_000);
+        let max_age = Duration::minutes(10);
+        let now = Timestamp::new(received_at.seconds() + max_age.whole_seconds() + 1);
+        // The taker's timestamp must not count, otherwise a taker could keep a proposal fresh
+        let proposal = SettlementProposal {
+            order_id: cfd.id(),
+            timestamp: now,
+            taker: Amount::from_sat(50_000),
+            maker: Amount::from_sat(50_000),
+            price: Price::new(dec!(40_000)).unwrap(),
+        };
+        let sig_taker = SECP256K1.sign(
+            &secp256k1_zkp::Message::from_slice(&[4; 32]).unwrap(),
+            &SecretKey::from_slice(&[2; 32]).unwrap(),
+        );
+
+        let error = cfd
+            .start_collaborative_settlement_maker(proposal, sig_taker, received_at, max_age, now)
+            .unwrap_err();
+
+        assert!(error.to_string().contains("exceeding the maximum age"));
+    }
+
+    /// A CFD whose lock transaction is confirmed, expiring at the settlement event of
+    /// [`Dlc::dummy`].
+    fn open_cfd() -> Cfd {
+        dummy_cfd()
+            .apply(Event::new(
+                OrderId::default(),
+                CfdEvent::ContractSetupCompleted { dlc: Dlc::dummy(0) },
+            ))
+            .apply(Event::new(OrderId::default(), CfdEvent::LockConfirmed))
+    }
+
+    /// An [`open_cfd`] the oracle attested to at 41,000 prior to the CET timelock.
+    fn attested_open_cfd(timestamp: Timestamp) -> Cfd {
+        open_cfd().apply(Event {
+            timestamp,
+            id: OrderId::default(),
+            event: CfdEvent::OracleAttestedPriorCetTimelock {
+                timelocked_cet: dummy_tx(1),
+                commit_tx: dummy_tx(0),
+                price: Price::new(dec!(41_000)).unwrap(),
+            },
+        })
+    }
+
+    fn attested_prior_cet_timelock(timestamp: Timestamp) -> Cfd {
+        dummy_cfd().apply(Event {
+            timestamp,
+            id: OrderId::default(),
+            event: CfdEvent::OracleAttestedPriorCetTimelock {
+                timelocked_cet: dummy_tx(1),
+                commit_tx: dummy_tx(0),
+                price: Price::new(dec!(40_000)).unwrap(),
+            },
+        })
+    }
 }
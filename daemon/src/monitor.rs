@@ -1,14 +1,15 @@
 use crate::db;
-use crate::model;
 use crate::model::cfd;
 use crate::model::cfd::CfdEvent;
-use crate::model::cfd::Dlc;
 use crate::model::cfd::OrderId;
+use crate::model::cfd::WatchOnlyCet;
+use crate::model::cfd::WatchOnlyDlc;
 use crate::model::cfd::CET_TIMELOCK;
 use crate::model::BitMexPriceEventId;
 use crate::oracle;
 use crate::oracle::Attestation;
 use crate::try_continue;
+use crate::wallet;
 use crate::Tasks;
 use anyhow::Context;
 use anyhow::Result;
@@ -29,6 +30,7 @@ use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::fmt;
 use std::marker::Send;
+use std::num::NonZeroU32;
 use std::ops::Add;
 use std::ops::RangeInclusive;
 use std::time::Duration;
@@ -56,7 +58,7 @@ pub struct MonitorParams {
     cets: HashMap<BitMexPriceEventId, Vec<Cet>>,
     refund: (Txid, Script, u32),
     revoked_commits: Vec<(Txid, Script)>,
-    event_id: BitMexPriceEventId,
+    event_ids: Vec<BitMexPriceEventId>,
 }
 
 pub struct Sync;
@@ -70,6 +72,8 @@ pub struct Actor<C = bdk::electrum_client::Client> {
     latest_block_height: BlockHeight,
     current_status: BTreeMap<(Txid, Script), ScriptStatus>,
     awaiting_status: HashMap<(Txid, Script), Vec<(ScriptStatus, Event)>>,
+    /// Number of confirmations of the lock transaction required for [`Event::LockFinality`].
+    lock_confirmations: u32,
     tasks: Tasks,
 }
 
@@ -87,6 +91,8 @@ struct Cfd {
 
     // Ideally, all of the above would be like this.
     monitor_collaborative_settlement_finality: Option<(Txid, Script)>,
+
+    monitoring_paused: bool,
 }
 
 impl Cfd {
@@ -101,7 +107,7 @@ impl Cfd {
     fn apply(self, event: cfd::Event) -> Self {
         match event.event {
             CfdEvent::ContractSetupCompleted { dlc, .. } => Self {
-                params: Some(MonitorParams::new(dlc)),
+                params: Some(MonitorParams::new(dlc.watch_only())),
                 monitor_lock_finality: true,
                 monitor_commit_finality: true,
                 monitor_cet_timelock: true,
@@ -109,10 +115,11 @@ impl Cfd {
                 monitor_refund_finality: true,
                 monitor_revoked_commit_transactions: false,
                 monitor_collaborative_settlement_finality: None,
+                monitoring_paused: false,
             },
             CfdEvent::RolloverCompleted { dlc } => {
                 Self {
-                    params: Some(MonitorParams::new(dlc)),
+                    params: Some(MonitorParams::new(dlc.watch_only())),
                     monitor_lock_finality: false, // Lock is already final after rollover.
                     monitor_commit_finality: true,
                     monitor_cet_timelock: true,
@@ -121,6 +128,7 @@ impl Cfd {
                     monitor_revoked_commit_transactions: true, /* After rollover, the other party
                                                                 * might publish old states. */
                     monitor_collaborative_settlement_finality: None,
+                    ..self
                 }
             }
             CfdEvent::CollaborativeSettlementCompleted {
@@ -133,7 +141,7 @@ impl Cfd {
                     ..self
                 }
             }
-            CfdEvent::ContractSetupFailed
+            CfdEvent::ContractSetupFailed { .. }
             | CfdEvent::OfferRejected
             | CfdEvent::RolloverRejected => {
                 Self::default() // all false / empty
@@ -161,11 +169,26 @@ impl Cfd {
             },
             CfdEvent::RolloverFailed
             | CfdEvent::ManualCommit { .. }
+            | CfdEvent::ManualCommitAndSettle { .. }
+            | CfdEvent::ManualRefund { .. }
             | CfdEvent::OracleAttestedPostCetTimelock { .. }
             | CfdEvent::OracleAttestedPriorCetTimelock { .. }
+            | CfdEvent::CetPublished
             | CfdEvent::CollaborativeSettlementRejected { .. }
-            | CfdEvent::CollaborativeSettlementFailed { .. } => self,
-            CfdEvent::RevokeConfirmed => todo!("Deal with revoked"),
+            | CfdEvent::CollaborativeSettlementFailed { .. }
+            | CfdEvent::ExitLevelsSet { .. }
+            | CfdEvent::Archived => self,
+            // The lock output was spent by a revoked commit transaction, none of the
+            // transactions we monitor can confirm anymore
+            CfdEvent::RevokeConfirmed => Self::default(),
+            CfdEvent::MonitoringPaused => Self {
+                monitoring_paused: true,
+                ..self
+            },
+            CfdEvent::MonitoringResumed => Self {
+                monitoring_paused: false,
+                ..self
+            },
         }
     }
 }
@@ -174,10 +197,12 @@ impl Actor<bdk::electrum_client::Client> {
     pub async fn new(
         db: SqlitePool,
         electrum_rpc_url: String,
+        electrum_timeout: Duration,
+        electrum_tls: &wallet::ElectrumTls,
+        lock_confirmations: NonZeroU32,
         event_channel: Box<dyn StrongMessageChannel<Event>>,
     ) -> Result<Self> {
-        let client = bdk::electrum_client::Client::new(&electrum_rpc_url)
-            .context("Failed to initialize Electrum RPC client")?;
+        let client = wallet::electrum_client(&electrum_rpc_url, electrum_timeout, electrum_tls)?;
 
         // Initially fetch the latest block for storing the height.
         // We do not act on this subscription after this call.
@@ -192,6 +217,7 @@ impl Actor<bdk::electrum_client::Client> {
             latest_block_height: BlockHeight::try_from(latest_block)?,
             current_status: BTreeMap::default(),
             awaiting_status: HashMap::default(),
+            lock_confirmations: lock_confirmations.get(),
             tasks: Tasks::default(),
         };
 
@@ -209,6 +235,7 @@ impl Actor<bdk::electrum_client::Client> {
                 monitor_refund_finality,
                 monitor_revoked_commit_transactions,
                 monitor_collaborative_settlement_finality,
+                monitoring_paused,
             } = events.into_iter().fold(Cfd::default(), Cfd::apply);
 
             let params = match params {
@@ -216,6 +243,11 @@ impl Actor<bdk::electrum_client::Client> {
                 Some(params) => params,
             };
 
+            if monitoring_paused {
+                tracing::info!(order_id=%id, "Monitoring paused, not monitoring CFD");
+                continue;
+            }
+
             actor.cfds.insert(id, params.clone());
 
             if monitor_lock_finality {
@@ -265,10 +297,12 @@ where
     }
 
     fn monitor_lock_finality(&mut self, params: &MonitorParams, order_id: OrderId) {
+        let target = ScriptStatus::with_confirmations(self.lock_confirmations);
+
         self.awaiting_status
             .entry((params.lock.0, params.lock.1.script_pubkey()))
             .or_default()
-            .push((ScriptStatus::finality(), Event::LockFinality(order_id)));
+            .push((target, Event::LockFinality(order_id)));
     }
 
     fn monitor_commit_finality(&mut self, params: &MonitorParams, order_id: OrderId) {
@@ -415,7 +449,7 @@ where
             .cfds
             .clone()
             .into_iter()
-            .filter(|(_, params)| params.event_id == attestation.id)
+            .filter(|(_, params)| params.event_ids.contains(&attestation.id))
         {
             try_continue!(self.monitor_cet_finality(cets, attestation.clone(), order_id))
         }
@@ -651,19 +685,25 @@ impl Event {
 }
 
 impl MonitorParams {
-    pub fn new(dlc: Dlc) -> Self {
-        let script_pubkey = dlc.maker_address.script_pubkey();
+    /// Only needs the public part of a DLC, see [`cfd::Dlc::watch_only`].
+    pub fn new(dlc: WatchOnlyDlc) -> Self {
+        let WatchOnlyDlc {
+            lock,
+            commit,
+            cets,
+            refund: (refund_txid, refund_script),
+            refund_timelock,
+            revoked_commits,
+            event_ids,
+        } = dlc;
+
         MonitorParams {
-            lock: (dlc.lock.0.txid(), dlc.lock.1),
-            commit: (dlc.commit.0.txid(), dlc.commit.2),
-            cets: map_cets(dlc.cets),
-            refund: (dlc.refund.0.txid(), script_pubkey, dlc.refund_timelock),
-            revoked_commits: dlc
-                .revoked_commit
-                .iter()
-                .map(|rev_commit| (rev_commit.txid, rev_commit.script_pubkey.clone()))
-                .collect(),
-            event_id: dlc.settlement_event_id,
+            lock,
+            commit,
+            cets: map_cets(cets),
+            refund: (refund_txid, refund_script, refund_timelock),
+            revoked_commits,
+            event_ids,
         }
     }
 }
@@ -675,18 +715,18 @@ struct Cet {
     range: RangeInclusive<u64>,
 }
 
-impl From<model::cfd::Cet> for Cet {
-    fn from(cet: model::cfd::Cet) -> Self {
+impl From<WatchOnlyCet> for Cet {
+    fn from(cet: WatchOnlyCet) -> Self {
         Cet {
-            txid: cet.tx.txid(),
-            script: cet.tx.output[0].script_pubkey.clone(),
-            range: cet.range.clone(),
+            txid: cet.txid,
+            script: cet.script_pubkey,
+            range: cet.range,
         }
     }
 }
 
 fn map_cets(
-    cets: HashMap<BitMexPriceEventId, Vec<model::cfd::Cet>>,
+    cets: HashMap<BitMexPriceEventId, Vec<WatchOnlyCet>>,
 ) -> HashMap<BitMexPriceEventId, Vec<Cet>> {
     cets.into_iter()
         .map(|(event_id, cets)| {
@@ -898,6 +938,59 @@ mod tests {
         assert!(monitor.awaiting_status.is_empty());
     }
 
+    #[tokio::test]
+    async fn lock_is_not_final_before_reaching_required_confirmations() {
+        let _guard = tracing_subscriber::fmt()
+            .with_env_filter("trace")
+            .with_test_writer()
+            .set_default();
+
+        let (recorder_address, mut recorder_context) =
+            xtra::Context::<MessageRecordingActor>::new(None);
+        let mut recorder = MessageRecordingActor::default();
+
+        let order_id = OrderId::default();
+        let mut monitor = Actor::for_test(Box::new(recorder_address), []);
+        monitor.lock_confirmations = 3;
+        monitor.monitor_lock_finality(&params_with_lock(txid1()), order_id);
+        monitor.client.include_tx(txid1(), 5);
+
+        monitor.client.advance_to_height(6);
+        recorder_context
+            .handle_while(&mut recorder, monitor.sync())
+            .await
+            .unwrap();
+
+        assert!(
+            recorder.events.is_empty(),
+            "lock with 2 confirmations not to be final"
+        );
+
+        monitor.client.advance_to_height(7);
+        recorder_context
+            .handle_while(&mut recorder, monitor.sync())
+            .await
+            .unwrap();
+
+        assert_eq!(recorder.events, vec![Event::LockFinality(order_id)]);
+    }
+
+    /// Parameters of a CFD whose lock transaction is `lock_txid`, not usable for anything else.
+    fn params_with_lock(lock_txid: Txid) -> MonitorParams {
+        let descriptor = "wsh(multi(2,0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798,02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5))"
+            .parse::<Descriptor<PublicKey>>()
+            .unwrap();
+
+        MonitorParams {
+            lock: (lock_txid, descriptor.clone()),
+            commit: (txid2(), descriptor),
+            cets: HashMap::default(),
+            refund: (txid2(), script1(), 0),
+            revoked_commits: Vec::new(),
+            event_ids: Vec::new(),
+        }
+    }
+
     impl Actor<stub::Client> {
         #[allow(clippy::type_complexity)]
         fn for_test<const N: usize>(
@@ -911,6 +1004,7 @@ mod tests {
                 latest_block_height: BlockHeight(0),
                 current_status: BTreeMap::default(),
                 awaiting_status: HashMap::from_iter(subscriptions),
+                lock_confirmations: FINALITY_CONFIRMATIONS,
                 tasks: Tasks::default(),
             }
         }
@@ -1,19 +1,26 @@
+use crate::cfd_actors::load_cfd;
 use crate::db;
 use crate::model::cfd::CfdEvent;
 use crate::model::cfd::Event;
 use crate::model::BitMexPriceEventId;
+use crate::model::Timestamp;
+use crate::projection;
 use crate::tokio_ext;
 use crate::try_continue;
 use crate::xtra_ext::LogFailure;
 use crate::Tasks;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
 use maia::secp256k1_zkp::schnorrsig;
 use maia::secp256k1_zkp::SecretKey;
+use reqwest::StatusCode;
+use reqwest::Url;
 use rocket::time::OffsetDateTime;
 use rocket::time::Time;
 use serde::Deserialize;
+use serde::Serialize;
 use sqlx::SqlitePool;
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -21,18 +28,91 @@ use std::ops::Add;
 use time::ext::NumericalDuration;
 use time::Duration;
 use xtra::prelude::StrongMessageChannel;
+use xtra::Address;
 use xtra_productivity::xtra_productivity;
 
+/// The public instance of the Olivia oracle.
+pub const OLIVIA_URL: &str = "https://h00.ooo";
+
+/// How often the oracle is polled for announcements and attestations by default.
+pub const SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How often we probe the oracle to find out whether it is reachable.
+const HEALTH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The oracle is deemed unavailable if it could not be reached for this long.
+const MAX_TIME_SINCE_LAST_HEALTH_CHECK: Duration = Duration::minutes(3);
+
+/// How often the events of open CFDs are checked against the oracle unless configured otherwise.
+pub const EVENT_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// How long to wait for the oracle to answer whether it serves the event of a CFD.
+const EVENT_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// What to do about CFDs settled by an event the oracle does not serve.
+///
+/// The oracle will never attest to such an event, so the CFD can only be refunded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnknownEventPolicy {
+    /// Check the events of all open CFDs against the oracle at `oracle_url` every
+    /// `check_interval` and flag the CFDs whose event is unavailable, see
+    /// [`check_events_periodically`].
+    Warn {
+        oracle_url: Url,
+        check_interval: std::time::Duration,
+    },
+    /// Do not check the events, CFDs are never flagged.
+    Ignore,
+}
+
+impl UnknownEventPolicy {
+    pub fn from_ignore(ignore: bool, oracle_url: Url) -> Self {
+        if ignore {
+            Self::Ignore
+        } else {
+            Self::Warn {
+                oracle_url,
+                check_interval: EVENT_CHECK_INTERVAL,
+            }
+        }
+    }
+}
+
 pub struct Actor {
     announcements: HashMap<BitMexPriceEventId, (OffsetDateTime, Vec<schnorrsig::PublicKey>)>,
     pending_attestations: HashSet<BitMexPriceEventId>,
     attestation_channel: Box<dyn StrongMessageChannel<Attestation>>,
     announcement_lookahead: Duration,
+    url: Url,
+    last_health_check: Option<HealthCheck>,
     tasks: Tasks,
 }
 
+/// Fetch missing announcements and the attestations of events that likely occurred.
+///
+/// Sent periodically by [`sync_periodically`].
 pub struct Sync;
 
+/// Message used to request the health of the oracle as observed by the
+/// periodic health check of the `oracle::Actor`.
+#[derive(Debug, Clone, Copy)]
+pub struct GetHealth;
+
+/// The outcome of the most recent successful health check.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Health {
+    pub last_reached: Timestamp,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Unavailable {
+    #[error("Oracle has not been reached yet")]
+    NeverReached,
+    #[error("Oracle has not been reached since {}", .0.seconds())]
+    NotReachedSince(Timestamp),
+}
+
 pub struct MonitorAttestation {
     pub event_id: BitMexPriceEventId,
 }
@@ -69,16 +149,32 @@ struct NewAttestationFetched {
     attestation: Attestation,
 }
 
+/// A module-private message to trigger a health check of the oracle.
+#[derive(Debug)]
+struct CheckHealth;
+
+/// A module-private message to record a successful health check.
+#[derive(Debug)]
+struct HealthCheckSucceeded {
+    latency: std::time::Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HealthCheck {
+    timestamp: OffsetDateTime,
+    latency: std::time::Duration,
+}
+
 #[derive(Default)]
 struct Cfd {
-    pending_attestation: Option<BitMexPriceEventId>,
+    pending_attestations: Vec<BitMexPriceEventId>,
 }
 
 impl Cfd {
     fn apply(self, event: Event) -> Self {
-        let settlement_event_id = match event.event {
-            CfdEvent::ContractSetupCompleted { dlc } => dlc.settlement_event_id,
-            CfdEvent::RolloverCompleted { dlc } => dlc.settlement_event_id,
+        let event_ids = match event.event {
+            CfdEvent::ContractSetupCompleted { dlc } => dlc.event_ids(),
+            CfdEvent::RolloverCompleted { dlc } => dlc.event_ids(),
             // TODO: There might be a few cases where we do not need to monitor the attestation,
             // e.g. when we already agreed to collab. settle. Ignoring it for now
             // because I don't want to think about it and it doesn't cause much harm to do the
@@ -89,7 +185,7 @@ impl Cfd {
         // we can comfortably overwrite what was there because events are processed in order, thus
         // old attestations don't matter.
         Self {
-            pending_attestation: Some(settlement_event_id),
+            pending_attestations: event_ids,
         }
     }
 }
@@ -99,6 +195,7 @@ impl Actor {
         db: SqlitePool,
         attestation_channel: Box<dyn StrongMessageChannel<Attestation>>,
         announcement_lookahead: Duration,
+        url: Url,
     ) -> Result<Self> {
         let mut pending_attestations = HashSet::new();
 
@@ -110,9 +207,7 @@ impl Actor {
                 .into_iter()
                 .fold(Cfd::default(), |cfd, event| cfd.apply(event));
 
-            if let Some(pending_attestation) = cfd.pending_attestation {
-                pending_attestations.insert(pending_attestation);
-            }
+            pending_attestations.extend(cfd.pending_attestations);
         }
 
         Ok(Self {
@@ -120,6 +215,8 @@ impl Actor {
             pending_attestations,
             attestation_channel,
             announcement_lookahead,
+            url,
+            last_health_check: None,
             tasks: Tasks::default(),
         })
     }
@@ -129,34 +226,17 @@ impl Actor {
         announcement_lookahead: Duration,
         ctx: &mut xtra::Context<Self>,
     ) {
-        // we want inclusive the settlement_time_interval_hours length hence +1
-        for hour in 1..announcement_lookahead.whole_hours() + 1 {
-            let event_id = try_continue!(next_announcement_after(
-                time::OffsetDateTime::now_utc() + Duration::hours(hour)
-            ));
-
+        for event_id in
+            announcements_within(time::OffsetDateTime::now_utc(), announcement_lookahead)
+        {
             if self.announcements.get(&event_id).is_some() {
                 continue;
             }
             let this = ctx.address().expect("self to be alive");
+            let oracle = self.url.clone();
 
             tokio_ext::spawn_fallible(async move {
-                let url = event_id.to_olivia_url();
-
-                tracing::debug!("Fetching announcement for {}", event_id);
-
-                let response = reqwest::get(url.clone())
-                    .await
-                    .with_context(|| format!("Failed to GET {}", url))?;
-
-                if !response.status().is_success() {
-                    anyhow::bail!("GET {} responded with {}", url, response.status());
-                }
-
-                let announcement = response
-                    .json::<Announcement>()
-                    .await
-                    .context("Failed to deserialize as Announcement")?;
+                let announcement = fetch_announcement(&oracle, event_id).await?;
 
                 this.send(NewAnnouncementFetched {
                     id: event_id,
@@ -182,10 +262,9 @@ impl Actor {
             }
 
             let this = ctx.address().expect("self to be alive");
+            let url = event_id.to_olivia_url(&self.url);
 
             tokio_ext::spawn_fallible(async move {
-                let url = event_id.to_olivia_url();
-
                 tracing::debug!("Fetching attestation for {}", event_id);
 
                 let response = reqwest::get(url.clone())
@@ -213,6 +292,36 @@ impl Actor {
         }
     }
 
+    fn check_health(&self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("self to be alive");
+        let oracle = self.url.clone();
+
+        tokio_ext::spawn_fallible(async move {
+            let event_id = next_announcement_after(OffsetDateTime::now_utc())?;
+            let url = event_id.to_olivia_url(&oracle);
+
+            let started_at = std::time::Instant::now();
+            let response = reqwest::get(url.clone())
+                .await
+                .with_context(|| format!("Oracle health check failed to GET {}", url))?;
+
+            if !response.status().is_success() {
+                anyhow::bail!(
+                    "Oracle health check: GET {} responded with {}",
+                    url,
+                    response.status()
+                );
+            }
+
+            this.send(HealthCheckSucceeded {
+                latency: started_at.elapsed(),
+            })
+            .await?;
+
+            Ok(())
+        });
+    }
+
     async fn handle_new_attestation_fetched(
         &mut self,
         id: BitMexPriceEventId,
@@ -267,6 +376,42 @@ impl Actor {
         self.ensure_having_announcements(self.announcement_lookahead, ctx);
         self.update_pending_attestations(ctx);
     }
+
+    fn handle_check_health(&mut self, _: CheckHealth, ctx: &mut xtra::Context<Self>) {
+        self.check_health(ctx);
+    }
+
+    fn handle_health_check_succeeded(
+        &mut self,
+        msg: HealthCheckSucceeded,
+        _ctx: &mut xtra::Context<Self>,
+    ) {
+        tracing::trace!(latency = ?msg.latency, "Oracle is reachable");
+
+        self.last_health_check = Some(HealthCheck {
+            timestamp: OffsetDateTime::now_utc(),
+            latency: msg.latency,
+        });
+    }
+
+    fn handle_get_health(
+        &mut self,
+        _: GetHealth,
+        _ctx: &mut xtra::Context<Self>,
+    ) -> Result<Health, Unavailable> {
+        let HealthCheck { timestamp, latency } =
+            self.last_health_check.ok_or(Unavailable::NeverReached)?;
+        let last_reached = Timestamp::new(timestamp.unix_timestamp());
+
+        if OffsetDateTime::now_utc() - timestamp > MAX_TIME_SINCE_LAST_HEALTH_CHECK {
+            return Err(Unavailable::NotReachedSince(last_reached));
+        }
+
+        Ok(Health {
+            last_reached,
+            latency_ms: latency.as_millis() as u64,
+        })
+    }
 }
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -285,6 +430,127 @@ impl xtra::Handler<NewAttestationFetched> for Actor {
     }
 }
 
+/// Fetch the announcement of `event_id` from the oracle at `oracle`.
+async fn fetch_announcement(oracle: &Url, event_id: BitMexPriceEventId) -> Result<Announcement> {
+    tracing::debug!("Fetching announcement for {}", event_id);
+
+    let url = event_id.to_olivia_url(oracle);
+    let response = reqwest::get(url.clone())
+        .await
+        .with_context(|| format!("Failed to GET {}", url))?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("GET {} responded with {}", url, response.status());
+    }
+
+    let announcement = response
+        .json::<Announcement>()
+        .await
+        .context("Failed to deserialize as Announcement")?;
+
+    Ok(announcement)
+}
+
+/// Whether the oracle at `oracle` serves the announcement of `event_id`.
+///
+/// Only a `404 Not Found` tells that it does not, failing to reach the oracle or any other
+/// response is an error.
+async fn is_event_served(oracle: &Url, event_id: BitMexPriceEventId) -> Result<bool> {
+    let url = event_id.to_olivia_url(oracle);
+    let response = reqwest::Client::new()
+        .get(url.clone())
+        .timeout(EVENT_CHECK_TIMEOUT)
+        .send()
+        .await
+        .with_context(|| format!("Failed to GET {}", url))?;
+
+    match response.status() {
+        StatusCode::NOT_FOUND => Ok(false),
+        status if status.is_success() => Ok(true),
+        status => anyhow::bail!("GET {} responded with {}", url, status),
+    }
+}
+
+/// The ids of the announcements we expect to be published within `lookahead` from `now`.
+fn announcements_within(now: OffsetDateTime, lookahead: Duration) -> Vec<BitMexPriceEventId> {
+    let mut event_ids = Vec::new();
+
+    // we want inclusive the settlement_time_interval_hours length hence +1
+    for hour in 1..lookahead.whole_hours() + 1 {
+        let event_id = try_continue!(next_announcement_after(now + Duration::hours(hour)));
+        event_ids.push(event_id);
+    }
+
+    event_ids
+}
+
+/// How the maker picks the oracle event that CFDs taken from its orders settle on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettlementEventSelection {
+    /// The first announcement after the settlement interval.
+    AfterInterval,
+    /// Out of the announcements every `hours` hours from midnight UTC, e.g. at 00:00, 08:00 and
+    /// 16:00 for a cadence of 8 hours, the one closest to the end of the settlement interval.
+    Cadence { hours: u8 },
+}
+
+impl SettlementEventSelection {
+    /// Without a cadence, CFDs settle on the first announcement after the settlement interval.
+    pub fn from_cadence(hours: Option<u8>) -> Result<Self> {
+        let hours = match hours {
+            None => return Ok(Self::AfterInterval),
+            Some(hours) => hours,
+        };
+
+        ensure!(
+            hours > 0 && 24 % hours == 0,
+            "Settlement cadence must divide a day into equal parts, got {} hours",
+            hours
+        );
+
+        Ok(Self::Cadence { hours })
+    }
+
+    /// Select the event a CFD opened at `now` settles on.
+    ///
+    /// Only announcements within `lookahead` from `now` are fetched from the oracle, events after
+    /// that are refused.
+    pub fn select(
+        &self,
+        now: OffsetDateTime,
+        settlement_interval: Duration,
+        lookahead: Duration,
+    ) -> Result<BitMexPriceEventId> {
+        let announcements = announcements_within(now, lookahead);
+        let target = now + settlement_interval;
+
+        let event_id = match *self {
+            Self::AfterInterval => next_announcement_after(target)?,
+            Self::Cadence { hours } => announcements
+                .iter()
+                .filter(|event_id| event_id.timestamp().hour() % hours == 0)
+                .min_by_key(|event_id| (event_id.timestamp() - target).abs())
+                .copied()
+                .with_context(|| {
+                    format!(
+                        "No announcement within {} hours matches the cadence of {} hours",
+                        lookahead.whole_hours(),
+                        hours
+                    )
+                })?,
+        };
+
+        ensure!(
+            announcements.contains(&event_id),
+            "Event {} is beyond the announcement lookahead of {} hours",
+            event_id,
+            lookahead.whole_hours()
+        );
+
+        Ok(event_id)
+    }
+}
+
 pub fn next_announcement_after(timestamp: OffsetDateTime) -> Result<BitMexPriceEventId> {
     let adjusted = ceil_to_next_hour(timestamp)?;
 
@@ -321,13 +587,112 @@ impl From<Announcement> for maia::Announcement {
     }
 }
 
+/// Polls the oracle actor at `address` every `interval`, starting right away, until it stops.
+pub async fn sync_periodically<A>(address: Address<A>, interval: std::time::Duration)
+where
+    A: xtra::Handler<Sync>,
+{
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        ticker.tick().await;
+
+        if address.send(Sync).await.is_err() {
+            tracing::debug!("Oracle actor stopped, no longer syncing");
+            return;
+        }
+    }
+}
+
+/// Checks every `interval` whether the oracle at `oracle` serves the events of the CFDs in `db`,
+/// until the projection actor stops.
+///
+/// The unavailable events are reported to the projection, which flags the CFDs settled by them.
+/// The oracle is queried directly rather than through the [`Actor`] so that a slow oracle does
+/// not hold up the actor.
+pub async fn check_events_periodically(
+    db: SqlitePool,
+    oracle: Url,
+    projection: Address<projection::Actor>,
+    interval: std::time::Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    let mut unavailable = HashSet::new();
+
+    loop {
+        ticker.tick().await;
+
+        unavailable = match unavailable_events(&db, &oracle, &unavailable).await {
+            Ok(unavailable) => unavailable,
+            Err(e) => {
+                tracing::warn!("Failed to check oracle events: {:#}", e);
+                continue;
+            }
+        };
+
+        if projection
+            .send(projection::Update(projection::UnavailableOracleEvents(
+                unavailable.clone(),
+            )))
+            .await
+            .is_err()
+        {
+            tracing::debug!("Projection actor stopped, no longer checking oracle events");
+            return;
+        }
+    }
+}
+
+/// The events of CFDs awaiting an attestation that the oracle does not serve.
+///
+/// Events that cannot be checked keep the availability they had in `previously_unavailable`.
+async fn unavailable_events(
+    db: &SqlitePool,
+    oracle: &Url,
+    previously_unavailable: &HashSet<BitMexPriceEventId>,
+) -> Result<HashSet<BitMexPriceEventId>> {
+    let mut conn = db.acquire().await?;
+    let mut checked = HashSet::new();
+    let mut unavailable = HashSet::new();
+
+    for id in db::load_all_cfd_ids(&mut conn).await? {
+        let cfd = try_continue!(load_cfd(id, &mut conn).await);
+        let event_id = match cfd.pending_settlement_event_id() {
+            Some(event_id) => event_id,
+            None => continue,
+        };
+
+        if checked.insert(event_id) {
+            match is_event_served(oracle, event_id).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    unavailable.insert(event_id);
+                }
+                Err(e) => {
+                    tracing::debug!(%event_id, "Failed to check oracle event: {:#}", e);
+
+                    if previously_unavailable.contains(&event_id) {
+                        unavailable.insert(event_id);
+                    }
+                }
+            }
+        }
+
+        if unavailable.contains(&event_id) {
+            tracing::warn!(order_id = %id, %event_id, "Oracle does not serve the event of CFD, it can only be refunded");
+        }
+    }
+
+    Ok(unavailable)
+}
+
 #[async_trait]
 impl xtra::Actor for Actor {
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        self.check_health(ctx);
         let fut = ctx
-            .notify_interval(std::time::Duration::from_secs(5), || Sync)
+            .notify_interval(HEALTH_CHECK_INTERVAL, || CheckHealth)
             .expect("we just started");
-
         self.tasks.add(fut);
     }
 }
@@ -624,6 +989,103 @@ mod tests {
         );
     }
 
+    #[test]
+    fn announcements_within_lookahead() {
+        let event_ids =
+            announcements_within(datetime!(2021-09-23 10:40:00).assume_utc(), 3.hours());
+
+        assert_eq!(
+            event_ids,
+            vec![
+                BitMexPriceEventId::with_20_digits(datetime!(2021-09-23 12:00:00).assume_utc()),
+                BitMexPriceEventId::with_20_digits(datetime!(2021-09-23 13:00:00).assume_utc()),
+                BitMexPriceEventId::with_20_digits(datetime!(2021-09-23 14:00:00).assume_utc()),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_announcements_within_empty_lookahead() {
+        let event_ids =
+            announcements_within(datetime!(2021-09-23 10:40:00).assume_utc(), Duration::ZERO);
+
+        assert!(event_ids.is_empty());
+    }
+
+    #[test]
+    fn after_interval_selects_next_announcement_after_interval() {
+        let event_id = SettlementEventSelection::AfterInterval
+            .select(
+                datetime!(2021-09-23 10:40:00).assume_utc(),
+                24.hours(),
+                24.hours(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            event_id,
+            BitMexPriceEventId::with_20_digits(datetime!(2021-09-24 11:00:00).assume_utc())
+        );
+    }
+
+    #[test]
+    fn cadence_selects_closest_announcement_within_lookahead() {
+        let event_id = SettlementEventSelection::Cadence { hours: 8 }
+            .select(
+                datetime!(2021-09-23 13:40:00).assume_utc(),
+                24.hours(),
+                24.hours(),
+            )
+            .unwrap();
+
+        // 16:00 would be closer to the end of the interval but is beyond the lookahead
+        assert_eq!(
+            event_id,
+            BitMexPriceEventId::with_20_digits(datetime!(2021-09-24 08:00:00).assume_utc())
+        );
+    }
+
+    #[test]
+    fn cadence_selects_announcement_after_interval_if_closer() {
+        let event_id = SettlementEventSelection::Cadence { hours: 6 }
+            .select(
+                datetime!(2021-09-23 10:40:00).assume_utc(),
+                13.hours(),
+                24.hours(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            event_id,
+            BitMexPriceEventId::with_20_digits(datetime!(2021-09-24 00:00:00).assume_utc())
+        );
+    }
+
+    #[test]
+    fn event_beyond_lookahead_is_refused() {
+        let result = SettlementEventSelection::AfterInterval.select(
+            datetime!(2021-09-23 10:40:00).assume_utc(),
+            25.hours(),
+            24.hours(),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn cadence_must_divide_a_day() {
+        assert_eq!(
+            SettlementEventSelection::from_cadence(None).unwrap(),
+            SettlementEventSelection::AfterInterval
+        );
+        assert_eq!(
+            SettlementEventSelection::from_cadence(Some(8)).unwrap(),
+            SettlementEventSelection::Cadence { hours: 8 }
+        );
+        assert!(SettlementEventSelection::from_cadence(Some(0)).is_err());
+        assert!(SettlementEventSelection::from_cadence(Some(5)).is_err());
+    }
+
     #[test]
     fn next_event_id_is_midnight_next_day() {
         let event_id =
@@ -2,6 +2,7 @@ use crate::model::Leverage;
 use crate::model::Price;
 use crate::model::Usd;
 use crate::payout_curve::curve::Curve;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use bdk::bitcoin;
@@ -62,6 +63,31 @@ pub fn calculate(
     Ok(payouts)
 }
 
+/// Ensure a contract with the given parameters does not need more than `max_cets` CETs.
+///
+/// Every payout results in one CET per oracle event. Too many CETs blow up the size of the DLC
+/// and the time needed for contract setup, hence we refuse such contracts before setting them up.
+pub fn ensure_within_cet_limit(
+    price: Price,
+    quantity: Usd,
+    leverage: Leverage,
+    n_payouts: usize,
+    max_cets: usize,
+) -> Result<()> {
+    let n_cets = calculate(price, quantity, leverage, n_payouts)?.len();
+
+    if n_cets > max_cets {
+        bail!(
+            "Contract would need {} CETs which exceeds the maximum of {}, use fewer payouts than {}",
+            n_cets,
+            max_cets,
+            n_payouts
+        );
+    }
+
+    Ok(())
+}
+
 const CONTRACT_VALUE: f64 = 1.;
 const SHORT_LEVERAGE: usize = 1;
 
@@ -727,6 +753,31 @@ mod tests {
         pretty_assertions::assert_eq!(actual_payouts, expected_payouts);
     }
 
+    #[test]
+    fn contract_within_cet_limit_is_accepted() {
+        ensure_within_cet_limit(
+            Price::new(dec!(54000.00)).unwrap(),
+            Usd::new(dec!(3500.00)),
+            Leverage::new(5).unwrap(),
+            200,
+            crate::MAX_CETS,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn contract_exceeding_cet_limit_is_rejected() {
+        let result = ensure_within_cet_limit(
+            Price::new(dec!(54000.00)).unwrap(),
+            Usd::new(dec!(3500.00)),
+            Leverage::new(5).unwrap(),
+            200,
+            100,
+        );
+
+        assert!(result.is_err());
+    }
+
     #[test]
     fn verify_effect_of_funding_fee() {
         let payouts = calculate_payout_parameters(
@@ -0,0 +1,497 @@
+use crate::bitmex_price_feed;
+use crate::model::cfd::Role;
+use crate::model::Price;
+use crate::model::Timestamp;
+use crate::projection;
+use crate::supervisor;
+use crate::Tasks;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::Stream;
+use futures::TryStreamExt;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use xtra::prelude::MessageChannel;
+use xtra_productivity::xtra_productivity;
+
+/// How often the simulated price source quotes its price.
+const SIMULATED_QUOTE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The quotes of a [`PriceSource`], ending or failing once the connection to it is lost.
+pub type Quotes = Pin<Box<dyn Stream<Item = Result<Quote>> + Send>>;
+
+/// A source of BTC/USD quotes, e.g. an exchange.
+#[async_trait]
+pub trait PriceSource: Send + Sync + 'static {
+    /// The name of the source used in logs and in the status of the price feed.
+    fn name(&self) -> &'static str;
+
+    /// Connect to the source to receive its quotes.
+    async fn connect(&self) -> Result<Quotes>;
+}
+
+/// The price sources the daemon can be configured with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SourceConfig {
+    /// The XBTUSD perpetual swap on BitMex.
+    BitMex,
+    /// A constant price, for testing without access to an exchange.
+    Simulated { price: Price },
+}
+
+impl SourceConfig {
+    pub fn into_source(self) -> Arc<dyn PriceSource> {
+        match self {
+            SourceConfig::BitMex => Arc::new(bitmex_price_feed::BitMex),
+            SourceConfig::Simulated { price } => {
+                Arc::new(Simulated::new(price, SIMULATED_QUOTE_INTERVAL))
+            }
+        }
+    }
+}
+
+impl FromStr for SourceConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "bitmex" => Ok(SourceConfig::BitMex),
+            Some(("simulated", price)) => Ok(SourceConfig::Simulated {
+                price: price.parse()?,
+            }),
+            _ => anyhow::bail!(
+                "Unknown price source {}, expected bitmex or simulated:<price>",
+                s
+            ),
+        }
+    }
+}
+
+/// Forwards the quotes of a [`PriceSource`], stopping once the connection to it is lost.
+pub struct Actor {
+    source: Arc<dyn PriceSource>,
+    tasks: Tasks,
+    receiver: Box<dyn MessageChannel<projection::Update<Quote>>>,
+    status_receiver: Box<dyn MessageChannel<projection::Update<Status>>>,
+    supervisor: xtra::Address<supervisor::Actor<Self, StopReason>>,
+}
+
+impl Actor {
+    pub fn new(
+        source: Arc<dyn PriceSource>,
+        receiver: impl MessageChannel<projection::Update<Quote>> + 'static,
+        status_receiver: impl MessageChannel<projection::Update<Status>> + 'static,
+        supervisor: xtra::Address<supervisor::Actor<Self, StopReason>>,
+    ) -> Self {
+        Self {
+            source,
+            tasks: Tasks::default(),
+            receiver: Box::new(receiver),
+            status_receiver: Box::new(status_receiver),
+            supervisor,
+        }
+    }
+}
+
+/// Whether we are currently receiving quotes from the price source.
+///
+/// Without quotes CFDs can neither be priced nor settled, so this is surfaced to the user.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Online,
+    Offline { reason: Option<String> },
+}
+
+impl From<&StopReason> for Status {
+    fn from(reason: &StopReason) -> Self {
+        Status::Offline {
+            reason: Some(reason.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        self.tasks.add({
+            let this = ctx.address().expect("we are alive");
+            let source = self.source.clone();
+            let receiver = self.receiver.clone_channel();
+            let status_receiver = self.status_receiver.clone_channel();
+
+            async move {
+                let price_source = source.name();
+
+                tracing::debug!("Connecting to {}", price_source);
+
+                let mut quotes = match source.connect().await {
+                    Ok(quotes) => quotes,
+                    Err(error) => {
+                        let _ = this
+                            .send(StopReason::FailedToConnect {
+                                price_source,
+                                error,
+                            })
+                            .await;
+                        return;
+                    }
+                };
+
+                tracing::info!("Connected to {}", price_source);
+
+                if status_receiver
+                    .send(projection::Update(Status::Online))
+                    .await
+                    .is_err()
+                {
+                    return; // if the receiver dies, our job is done
+                }
+
+                loop {
+                    match quotes.try_next().await {
+                        Ok(Some(quote)) => {
+                            if receiver.send(projection::Update(quote)).await.is_err() {
+                                return; // if the receiver dies, our job is done
+                            }
+                        }
+                        Ok(None) => {
+                            let _ = this.send(StopReason::StreamEnded { price_source }).await;
+                            return;
+                        }
+                        Err(error) => {
+                            let _ = this
+                                .send(StopReason::Failed {
+                                    price_source,
+                                    error,
+                                })
+                                .await;
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, msg: StopReason, ctx: &mut xtra::Context<Self>) {
+        let _ = self
+            .status_receiver
+            .send(projection::Update(Status::from(&msg)))
+            .await;
+        let _ = self
+            .supervisor
+            .send(supervisor::Stopped { reason: msg })
+            .await;
+        ctx.stop();
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StopReason {
+    #[error("Connection to {price_source} failed: {error:#}")]
+    Failed {
+        price_source: &'static str,
+        error: anyhow::Error,
+    },
+    #[error("Failed to connect to {price_source}: {error:#}")]
+    FailedToConnect {
+        price_source: &'static str,
+        error: anyhow::Error,
+    },
+    #[error("Quotes from {price_source} ended")]
+    StreamEnded { price_source: &'static str },
+}
+
+/// How old a quote may get before it no longer reflects the market.
+///
+/// BitMEX publishes a quote every minute, this allows for a few missed ones.
+pub const MAX_QUOTE_AGE: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quote {
+    pub timestamp: Timestamp,
+    pub bid: Price,
+    pub ask: Price,
+}
+
+impl Quote {
+    /// The price of this quote according to the given pricing mode.
+    pub fn price(&self, mode: PricingMode) -> Price {
+        match mode {
+            PricingMode::Bid => self.bid,
+            PricingMode::Ask => self.ask,
+            PricingMode::Mid => self.mid_range(),
+        }
+    }
+
+    /// Whether this quote is older than [`MAX_QUOTE_AGE`] at `now`.
+    pub fn is_stale(&self, now: Timestamp) -> bool {
+        now.seconds() - self.timestamp.seconds() > MAX_QUOTE_AGE.as_secs() as i64
+    }
+
+    fn mid_range(&self) -> Price {
+        (self.bid + self.ask) / 2
+    }
+}
+
+/// Which price of a quote is used to mark CFDs and to propose settlements.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PricingMode {
+    Bid,
+    Ask,
+    Mid,
+}
+
+impl PricingMode {
+    /// The pricing mode used by each role unless configured otherwise.
+    pub fn default_for(role: Role) -> Self {
+        match role {
+            Role::Maker => PricingMode::Ask,
+            Role::Taker => PricingMode::Mid,
+        }
+    }
+}
+
+impl FromStr for PricingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bid" => Ok(PricingMode::Bid),
+            "ask" => Ok(PricingMode::Ask),
+            "mid" => Ok(PricingMode::Mid),
+            other => anyhow::bail!("Unknown pricing mode {}, expected bid, ask or mid", other),
+        }
+    }
+}
+
+/// Quotes a constant price at a regular interval.
+pub struct Simulated {
+    price: Price,
+    interval: Duration,
+}
+
+impl Simulated {
+    pub fn new(price: Price, interval: Duration) -> Self {
+        Self { price, interval }
+    }
+}
+
+#[async_trait]
+impl PriceSource for Simulated {
+    fn name(&self) -> &'static str {
+        "simulated price source"
+    }
+
+    async fn connect(&self) -> Result<Quotes> {
+        let price = self.price;
+        let interval = self.interval;
+
+        // The first quote is sent right away, the following ones once per interval
+        let quotes = futures::stream::unfold(Duration::ZERO, move |delay| async move {
+            tokio::time::sleep(delay).await;
+
+            let quote = Quote {
+                timestamp: Timestamp::now(),
+                bid: price,
+                ask: price,
+            };
+
+            Some((Ok(quote), interval))
+        });
+
+        Ok(Box::pin(quotes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use xtra::Actor as _;
+
+    #[test]
+    fn mid_pricing_mode_uses_midpoint_of_bid_and_ask() {
+        let quote = Quote {
+            timestamp: Timestamp::now(),
+            bid: Price::new(dec!(42000)).unwrap(),
+            ask: Price::new(dec!(42010)).unwrap(),
+        };
+
+        assert_eq!(
+            quote.price(PricingMode::Mid),
+            Price::new(dec!(42005)).unwrap()
+        );
+        assert_eq!(quote.price(PricingMode::Bid), quote.bid);
+        assert_eq!(quote.price(PricingMode::Ask), quote.ask);
+    }
+
+    #[test]
+    fn quote_becomes_stale_after_max_age() {
+        let quote = quote_at(dec!(42000), dec!(42010));
+        let max_age = MAX_QUOTE_AGE.as_secs() as i64;
+
+        assert!(!quote.is_stale(Timestamp::new(quote.timestamp.seconds() + max_age)));
+        assert!(quote.is_stale(Timestamp::new(quote.timestamp.seconds() + max_age + 1)));
+    }
+
+    #[test]
+    fn pricing_mode_defaults_to_current_sides() {
+        assert_eq!(PricingMode::default_for(Role::Maker), PricingMode::Ask);
+        assert_eq!(PricingMode::default_for(Role::Taker), PricingMode::Mid);
+    }
+
+    #[test]
+    fn stopped_feed_is_offline_with_reason() {
+        let status = Status::from(&StopReason::StreamEnded {
+            price_source: "BitMex",
+        });
+
+        assert_eq!(
+            status,
+            Status::Offline {
+                reason: Some("Quotes from BitMex ended".to_owned())
+            }
+        );
+    }
+
+    #[test]
+    fn parses_price_sources() {
+        assert_eq!(
+            "bitmex".parse::<SourceConfig>().unwrap(),
+            SourceConfig::BitMex
+        );
+        assert_eq!(
+            "simulated:42000".parse::<SourceConfig>().unwrap(),
+            SourceConfig::Simulated {
+                price: Price::new(dec!(42000)).unwrap()
+            }
+        );
+        assert!("kraken".parse::<SourceConfig>().is_err());
+        assert!("simulated:zero".parse::<SourceConfig>().is_err());
+    }
+
+    #[tokio::test]
+    async fn forwards_quotes_of_any_price_source() {
+        let quotes = vec![
+            quote_at(dec!(42000), dec!(42010)),
+            quote_at(dec!(42020), dec!(42030)),
+        ];
+        let (recorder, fut) = Recorder::default().create(None).run();
+        tokio::spawn(fut);
+
+        let source = Arc::new(Replay(quotes.clone()));
+        let (supervisor, _price_feed) = supervisor::Actor::new(
+            {
+                let recorder = recorder.clone();
+                move |supervisor| {
+                    Actor::new(
+                        source.clone(),
+                        recorder.clone(),
+                        recorder.clone(),
+                        supervisor,
+                    )
+                }
+            },
+            |_| false,
+        );
+        let (_supervisor_address, fut) = supervisor.create(None).run();
+        tokio::spawn(fut);
+
+        let mut recorded = recorder.send(GetRecorded).await.unwrap();
+        for _ in 0..100 {
+            if recorded.statuses.len() == 2 {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            recorded = recorder.send(GetRecorded).await.unwrap();
+        }
+
+        assert_eq!(recorded.quotes, quotes);
+        assert_eq!(
+            recorded.statuses,
+            vec![
+                Status::Online,
+                Status::Offline {
+                    reason: Some("Quotes from replay ended".to_owned())
+                }
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn simulated_source_quotes_its_price() {
+        let price = Price::new(dec!(42000)).unwrap();
+        let source = Simulated::new(price, Duration::from_millis(10));
+
+        let mut quotes = source.connect().await.unwrap();
+        let first = quotes.try_next().await.unwrap().unwrap();
+        let second = quotes.try_next().await.unwrap().unwrap();
+
+        assert_eq!((first.bid, first.ask), (price, price));
+        assert_eq!((second.bid, second.ask), (price, price));
+    }
+
+    fn quote_at(bid: rust_decimal::Decimal, ask: rust_decimal::Decimal) -> Quote {
+        Quote {
+            timestamp: Timestamp::now(),
+            bid: Price::new(bid).unwrap(),
+            ask: Price::new(ask).unwrap(),
+        }
+    }
+
+    /// Sends the given quotes, then ends.
+    struct Replay(Vec<Quote>);
+
+    #[async_trait]
+    impl PriceSource for Replay {
+        fn name(&self) -> &'static str {
+            "replay"
+        }
+
+        async fn connect(&self) -> Result<Quotes> {
+            let quotes = self.0.clone().into_iter().map(Ok);
+
+            Ok(Box::pin(futures::stream::iter(quotes)))
+        }
+    }
+
+    #[derive(Default, Clone)]
+    struct Recorded {
+        quotes: Vec<Quote>,
+        statuses: Vec<Status>,
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        recorded: Recorded,
+    }
+
+    impl xtra::Actor for Recorder {}
+
+    #[xtra_productivity(message_impl = false)]
+    impl Recorder {
+        fn handle_quote(&mut self, msg: projection::Update<Quote>) {
+            self.recorded.quotes.push(msg.0);
+        }
+
+        fn handle_status(&mut self, msg: projection::Update<Status>) {
+            self.recorded.statuses.push(msg.0);
+        }
+    }
+
+    #[xtra_productivity]
+    impl Recorder {
+        fn handle_get_recorded(&mut self, _: GetRecorded) -> Recorded {
+            self.recorded.clone()
+        }
+    }
+
+    struct GetRecorded;
+}
@@ -1,25 +1,97 @@
+use crate::auto_commit::CommitPolicy;
+use crate::cfd_actors::load_cfd;
+use crate::db;
 use crate::db::append_event;
 use crate::model::cfd;
+use crate::model::cfd::Cfd;
 use crate::model::cfd::CfdEvent;
+use crate::model::cfd::OrderId;
 use crate::model::cfd::Role;
+use crate::model::cfd::UnconfirmedTransactions;
 use crate::monitor;
 use crate::monitor::MonitorParams;
 use crate::oracle;
 use crate::projection;
+use crate::try_continue;
 use crate::wallet;
+use crate::Tasks;
 use anyhow::Context;
 use anyhow::Result;
+use async_trait::async_trait;
+use bdk::bitcoin::Transaction;
+use sqlx::pool::PoolConnection;
+use sqlx::Sqlite;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
 use xtra::prelude::MessageChannel;
 use xtra_productivity::xtra_productivity;
 
+/// How often transactions that are not confirmed yet are broadcast again.
+pub const REBROADCAST_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// How often a transaction is broadcast again before we stop trying.
+const MAX_REBROADCASTS: u32 = 144;
+
+/// Whether to publish the CET once it can be decrypted after the CET timelock expired.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CetPolicy {
+    /// Publish the CET as soon as the oracle attested and the CET timelock expired.
+    AutoPublish,
+    /// Leave publishing the CET to the counterparty.
+    ///
+    /// If neither party publishes the CET, the CFD can only be refunded once the refund timelock
+    /// expired.
+    LeaveToCounterparty,
+}
+
+impl CetPolicy {
+    pub fn from_auto_publish(auto_publish: bool) -> Self {
+        if auto_publish {
+            Self::AutoPublish
+        } else {
+            Self::LeaveToCounterparty
+        }
+    }
+}
+
 pub struct Actor {
     db: sqlx::SqlitePool,
     role: Role,
     cfds_changed: Box<dyn MessageChannel<projection::CfdsChanged>>,
     try_broadcast_transaction: Box<dyn MessageChannel<wallet::TryBroadcastTransaction>>,
+    release_utxos: Box<dyn MessageChannel<wallet::ReleaseUtxos>>,
     start_monitoring: Box<dyn MessageChannel<monitor::StartMonitoring>>,
     monitor_collaborative_settlement: Box<dyn MessageChannel<monitor::CollaborativeSettlement>>,
     monitor_attestation: Box<dyn MessageChannel<oracle::MonitorAttestation>>,
+    commit_policy: CommitPolicy,
+    cet_policy: CetPolicy,
+    rebroadcast_interval: Duration,
+    pending_broadcasts: HashMap<(OrderId, Broadcast), PendingBroadcast>,
+    tasks: Tasks,
+}
+
+/// The transactions we depend on being confirmed, broadcast again until they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Broadcast {
+    Lock,
+    Commit,
+    Cet,
+}
+
+impl fmt::Display for Broadcast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Broadcast::Lock => write!(f, "Lock transaction"),
+            Broadcast::Commit => write!(f, "Commit transaction"),
+            Broadcast::Cet => write!(f, "CET"),
+        }
+    }
+}
+
+struct PendingBroadcast {
+    tx: Transaction,
+    rebroadcasts: u32,
 }
 
 pub struct Event(cfd::Event);
@@ -31,61 +103,152 @@ impl Event {
 }
 
 impl Actor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         db: sqlx::SqlitePool,
         role: Role,
         cfds_changed: &(impl MessageChannel<projection::CfdsChanged> + 'static),
         try_broadcast_transaction: &(impl MessageChannel<wallet::TryBroadcastTransaction> + 'static),
+        release_utxos: &(impl MessageChannel<wallet::ReleaseUtxos> + 'static),
         start_monitoring: &(impl MessageChannel<monitor::StartMonitoring> + 'static),
         monitor_collaborative_settlement: &(impl MessageChannel<monitor::CollaborativeSettlement>
               + 'static),
         monitor_attestation: &(impl MessageChannel<oracle::MonitorAttestation> + 'static),
+        commit_policy: CommitPolicy,
+        cet_policy: CetPolicy,
+        rebroadcast_interval: Duration,
     ) -> Self {
         Self {
             db,
             role,
             cfds_changed: cfds_changed.clone_channel(),
             try_broadcast_transaction: try_broadcast_transaction.clone_channel(),
+            release_utxos: release_utxos.clone_channel(),
             start_monitoring: start_monitoring.clone_channel(),
             monitor_collaborative_settlement: monitor_collaborative_settlement.clone_channel(),
             monitor_attestation: monitor_attestation.clone_channel(),
+            commit_policy,
+            cet_policy,
+            rebroadcast_interval,
+            pending_broadcasts: HashMap::new(),
+            tasks: Tasks::default(),
+        }
+    }
+
+    /// Broadcast a transaction and keep broadcasting it until it is confirmed.
+    ///
+    /// A failed broadcast is only logged, it is attempted again on the next rebroadcast.
+    async fn broadcast_until_confirmed(
+        &mut self,
+        order_id: OrderId,
+        broadcast: Broadcast,
+        tx: Transaction,
+    ) -> Result<()> {
+        let txid = tx.txid();
+
+        self.pending_broadcasts.insert(
+            (order_id, broadcast),
+            PendingBroadcast {
+                tx: tx.clone(),
+                rebroadcasts: 0,
+            },
+        );
+
+        match self
+            .try_broadcast_transaction
+            .send(wallet::TryBroadcastTransaction { tx })
+            .await?
+        {
+            Ok(txid) => tracing::info!(%order_id, %txid, "{} published", broadcast),
+            Err(e) if is_rejected(&e) => {
+                self.pending_broadcasts.remove(&(order_id, broadcast));
+                tracing::error!(%order_id, %txid, "Failed to publish {}, not retrying: {:#}", broadcast, e)
+            }
+            Err(e) => {
+                tracing::warn!(%order_id, %txid, "Failed to publish {}, retrying later: {:#}", broadcast, e)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rebuild the pending broadcasts from the CFDs after a restart.
+    ///
+    /// The transactions are broadcast again on the next rebroadcast, until the monitor reports them
+    /// as confirmed.
+    async fn load_pending_broadcasts(&mut self) -> Result<()> {
+        let mut conn = self.db.acquire().await?;
+        let commit_on_attestation = self.commit_policy.publishes_commit_on_attestation();
+
+        for order_id in db::load_all_cfd_ids(&mut conn).await? {
+            let cfd = try_continue!(load_cfd(order_id, &mut conn).await);
+            let UnconfirmedTransactions { lock, commit, cet } =
+                cfd.unconfirmed_transactions(commit_on_attestation);
+
+            for (broadcast, tx) in [
+                (Broadcast::Lock, lock),
+                (Broadcast::Commit, commit),
+                (Broadcast::Cet, cet),
+            ] {
+                if let Some(tx) = tx {
+                    tracing::debug!(%order_id, txid = %tx.txid(), "Resuming broadcast of {}", broadcast);
+
+                    self.pending_broadcasts.insert(
+                        (order_id, broadcast),
+                        PendingBroadcast {
+                            tx,
+                            rebroadcasts: 0,
+                        },
+                    );
+                }
+            }
         }
+
+        Ok(())
     }
 }
 
+/// Whether the wallet rejected the transaction for good, broadcasting it again cannot succeed.
+fn is_rejected(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<wallet::TransactionRejected>()
+        .is_some()
+}
+
 #[xtra_productivity]
 impl Actor {
     fn handle(&mut self, msg: Event) -> Result<()> {
         let event = msg.0;
 
-        // 1. Safe in DB
+        // 1. Safe in DB, refusing events that cannot follow the ones already recorded
         let mut conn = self.db.acquire().await?;
-        append_event(event.clone(), &mut conn).await?;
+        let cfd = load_cfd(event.id, &mut conn).await?;
+        let cfd = append_applicable_event(cfd, event.clone(), &mut conn).await?;
 
         // 2. Post process event
         match event.event {
             CfdEvent::ContractSetupCompleted { dlc } => {
                 tracing::info!("Setup complete, publishing on chain now");
 
-                let lock_tx = dlc.lock.0.clone();
-                let txid = self
-                    .try_broadcast_transaction
-                    .send(wallet::TryBroadcastTransaction { tx: lock_tx })
-                    .await??;
-
-                tracing::info!("Lock transaction published with txid {}", txid);
+                self.broadcast_until_confirmed(event.id, Broadcast::Lock, dlc.lock.0.clone())
+                    .await?;
 
                 self.start_monitoring
                     .send(monitor::StartMonitoring {
                         id: event.id,
-                        params: MonitorParams::new(dlc.clone()),
+                        params: MonitorParams::new(dlc.watch_only()),
                     })
                     .await?;
 
-                self.monitor_attestation
-                    .send(oracle::MonitorAttestation {
-                        event_id: dlc.settlement_event_id,
-                    })
+                for event_id in dlc.event_ids() {
+                    self.monitor_attestation
+                        .send(oracle::MonitorAttestation { event_id })
+                        .await?;
+                }
+            }
+            CfdEvent::ContractSetupFailed { .. } => {
+                self.release_utxos
+                    .send(wallet::ReleaseUtxos { order_id: event.id })
                     .await?;
             }
             CfdEvent::CollaborativeSettlementCompleted {
@@ -93,6 +256,8 @@ impl Actor {
             } => {
                 let txid = match self.role {
                     Role::Maker => {
+                        verify_finalized(&cfd, &spend_tx)?;
+
                         let txid = self
                             .try_broadcast_transaction
                             .send(wallet::TryBroadcastTransaction { tx: spend_tx })
@@ -120,48 +285,101 @@ impl Actor {
                     .await?;
             }
             CfdEvent::CollaborativeSettlementRejected { commit_tx } => {
-                let txid = self
-                    .try_broadcast_transaction
-                    .send(wallet::TryBroadcastTransaction { tx: commit_tx })
-                    .await?
-                    .context("Broadcasting commit transaction")?;
+                tracing::info!(order_id=%event.id, "Collaborative settlement rejected, closing non-collaboratively");
 
-                tracing::info!(
-                    "Closing non-collaboratively. Commit tx published with txid {}",
-                    txid
-                )
+                verify_finalized(&cfd, &commit_tx)?;
+                self.broadcast_until_confirmed(event.id, Broadcast::Commit, commit_tx)
+                    .await?;
             }
             CfdEvent::CollaborativeSettlementFailed { commit_tx } => {
-                let txid = self
-                    .try_broadcast_transaction
-                    .send(wallet::TryBroadcastTransaction { tx: commit_tx })
-                    .await?
-                    .context("Broadcasting commit transaction")?;
+                tracing::warn!(order_id=%event.id, "Collaborative settlement failed, closing non-collaboratively");
 
-                tracing::warn!(
-                    "Closing non-collaboratively. Commit tx published with txid {}",
-                    txid
-                )
+                verify_finalized(&cfd, &commit_tx)?;
+                self.broadcast_until_confirmed(event.id, Broadcast::Commit, commit_tx)
+                    .await?;
+            }
+            CfdEvent::OracleAttestedPostCetTimelock { .. }
+            | CfdEvent::CetTimelockConfirmedPostOracleAttestation { .. }
+                if self.cet_policy == CetPolicy::LeaveToCounterparty =>
+            {
+                tracing::info!(order_id=%event.id, "CET decrypted, leaving its publication to the counterparty");
             }
             CfdEvent::OracleAttestedPostCetTimelock { cet, .. }
             | CfdEvent::CetTimelockConfirmedPostOracleAttestation { cet } => {
-                let txid = self
-                    .try_broadcast_transaction
-                    .send(wallet::TryBroadcastTransaction { tx: cet })
-                    .await?
-                    .context("Failed to broadcast CET")?;
+                verify_finalized(&cfd, &cet)?;
+                self.broadcast_until_confirmed(event.id, Broadcast::Cet, cet)
+                    .await?;
 
-                tracing::info!(%txid, "CET published");
+                // A re-delivered attestation decrypts the CET again, its publication is only
+                // recorded once
+                if cfd.ensure_applicable(&CfdEvent::CetPublished).is_ok() {
+                    append_applicable_event(
+                        cfd,
+                        cfd::Event::new(event.id, CfdEvent::CetPublished),
+                        &mut conn,
+                    )
+                    .await?;
+                }
+            }
+            CfdEvent::OracleAttestedPriorCetTimelock { .. }
+                if !self.commit_policy.publishes_commit_on_attestation() =>
+            {
+                tracing::info!(order_id=%event.id, "Oracle attested, delaying publication of the commit transaction");
             }
             CfdEvent::OracleAttestedPriorCetTimelock { commit_tx: tx, .. }
-            | CfdEvent::ManualCommit { tx } => {
+            | CfdEvent::ManualCommit { tx }
+            | CfdEvent::ManualCommitAndSettle { tx } => {
+                verify_finalized(&cfd, &tx)?;
+                self.broadcast_until_confirmed(event.id, Broadcast::Commit, tx)
+                    .await?;
+            }
+            CfdEvent::ManualRefund { tx } => {
+                verify_finalized(&cfd, &tx)?;
+
                 let txid = self
                     .try_broadcast_transaction
                     .send(wallet::TryBroadcastTransaction { tx })
                     .await?
-                    .context("Failed to broadcast commit transaction")?;
+                    .context("Failed to broadcast refund transaction")?;
 
-                tracing::info!(%txid, "Commit transaction published");
+                tracing::info!(%txid, "Refund transaction published");
+            }
+            CfdEvent::MonitoringResumed => {
+                let dlc = cfd
+                    .dlc()
+                    .context("Cannot resume monitoring without a DLC")?
+                    .clone();
+
+                tracing::info!(order_id=%event.id, "Monitoring resumed");
+
+                // Whatever happened while monitoring was paused is picked up again, e.g. an
+                // attestation that was ignored is re-delivered by the oracle actor.
+                self.start_monitoring
+                    .send(monitor::StartMonitoring {
+                        id: event.id,
+                        params: MonitorParams::new(dlc.watch_only()),
+                    })
+                    .await?;
+
+                for event_id in dlc.event_ids() {
+                    self.monitor_attestation
+                        .send(oracle::MonitorAttestation { event_id })
+                        .await?;
+                }
+            }
+
+            CfdEvent::LockConfirmed => {
+                self.pending_broadcasts.remove(&(event.id, Broadcast::Lock));
+            }
+            CfdEvent::CommitConfirmed => {
+                self.pending_broadcasts
+                    .remove(&(event.id, Broadcast::Commit));
+            }
+            // Once the CFD is closed none of its transactions need to be broadcast anymore
+            CfdEvent::CetConfirmed
+            | CfdEvent::RefundConfirmed
+            | CfdEvent::CollaborativeSettlementConfirmed => {
+                self.pending_broadcasts.retain(|(id, _), _| *id != event.id);
             }
 
             _ => {} // TODO: Monitor post processing for rollover
@@ -172,6 +390,92 @@ impl Actor {
 
         Ok(())
     }
+
+    fn handle(&mut self, _: RebroadcastPending) {
+        let mut given_up = Vec::new();
+        let mut rejected = Vec::new();
+
+        for ((order_id, broadcast), pending) in self.pending_broadcasts.iter_mut() {
+            if pending.rebroadcasts >= MAX_REBROADCASTS {
+                given_up.push((*order_id, *broadcast));
+                continue;
+            }
+
+            pending.rebroadcasts += 1;
+
+            let txid = pending.tx.txid();
+            let result = self
+                .try_broadcast_transaction
+                .send(wallet::TryBroadcastTransaction {
+                    tx: pending.tx.clone(),
+                })
+                .await;
+
+            match result {
+                Ok(Ok(_)) => {
+                    tracing::debug!(%order_id, %txid, "{} broadcast again", broadcast)
+                }
+                Ok(Err(e)) if is_rejected(&e) => {
+                    rejected.push((*order_id, *broadcast));
+                    tracing::error!(%order_id, %txid, "Failed to broadcast {} again, giving up: {:#}", broadcast, e)
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!(%order_id, %txid, "Failed to broadcast {} again: {:#}", broadcast, e)
+                }
+                Err(e) => tracing::warn!("Wallet unavailable for rebroadcast: {:#}", e),
+            }
+        }
+
+        for (order_id, broadcast) in given_up {
+            self.pending_broadcasts.remove(&(order_id, broadcast));
+            tracing::error!(%order_id, "{} still not confirmed after {} broadcasts, giving up", broadcast, MAX_REBROADCASTS + 1);
+        }
+
+        for key in rejected {
+            self.pending_broadcasts.remove(&key);
+        }
+    }
+}
+
+/// Append `event` to `cfd`, refusing events that cannot follow the ones already recorded.
+///
+/// Returns the CFD with the event applied.
+async fn append_applicable_event(
+    cfd: Cfd,
+    event: cfd::Event,
+    conn: &mut PoolConnection<Sqlite>,
+) -> Result<Cfd> {
+    cfd.ensure_applicable(&event.event)
+        .with_context(|| format!("Refusing to append event to CFD {}", event.id))?;
+    append_event(event.clone(), conn).await?;
+
+    Ok(cfd.apply(event))
+}
+
+/// Verify that `tx` is fully signed according to the DLC of the CFD before broadcasting it.
+fn verify_finalized(cfd: &Cfd, tx: &Transaction) -> Result<()> {
+    let dlc = cfd
+        .dlc()
+        .context("Cannot verify transaction without a DLC")?;
+
+    dlc.verify_finalized(tx)
+        .with_context(|| format!("Refusing to broadcast invalid transaction {}", tx.txid()))
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        if let Err(e) = self.load_pending_broadcasts().await {
+            tracing::error!("Failed to resume pending broadcasts: {:#}", e);
+        }
+
+        let fut = ctx
+            .notify_interval(self.rebroadcast_interval, || RebroadcastPending)
+            .expect("we are alive");
+
+        self.tasks.add(fut);
+    }
 }
 
-impl xtra::Actor for Actor {}
+/// Message to broadcast the transactions that are not confirmed yet again
+pub struct RebroadcastPending;
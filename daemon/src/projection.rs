@@ -1,6 +1,6 @@
-use crate::bitmex_price_feed;
 use crate::db;
 use crate::model;
+use crate::model::cfd::calculate_break_even_price;
 use crate::model::cfd::calculate_long_liquidation_price;
 use crate::model::cfd::calculate_long_margin;
 use crate::model::cfd::calculate_profit;
@@ -13,26 +13,37 @@ use crate::model::cfd::Role;
 use crate::model::cfd::RolloverProposal;
 use crate::model::cfd::SettlementKind;
 use crate::model::cfd::SettlementProposal;
+use crate::model::BitMexPriceEventId;
+use crate::model::DisplayName;
 use crate::model::Identity;
+use crate::model::InversePrice;
 use crate::model::Leverage;
 use crate::model::Position;
 use crate::model::Price;
 use crate::model::Timestamp;
 use crate::model::TradingPair;
 use crate::model::Usd;
+use crate::price_feed;
+use crate::price_feed::PricingMode;
 use crate::send_async_safe::SendAsyncSafe;
 use crate::Order;
 use anyhow::Result;
 use async_trait::async_trait;
 use bdk::bitcoin::Amount;
 use bdk::bitcoin::Network;
+use bdk::bitcoin::Script;
 use bdk::bitcoin::SignedAmount;
+use bdk::bitcoin::Transaction;
 use bdk::bitcoin::Txid;
+use reqwest::Url;
 use rust_decimal::Decimal;
 use serde::Deserialize;
 use serde::Serialize;
 use sqlx::pool::PoolConnection;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use time::Duration;
 use time::OffsetDateTime;
 use tokio::sync::watch;
 use xtra::Context;
@@ -52,31 +63,56 @@ pub struct UpdateRollOverProposal {
 
 /// Store the latest state of `T` for display purposes
 /// (replaces previously stored values)
+#[derive(Clone)]
 pub struct Update<T>(pub T);
 
+/// The oracle events CFDs are settled by that the oracle does not serve.
+///
+/// Sent by [`crate::oracle::check_events_periodically`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnavailableOracleEvents(pub HashSet<BitMexPriceEventId>);
+
 /// Message indicating that the Cfds in the projection need to be reloaded, as at
 /// least one of the Cfds has changed.
 pub struct CfdsChanged;
 
+/// Discard the proposals held in memory and rebuild the Cfds from the database.
+///
+/// Useful after a bug fix in the projection or a manual edit of the database. Pending proposals
+/// are only shown again once they are re-proposed.
+pub struct Rebuild;
+
 pub struct Actor {
     db: sqlx::SqlitePool,
     tx: Tx,
     state: State,
 }
 
+#[derive(Clone)]
 pub struct Feeds {
     pub quote: watch::Receiver<Option<Quote>>,
     pub order: watch::Receiver<Option<CfdOrder>>,
-    pub connected_takers: watch::Receiver<Vec<Identity>>,
+    pub connected_takers: watch::Receiver<Vec<ConnectedTaker>>,
+    pub order_taken: watch::Receiver<Option<OrderTaken>>,
     pub cfds: watch::Receiver<Vec<Cfd>>,
+    pub price_feed_status: watch::Receiver<price_feed::Status>,
 }
 
 impl Actor {
-    pub fn new(db: sqlx::SqlitePool, _role: Role, network: Network) -> (Self, Feeds) {
+    pub fn new(
+        db: sqlx::SqlitePool,
+        _role: Role,
+        explorer: Explorer,
+        pricing_mode: PricingMode,
+        max_position_age: Option<Duration>,
+    ) -> (Self, Feeds) {
         let (tx_cfds, rx_cfds) = watch::channel(Vec::new());
         let (tx_order, rx_order) = watch::channel(None);
         let (tx_quote, rx_quote) = watch::channel(None);
         let (tx_connected_takers, rx_connected_takers) = watch::channel(Vec::new());
+        let (tx_order_taken, rx_order_taken) = watch::channel(None);
+        let (tx_price_feed_status, rx_price_feed_status) =
+            watch::channel(price_feed::Status::Offline { reason: None });
 
         let actor = Self {
             db,
@@ -85,14 +121,18 @@ impl Actor {
                 order: tx_order,
                 quote: tx_quote,
                 connected_takers: tx_connected_takers,
+                order_taken: tx_order_taken,
+                price_feed_status: tx_price_feed_status,
             },
-            state: State::new(network),
+            state: State::new(explorer, pricing_mode, max_position_age),
         };
         let feeds = Feeds {
             cfds: rx_cfds,
             order: rx_order,
             quote: rx_quote,
             connected_takers: rx_connected_takers,
+            order_taken: rx_order_taken,
+            price_feed_status: rx_price_feed_status,
         };
 
         (actor, feeds)
@@ -109,9 +149,12 @@ impl Actor {
         let cfds = match load_and_hydrate_cfds(
             &mut conn,
             self.state.quote,
-            self.state.network,
+            self.state.pricing_mode,
+            &self.state.explorer,
             &self.state.settlement_proposals,
             &self.state.rollover_proposals,
+            &self.state.unavailable_oracle_events,
+            self.state.max_position_age,
         )
         .await
         {
@@ -128,30 +171,41 @@ impl Actor {
 
 async fn load_and_hydrate_cfds(
     conn: &mut PoolConnection<sqlx::Sqlite>,
-    quote: Option<bitmex_price_feed::Quote>,
-    network: Network,
+    quote: Option<price_feed::Quote>,
+    pricing_mode: PricingMode,
+    explorer: &Explorer,
     settlement_proposals: &HashMap<OrderId, (SettlementProposal, SettlementKind)>,
     rollover_proposals: &HashMap<OrderId, (RolloverProposal, SettlementKind)>,
+    unavailable_oracle_events: &UnavailableOracleEvents,
+    max_position_age: Option<Duration>,
 ) -> Result<Vec<Cfd>> {
     let ids = db::load_all_cfd_ids(conn).await?;
 
     let mut cfds = Vec::with_capacity(ids.len());
+    let latest_price = latest_price(quote, pricing_mode);
 
     for id in ids {
         let (cfd, events) = db::load_cfd(id, conn).await?;
         let role = cfd.role;
 
-        let cfd = events.into_iter().fold(Cfd::new(cfd, quote), |cfd, event| {
-            cfd.apply(
-                event,
-                network,
-                settlement_proposals.get(&id),
-                rollover_proposals.get(&id),
-                role,
-            )
-        });
+        let cfd = events
+            .into_iter()
+            .fold(Cfd::new(cfd, latest_price), |cfd, event| {
+                cfd.apply(
+                    event,
+                    explorer,
+                    settlement_proposals.get(&id),
+                    rollover_proposals.get(&id),
+                    role,
+                )
+            });
 
-        cfds.push(cfd);
+        cfds.push(
+            cfd.with_profit_in_usd(latest_price)
+                .with_distance_to_liquidation(latest_price)
+                .with_max_age(max_position_age)
+                .with_oracle_event_availability(unavailable_oracle_events),
+        );
     }
 
     Ok(cfds)
@@ -180,6 +234,17 @@ pub struct Cfd {
     #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
     pub profit_btc: Option<SignedAmount>,
     pub profit_percent: Option<String>,
+    /// `profit_btc` converted at the latest price
+    pub profit_usd: Option<Usd>,
+
+    /// How much the position can lose in BTC until the latest price reaches the liquidation
+    /// price.
+    ///
+    /// Only available for long positions that are not closed, the short side is not leveraged.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub distance_to_liquidation_btc: Option<Amount>,
+    /// How far in percent the latest price can drop until it reaches the liquidation price.
+    pub distance_to_liquidation_percent: Option<String>,
 
     pub state: CfdState,
     pub actions: Vec<CfdAction>, // TODO: This should be a HashMap.
@@ -192,10 +257,75 @@ pub struct Cfd {
 
     pub counterparty: Identity,
 
+    /// Only available once the CFD was closed collaboratively or through a CET.
+    pub receipt: Option<SettlementReceipt>,
+
+    /// Miner fees of the transactions of this CFD published so far, shared by maker and taker.
+    ///
+    /// Does not include the fee of the lock transaction for contracts set up before it was
+    /// recorded.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub total_fees_btc: Amount,
+
+    /// Whether monitoring was paused, in which case no transactions are published automatically.
+    pub monitoring_paused: bool,
+
+    /// Only available if the contract setup failed and the reason was recorded.
+    pub setup_failure_reason: Option<String>,
+
+    /// The terms of a collaborative settlement that was proposed but not answered yet.
+    pub settlement_proposal: Option<ProposedSettlement>,
+
+    /// Whether the user archived the CFD after its contract setup failed.
+    ///
+    /// Archived CFDs are only listed if requested, see [`listed_cfds`].
+    pub archived: bool,
+
+    /// Whether the oracle does not serve the event the CFD is settled by.
+    ///
+    /// The oracle will not attest to the event, so the CFD can only be refunded.
+    pub oracle_event_unavailable: bool,
+
+    /// The price at which the CFD is settled automatically to take the profit.
+    pub take_profit: Option<Price>,
+    /// The price at which the CFD is settled automatically to limit the loss.
+    pub stop_loss: Option<Price>,
+
+    /// When the CFD reaches the maximum position age, after which it is no longer rolled over.
+    ///
+    /// Only available if a maximum position age is configured and the contract was set up.
+    #[serde(with = "::time::serde::timestamp::option")]
+    pub max_age_timestamp: Option<OffsetDateTime>,
+
+    /// How many seconds each transaction published for this CFD took to confirm.
+    pub confirmation_seconds: HashMap<TxLabel, i64>,
+
+    // When the transactions awaiting confirmation were published, to measure how long they take
+    // to confirm.
+    #[serde(skip)]
+    published_at: HashMap<TxLabel, Timestamp>,
+
+    // When the contract setup completed, the maximum position age is measured from here.
+    #[serde(skip)]
+    opened_at: Option<Timestamp>,
+
+    // Details of the transaction closing the CFD, kept until the transaction is confirmed and a
+    // receipt can be issued.
+    #[serde(skip)]
+    closing: Option<Closing>,
+
+    // The fee of each transaction accounted for in `total_fees_btc`, to count each one only once.
+    #[serde(skip)]
+    fees: HashMap<Txid, Amount>,
+
     // This is a bit awkward but we need this to compute the appropriate state as more events are
     // processed.
     #[serde(skip)]
     latest_dlc: Option<Dlc>,
+
+    // Whether the oracle attested, which decides what the CFD waits for once it is committed.
+    #[serde(skip)]
+    attested: bool,
 }
 
 impl Cfd {
@@ -210,7 +340,7 @@ impl Cfd {
             role,
             ..
         }: db::Cfd,
-        latest_quote: Option<bitmex_price_feed::Quote>,
+        latest_price: Option<Price>,
     ) -> Self {
         let long_margin = calculate_long_margin(initial_price, quantity_usd, leverage);
         let short_margin = calculate_short_margin(initial_price, quantity_usd);
@@ -221,12 +351,6 @@ impl Cfd {
         };
         let liquidation_price = calculate_long_liquidation_price(leverage, initial_price);
 
-        let latest_price = match (latest_quote, role) {
-            (None, _) => None,
-            (Some(quote), Role::Maker) => Some(quote.for_maker()),
-            (Some(quote), Role::Taker) => Some(quote.for_taker()),
-        };
-
         let (profit_btc_latest_price, profit_percent_latest_price) = latest_price.and_then(|latest_price| {
             match calculate_profit(initial_price, latest_price, quantity_usd, leverage, position) {
                 Ok(profit) => Some(profit),
@@ -263,6 +387,9 @@ impl Cfd {
             // By default, we assume profit should be based on the latest price!
             profit_btc: profit_btc_latest_price,
             profit_percent: profit_percent_latest_price,
+            profit_usd: None,
+            distance_to_liquidation_btc: None,
+            distance_to_liquidation_percent: None,
 
             state: CfdState::PendingSetup,
             actions: initial_actions,
@@ -273,39 +400,70 @@ impl Cfd {
             },
             expiry_timestamp: None,
             counterparty: counterparty_network_identity,
+            receipt: None,
+            total_fees_btc: Amount::ZERO,
+            monitoring_paused: false,
+            setup_failure_reason: None,
+            settlement_proposal: None,
+            archived: false,
+            oracle_event_unavailable: false,
+            take_profit: None,
+            stop_loss: None,
+            max_age_timestamp: None,
+            confirmation_seconds: HashMap::new(),
+            published_at: HashMap::new(),
+            opened_at: None,
+            closing: None,
+            fees: HashMap::new(),
             latest_dlc: None,
+            attested: false,
         }
     }
 
     // TODO: There is probably a better way of doing this?
     // The issue is, we need to re-hydrate the CFD to get the latest state but at the same time
-    // incorporate other data like network, current price, etc ...
+    // incorporate other data like the explorer, current price, etc ...
     fn apply(
         mut self,
         event: Event,
-        network: Network,
+        explorer: &Explorer,
         pending_settlement_proposal: Option<&(SettlementProposal, SettlementKind)>,
         pending_rollover_proposal: Option<&(RolloverProposal, SettlementKind)>,
         role: Role,
     ) -> Self {
+        let timestamp = event.timestamp;
+
+        // A contract is only set up once, a second completion must not replace its DLC
+        if matches!(event.event, CfdEvent::ContractSetupCompleted { .. })
+            && self.latest_dlc.is_some()
+        {
+            tracing::error!(order_id = %self.order_id, "Ignoring duplicate contract setup completion");
+            return self;
+        }
+
         // First, try to set state based on event.
         let (state, actions) = match event.event {
             CfdEvent::ContractSetupCompleted { dlc } => {
                 self.details.tx_url_list.push(TxUrl::new(
                     dlc.lock.0.txid(),
-                    network,
+                    explorer,
                     TxLabel::Lock,
                 ));
+                let lock_tx = dlc.lock.0.clone();
                 self.latest_dlc = Some(dlc);
+                self.opened_at.get_or_insert(timestamp);
+                self.record_published(TxLabel::Lock, timestamp);
+                self.record_fee(&lock_tx);
 
                 (CfdState::PendingOpen, vec![])
             }
-            CfdEvent::ContractSetupFailed => {
+            CfdEvent::ContractSetupFailed { reason } => {
                 // Don't display profit for failed contracts.
                 self.profit_btc = None;
                 self.profit_percent = None;
+                self.setup_failure_reason = reason;
 
-                (CfdState::SetupFailed, vec![])
+                (CfdState::SetupFailed, vec![CfdAction::Archive])
             }
             CfdEvent::OfferRejected => {
                 // Don't display profit for rejected contracts.
@@ -322,13 +480,29 @@ impl Cfd {
             CfdEvent::RolloverRejected => (CfdState::Open, vec![]),
             CfdEvent::RolloverFailed => (CfdState::Open, vec![]),
             CfdEvent::CollaborativeSettlementCompleted {
-                spend_tx, price, ..
+                spend_tx,
+                script,
+                price,
+                maker,
+                taker,
             } => {
                 self.details.tx_url_list.push(TxUrl::new(
                     spend_tx.txid(),
-                    network,
+                    explorer,
                     TxLabel::Collaborative,
                 ));
+                self.record_fee(&spend_tx);
+                self.record_published(TxLabel::Collaborative, timestamp);
+
+                let payout = match role {
+                    Role::Maker => maker,
+                    Role::Taker => taker,
+                };
+                match payout {
+                    Some(payout) => self.record_payout(spend_tx.txid(), price, payout),
+                    // Events recorded before the amounts were tracked only carry our script
+                    None => self.record_closing(&spend_tx, &script, price),
+                }
 
                 let (profit_btc, profit_percent) = self.maybe_calculate_profit(price);
                 self.profit_btc = profit_btc;
@@ -339,88 +513,178 @@ impl Cfd {
             CfdEvent::CollaborativeSettlementRejected { commit_tx } => {
                 self.details.tx_url_list.push(TxUrl::new(
                     commit_tx.txid(),
-                    network,
+                    explorer,
                     TxLabel::Commit,
                 ));
+                self.record_fee(&commit_tx);
+                self.record_published(TxLabel::Commit, timestamp);
 
                 (CfdState::PendingCommit, vec![])
             }
             CfdEvent::CollaborativeSettlementFailed { commit_tx } => {
                 self.details.tx_url_list.push(TxUrl::new(
                     commit_tx.txid(),
-                    network,
+                    explorer,
                     TxLabel::Commit,
                 ));
+                self.record_fee(&commit_tx);
+                self.record_published(TxLabel::Commit, timestamp);
 
                 (CfdState::PendingCommit, vec![])
             }
-            CfdEvent::LockConfirmed => (CfdState::Open, vec![CfdAction::Commit, CfdAction::Settle]),
+            CfdEvent::LockConfirmed => {
+                self.record_confirmed(TxLabel::Lock, timestamp);
+
+                (
+                    CfdState::Open,
+                    vec![
+                        CfdAction::Commit,
+                        CfdAction::CommitAndSettle,
+                        CfdAction::Settle,
+                    ],
+                )
+            }
             CfdEvent::CommitConfirmed => {
+                self.record_confirmed(TxLabel::Commit, timestamp);
+
+                let state = if self.attested {
+                    CfdState::PendingCetTimelock
+                } else {
+                    CfdState::OpenCommitted
+                };
+
                 // pretty weird if this is not defined ...
-                if let Some(dlc) = self.latest_dlc.as_ref() {
+                if let Some(commit_tx) = self.latest_dlc.as_ref().map(|dlc| dlc.commit.0.clone()) {
                     self.details.tx_url_list.push(TxUrl::new(
-                        dlc.commit.0.txid(),
-                        network,
+                        commit_tx.txid(),
+                        explorer,
                         TxLabel::Commit,
                     ));
+                    self.record_fee(&commit_tx);
                 }
-                (CfdState::OpenCommitted, vec![])
+                (state, vec![])
+            }
+            CfdEvent::CetConfirmed => {
+                self.record_confirmed(TxLabel::Cet, timestamp);
+                self.issue_receipt(timestamp);
+
+                (CfdState::Closed, vec![])
             }
-            CfdEvent::CetConfirmed => (CfdState::Closed, vec![]),
             CfdEvent::RefundConfirmed => {
-                if let Some(dlc) = self.latest_dlc.as_ref() {
+                self.record_confirmed(TxLabel::Refund, timestamp);
+
+                if let Some(refund_tx) = self.latest_dlc.as_ref().map(|dlc| dlc.refund.0.clone()) {
                     self.details.tx_url_list.push(TxUrl::new(
-                        dlc.refund.0.txid(),
-                        network,
+                        refund_tx.txid(),
+                        explorer,
                         TxLabel::Refund,
                     ));
+                    self.record_fee(&refund_tx);
                 }
                 (CfdState::Refunded, vec![])
             }
-            CfdEvent::CollaborativeSettlementConfirmed => (CfdState::Closed, vec![]),
+            CfdEvent::CollaborativeSettlementConfirmed => {
+                self.record_confirmed(TxLabel::Collaborative, timestamp);
+                self.issue_receipt(timestamp);
+
+                (CfdState::Closed, vec![])
+            }
             CfdEvent::CetTimelockConfirmedPriorOracleAttestation => {
                 (CfdState::OpenCommitted, self.actions)
             }
-            CfdEvent::CetTimelockConfirmedPostOracleAttestation { .. } => {
-                (CfdState::PendingCet, self.actions)
+            CfdEvent::CetTimelockConfirmedPostOracleAttestation { cet } => {
+                self.record_fee(&cet);
+
+                (CfdState::CetReady, self.actions)
+            }
+            CfdEvent::RefundTimelockConfirmed { .. } => {
+                // The refund transaction can only be spent once the refund timelock expired.
+                let mut actions = self.actions;
+                actions.push(CfdAction::Refund);
+
+                (self.state, actions)
             }
-            CfdEvent::RefundTimelockConfirmed { .. } => (self.state, self.actions),
             CfdEvent::OracleAttestedPriorCetTimelock {
-                price, commit_tx, ..
+                timelocked_cet,
+                commit_tx,
+                price,
             } => {
                 let (profit_btc, profit_percent) = self.maybe_calculate_profit(price);
                 self.profit_btc = profit_btc;
                 self.profit_percent = profit_percent;
+                self.record_cet(&timelocked_cet, price, role);
 
                 self.details.tx_url_list.push(TxUrl::new(
                     commit_tx.txid(),
-                    network,
+                    explorer,
                     TxLabel::Commit,
                 ));
+                self.record_fee(&commit_tx);
+                self.record_published(TxLabel::Commit, timestamp);
+                self.attested = true;
 
-                // Only allow committing once the oracle attested.
-                (CfdState::PendingCommit, vec![])
+                (CfdState::PendingCetTimelock, vec![])
             }
             CfdEvent::OracleAttestedPostCetTimelock { cet, price } => {
                 self.details
                     .tx_url_list
-                    .push(TxUrl::new(cet.txid(), network, TxLabel::Cet));
+                    .push(TxUrl::new(cet.txid(), explorer, TxLabel::Cet));
+                self.record_fee(&cet);
 
                 let (profit_btc, profit_percent) = self.maybe_calculate_profit(price);
                 self.profit_btc = profit_btc;
                 self.profit_percent = profit_percent;
+                self.record_cet(&cet, price, role);
+                self.attested = true;
 
                 // Only allow committing once the oracle attested.
-                (CfdState::PendingCet, vec![CfdAction::Commit])
+                (CfdState::CetReady, vec![CfdAction::Commit])
             }
-            CfdEvent::ManualCommit { tx } => {
+            CfdEvent::CetPublished => {
+                self.record_published(TxLabel::Cet, timestamp);
+
+                (CfdState::PendingCet, self.actions)
+            }
+            CfdEvent::ManualCommit { tx } | CfdEvent::ManualCommitAndSettle { tx } => {
                 self.details
                     .tx_url_list
-                    .push(TxUrl::new(tx.txid(), network, TxLabel::Commit));
+                    .push(TxUrl::new(tx.txid(), explorer, TxLabel::Commit));
+                self.record_fee(&tx);
+                self.record_published(TxLabel::Commit, timestamp);
 
                 (CfdState::PendingCommit, vec![])
             }
-            CfdEvent::RevokeConfirmed => todo!("Deal with revoked"),
+            CfdEvent::ManualRefund { tx } => {
+                self.details
+                    .tx_url_list
+                    .push(TxUrl::new(tx.txid(), explorer, TxLabel::Refund));
+                self.record_fee(&tx);
+                self.record_published(TxLabel::Refund, timestamp);
+
+                (CfdState::PendingRefund, vec![])
+            }
+            CfdEvent::RevokeConfirmed => (CfdState::Revoked, vec![]),
+            CfdEvent::MonitoringPaused => {
+                self.monitoring_paused = true;
+
+                (self.state, self.actions)
+            }
+            CfdEvent::MonitoringResumed => {
+                self.monitoring_paused = false;
+
+                (self.state, self.actions)
+            }
+            CfdEvent::ExitLevelsSet { levels } => {
+                self.take_profit = levels.take_profit;
+                self.stop_loss = levels.stop_loss;
+
+                (self.state, self.actions)
+            }
+            CfdEvent::Archived => {
+                self.archived = true;
+
+                (self.state, vec![])
+            }
         };
 
         self.state = state;
@@ -428,13 +692,15 @@ impl Cfd {
 
         // If we have pending proposals, override the state
 
+        self.settlement_proposal = pending_settlement_proposal
+            .map(|(proposal, kind)| ProposedSettlement::new(proposal, kind, role));
+
         match pending_settlement_proposal {
             Some((_, SettlementKind::Incoming)) => {
                 self.state = CfdState::IncomingSettlementProposal;
 
-                if role == Role::Maker {
-                    self.actions = vec![CfdAction::AcceptSettlement, CfdAction::RejectSettlement];
-                }
+                // The taker only receives counter-proposals, which it can answer as well
+                self.actions = vec![CfdAction::AcceptSettlement, CfdAction::RejectSettlement];
             }
             Some((_, SettlementKind::Outgoing)) => {
                 self.state = CfdState::OutgoingSettlementProposal;
@@ -458,6 +724,174 @@ impl Cfd {
         self
     }
 
+    /// Express the profit in USD, which is only possible once we have a price.
+    ///
+    /// Other than the closing price used for `profit_btc`, the latest price is used for the
+    /// conversion because that is what the BTC amount is worth now.
+    fn with_profit_in_usd(self, latest_price: Option<Price>) -> Self {
+        let profit_usd = self
+            .profit_btc
+            .zip(latest_price)
+            .map(|(profit_btc, price)| profit_in_usd(profit_btc, price));
+
+        Self { profit_usd, ..self }
+    }
+
+    /// The price at which the CFD's profit covers `fees`, next to the price it is liquidated at.
+    pub fn break_even(&self, fees: Amount) -> Result<BreakEven> {
+        let break_even_price = calculate_break_even_price(
+            self.initial_price,
+            self.quantity_usd,
+            self.leverage,
+            self.position,
+            fees,
+        )?;
+
+        Ok(BreakEven {
+            break_even_price,
+            liquidation_price: self.liquidation_price,
+        })
+    }
+
+    /// Flag the CFD if the oracle does not serve the event it is settled by.
+    fn with_oracle_event_availability(self, unavailable: &UnavailableOracleEvents) -> Self {
+        let oracle_event_unavailable = !self.attested
+            && self.latest_dlc.as_ref().map_or(false, |dlc| {
+                unavailable.0.contains(&dlc.settlement_event_id)
+            });
+
+        Self {
+            oracle_event_unavailable,
+            ..self
+        }
+    }
+
+    /// Compute when the CFD exceeds the maximum position age and stops being rolled over.
+    fn with_max_age(self, max_position_age: Option<Duration>) -> Self {
+        let max_age_timestamp = self.opened_at.zip(max_position_age).and_then(
+            |(opened_at, max_age)| match OffsetDateTime::from_unix_timestamp(opened_at.seconds()) {
+                Ok(opened_at) => Some(opened_at + max_age),
+                Err(e) => {
+                    tracing::warn!(order_id = %self.order_id, "Invalid contract setup timestamp: {:#}", e);
+                    None
+                }
+            },
+        );
+
+        Self {
+            max_age_timestamp,
+            ..self
+        }
+    }
+
+    /// Compute how far the latest price is from liquidating the position.
+    fn with_distance_to_liquidation(self, latest_price: Option<Price>) -> Self {
+        let is_live = !matches!(
+            self.state,
+            CfdState::Rejected
+                | CfdState::Closed
+                | CfdState::Refunded
+                | CfdState::SetupFailed
+                | CfdState::Revoked
+        );
+
+        let distance = match (latest_price, self.position) {
+            (Some(latest_price), Position::Long) if is_live => {
+                match distance_to_liquidation(
+                    latest_price,
+                    self.liquidation_price,
+                    self.quantity_usd,
+                ) {
+                    Ok(distance) => Some(distance),
+                    Err(e) => {
+                        tracing::warn!(order_id = %self.order_id, "Failed to calculate distance to liquidation: {:#}", e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let (distance_to_liquidation_btc, distance_to_liquidation_percent) = match distance {
+            Some((btc, percent)) => (Some(btc), Some(percent.round_dp(2).to_string())),
+            None => (None, None),
+        };
+
+        Self {
+            distance_to_liquidation_btc,
+            distance_to_liquidation_percent,
+            ..self
+        }
+    }
+
+    fn record_closing(&mut self, tx: &Transaction, own_script_pubkey: &Script, price: Price) {
+        // Our output is missing in case we were liquidated
+        let payout = tx
+            .output
+            .iter()
+            .find(|output| &output.script_pubkey == own_script_pubkey)
+            .map(|output| Amount::from_sat(output.value))
+            .unwrap_or(Amount::ZERO);
+
+        self.record_payout(tx.txid(), price, payout);
+    }
+
+    /// Account for the fee of `tx` in `total_fees_btc`, unless it was accounted for already.
+    fn record_fee(&mut self, tx: &Transaction) {
+        let fee = match self.latest_dlc.as_ref().and_then(|dlc| dlc.fee(tx)) {
+            Some(fee) => fee,
+            None => return,
+        };
+
+        if self.fees.insert(tx.txid(), fee).is_none() {
+            self.total_fees_btc += fee;
+        }
+    }
+
+    /// Remember when a transaction was published, the first time counts if it is re-published.
+    fn record_published(&mut self, label: TxLabel, timestamp: Timestamp) {
+        self.published_at.entry(label).or_insert(timestamp);
+    }
+
+    fn record_confirmed(&mut self, label: TxLabel, timestamp: Timestamp) {
+        if let Some(published_at) = self.published_at.get(&label) {
+            self.confirmation_seconds
+                .insert(label, timestamp.seconds() - published_at.seconds());
+        }
+    }
+
+    fn record_payout(&mut self, spend_txid: Txid, price: Price, payout: Amount) {
+        self.details.payout = Some(payout);
+        self.closing = Some(Closing {
+            spend_txid,
+            price,
+            payout,
+        });
+    }
+
+    fn record_cet(&mut self, cet: &Transaction, price: Price, role: Role) {
+        let own_script_pubkey = match self.latest_dlc.as_ref() {
+            Some(dlc) => dlc.script_pubkey_for(role),
+            None => {
+                tracing::warn!(order_id = %self.order_id, "Unable to determine payout of CET without dlc");
+                return;
+            }
+        };
+
+        self.record_closing(cet, &own_script_pubkey, price);
+    }
+
+    fn issue_receipt(&mut self, timestamp: Timestamp) {
+        self.receipt = self.closing.as_ref().map(|closing| SettlementReceipt {
+            order_id: self.order_id,
+            closing_price: closing.price,
+            payout: closing.payout,
+            realized_profit_btc: self.profit_btc,
+            spend_txid: closing.spend_txid,
+            timestamp,
+        });
+    }
+
     fn maybe_calculate_profit(
         &self,
         closing_price: Price,
@@ -480,6 +914,271 @@ impl Cfd {
     }
 }
 
+/// The price we would get if we closed a CFD right now.
+fn latest_price(quote: Option<price_feed::Quote>, pricing_mode: PricingMode) -> Option<Price> {
+    quote.map(|quote| quote.price(pricing_mode))
+}
+
+/// The loss in BTC of a long position of `quantity` if the price drops from `latest_price` to
+/// `liquidation_price`, and that drop in percent.
+fn distance_to_liquidation(
+    latest_price: Price,
+    liquidation_price: Price,
+    quantity: Usd,
+) -> Result<(Amount, Decimal)> {
+    if latest_price <= liquidation_price {
+        return Ok((Amount::ZERO, Decimal::ZERO));
+    }
+
+    let btc = quantity * InversePrice::new(liquidation_price)?
+        - quantity * InversePrice::new(latest_price)?;
+    let percent = (latest_price - liquidation_price).into_decimal() / latest_price.into_decimal()
+        * Decimal::ONE_HUNDRED;
+
+    Ok((btc, percent))
+}
+
+fn profit_in_usd(profit_btc: SignedAmount, price: Price) -> Usd {
+    let btc = Decimal::from(profit_btc.as_sat()) / Decimal::from(Amount::ONE_BTC.as_sat());
+
+    Usd::new((btc * price.into_decimal()).round_dp(2))
+}
+
+/// The CFDs to list, archived ones are only included if `include_archived`.
+pub fn listed_cfds(cfds: Vec<Cfd>, include_archived: bool) -> Vec<Cfd> {
+    if include_archived {
+        return cfds;
+    }
+
+    cfds.into_iter().filter(|cfd| !cfd.archived).collect()
+}
+
+/// Aggregated view over all CFDs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Portfolio {
+    /// Margin locked up in CFDs that are neither closed nor failed.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub margin_locked: Amount,
+    /// Profit/loss of the CFDs holding the locked margin, based on the current price.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub unrealized_profit_btc: SignedAmount,
+    /// `unrealized_profit_btc` relative to `margin_locked`.
+    pub unrealized_profit_percent: Option<String>,
+    /// Profit/loss of closed CFDs.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub realized_profit_btc: SignedAmount,
+    pub cfds_per_state: HashMap<CfdState, usize>,
+}
+
+impl Portfolio {
+    pub fn new(cfds: &[Cfd]) -> Self {
+        let mut margin_locked = Amount::ZERO;
+        let mut unrealized_profit_btc = SignedAmount::ZERO;
+        let mut realized_profit_btc = SignedAmount::ZERO;
+        let mut cfds_per_state = HashMap::new();
+
+        for cfd in cfds {
+            *cfds_per_state.entry(cfd.state).or_insert(0) += 1;
+
+            let profit_btc = cfd.profit_btc.unwrap_or(SignedAmount::ZERO);
+
+            match cfd.state {
+                CfdState::PendingSetup | CfdState::Rejected | CfdState::SetupFailed => {}
+                CfdState::Closed | CfdState::Refunded => realized_profit_btc += profit_btc,
+                _ => {
+                    margin_locked += cfd.margin;
+                    unrealized_profit_btc += profit_btc;
+                }
+            }
+        }
+
+        let unrealized_profit_percent = (margin_locked != Amount::ZERO).then(|| {
+            let percent = Decimal::from(unrealized_profit_btc.as_sat())
+                / Decimal::from(margin_locked.as_sat())
+                * Decimal::ONE_HUNDRED;
+
+            percent.round_dp(1).to_string()
+        });
+
+        Self {
+            margin_locked,
+            unrealized_profit_btc,
+            unrealized_profit_percent,
+            realized_profit_btc,
+            cfds_per_state,
+        }
+    }
+}
+
+/// How long the transactions published for all CFDs took to confirm, per kind of transaction.
+///
+/// Useful to tune the fee rates. Measured from the events of each CFD, the time a transaction
+/// was published is when we decided to publish it and confirmation is when the monitor saw it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ConfirmationTimes(BTreeMap<TxLabel, ConfirmationTime>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ConfirmationTime {
+    pub confirmed: usize,
+    pub average_seconds: i64,
+    pub max_seconds: i64,
+}
+
+impl ConfirmationTimes {
+    pub fn new(cfds: &[Cfd]) -> Self {
+        let mut seconds_per_label = BTreeMap::<TxLabel, Vec<i64>>::new();
+
+        for cfd in cfds {
+            for (label, seconds) in &cfd.confirmation_seconds {
+                seconds_per_label.entry(*label).or_default().push(*seconds);
+            }
+        }
+
+        let times = seconds_per_label
+            .into_iter()
+            .map(|(label, seconds)| {
+                let confirmed = seconds.len();
+                let time = ConfirmationTime {
+                    confirmed,
+                    average_seconds: seconds.iter().sum::<i64>() / confirmed as i64,
+                    max_seconds: seconds.into_iter().max().unwrap_or_default(),
+                };
+
+                (label, time)
+            })
+            .collect();
+
+        Self(times)
+    }
+
+    pub fn get(&self, label: TxLabel) -> Option<ConfirmationTime> {
+        self.0.get(&label).copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BreakEven {
+    #[serde(with = "round_to_two_dp")]
+    pub break_even_price: Price,
+    #[serde(with = "round_to_two_dp")]
+    pub liquidation_price: Price,
+}
+
+/// The terms of a pending collaborative settlement, for users to review before answering.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProposedSettlement {
+    pub proposed_by: Role,
+    #[serde(with = "round_to_two_dp")]
+    pub price: Price,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub taker_payout: Amount,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub maker_payout: Amount,
+    /// When the settlement was proposed
+    pub timestamp: Timestamp,
+}
+
+impl ProposedSettlement {
+    fn new(proposal: &SettlementProposal, kind: &SettlementKind, role: Role) -> Self {
+        let proposed_by = match (kind, role) {
+            (SettlementKind::Outgoing, role) => role,
+            (SettlementKind::Incoming, Role::Maker) => Role::Taker,
+            (SettlementKind::Incoming, Role::Taker) => Role::Maker,
+        };
+
+        Self {
+            proposed_by,
+            price: proposal.price,
+            taker_payout: proposal.taker,
+            maker_payout: proposal.maker,
+            timestamp: proposal.timestamp,
+        }
+    }
+}
+
+/// Summary of the outcome of a closed CFD, for users to keep as proof.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SettlementReceipt {
+    pub order_id: OrderId,
+    #[serde(with = "round_to_two_dp")]
+    pub closing_price: Price,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub payout: Amount,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub realized_profit_btc: Option<SignedAmount>,
+    pub spend_txid: Txid,
+    /// When the closing transaction was confirmed
+    pub timestamp: Timestamp,
+}
+
+#[derive(Debug, Clone)]
+struct Closing {
+    spend_txid: Txid,
+    price: Price,
+    payout: Amount,
+}
+
+/// Keeps track of the CFDs sent to a client, so that only the ones that changed are sent again.
+///
+/// The `cfds` feed always contains all CFDs, which is expensive to send on every quote tick for
+/// large portfolios. Clients are expected to merge the changed CFDs by `order_id` and drop the
+/// removed ones, e.g. CFDs that were archived.
+#[derive(Debug, Default)]
+pub struct CfdsDelta {
+    sent: HashMap<OrderId, serde_json::Value>,
+}
+
+impl CfdsDelta {
+    /// Returns the CFDs that changed since the last call; the first call returns all of them.
+    ///
+    /// CFDs are compared by their serialized representation, i.e. by what the client sees. CFDs
+    /// that were sent before but are no longer part of `cfds` are returned as removed.
+    pub fn changed(&mut self, cfds: &[Cfd]) -> ChangedCfds {
+        let current = cfds.iter().map(|cfd| cfd.order_id).collect::<HashSet<_>>();
+        let removed = self
+            .sent
+            .keys()
+            .filter(|order_id| !current.contains(order_id))
+            .copied()
+            .collect::<Vec<_>>();
+        for order_id in &removed {
+            self.sent.remove(order_id);
+        }
+
+        let changed = cfds
+            .iter()
+            .filter(|cfd| {
+                let json = match serde_json::to_value(cfd) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        tracing::warn!(order_id = %cfd.order_id, "Failed to serialize CFD: {:#}", e);
+                        return true;
+                    }
+                };
+
+                self.sent.insert(cfd.order_id, json.clone()) != Some(json)
+            })
+            .cloned()
+            .collect();
+
+        ChangedCfds { changed, removed }
+    }
+}
+
+/// The CFDs that changed or were removed since the last update, see [`CfdsDelta`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedCfds {
+    pub changed: Vec<Cfd>,
+    pub removed: Vec<OrderId>,
+}
+
+impl ChangedCfds {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
 /// Internal struct to keep all the senders around in one place
 struct Tx {
     pub cfds: watch::Sender<Vec<Cfd>>,
@@ -487,24 +1186,36 @@ struct Tx {
     pub quote: watch::Sender<Option<Quote>>,
     // TODO: Use this channel to communicate maker status as well with generic
     // ID of connected counterparties
-    pub connected_takers: watch::Sender<Vec<Identity>>,
+    pub connected_takers: watch::Sender<Vec<ConnectedTaker>>,
+    pub order_taken: watch::Sender<Option<OrderTaken>>,
+    pub price_feed_status: watch::Sender<price_feed::Status>,
 }
 
 /// Internal struct to keep state in one place
 struct State {
-    network: Network,
-    quote: Option<bitmex_price_feed::Quote>,
+    explorer: Explorer,
+    pricing_mode: PricingMode,
+    max_position_age: Option<Duration>,
+    quote: Option<price_feed::Quote>,
     settlement_proposals: HashMap<OrderId, (SettlementProposal, SettlementKind)>,
     rollover_proposals: HashMap<OrderId, (RolloverProposal, SettlementKind)>,
+    unavailable_oracle_events: UnavailableOracleEvents,
 }
 
 impl State {
-    fn new(network: Network) -> Self {
+    fn new(
+        explorer: Explorer,
+        pricing_mode: PricingMode,
+        max_position_age: Option<Duration>,
+    ) -> Self {
         Self {
-            network,
+            explorer,
+            pricing_mode,
+            max_position_age,
             quote: None,
             settlement_proposals: Default::default(),
             rollover_proposals: Default::default(),
+            unavailable_oracle_events: UnavailableOracleEvents(HashSet::new()),
         }
     }
 
@@ -530,7 +1241,7 @@ impl State {
         }
     }
 
-    fn update_quote(&mut self, quote: bitmex_price_feed::Quote) {
+    fn update_quote(&mut self, quote: price_feed::Quote) {
         self.quote = Some(quote);
     }
 }
@@ -541,20 +1252,52 @@ impl Actor {
         self.refresh_cfds().await
     }
 
+    async fn handle(&mut self, _: Rebuild) {
+        tracing::info!("Rebuilding projection from the database");
+
+        self.state.settlement_proposals.clear();
+        self.state.rollover_proposals.clear();
+        self.refresh_cfds().await
+    }
+
     fn handle(&mut self, msg: Update<Option<Order>>) {
         let _ = self.tx.order.send(msg.0.map(|x| x.into()));
     }
 
-    fn handle(&mut self, msg: Update<bitmex_price_feed::Quote>) {
+    fn handle(&mut self, msg: Update<price_feed::Quote>) {
         self.state.update_quote(msg.0);
         let _ = self.tx.quote.send(Some(msg.0.into()));
         self.refresh_cfds().await;
     }
 
-    fn handle(&mut self, msg: Update<Vec<model::Identity>>) {
+    fn handle(&mut self, msg: Update<price_feed::Status>) {
+        if let price_feed::Status::Offline { reason } = &msg.0 {
+            tracing::warn!(
+                reason = reason.as_deref().unwrap_or("unknown"),
+                "Price feed is offline"
+            );
+        }
+
+        let _ = self.tx.price_feed_status.send(msg.0);
+    }
+
+    fn handle(&mut self, msg: Update<Vec<ConnectedTaker>>) {
         let _ = self.tx.connected_takers.send(msg.0);
     }
 
+    fn handle(&mut self, msg: Update<OrderTaken>) {
+        let _ = self.tx.order_taken.send(Some(msg.0));
+    }
+
+    fn handle(&mut self, msg: Update<UnavailableOracleEvents>) {
+        if self.state.unavailable_oracle_events == msg.0 {
+            return;
+        }
+
+        self.state.unavailable_oracle_events = msg.0;
+        self.refresh_cfds().await;
+    }
+
     fn handle(&mut self, msg: UpdateSettlementProposal) {
         self.state.amend_settlement_proposal(msg);
         self.refresh_cfds().await;
@@ -585,8 +1328,8 @@ pub struct Quote {
     last_updated_at: Timestamp,
 }
 
-impl From<bitmex_price_feed::Quote> for Quote {
-    fn from(quote: bitmex_price_feed::Quote) -> Self {
+impl From<price_feed::Quote> for Quote {
+    fn from(quote: price_feed::Quote) -> Self {
         Quote {
             bid: quote.bid,
             ask: quote.ask,
@@ -596,7 +1339,7 @@ impl From<bitmex_price_feed::Quote> for Quote {
 }
 
 // FIXME: Remove this hack when it's not needed
-impl From<Quote> for bitmex_price_feed::Quote {
+impl From<Quote> for price_feed::Quote {
     fn from(quote: Quote) -> Self {
         Self {
             timestamp: quote.last_updated_at,
@@ -622,6 +1365,8 @@ pub struct CfdOrder {
     pub max_quantity: Usd,
 
     pub leverage: Leverage,
+    /// The leverages the taker can choose from, see [`Order::leverage_choices`].
+    pub leverage_choices: Vec<Leverage>,
     #[serde(with = "round_to_two_dp")]
     pub liquidation_price: Price,
 
@@ -639,6 +1384,7 @@ impl From<Order> for CfdOrder {
             min_quantity: order.min_quantity,
             max_quantity: order.max_quantity,
             leverage: order.leverage,
+            leverage_choices: order.leverage_choices(),
             liquidation_price: order.liquidation_price,
             creation_timestamp: order.creation_timestamp,
             settlement_time_interval_in_secs: order
@@ -650,13 +1396,38 @@ impl From<Order> for CfdOrder {
     }
 }
 
+/// The most recent order taken from the maker, to let the maker follow the trade flow.
+///
+/// This is not persisted, the resulting CFD records the taker and quantity already.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OrderTaken {
+    pub order_id: OrderId,
+    pub taker_id: Identity,
+    #[serde(with = "round_to_two_dp")]
+    pub quantity: Usd,
+}
+
+/// A taker connected to the maker.
 #[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConnectedTaker {
+    pub id: Identity,
+    /// The name the taker introduced itself with, if any.
+    pub display_name: Option<DisplayName>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 pub enum CfdState {
     PendingSetup,
     Rejected,
     PendingOpen,
     Open,
     PendingCommit,
+    /// The oracle attested but the CET can only be published once the CET timelock expired.
+    PendingCetTimelock,
+    /// The CET can be published but we did not publish it, e.g. because it is left to the
+    /// counterparty.
+    CetReady,
+    /// The CET was published and awaits confirmation.
     PendingCet,
     PendingClose,
     OpenCommitted,
@@ -668,6 +1439,8 @@ pub enum CfdState {
     PendingRefund,
     Refunded,
     SetupFailed,
+    /// The counterparty published a revoked commit transaction.
+    Revoked,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -685,14 +1458,25 @@ pub enum CfdAction {
     AcceptOrder,
     RejectOrder,
     Commit,
+    /// Commit and publish whatever settles the CFD next without further interaction.
+    CommitAndSettle,
     Settle,
     AcceptSettlement,
     RejectSettlement,
     AcceptRollOver,
     RejectRollOver,
+    Refund,
+    PauseMonitoring,
+    ResumeMonitoring,
+    /// Hide a CFD whose contract setup failed from the default list.
+    Archive,
 }
 
-mod round_to_two_dp {
+/// Serializes USD amounts and prices rounded to `DP` decimal places.
+///
+/// Use it through `#[serde(serialize_with = "round_dp::serialize::<_, _, 4>")]`; fields that go
+/// with the default of two decimal places use [`round_to_two_dp`].
+mod round_dp {
     use super::*;
     use serde::Serializer;
 
@@ -712,16 +1496,70 @@ mod round_to_two_dp {
         }
     }
 
-    pub fn serialize<D: ToDecimal, S: Serializer>(
+    pub fn serialize<D: ToDecimal, S: Serializer, const DP: u32>(
         value: &D,
         serializer: S,
     ) -> Result<S::Ok, S::Error> {
         let decimal = value.to_decimal();
-        let decimal = decimal.round_dp(2);
+        let decimal = decimal.round_dp(DP);
 
         Serialize::serialize(&decimal, serializer)
     }
 
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use rust_decimal_macros::dec;
+        use serde_test::assert_ser_tokens;
+        use serde_test::Token;
+
+        #[derive(Serialize)]
+        struct WithDecimalPlaces<I: ToDecimal> {
+            #[serde(serialize_with = "super::serialize::<_, _, 2>")]
+            two: I,
+            #[serde(serialize_with = "super::serialize::<_, _, 4>")]
+            four: I,
+        }
+
+        #[test]
+        fn price_serializes_with_configured_decimal_places() {
+            let price = model::Price::new(dec!(1000.123456)).unwrap();
+            let prices = WithDecimalPlaces {
+                two: price,
+                four: price,
+            };
+
+            assert_ser_tokens(
+                &prices,
+                &[
+                    Token::Struct {
+                        name: "WithDecimalPlaces",
+                        len: 2,
+                    },
+                    Token::Str("two"),
+                    Token::Str("1000.12"),
+                    Token::Str("four"),
+                    Token::Str("1000.1235"),
+                    Token::StructEnd,
+                ],
+            );
+        }
+    }
+}
+
+/// Serializes USD amounts and prices rounded to two decimal places, the default for display.
+mod round_to_two_dp {
+    use super::round_dp::ToDecimal;
+    use super::*;
+    use serde::Serializer;
+
+    pub fn serialize<D: ToDecimal, S: Serializer>(
+        value: &D,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        round_dp::serialize::<_, _, 2>(value, serializer)
+    }
+
     #[cfg(test)]
     mod tests {
         use super::*;
@@ -762,26 +1600,55 @@ pub struct TxUrl {
     pub url: String,
 }
 
-/// Construct a mempool.space URL for a given txid
-pub fn to_mempool_url(txid: Txid, network: Network) -> String {
-    match network {
-        Network::Bitcoin => format!("https://mempool.space/tx/{}", txid),
-        Network::Testnet => format!("https://mempool.space/testnet/tx/{}", txid),
-        Network::Signet => format!("https://mempool.space/signet/tx/{}", txid),
-        Network::Regtest => txid.to_string(),
+/// Block explorer used to link to transactions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explorer(Option<Url>);
+
+impl Explorer {
+    pub fn new(mut base: Url) -> Self {
+        // Make sure `tx/<txid>` is appended to the base instead of replacing its last segment
+        if !base.path().ends_with('/') {
+            let path = format!("{}/", base.path());
+            base.set_path(&path);
+        }
+
+        Self(Some(base))
+    }
+
+    /// The mempool.space explorer of the given network, there is none for regtest.
+    pub fn mempool_space(network: Network) -> Self {
+        let base = match network {
+            Network::Bitcoin => "https://mempool.space/",
+            Network::Testnet => "https://mempool.space/testnet/",
+            Network::Signet => "https://mempool.space/signet/",
+            Network::Regtest => return Self(None),
+        };
+
+        Self::new(base.parse().expect("valid URL from constant"))
+    }
+
+    /// Construct the URL of a given txid, falls back to the txid itself without an explorer.
+    pub fn tx_url(&self, txid: Txid) -> String {
+        match &self.0 {
+            Some(base) => base
+                .join(&format!("tx/{}", txid))
+                .expect("txid can be joined")
+                .to_string(),
+            None => txid.to_string(),
+        }
     }
 }
 
 impl TxUrl {
-    pub fn new(txid: Txid, network: Network, label: TxLabel) -> Self {
+    pub fn new(txid: Txid, explorer: &Explorer, label: TxLabel) -> Self {
         Self {
             label,
-            url: to_mempool_url(txid, network),
+            url: explorer.tx_url(txid),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum TxLabel {
     Lock,
     Commit,
@@ -793,6 +1660,10 @@ pub enum TxLabel {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bdk::bitcoin::OutPoint;
+    use bdk::bitcoin::TxIn;
+    use bdk::bitcoin::TxOut;
+    use rust_decimal_macros::dec;
 
     #[test]
     fn state_snapshot_test() {
@@ -806,6 +1677,12 @@ mod tests {
         assert_eq!(json, "\"PendingOpen\"");
         let json = serde_json::to_string(&CfdState::Open).unwrap();
         assert_eq!(json, "\"Open\"");
+        let json = serde_json::to_string(&CfdState::PendingCetTimelock).unwrap();
+        assert_eq!(json, "\"PendingCetTimelock\"");
+        let json = serde_json::to_string(&CfdState::CetReady).unwrap();
+        assert_eq!(json, "\"CetReady\"");
+        let json = serde_json::to_string(&CfdState::PendingCet).unwrap();
+        assert_eq!(json, "\"PendingCet\"");
         let json = serde_json::to_string(&CfdState::OpenCommitted).unwrap();
         assert_eq!(json, "\"OpenCommitted\"");
         let json = serde_json::to_string(&CfdState::PendingRefund).unwrap();
@@ -815,4 +1692,710 @@ mod tests {
         let json = serde_json::to_string(&CfdState::SetupFailed).unwrap();
         assert_eq!(json, "\"SetupFailed\"");
     }
+
+    #[test]
+    fn mempool_space_tx_urls() {
+        let txid = "2e3c7d0fa4a9c27c0b1cc3b5c5a4e4b3f1b7c58a4f3fcbdc0b0b17a0b1a1d2f3"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            Explorer::mempool_space(Network::Signet).tx_url(txid),
+            format!("https://mempool.space/signet/tx/{}", txid)
+        );
+        assert_eq!(
+            Explorer::mempool_space(Network::Bitcoin).tx_url(txid),
+            format!("https://mempool.space/tx/{}", txid)
+        );
+        assert_eq!(
+            Explorer::mempool_space(Network::Regtest).tx_url(txid),
+            txid.to_string()
+        );
+    }
+
+    #[test]
+    fn custom_explorer_without_trailing_slash_keeps_path() {
+        let txid = "2e3c7d0fa4a9c27c0b1cc3b5c5a4e4b3f1b7c58a4f3fcbdc0b0b17a0b1a1d2f3"
+            .parse()
+            .unwrap();
+        let explorer = Explorer::new("https://explorer.example/signet".parse().unwrap());
+
+        assert_eq!(
+            explorer.tx_url(txid),
+            format!("https://explorer.example/signet/tx/{}", txid)
+        );
+    }
+
+    #[test]
+    fn profit_is_converted_to_usd_at_given_price() {
+        let price = Price::new(dec!(40_000)).unwrap();
+
+        let profit = profit_in_usd(SignedAmount::from_sat(250_000), price);
+        let loss = profit_in_usd(SignedAmount::from_sat(-1_234_567), price);
+
+        assert_eq!(profit, Usd::new(dec!(100)));
+        assert_eq!(loss, Usd::new(dec!(-493.83)));
+    }
+
+    #[test]
+    fn no_profit_in_usd_without_price() {
+        let cfd = dummy_cfd(CfdState::Open, 1_000, Some(100)).with_profit_in_usd(None);
+
+        assert_eq!(cfd.profit_usd, None);
+    }
+
+    #[test]
+    fn profit_in_usd_is_serialized_as_decimal_string() {
+        let cfd = dummy_cfd(CfdState::Open, 1_000, Some(250_000))
+            .with_profit_in_usd(Some(Price::new(dec!(40_000)).unwrap()));
+
+        let json = serde_json::to_value(&cfd).unwrap();
+
+        let profit_usd = json["profit_usd"].as_str().unwrap();
+        assert_eq!(profit_usd.parse::<Decimal>().unwrap(), dec!(100));
+    }
+
+    #[test]
+    fn distance_to_liquidation_at_latest_price() {
+        let cfd = Cfd {
+            liquidation_price: Price::new(dec!(30_000)).unwrap(),
+            ..dummy_cfd(CfdState::Open, 1_000, Some(100))
+        }
+        .with_distance_to_liquidation(Some(Price::new(dec!(40_000)).unwrap()));
+
+        assert_eq!(
+            cfd.distance_to_liquidation_btc,
+            Some(Amount::from_sat(83_333))
+        );
+        assert_eq!(
+            cfd.distance_to_liquidation_percent
+                .unwrap()
+                .parse::<Decimal>()
+                .unwrap(),
+            dec!(25)
+        );
+    }
+
+    #[test]
+    fn no_distance_to_liquidation_below_liquidation_price() {
+        let (btc, percent) = distance_to_liquidation(
+            Price::new(dec!(25_000)).unwrap(),
+            Price::new(dec!(30_000)).unwrap(),
+            Usd::new(dec!(100)),
+        )
+        .unwrap();
+
+        assert_eq!(btc, Amount::ZERO);
+        assert_eq!(percent, Decimal::ZERO);
+    }
+
+    #[test]
+    fn no_distance_to_liquidation_for_short_or_closed_cfd() {
+        let price = Some(Price::new(dec!(40_000)).unwrap());
+
+        let short = Cfd {
+            position: Position::Short,
+            ..dummy_cfd(CfdState::Open, 1_000, Some(100))
+        }
+        .with_distance_to_liquidation(price);
+        let closed =
+            dummy_cfd(CfdState::Closed, 1_000, Some(100)).with_distance_to_liquidation(price);
+
+        assert_eq!(short.distance_to_liquidation_btc, None);
+        assert_eq!(closed.distance_to_liquidation_btc, None);
+    }
+
+    #[test]
+    fn portfolio_aggregates_margin_and_profit() {
+        let cfds = vec![
+            dummy_cfd(CfdState::Open, 1_000, Some(100)),
+            dummy_cfd(CfdState::OpenCommitted, 3_000, Some(-500)),
+            dummy_cfd(CfdState::Closed, 2_000, Some(700)),
+            dummy_cfd(CfdState::Rejected, 5_000, None),
+        ];
+
+        let portfolio = Portfolio::new(&cfds);
+
+        assert_eq!(portfolio.margin_locked, Amount::from_sat(4_000));
+        assert_eq!(
+            portfolio.unrealized_profit_btc,
+            SignedAmount::from_sat(-400)
+        );
+        assert_eq!(
+            portfolio.unrealized_profit_percent,
+            Some("-10.0".to_owned())
+        );
+        assert_eq!(portfolio.realized_profit_btc, SignedAmount::from_sat(700));
+        assert_eq!(portfolio.cfds_per_state[&CfdState::Open], 1);
+        assert_eq!(portfolio.cfds_per_state[&CfdState::Rejected], 1);
+        assert_eq!(portfolio.cfds_per_state.len(), 4);
+    }
+
+    #[test]
+    fn empty_portfolio_has_no_profit_percentage() {
+        let portfolio = Portfolio::new(&[]);
+
+        assert_eq!(portfolio.margin_locked, Amount::ZERO);
+        assert_eq!(portfolio.unrealized_profit_percent, None);
+        assert!(portfolio.cfds_per_state.is_empty());
+    }
+
+    #[test]
+    fn first_delta_contains_all_cfds() {
+        let cfds = vec![
+            dummy_cfd(CfdState::Open, 100_000, None),
+            dummy_cfd(CfdState::Closed, 100_000, Some(1_000)),
+        ];
+        let mut delta = CfdsDelta::default();
+
+        let changed = delta.changed(&cfds);
+
+        assert_eq!(changed.changed.len(), 2);
+    }
+
+    #[test]
+    fn delta_only_contains_changed_cfds() {
+        let mut cfds = vec![
+            dummy_cfd(CfdState::Open, 100_000, Some(1_000)),
+            dummy_cfd(CfdState::Open, 100_000, Some(2_000)),
+            dummy_cfd(CfdState::Open, 100_000, Some(3_000)),
+        ];
+        let mut delta = CfdsDelta::default();
+        delta.changed(&cfds);
+
+        cfds[1].profit_btc = Some(SignedAmount::from_sat(2_500));
+        let changed = delta.changed(&cfds);
+
+        assert_eq!(changed.changed.len(), 1);
+        assert_eq!(changed.changed[0].order_id, cfds[1].order_id);
+        assert_eq!(
+            changed.changed[0].profit_btc,
+            Some(SignedAmount::from_sat(2_500))
+        );
+    }
+
+    #[test]
+    fn unchanged_cfds_yield_empty_delta() {
+        let cfds = vec![dummy_cfd(CfdState::Open, 100_000, None)];
+        let mut delta = CfdsDelta::default();
+        delta.changed(&cfds);
+
+        let changed = delta.changed(&cfds);
+
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn new_cfd_appears_in_delta() {
+        let mut cfds = vec![dummy_cfd(CfdState::Open, 100_000, None)];
+        let mut delta = CfdsDelta::default();
+        delta.changed(&cfds);
+
+        cfds.push(dummy_cfd(CfdState::PendingSetup, 100_000, None));
+        let changed = delta.changed(&cfds);
+
+        assert_eq!(changed.changed.len(), 1);
+        assert_eq!(changed.changed[0].state, CfdState::PendingSetup);
+    }
+
+    #[test]
+    fn removed_cfd_appears_in_delta() {
+        let mut cfds = vec![
+            dummy_cfd(CfdState::Open, 100_000, None),
+            dummy_cfd(CfdState::SetupFailed, 100_000, None),
+        ];
+        let mut delta = CfdsDelta::default();
+        delta.changed(&cfds);
+
+        let archived = cfds.pop().unwrap();
+        let changed = delta.changed(&cfds);
+
+        assert!(changed.changed.is_empty());
+        assert_eq!(changed.removed, vec![archived.order_id]);
+        assert!(delta.changed(&cfds).is_empty());
+    }
+
+    #[test]
+    fn collaborative_close_issues_receipt_once_confirmed() {
+        let cfd = dummy_cfd(CfdState::Open, 100_000, None);
+        let order_id = cfd.order_id;
+        let own_script = Script::from(vec![1u8; 22]);
+        let spend_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: 150_000,
+                    script_pubkey: own_script.clone(),
+                },
+                TxOut {
+                    value: 50_000,
+                    script_pubkey: Script::from(vec![2u8; 22]),
+                },
+            ],
+        };
+        let price = Price::new(dec!(44_000)).unwrap();
+
+        let cfd = apply(
+            cfd,
+            CfdEvent::CollaborativeSettlementCompleted {
+                spend_tx: spend_tx.clone(),
+                script: own_script,
+                price,
+                maker: Some(Amount::from_sat(50_000)),
+                taker: Some(Amount::from_sat(150_000)),
+            },
+        );
+        assert_eq!(cfd.receipt, None, "no receipt before confirmation");
+
+        let confirmed = Event::new(order_id, CfdEvent::CollaborativeSettlementConfirmed);
+        let timestamp = confirmed.timestamp;
+        let cfd = cfd.apply(
+            confirmed,
+            &Explorer::mempool_space(Network::Regtest),
+            None,
+            None,
+            Role::Taker,
+        );
+
+        assert_eq!(cfd.state, CfdState::Closed);
+        assert_eq!(
+            cfd.receipt,
+            Some(SettlementReceipt {
+                order_id,
+                closing_price: price,
+                payout: Amount::from_sat(150_000),
+                realized_profit_btc: cfd.profit_btc,
+                spend_txid: spend_tx.txid(),
+                timestamp,
+            })
+        );
+        assert!(cfd.profit_btc.unwrap().is_positive());
+    }
+
+    #[test]
+    fn collaborative_close_without_amounts_takes_payout_from_own_output() {
+        let cfd = dummy_cfd(CfdState::Open, 100_000, None);
+        let own_script = Script::from(vec![1u8; 22]);
+        let spend_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: 50_000,
+                    script_pubkey: Script::from(vec![2u8; 22]),
+                },
+                TxOut {
+                    value: 150_000,
+                    script_pubkey: own_script.clone(),
+                },
+            ],
+        };
+
+        let cfd = apply(
+            cfd,
+            CfdEvent::CollaborativeSettlementCompleted {
+                spend_tx,
+                script: own_script,
+                price: Price::new(dec!(44_000)).unwrap(),
+                maker: None,
+                taker: None,
+            },
+        );
+
+        assert_eq!(cfd.details.payout, Some(Amount::from_sat(150_000)));
+    }
+
+    #[test]
+    fn pending_settlement_proposal_surfaces_its_terms() {
+        let cfd = dummy_cfd(CfdState::Open, 100_000, None);
+        let order_id = cfd.order_id;
+        let proposal = SettlementProposal {
+            order_id,
+            timestamp: Timestamp::new(1_640_000_000),
+            taker: Amount::from_sat(150_000),
+            maker: Amount::from_sat(50_000),
+            price: Price::new(dec!(44_000)).unwrap(),
+        };
+
+        let cfd = cfd.apply(
+            Event::new(order_id, CfdEvent::LockConfirmed),
+            &Explorer::mempool_space(Network::Regtest),
+            Some(&(proposal, SettlementKind::Incoming)),
+            None,
+            Role::Maker,
+        );
+
+        assert_eq!(cfd.state, CfdState::IncomingSettlementProposal);
+        assert_eq!(
+            cfd.settlement_proposal,
+            Some(ProposedSettlement {
+                proposed_by: Role::Taker,
+                price: Price::new(dec!(44_000)).unwrap(),
+                taker_payout: Amount::from_sat(150_000),
+                maker_payout: Amount::from_sat(50_000),
+                timestamp: Timestamp::new(1_640_000_000),
+            })
+        );
+
+        let json = serde_json::to_value(&cfd).unwrap();
+        assert_eq!(json["settlement_proposal"]["proposed_by"], "Taker");
+        assert_eq!(json["settlement_proposal"]["taker_payout"], 0.0015);
+        assert_eq!(json["settlement_proposal"]["maker_payout"], 0.0005);
+    }
+
+    #[test]
+    fn answered_settlement_proposal_is_removed() {
+        let cfd = dummy_cfd(CfdState::Open, 100_000, None);
+        let cfd = Cfd {
+            settlement_proposal: Some(ProposedSettlement {
+                proposed_by: Role::Taker,
+                price: Price::new(dec!(44_000)).unwrap(),
+                taker_payout: Amount::from_sat(150_000),
+                maker_payout: Amount::from_sat(50_000),
+                timestamp: Timestamp::new(1_640_000_000),
+            }),
+            ..cfd
+        };
+
+        let cfd = apply(cfd, CfdEvent::LockConfirmed);
+
+        assert_eq!(cfd.settlement_proposal, None);
+    }
+
+    #[test]
+    fn total_fees_of_closed_cfd_are_sum_of_fees_per_transaction() {
+        let mut dlc = Dlc::dummy(0);
+        dlc.lock.0.output.push(TxOut {
+            value: 200_000,
+            script_pubkey: Script::new(),
+        });
+        dlc.commit.0 = spending_tx(&dlc.lock.0, 199_000);
+        let commit_tx = dlc.commit.0.clone();
+        let cet = spending_tx(&commit_tx, 198_500);
+        let expected_fees =
+            dlc.lock_fee.unwrap() + dlc.fee(&commit_tx).unwrap() + dlc.fee(&cet).unwrap();
+
+        let cfd = dummy_cfd(CfdState::PendingSetup, 100_000, None);
+        let cfd = apply(cfd, CfdEvent::ContractSetupCompleted { dlc });
+        let cfd = apply(cfd, CfdEvent::LockConfirmed);
+        let cfd = apply(cfd, CfdEvent::ManualCommit { tx: commit_tx });
+        let cfd = apply(cfd, CfdEvent::CommitConfirmed);
+        let cfd = apply(
+            cfd,
+            CfdEvent::CetTimelockConfirmedPostOracleAttestation { cet },
+        );
+        let cfd = apply(cfd, CfdEvent::CetConfirmed);
+
+        assert_eq!(cfd.state, CfdState::Closed);
+        assert_eq!(cfd.total_fees_btc, expected_fees);
+        assert_eq!(cfd.total_fees_btc, Amount::from_sat(2_000));
+    }
+
+    #[test]
+    fn lock_fee_is_not_recorded_for_contracts_set_up_before_it_was_tracked() {
+        let dlc = Dlc {
+            lock_fee: None,
+            ..Dlc::dummy(0)
+        };
+
+        let cfd = dummy_cfd(CfdState::PendingSetup, 100_000, None);
+        let cfd = apply(cfd, CfdEvent::ContractSetupCompleted { dlc });
+
+        assert_eq!(cfd.total_fees_btc, Amount::ZERO);
+    }
+
+    #[test]
+    fn attestation_prior_cet_timelock_waits_for_cet_timelock_then_publishes_cet() {
+        let price = Price::new(dec!(40_000)).unwrap();
+        let commit_tx = spending_tx(&dummy_tx(), 199_000);
+        let cet = spending_tx(&commit_tx, 198_500);
+
+        let cfd = apply(
+            open_cfd(),
+            CfdEvent::OracleAttestedPriorCetTimelock {
+                timelocked_cet: cet.clone(),
+                commit_tx,
+                price,
+            },
+        );
+        assert_eq!(cfd.state, CfdState::PendingCetTimelock);
+
+        let cfd = apply(cfd, CfdEvent::CommitConfirmed);
+        assert_eq!(cfd.state, CfdState::PendingCetTimelock);
+
+        let cfd = apply(
+            cfd,
+            CfdEvent::CetTimelockConfirmedPostOracleAttestation { cet },
+        );
+        assert_eq!(cfd.state, CfdState::CetReady);
+
+        let cfd = apply(cfd, CfdEvent::CetPublished);
+        assert_eq!(cfd.state, CfdState::PendingCet);
+
+        let cfd = apply(cfd, CfdEvent::CetConfirmed);
+        assert_eq!(cfd.state, CfdState::Closed);
+    }
+
+    #[test]
+    fn attestation_post_cet_timelock_publishes_cet() {
+        let commit_tx = spending_tx(&dummy_tx(), 199_000);
+        let cet = spending_tx(&commit_tx, 198_500);
+
+        let cfd = apply(open_cfd(), CfdEvent::ManualCommit { tx: commit_tx });
+        let cfd = apply(cfd, CfdEvent::CommitConfirmed);
+        assert_eq!(cfd.state, CfdState::OpenCommitted);
+
+        let cfd = apply(cfd, CfdEvent::CetTimelockConfirmedPriorOracleAttestation);
+        assert_eq!(cfd.state, CfdState::OpenCommitted);
+
+        let cfd = apply(
+            cfd,
+            CfdEvent::OracleAttestedPostCetTimelock {
+                cet,
+                price: Price::new(dec!(40_000)).unwrap(),
+            },
+        );
+        assert_eq!(cfd.state, CfdState::CetReady);
+
+        let cfd = apply(cfd, CfdEvent::CetPublished);
+        assert_eq!(cfd.state, CfdState::PendingCet);
+    }
+
+    #[test]
+    fn cet_not_published_by_us_is_ready_until_confirmed() {
+        let price = Price::new(dec!(40_000)).unwrap();
+        let commit_tx = spending_tx(&dummy_tx(), 199_000);
+        let cet = spending_tx(&commit_tx, 198_500);
+
+        let cfd = apply(
+            open_cfd(),
+            CfdEvent::OracleAttestedPriorCetTimelock {
+                timelocked_cet: cet.clone(),
+                commit_tx,
+                price,
+            },
+        );
+        let cfd = apply(cfd, CfdEvent::CommitConfirmed);
+        assert_eq!(cfd.state, CfdState::PendingCetTimelock);
+
+        let cfd = apply(
+            cfd,
+            CfdEvent::CetTimelockConfirmedPostOracleAttestation { cet },
+        );
+        assert_eq!(cfd.state, CfdState::CetReady);
+
+        let cfd = apply(cfd, CfdEvent::CetConfirmed);
+        assert_eq!(cfd.state, CfdState::Closed);
+        assert_eq!(cfd.confirmation_seconds.get(&TxLabel::Cet), None);
+    }
+
+    #[test]
+    fn duplicate_contract_setup_completion_keeps_first_dlc() {
+        let cfd = dummy_cfd(CfdState::PendingSetup, 100_000, None);
+        let cfd = apply(cfd, CfdEvent::ContractSetupCompleted { dlc: Dlc::dummy(0) });
+        let cfd = apply(cfd, CfdEvent::LockConfirmed);
+        let cfd = apply(cfd, CfdEvent::ContractSetupCompleted { dlc: Dlc::dummy(1) });
+
+        assert_eq!(cfd.latest_dlc, Some(Dlc::dummy(0)));
+        assert_eq!(cfd.state, CfdState::Open);
+    }
+
+    #[test]
+    fn confirmation_time_is_measured_from_publication() {
+        let commit_tx = spending_tx(&dummy_tx(), 199_000);
+
+        let cfd = dummy_cfd(CfdState::PendingSetup, 100_000, None);
+        let cfd = apply_at(
+            cfd,
+            CfdEvent::ContractSetupCompleted { dlc: Dlc::dummy(0) },
+            1_000,
+        );
+        let cfd = apply_at(cfd, CfdEvent::LockConfirmed, 1_600);
+        let cfd = apply_at(cfd, CfdEvent::ManualCommit { tx: commit_tx }, 5_000);
+        let cfd = apply_at(cfd, CfdEvent::CommitConfirmed, 7_400);
+
+        assert_eq!(cfd.confirmation_seconds.get(&TxLabel::Lock), Some(&600));
+        assert_eq!(cfd.confirmation_seconds.get(&TxLabel::Commit), Some(&2_400));
+        assert_eq!(cfd.confirmation_seconds.get(&TxLabel::Cet), None);
+    }
+
+    #[test]
+    fn cet_confirmation_time_is_measured_from_when_we_published_it() {
+        let commit_tx = spending_tx(&dummy_tx(), 199_000);
+        let cet = spending_tx(&commit_tx, 198_500);
+
+        let cfd = apply_at(open_cfd(), CfdEvent::ManualCommit { tx: commit_tx }, 5_000);
+        let cfd = apply_at(cfd, CfdEvent::CommitConfirmed, 7_400);
+        let cfd = apply_at(
+            cfd,
+            CfdEvent::OracleAttestedPostCetTimelock {
+                cet,
+                price: Price::new(dec!(40_000)).unwrap(),
+            },
+            8_000,
+        );
+        let cfd = apply_at(cfd, CfdEvent::CetPublished, 8_100);
+        let cfd = apply_at(cfd, CfdEvent::CetConfirmed, 9_000);
+
+        assert_eq!(cfd.confirmation_seconds.get(&TxLabel::Cet), Some(&900));
+    }
+
+    #[test]
+    fn confirmation_times_are_aggregated_per_label() {
+        let lock_confirmed_after = |seconds| {
+            let cfd = dummy_cfd(CfdState::PendingSetup, 100_000, None);
+            let cfd = apply_at(
+                cfd,
+                CfdEvent::ContractSetupCompleted { dlc: Dlc::dummy(0) },
+                1_000,
+            );
+
+            apply_at(cfd, CfdEvent::LockConfirmed, 1_000 + seconds)
+        };
+        let pending = dummy_cfd(CfdState::PendingSetup, 100_000, None);
+
+        let times = ConfirmationTimes::new(&[
+            lock_confirmed_after(600),
+            lock_confirmed_after(1_200),
+            pending,
+        ]);
+
+        assert_eq!(
+            times.get(TxLabel::Lock),
+            Some(ConfirmationTime {
+                confirmed: 2,
+                average_seconds: 900,
+                max_seconds: 1_200,
+            })
+        );
+        assert_eq!(times.get(TxLabel::Commit), None);
+    }
+
+    #[test]
+    fn archived_cfd_is_only_listed_on_request() {
+        let failed = apply(
+            dummy_cfd(CfdState::PendingSetup, 100_000, None),
+            CfdEvent::ContractSetupFailed { reason: None },
+        );
+        assert_eq!(failed.actions, vec![CfdAction::Archive]);
+        let archived = apply(failed, CfdEvent::Archived);
+        let open = open_cfd();
+
+        let listed = listed_cfds(vec![archived.clone(), open.clone()], false);
+        assert_eq!(listed.len(), 1);
+        assert!(!listed[0].archived);
+
+        let listed = listed_cfds(vec![archived, open], true);
+        assert_eq!(listed.len(), 2);
+        assert!(listed[0].archived);
+    }
+
+    fn dummy_tx() -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        }
+    }
+
+    /// A transaction paying `value` from the first output of `spent_tx`.
+    fn spending_tx(spent_tx: &Transaction, value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(spent_tx.txid(), 0),
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: Script::new(),
+            }],
+        }
+    }
+
+    fn apply(cfd: Cfd, event: CfdEvent) -> Cfd {
+        let order_id = cfd.order_id;
+
+        cfd.apply(
+            Event::new(order_id, event),
+            &Explorer::mempool_space(Network::Regtest),
+            None,
+            None,
+            Role::Taker,
+        )
+    }
+
+    fn apply_at(cfd: Cfd, event: CfdEvent, seconds: i64) -> Cfd {
+        let order_id = cfd.order_id;
+        let event = Event {
+            timestamp: Timestamp::new(seconds),
+            id: order_id,
+            event,
+        };
+
+        cfd.apply(
+            event,
+            &Explorer::mempool_space(Network::Regtest),
+            None,
+            None,
+            Role::Taker,
+        )
+    }
+
+    fn open_cfd() -> Cfd {
+        let cfd = dummy_cfd(CfdState::PendingSetup, 100_000, None);
+        let cfd = apply(cfd, CfdEvent::ContractSetupCompleted { dlc: Dlc::dummy(0) });
+
+        apply(cfd, CfdEvent::LockConfirmed)
+    }
+
+    fn dummy_cfd(state: CfdState, margin_sat: u64, profit_sat: Option<i64>) -> Cfd {
+        Cfd {
+            order_id: OrderId::default(),
+            initial_price: Price::new(Decimal::from(40_000)).unwrap(),
+            leverage: Leverage::new(2).unwrap(),
+            trading_pair: TradingPair::BtcUsd,
+            position: Position::Long,
+            liquidation_price: Price::new(Decimal::from(26_667)).unwrap(),
+            quantity_usd: Usd::new(Decimal::from(100)),
+            margin: Amount::from_sat(margin_sat),
+            margin_counterparty: Amount::from_sat(margin_sat),
+            profit_btc: profit_sat.map(SignedAmount::from_sat),
+            profit_percent: None,
+            profit_usd: None,
+            distance_to_liquidation_btc: None,
+            distance_to_liquidation_percent: None,
+            state,
+            actions: vec![],
+            state_transition_timestamp: 0,
+            details: CfdDetails {
+                tx_url_list: vec![],
+                payout: None,
+            },
+            expiry_timestamp: None,
+            counterparty: Identity::new(x25519_dalek::PublicKey::from([0u8; 32])),
+            receipt: None,
+            total_fees_btc: Amount::ZERO,
+            monitoring_paused: false,
+            setup_failure_reason: None,
+            settlement_proposal: None,
+            archived: false,
+            oracle_event_unavailable: false,
+            take_profit: None,
+            stop_loss: None,
+            max_age_timestamp: None,
+            confirmation_seconds: HashMap::new(),
+            published_at: HashMap::new(),
+            opened_at: None,
+            closing: None,
+            fees: HashMap::new(),
+            latest_dlc: None,
+            attested: false,
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use crate::address_map::ActorName;
 use crate::address_map::Stopping;
+use crate::clock::Clock;
 use crate::connection;
 use crate::model::cfd::CannotRollover;
 use crate::model::cfd::Cfd;
@@ -28,8 +29,8 @@ use futures::channel::mpsc::UnboundedSender;
 use futures::future;
 use futures::SinkExt;
 use maia::secp256k1_zkp::schnorrsig;
+use std::sync::Arc;
 use std::time::Duration;
-use time::OffsetDateTime;
 use xtra::prelude::MessageChannel;
 use xtra_productivity::xtra_productivity;
 
@@ -46,6 +47,7 @@ pub struct Actor {
     on_completed: Box<dyn MessageChannel<RolloverCompleted>>,
     on_stopping: Vec<Box<dyn MessageChannel<Stopping<Self>>>>,
     rollover_msg_sender: Option<UnboundedSender<RollOverMsg>>,
+    clock: Arc<dyn Clock>,
     tasks: Tasks,
 }
 
@@ -61,18 +63,20 @@ impl Actor {
             &(impl MessageChannel<Stopping<Self>> + 'static),
             &(impl MessageChannel<Stopping<Self>> + 'static),
         ),
+        clock: Arc<dyn Clock>,
     ) -> Self {
         Self {
             cfd,
             n_payouts,
             oracle_pk,
-            timestamp: Timestamp::now(),
+            timestamp: clock.timestamp(),
             maker,
             get_announcement: get_announcement.clone_channel(),
             projection,
             on_completed: on_completed.clone_channel(),
             on_stopping: vec![on_stopping0.clone_channel(), on_stopping1.clone_channel()],
             rollover_msg_sender: None,
+            clock,
             tasks: Tasks::default(),
         }
     }
@@ -181,7 +185,7 @@ impl Actor {
 #[async_trait]
 impl xtra::Actor for Actor {
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
-        if let Err(e) = self.cfd.is_rollover_possible(OffsetDateTime::now_utc()) {
+        if let Err(e) = self.cfd.is_rollover_possible(self.clock.now()) {
             self.complete(
                 match e {
                     CannotRollover::NoDlc => RolloverCompleted::Failed {
@@ -305,6 +309,17 @@ impl Actor {
         .await;
     }
 
+    pub async fn handle_cancel(&mut self, _: Cancel, ctx: &mut xtra::Context<Self>) {
+        self.complete(
+            RolloverCompleted::Failed {
+                order_id: self.cfd.id(),
+                error: anyhow!("Rollover was cancelled"),
+            },
+            ctx,
+        )
+        .await;
+    }
+
     pub async fn handle_protocol_msg(
         &mut self,
         msg: wire::RollOverMsg,
@@ -341,6 +356,10 @@ pub struct RolloverSucceeded {
     dlc: Dlc,
 }
 
+/// Message to abort a rollover that is stuck, e.g. because the maker
+/// stopped responding.
+pub struct Cancel;
+
 /// Message sent from the spawned task to `rollover_taker::Actor` to
 /// notify that rollover has failed.
 pub struct RolloverFailed {
@@ -1,8 +1,19 @@
+use rocket::fairing::Fairing;
+use rocket::fairing::Info;
+use rocket::fairing::Kind;
 use rocket::http::ContentType;
 use rocket::http::Status;
+use rocket::Data;
+use rocket::Request;
+use rocket::Response;
 use rust_embed::EmbeddedFile;
 use std::borrow::Cow;
 use std::path::PathBuf;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Response header carrying the correlation id of a request.
+pub const CORRELATION_ID_HEADER: &str = "X-Correlation-Id";
 
 pub trait EmbeddedFileExt {
     fn into_response(self, file: PathBuf) -> Result<(ContentType, Cow<'static, [u8]>), Status>;
@@ -22,3 +33,81 @@ impl EmbeddedFileExt for Option<EmbeddedFile> {
         Ok((content_type, embedded_file.data))
     }
 }
+
+/// A fairing logging method, path, status and latency of every request.
+///
+/// Each request is assigned a correlation id which is included in the log line and echoed back to
+/// the client in the [`CORRELATION_ID_HEADER`]. Request bodies are never logged as some of them
+/// contain secrets, e.g. the seed words when reinitialising the wallet.
+pub struct RequestLog;
+
+/// Per-request state of the [`RequestLog`], kept in Rocket's request-local cache.
+struct RequestStart {
+    correlation_id: Uuid,
+    started_at: Instant,
+}
+
+impl RequestStart {
+    fn now() -> Self {
+        Self {
+            correlation_id: Uuid::new_v4(),
+            started_at: Instant::now(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for RequestLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data) {
+        req.local_cache(RequestStart::now);
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let start = req.local_cache(RequestStart::now);
+        let correlation_id = start.correlation_id.to_string();
+
+        tracing::info!(
+            correlation_id = %correlation_id,
+            method = %req.method(),
+            path = %req.uri().path(),
+            status = res.status().code,
+            latency_ms = start.started_at.elapsed().as_millis() as u64,
+            "Handled HTTP request"
+        );
+
+        res.set_raw_header(CORRELATION_ID_HEADER, correlation_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    #[rocket::get("/ping")]
+    fn ping() {}
+
+    #[test]
+    fn responses_carry_a_unique_correlation_id() {
+        let rocket = rocket::build()
+            .attach(RequestLog)
+            .mount("/", rocket::routes![ping]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let first = client.get("/ping").dispatch();
+        let second = client.get("/ping").dispatch();
+
+        let first_id = first.headers().get_one(CORRELATION_ID_HEADER).unwrap();
+        let second_id = second.headers().get_one(CORRELATION_ID_HEADER).unwrap();
+
+        assert!(first_id.parse::<Uuid>().is_ok());
+        assert_ne!(first_id, second_id);
+    }
+}
@@ -1,19 +1,35 @@
 use anyhow::Result;
-use bdk::bitcoin::Network;
+use bdk::bitcoin::Amount;
 use daemon::auth::Authenticated;
 use daemon::maker_inc_connections;
+use daemon::model::cfd::ActionCosts;
+use daemon::model::cfd::CloseOption;
+use daemon::model::cfd::DlcSummary;
 use daemon::model::cfd::OrderId;
+use daemon::model::cfd::SignedCommitTx;
+use daemon::model::cfd::WatchOnlyDlc;
 use daemon::model::Identity;
 use daemon::model::Price;
 use daemon::model::Usd;
 use daemon::model::WalletInfo;
 use daemon::oracle;
+use daemon::price_feed;
+use daemon::price_feed::PricingMode;
+use daemon::projection::listed_cfds;
+use daemon::projection::BreakEven;
 use daemon::projection::Cfd;
 use daemon::projection::CfdAction;
+use daemon::projection::CfdOrder;
+use daemon::projection::CfdsDelta;
+use daemon::projection::ConfirmationTimes;
+use daemon::projection::ConnectedTaker;
+use daemon::projection::Explorer;
 use daemon::projection::Feeds;
+use daemon::projection::SettlementReceipt;
 use daemon::routes::EmbeddedFileExt;
 use daemon::to_sse_event::ToSseEvent;
 use daemon::wallet;
+use daemon::watch_only::WatchedCfd;
 use daemon::MakerActorSystem;
 use http_api_problem::HttpApiProblem;
 use http_api_problem::StatusCode;
@@ -27,6 +43,7 @@ use rocket::serde::json::Json;
 use rocket::State;
 use rust_embed::RustEmbed;
 use serde::Deserialize;
+use serde::Serialize;
 use std::borrow::Cow;
 use std::path::PathBuf;
 use tokio::select;
@@ -34,19 +51,28 @@ use tokio::sync::watch;
 
 pub type Maker = MakerActorSystem<oracle::Actor, maker_inc_connections::Actor, wallet::Actor>;
 
+/// Archived CFDs are only part of the `cfds` snapshots and the `cfds_delta` updates if requested
+/// through `archived`.
 #[allow(clippy::too_many_arguments)]
-#[rocket::get("/feed")]
+#[rocket::get("/feed?<delta>&<archived>")]
 pub async fn maker_feed(
+    delta: Option<bool>,
+    archived: Option<bool>,
     rx: &State<Feeds>,
     rx_wallet: &State<watch::Receiver<Option<WalletInfo>>>,
     _auth: Authenticated,
 ) -> EventStream![] {
     let rx = rx.inner();
+    let delta = delta.unwrap_or(false);
+    let include_archived = archived.unwrap_or(false);
+    let mut cfds_delta = CfdsDelta::default();
     let mut rx_cfds = rx.cfds.clone();
     let mut rx_order = rx.order.clone();
     let mut rx_wallet = rx_wallet.inner().clone();
     let mut rx_quote = rx.quote.clone();
+    let mut rx_price_feed_status = rx.price_feed_status.clone();
     let mut rx_connected_takers = rx.connected_takers.clone();
+    let mut rx_order_taken = rx.order_taken.clone();
 
     EventStream! {
         let wallet_info = rx_wallet.borrow().clone();
@@ -58,12 +84,20 @@ pub async fn maker_feed(
         let quote = rx_quote.borrow().clone();
         yield quote.to_sse_event();
 
-        let cfds = rx_cfds.borrow().clone();
+        let price_feed_status = rx_price_feed_status.borrow().clone();
+        yield price_feed_status.to_sse_event();
+
+        // Always start with a full snapshot that deltas are applied to
+        let cfds = listed_cfds(rx_cfds.borrow().clone(), include_archived);
+        cfds_delta.changed(&cfds);
         yield cfds.to_sse_event();
 
         let takers = rx_connected_takers.borrow().clone();
         yield takers.to_sse_event();
 
+        let order_taken = rx_order_taken.borrow().clone();
+        yield order_taken.to_sse_event();
+
         loop{
             select! {
                 Ok(()) = rx_wallet.changed() => {
@@ -78,14 +112,31 @@ pub async fn maker_feed(
                     let takers = rx_connected_takers.borrow().clone();
                     yield takers.to_sse_event();
                 }
+                Ok(()) = rx_order_taken.changed() => {
+                    let order_taken = rx_order_taken.borrow().clone();
+                    yield order_taken.to_sse_event();
+                }
                 Ok(()) = rx_cfds.changed() => {
-                    let cfds = rx_cfds.borrow().clone();
-                    yield cfds.to_sse_event();
+                    let cfds = listed_cfds(rx_cfds.borrow().clone(), include_archived);
+
+                    if delta {
+                        let changed = cfds_delta.changed(&cfds);
+
+                        if !changed.is_empty() {
+                            yield changed.to_sse_event();
+                        }
+                    } else {
+                        yield cfds.to_sse_event();
+                    }
                 }
                 Ok(()) = rx_quote.changed() => {
                     let quote = rx_quote.borrow().clone();
                     yield quote.to_sse_event();
                 }
+                Ok(()) = rx_price_feed_status.changed() => {
+                    let price_feed_status = rx_price_feed_status.borrow().clone();
+                    yield price_feed_status.to_sse_event();
+                }
             }
         }
     }
@@ -95,7 +146,8 @@ pub async fn maker_feed(
 // TODO: Use Rocket form?
 #[derive(Debug, Clone, Deserialize)]
 pub struct CfdNewOrderRequest {
-    pub price: Price,
+    /// Quoted from the latest quote including the configured markup if omitted.
+    pub price: Option<Price>,
     // TODO: [post-MVP] Representation of the contract size; at the moment the contract size is
     // always 1 USD
     pub min_quantity: Usd,
@@ -126,6 +178,64 @@ pub async fn post_sell_order(
     Ok(status::Accepted(None))
 }
 
+/// Offer the taker with `taker_id` a different order than the one offered to all takers.
+///
+/// Overrides are not persisted and have to be set again after the maker restarts.
+#[rocket::put("/order/override/<taker_id>", data = "<order>")]
+pub async fn put_order_override(
+    taker_id: Identity,
+    order: Json<CfdNewOrderRequest>,
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<status::Accepted<()>, HttpApiProblem> {
+    maker
+        .set_order_override(
+            taker_id,
+            order.price,
+            order.min_quantity,
+            order.max_quantity,
+            order.fee_rate,
+        )
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Overriding offer failed")
+                .detail(format!("{:#}", e))
+        })?;
+
+    Ok(status::Accepted(None))
+}
+
+/// Remove the override of the taker with `taker_id`, offering it the current order again.
+#[rocket::delete("/order/override/<taker_id>")]
+pub async fn delete_order_override(
+    taker_id: Identity,
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<status::Accepted<()>, HttpApiProblem> {
+    maker.override_order(taker_id, None).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Removing offer override failed")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(status::Accepted(None))
+}
+
+#[rocket::delete("/order")]
+pub async fn delete_order(
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<status::Accepted<()>, HttpApiProblem> {
+    maker.withdraw_order().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Withdrawing offer failed")
+            .detail(e.to_string())
+    })?;
+
+    Ok(status::Accepted(None))
+}
+
 /// A "catcher" for all 401 responses, triggers the browser's basic auth implementation.
 #[rocket::catch(401)]
 pub fn unauthorized() -> PromptAuthentication {
@@ -150,6 +260,93 @@ pub async fn post_cfd_action(
     maker: &State<Maker>,
     _auth: Authenticated,
 ) -> Result<status::Accepted<()>, HttpApiProblem> {
+    apply_cfd_action(maker, id, action).await?;
+
+    Ok(status::Accepted(None))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CounterSettlementRequest {
+    pub price: Price,
+}
+
+/// Answer the settlement proposal of the taker with a proposal to settle at a different price.
+///
+/// The taker can accept or reject the counter-proposal.
+#[rocket::post("/cfd/<id>/counter-settlement", data = "<counter_settlement_request>")]
+pub async fn post_counter_settlement(
+    id: OrderId,
+    counter_settlement_request: Json<CounterSettlementRequest>,
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<status::Accepted<()>, HttpApiProblem> {
+    maker
+        .counter_settlement(id, counter_settlement_request.price)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Countering settlement failed")
+                .detail(format!("{:#}", e))
+        })?;
+
+    Ok(status::Accepted(None))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfdActionRequest {
+    pub order_id: OrderId,
+    pub action: CfdAction,
+}
+
+/// The outcome of a single action of a batch, `error` is only set if the action failed.
+#[derive(Debug, Clone, Serialize)]
+pub struct CfdActionResult {
+    pub order_id: OrderId,
+    pub action: CfdAction,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Applies several actions one after another.
+///
+/// A failing action does not prevent the remaining ones from being applied, the result of each
+/// action is reported in the same order as the request.
+#[rocket::post("/cfd/actions", data = "<requests>")]
+pub async fn post_cfd_actions(
+    requests: Json<Vec<CfdActionRequest>>,
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Json<Vec<CfdActionResult>> {
+    let mut results = Vec::with_capacity(requests.len());
+
+    for CfdActionRequest { order_id, action } in requests.into_inner() {
+        let error = apply_cfd_action(maker, order_id, action.clone())
+            .await
+            .err()
+            .map(|problem| problem.detail.unwrap_or(problem.title));
+
+        results.push(CfdActionResult {
+            order_id,
+            action,
+            success: error.is_none(),
+            error,
+        });
+    }
+
+    Json(results)
+}
+
+async fn apply_cfd_action(
+    maker: &Maker,
+    id: OrderId,
+    action: CfdAction,
+) -> Result<(), HttpApiProblem> {
+    maker.ensure_can_sign(id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title(action.to_string() + " rejected")
+            .detail(format!("{:#}", e))
+    })?;
+
     let result = match action {
         CfdAction::AcceptOrder => maker.accept_order(id).await,
         CfdAction::RejectOrder => maker.reject_order(id).await,
@@ -158,6 +355,11 @@ pub async fn post_cfd_action(
         CfdAction::AcceptRollOver => maker.accept_rollover(id).await,
         CfdAction::RejectRollOver => maker.reject_rollover(id).await,
         CfdAction::Commit => maker.commit(id).await,
+        CfdAction::CommitAndSettle => maker.commit_and_settle(id).await,
+        CfdAction::Refund => maker.refund(id).await,
+        CfdAction::PauseMonitoring => maker.pause_monitoring(id).await,
+        CfdAction::ResumeMonitoring => maker.resume_monitoring(id).await,
+        CfdAction::Archive => maker.archive(id).await,
         CfdAction::Settle => {
             let msg = "Collaborative settlement can only be triggered by taker";
             tracing::error!(msg);
@@ -169,6 +371,179 @@ pub async fn post_cfd_action(
         HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
             .title(action.to_string() + " failed")
             .detail(e.to_string())
+    })
+}
+
+#[rocket::get("/cfd/<id>/commit-tx")]
+pub async fn get_commit_tx(
+    id: OrderId,
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<Json<SignedCommitTx>, HttpApiProblem> {
+    let commit_tx = maker.commit_tx(id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Commit transaction unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(commit_tx))
+}
+
+/// The details of the contract of a CFD, without any secrets.
+#[rocket::get("/cfd/<id>/dlc")]
+pub async fn get_dlc_summary(
+    id: OrderId,
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<Json<DlcSummary>, HttpApiProblem> {
+    let summary = maker.dlc_summary(id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Contract details unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(summary))
+}
+
+/// Watch the CFD of a third party, importing the `watch_only` part of its contract details.
+///
+/// CFDs we are party to cannot be imported, they are monitored already.
+#[rocket::post("/watch-only/<id>", data = "<dlc>")]
+pub async fn post_watch_only(
+    id: OrderId,
+    dlc: Json<WatchOnlyDlc>,
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<status::Accepted<()>, HttpApiProblem> {
+    maker.watch(id, dlc.into_inner()).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Watching CFD failed")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(status::Accepted(None))
+}
+
+#[rocket::get("/watch-only")]
+pub async fn get_watch_only(
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<Json<Vec<WatchedCfd>>, HttpApiProblem> {
+    let cfds = maker.watched_cfds().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Watched CFDs unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(cfds))
+}
+
+#[rocket::get("/cfd/<id>/receipt")]
+pub async fn get_settlement_receipt(
+    id: OrderId,
+    feeds: &State<Feeds>,
+    _auth: Authenticated,
+) -> Result<Json<SettlementReceipt>, HttpApiProblem> {
+    let receipt = feeds
+        .cfds
+        .borrow()
+        .iter()
+        .find(|cfd| cfd.order_id == id)
+        .and_then(|cfd| cfd.receipt.clone())
+        .ok_or_else(|| {
+            HttpApiProblem::new(StatusCode::NOT_FOUND)
+                .title("Settlement receipt unavailable")
+                .detail(format!("CFD {} is unknown or not closed yet", id))
+        })?;
+
+    Ok(Json(receipt))
+}
+
+/// The price at which the CFD breaks even after paying `fees` (in satoshis, none if not given).
+#[rocket::get("/cfd/<id>/break-even?<fees>")]
+pub async fn get_break_even(
+    id: OrderId,
+    fees: Option<u64>,
+    feeds: &State<Feeds>,
+    _auth: Authenticated,
+) -> Result<Json<BreakEven>, HttpApiProblem> {
+    let break_even = feeds
+        .cfds
+        .borrow()
+        .iter()
+        .find(|cfd| cfd.order_id == id)
+        .ok_or_else(|| {
+            HttpApiProblem::new(StatusCode::NOT_FOUND)
+                .title("Break-even price unavailable")
+                .detail(format!("CFD {} is unknown", id))
+        })?
+        .break_even(Amount::from_sat(fees.unwrap_or_default()))
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Break-even price unavailable")
+                .detail(format!("{:#}", e))
+        })?;
+
+    Ok(Json(break_even))
+}
+
+#[rocket::get("/cfd/<id>/close-options")]
+pub async fn get_close_options(
+    id: OrderId,
+    maker: &State<Maker>,
+    feeds: &State<Feeds>,
+    pricing_mode: &State<PricingMode>,
+    _auth: Authenticated,
+) -> Result<Json<Vec<CloseOption>>, HttpApiProblem> {
+    let current_price = feeds
+        .quote
+        .borrow()
+        .clone()
+        .map(|quote| price_feed::Quote::from(quote).price(*pricing_mode.inner()));
+
+    let close_options = maker.close_options(id, current_price).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Close options unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(close_options))
+}
+
+/// What rolling over and settling the CFD would cost right now, to warn about insufficient funds
+/// before taking either action.
+#[rocket::get("/cfd/<id>/action-costs")]
+pub async fn get_action_costs(
+    id: OrderId,
+    maker: &State<Maker>,
+    feeds: &State<Feeds>,
+    pricing_mode: &State<PricingMode>,
+    _auth: Authenticated,
+) -> Result<Json<ActionCosts>, HttpApiProblem> {
+    let current_price = feeds
+        .quote
+        .borrow()
+        .clone()
+        .map(|quote| price_feed::Quote::from(quote).price(*pricing_mode.inner()));
+
+    let action_costs = maker.action_costs(id, current_price).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Action costs unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(action_costs))
+}
+
+#[rocket::post("/projection/rebuild")]
+pub async fn post_rebuild_projection(
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<status::Accepted<()>, HttpApiProblem> {
+    maker.rebuild_projection().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Rebuilding projection failed")
+            .detail(e.to_string())
     })?;
 
     Ok(status::Accepted(None))
@@ -205,7 +580,7 @@ pub struct WithdrawRequest {
 pub async fn post_withdraw_request(
     withdraw_request: Json<WithdrawRequest>,
     maker: &State<Maker>,
-    network: &State<Network>,
+    explorer: &State<Explorer>,
     _auth: Authenticated,
 ) -> Result<String, HttpApiProblem> {
     let amount =
@@ -224,18 +599,28 @@ pub async fn post_withdraw_request(
                 .detail(e.to_string())
         })?;
 
-    let url = match network.inner() {
-        Network::Bitcoin => format!("https://mempool.space/tx/{}", txid),
-        Network::Testnet => format!("https://mempool.space/testnet/tx/{}", txid),
-        Network::Signet => format!("https://mempool.space/signet/tx/{}", txid),
-        Network::Regtest => txid.to_string(),
-    };
+    Ok(explorer.tx_url(txid))
+}
+
+/// Hand out an address of the wallet that was not handed out before, e.g. to deposit funds on.
+#[rocket::get("/wallet/address")]
+pub async fn get_wallet_address(
+    maker: &State<Maker>,
+    _auth: Authenticated,
+) -> Result<Json<wallet::ReceiveAddress>, HttpApiProblem> {
+    let address = maker.new_address().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to get new address")
+            .detail(format!("{:#}", e))
+    })?;
 
-    Ok(url)
+    Ok(Json(address))
 }
 
-#[rocket::get("/cfds")]
+/// Archived CFDs are only included if requested through `archived`.
+#[rocket::get("/cfds?<archived>")]
 pub async fn get_cfds<'r>(
+    archived: Option<bool>,
     rx: &State<Feeds>,
     _auth: Authenticated,
 ) -> Result<Json<Vec<Cfd>>, HttpApiProblem> {
@@ -243,14 +628,35 @@ pub async fn get_cfds<'r>(
     let rx_cfds = rx.cfds.clone();
     let cfds = rx_cfds.borrow().clone();
 
-    Ok(Json(cfds))
+    Ok(Json(listed_cfds(cfds, archived.unwrap_or(false))))
+}
+
+/// How long the transactions published for our CFDs took to confirm.
+#[rocket::get("/confirmation-times")]
+pub fn get_confirmation_times(rx: &State<Feeds>, _auth: Authenticated) -> Json<ConfirmationTimes> {
+    let cfds = rx.cfds.borrow();
+
+    Json(ConfirmationTimes::new(&cfds))
+}
+
+/// The order currently advertised to takers, empty if there is none.
+#[rocket::get("/orders")]
+pub async fn get_orders<'r>(
+    rx: &State<Feeds>,
+    _auth: Authenticated,
+) -> Result<Json<Vec<CfdOrder>>, HttpApiProblem> {
+    let rx = rx.inner();
+    let rx_order = rx.order.clone();
+    let orders = rx_order.borrow().clone().into_iter().collect();
+
+    Ok(Json(orders))
 }
 
 #[rocket::get("/takers")]
 pub async fn get_takers<'r>(
     rx: &State<Feeds>,
     _auth: Authenticated,
-) -> Result<Json<Vec<Identity>>, HttpApiProblem> {
+) -> Result<Json<Vec<ConnectedTaker>>, HttpApiProblem> {
     let rx = rx.inner();
     let rx_connected_takers = rx.connected_takers.clone();
     let takers = rx_connected_takers.borrow().clone();
@@ -1,21 +1,47 @@
 use bdk::bitcoin::Amount;
 use bdk::bitcoin::Network;
-use daemon::bitmex_price_feed;
+use daemon::address_map::AtCapacity;
+use daemon::auth::TokenAuthenticated;
+use daemon::auth::TokenRequired;
 use daemon::connection::ConnectionStatus;
+use daemon::connection::MakerAddresses;
 use daemon::model::cfd::calculate_long_margin;
+use daemon::model::cfd::ActionCosts;
+use daemon::model::cfd::CannotTakeOrder;
+use daemon::model::cfd::CloseOption;
+use daemon::model::cfd::DlcSummary;
+use daemon::model::cfd::ExitLevels;
+use daemon::model::cfd::LeverageNotOffered;
 use daemon::model::cfd::OrderId;
+use daemon::model::cfd::SignedCommitTx;
+use daemon::model::cfd::SlippageBound;
 use daemon::model::Leverage;
 use daemon::model::Price;
 use daemon::model::Usd;
 use daemon::model::WalletInfo;
 use daemon::oracle;
+use daemon::price_feed;
+use daemon::price_feed::PricingMode;
 use daemon::projection;
+use daemon::projection::BreakEven;
 use daemon::projection::CfdAction;
+use daemon::projection::CfdOrder;
+use daemon::projection::CfdsDelta;
+use daemon::projection::Explorer;
 use daemon::projection::Feeds;
+use daemon::projection::SettlementReceipt;
 use daemon::routes::EmbeddedFileExt;
+use daemon::seed;
+use daemon::seed::Seed;
+use daemon::supervisor::RestartStats;
+use daemon::taker_cfd;
+use daemon::to_sse_event;
+use daemon::to_sse_event::PriceFeedStatus;
 use daemon::to_sse_event::ToSseEvent;
 use daemon::wallet;
+use daemon::wire;
 use daemon::TakerActorSystem;
+use daemon::SETTLEMENT_INTERVAL;
 use http_api_problem::HttpApiProblem;
 use http_api_problem::StatusCode;
 use rocket::http::ContentType;
@@ -30,28 +56,42 @@ use serde::Deserialize;
 use serde::Serialize;
 use std::borrow::Cow;
 use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::select;
 use tokio::sync::watch;
 
-type Taker = TakerActorSystem<oracle::Actor, wallet::Actor>;
+type Taker = Arc<TakerActorSystem<oracle::Actor, wallet::Actor>>;
 
-#[rocket::get("/feed")]
+/// Archived CFDs are only part of the `cfds` snapshots and the `cfds_delta` updates if requested
+/// through `archived`.
+#[rocket::get("/feed?<delta>&<archived>")]
 pub async fn feed(
+    delta: Option<bool>,
+    archived: Option<bool>,
     rx: &State<Feeds>,
     rx_wallet: &State<watch::Receiver<Option<WalletInfo>>>,
+    rx_wallet_sync: &State<watch::Receiver<Option<wallet::SyncProgress>>>,
     rx_maker_status: &State<watch::Receiver<ConnectionStatus>>,
 ) -> EventStream![] {
     let rx = rx.inner();
+    let delta = delta.unwrap_or(false);
+    let include_archived = archived.unwrap_or(false);
+    let mut cfds_delta = CfdsDelta::default();
     let mut rx_cfds = rx.cfds.clone();
     let mut rx_order = rx.order.clone();
     let mut rx_quote = rx.quote.clone();
+    let mut rx_price_feed_status = rx.price_feed_status.clone();
     let mut rx_wallet = rx_wallet.inner().clone();
+    let mut rx_wallet_sync = rx_wallet_sync.inner().clone();
     let mut rx_maker_status = rx_maker_status.inner().clone();
 
     EventStream! {
         let wallet_info = rx_wallet.borrow().clone();
         yield wallet_info.to_sse_event();
 
+        let wallet_sync = *rx_wallet_sync.borrow();
+        yield wallet_sync.to_sse_event();
+
         let maker_status = rx_maker_status.borrow().clone();
         yield maker_status.to_sse_event();
 
@@ -61,7 +101,12 @@ pub async fn feed(
         let quote = rx_quote.borrow().clone();
         yield quote.to_sse_event();
 
-        let cfds = rx_cfds.borrow().clone();
+        let price_feed_status = rx_price_feed_status.borrow().clone();
+        yield price_feed_status.to_sse_event();
+
+        // Always start with a full snapshot that deltas are applied to
+        let cfds = projection::listed_cfds(rx_cfds.borrow().clone(), include_archived);
+        cfds_delta.changed(&cfds);
         yield cfds.to_sse_event();
 
         loop{
@@ -70,6 +115,10 @@ pub async fn feed(
                     let wallet_info = rx_wallet.borrow().clone();
                     yield wallet_info.to_sse_event();
                 },
+                Ok(()) = rx_wallet_sync.changed() => {
+                    let wallet_sync = *rx_wallet_sync.borrow();
+                    yield wallet_sync.to_sse_event();
+                },
                 Ok(()) = rx_maker_status.changed() => {
                     let maker_status = rx_maker_status.borrow().clone();
                     yield maker_status.to_sse_event();
@@ -79,13 +128,26 @@ pub async fn feed(
                     yield order.to_sse_event();
                 }
                 Ok(()) = rx_cfds.changed() => {
-                    let cfds = rx_cfds.borrow().clone();
-                    yield cfds.to_sse_event();
+                    let cfds = projection::listed_cfds(rx_cfds.borrow().clone(), include_archived);
+
+                    if delta {
+                        let changed = cfds_delta.changed(&cfds);
+
+                        if !changed.is_empty() {
+                            yield changed.to_sse_event();
+                        }
+                    } else {
+                        yield cfds.to_sse_event();
+                    }
                 }
                 Ok(()) = rx_quote.changed() => {
                     let quote = rx_quote.borrow().clone();
                     yield quote.to_sse_event();
                 }
+                Ok(()) = rx_price_feed_status.changed() => {
+                    let price_feed_status = rx_price_feed_status.borrow().clone();
+                    yield price_feed_status.to_sse_event();
+                }
             }
         }
     }
@@ -95,18 +157,53 @@ pub async fn feed(
 pub struct CfdOrderRequest {
     pub order_id: OrderId,
     pub quantity: Usd,
+    /// Overrides the fee rate of the order, in sat/vbyte.
+    #[serde(default)]
+    pub fee_rate: Option<u32>,
+    /// The highest price to enter a long position at.
+    #[serde(default)]
+    pub max_price: Option<Price>,
+    /// The lowest price to enter a short position at.
+    #[serde(default)]
+    pub min_price: Option<Price>,
+    /// One of the leverages offered by the order, defaults to the order's leverage.
+    #[serde(default)]
+    pub leverage: Option<Leverage>,
 }
 
 #[rocket::post("/cfd/order", data = "<cfd_order_request>")]
 pub async fn post_order_request(
     cfd_order_request: Json<CfdOrderRequest>,
     taker: &State<Taker>,
+    _auth: TokenAuthenticated,
 ) -> Result<status::Accepted<()>, HttpApiProblem> {
     taker
-        .take_offer(cfd_order_request.order_id, cfd_order_request.quantity)
+        .take_offer(
+            cfd_order_request.order_id,
+            cfd_order_request.quantity,
+            cfd_order_request.fee_rate,
+            SlippageBound {
+                max_price: cfd_order_request.max_price,
+                min_price: cfd_order_request.min_price,
+            },
+            cfd_order_request.leverage,
+        )
         .await
         .map_err(|e| {
-            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            let status = if e.downcast_ref::<CannotTakeOrder>().is_some() {
+                // The order changed since the user looked at it, asking them to review the
+                // current one
+                StatusCode::CONFLICT
+            } else if e.downcast_ref::<LeverageNotOffered>().is_some() {
+                StatusCode::BAD_REQUEST
+            } else if e.downcast_ref::<AtCapacity>().is_some() {
+                // Too many contract setups are running, asking them to try again later
+                StatusCode::TOO_MANY_REQUESTS
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+
+            HttpApiProblem::new(status)
                 .title("Order request failed")
                 .detail(e.to_string())
         })?;
@@ -120,36 +217,40 @@ pub async fn post_cfd_action(
     action: CfdAction,
     taker: &State<Taker>,
     feeds: &State<Feeds>,
+    pricing_mode: &State<PricingMode>,
+    _auth: TokenAuthenticated,
 ) -> Result<status::Accepted<()>, HttpApiProblem> {
     let result = match action {
         CfdAction::AcceptOrder
         | CfdAction::RejectOrder
-        | CfdAction::AcceptSettlement
-        | CfdAction::RejectSettlement
         | CfdAction::AcceptRollOver
         | CfdAction::RejectRollOver => {
             return Err(HttpApiProblem::new(StatusCode::BAD_REQUEST)
                 .detail(format!("taker cannot invoke action {}", action)));
         }
+        CfdAction::AcceptSettlement => taker.accept_settlement(id).await,
+        CfdAction::RejectSettlement => taker.reject_settlement(id).await,
         CfdAction::Commit => taker.commit(id).await,
+        CfdAction::CommitAndSettle => taker.commit_and_settle(id).await,
+        CfdAction::Refund => taker.refund(id).await,
+        CfdAction::PauseMonitoring => taker.pause_monitoring(id).await,
+        CfdAction::ResumeMonitoring => taker.resume_monitoring(id).await,
+        CfdAction::Archive => taker.archive(id).await,
         CfdAction::Settle => {
-            let quote: bitmex_price_feed::Quote = match feeds.quote.borrow().as_ref() {
-                Some(quote) => quote.clone().into(),
-                None => {
-                    return Err(HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
-                        .title("Quote unavailable")
-                        .detail("Cannot settle without current price information."))
-                }
-            };
-
-            let current_price = quote.for_taker();
+            let current_price = current_price(feeds, pricing_mode)?;
 
             taker.propose_settlement(id, current_price).await
         }
     };
 
     result.map_err(|e| {
-        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+        // Too many settlements are running, asking them to try again later
+        let status = match e.downcast_ref::<AtCapacity>() {
+            Some(_) => StatusCode::TOO_MANY_REQUESTS,
+            None => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        HttpApiProblem::new(status)
             .title(action.to_string() + " failed")
             .detail(e.to_string())
     })?;
@@ -157,6 +258,218 @@ pub async fn post_cfd_action(
     Ok(status::Accepted(None))
 }
 
+/// The outcome of proposing to settle a CFD as part of settling all CFDs.
+#[derive(Debug, Clone, Serialize)]
+pub struct SettlementResult {
+    pub order_id: OrderId,
+    pub proposed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Propose to settle all CFDs that can be settled collaboratively at the current price.
+///
+/// A failure to propose the settlement of one CFD does not prevent proposing it for the others.
+#[rocket::post("/settle-all")]
+pub async fn post_settle_all(
+    taker: &State<Taker>,
+    feeds: &State<Feeds>,
+    pricing_mode: &State<PricingMode>,
+    _auth: TokenAuthenticated,
+) -> Result<Json<Vec<SettlementResult>>, HttpApiProblem> {
+    let current_price = current_price(feeds, pricing_mode)?;
+
+    let attempts = taker.settle_all(current_price).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Settle all failed")
+            .detail(format!("{:#}", e))
+    })?;
+
+    let results = attempts
+        .into_iter()
+        .map(|attempt| SettlementResult {
+            order_id: attempt.order_id,
+            proposed: attempt.result.is_ok(),
+            error: attempt.result.err().map(|e| format!("{:#}", e)),
+        })
+        .collect();
+
+    Ok(Json(results))
+}
+
+fn current_price(
+    feeds: &State<Feeds>,
+    pricing_mode: &State<PricingMode>,
+) -> Result<Price, HttpApiProblem> {
+    let quote: price_feed::Quote = match feeds.quote.borrow().as_ref() {
+        Some(quote) => quote.clone().into(),
+        None => {
+            return Err(HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Quote unavailable")
+                .detail("Cannot settle without current price information."))
+        }
+    };
+
+    Ok(quote.price(*pricing_mode.inner()))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExitLevelsRequest {
+    /// Settle once the price reaches this level in favour of the position.
+    #[serde(default)]
+    pub take_profit: Option<Price>,
+    /// Settle once the price reaches this level against the position.
+    #[serde(default)]
+    pub stop_loss: Option<Price>,
+}
+
+/// Set the prices at which the CFD is proposed to be settled automatically.
+///
+/// Omitting both levels clears them.
+#[rocket::post("/cfd/<id>/exit-levels", data = "<exit_levels_request>")]
+pub async fn post_exit_levels(
+    id: OrderId,
+    exit_levels_request: Json<ExitLevelsRequest>,
+    taker: &State<Taker>,
+    _auth: TokenAuthenticated,
+) -> Result<status::Accepted<()>, HttpApiProblem> {
+    let levels = ExitLevels {
+        take_profit: exit_levels_request.take_profit,
+        stop_loss: exit_levels_request.stop_loss,
+    };
+
+    taker.set_exit_levels(id, levels).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Setting exit levels failed")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(status::Accepted(None))
+}
+
+#[rocket::get("/cfd/<id>/commit-tx")]
+pub async fn get_commit_tx(
+    id: OrderId,
+    taker: &State<Taker>,
+    _auth: TokenAuthenticated,
+) -> Result<Json<SignedCommitTx>, HttpApiProblem> {
+    let commit_tx = taker.commit_tx(id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Commit transaction unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(commit_tx))
+}
+
+/// The details of the contract of a CFD, without any secrets.
+#[rocket::get("/cfd/<id>/dlc")]
+pub async fn get_dlc_summary(
+    id: OrderId,
+    taker: &State<Taker>,
+) -> Result<Json<DlcSummary>, HttpApiProblem> {
+    let summary = taker.dlc_summary(id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Contract details unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(summary))
+}
+
+#[rocket::get("/cfd/<id>/receipt")]
+pub async fn get_settlement_receipt(
+    id: OrderId,
+    feeds: &State<Feeds>,
+) -> Result<Json<SettlementReceipt>, HttpApiProblem> {
+    let receipt = feeds
+        .cfds
+        .borrow()
+        .iter()
+        .find(|cfd| cfd.order_id == id)
+        .and_then(|cfd| cfd.receipt.clone())
+        .ok_or_else(|| {
+            HttpApiProblem::new(StatusCode::NOT_FOUND)
+                .title("Settlement receipt unavailable")
+                .detail(format!("CFD {} is unknown or not closed yet", id))
+        })?;
+
+    Ok(Json(receipt))
+}
+
+/// The price at which the CFD breaks even after paying `fees` (in satoshis, none if not given).
+#[rocket::get("/cfd/<id>/break-even?<fees>")]
+pub async fn get_break_even(
+    id: OrderId,
+    fees: Option<u64>,
+    feeds: &State<Feeds>,
+) -> Result<Json<BreakEven>, HttpApiProblem> {
+    let break_even = feeds
+        .cfds
+        .borrow()
+        .iter()
+        .find(|cfd| cfd.order_id == id)
+        .ok_or_else(|| {
+            HttpApiProblem::new(StatusCode::NOT_FOUND)
+                .title("Break-even price unavailable")
+                .detail(format!("CFD {} is unknown", id))
+        })?
+        .break_even(Amount::from_sat(fees.unwrap_or_default()))
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Break-even price unavailable")
+                .detail(format!("{:#}", e))
+        })?;
+
+    Ok(Json(break_even))
+}
+
+#[rocket::get("/cfd/<id>/close-options")]
+pub async fn get_close_options(
+    id: OrderId,
+    taker: &State<Taker>,
+    feeds: &State<Feeds>,
+    pricing_mode: &State<PricingMode>,
+) -> Result<Json<Vec<CloseOption>>, HttpApiProblem> {
+    let current_price = feeds
+        .quote
+        .borrow()
+        .clone()
+        .map(|quote| price_feed::Quote::from(quote).price(*pricing_mode.inner()));
+
+    let close_options = taker.close_options(id, current_price).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Close options unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(close_options))
+}
+
+/// What rolling over and settling the CFD would cost right now, to warn about insufficient funds
+/// before taking either action.
+#[rocket::get("/cfd/<id>/action-costs")]
+pub async fn get_action_costs(
+    id: OrderId,
+    taker: &State<Taker>,
+    feeds: &State<Feeds>,
+    pricing_mode: &State<PricingMode>,
+) -> Result<Json<ActionCosts>, HttpApiProblem> {
+    let current_price = feeds
+        .quote
+        .borrow()
+        .clone()
+        .map(|quote| price_feed::Quote::from(quote).price(*pricing_mode.inner()));
+
+    let action_costs = taker.action_costs(id, current_price).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Action costs unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(action_costs))
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct WalletReinitialiseRequest {
     pub seed_words: String,
@@ -166,6 +479,7 @@ pub struct WalletReinitialiseRequest {
 pub async fn post_wallet_reinitialise(
     wallet_reinitialise_request: Json<WalletReinitialiseRequest>,
     taker: &State<Taker>,
+    _auth: TokenAuthenticated,
 ) -> Result<status::Accepted<()>, HttpApiProblem> {
     taker
         .reinitialise_wallet(&wallet_reinitialise_request.seed_words)
@@ -179,9 +493,249 @@ pub async fn post_wallet_reinitialise(
     Ok(status::Accepted(None))
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalletRescanRequest {
+    /// Height of the block before which the wallet cannot have received funds.
+    pub from_height: Option<u32>,
+}
+
+/// Scan the blockchain for the funds of the wallet again, e.g. after reinitialising it.
+///
+/// The rescan runs in the background, its progress is reported through the `wallet_sync` event of
+/// the feed.
+#[rocket::post("/wallet/rescan", data = "<wallet_rescan_request>")]
+pub async fn post_wallet_rescan(
+    wallet_rescan_request: Json<WalletRescanRequest>,
+    taker: &State<Taker>,
+    _auth: TokenAuthenticated,
+) -> Result<status::Accepted<()>, HttpApiProblem> {
+    taker
+        .rescan_wallet(wallet_rescan_request.from_height)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Wallet rescan request failed")
+                .detail(format!("{:#}", e))
+        })?;
+
+    Ok(status::Accepted(None))
+}
+
+/// Hand out an address of the wallet that was not handed out before, e.g. to deposit funds on.
+#[rocket::get("/wallet/address")]
+pub async fn get_wallet_address(
+    taker: &State<Taker>,
+    _auth: TokenAuthenticated,
+) -> Result<Json<wallet::ReceiveAddress>, HttpApiProblem> {
+    let address = taker.new_address().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to get new address")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(address))
+}
+
+/// Export the seed of the taker so that a daemon-generated wallet can be backed up.
+///
+/// The backup is restored with the `restore` subcommand, `/wallet/reinitialise` does not accept
+/// it.
+///
+/// Because the response grants full control over the funds, the export is refused unless an API
+/// token is configured and the caller has to explicitly confirm it with `?confirm=true`.
+#[rocket::get("/wallet/backup?<confirm>")]
+pub async fn get_wallet_backup(
+    confirm: Option<bool>,
+    seed: &State<Seed>,
+    network: &State<Network>,
+    _auth: TokenRequired,
+) -> Result<Json<seed::Backup>, HttpApiProblem> {
+    if confirm != Some(true) {
+        return Err(HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Confirmation required")
+            .detail("The backup grants full control over the wallet, confirm the export with `?confirm=true`"));
+    }
+
+    tracing::warn!("Exporting seed backup");
+
+    let backup = seed.backup(*network.inner()).map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Seed backup failed")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(backup))
+}
+
+/// A "catcher" for all 401 responses, i.e. requests lacking the configured API token.
+#[rocket::catch(401)]
+pub fn unauthorized() -> HttpApiProblem {
+    HttpApiProblem::new(StatusCode::UNAUTHORIZED)
+        .title("Unauthorized")
+        .detail("Missing or invalid API token, expected `Authorization: Bearer <token>`")
+}
+
+/// A "catcher" for all 403 responses, i.e. requests to routes that require an API token to be
+/// configured.
+#[rocket::catch(403)]
+pub fn forbidden() -> HttpApiProblem {
+    HttpApiProblem::new(StatusCode::FORBIDDEN)
+        .title("Forbidden")
+        .detail("This route is only available if the daemon is started with an API token")
+}
+
 #[rocket::get("/alive")]
 pub fn get_health_check() {}
 
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionInfo {
+    pub daemon_version: &'static str,
+    pub protocol_version: wire::Version,
+    pub network: Network,
+    pub settlement_interval_hours: i64,
+}
+
+#[rocket::get("/version")]
+pub fn get_version(network: &State<Network>) -> Json<VersionInfo> {
+    Json(VersionInfo {
+        daemon_version: env!("VERGEN_GIT_SEMVER_LIGHTWEIGHT"),
+        protocol_version: wire::Version::current(),
+        network: *network.inner(),
+        settlement_interval_hours: SETTLEMENT_INTERVAL.whole_hours(),
+    })
+}
+
+#[rocket::get("/portfolio")]
+pub fn get_portfolio(feeds: &State<Feeds>) -> Json<projection::Portfolio> {
+    let cfds = feeds.cfds.borrow();
+
+    Json(projection::Portfolio::new(&cfds))
+}
+
+/// How long the transactions published for our CFDs took to confirm.
+#[rocket::get("/confirmation-times")]
+pub fn get_confirmation_times(feeds: &State<Feeds>) -> Json<projection::ConfirmationTimes> {
+    let cfds = feeds.cfds.borrow();
+
+    Json(projection::ConfirmationTimes::new(&cfds))
+}
+
+/// The order currently offered by the maker, `null` if there is none.
+#[rocket::get("/order")]
+pub fn get_order(feeds: &State<Feeds>) -> Json<Option<CfdOrder>> {
+    let order = feeds.order.borrow().clone();
+
+    Json(order)
+}
+
+/// The status of the price feed, including how often we reconnected to the price source.
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceFeedHealth {
+    #[serde(flatten)]
+    pub status: PriceFeedStatus,
+    #[serde(flatten)]
+    pub restarts: RestartStats,
+}
+
+#[rocket::get("/price-feed/status")]
+pub fn get_price_feed_status(
+    feeds: &State<Feeds>,
+    restart_stats: &State<watch::Receiver<RestartStats>>,
+) -> Json<PriceFeedHealth> {
+    let status = feeds.price_feed_status.borrow();
+
+    Json(PriceFeedHealth {
+        status: PriceFeedStatus::from(&*status),
+        restarts: restart_stats.borrow().clone(),
+    })
+}
+
+/// The connection to the maker, including the health of each address the maker resolved to.
+#[derive(Debug, Clone, Serialize)]
+pub struct MakerStatus {
+    #[serde(flatten)]
+    pub connection: to_sse_event::ConnectionStatus,
+    pub addresses: MakerAddresses,
+}
+
+#[rocket::get("/maker/status")]
+pub fn get_maker_status(
+    rx_maker_status: &State<watch::Receiver<ConnectionStatus>>,
+    rx_maker_addresses: &State<watch::Receiver<MakerAddresses>>,
+) -> Json<MakerStatus> {
+    let connection = to_sse_event::ConnectionStatus::from(&*rx_maker_status.borrow());
+    let addresses = rx_maker_addresses.borrow().clone();
+
+    Json(MakerStatus {
+        connection,
+        addresses,
+    })
+}
+
+/// List the protocols currently running with the maker.
+#[rocket::get("/protocols")]
+pub async fn get_running_protocols(
+    taker: &State<Taker>,
+) -> Result<Json<Vec<taker_cfd::RunningProtocol>>, HttpApiProblem> {
+    let running = taker.running_protocols().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Running protocols unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(running))
+}
+
+/// Cancel the protocol running for a CFD, e.g. because the maker stopped responding.
+#[rocket::post("/protocols/<id>/cancel")]
+pub async fn post_cancel_protocol(
+    id: OrderId,
+    taker: &State<Taker>,
+    _auth: TokenAuthenticated,
+) -> Result<Json<taker_cfd::Protocol>, HttpApiProblem> {
+    let protocol = taker.cancel_protocol(id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Cancelling protocol failed")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(protocol))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RolloverCheck {
+    /// The number of CFDs a rollover was started for.
+    pub started: usize,
+}
+
+/// Check all CFDs for rollover right away, e.g. after fixing what made previous rollovers fail.
+#[rocket::post("/rollover/check")]
+pub async fn post_rollover_check(
+    taker: &State<Taker>,
+    _auth: TokenAuthenticated,
+) -> Result<Json<RolloverCheck>, HttpApiProblem> {
+    let started = taker.trigger_rollover_check().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Rollover check failed")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(RolloverCheck { started }))
+}
+
+#[rocket::get("/oracle/health")]
+pub async fn get_oracle_health(
+    taker: &State<Taker>,
+) -> Result<Json<oracle::Health>, HttpApiProblem> {
+    let health = taker.oracle_health().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::SERVICE_UNAVAILABLE)
+            .title("Oracle unavailable")
+            .detail(format!("{:#}", e))
+    })?;
+
+    Ok(Json(health))
+}
+
 #[derive(Debug, Clone, Copy, Deserialize)]
 pub struct MarginRequest {
     pub price: Price,
@@ -239,7 +793,8 @@ pub struct WithdrawRequest {
 pub async fn post_withdraw_request(
     withdraw_request: Json<WithdrawRequest>,
     taker: &State<Taker>,
-    network: &State<Network>,
+    explorer: &State<Explorer>,
+    _auth: TokenAuthenticated,
 ) -> Result<String, HttpApiProblem> {
     let amount =
         (withdraw_request.amount != bdk::bitcoin::Amount::ZERO).then(|| withdraw_request.amount);
@@ -257,5 +812,203 @@ pub async fn post_withdraw_request(
                 .detail(e.to_string())
         })?;
 
-    Ok(projection::to_mempool_url(txid, *network.inner()))
+    Ok(explorer.tx_url(txid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use daemon::auth::ApiToken;
+    use daemon::auto_commit::CommitPolicy;
+    use daemon::auto_refund::RefundStrategy;
+    use daemon::clock::SystemClock;
+    use daemon::db;
+    use daemon::model::cfd::MinimumSize;
+    use daemon::model::cfd::Role;
+    use daemon::model::cfd::SettlementRejectionPolicy;
+    use daemon::model::Identity;
+    use daemon::monitor;
+    use daemon::oracle::UnknownEventPolicy;
+    use daemon::process_manager;
+    use daemon::process_manager::CetPolicy;
+    use daemon::wire::Version;
+    use daemon::MAX_CETS;
+    use daemon::MAX_CONCURRENT_PROTOCOLS;
+    use daemon::N_PAYOUTS;
+    use maia::secp256k1_zkp::schnorrsig;
+    use rocket::http::Header;
+    use rocket::local::asynchronous;
+    use rocket::local::blocking::Client;
+    use rocket::Build;
+    use rocket::Rocket;
+
+    #[test]
+    fn routes_are_open_without_api_token() {
+        let client = Client::tracked(rocket(None)).unwrap();
+
+        let response = client.get("/protected").dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn routes_are_token_protected() {
+        let client = Client::tracked(rocket(Some("secret"))).unwrap();
+
+        let response = client.get("/protected").dispatch();
+
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn wrong_token_is_rejected() {
+        let client = Client::tracked(rocket(Some("secret"))).unwrap();
+
+        let response = client
+            .get("/protected")
+            .header(Header::new("Authorization", "Bearer wrong"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn correct_token_grants_access() {
+        let client = Client::tracked(rocket(Some("secret"))).unwrap();
+
+        let response = client
+            .get("/protected")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn token_required_routes_are_forbidden_without_api_token() {
+        let client = Client::tracked(rocket(None)).unwrap();
+
+        let response = client.get("/sensitive").dispatch();
+
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn token_required_routes_are_token_protected() {
+        let client = Client::tracked(rocket(Some("secret"))).unwrap();
+
+        let without_token = client.get("/sensitive").dispatch().status();
+        let with_token = client
+            .get("/sensitive")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch()
+            .status();
+
+        assert_eq!(without_token, Status::Unauthorized);
+        assert_eq!(with_token, Status::Ok);
+    }
+
+    #[tokio::test]
+    async fn commit_tx_is_token_protected() {
+        let rocket = rocket(Some("secret"))
+            .mount("/", rocket::routes![get_commit_tx])
+            .manage(taker().await);
+        let client = asynchronous::Client::tracked(rocket).await.unwrap();
+
+        let response = client
+            .get(format!("/cfd/{}/commit-tx", OrderId::default()))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[rocket::get("/protected")]
+    async fn protected(_auth: TokenAuthenticated) {}
+
+    #[rocket::get("/sensitive")]
+    async fn sensitive(_auth: TokenRequired) {}
+
+    /// Constructs a Rocket instance for testing.
+    fn rocket(api_token: Option<&str>) -> Rocket<Build> {
+        let rocket = rocket::build()
+            .mount("/", rocket::routes![protected, sensitive])
+            .register("/", rocket::catchers![unauthorized, forbidden]);
+
+        match api_token {
+            Some(api_token) => rocket.manage(api_token.parse::<ApiToken>().unwrap()),
+            None => rocket,
+        }
+    }
+
+    /// Starts a taker that neither connects to a maker nor to electrum.
+    async fn taker() -> Taker {
+        let db = sqlx::SqlitePool::connect(":memory:").await.unwrap();
+        db::run_migrations(&db).await.unwrap();
+
+        let (wallet, _) = xtra::Context::<wallet::Actor>::new(None);
+        let (projection, _) = xtra::Context::<projection::Actor>::new(None);
+        let oracle_pk = "ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7"
+            .parse::<schnorrsig::PublicKey>()
+            .unwrap();
+        let maker_identity = Identity::new(x25519_dalek::PublicKey::from(
+            &x25519_dalek::StaticSecret::from([1u8; 32]),
+        ));
+
+        let taker = TakerActorSystem::new(
+            db.clone(),
+            wallet,
+            oracle_pk,
+            x25519_dalek::StaticSecret::from([2u8; 32]),
+            |channel| {
+                oracle::Actor::new(
+                    db.clone(),
+                    channel,
+                    SETTLEMENT_INTERVAL,
+                    "http://127.0.0.1:1".parse().unwrap(),
+                )
+            },
+            |_| async { Ok(MonitorStub) },
+            N_PAYOUTS,
+            MinimumSize::default(),
+            MAX_CETS,
+            std::time::Duration::from_secs(10),
+            std::time::Duration::from_secs(10),
+            projection,
+            maker_identity,
+            None,
+            Version::current(),
+            CommitPolicy::Immediate,
+            CetPolicy::AutoPublish,
+            SettlementRejectionPolicy::Commit,
+            PricingMode::default_for(Role::Taker),
+            RefundStrategy::Manual,
+            UnknownEventPolicy::Ignore,
+            None,
+            MAX_CONCURRENT_PROTOCOLS,
+            process_manager::REBROADCAST_INTERVAL,
+            oracle::SYNC_INTERVAL,
+            Arc::new(SystemClock),
+        )
+        .await
+        .unwrap();
+
+        Arc::new(taker)
+    }
+
+    /// Stands in for the monitor, which needs an electrum server.
+    struct MonitorStub;
+
+    impl xtra::Actor for MonitorStub {}
+
+    #[xtra_productivity::xtra_productivity(message_impl = false)]
+    impl MonitorStub {
+        async fn handle(&mut self, _msg: monitor::Sync) {}
+
+        async fn handle(&mut self, _msg: monitor::StartMonitoring) {}
+
+        async fn handle(&mut self, _msg: monitor::CollaborativeSettlement) {}
+
+        async fn handle(&mut self, _msg: oracle::Attestation) {}
+    }
 }
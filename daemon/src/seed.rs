@@ -1,16 +1,44 @@
 use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
 use bdk::bitcoin::util::bip32::ExtendedPrivKey;
 use bdk::bitcoin::Network;
 use hkdf::Hkdf;
 use rand::Rng;
+use serde::Deserialize;
+use serde::Serialize;
 use sha2::Sha256;
 use std::convert::TryInto;
 use std::path::Path;
+use std::str::FromStr;
 
 #[derive(Copy, Clone)]
 pub struct Seed([u8; 256]);
 
+/// Which identity to present to the maker, see [`Seed::session_identity`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdentityPolicy {
+    /// Always present the identity derived from the seed, which lets the maker link all sessions.
+    Fixed,
+    /// Present a new identity on every start, derived from the seed at a rotating index.
+    ///
+    /// This only stops the maker from linking sessions by identity: all CFDs taken within a
+    /// session share its identity, the maker still sees the order ids of CFDs from earlier
+    /// sessions when they are rolled over or settled, and the lock transactions of all CFDs are
+    /// funded by the same wallet.
+    PerSession,
+}
+
+impl IdentityPolicy {
+    pub fn from_ephemeral(ephemeral: bool) -> Self {
+        if ephemeral {
+            Self::PerSession
+        } else {
+            Self::Fixed
+        }
+    }
+}
+
 impl Seed {
     /// Initialize a [`Seed`] from a path.
     /// Generates new seed if there was no seed found in the given path
@@ -58,6 +86,38 @@ impl Seed {
         Ok(ext_priv_key)
     }
 
+    /// Export the seed together with the master key of the wallet derived from it.
+    ///
+    /// The seed consists of 256 random bytes and thus cannot be represented as BIP39 mnemonic.
+    pub fn backup(&self, network: Network) -> Result<Backup> {
+        Ok(Backup {
+            seed: hex::encode(&self.0),
+            wallet_xprv: self.derive_extended_priv_key(network)?.to_string(),
+        })
+    }
+
+    /// Restore the seed exported with [`Seed::backup`].
+    ///
+    /// Fails if the wallet key of the backup was not derived from its seed, e.g. because one of
+    /// them was mistyped.
+    pub fn restore(backup: &Backup) -> Result<Self> {
+        let bytes = hex::decode(&backup.seed).context("Seed of the backup is not hex")?;
+        let seed = Seed(
+            bytes
+                .try_into()
+                .map_err(|_| anyhow!("Seed of the backup doesn't fit into array"))?,
+        );
+
+        let wallet_xprv = ExtendedPrivKey::from_str(&backup.wallet_xprv)
+            .context("Wallet key of the backup is invalid")?;
+        anyhow::ensure!(
+            seed.derive_extended_priv_key(wallet_xprv.network)? == wallet_xprv,
+            "Wallet key of the backup does not belong to its seed"
+        );
+
+        Ok(seed)
+    }
+
     pub fn derive_auth_password<P: From<[u8; 32]>>(&self) -> P {
         let mut password = [0u8; 32];
 
@@ -69,10 +129,64 @@ impl Seed {
     }
 
     pub fn derive_identity(&self) -> (x25519_dalek::PublicKey, x25519_dalek::StaticSecret) {
+        self.derive_identity_from(b"NOISE_STATIC_SECRET")
+    }
+
+    /// Derive the ephemeral identity at `index`, unrelated to the one of [`Seed::derive_identity`].
+    pub fn derive_identity_at(
+        &self,
+        index: u32,
+    ) -> (x25519_dalek::PublicKey, x25519_dalek::StaticSecret) {
+        let info = [b"NOISE_EPHEMERAL_SECRET".as_ref(), &index.to_be_bytes()].concat();
+
+        self.derive_identity_from(&info)
+    }
+
+    /// The identity to present to the maker in this session.
+    ///
+    /// With [`IdentityPolicy::PerSession`] the index following the one stored at `index_path` is
+    /// persisted before its identity is used, so no identity is presented twice. The wallet is
+    /// derived from the seed independently of the identity. The maker records the identity with
+    /// every CFD taken in the session, which is why the taker records it as well, see
+    /// [`crate::db::insert_session_identity`].
+    pub async fn session_identity(
+        &self,
+        policy: IdentityPolicy,
+        index_path: &Path,
+    ) -> Result<(x25519_dalek::PublicKey, x25519_dalek::StaticSecret)> {
+        match policy {
+            IdentityPolicy::Fixed => Ok(self.derive_identity()),
+            IdentityPolicy::PerSession => {
+                let index = match load_identity_index(index_path).await? {
+                    Some(last_index) => last_index
+                        .checked_add(1)
+                        .context("No ephemeral identity left to use")?,
+                    None => 0,
+                };
+                store_identity_index(index_path, index)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to persist identity index to {}",
+                            index_path.display()
+                        )
+                    })?;
+
+                tracing::info!(index, "Using ephemeral identity");
+
+                Ok(self.derive_identity_at(index))
+            }
+        }
+    }
+
+    fn derive_identity_from(
+        &self,
+        info: &[u8],
+    ) -> (x25519_dalek::PublicKey, x25519_dalek::StaticSecret) {
         let mut secret = [0u8; 32];
 
         Hkdf::<Sha256>::new(None, &self.0)
-            .expand(b"NOISE_STATIC_SECRET", &mut secret)
+            .expand(info, &mut secret)
             .expect("okm array is of correct length");
 
         let identity_sk = x25519_dalek::StaticSecret::from(secret);
@@ -80,6 +194,45 @@ impl Seed {
     }
 }
 
+/// Everything needed to restore a daemon, see [`Seed::backup`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    /// The seed as hex, restores wallet and identity, see [`Seed::restore`].
+    pub seed: String,
+    /// The master key of the wallet, can be imported into any wallet supporting BIP84.
+    pub wallet_xprv: String,
+}
+
+async fn load_identity_index(index_path: &Path) -> Result<Option<u32>> {
+    if !index_path.exists() {
+        return Ok(None);
+    }
+
+    let last_index = tokio::fs::read_to_string(index_path)
+        .await?
+        .trim()
+        .parse()
+        .with_context(|| {
+            format!(
+                "Failed to parse identity index from {}",
+                index_path.display()
+            )
+        })?;
+
+    Ok(Some(last_index))
+}
+
+/// Store the index by replacing the file, so an interrupted write cannot leave a truncated index
+/// that would make us present an identity again.
+async fn store_identity_index(index_path: &Path, index: u32) -> Result<()> {
+    let tmp_path = index_path.with_extension("tmp");
+
+    tokio::fs::write(&tmp_path, index.to_string()).await?;
+    tokio::fs::rename(&tmp_path, index_path).await?;
+
+    Ok(())
+}
+
 impl Default for Seed {
     fn default() -> Self {
         let mut seed = [0u8; 256];
@@ -88,3 +241,81 @@ impl Default for Seed {
         Self(seed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_contains_seed_and_wallet_key() {
+        let seed = Seed::default();
+
+        let backup = seed.backup(Network::Testnet).unwrap();
+
+        assert_eq!(hex::decode(&backup.seed).unwrap(), seed.0.to_vec());
+        assert_eq!(
+            backup.wallet_xprv,
+            seed.derive_extended_priv_key(Network::Testnet)
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn restored_backup_derives_same_wallet_key() {
+        let seed = Seed::default();
+        let backup = seed.backup(Network::Testnet).unwrap();
+
+        let restored = Seed::restore(&backup).unwrap();
+
+        assert_eq!(
+            restored.derive_extended_priv_key(Network::Testnet).unwrap(),
+            seed.derive_extended_priv_key(Network::Testnet).unwrap()
+        );
+        assert_eq!(restored.derive_identity().0, seed.derive_identity().0);
+    }
+
+    #[test]
+    fn backup_with_wallet_key_of_other_seed_is_refused() {
+        let mut backup = Seed::default().backup(Network::Testnet).unwrap();
+        backup.wallet_xprv = Seed::default()
+            .backup(Network::Testnet)
+            .unwrap()
+            .wallet_xprv;
+
+        assert!(Seed::restore(&backup).is_err());
+    }
+
+    #[tokio::test]
+    async fn sessions_present_different_identities_of_same_wallet() {
+        let seed = Seed::default();
+        let index_path = std::env::temp_dir().join(format!(
+            "identity-index-{}",
+            rand::thread_rng().gen::<u64>()
+        ));
+        let wallet = seed.derive_extended_priv_key(Network::Testnet).unwrap();
+
+        let (first, _) = seed
+            .session_identity(IdentityPolicy::PerSession, &index_path)
+            .await
+            .unwrap();
+        let (second, _) = seed
+            .session_identity(IdentityPolicy::PerSession, &index_path)
+            .await
+            .unwrap();
+        let _ = std::fs::remove_file(&index_path);
+
+        let (fixed, _) = seed.derive_identity();
+        assert_ne!(first, second);
+        assert_ne!(first, fixed);
+        assert_ne!(second, fixed);
+
+        // Both identities can be derived again from the seed, which the wallet is derived from
+        assert_eq!(seed.derive_identity_at(0).0, first);
+        assert_eq!(seed.derive_identity_at(1).0, second);
+        assert_eq!(
+            seed.derive_extended_priv_key(Network::Testnet).unwrap(),
+            wallet
+        );
+    }
+}
@@ -0,0 +1,415 @@
+//! Offline check that key derivation and the signing of contract transactions work.
+//!
+//! Sets up a contract between two in-memory parties using fixed oracle data, then signs the
+//! transactions a party might have to publish. Fails on the first step that does not work, e.g.
+//! because the build links against a broken crypto library.
+
+use crate::bdk_ext::new_test_wallet;
+use crate::model::cfd::calculate_long_margin;
+use crate::model::cfd::calculate_short_margin;
+use crate::model::cfd::Dlc;
+use crate::model::cfd::OrderId;
+use crate::model::cfd::Role;
+use crate::model::cfd::SettlementProposal;
+use crate::model::BitMexPriceEventId;
+use crate::model::Leverage;
+use crate::model::Price;
+use crate::model::Timestamp;
+use crate::model::Usd;
+use crate::oracle;
+use crate::seed::Seed;
+use crate::setup_contract;
+use crate::setup_contract::SetupParams;
+use crate::wallet;
+use crate::wire::SetupMsg;
+use crate::Tasks;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::hashes::Hash;
+use bdk::bitcoin::secp256k1;
+use bdk::bitcoin::util::bip32::DerivationPath;
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+use bdk::bitcoin::Amount;
+use bdk::bitcoin::Network;
+use bdk::wallet::tx_builder::TxOrdering;
+use bdk::wallet::AddressIndex;
+use bdk::FeeRate;
+use bdk::KeychainKind;
+use bdk::SignOptions;
+use futures::channel::mpsc;
+use futures::SinkExt;
+use maia::secp256k1_zkp::schnorrsig;
+use maia::secp256k1_zkp::SecretKey;
+use maia::PartyParams;
+use maia::TxBuilderExt;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rust_decimal_macros::dec;
+use std::str::FromStr;
+use xtra::prelude::MessageChannel;
+use xtra::Actor as _;
+use xtra_productivity::xtra_productivity;
+
+const ORACLE_PK: &str = "ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7";
+const EVENT_ID: &str = "/x/BitMEX/BXBT/2021-10-05T02:00:00.price?n=20";
+const NONCE_PKS: [&str; 20] = [
+    "d02d163cf9623f567c4e3faf851a9266ac1ede13da4ca4141f3a7717fba9a739",
+    "bc310f26aa5addbc382f653d8530aaead7c25e3546abc24639f490e36d4bdb88",
+    "2661375f570dcc32300d442e85b6d72dfa3232dccda45e8fb4a2d1e758d1d374",
+    "fcc68fbf071d391b14c0867cb4defb5a8abc12418dff3dfc2f84fd4025cb2716",
+    "cf5c2b7fe3851c64a7ff9635a9bfc50cdd301401d002f2da049f4c6a20e8457b",
+    "14f1005d8c2832a2c4666dd732dd9bb3af9c8f70ebcdaec96869b1ca0c8e0de6",
+    "299ee1c9c20fab8b067adf452a7d7661b5e7f5dd6bc707562805002e7cb8443e",
+    "bcb4e5a594346de298993a7a31762f598b5224b977e23182369e9ed3e5127f78",
+    "25e09a16ee5d469069abfb62cd5e1f20af50cf15241f571e64fa28b127304574",
+    "3ed5a1422f43299caf281123aba88bd4bc61ec863f4afb79c7ce7663ad44db5d",
+    "a7e0f61212735c192c4bf16e0a3e925e65f9f3feb6f1e5e8d6f5c18cf2dbb5a8",
+    "a36a631015d9036d0c321fea7cf12f589aa196e7279b4a290de5112c2940e540",
+    "b5bdd931f81970139e7301ac654b378077c3ed993ca7893ed93fee5fc6f7a782",
+    "00090816e256b41e042dce38bde99ab3cf9482f9b066836988d3ed54833638e8",
+    "3530408e93c251f5f488d3b1c608157177c459d6fab1966abebf765bcc9338d2",
+    "603269ce88d112ff7fcfcaab82f228be97deca37f8190084d509c71b51a30432",
+    "f0587414fcc6c56aef11d4a1d287ad6b55b237c5b8a5d5d93eb9ca06f6466ccf",
+    "763009afb0ffd99c7b835488cb3b0302f3b78f59bbfd5292bedab8ef9da8c1b7",
+    "3867af9048309a05004a164bdea09899f23ff1d83b6491b2b53a1b7b92e0eb2e",
+    "688118e6b59e27944c277513db2711a520f4283c7c53a11f58d9f6a46d82c964",
+];
+/// The price attested to by the oracle in [`ATTESTATIONS`].
+const ATTESTED_PRICE: u64 = 49262;
+const ATTESTATIONS: [&str; 20] = [
+    "5bc7663195971daaa1e3e6a81b4bca65882791644bc446fc060cbc118a3ace0f",
+    "721d0cb56a0778a1ca7907f81a0787f34385b13f854c845c4c5539f7f6267958",
+    "044aeef0d525c8ff48758c80939e95807bc640990cc03f53ab6fc0b262045221",
+    "79f5175423ec6ee69c8d0e55251db85f3015c2edfa5a03095443fbbf35eb2282",
+    "233b9ec549e9cc7c702109d29636db85a3ec63a66f3b53444bcc7586d36ca439",
+    "2961a00320b7c9a70220060019a6ca88e18c205fadd2f873c174e5ccbbed527e",
+    "bdb76e8f81c39ade4205ead9b68118757fc49ec22769605f26ef904b235283d6",
+    "6e75dafedf4ed685513ec1f5c93508de4fad2be05b46001ac00c03474f4690e1",
+    "cfcfc27eb9273b343b3042f0386e77efe329066be079788bb00ab47d72f26780",
+    "2d931ffd2963e74566365674583abc427bdb6ae571c4887d81f1920f0850665d",
+    "33b6f1112fa046cbc04be44c615e70519702662c1f72d8d49b3c4613614a8a46",
+    "19e569b15410fa9a758c1a6c211eae8c1547efbe0ac6a7709902be93415f2f09",
+    "d859dd5c9a58e1836d1eea3ebe7f48198a681d29e5a5cd6922532d2e94a53a1d",
+    "3387eb2ad5e64cd102167766bb72b447f4a2e5129d161e422f9d41cd7d1cc281",
+    "db35a9778a1e3abc8d8ab2f4a79346ae2154c9e0b4932d859d1f3e244f67ae76",
+    "c3be969e8b889cfb2ece71123e6be5538a2d3a1229637b18bccc179073c38059",
+    "6f73263f430e10b82d0fd06c4ddd3b8a6b58c3e756745bd0d9e71a399e517921",
+    "0818c9c245d7d2162cd393c562a121f80405a27d22ae465e95030c31ebb4bd24",
+    "b7c03f0bd6d63bd78ad4ea0f3452ff9717ba65ca42038e6e90a1aa558b7942dc",
+    "90c4d8ec9f408ccb62a62daa993c20f2f86799e1fdea520c6d060418e55fd216",
+];
+
+/// Small enough to keep the number of CETs and thus the runtime low.
+const N_PAYOUTS: usize = 20;
+const REFUND_TIMELOCK: u32 = 144;
+
+/// Run all checks, returning the first one that failed.
+pub async fn run(seed: &Seed, network: Network) -> Result<()> {
+    check_key_derivation(seed, network).context("Key derivation failed")?;
+    tracing::info!("Key derivation works");
+
+    let (maker, taker) = set_up_contract().await.context("Contract setup failed")?;
+    tracing::info!("Contract setup works");
+
+    check_commit_tx(&maker, &taker).context("Signing the commit transaction failed")?;
+    tracing::info!("Signing the commit transaction works");
+
+    check_refund_tx(&maker, &taker).context("Signing the refund transaction failed")?;
+    tracing::info!("Signing the refund transaction works");
+
+    check_cet(&maker, &taker).context("Signing the CET failed")?;
+    tracing::info!("Signing the CET works");
+
+    check_close_tx(&maker, &taker).context("Signing the collaborative close failed")?;
+    tracing::info!("Signing the collaborative close works");
+
+    Ok(())
+}
+
+fn check_key_derivation(seed: &Seed, network: Network) -> Result<()> {
+    let (identity_pk, identity_sk) = seed.derive_identity();
+    if x25519_dalek::PublicKey::from(&identity_sk) != identity_pk {
+        bail!("Identity public key does not match the secret key");
+    }
+
+    let ext_priv_key = seed.derive_extended_priv_key(network)?;
+    let wallet = bdk::Wallet::new_offline(
+        bdk::template::Bip84(ext_priv_key, KeychainKind::External),
+        Some(bdk::template::Bip84(ext_priv_key, KeychainKind::Internal)),
+        network,
+        bdk::database::MemoryDatabase::new(),
+    )?;
+    wallet.get_address(AddressIndex::Peek(0))?;
+
+    let secp = secp256k1::Secp256k1::new();
+    let path = DerivationPath::from_str("m/84'/0'/0'/0/0")?;
+    let sk = ext_priv_key.derive_priv(&secp, &path)?.private_key.key;
+    let pk = secp256k1::PublicKey::from_secret_key(&secp, &sk);
+    let message = secp256k1::Message::from_slice(&sha256::Hash::hash(b"itchysats").into_inner())?;
+    let signature = secp.sign(&message, &sk);
+    secp.verify(&message, &signature, &pk)
+        .context("Signature of derived key does not verify")?;
+
+    Ok(())
+}
+
+/// Set up a contract between an in-memory maker and taker, returning the DLC of each party.
+pub(crate) async fn set_up_contract() -> Result<(Dlc, Dlc)> {
+    let mut tasks = Tasks::default();
+    let mut rng = StdRng::from_seed([0u8; 32]);
+
+    let (maker_wallet, fut) = Wallet::new(&mut rng)?.create(None).run();
+    tasks.add(fut);
+    let (taker_wallet, fut) = Wallet::new(&mut rng)?.create(None).run();
+    tasks.add(fut);
+
+    let oracle_pk = schnorrsig::PublicKey::from_str(ORACLE_PK)?;
+    let id = EVENT_ID.parse::<BitMexPriceEventId>()?;
+    let announcement = oracle::Announcement {
+        id,
+        expected_outcome_time: id.timestamp(),
+        nonce_pks: NONCE_PKS
+            .iter()
+            .map(|pk| schnorrsig::PublicKey::from_str(pk))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let order_id = OrderId::default();
+    let price = Price::new(dec!(50_000))?;
+    let quantity = Usd::new(dec!(100));
+    let leverage = Leverage::new(2)?;
+    let maker_margin = calculate_short_margin(price, quantity);
+    let taker_margin = calculate_long_margin(price, quantity, leverage);
+    let setup_params = |margin, counterparty_margin| {
+        SetupParams::new(
+            order_id,
+            margin,
+            counterparty_margin,
+            price,
+            quantity,
+            leverage,
+            REFUND_TIMELOCK,
+            1,
+        )
+    };
+
+    let (maker_sender, maker_receiver) = mpsc::unbounded::<SetupMsg>();
+    let (taker_sender, taker_receiver) = mpsc::unbounded::<SetupMsg>();
+
+    let maker = setup_contract::new(
+        taker_sender.sink_map_err(anyhow::Error::from),
+        maker_receiver,
+        (oracle_pk, announcement.clone()),
+        setup_params(maker_margin, taker_margin),
+        maker_wallet.clone_channel(),
+        maker_wallet.clone_channel(),
+        Role::Maker,
+        N_PAYOUTS,
+    );
+    let taker = setup_contract::new(
+        maker_sender.sink_map_err(anyhow::Error::from),
+        taker_receiver,
+        (oracle_pk, announcement),
+        setup_params(taker_margin, maker_margin),
+        taker_wallet.clone_channel(),
+        taker_wallet.clone_channel(),
+        Role::Taker,
+        N_PAYOUTS,
+    );
+
+    futures::future::try_join(maker, taker).await
+}
+
+fn check_commit_tx(maker: &Dlc, taker: &Dlc) -> Result<()> {
+    let maker_tx = maker.signed_commit_tx().context("Maker")?;
+    let taker_tx = taker.signed_commit_tx().context("Taker")?;
+
+    if maker_tx.txid() != taker_tx.txid() {
+        bail!("Maker and taker signed different commit transactions");
+    }
+    maker.verify_finalized(&maker_tx).context("Maker")?;
+    taker.verify_finalized(&taker_tx).context("Taker")?;
+
+    Ok(())
+}
+
+fn check_refund_tx(maker: &Dlc, taker: &Dlc) -> Result<()> {
+    let maker_tx = maker.signed_refund_tx().context("Maker")?;
+    let taker_tx = taker.signed_refund_tx().context("Taker")?;
+
+    if maker_tx.txid() != taker_tx.txid() {
+        bail!("Maker and taker signed different refund transactions");
+    }
+    maker.verify_finalized(&maker_tx).context("Maker")?;
+    taker.verify_finalized(&taker_tx).context("Taker")?;
+
+    Ok(())
+}
+
+fn check_cet(maker: &Dlc, taker: &Dlc) -> Result<()> {
+    let attestation = attestation()?;
+
+    let maker_tx = maker
+        .signed_cet(&attestation)
+        .context("Maker")?
+        .context("Maker has no CET for the attestation")?;
+    let taker_tx = taker
+        .signed_cet(&attestation)
+        .context("Taker")?
+        .context("Taker has no CET for the attestation")?;
+
+    if maker_tx.txid() != taker_tx.txid() {
+        bail!("Maker and taker signed different CETs");
+    }
+    maker.verify_finalized(&maker_tx).context("Maker")?;
+    taker.verify_finalized(&taker_tx).context("Taker")?;
+
+    Ok(())
+}
+
+fn attestation() -> Result<oracle::Attestation> {
+    Ok(oracle::Attestation {
+        id: EVENT_ID.parse()?,
+        price: ATTESTED_PRICE,
+        scalars: ATTESTATIONS
+            .iter()
+            .map(|scalar| SecretKey::from_str(scalar))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+fn check_close_tx(maker: &Dlc, taker: &Dlc) -> Result<()> {
+    let proposal = SettlementProposal {
+        order_id: OrderId::default(),
+        timestamp: Timestamp::now(),
+        taker: taker.taker_lock_amount,
+        maker: taker.maker_lock_amount,
+        price: Price::new(dec!(50_000))?,
+    };
+
+    let (taker_tx, taker_sig) = taker.close_transaction(&proposal).context("Taker")?;
+    let (maker_tx, maker_sig) = maker.close_transaction(&proposal).context("Maker")?;
+
+    if maker_tx.txid() != taker_tx.txid() {
+        bail!("Maker and taker signed different close transactions");
+    }
+
+    let close_tx = maker
+        .finalize_spend_transaction((maker_tx, maker_sig), taker_sig)
+        .context("Signatures of the close transaction do not verify")?;
+    maker.verify_finalized(&close_tx)?;
+
+    Ok(())
+}
+
+/// Wallet funded with made-up UTXOs, good enough to build and sign a lock transaction.
+struct Wallet {
+    wallet: bdk::Wallet<(), bdk::database::MemoryDatabase>,
+}
+
+impl Wallet {
+    fn new(rng: &mut StdRng) -> Result<Self> {
+        Ok(Self {
+            wallet: new_test_wallet(rng, Amount::from_btc(0.4)?, 5)?,
+        })
+    }
+}
+
+impl xtra::Actor for Wallet {}
+
+#[xtra_productivity(message_impl = false)]
+impl Wallet {
+    fn handle(&mut self, msg: wallet::BuildPartyParams) -> Result<PartyParams> {
+        let mut builder = self.wallet.build_tx();
+        builder
+            .ordering(TxOrdering::Bip69Lexicographic)
+            .fee_rate(FeeRate::from_sat_per_vb(msg.fee_rate as f32))
+            .add_2of2_multisig_recipient(msg.amount);
+        let (psbt, _) = builder.finish()?;
+
+        Ok(PartyParams {
+            lock_psbt: psbt,
+            identity_pk: msg.identity_pk,
+            lock_amount: msg.amount,
+            address: self.wallet.get_address(AddressIndex::New)?.address,
+        })
+    }
+
+    fn handle(&mut self, msg: wallet::Sign) -> Result<PartiallySignedTransaction> {
+        let mut psbt = msg.psbt;
+        self.wallet.sign(
+            &mut psbt,
+            SignOptions {
+                trust_witness_utxo: true,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(psbt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::cfd::Cfd;
+    use crate::model::cfd::CfdEvent;
+    use crate::model::cfd::Event;
+    use crate::model::Identity;
+    use crate::model::Position;
+    use time::Duration;
+
+    #[tokio::test]
+    async fn self_test_passes() {
+        run(&Seed::default(), Network::Testnet).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cet_and_refund_pass_verification_unless_tampered() {
+        let (maker, taker) = set_up_contract().await.unwrap();
+        check_cet(&maker, &taker).unwrap();
+        check_refund_tx(&maker, &taker).unwrap();
+
+        let mut refund_tx = taker.signed_refund_tx().unwrap();
+        refund_tx.input[0].witness.insert(0, Vec::new());
+        assert!(taker.verify_finalized(&refund_tx).is_err());
+
+        let mut refund_tx = taker.signed_refund_tx().unwrap();
+        refund_tx.output[0].value -= 1;
+        assert!(taker.verify_finalized(&refund_tx).is_err());
+    }
+
+    #[tokio::test]
+    async fn redelivered_attestation_decrypts_the_same_cet() {
+        let (_, taker) = set_up_contract().await.unwrap();
+        let order_id = OrderId::default();
+        let open_cfd = || {
+            Cfd::new(
+                order_id,
+                Position::Long,
+                Price::new(dec!(50_000)).unwrap(),
+                Leverage::new(2).unwrap(),
+                Duration::hours(24),
+                Role::Taker,
+                Usd::new(dec!(1_000)),
+                Identity::new(x25519_dalek::PublicKey::from([1; 32])),
+            )
+            .apply(Event::new(
+                order_id,
+                CfdEvent::ContractSetupCompleted { dlc: taker.clone() },
+            ))
+        };
+        let attestation = attestation().unwrap();
+
+        let first = open_cfd().decrypt_cet(&attestation).unwrap().unwrap();
+        let second = open_cfd()
+            .apply(first.clone())
+            .decrypt_cet(&attestation)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(first.event, second.event);
+    }
+}
@@ -57,6 +57,29 @@ where
     }
 }
 
+#[async_trait]
+impl<M> SendAsyncSafe<M, ()> for Box<dyn xtra::prelude::MessageChannel<M>>
+where
+    M: xtra::Message<Result = ()>,
+{
+    async fn send_async_safe(&self, msg: M) -> Result<(), xtra::Disconnected> {
+        if !self.is_connected() {
+            return Err(xtra::Disconnected);
+        }
+
+        let send_fut = self.send(msg);
+
+        #[allow(clippy::disallowed_method)]
+        tokio::spawn(async {
+            if let Err(e) = send_fut.await {
+                tracing::warn!("Async message invocation failed: {:#}", e)
+            }
+        });
+
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl<M, E> SendAsyncSafe<M, Result<(), E>> for Box<dyn xtra::prelude::MessageChannel<M>>
 where
@@ -1,9 +1,13 @@
+use crate::model::cfd::calculate_long_margin;
+use crate::model::cfd::calculate_short_margin;
 use crate::model::cfd::Cet;
 use crate::model::cfd::Dlc;
+use crate::model::cfd::OrderId;
 use crate::model::cfd::RevokedCommit;
 use crate::model::cfd::Role;
 use crate::model::cfd::CET_TIMELOCK;
 use crate::model::Leverage;
+use crate::model::Position;
 use crate::model::Price;
 use crate::model::Usd;
 use crate::oracle;
@@ -54,6 +58,7 @@ use std::time::Duration;
 use xtra::prelude::MessageChannel;
 
 pub struct SetupParams {
+    order_id: OrderId,
     margin: Amount,
     counterparty_margin: Amount,
     price: Price,
@@ -65,6 +70,7 @@ pub struct SetupParams {
 
 impl SetupParams {
     pub fn new(
+        order_id: OrderId,
         margin: Amount,
         counterparty_margin: Amount,
         price: Price,
@@ -74,6 +80,7 @@ impl SetupParams {
         fee_rate: u32,
     ) -> Self {
         Self {
+            order_id,
             margin,
             counterparty_margin,
             price,
@@ -83,6 +90,22 @@ impl SetupParams {
             fee_rate,
         }
     }
+
+    pub fn fee_rate(&self) -> u32 {
+        self.fee_rate
+    }
+
+    pub fn leverage(&self) -> Leverage {
+        self.leverage
+    }
+
+    pub fn margin(&self) -> Amount {
+        self.margin
+    }
+
+    pub fn counterparty_margin(&self) -> Amount {
+        self.counterparty_margin
+    }
 }
 
 /// Given an initial set of parameters, sets up the CFD contract with
@@ -104,6 +127,7 @@ pub async fn new(
 
     let own_params = build_party_params_channel
         .send(wallet::BuildPartyParams {
+            order_id: setup_params.order_id,
             amount: setup_params.margin,
             identity_pk: pk,
             fee_rate: setup_params.fee_rate,
@@ -306,20 +330,12 @@ pub async fn new(
         })
         .collect::<Result<HashMap<_, _>>>()?;
 
-    // TODO: Remove send- and receiving ACK messages once we are able to handle incomplete DLC
-    // monitoring
-    sink.send(SetupMsg::Msg3(Msg3))
-        .await
-        .context("Failed to send Msg3")?;
-    let _ = stream
-        .select_next_some()
-        .timeout(Duration::from_secs(60))
-        .await
-        .context("Expected Msg3 within 60 seconds")?
-        .try_into_msg3()
-        .context("Failed to read Msg3")?;
+    let lock_fee = lock_fee(&signed_lock_tx);
+    if lock_fee.is_none() {
+        tracing::warn!("Unable to compute the fee of the lock transaction");
+    }
 
-    Ok(Dlc {
+    let dlc = Dlc {
         identity: sk,
         identity_counterparty: params.other.identity_pk,
         revocation: rev_sk,
@@ -330,14 +346,41 @@ pub async fn new(
         taker_address: params.taker().address.clone(),
         lock: (signed_lock_tx.extract_tx(), lock_desc),
         commit: (commit_tx, msg1.commit, commit_desc),
-        cets,
         refund: (refund_tx, msg1.refund),
         maker_lock_amount: params.maker().lock_amount,
         taker_lock_amount: params.taker().lock_amount,
+        lock_fee,
         revoked_commit: Vec::new(),
+        cets,
         settlement_event_id,
         refund_timelock: setup_params.refund_timelock,
-    })
+    };
+
+    // TODO: Remove send- and receiving ACK messages once we are able to handle incomplete DLC
+    // monitoring
+    let dlc_fingerprint = dlc.fingerprint();
+    sink.send(SetupMsg::Msg3(Msg3 { dlc_fingerprint }))
+        .await
+        .context("Failed to send Msg3")?;
+    let msg3 = stream
+        .select_next_some()
+        .timeout(Duration::from_secs(60))
+        .await
+        .context("Expected Msg3 within 60 seconds")?
+        .try_into_msg3()
+        .context("Failed to read Msg3")?;
+
+    if msg3.dlc_fingerprint != dlc_fingerprint {
+        anyhow::bail!(
+            "Counterparty built a different contract: our DLC fingerprint is {} but theirs is {}",
+            dlc_fingerprint,
+            msg3.dlc_fingerprint
+        );
+    }
+
+    tracing::info!(%dlc_fingerprint, "Confirmed counterparty built identical contract");
+
+    Ok(dlc)
 }
 
 pub struct RolloverParams {
@@ -364,6 +407,14 @@ impl RolloverParams {
             fee_rate,
         }
     }
+
+    /// The margin of the party with `position` in the rolled over contract.
+    pub fn margin(&self, position: Position) -> Amount {
+        match position {
+            Position::Long => calculate_long_margin(self.price, self.quantity, self.leverage),
+            Position::Short => calculate_short_margin(self.price, self.quantity),
+        }
+    }
 }
 
 pub async fn roll_over(
@@ -637,16 +688,43 @@ pub async fn roll_over(
         taker_address: dlc.taker_address,
         lock: dlc.lock.clone(),
         commit: (commit_tx, msg1.commit, commit_desc),
-        cets,
         refund: (refund_tx, msg1.refund),
         maker_lock_amount,
         taker_lock_amount,
+        lock_fee: dlc.lock_fee,
         revoked_commit,
+        cets,
         settlement_event_id: announcement.id,
         refund_timelock: rollover_params.refund_timelock,
     })
 }
 
+/// The miner fee of the signed lock transaction, from the UTXOs spent by both wallets.
+///
+/// Returns `None` if the PSBT lacks the previous output of any input.
+fn lock_fee(psbt: &PartiallySignedTransaction) -> Option<Amount> {
+    let tx = &psbt.global.unsigned_tx;
+
+    let spent = tx
+        .input
+        .iter()
+        .zip(psbt.inputs.iter())
+        .map(
+            |(txin, input)| match (&input.witness_utxo, &input.non_witness_utxo) {
+                (Some(utxo), _) => Some(utxo.value),
+                (None, Some(prev_tx)) => prev_tx
+                    .output
+                    .get(txin.previous_output.vout as usize)
+                    .map(|output| output.value),
+                (None, None) => None,
+            },
+        )
+        .sum::<Option<u64>>()?;
+    let paid = tx.output.iter().map(|output| output.value).sum::<u64>();
+
+    spent.checked_sub(paid).map(Amount::from_sat)
+}
+
 /// A convenience struct for storing PartyParams and PunishParams of both
 /// parties and the role of the caller.
 struct AllParams {
@@ -32,6 +32,7 @@ use xtra_productivity::xtra_productivity;
 pub struct Actor {
     cfd: Cfd,
     order: Order,
+    fee_rate: u32,
     n_payouts: usize,
     oracle_pk: schnorrsig::PublicKey,
     announcement: Announcement,
@@ -47,7 +48,7 @@ pub struct Actor {
 
 impl Actor {
     pub fn new(
-        (cfd, order, n_payouts): (Cfd, Order, usize),
+        (cfd, order, fee_rate, n_payouts): (Cfd, Order, u32, usize),
         (oracle_pk, announcement): (schnorrsig::PublicKey, Announcement),
         build_party_params: &(impl MessageChannel<wallet::BuildPartyParams> + 'static),
         sign: &(impl MessageChannel<wallet::Sign> + 'static),
@@ -65,6 +66,7 @@ impl Actor {
         Self {
             cfd,
             order,
+            fee_rate,
             n_payouts,
             oracle_pk,
             announcement,
@@ -87,7 +89,7 @@ impl Actor {
         // the spawned contract setup task
         self.setup_msg_sender = Some(sender);
 
-        let (setup_params, identity) = self.cfd.start_contract_setup()?;
+        let (setup_params, identity) = self.cfd.start_contract_setup(self.fee_rate)?;
 
         let contract_future = setup_contract::new(
             self.taker.sink().with(move |msg| {
@@ -25,6 +25,7 @@ use xtra_productivity::xtra_productivity;
 
 pub struct Actor {
     cfd: Cfd,
+    fee_rate: u32,
     n_payouts: usize,
     oracle_pk: schnorrsig::PublicKey,
     announcement: Announcement,
@@ -38,7 +39,7 @@ pub struct Actor {
 impl Actor {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        (cfd, n_payouts): (Cfd, usize),
+        (cfd, fee_rate, n_payouts): (Cfd, u32, usize),
         (oracle_pk, announcement): (schnorrsig::PublicKey, Announcement),
         build_party_params: &(impl MessageChannel<wallet::BuildPartyParams> + 'static),
         sign: &(impl MessageChannel<wallet::Sign> + 'static),
@@ -47,6 +48,7 @@ impl Actor {
     ) -> Self {
         Self {
             cfd,
+            fee_rate,
             n_payouts,
             oracle_pk,
             announcement,
@@ -65,7 +67,7 @@ impl Actor {
         let order_id = self.cfd.id();
         tracing::info!(%order_id, "Order got accepted");
 
-        let (setup_params, _) = self.cfd.start_contract_setup()?;
+        let (setup_params, _) = self.cfd.start_contract_setup(self.fee_rate)?;
         let (sender, receiver) = mpsc::unbounded::<SetupMsg>();
         // store the writing end to forward messages from the maker to
         // the spawned contract setup task
@@ -137,6 +139,23 @@ impl Actor {
         Ok(())
     }
 
+    fn handle(&mut self, _: Cancel, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        let order_id = self.cfd.id();
+        tracing::info!(%order_id, "Contract setup got cancelled");
+
+        self.on_completed
+            .send(SetupCompleted::Failed {
+                order_id,
+                error: anyhow::anyhow!("Contract setup was cancelled"),
+            })
+            .log_failure("Failed to inform about contract setup cancellation")
+            .await?;
+
+        ctx.stop();
+
+        Ok(())
+    }
+
     fn handle(&mut self, msg: SetupFailed, ctx: &mut xtra::Context<Self>) -> Result<()> {
         self.on_completed
             .send(SetupCompleted::Failed {
@@ -163,6 +182,8 @@ impl xtra::Actor for Actor {
             .send(connection::TakeOrder {
                 order_id: self.cfd.id(),
                 quantity: self.cfd.quantity(),
+                fee_rate: self.fee_rate,
+                leverage: self.cfd.leverage(),
                 address,
             })
             .await;
@@ -195,6 +216,10 @@ pub struct SetupSucceeded {
     dlc: Dlc,
 }
 
+/// Message to abort a contract setup that is stuck, e.g. because the
+/// maker stopped responding.
+pub struct Cancel;
+
 /// Message sent from the spawned task to `setup_taker::Actor` to
 /// notify that the contract setup has failed.
 pub struct SetupFailed {
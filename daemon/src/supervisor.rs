@@ -1,6 +1,11 @@
 use crate::Tasks;
 use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::VecDeque;
 use std::fmt;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
 use xtra::Address;
 use xtra::Context;
 use xtra_productivity::xtra_productivity;
@@ -12,6 +17,22 @@ pub struct Actor<T, R> {
     ctor: Box<dyn Fn(Address<Self>) -> T + Send + 'static>,
     tasks: Tasks,
     restart_policy: Box<dyn FnMut(R) -> bool + Send + 'static>,
+    /// How often the actor may be restarted within how long.
+    max_restarts: Option<(u32, Duration)>,
+    /// When the actor was restarted within the last period of `max_restarts`.
+    recent_restarts: VecDeque<Instant>,
+    restart_stats: RestartStats,
+    restart_stats_sender: watch::Sender<RestartStats>,
+    restart_stats_receiver: watch::Receiver<RestartStats>,
+}
+
+/// How often the supervised actor was restarted, to tell whether it is flapping.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RestartStats {
+    pub restarts: u32,
+    pub last_restart_reason: Option<String>,
+    /// Whether the supervisor stopped restarting the actor because it was restarted too often.
+    pub gave_up: bool,
 }
 
 impl<T, R> Actor<T, R>
@@ -29,17 +50,39 @@ where
         restart_policy: impl (FnMut(R) -> bool) + Send + 'static,
     ) -> (Self, Address<T>) {
         let (address, context) = Context::new(None);
+        let (restart_stats_sender, restart_stats_receiver) =
+            watch::channel(RestartStats::default());
 
         let supervisor = Self {
             context,
             ctor: Box::new(ctor),
             tasks: Tasks::default(),
             restart_policy: Box::new(restart_policy),
+            max_restarts: None,
+            recent_restarts: VecDeque::new(),
+            restart_stats: RestartStats::default(),
+            restart_stats_sender,
+            restart_stats_receiver,
         };
 
         (supervisor, address)
     }
 
+    /// Stop restarting the actor once it was restarted `max_restarts` times within `period`,
+    /// regardless of the restart policy.
+    ///
+    /// Restarts longer than `period` ago do not count, so an actor that fails now and then keeps
+    /// being restarted.
+    pub fn with_max_restarts(mut self, max_restarts: u32, period: Duration) -> Self {
+        self.max_restarts = Some((max_restarts, period));
+        self
+    }
+
+    /// Subscribe to how often the actor was restarted.
+    pub fn restart_stats(&self) -> watch::Receiver<RestartStats> {
+        self.restart_stats_receiver.clone()
+    }
+
     fn spawn_new(&mut self, ctx: &mut Context<Self>) {
         tracing::info!("Spawning new instance of actor"); // TODO: Include name
 
@@ -48,6 +91,11 @@ where
 
         self.tasks.add(self.context.attach(actor));
     }
+
+    fn publish_restart_stats(&self) {
+        // We hold on to a receiver, hence sending cannot fail
+        let _ = self.restart_stats_sender.send(self.restart_stats.clone());
+    }
 }
 
 #[async_trait]
@@ -72,13 +120,44 @@ where
 
         tracing::info!("Actor stopped: {}", reason); // TODO: Include name of actor
 
+        let reason_text = reason.to_string();
         let should_restart = (self.restart_policy)(reason);
 
         tracing::debug!("Restart actor? {}", should_restart);
 
-        if should_restart {
-            self.spawn_new(ctx)
+        if !should_restart {
+            return;
+        }
+
+        if let Some((max_restarts, period)) = self.max_restarts {
+            let now = Instant::now();
+            while let Some(restarted_at) = self.recent_restarts.front() {
+                if now.duration_since(*restarted_at) < period {
+                    break;
+                }
+                self.recent_restarts.pop_front();
+            }
+
+            if self.recent_restarts.len() >= max_restarts as usize {
+                tracing::warn!(
+                    "Not restarting actor after {} restarts within {:?}",
+                    max_restarts,
+                    period
+                );
+
+                self.restart_stats.gave_up = true;
+                self.publish_restart_stats();
+                return;
+            }
+
+            self.recent_restarts.push_back(now);
         }
+
+        self.restart_stats.restarts += 1;
+        self.restart_stats.last_restart_reason = Some(reason_text);
+        self.publish_restart_stats();
+
+        self.spawn_new(ctx)
     }
 }
 
@@ -89,3 +168,111 @@ where
 pub struct Stopped<R> {
     pub reason: R,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use xtra::Actor as _;
+
+    #[tokio::test]
+    async fn reports_restarts_with_last_reason() {
+        let spawned = Arc::new(AtomicU32::new(0));
+        let (supervisor, _dummy) = supervisor(spawned.clone());
+        let restart_stats = supervisor.restart_stats();
+        let (supervisor, fut) = supervisor.create(None).run();
+        tokio::spawn(fut);
+
+        for reason in ["first", "second", "third"] {
+            supervisor
+                .send(Stopped {
+                    reason: reason.to_owned(),
+                })
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            *restart_stats.borrow(),
+            RestartStats {
+                restarts: 3,
+                last_restart_reason: Some("third".to_owned()),
+                gave_up: false,
+            }
+        );
+        assert_eq!(spawned.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn stops_restarting_after_max_restarts() {
+        let spawned = Arc::new(AtomicU32::new(0));
+        let (supervisor, _dummy) = supervisor(spawned.clone());
+        let supervisor = supervisor.with_max_restarts(2, Duration::from_secs(60));
+        let restart_stats = supervisor.restart_stats();
+        let (supervisor, fut) = supervisor.create(None).run();
+        tokio::spawn(fut);
+
+        for _ in 0..5 {
+            supervisor
+                .send(Stopped {
+                    reason: "flapping".to_owned(),
+                })
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            *restart_stats.borrow(),
+            RestartStats {
+                restarts: 2,
+                last_restart_reason: Some("flapping".to_owned()),
+                gave_up: true,
+            }
+        );
+        assert_eq!(spawned.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn restarts_again_once_earlier_restarts_are_outside_period() {
+        tokio::time::pause();
+        let spawned = Arc::new(AtomicU32::new(0));
+        let (supervisor, _dummy) = supervisor(spawned.clone());
+        let supervisor = supervisor.with_max_restarts(2, Duration::from_secs(60));
+        let restart_stats = supervisor.restart_stats();
+        let (supervisor, fut) = supervisor.create(None).run();
+        tokio::spawn(fut);
+        let stopped = || Stopped {
+            reason: "failed now and then".to_owned(),
+        };
+
+        supervisor.send(stopped()).await.unwrap();
+        tokio::time::advance(Duration::from_secs(40)).await;
+        supervisor.send(stopped()).await.unwrap();
+        tokio::time::advance(Duration::from_secs(40)).await;
+        supervisor.send(stopped()).await.unwrap();
+
+        assert!(!restart_stats.borrow().gave_up);
+        assert_eq!(restart_stats.borrow().restarts, 3);
+
+        supervisor.send(stopped()).await.unwrap();
+
+        assert!(restart_stats.borrow().gave_up);
+        assert_eq!(spawned.load(Ordering::SeqCst), 4);
+    }
+
+    fn supervisor(spawned: Arc<AtomicU32>) -> (Actor<Dummy, String>, Address<Dummy>) {
+        Actor::new(
+            move |_| {
+                spawned.fetch_add(1, Ordering::SeqCst);
+                Dummy
+            },
+            |_| true,
+        )
+    }
+
+    struct Dummy;
+
+    impl xtra::Actor for Dummy {}
+}
@@ -1,3 +1,4 @@
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use bdk::bitcoin;
@@ -7,35 +8,76 @@ use bdk::bitcoin::Amount;
 use bdk::FeeRate;
 use clap::Parser;
 use clap::Subcommand;
-use daemon::bitmex_price_feed;
+use daemon::archive;
+use daemon::auth::ApiToken;
+use daemon::auto_commit::CommitPolicy;
+use daemon::auto_refund::RefundStrategy;
+use daemon::clock::SystemClock;
 use daemon::connection::connect;
+use daemon::connection::MakerAddresses;
+use daemon::data_dir;
 use daemon::db;
+use daemon::fan_out;
+use daemon::integrity_check;
+use daemon::integrity_check::Repair;
 use daemon::logger;
+use daemon::logger::LogFile;
+use daemon::logger::LogRotation;
+use daemon::model::cfd::MinimumSize;
 use daemon::model::cfd::Role;
+use daemon::model::cfd::SettlementRejectionPolicy;
+use daemon::model::DisplayName;
 use daemon::model::Identity;
+use daemon::model::Usd;
 use daemon::monitor;
 use daemon::oracle;
+use daemon::oracle::UnknownEventPolicy;
+use daemon::price_feed;
+use daemon::price_feed::PricingMode;
+use daemon::price_feed::SourceConfig;
+use daemon::process_manager;
+use daemon::process_manager::CetPolicy;
 use daemon::projection;
+use daemon::projection::Explorer;
+use daemon::routes::RequestLog;
+use daemon::seed::IdentityPolicy;
 use daemon::seed::Seed;
+use daemon::self_test;
 use daemon::supervisor;
 use daemon::wallet;
+use daemon::wallet::ElectrumTls;
+use daemon::wire::Version;
 use daemon::TakerActorSystem;
 use daemon::Tasks;
 use daemon::HEARTBEAT_INTERVAL;
+use daemon::MAX_CETS;
+use daemon::MAX_CONCURRENT_PROTOCOLS;
 use daemon::N_PAYOUTS;
 use daemon::SETTLEMENT_INTERVAL;
-use sqlx::sqlite::SqliteConnectOptions;
-use sqlx::SqlitePool;
+use reqwest::Url;
+use std::collections::HashSet;
 use std::net::SocketAddr;
+use std::num::NonZeroU32;
 use std::path::PathBuf;
-use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::watch;
 use tracing_subscriber::filter::LevelFilter;
 use xtra::Actor;
 
 mod routes_taker;
 
-pub const ANNOUNCEMENT_LOOKAHEAD: time::Duration = time::Duration::hours(24);
+/// Upper bound for `--announcement-lookahead-hours`, the oracle does not publish announcements
+/// this far into the future.
+const MAX_ANNOUNCEMENT_LOOKAHEAD: time::Duration = time::Duration::days(7);
+
+/// Upper bound for `--oracle-poll-interval-secs`, polling less often delays settling CFDs after
+/// the oracle attested.
+const MAX_ORACLE_POLL_INTERVAL: Duration = Duration::from_secs(600);
+
+/// The period within which `--price-feed-max-restarts` reconnects make us give up on the price
+/// source.
+const PRICE_FEED_MAX_RESTARTS_PERIOD: Duration = Duration::from_secs(60 * 60);
 
 #[derive(Parser)]
 struct Opts {
@@ -47,14 +89,39 @@ struct Opts {
     #[clap(long, parse(try_from_str = parse_x25519_pubkey))]
     maker_id: x25519_dalek::PublicKey,
 
-    /// The IP address to listen on for the HTTP API.
-    #[clap(long, default_value = "127.0.0.1:8000")]
-    http_address: SocketAddr,
+    /// The IP addresses to listen on for the HTTP API.
+    ///
+    /// Can be specified multiple times or as a comma-separated list to serve the API on several
+    /// interfaces.
+    #[clap(
+        long = "http-address",
+        default_value = "127.0.0.1:8000",
+        use_delimiter = true
+    )]
+    http_addresses: Vec<SocketAddr>,
+
+    /// Require this token as `Authorization: Bearer <token>` on API requests that move funds or
+    /// act on CFDs. If not specified, the HTTP API is unauthenticated.
+    #[clap(long)]
+    api_token: Option<ApiToken>,
 
     /// Where to permanently store data, defaults to the current working directory.
     #[clap(long)]
     data_dir: Option<PathBuf>,
 
+    /// Path to the seed file, defaults to `taker_seed` in the network's data directory.
+    #[clap(long)]
+    seed_file: Option<PathBuf>,
+
+    /// Path to the sqlite database, defaults to `taker.sqlite` in the network's data directory.
+    #[clap(long)]
+    db_file: Option<PathBuf>,
+
+    /// Path to the file persisting the index of the last address handed out by the wallet,
+    /// defaults to `taker_wallet_address_index` in the network's data directory.
+    #[clap(long)]
+    wallet_address_index_file: Option<PathBuf>,
+
     /// If enabled logs will be in json format
     #[clap(short, long)]
     json: bool,
@@ -63,6 +130,165 @@ struct Opts {
     #[clap(short, long, default_value = "Debug")]
     log_level: LevelFilter,
 
+    /// Write logs to `taker.log` files in this directory instead of stderr.
+    #[clap(long)]
+    log_dir: Option<PathBuf>,
+
+    /// How often to start a new log file: hourly, daily or never.
+    #[clap(long, default_value = "daily")]
+    log_rotation: LogRotation,
+
+    /// How many log files to keep, deleting the oldest ones. Keeps all log files if not
+    /// specified.
+    #[clap(long)]
+    log_max_files: Option<usize>,
+
+    /// How many hours ahead oracle announcements are fetched.
+    ///
+    /// Should cover the settlement interval of the maker's orders, otherwise they cannot be taken.
+    #[clap(
+        long = "announcement-lookahead-hours",
+        default_value = "24",
+        parse(try_from_str = parse_announcement_lookahead)
+    )]
+    announcement_lookahead: time::Duration,
+
+    /// How many seconds to wait between polling the oracle for announcements and attestations.
+    ///
+    /// Polling more often settles CFDs sooner after the oracle attested.
+    #[clap(
+        long = "oracle-poll-interval-secs",
+        default_value = "5",
+        parse(try_from_str = parse_oracle_poll_interval)
+    )]
+    oracle_poll_interval: Duration,
+
+    /// The minimum quantity in USD of a CFD. Smaller orders are not taken.
+    #[clap(long)]
+    min_notional: Option<Usd>,
+
+    /// The minimum margin to lock up in a CFD. Orders requiring less margin are not taken. Amount
+    /// is to be specified with denomination, e.g. "0.0001 BTC"
+    #[clap(long)]
+    min_margin: Option<Amount>,
+
+    /// The maximum number of CETs of a contract. Contracts that would need more are refused.
+    /// Defaults to 10000.
+    #[clap(long)]
+    max_cets: Option<usize>,
+
+    /// The maximum number of contract setups, collaborative settlements and rollovers each
+    /// running at the same time. Taking an order or settling beyond it is refused, further
+    /// rollovers wait until others completed. Defaults to 10.
+    #[clap(long)]
+    max_concurrent_protocols: Option<usize>,
+
+    /// Seconds after which a request to the electrum backend fails instead of waiting for a slow
+    /// or unresponsive server, between 1 and 255.
+    #[clap(long, default_value = "30", parse(try_from_str = wallet::parse_electrum_timeout))]
+    electrum_timeout_secs: Duration,
+
+    /// PEM file with the certificate of the electrum backend, e.g. the self-signed certificate of
+    /// a private server. The backend has to present exactly this certificate.
+    #[clap(long)]
+    electrum_certificate: Option<PathBuf>,
+
+    /// Accept any TLS certificate of the electrum backend. This allows anyone between you and the
+    /// backend to intercept the connection, prefer `--electrum-certificate`.
+    #[clap(long)]
+    electrum_accept_invalid_certs: bool,
+
+    /// Number of confirmations of the lock transaction after which a CFD is considered open.
+    #[clap(long, default_value = "1")]
+    lock_confirmations: NonZeroU32,
+
+    /// Maximum number of connections to the database the actors share.
+    #[clap(long, default_value = "10")]
+    db_max_connections: NonZeroU32,
+
+    /// Seconds a database connection waits for another one to release its lock before failing.
+    #[clap(long, default_value = "30")]
+    db_busy_timeout_secs: u64,
+
+    /// Minutes to wait for the counterparty to publish the commit transaction after the oracle
+    /// attested before the CET timelock expired, proposing to settle collaboratively at the
+    /// attested price in the meantime. Settling only succeeds if the maker delays its commit
+    /// transaction as well, which it does not by default. If not specified, the commit transaction
+    /// is published immediately.
+    #[clap(long)]
+    commit_delay_mins: Option<u32>,
+
+    /// Keep a CFD open when a collaborative settlement is rejected or fails instead of publishing
+    /// the commit transaction, allowing for another settlement attempt.
+    #[clap(long)]
+    keep_open_on_rejected_settlement: bool,
+
+    /// Do not publish the CET when the oracle attested after the CET timelock expired, leaving
+    /// it to the counterparty instead.
+    #[clap(long)]
+    no_auto_publish_cet: bool,
+
+    /// What to do about CFDs the oracle did not attest to by their expiry: manual, refund or
+    /// settle-then-refund.
+    ///
+    /// Defaults to settle-then-refund, proposing to settle at the initial price before refunding.
+    #[clap(long)]
+    refund_strategy: Option<RefundStrategy>,
+
+    /// Do not check whether the oracle still serves the events of open CFDs. By default CFDs
+    /// whose event is unavailable are flagged because they can only be refunded.
+    #[clap(long)]
+    ignore_unknown_oracle_events: bool,
+
+    /// Move CFDs that cannot be loaded and events of non-existent CFDs into separate tables when
+    /// the database integrity check at startup finds them, instead of only logging them. The
+    /// hourly check while running only ever logs them.
+    #[clap(long)]
+    quarantine_corrupt_events: bool,
+
+    /// Stop rolling over CFDs that have been open for longer than this many hours, letting them
+    /// settle at their expiry instead.
+    #[clap(long)]
+    max_position_age_hours: Option<u32>,
+
+    /// Which price of the quote to mark CFDs, propose settlements and check exit levels at: bid,
+    /// ask or mid.
+    ///
+    /// Defaults to mid.
+    #[clap(long)]
+    pricing_mode: Option<PricingMode>,
+
+    /// Where to get quotes from: bitmex or simulated:<price>, the latter quoting a constant price
+    /// for testing.
+    #[clap(long, default_value = "bitmex")]
+    price_source: SourceConfig,
+
+    /// Stop reconnecting to the price source after this many reconnects within an hour, e.g. if
+    /// it keeps failing. If not specified, we always reconnect.
+    #[clap(long)]
+    price_feed_max_restarts: Option<u32>,
+
+    /// The name to introduce ourselves to the maker with.
+    #[clap(long)]
+    display_name: Option<DisplayName>,
+
+    /// The oldest protocol version of the maker to stay connected to, e.g. 2.3.0. Only for
+    /// versions known to be compatible with ours, a mismatch is logged as warning.
+    ///
+    /// Defaults to our own version.
+    #[clap(long)]
+    min_compatible_version: Option<Version>,
+
+    /// Present a new identity to the maker on every start instead of the one derived from the
+    /// seed, so the maker cannot link our sessions by identity. The identities are derived from
+    /// the seed at an index stored in the data directory.
+    ///
+    /// CFDs taken within one session share its identity and the maker can still link sessions
+    /// through the order ids of CFDs rolled over or settled later and through the wallet funding
+    /// the lock transactions.
+    #[clap(long)]
+    ephemeral_identity: bool,
+
     #[clap(subcommand)]
     network: Network,
 }
@@ -73,6 +299,40 @@ fn parse_x25519_pubkey(s: &str) -> Result<x25519_dalek::PublicKey> {
     Ok(x25519_dalek::PublicKey::from(bytes))
 }
 
+fn parse_announcement_lookahead(s: &str) -> Result<time::Duration> {
+    let hours = s.parse::<u32>()?;
+    let lookahead = time::Duration::hours(hours.into());
+
+    if lookahead.is_zero() {
+        bail!("Announcement look-ahead must be positive");
+    }
+    if lookahead > MAX_ANNOUNCEMENT_LOOKAHEAD {
+        bail!(
+            "Announcement look-ahead must not exceed {} hours",
+            MAX_ANNOUNCEMENT_LOOKAHEAD.whole_hours()
+        );
+    }
+
+    Ok(lookahead)
+}
+
+fn parse_oracle_poll_interval(s: &str) -> Result<Duration> {
+    let seconds = s.parse::<u64>()?;
+    let interval = Duration::from_secs(seconds);
+
+    if interval.is_zero() {
+        bail!("Oracle poll interval must be positive");
+    }
+    if interval > MAX_ORACLE_POLL_INTERVAL {
+        bail!(
+            "Oracle poll interval must not exceed {} seconds",
+            MAX_ORACLE_POLL_INTERVAL.as_secs()
+        );
+    }
+
+    Ok(interval)
+}
+
 #[derive(Parser)]
 enum Network {
     Mainnet {
@@ -80,16 +340,46 @@ enum Network {
         #[clap(long, default_value = "ssl://electrum.blockstream.info:50002")]
         electrum: String,
 
+        /// URL of the oracle attesting to the price of the CFDs.
+        #[clap(long, default_value = "https://h00.ooo")]
+        oracle_url: Url,
+
+        /// Public key of the oracle.
+        #[clap(
+            long,
+            default_value = "ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7"
+        )]
+        oracle_pubkey: schnorrsig::PublicKey,
+
+        /// Base URL of the block explorer used to link to transactions.
+        #[clap(long, default_value = "https://mempool.space/")]
+        explorer: Url,
+
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        wallet_command: Option<WalletCommand>,
     },
     Testnet {
         /// URL to the electrum backend to use for the wallet.
         #[clap(long, default_value = "ssl://electrum.blockstream.info:60002")]
         electrum: String,
 
+        /// URL of the oracle attesting to the price of the CFDs.
+        #[clap(long, default_value = "https://h00.ooo")]
+        oracle_url: Url,
+
+        /// Public key of the oracle.
+        #[clap(
+            long,
+            default_value = "ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7"
+        )]
+        oracle_pubkey: schnorrsig::PublicKey,
+
+        /// Base URL of the block explorer used to link to transactions.
+        #[clap(long, default_value = "https://mempool.space/testnet/")]
+        explorer: Url,
+
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        wallet_command: Option<WalletCommand>,
     },
     /// Run on signet
     Signet {
@@ -97,13 +387,28 @@ enum Network {
         #[clap(long)]
         electrum: String,
 
+        /// URL of the oracle attesting to the price of the CFDs.
+        #[clap(long, default_value = "https://h00.ooo")]
+        oracle_url: Url,
+
+        /// Public key of the oracle.
+        #[clap(
+            long,
+            default_value = "ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7"
+        )]
+        oracle_pubkey: schnorrsig::PublicKey,
+
+        /// Base URL of the block explorer used to link to transactions.
+        #[clap(long, default_value = "https://mempool.space/signet/")]
+        explorer: Url,
+
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        wallet_command: Option<WalletCommand>,
     },
 }
 
 #[derive(Subcommand)]
-enum Withdraw {
+enum WalletCommand {
     Withdraw {
         /// Optionally specify the amount of Bitcoin to be withdrawn. If not specified the wallet
         /// will be drained. Amount is to be specified with denomination, e.g. "0.1 BTC"
@@ -117,6 +422,37 @@ enum Withdraw {
         #[clap(long)]
         address: Address,
     },
+    /// Scan the blockchain for the funds of the wallet again when starting the daemon.
+    ///
+    /// The rescan runs in the background, the wallet is replaced once it completed.
+    Rescan {
+        /// Height of the block before which the wallet cannot have received funds.
+        #[clap(long)]
+        from_height: Option<u32>,
+    },
+    /// Check without network access that keys can be derived from the seed and contract
+    /// transactions can be signed, then exit.
+    SelfTest,
+    /// Export the CFDs and the wallet's address index into a portable archive for moving the
+    /// daemon to another host, then exit. The seed is not part of the archive.
+    Export {
+        /// Where to write the archive to.
+        #[clap(long)]
+        archive: PathBuf,
+    },
+    /// Import an archive created with `export` into a data directory without CFDs, then exit.
+    Import {
+        /// The archive to import.
+        #[clap(long)]
+        archive: PathBuf,
+    },
+    /// Write the seed of a backup from `/api/wallet/backup` to the seed file, then exit. Refuses
+    /// to overwrite an existing seed file.
+    Restore {
+        /// The JSON file the backup was saved to.
+        #[clap(long)]
+        backup: PathBuf,
+    },
 }
 
 impl Network {
@@ -128,6 +464,32 @@ impl Network {
         }
     }
 
+    fn oracle_url(&self) -> &Url {
+        match self {
+            Network::Mainnet { oracle_url, .. } => oracle_url,
+            Network::Testnet { oracle_url, .. } => oracle_url,
+            Network::Signet { oracle_url, .. } => oracle_url,
+        }
+    }
+
+    fn oracle_pubkey(&self) -> schnorrsig::PublicKey {
+        match self {
+            Network::Mainnet { oracle_pubkey, .. } => *oracle_pubkey,
+            Network::Testnet { oracle_pubkey, .. } => *oracle_pubkey,
+            Network::Signet { oracle_pubkey, .. } => *oracle_pubkey,
+        }
+    }
+
+    fn explorer(&self) -> Explorer {
+        let base = match self {
+            Network::Mainnet { explorer, .. } => explorer,
+            Network::Testnet { explorer, .. } => explorer,
+            Network::Signet { explorer, .. } => explorer,
+        };
+
+        Explorer::new(base.clone())
+    }
+
     fn bitcoin_network(&self) -> bitcoin::Network {
         match self {
             Network::Mainnet { .. } => bitcoin::Network::Bitcoin,
@@ -144,11 +506,11 @@ impl Network {
         }
     }
 
-    fn withdraw(&self) -> &Option<Withdraw> {
+    fn wallet_command(&self) -> &Option<WalletCommand> {
         match self {
-            Network::Mainnet { withdraw, .. } => withdraw,
-            Network::Testnet { withdraw, .. } => withdraw,
-            Network::Signet { withdraw, .. } => withdraw,
+            Network::Mainnet { wallet_command, .. } => wallet_command,
+            Network::Testnet { wallet_command, .. } => wallet_command,
+            Network::Signet { wallet_command, .. } => wallet_command,
         }
     }
 }
@@ -157,7 +519,14 @@ impl Network {
 async fn main() -> Result<()> {
     let opts = Opts::parse();
 
-    logger::init(opts.log_level, opts.json).context("initialize logger")?;
+    let log_file = opts.log_dir.clone().map(|dir| LogFile {
+        dir,
+        prefix: "taker.log",
+        rotation: opts.log_rotation,
+        max_files: opts.log_max_files,
+    });
+    let _log_guard =
+        logger::init(opts.log_level, opts.json, log_file.clone()).context("initialize logger")?;
     tracing::info!("Running version: {}", env!("VERGEN_GIT_SEMVER_LIGHTWEIGHT"));
     tracing::info!(
         "CFDs created with this release will settle after {} hours",
@@ -177,49 +546,152 @@ async fn main() -> Result<()> {
 
     let maker_identity = Identity::new(opts.maker_id);
 
-    let seed = Seed::initialize(&data_dir.join("taker_seed")).await?;
+    let seed_file =
+        data_dir::configured_or_default(opts.seed_file.clone(), data_dir.join("taker_seed"))
+            .await?;
+    let db_file =
+        data_dir::configured_or_default(opts.db_file.clone(), data_dir.join("taker.sqlite"))
+            .await?;
+    let address_index_file = data_dir::configured_or_default(
+        opts.wallet_address_index_file.clone(),
+        data_dir.join("taker_wallet_address_index"),
+    )
+    .await?;
+
+    if let Some(WalletCommand::Restore { backup }) = opts.network.wallet_command() {
+        let backup = tokio::fs::read(backup)
+            .await
+            .with_context(|| format!("Failed to read backup {}", backup.display()))?;
+        let seed = Seed::restore(&serde_json::from_slice(&backup)?)?;
+        seed.write_to(&seed_file).await?;
+        println!("Restored seed to {}", seed_file.display());
+
+        return Ok(());
+    }
+
+    let seed = Seed::initialize(&seed_file).await?;
 
     let bitcoin_network = opts.network.bitcoin_network();
     let ext_priv_key = seed.derive_extended_priv_key(bitcoin_network)?;
-    let (_, identity_sk) = seed.derive_identity();
+
+    if let Some(WalletCommand::SelfTest) = opts.network.wallet_command() {
+        self_test::run(&seed, bitcoin_network)
+            .await
+            .context("Self-test failed")?;
+        println!("OK");
+
+        return Ok(());
+    }
+
+    match opts.network.wallet_command() {
+        Some(WalletCommand::Export {
+            archive: archive_file,
+        }) => {
+            archive::export_to_file(archive_file, bitcoin_network, &db_file, &address_index_file)
+                .await
+                .context("Export failed")?;
+
+            return Ok(());
+        }
+        Some(WalletCommand::Import {
+            archive: archive_file,
+        }) => {
+            archive::import_from_file(archive_file, bitcoin_network, &db_file, &address_index_file)
+                .await
+                .context("Import failed")?;
+
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let (identity_pk, identity_sk) = seed
+        .session_identity(
+            IdentityPolicy::from_ephemeral(opts.ephemeral_identity),
+            &data_dir.join("taker_identity_index"),
+        )
+        .await?;
+    tracing::info!(
+        "Connecting to the maker with identity {}",
+        hex::encode(identity_pk.to_bytes())
+    );
 
     let mut tasks = Tasks::default();
 
-    let (wallet, wallet_feed_receiver) = wallet::Actor::new(opts.network.electrum(), ext_priv_key)?;
+    if let Some(log_file) = log_file {
+        tasks.add(log_file.prune_periodically());
+    }
+
+    let electrum_timeout = opts.electrum_timeout_secs;
+    let electrum_tls = ElectrumTls::new(
+        opts.network.electrum(),
+        opts.electrum_certificate.as_deref(),
+        opts.electrum_accept_invalid_certs,
+    )?;
+    let (wallet, wallet_feed_receiver) = wallet::Actor::new(
+        opts.network.electrum(),
+        electrum_timeout,
+        electrum_tls.clone(),
+        ext_priv_key,
+        address_index_file,
+    )?;
+
+    let wallet_sync_feed_receiver = wallet.sync_progress_feed();
 
     let (wallet, wallet_fut) = wallet.create(None).run();
     tasks.add(wallet_fut);
 
-    if let Some(Withdraw::Withdraw {
-        amount,
-        address,
-        fee,
-    }) = opts.network.withdraw()
-    {
-        wallet
-            .send(wallet::Withdraw {
-                amount: *amount,
-                address: address.clone(),
-                fee: fee.map(FeeRate::from_sat_per_vb),
-            })
-            .await??;
-
-        return Ok(());
+    match opts.network.wallet_command() {
+        Some(WalletCommand::Withdraw {
+            amount,
+            address,
+            fee,
+        }) => {
+            wallet
+                .send(wallet::Withdraw {
+                    amount: *amount,
+                    address: address.clone(),
+                    fee: fee.map(FeeRate::from_sat_per_vb),
+                })
+                .await??;
+
+            return Ok(());
+        }
+        Some(WalletCommand::Rescan { from_height }) => {
+            wallet
+                .send(wallet::Rescan {
+                    from_height: *from_height,
+                })
+                .await??;
+        }
+        Some(WalletCommand::SelfTest)
+        | Some(WalletCommand::Export { .. })
+        | Some(WalletCommand::Import { .. })
+        | Some(WalletCommand::Restore { .. }) => {
+            unreachable!("exits before starting the wallet")
+        }
+        None => {}
     }
 
-    // TODO: Actually fetch it from Olivia
-    let oracle = schnorrsig::PublicKey::from_str(
-        "ddd4636845a90185991826be5a494cde9f4a6947b1727217afedc6292fa4caf7",
-    )?;
+    let oracle = opts.network.oracle_pubkey();
+    let oracle_url = opts.network.oracle_url().clone();
 
-    let figment = rocket::Config::figment()
-        .merge(("address", opts.http_address.ip()))
-        .merge(("port", opts.http_address.port()));
+    let distinct_http_addresses = opts.http_addresses.iter().collect::<HashSet<_>>();
+    if distinct_http_addresses.len() != opts.http_addresses.len() {
+        bail!("Each --http-address must only be specified once");
+    }
 
-    let db = SqlitePool::connect_with(
-        SqliteConnectOptions::new()
-            .create_if_missing(true)
-            .filename(data_dir.join("taker.sqlite")),
+    let pricing_mode = opts
+        .pricing_mode
+        .unwrap_or_else(|| PricingMode::default_for(Role::Taker));
+    let max_position_age = opts
+        .max_position_age_hours
+        .map(|hours| time::Duration::hours(hours.into()));
+
+    let db = db::connect(
+        &db_file,
+        opts.db_max_connections.get(),
+        Duration::from_secs(opts.db_busy_timeout_secs),
     )
     .await?;
 
@@ -227,76 +699,199 @@ async fn main() -> Result<()> {
         .await
         .context("Db migrations failed")?;
 
+    integrity_check::check(&db, Repair::from_quarantine(opts.quarantine_corrupt_events))
+        .await
+        .context("Database integrity check failed")?;
+
     // Create actors
 
     let (projection_actor, projection_context) = xtra::Context::new(None);
+    let clock = Arc::new(SystemClock);
 
     let taker = TakerActorSystem::new(
         db.clone(),
         wallet.clone(),
         oracle,
         identity_sk,
-        |channel| oracle::Actor::new(db.clone(), channel, SETTLEMENT_INTERVAL),
+        |channel| oracle::Actor::new(db.clone(), channel, opts.announcement_lookahead, oracle_url),
         {
             |channel| {
                 let electrum = opts.network.electrum().to_string();
-                monitor::Actor::new(db.clone(), electrum, channel)
+                monitor::Actor::new(
+                    db.clone(),
+                    electrum,
+                    electrum_timeout,
+                    &electrum_tls,
+                    opts.lock_confirmations,
+                    channel,
+                )
             }
         },
         N_PAYOUTS,
+        MinimumSize {
+            notional: opts.min_notional,
+            margin: opts.min_margin,
+        },
+        opts.max_cets.unwrap_or(MAX_CETS),
         HEARTBEAT_INTERVAL * 2,
         Duration::from_secs(10),
         projection_actor.clone(),
         maker_identity,
+        opts.display_name.clone(),
+        Version::min_compatible(opts.min_compatible_version)?,
+        CommitPolicy::from_delay(
+            opts.commit_delay_mins
+                .map(|mins| time::Duration::minutes(mins.into())),
+        ),
+        CetPolicy::from_auto_publish(!opts.no_auto_publish_cet),
+        SettlementRejectionPolicy::from_keep_open(opts.keep_open_on_rejected_settlement),
+        pricing_mode,
+        opts.refund_strategy
+            .unwrap_or_else(|| RefundStrategy::default_for(Role::Taker)),
+        UnknownEventPolicy::from_ignore(
+            opts.ignore_unknown_oracle_events,
+            opts.network.oracle_url().clone(),
+        ),
+        max_position_age,
+        opts.max_concurrent_protocols
+            .unwrap_or(MAX_CONCURRENT_PROTOCOLS),
+        process_manager::REBROADCAST_INTERVAL,
+        opts.oracle_poll_interval,
+        clock.clone(),
     )
     .await?;
 
+    // The taker settles CFDs that reach their exit levels at the latest quote
+    let (quote_fan_out, task) = fan_out::Actor::new(&[&projection_actor, &taker.cfd_actor_addr])
+        .create(None)
+        .run();
+    tasks.add(task);
+
+    let price_source = opts.price_source.into_source();
     let (supervisor, _price_feed) = supervisor::Actor::new(
-        move |supervisor| bitmex_price_feed::Actor::new(projection_actor.clone(), supervisor),
+        move |supervisor| {
+            price_feed::Actor::new(
+                price_source.clone(),
+                quote_fan_out.clone(),
+                projection_actor.clone(),
+                supervisor,
+            )
+        },
         |_| true, // always restart price feed actor
     );
+    let supervisor = match opts.price_feed_max_restarts {
+        Some(max_restarts) => {
+            supervisor.with_max_restarts(max_restarts, PRICE_FEED_MAX_RESTARTS_PERIOD)
+        }
+        None => supervisor,
+    };
+    let price_feed_restart_stats = supervisor.restart_stats();
 
     let (_supervisor_address, task) = supervisor.create(None).run();
     tasks.add(task);
 
-    let (proj_actor, projection_feeds) =
-        projection::Actor::new(db.clone(), Role::Taker, bitcoin_network);
+    let (_integrity_check_address, task) =
+        integrity_check::Actor::new(db.clone()).create(None).run();
+    tasks.add(task);
+
+    let (proj_actor, projection_feeds) = projection::Actor::new(
+        db.clone(),
+        Role::Taker,
+        opts.network.explorer(),
+        pricing_mode,
+        max_position_age,
+    );
     tasks.add(projection_context.run(proj_actor));
 
     let possible_addresses = resolve_maker_addresses(&opts.maker).await?;
+    let (maker_addresses_sender, maker_addresses_receiver) =
+        watch::channel(MakerAddresses::new(possible_addresses));
 
     tasks.add(connect(
         taker.maker_online_status_feed_receiver.clone(),
         taker.connection_actor_addr.clone(),
         maker_identity,
-        possible_addresses,
+        maker_addresses_sender,
+        clock,
     ));
 
-    let rocket = rocket::custom(figment)
-        .manage(projection_feeds)
-        .manage(wallet_feed_receiver)
-        .manage(bitcoin_network)
-        .manage(taker.maker_online_status_feed_receiver.clone())
-        .manage(taker)
-        .mount(
-            "/api",
-            rocket::routes![
-                routes_taker::feed,
-                routes_taker::post_order_request,
-                routes_taker::get_health_check,
-                routes_taker::margin_calc,
-                routes_taker::post_cfd_action,
-                routes_taker::post_withdraw_request,
-                routes_taker::post_wallet_reinitialise,
-            ],
-        )
-        .mount(
-            "/",
-            rocket::routes![routes_taker::dist, routes_taker::index],
-        );
+    let taker = Arc::new(taker);
+    let maker_online_status_feed_receiver = taker.maker_online_status_feed_receiver.clone();
+
+    if opts.api_token.is_none() {
+        tracing::info!("No API token configured, the HTTP API is unauthenticated");
+    }
+
+    let mut launches = Vec::new();
+    for http_address in &opts.http_addresses {
+        let figment = rocket::Config::figment()
+            .merge(("address", http_address.ip()))
+            .merge(("port", http_address.port()));
+
+        let mut rocket = rocket::custom(figment)
+            .attach(RequestLog)
+            .manage(projection_feeds.clone())
+            .manage(wallet_feed_receiver.clone())
+            .manage(wallet_sync_feed_receiver.clone())
+            .manage(bitcoin_network)
+            .manage(seed)
+            .manage(opts.network.explorer())
+            .manage(pricing_mode)
+            .manage(maker_online_status_feed_receiver.clone())
+            .manage(maker_addresses_receiver.clone())
+            .manage(price_feed_restart_stats.clone())
+            .manage(taker.clone())
+            .mount(
+                "/api",
+                rocket::routes![
+                    routes_taker::feed,
+                    routes_taker::post_order_request,
+                    routes_taker::get_health_check,
+                    routes_taker::get_oracle_health,
+                    routes_taker::get_maker_status,
+                    routes_taker::get_running_protocols,
+                    routes_taker::post_cancel_protocol,
+                    routes_taker::post_rollover_check,
+                    routes_taker::get_portfolio,
+                    routes_taker::get_confirmation_times,
+                    routes_taker::get_order,
+                    routes_taker::get_price_feed_status,
+                    routes_taker::get_version,
+                    routes_taker::margin_calc,
+                    routes_taker::post_cfd_action,
+                    routes_taker::post_settle_all,
+                    routes_taker::post_exit_levels,
+                    routes_taker::get_commit_tx,
+                    routes_taker::get_dlc_summary,
+                    routes_taker::get_close_options,
+                    routes_taker::get_action_costs,
+                    routes_taker::get_break_even,
+                    routes_taker::get_settlement_receipt,
+                    routes_taker::post_withdraw_request,
+                    routes_taker::post_wallet_reinitialise,
+                    routes_taker::post_wallet_rescan,
+                    routes_taker::get_wallet_backup,
+                    routes_taker::get_wallet_address,
+                ],
+            )
+            .register(
+                "/api",
+                rocket::catchers![routes_taker::unauthorized, routes_taker::forbidden],
+            )
+            .mount(
+                "/",
+                rocket::routes![routes_taker::dist, routes_taker::index],
+            );
+
+        if let Some(api_token) = &opts.api_token {
+            rocket = rocket.manage(api_token.clone());
+        }
+
+        let rocket = rocket.ignite().await?;
+        launches.push(rocket.launch());
+    }
 
-    let rocket = rocket.ignite().await?;
-    rocket.launch().await?;
+    futures::future::try_join_all(launches).await?;
 
     db.close().await;
 
@@ -2,30 +2,50 @@ use crate::address_map::AddressMap;
 use crate::cfd_actors;
 use crate::cfd_actors::insert_cfd_and_update_feed;
 use crate::cfd_actors::load_cfd;
+use crate::cfd_actors::PausedEvents;
 use crate::collab_settlement_taker;
 use crate::connection;
+use crate::db;
+use crate::model::cfd::order_to_take;
+use crate::model::cfd::ActionCosts;
 use crate::model::cfd::Cfd;
+use crate::model::cfd::CloseOption;
 use crate::model::cfd::CollaborativeSettlement;
 use crate::model::cfd::Completed;
+use crate::model::cfd::DlcSummary;
+use crate::model::cfd::ExitLevels;
+use crate::model::cfd::MinimumSize;
 use crate::model::cfd::Order;
 use crate::model::cfd::OrderId;
 use crate::model::cfd::Origin;
 use crate::model::cfd::Role;
+use crate::model::cfd::SettlementRejectionPolicy;
 use crate::model::cfd::SetupCompleted;
+use crate::model::cfd::SignedCommitTx;
+use crate::model::cfd::SlippageBound;
 use crate::model::Identity;
+use crate::model::Leverage;
 use crate::model::Position;
 use crate::model::Price;
 use crate::model::Usd;
 use crate::monitor;
 use crate::oracle;
+use crate::payout_curve;
+use crate::price_feed;
+use crate::price_feed::PricingMode;
 use crate::process_manager;
 use crate::projection;
 use crate::setup_taker;
+use crate::try_continue;
 use crate::wallet;
 use crate::Tasks;
 use anyhow::Context as _;
 use anyhow::Result;
+use async_trait::async_trait;
 use bdk::bitcoin::secp256k1::schnorrsig;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::Instrument;
 use xtra::prelude::*;
 use xtra::Actor as _;
 use xtra_productivity::xtra_productivity;
@@ -35,6 +55,11 @@ pub struct CurrentOrder(pub Option<Order>);
 pub struct TakeOffer {
     pub order_id: OrderId,
     pub quantity: Usd,
+    /// Overrides the fee rate of the order.
+    pub fee_rate: Option<u32>,
+    pub slippage_bound: SlippageBound,
+    /// One of the leverages offered by the order, defaults to the order's leverage.
+    pub leverage: Option<Leverage>,
 }
 
 pub struct ProposeSettlement {
@@ -42,10 +67,100 @@ pub struct ProposeSettlement {
     pub current_price: Price,
 }
 
+/// Propose to settle all CFDs that can be settled collaboratively at the given price.
+pub struct SettleAll {
+    pub current_price: Price,
+}
+
+/// The outcome of proposing to settle one of the CFDs of [`SettleAll`].
+pub struct SettlementAttempt {
+    pub order_id: OrderId,
+    pub result: Result<()>,
+}
+
+/// Settle at the price the maker countered a settlement proposal with.
+pub struct AcceptSettlement {
+    pub order_id: OrderId,
+}
+
+/// Reject the price the maker countered a settlement proposal with, keeping the CFD open.
+pub struct RejectSettlement {
+    pub order_id: OrderId,
+}
+
 pub struct Commit {
     pub order_id: OrderId,
 }
 
+pub struct CommitAndSettle {
+    pub order_id: OrderId,
+}
+
+pub struct Refund {
+    pub order_id: OrderId,
+}
+
+pub struct PauseMonitoring {
+    pub order_id: OrderId,
+}
+
+pub struct ResumeMonitoring {
+    pub order_id: OrderId,
+}
+
+/// Hides a CFD whose contract setup failed from the default list.
+pub struct Archive {
+    pub order_id: OrderId,
+}
+
+/// Sets the prices at which a CFD is settled automatically, replacing the ones set before.
+pub struct SetExitLevels {
+    pub order_id: OrderId,
+    pub levels: ExitLevels,
+}
+
+pub struct GetCommitTx {
+    pub order_id: OrderId,
+}
+
+/// Request a read-only view of the contract of a CFD, see [`DlcSummary`].
+pub struct GetDlcSummary {
+    pub order_id: OrderId,
+}
+
+/// Lists the protocols that are currently running with the maker.
+pub struct GetRunningProtocols;
+
+/// Stops the protocol running for the given CFD, returning which protocol was cancelled.
+pub struct CancelProtocol {
+    pub order_id: OrderId,
+}
+
+/// A protocol running with the maker for a CFD.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RunningProtocol {
+    pub order_id: OrderId,
+    pub protocol: Protocol,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Protocol {
+    ContractSetup,
+    CollaborativeSettlement,
+    Rollover,
+}
+
+pub struct GetCloseOptions {
+    pub order_id: OrderId,
+    pub current_price: Option<Price>,
+}
+
+/// Compute the cost of rolling over and settling a CFD, see [`ActionCosts`].
+pub struct GetActionCosts {
+    pub order_id: OrderId,
+    pub current_price: Option<Price>,
+}
+
 pub struct Actor<O, W> {
     db: sqlx::SqlitePool,
     wallet: Address<W>,
@@ -57,9 +172,21 @@ pub struct Actor<O, W> {
     collab_settlement_actors: AddressMap<OrderId, collab_settlement_taker::Actor>,
     oracle_actor: Address<O>,
     n_payouts: usize,
+    minimum_size: MinimumSize,
+    max_cets: usize,
+    settlement_rejection_policy: SettlementRejectionPolicy,
+    /// Which price of a quote exit levels are compared to
+    pricing_mode: PricingMode,
+    /// How many contract setups and how many collaborative settlements can run at the same time
+    max_concurrent_protocols: usize,
+    paused_events: PausedEvents,
     tasks: Tasks,
     current_order: Option<Order>,
     maker_identity: Identity,
+    /// The identity we present to the maker in this session, recorded for every CFD we take
+    session_identity: Identity,
+    /// Exit levels of the CFDs that were not yet proposed to be settled because of them.
+    exit_levels: HashMap<OrderId, (Position, ExitLevels)>,
 }
 
 impl<O, W> Actor<O, W>
@@ -78,7 +205,13 @@ where
         conn_actor: Address<connection::Actor>,
         oracle_actor: Address<O>,
         n_payouts: usize,
+        minimum_size: MinimumSize,
+        max_cets: usize,
+        settlement_rejection_policy: SettlementRejectionPolicy,
+        pricing_mode: PricingMode,
         maker_identity: Identity,
+        session_identity: Identity,
+        max_concurrent_protocols: usize,
     ) -> Self {
         Self {
             db,
@@ -89,11 +222,19 @@ where
             conn_actor,
             oracle_actor,
             n_payouts,
+            minimum_size,
+            max_cets,
+            settlement_rejection_policy,
+            pricing_mode,
+            max_concurrent_protocols,
             setup_actors: AddressMap::default(),
             collab_settlement_actors: AddressMap::default(),
+            paused_events: PausedEvents::default(),
             tasks: Tasks::default(),
             current_order: None,
             maker_identity,
+            session_identity,
+            exit_levels: HashMap::default(),
         }
     }
 }
@@ -132,16 +273,236 @@ where
         Ok(())
     }
 
+    async fn handle_commit_and_settle(&mut self, msg: CommitAndSettle) -> Result<()> {
+        let CommitAndSettle { order_id } = msg;
+
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::handle_commit_and_settle(order_id, &mut conn, &self.process_manager_actor)
+            .await?;
+        Ok(())
+    }
+
+    async fn handle_refund(&mut self, msg: Refund) -> Result<()> {
+        let Refund { order_id } = msg;
+
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::handle_refund(order_id, &mut conn, &self.process_manager_actor).await?;
+        Ok(())
+    }
+
+    async fn handle_pause_monitoring(&mut self, msg: PauseMonitoring) -> Result<()> {
+        let PauseMonitoring { order_id } = msg;
+
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::handle_pause_monitoring(order_id, &mut conn, &self.process_manager_actor)
+            .await?;
+        Ok(())
+    }
+
+    async fn handle_resume_monitoring(&mut self, msg: ResumeMonitoring) -> Result<()> {
+        let ResumeMonitoring { order_id } = msg;
+
+        cfd_actors::handle_resume_monitoring(
+            order_id,
+            &self.db,
+            &self.process_manager_actor,
+            &mut self.paused_events,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn handle_archive(&mut self, msg: Archive) -> Result<()> {
+        let Archive { order_id } = msg;
+
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::handle_archive(order_id, &mut conn, &self.process_manager_actor).await?;
+        Ok(())
+    }
+
+    async fn handle_set_exit_levels(&mut self, msg: SetExitLevels) -> Result<()> {
+        let SetExitLevels { order_id, levels } = msg;
+
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::handle_set_exit_levels(
+            order_id,
+            levels,
+            &mut conn,
+            &self.process_manager_actor,
+        )
+        .await?;
+
+        // New levels may trigger another settlement
+        let cfd = load_cfd(order_id, &mut conn).await?;
+        self.watch_exit_levels(&cfd);
+
+        Ok(())
+    }
+
+    async fn handle_get_commit_tx(&mut self, msg: GetCommitTx) -> Result<SignedCommitTx> {
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::load_signed_commit_tx(msg.order_id, &mut conn).await
+    }
+
+    async fn handle_get_dlc_summary(&mut self, msg: GetDlcSummary) -> Result<DlcSummary> {
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::load_dlc_summary(msg.order_id, &mut conn).await
+    }
+
+    async fn handle_get_running_protocols(
+        &mut self,
+        _: GetRunningProtocols,
+    ) -> Vec<RunningProtocol> {
+        let setups = self
+            .setup_actors
+            .connected_keys()
+            .map(|order_id| RunningProtocol {
+                order_id: *order_id,
+                protocol: Protocol::ContractSetup,
+            });
+        let settlements = self
+            .collab_settlement_actors
+            .connected_keys()
+            .map(|order_id| RunningProtocol {
+                order_id: *order_id,
+                protocol: Protocol::CollaborativeSettlement,
+            });
+
+        setups.chain(settlements).collect()
+    }
+
+    async fn handle_cancel_protocol(&mut self, msg: CancelProtocol) -> Option<Protocol> {
+        let CancelProtocol { order_id } = msg;
+
+        if let Some(addr) = self.setup_actors.remove_connected(&order_id) {
+            let _ = addr.send(setup_taker::Cancel).await;
+            return Some(Protocol::ContractSetup);
+        }
+
+        if let Some(addr) = self.collab_settlement_actors.remove_connected(&order_id) {
+            let _ = addr.send(collab_settlement_taker::Cancel).await;
+            return Some(Protocol::CollaborativeSettlement);
+        }
+
+        None
+    }
+
+    async fn handle_accept_settlement(&mut self, msg: AcceptSettlement) -> Result<()> {
+        let AcceptSettlement { order_id } = msg;
+
+        self.collab_settlement_actors
+            .send_fallible(&order_id, collab_settlement_taker::AcceptCounter)
+            .await
+            .with_context(|| format!("No settlement in progress for order {}", order_id))??;
+
+        Ok(())
+    }
+
+    async fn handle_reject_settlement(&mut self, msg: RejectSettlement) -> Result<()> {
+        let RejectSettlement { order_id } = msg;
+
+        self.collab_settlement_actors
+            .send_fallible(&order_id, collab_settlement_taker::RejectCounter)
+            .await
+            .with_context(|| format!("No settlement in progress for order {}", order_id))??;
+
+        Ok(())
+    }
+
+    async fn handle_get_close_options(&mut self, msg: GetCloseOptions) -> Result<Vec<CloseOption>> {
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::load_close_options(msg.order_id, msg.current_price, self.n_payouts, &mut conn)
+            .await
+    }
+
+    async fn handle_get_action_costs(&mut self, msg: GetActionCosts) -> Result<ActionCosts> {
+        let mut conn = self.db.acquire().await?;
+        cfd_actors::load_action_costs(msg.order_id, msg.current_price, self.n_payouts, &mut conn)
+            .await
+    }
+
     async fn handle_propose_settlement(
         &mut self,
         msg: ProposeSettlement,
         ctx: &mut xtra::Context<Self>,
+    ) -> Result<()> {
+        self.propose_settlement(msg, ctx).await
+    }
+
+    async fn handle_settle_all(
+        &mut self,
+        msg: SettleAll,
+        ctx: &mut xtra::Context<Self>,
+    ) -> Result<Vec<SettlementAttempt>> {
+        let mut conn = self.db.acquire().await?;
+        let mut attempts = Vec::new();
+
+        for order_id in db::load_all_cfd_ids(&mut conn).await? {
+            let cfd = try_continue!(load_cfd(order_id, &mut conn).await);
+
+            if !cfd.can_settle_collaboratively() {
+                continue;
+            }
+
+            let result = self
+                .propose_settlement(
+                    ProposeSettlement {
+                        order_id,
+                        current_price: msg.current_price,
+                    },
+                    ctx,
+                )
+                .await;
+
+            if let Err(e) = &result {
+                tracing::warn!(%order_id, "Failed to propose settlement: {:#}", e);
+            }
+
+            attempts.push(SettlementAttempt { order_id, result });
+        }
+
+        Ok(attempts)
+    }
+}
+
+#[xtra_productivity(message_impl = false)]
+impl<O, W> Actor<O, W>
+where
+    W: xtra::Handler<wallet::TryBroadcastTransaction>,
+{
+    /// Propose to settle the CFDs whose exit levels the market reached.
+    ///
+    /// Each CFD is only proposed to be settled once, until its exit levels are set again or the
+    /// proposal failed or was rejected.
+    async fn handle_quote(
+        &mut self,
+        msg: projection::Update<price_feed::Quote>,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        let current_price = msg.0.price(self.pricing_mode);
+
+        if let Err(e) = self.settle_at_exit_levels(current_price, ctx).await {
+            tracing::warn!("Failed to check exit levels: {:#}", e);
+        }
+    }
+}
+
+impl<O, W> Actor<O, W>
+where
+    W: xtra::Handler<wallet::TryBroadcastTransaction>,
+{
+    async fn propose_settlement(
+        &mut self,
+        msg: ProposeSettlement,
+        ctx: &mut xtra::Context<Self>,
     ) -> Result<()> {
         let ProposeSettlement {
             order_id,
             current_price,
         } = msg;
 
+        self.collab_settlement_actors
+            .ensure_capacity(self.max_concurrent_protocols)?;
         let disconnected = self
             .collab_settlement_actors
             .get_disconnected(order_id)
@@ -165,12 +526,72 @@ where
         .run();
 
         disconnected.insert(addr);
-        self.tasks.add(fut);
+        self.tasks
+            .add(fut.instrument(cfd_actors::cfd_span(order_id)));
+
+        Ok(())
+    }
+
+    async fn settle_at_exit_levels(
+        &mut self,
+        current_price: Price,
+        ctx: &mut xtra::Context<Self>,
+    ) -> Result<()> {
+        let crossed = self
+            .exit_levels
+            .iter()
+            .filter_map(|(order_id, (position, levels))| {
+                levels
+                    .crossed_by(*position, current_price)
+                    .map(|level| (*order_id, level))
+            })
+            .collect::<Vec<_>>();
+
+        if crossed.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.db.acquire().await?;
+
+        for (order_id, level) in crossed {
+            let cfd = try_continue!(load_cfd(order_id, &mut conn).await);
+            if !cfd.can_settle_at_exit_level() {
+                continue;
+            }
+
+            tracing::info!(%order_id, %current_price, "{} reached, proposing settlement", level);
+
+            try_continue!(
+                self.propose_settlement(
+                    ProposeSettlement {
+                        order_id,
+                        current_price,
+                    },
+                    ctx,
+                )
+                .await
+            );
+            self.exit_levels.remove(&order_id);
+        }
 
         Ok(())
     }
 }
 
+impl<O, W> Actor<O, W> {
+    /// Watch the exit levels of the `cfd`, replacing the ones watched before.
+    fn watch_exit_levels(&mut self, cfd: &Cfd) {
+        let levels = cfd.exit_levels();
+
+        if levels == ExitLevels::default() || cfd.is_final() {
+            self.exit_levels.remove(&cfd.id());
+            return;
+        }
+
+        self.exit_levels.insert(cfd.id(), (cfd.position(), levels));
+    }
+}
+
 #[xtra_productivity(message_impl = false)]
 impl<O, W> Actor<O, W> {
     async fn handle_setup_completed(&mut self, msg: SetupCompleted) -> Result<()> {
@@ -198,21 +619,36 @@ impl<O, W> Actor<O, W> {
         let mut conn = self.db.acquire().await?;
         let cfd = load_cfd(order_id, &mut conn).await?;
 
-        let event = cfd.settle_collaboratively(msg)?;
+        let settled = matches!(msg, Completed::Succeeded { .. });
+        let event = match cfd.settle_collaboratively(msg, self.settlement_rejection_policy)? {
+            Some(event) => event,
+            None => return Ok(()),
+        };
         if let Err(e) = self
             .process_manager_actor
-            .send(process_manager::Event::new(event.clone()))
+            .send(process_manager::Event::new(event))
             .await?
         {
             tracing::error!("Sending event to process manager failed: {:#}", e);
         }
 
+        // The exit levels remain to be watched if the proposal did not go through
+        if !settled {
+            let cfd = load_cfd(order_id, &mut conn).await?;
+            self.watch_exit_levels(&cfd);
+        }
+
         Ok(())
     }
 
     async fn handle_monitor(&mut self, msg: monitor::Event) {
-        if let Err(e) =
-            cfd_actors::handle_monitoring_event(msg, &self.db, &self.process_manager_actor).await
+        if let Err(e) = cfd_actors::handle_monitoring_event(
+            msg,
+            &self.db,
+            &self.process_manager_actor,
+            &mut self.paused_events,
+        )
+        .await
         {
             tracing::error!("Unable to handle monotoring event: {:#}", e)
         }
@@ -231,12 +667,22 @@ impl<O, W> Actor<O, W> {
 impl<O, W> Actor<O, W>
 where
     Self: xtra::Handler<SetupCompleted>,
-    O: xtra::Handler<oracle::GetAnnouncement> + xtra::Handler<oracle::MonitorAttestation>,
+    O: xtra::Handler<oracle::GetAnnouncement>
+        + xtra::Handler<oracle::MonitorAttestation>
+        + xtra::Handler<oracle::GetHealth>,
     W: xtra::Handler<wallet::BuildPartyParams> + xtra::Handler<wallet::Sign>,
 {
     async fn handle_take_offer(&mut self, msg: TakeOffer, ctx: &mut Context<Self>) -> Result<()> {
-        let TakeOffer { order_id, quantity } = msg;
+        let TakeOffer {
+            order_id,
+            quantity,
+            fee_rate,
+            slippage_bound,
+            leverage,
+        } = msg;
 
+        self.setup_actors
+            .ensure_capacity(self.max_concurrent_protocols)?;
         let disconnected = self
             .setup_actors
             .get_disconnected(order_id)
@@ -249,10 +695,27 @@ where
 
         let mut conn = self.db.acquire().await?;
 
-        let current_order = self
-            .current_order
-            .clone()
-            .context("No current order from maker")?;
+        let current_order = order_to_take(self.current_order.as_ref(), order_id)?.clone();
+        let leverage = current_order.leverage_for_take(leverage)?;
+
+        self.minimum_size
+            .ensure_satisfied_by(current_order.price, quantity, leverage)?;
+        payout_curve::ensure_within_cet_limit(
+            current_order.price,
+            quantity,
+            leverage,
+            self.n_payouts,
+            self.max_cets,
+        )?;
+        let fee_rate = current_order.fee_rate_for_setup(fee_rate)?;
+        let position = current_order.taker_position();
+        slippage_bound.ensure_satisfied_by(position, current_order.price)?;
+
+        // Don't lock up funds in a contract setup that cannot succeed without the oracle
+        self.oracle_actor
+            .send(oracle::GetHealth)
+            .await?
+            .context("Oracle unavailable")?;
 
         tracing::info!("Taking current order: {:?}", &current_order);
 
@@ -261,13 +724,15 @@ where
         // recorded
         let cfd = Cfd::from_order(
             current_order.clone(),
-            Position::Long,
+            position,
             quantity,
+            leverage,
             self.maker_identity,
             Role::Taker,
         );
 
         insert_cfd_and_update_feed(&cfd, &mut conn, &self.projection_actor).await?;
+        db::insert_session_identity(cfd.id(), self.session_identity, &mut conn).await?;
 
         // Cleanup own order feed, after inserting the cfd.
         // Due to the 1:1 relationship between order and cfd we can never create another cfd for the
@@ -284,7 +749,7 @@ where
             .address()
             .expect("actor to be able to give address to itself");
         let (addr, fut) = setup_taker::Actor::new(
-            (cfd, self.n_payouts),
+            (cfd, fee_rate, self.n_payouts),
             (self.oracle_pk, announcement),
             &self.wallet,
             &self.wallet,
@@ -296,10 +761,34 @@ where
 
         disconnected.insert(addr);
 
-        self.tasks.add(fut);
+        self.tasks
+            .add(fut.instrument(cfd_actors::cfd_span(order_id)));
 
         Ok(())
     }
 }
 
-impl<O: 'static, W: 'static> xtra::Actor for Actor<O, W> {}
+#[async_trait]
+impl<O: 'static, W: 'static> xtra::Actor for Actor<O, W> {
+    async fn started(&mut self, _ctx: &mut xtra::Context<Self>) {
+        let mut conn = match self.db.acquire().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::error!("Failed to load exit levels: {:#}", e);
+                return;
+            }
+        };
+        let order_ids = match db::load_all_cfd_ids(&mut conn).await {
+            Ok(order_ids) => order_ids,
+            Err(e) => {
+                tracing::error!("Failed to load exit levels: {:#}", e);
+                return;
+            }
+        };
+
+        for order_id in order_ids {
+            let cfd = try_continue!(load_cfd(order_id, &mut conn).await);
+            self.watch_exit_levels(&cfd);
+        }
+    }
+}
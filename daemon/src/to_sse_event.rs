@@ -1,13 +1,21 @@
 use crate::connection;
 use crate::model;
-use crate::model::Identity;
+use crate::model::DisplayName;
 use crate::model::Timestamp;
+use crate::price_feed;
 use crate::projection::Cfd;
 use crate::projection::CfdAction;
 use crate::projection::CfdOrder;
+use crate::projection::ChangedCfds;
+use crate::projection::ConnectedTaker;
+use crate::projection::OrderTaken;
 use crate::projection::Quote;
 use crate::to_sse_event::ConnectionCloseReason::MakerVersionOutdated;
+use crate::to_sse_event::ConnectionCloseReason::NetworkError;
+use crate::to_sse_event::ConnectionCloseReason::RemoteClosed;
 use crate::to_sse_event::ConnectionCloseReason::TakerVersionOutdated;
+use crate::to_sse_event::ConnectionCloseReason::Timeout;
+use crate::wallet;
 use bdk::bitcoin::Amount;
 use rocket::request::FromParam;
 use rocket::response::stream::Event;
@@ -32,7 +40,13 @@ impl ToSseEvent for Vec<Cfd> {
     }
 }
 
-impl ToSseEvent for Vec<Identity> {
+impl ToSseEvent for ChangedCfds {
+    fn to_sse_event(&self) -> Event {
+        Event::json(&self).event("cfds_delta")
+    }
+}
+
+impl ToSseEvent for Vec<ConnectedTaker> {
     fn to_sse_event(&self) -> Event {
         Event::json(&self).event("takers")
     }
@@ -44,6 +58,12 @@ impl ToSseEvent for Option<CfdOrder> {
     }
 }
 
+impl ToSseEvent for Option<OrderTaken> {
+    fn to_sse_event(&self) -> Event {
+        Event::json(&self).event("order_taken")
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct WalletInfo {
     #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
@@ -64,43 +84,79 @@ impl ToSseEvent for Option<model::WalletInfo> {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SyncProgress {
+    from_height: Option<u32>,
+    percent: f32,
+}
+
+impl ToSseEvent for Option<wallet::SyncProgress> {
+    fn to_sse_event(&self) -> Event {
+        let sync_progress = self.map(|sync_progress| SyncProgress {
+            from_height: sync_progress.from_height,
+            percent: sync_progress.percent,
+        });
+
+        Event::json(&sync_progress).event("wallet_sync")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
 pub struct ConnectionStatus {
     online: bool,
     connection_close_reason: Option<ConnectionCloseReason>,
+    maker_name: Option<DisplayName>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
 pub enum ConnectionCloseReason {
     MakerVersionOutdated,
     TakerVersionOutdated,
+    Timeout,
+    NetworkError,
+    RemoteClosed,
 }
 
-impl ToSseEvent for connection::ConnectionStatus {
-    fn to_sse_event(&self) -> Event {
-        let connected = match self {
-            connection::ConnectionStatus::Online => ConnectionStatus {
+impl From<&connection::ConnectionStatus> for ConnectionStatus {
+    fn from(status: &connection::ConnectionStatus) -> Self {
+        match status {
+            connection::ConnectionStatus::Online { maker_name } => ConnectionStatus {
                 online: true,
                 connection_close_reason: None,
+                maker_name: maker_name.clone(),
             },
             connection::ConnectionStatus::Offline { reason } => ConnectionStatus {
                 online: false,
-                connection_close_reason: reason.as_ref().map(|g| match g {
-                    connection::ConnectionCloseReason::VersionMismatch {
-                        maker_version,
-                        taker_version,
-                    } => {
-                        if *maker_version < *taker_version {
-                            MakerVersionOutdated
-                        } else {
-                            TakerVersionOutdated
-                        }
-                    }
-                }),
+                connection_close_reason: reason.as_ref().map(ConnectionCloseReason::from),
+                maker_name: None,
             },
-        };
+        }
+    }
+}
+
+impl From<&connection::ConnectionCloseReason> for ConnectionCloseReason {
+    fn from(reason: &connection::ConnectionCloseReason) -> Self {
+        match reason {
+            connection::ConnectionCloseReason::VersionMismatch {
+                maker_version,
+                taker_version,
+            } => {
+                if *maker_version < *taker_version {
+                    MakerVersionOutdated
+                } else {
+                    TakerVersionOutdated
+                }
+            }
+            connection::ConnectionCloseReason::Timeout => Timeout,
+            connection::ConnectionCloseReason::NetworkError => NetworkError,
+            connection::ConnectionCloseReason::RemoteClosed => RemoteClosed,
+        }
+    }
+}
 
-        Event::json(&connected).event("maker_status")
+impl ToSseEvent for connection::ConnectionStatus {
+    fn to_sse_event(&self) -> Event {
+        Event::json(&ConnectionStatus::from(self)).event("maker_status")
     }
 }
 
@@ -109,3 +165,161 @@ impl ToSseEvent for Option<Quote> {
         Event::json(self).event("quote")
     }
 }
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PriceFeedStatus {
+    online: bool,
+    offline_reason: Option<String>,
+}
+
+impl From<&price_feed::Status> for PriceFeedStatus {
+    fn from(status: &price_feed::Status) -> Self {
+        match status {
+            price_feed::Status::Online => PriceFeedStatus {
+                online: true,
+                offline_reason: None,
+            },
+            price_feed::Status::Offline { reason } => PriceFeedStatus {
+                online: false,
+                offline_reason: reason.clone(),
+            },
+        }
+    }
+}
+
+impl ToSseEvent for price_feed::Status {
+    fn to_sse_event(&self) -> Event {
+        Event::json(&PriceFeedStatus::from(self)).event("price_feed_status")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::Version;
+
+    #[test]
+    fn online_maps_to_online_without_reason() {
+        let status =
+            ConnectionStatus::from(&connection::ConnectionStatus::Online { maker_name: None });
+
+        assert_eq!(
+            status,
+            ConnectionStatus {
+                online: true,
+                connection_close_reason: None,
+                maker_name: None,
+            }
+        );
+    }
+
+    #[test]
+    fn offline_without_reason_maps_to_offline_without_reason() {
+        let status =
+            ConnectionStatus::from(&connection::ConnectionStatus::Offline { reason: None });
+
+        assert_eq!(
+            status,
+            ConnectionStatus {
+                online: false,
+                connection_close_reason: None,
+                maker_name: None,
+            }
+        );
+    }
+
+    #[test]
+    fn older_maker_version_maps_to_maker_version_outdated() {
+        let reason = connection::ConnectionCloseReason::VersionMismatch {
+            taker_version: version("1.0.0"),
+            maker_version: version("0.9.0"),
+        };
+
+        assert_eq!(offline_reason(reason), Some(MakerVersionOutdated));
+    }
+
+    #[test]
+    fn newer_maker_version_maps_to_taker_version_outdated() {
+        let reason = connection::ConnectionCloseReason::VersionMismatch {
+            taker_version: version("1.0.0"),
+            maker_version: version("1.1.0"),
+        };
+
+        assert_eq!(offline_reason(reason), Some(TakerVersionOutdated));
+    }
+
+    #[test]
+    fn timeout_maps_to_timeout() {
+        assert_eq!(
+            offline_reason(connection::ConnectionCloseReason::Timeout),
+            Some(Timeout)
+        );
+    }
+
+    #[test]
+    fn network_error_maps_to_network_error() {
+        assert_eq!(
+            offline_reason(connection::ConnectionCloseReason::NetworkError),
+            Some(NetworkError)
+        );
+    }
+
+    #[test]
+    fn remote_closed_maps_to_remote_closed() {
+        assert_eq!(
+            offline_reason(connection::ConnectionCloseReason::RemoteClosed),
+            Some(RemoteClosed)
+        );
+    }
+
+    #[test]
+    fn close_reason_serializes_as_variant_name() {
+        let json = serde_json::to_string(&ConnectionStatus {
+            online: false,
+            connection_close_reason: Some(RemoteClosed),
+            maker_name: None,
+        })
+        .unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"online":false,"connection_close_reason":"RemoteClosed","maker_name":null}"#
+        );
+    }
+
+    #[test]
+    fn down_price_feed_is_reported_offline() {
+        let status = PriceFeedStatus::from(&price_feed::Status::Offline {
+            reason: Some("Failed to connect to BitMex API".to_owned()),
+        });
+
+        assert_eq!(
+            status,
+            PriceFeedStatus {
+                online: false,
+                offline_reason: Some("Failed to connect to BitMex API".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn price_feed_is_offline_until_connected() {
+        let status = PriceFeedStatus::from(&price_feed::Status::Offline { reason: None });
+
+        assert!(!status.online);
+        assert_eq!(status.offline_reason, None);
+    }
+
+    fn offline_reason(reason: connection::ConnectionCloseReason) -> Option<ConnectionCloseReason> {
+        let status = ConnectionStatus::from(&connection::ConnectionStatus::Offline {
+            reason: Some(reason),
+        });
+        assert!(!status.online);
+
+        status.connection_close_reason
+    }
+
+    fn version(version: &str) -> Version {
+        serde_json::from_str(&format!("\"{}\"", version)).unwrap()
+    }
+}
@@ -1,12 +1,16 @@
+use crate::model::cfd::OrderId;
 use crate::model::Timestamp;
 use crate::model::WalletInfo;
 use crate::tokio_ext::spawn_fallible;
 use crate::Tasks;
+use anyhow::anyhow;
 use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
 use bdk::bitcoin::consensus::encode::serialize_hex;
+use bdk::bitcoin::util::address::Payload;
 use bdk::bitcoin::util::bip32::ExtendedPrivKey;
 use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
 use bdk::bitcoin::Address;
@@ -19,7 +23,9 @@ use bdk::bitcoin::Txid;
 use bdk::blockchain::Blockchain;
 use bdk::blockchain::ElectrumBlockchain;
 use bdk::blockchain::NoopProgress;
+use bdk::blockchain::Progress;
 use bdk::database::BatchDatabase;
+use bdk::database::Database;
 use bdk::electrum_client;
 use bdk::wallet::tx_builder::TxOrdering;
 use bdk::wallet::AddressIndex;
@@ -29,19 +35,88 @@ use bdk::SignOptions;
 use maia::PartyParams;
 use maia::TxBuilderExt;
 use rocket::serde::json::Value;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::watch;
+use tokio_rustls::rustls;
+use tokio_rustls::webpki;
+use tokio_rustls::TlsConnector;
 use xtra_productivity::xtra_productivity;
 
 const DUST_AMOUNT: u64 = 546;
 
+/// How often a failed Electrum request is retried before giving up.
+const ELECTRUM_RETRIES: u8 = 2;
+
+/// How often an operation against the Electrum server is attempted in total if it keeps failing
+/// because of a network problem.
+const NETWORK_ATTEMPTS: u32 = 4;
+
+/// How long to wait before the first retry of a failed network operation, doubled on every
+/// further retry.
+const NETWORK_BACKOFF: Duration = Duration::from_millis(500);
+
 pub struct Actor {
     wallet: bdk::Wallet<ElectrumBlockchain, bdk::database::MemoryDatabase>,
+    /// The UTXOs spent by lock transactions, which are not available for withdrawals and are not
+    /// part of the balance until released.
     used_utxos: HashSet<OutPoint>,
+    /// The UTXOs reserved for the lock transaction of each contract setup, until it is published
+    /// or the setup fails.
+    reserved_utxos: HashMap<OrderId, Vec<OutPoint>>,
     tasks: Tasks,
     sender: watch::Sender<Option<WalletInfo>>,
+    sync_progress: Arc<watch::Sender<Option<SyncProgress>>>,
     electrum_rpc_url: String,
+    electrum_timeout: Duration,
+    electrum_tls: ElectrumTls,
+    ext_priv_key: ExtendedPrivKey,
+    /// Where the index of the last address handed out is persisted.
+    address_index_path: PathBuf,
+    rescan_in_progress: bool,
+}
+
+/// An address to receive funds on, e.g. to fund the wallet before trading.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReceiveAddress {
+    pub address: String,
+    /// BIP21 URI of the address, in upper case for segwit addresses so that it fits the more
+    /// compact alphanumeric mode of QR codes.
+    pub bip21: String,
+}
+
+impl From<Address> for ReceiveAddress {
+    fn from(address: Address) -> Self {
+        let bip21 = format!("bitcoin:{}", address);
+        let bip21 = match address.payload {
+            // Bech32 is case-insensitive as long as the whole address has the same case
+            Payload::WitnessProgram { .. } => bip21.to_uppercase(),
+            Payload::PubkeyHash(_) | Payload::ScriptHash(_) => bip21,
+        };
+
+        Self {
+            address: address.to_string(),
+            bip21,
+        }
+    }
+}
+
+/// Progress of a rescan of the wallet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncProgress {
+    /// The height the rescan started from, if a birthday was given.
+    pub from_height: Option<u32>,
+    /// How far the rescan progressed, from 0 to 100.
+    pub percent: f32,
 }
 
 #[derive(thiserror::Error, Debug, Clone, Copy)]
@@ -51,43 +126,51 @@ pub struct TransactionAlreadyInBlockchain;
 impl Actor {
     pub fn new(
         electrum_rpc_url: &str,
+        electrum_timeout: Duration,
+        electrum_tls: ElectrumTls,
         ext_priv_key: ExtendedPrivKey,
+        address_index_path: PathBuf,
     ) -> Result<(Self, watch::Receiver<Option<WalletInfo>>)> {
-        let client = bdk::electrum_client::Client::new(electrum_rpc_url)
-            .context("Failed to initialize Electrum RPC client")?;
+        let client = electrum_client(electrum_rpc_url, electrum_timeout, &electrum_tls)?;
 
-        let db = bdk::database::MemoryDatabase::new();
-
-        let wallet = bdk::Wallet::new(
-            bdk::template::Bip84(ext_priv_key, KeychainKind::External),
-            Some(bdk::template::Bip84(ext_priv_key, KeychainKind::Internal)),
-            ext_priv_key.network,
-            db,
-            ElectrumBlockchain::from(client),
-        )?;
+        let wallet = new_wallet(ext_priv_key, ElectrumBlockchain::from(client))?;
+        restore_address_index(&wallet, load_address_index(&address_index_path)?)?;
 
         let (sender, receiver) = watch::channel(None);
+        let (sync_progress, _) = watch::channel(None);
         let actor = Self {
             wallet,
             tasks: Tasks::default(),
             sender,
+            sync_progress: Arc::new(sync_progress),
             used_utxos: HashSet::default(),
+            reserved_utxos: HashMap::default(),
             electrum_rpc_url: electrum_rpc_url.to_string(),
+            electrum_timeout,
+            electrum_tls,
+            ext_priv_key,
+            address_index_path,
+            rescan_in_progress: false,
         };
 
         Ok((actor, receiver))
     }
 
+    /// Subscribe to the progress of rescans of the wallet.
+    pub fn sync_progress_feed(&self) -> watch::Receiver<Option<SyncProgress>> {
+        self.sync_progress.subscribe()
+    }
+
     /// Calculates the maximum "giveable" amount of this wallet.
     ///
     /// We define this as the maximum amount we can pay to a single output,
     /// given a fee rate.
     pub fn max_giveable(&self, locking_script_size: usize, fee_rate: FeeRate) -> Result<Amount> {
-        let balance = self.wallet.get_balance()?;
+        let balance = available_balance(&self.wallet, &self.used_utxos)?;
 
         // TODO: Do we have to deal with the min_relay_fee here as well, i.e. if balance below
         // min_relay_fee we should return Amount::ZERO?
-        if balance < DUST_AMOUNT {
+        if balance.as_sat() < DUST_AMOUNT {
             return Ok(Amount::ZERO);
         }
 
@@ -114,16 +197,19 @@ impl Actor {
     }
 
     fn sync_internal(&mut self) -> Result<WalletInfo> {
-        self.wallet
-            .sync(NoopProgress, None)
+        retry_on_network_error(|| self.wallet.sync(NoopProgress, None))
             .context("Failed to sync wallet")?;
 
-        let balance = self.wallet.get_balance()?;
+        self.wallet_info()
+    }
+
+    fn wallet_info(&self) -> Result<WalletInfo> {
+        let balance = available_balance(&self.wallet, &self.used_utxos)?;
 
         let address = self.wallet.get_address(AddressIndex::LastUnused)?.address;
 
         let wallet_info = WalletInfo {
-            balance: Amount::from_sat(balance),
+            balance,
             address,
             last_updated_at: Timestamp::now(),
         };
@@ -139,25 +225,21 @@ impl Actor {
         msg: Reinitialise,
         ctx: &mut xtra::Context<Self>,
     ) -> Result<()> {
-        let client = bdk::electrum_client::Client::new(&self.electrum_rpc_url)
-            .context("Failed to initialize Electrum RPC client")?;
+        let client = electrum_client(
+            &self.electrum_rpc_url,
+            self.electrum_timeout,
+            &self.electrum_tls,
+        )?;
 
         let ext_priv_key =
             ExtendedPrivKey::new_master(self.wallet.network(), msg.seed_words.as_bytes())?;
 
-        let db = bdk::database::MemoryDatabase::new();
-
-        let wallet = bdk::Wallet::new(
-            bdk::template::Bip84(ext_priv_key, KeychainKind::External),
-            Some(bdk::template::Bip84(ext_priv_key, KeychainKind::Internal)),
-            ext_priv_key.network,
-            db,
-            ElectrumBlockchain::from(client),
-        )?;
-
-        self.wallet = wallet;
+        self.wallet = new_wallet(ext_priv_key, ElectrumBlockchain::from(client))?;
+        self.ext_priv_key = ext_priv_key;
+        persist_address_index(&self.wallet, &self.address_index_path)?;
 
-        self.used_utxos.clear();
+        // The UTXOs used by contract setups in progress stay unspendable, they are released once
+        // the setup completes or fails
 
         let this = ctx.address().expect("self to be alive");
 
@@ -169,6 +251,99 @@ impl Actor {
         Ok(())
     }
 
+    /// Forget everything the wallet knows about the blockchain and scan it again.
+    ///
+    /// The new wallet is built and synced in the background, the current one keeps serving
+    /// requests until the rescan succeeded and replaces it. Addresses handed out before the rescan
+    /// are derived again so that funds sent to them are found.
+    ///
+    /// Electrum serves the complete history of each script, so the birthday in `from_height`
+    /// cannot speed the scan up. It rejects rescans from a height the backend has not seen yet and
+    /// transactions confirmed before it are reported, as they mean the birthday is wrong.
+    ///
+    /// The UTXOs used by contract setups in progress stay unspendable.
+    pub fn handle_rescan(&mut self, msg: Rescan, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        ensure!(
+            !self.rescan_in_progress,
+            "A rescan of the wallet is already in progress"
+        );
+
+        let from_height = msg.from_height;
+        tracing::info!(?from_height, "Rescanning wallet");
+
+        let last_index = self
+            .wallet
+            .database()
+            .get_last_index(KeychainKind::External)?;
+
+        let electrum_rpc_url = self.electrum_rpc_url.clone();
+        let electrum_timeout = self.electrum_timeout;
+        let electrum_tls = self.electrum_tls.clone();
+        let ext_priv_key = self.ext_priv_key;
+        let progress = RescanProgress {
+            sender: self.sync_progress.clone(),
+            from_height,
+        };
+
+        let this = ctx.address().expect("self to be alive");
+
+        self.tasks.add(async move {
+            let wallet = tokio::task::spawn_blocking(move || {
+                rescan(
+                    &electrum_rpc_url,
+                    electrum_timeout,
+                    &electrum_tls,
+                    ext_priv_key,
+                    last_index,
+                    from_height,
+                    progress,
+                )
+            })
+            .await
+            .unwrap_or_else(|e| Err(anyhow!("Rescan of the wallet did not complete: {}", e)));
+
+            let _ = this
+                .send(RescanCompleted {
+                    ext_priv_key,
+                    wallet,
+                })
+                .await;
+        });
+        self.rescan_in_progress = true;
+
+        Ok(())
+    }
+
+    pub fn handle_rescan_completed(&mut self, msg: RescanCompleted) -> Result<()> {
+        self.rescan_in_progress = false;
+
+        let wallet = match msg.wallet {
+            Ok(wallet) => wallet,
+            Err(e) => {
+                tracing::error!("Failed to rescan wallet: {:#}", e);
+                return Ok(());
+            }
+        };
+
+        if msg.ext_priv_key != self.ext_priv_key {
+            tracing::info!("Discarding rescan of the wallet, it was reinitialised in the meantime");
+            return Ok(());
+        }
+
+        // Addresses handed out by the current wallet while the rescan was running
+        let last_index = self
+            .wallet
+            .database()
+            .get_last_index(KeychainKind::External)?;
+        restore_address_index(&wallet, last_index)?;
+
+        self.wallet = wallet;
+
+        let _ = self.sender.send(Some(self.wallet_info()?));
+
+        Ok(())
+    }
+
     pub fn handle_sync(&mut self, _msg: Sync) -> Result<()> {
         let wallet_info_update = match self.sync_internal() {
             Ok(wallet_info) => Some(wallet_info),
@@ -203,6 +378,7 @@ impl Actor {
     pub fn build_party_params(
         &mut self,
         BuildPartyParams {
+            order_id,
             amount,
             identity_pk,
             fee_rate,
@@ -214,14 +390,36 @@ impl Actor {
             FeeRate::from_sat_per_vb(fee_rate as f32),
         )?;
 
+        self.reserved_utxos.insert(order_id, lock_tx_inputs(&psbt));
+
         Ok(PartyParams {
             lock_psbt: psbt,
             identity_pk,
             lock_amount: amount,
-            address: self.wallet.get_address(AddressIndex::New)?.address,
+            address: new_address(&self.wallet, &self.address_index_path)?,
         })
     }
 
+    /// Hand out an address that was not handed out before, e.g. to deposit funds on.
+    pub fn handle_new_address(&mut self, _msg: NewAddress) -> Result<ReceiveAddress> {
+        let address = new_address(&self.wallet, &self.address_index_path)?;
+
+        tracing::debug!(%address, "Handing out new receive address");
+
+        Ok(address.into())
+    }
+
+    /// Make the UTXOs reserved for the lock transaction of a failed contract setup spendable
+    /// again.
+    pub fn handle_release_utxos(&mut self, msg: ReleaseUtxos) {
+        let released = match self.reserved_utxos.remove(&msg.order_id) {
+            Some(reserved) => release_utxos(&mut self.used_utxos, &reserved),
+            None => 0,
+        };
+
+        tracing::debug!(order_id = %msg.order_id, "Released {} reserved UTXOs", released);
+    }
+
     pub fn handle_try_broadcast_transaction(
         &mut self,
         msg: TryBroadcastTransaction,
@@ -258,6 +456,14 @@ impl Actor {
                     return Ok(txid);
                 }
             }
+
+            if rpc_error.is_permanent() {
+                return Err(TransactionRejected {
+                    txid,
+                    reason: rpc_error.message,
+                }
+                .into());
+            }
         }
 
         let txid = result.with_context(|| {
@@ -268,12 +474,15 @@ impl Actor {
             )
         })?;
 
+        for order_id in forget_published_reservations(&mut self.reserved_utxos, &tx) {
+            tracing::debug!(%order_id, "Lock transaction published, UTXOs no longer reserved");
+        }
+
         Ok(txid)
     }
 
     pub fn handle_withdraw(&mut self, msg: Withdraw) -> Result<Txid> {
-        self.wallet
-            .sync(NoopProgress, None)
+        retry_on_network_error(|| self.wallet.sync(NoopProgress, None))
             .context("Failed to sync wallet")?;
 
         if msg.address.network != self.wallet.network() {
@@ -296,15 +505,8 @@ impl Actor {
 
         tracing::info!(%amount, %address, "Amount to be sent to address");
 
-        let mut tx_builder = self.wallet.build_tx();
-
-        tx_builder
-            .add_recipient(address.script_pubkey(), amount.as_sat())
-            .fee_rate(fee_rate)
-            // Turn on RBF signaling
-            .enable_rbf();
-
-        let (mut psbt, _) = tx_builder.finish()?;
+        let mut psbt =
+            build_withdraw_tx(&self.wallet, &address, amount, fee_rate, &self.used_utxos)?;
 
         self.wallet.sign(&mut psbt, SignOptions::default())?;
 
@@ -334,6 +536,7 @@ impl xtra::Actor for Actor {
 }
 
 pub struct BuildPartyParams {
+    pub order_id: OrderId,
     pub amount: Amount,
     pub identity_pk: PublicKey,
     pub fee_rate: u32,
@@ -346,6 +549,25 @@ pub struct Reinitialise {
     pub seed_words: String,
 }
 
+pub struct Rescan {
+    /// The birthday of the wallet, i.e. the height of the block before which it cannot have
+    /// received funds.
+    pub from_height: Option<u32>,
+}
+
+/// The outcome of a rescan, the wallet that replaces the current one if it succeeded.
+struct RescanCompleted {
+    /// The key the rescanned wallet was derived from.
+    ext_priv_key: ExtendedPrivKey,
+    wallet: Result<bdk::Wallet<ElectrumBlockchain, bdk::database::MemoryDatabase>>,
+}
+
+pub struct ReleaseUtxos {
+    pub order_id: OrderId,
+}
+
+pub struct NewAddress;
+
 pub struct Sign {
     pub psbt: PartiallySignedTransaction,
 }
@@ -373,16 +595,45 @@ fn parse_rpc_protocol_error(error_value: &Value) -> Result<RpcError> {
     Ok(error)
 }
 
+/// The transaction was rejected for a reason that broadcasting it again cannot fix.
+#[derive(thiserror::Error, Debug)]
+#[error("Transaction {txid} was rejected: {reason}")]
+pub struct TransactionRejected {
+    pub txid: Txid,
+    pub reason: String,
+}
+
 #[derive(serde::Deserialize)]
 struct RpcError {
     code: i64,
     message: String,
 }
 
+impl RpcError {
+    /// Whether the transaction itself is invalid, as opposed to e.g. not being final yet, paying
+    /// too little fees for a full mempool or spending outputs that are not known yet.
+    fn is_permanent(&self) -> bool {
+        if self.code == i64::from(RpcErrorCode::RpcDeserializationError) {
+            return true;
+        }
+
+        self.code == i64::from(RpcErrorCode::RpcVerifyRejected)
+            && (self
+                .message
+                .starts_with("mandatory-script-verify-flag-failed")
+                || self.message.starts_with("bad-txns-")
+                || self.message.starts_with("bad-witness-"))
+    }
+}
+
 /// Bitcoin error codes: <https://github.com/bitcoin/bitcoin/blob/97d3500601c1d28642347d014a6de1e38f53ae4e/src/rpc/protocol.h#L23>
 pub enum RpcErrorCode {
+    /// Error parsing or validating structure in raw format. Error code -22.
+    RpcDeserializationError,
     /// General error during transaction or block submission Error code -25.
     RpcVerifyError,
+    /// Transaction or block was rejected by network rules. Error code -26.
+    RpcVerifyRejected,
     /// Transaction already in chain. Error code -27.
     RpcVerifyAlreadyInChain,
 }
@@ -390,7 +641,9 @@ pub enum RpcErrorCode {
 impl From<RpcErrorCode> for i64 {
     fn from(code: RpcErrorCode) -> Self {
         match code {
+            RpcErrorCode::RpcDeserializationError => -22,
             RpcErrorCode::RpcVerifyError => -25,
+            RpcErrorCode::RpcVerifyRejected => -26,
             RpcErrorCode::RpcVerifyAlreadyInChain => -27,
         }
     }
@@ -441,12 +694,592 @@ where
     }
 }
 
+/// Build a transaction paying `amount` to `address` without spending any of the `used_utxos`.
+fn build_withdraw_tx<B, D>(
+    wallet: &bdk::Wallet<B, D>,
+    address: &Address,
+    amount: Amount,
+    fee_rate: FeeRate,
+    used_utxos: &HashSet<OutPoint>,
+) -> Result<PartiallySignedTransaction>
+where
+    D: BatchDatabase,
+{
+    let mut tx_builder = wallet.build_tx();
+
+    tx_builder
+        .add_recipient(address.script_pubkey(), amount.as_sat())
+        .fee_rate(fee_rate)
+        .unspendable(used_utxos.iter().copied().collect())
+        // Turn on RBF signaling
+        .enable_rbf();
+
+    let (psbt, _) = tx_builder
+        .finish()
+        .context("Failed to build withdraw transaction")?;
+
+    Ok(psbt)
+}
+
+/// The balance of `wallet` without the UTXOs among `used_utxos` that are not spent yet.
+fn available_balance<B, D>(
+    wallet: &bdk::Wallet<B, D>,
+    used_utxos: &HashSet<OutPoint>,
+) -> Result<Amount>
+where
+    D: BatchDatabase,
+{
+    let available = wallet
+        .list_unspent()?
+        .into_iter()
+        .filter(|utxo| !used_utxos.contains(&utxo.outpoint))
+        .map(|utxo| utxo.txout.value)
+        .sum();
+
+    Ok(Amount::from_sat(available))
+}
+
+fn new_wallet<B>(
+    ext_priv_key: ExtendedPrivKey,
+    blockchain: B,
+) -> Result<bdk::Wallet<B, bdk::database::MemoryDatabase>>
+where
+    B: Blockchain,
+{
+    let wallet = bdk::Wallet::new(
+        bdk::template::Bip84(ext_priv_key, KeychainKind::External),
+        Some(bdk::template::Bip84(ext_priv_key, KeychainKind::Internal)),
+        ext_priv_key.network,
+        bdk::database::MemoryDatabase::new(),
+        blockchain,
+    )?;
+
+    Ok(wallet)
+}
+
+/// Build a fresh wallet for `ext_priv_key` and scan the blockchain for its funds.
+///
+/// Blocks until the scan completed, it is meant to run outside of the wallet actor.
+fn rescan(
+    electrum_rpc_url: &str,
+    electrum_timeout: Duration,
+    electrum_tls: &ElectrumTls,
+    ext_priv_key: ExtendedPrivKey,
+    last_index: Option<u32>,
+    from_height: Option<u32>,
+    progress: RescanProgress,
+) -> Result<bdk::Wallet<ElectrumBlockchain, bdk::database::MemoryDatabase>> {
+    let client = electrum_client(electrum_rpc_url, electrum_timeout, electrum_tls)?;
+    let wallet = new_wallet(ext_priv_key, ElectrumBlockchain::from(client))?;
+
+    if let Some(from_height) = from_height {
+        let tip = retry_on_network_error(|| wallet.client().get_height())?;
+        ensure!(
+            from_height <= tip,
+            "Cannot rescan from height {} above the current tip {}",
+            from_height,
+            tip
+        );
+    }
+
+    restore_address_index(&wallet, last_index)?;
+
+    progress.update(0.0, None)?;
+
+    retry_on_network_error(|| wallet.sync(progress.clone(), None))
+        .context("Failed to rescan wallet")?;
+
+    if let Some(from_height) = from_height {
+        let before_birthday = wallet
+            .list_transactions(false)?
+            .into_iter()
+            .filter(|tx| match &tx.confirmation_time {
+                Some(confirmation) => confirmation.height < from_height,
+                None => false,
+            })
+            .map(|tx| tx.txid)
+            .collect::<Vec<_>>();
+
+        if !before_birthday.is_empty() {
+            tracing::warn!(
+                %from_height,
+                txids = ?before_birthday,
+                "Found transactions of the wallet confirmed before its birthday, the birthday is too late"
+            );
+        }
+    }
+
+    progress.update(100.0, None)?;
+
+    Ok(wallet)
+}
+
+/// Derive the addresses that were handed out up to `last_index` again.
+///
+/// A wallet with a fresh database starts handing out addresses from index 0, this makes it
+/// continue after the ones already in use instead.
+fn restore_address_index<B, D>(wallet: &bdk::Wallet<B, D>, last_index: Option<u32>) -> Result<()>
+where
+    D: BatchDatabase,
+{
+    if let Some(last_index) = last_index {
+        wallet.get_address(AddressIndex::Reset(last_index))?;
+    }
+
+    Ok(())
+}
+
+/// Derive a new address of `wallet`, persisting its index at `address_index_path`.
+fn new_address<B, D>(wallet: &bdk::Wallet<B, D>, address_index_path: &Path) -> Result<Address>
+where
+    D: BatchDatabase,
+{
+    let address = wallet.get_address(AddressIndex::New)?.address;
+    persist_address_index(wallet, address_index_path)?;
+
+    Ok(address)
+}
+
+/// Remember the index of the last address handed out by `wallet` across restarts.
+///
+/// The wallet keeps its database in memory and would otherwise start handing out the same
+/// addresses again after a restart.
+fn persist_address_index<B, D>(wallet: &bdk::Wallet<B, D>, address_index_path: &Path) -> Result<()>
+where
+    D: BatchDatabase,
+{
+    let last_index = wallet.database().get_last_index(KeychainKind::External)?;
+
+    match last_index {
+        Some(last_index) => store_address_index(address_index_path, last_index)?,
+        None if address_index_path.exists() => std::fs::remove_file(address_index_path)?,
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Store the index by replacing the file, so an interrupted write cannot leave a truncated index
+/// that would make us hand out addresses again.
+pub(crate) fn store_address_index(address_index_path: &Path, last_index: u32) -> Result<()> {
+    let tmp_path = address_index_path.with_extension("tmp");
+
+    std::fs::write(&tmp_path, last_index.to_string())
+        .and_then(|()| std::fs::rename(&tmp_path, address_index_path))
+        .with_context(|| {
+            format!(
+                "Failed to persist address index to {}",
+                address_index_path.display()
+            )
+        })
+}
+
+pub(crate) fn load_address_index(address_index_path: &Path) -> Result<Option<u32>> {
+    if !address_index_path.exists() {
+        return Ok(None);
+    }
+
+    let last_index = std::fs::read_to_string(address_index_path)?
+        .trim()
+        .parse()
+        .with_context(|| {
+            format!(
+                "Failed to parse address index from {}",
+                address_index_path.display()
+            )
+        })?;
+
+    Ok(Some(last_index))
+}
+
+/// Reports the progress of a rescan through the sync progress feed.
+#[derive(Clone)]
+struct RescanProgress {
+    sender: Arc<watch::Sender<Option<SyncProgress>>>,
+    from_height: Option<u32>,
+}
+
+impl Progress for RescanProgress {
+    fn update(&self, progress: f32, _message: Option<String>) -> Result<(), bdk::Error> {
+        // Nobody listening to the progress is not a reason to abort the rescan
+        let _ = self.sender.send(Some(SyncProgress {
+            from_height: self.from_height,
+            percent: progress,
+        }));
+
+        Ok(())
+    }
+}
+
+fn lock_tx_inputs(psbt: &PartiallySignedTransaction) -> Vec<OutPoint> {
+    psbt.global
+        .unsigned_tx
+        .input
+        .iter()
+        .map(|input| input.previous_output)
+        .collect()
+}
+
+/// Remove the `reserved` UTXOs from the `used_utxos`, returning how many were released.
+fn release_utxos(used_utxos: &mut HashSet<OutPoint>, reserved: &[OutPoint]) -> usize {
+    reserved
+        .iter()
+        .filter(|utxo| used_utxos.remove(utxo))
+        .count()
+}
+
+/// Drop the reservations of the contract setups whose lock transaction is `tx`, returning their
+/// order ids.
+///
+/// The UTXOs stay in `used_utxos` because `tx` spends them.
+fn forget_published_reservations(
+    reserved_utxos: &mut HashMap<OrderId, Vec<OutPoint>>,
+    tx: &Transaction,
+) -> Vec<OrderId> {
+    let published = reserved_utxos
+        .iter()
+        .filter(|(_, reserved)| {
+            tx.input
+                .iter()
+                .any(|input| reserved.contains(&input.previous_output))
+        })
+        .map(|(order_id, _)| *order_id)
+        .collect::<Vec<_>>();
+
+    for order_id in &published {
+        reserved_utxos.remove(order_id);
+    }
+
+    published
+}
+
+/// How the TLS certificate of an `ssl://` Electrum server is validated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElectrumTls {
+    /// Require a certificate issued for the domain of the server by a well-known authority.
+    WebPki,
+    /// Only accept a server presenting exactly the pinned certificate, e.g. the self-signed
+    /// certificate of a private Electrum server.
+    Pinned(PinnedTlsProxy),
+    /// Accept any certificate, allowing anyone on the network path to intercept the connection.
+    AcceptInvalidCerts,
+}
+
+impl ElectrumTls {
+    pub fn new(
+        electrum_rpc_url: &str,
+        certificate: Option<&Path>,
+        accept_invalid_certs: bool,
+    ) -> Result<Self> {
+        match (certificate, accept_invalid_certs) {
+            (Some(_), true) => {
+                bail!("Cannot both pin a certificate and accept invalid certificates")
+            }
+            (Some(path), false) => {
+                let file = File::open(path)
+                    .with_context(|| format!("Failed to open certificate {}", path.display()))?;
+                let certificate = parse_pem_certificate(&mut BufReader::new(file))
+                    .with_context(|| format!("Failed to read certificate {}", path.display()))?;
+
+                Ok(Self::Pinned(PinnedTlsProxy::spawn(
+                    electrum_rpc_url,
+                    certificate,
+                )?))
+            }
+            (None, true) => {
+                tracing::warn!("!!! Accepting any TLS certificate from the Electrum server, the connection is NOT protected against interception !!!");
+
+                Ok(Self::AcceptInvalidCerts)
+            }
+            (None, false) => Ok(Self::WebPki),
+        }
+    }
+}
+
+/// Parse the first certificate of a PEM file into its DER encoding.
+fn parse_pem_certificate(pem: &mut dyn BufRead) -> Result<Vec<u8>> {
+    let certificate = rustls::internal::pemfile::certs(pem)
+        .map_err(|()| anyhow!("Invalid PEM encoding"))?
+        .into_iter()
+        .next()
+        .context("No certificate found")?;
+
+    Ok(certificate.0)
+}
+
+/// Accepts a server only if it presents the pinned certificate, regardless of who issued it.
+struct PinnedCertificate(Vec<u8>);
+
+impl rustls::ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        _: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        _: webpki::DNSNameRef<'_>,
+        _: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        match presented_certs.first() {
+            Some(certificate) if certificate.0 == self.0 => {
+                Ok(rustls::ServerCertVerified::assertion())
+            }
+            _ => Err(rustls::TLSError::General(
+                "Server certificate does not match the pinned certificate".to_owned(),
+            )),
+        }
+    }
+}
+
+/// Terminates TLS to an Electrum server on behalf of the Electrum client, only accepting the
+/// pinned certificate.
+///
+/// The Electrum client cannot be given a custom certificate verifier. Instead it connects in
+/// plaintext to this proxy on the loopback interface, which forwards every connection of the
+/// client, reconnects included, over TLS to the server. Thus no wallet traffic bypasses the
+/// pinned certificate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinnedTlsProxy {
+    server_url: String,
+    local_addr: SocketAddr,
+}
+
+impl PinnedTlsProxy {
+    fn spawn(server_url: &str, certificate: Vec<u8>) -> Result<Self> {
+        let address = server_url
+            .strip_prefix("ssl://")
+            .context("A certificate can only be pinned for an ssl:// Electrum server")?
+            .to_owned();
+
+        // The pinned certificate takes the place of checking the name of the server, but the TLS
+        // session needs a valid DNS name, which an IP address is not.
+        let host = address
+            .rsplit_once(':')
+            .map_or(address.as_str(), |(host, _)| host);
+        let dns_name = webpki::DNSNameRef::try_from_ascii_str(host)
+            .or_else(|_| webpki::DNSNameRef::try_from_ascii_str("localhost"))
+            .expect("localhost to be a valid DNS name")
+            .to_owned();
+
+        let mut config = rustls::ClientConfig::new();
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(PinnedCertificate(certificate)));
+        let connector = TlsConnector::from(Arc::new(config));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        // A runtime of its own keeps the proxy going while the blocking Electrum client occupies
+        // the thread it is called on
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()?;
+        std::thread::spawn(move || {
+            runtime.block_on(async move {
+                let listener = match tokio::net::TcpListener::from_std(listener) {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        tracing::error!("Failed to start TLS proxy for Electrum server: {:#}", e);
+                        return;
+                    }
+                };
+
+                loop {
+                    let client = match listener.accept().await {
+                        Ok((client, _)) => client,
+                        Err(e) => {
+                            tracing::warn!(
+                                "Failed to accept connection of Electrum client: {:#}",
+                                e
+                            );
+                            continue;
+                        }
+                    };
+
+                    let address = address.clone();
+                    let dns_name = dns_name.clone();
+                    let connector = connector.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) =
+                            forward_over_tls(client, &address, dns_name.as_ref(), connector).await
+                        {
+                            tracing::warn!("Connection to Electrum server failed: {:#}", e);
+                        }
+                    });
+                }
+            })
+        });
+
+        Ok(Self {
+            server_url: server_url.to_owned(),
+            local_addr,
+        })
+    }
+
+    /// The URL for the Electrum client to connect to instead of `server_url`.
+    fn client_url(&self, server_url: &str) -> Result<String> {
+        ensure!(
+            server_url == self.server_url,
+            "Certificate was pinned for Electrum server {}, not {}",
+            self.server_url,
+            server_url
+        );
+
+        Ok(format!("tcp://{}", self.local_addr))
+    }
+}
+
+async fn forward_over_tls(
+    mut client: tokio::net::TcpStream,
+    address: &str,
+    dns_name: webpki::DNSNameRef<'_>,
+    connector: TlsConnector,
+) -> Result<()> {
+    let server = tokio::net::TcpStream::connect(address)
+        .await
+        .with_context(|| format!("Failed to connect to Electrum server {}", address))?;
+    let mut server = connector
+        .connect(dns_name, server)
+        .await
+        .context("Electrum server does not present the pinned certificate")?;
+
+    tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+
+    Ok(())
+}
+
+/// Parse the timeout of Electrum requests in seconds.
+///
+/// The Electrum client takes the timeout as `u8`, and a timeout of zero would fail every request.
+pub fn parse_electrum_timeout(s: &str) -> Result<Duration> {
+    let seconds = s
+        .parse::<u8>()
+        .with_context(|| format!("Electrum timeout must be between 1 and {} seconds", u8::MAX))?;
+
+    if seconds == 0 {
+        bail!("Electrum timeout must be positive");
+    }
+
+    Ok(Duration::from_secs(seconds.into()))
+}
+
+/// Connect to an Electrum server.
+///
+/// Requests that don't complete within `timeout` fail instead of blocking forever on a slow or hung
+/// server. They are retried a few times before giving up.
+pub fn electrum_client(
+    electrum_rpc_url: &str,
+    timeout: Duration,
+    tls: &ElectrumTls,
+) -> Result<electrum_client::Client> {
+    let timeout_secs =
+        u8::try_from(timeout.as_secs()).context("Electrum timeout cannot exceed 255 seconds")?;
+
+    let (electrum_rpc_url, validate_domain) = match tls {
+        ElectrumTls::WebPki => (electrum_rpc_url.to_owned(), true),
+        ElectrumTls::Pinned(proxy) => (proxy.client_url(electrum_rpc_url)?, false),
+        ElectrumTls::AcceptInvalidCerts => (electrum_rpc_url.to_owned(), false),
+    };
+
+    let config = electrum_client::ConfigBuilder::new()
+        .timeout(Some(timeout_secs))
+        .context("Invalid Electrum timeout")?
+        .retry(ELECTRUM_RETRIES)
+        .validate_domain(validate_domain)
+        .build();
+
+    retry_on_network_error(|| {
+        electrum_client::Client::from_config(&electrum_rpc_url, config.clone())
+    })
+    .context("Failed to initialize Electrum RPC client")
+}
+
+/// Errors that can be resolved by simply trying again, like a dropped connection.
+///
+/// Errors caused by the configuration, e.g. an invalid Electrum URL, or by the request itself are
+/// not retried.
+trait NetworkError {
+    fn is_transient(&self) -> bool;
+}
+
+impl NetworkError for electrum_client::Error {
+    fn is_transient(&self) -> bool {
+        match self {
+            electrum_client::Error::IOError(_)
+            | electrum_client::Error::SharedIOError(_)
+            | electrum_client::Error::CouldntLockReader
+            | electrum_client::Error::Mpsc => true,
+            electrum_client::Error::AllAttemptsErrored(errors) => {
+                errors.iter().all(NetworkError::is_transient)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl NetworkError for bdk::Error {
+    fn is_transient(&self) -> bool {
+        match self {
+            bdk::Error::Electrum(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+/// Run an operation against the Electrum server, retrying it with exponential backoff while it
+/// fails because of a network problem.
+///
+/// This blocks the calling thread while waiting, just like the operations themselves do.
+fn retry_on_network_error<T, E>(operation: impl FnMut() -> Result<T, E>) -> Result<T, E>
+where
+    E: NetworkError + std::fmt::Display,
+{
+    retry_with_backoff(NETWORK_ATTEMPTS, NETWORK_BACKOFF, operation)
+}
+
+fn retry_with_backoff<T, E>(
+    attempts: u32,
+    initial_backoff: Duration,
+    mut operation: impl FnMut() -> Result<T, E>,
+) -> Result<T, E>
+where
+    E: NetworkError + std::fmt::Display,
+{
+    let mut backoff = initial_backoff;
+    let mut attempt = 1;
+
+    loop {
+        match operation() {
+            Err(e) if e.is_transient() && attempt < attempts => {
+                tracing::debug!(
+                    "Network error on attempt {}/{}, retrying in {:?}: {}",
+                    attempt,
+                    attempts,
+                    backoff,
+                    e
+                );
+
+                std::thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::bdk_ext::new_test_wallet;
+    use bdk::electrum_client::ElectrumApi;
     use rand::thread_rng;
     use std::collections::HashSet;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::net::TcpStream;
+    use std::time::Instant;
 
     #[test]
     fn parse_error_response() {
@@ -458,6 +1291,23 @@ mod tests {
         assert_eq!(rpc_error.message, "Transaction already in block chain");
     }
 
+    #[test]
+    fn only_invalid_transactions_are_rejected_permanently() {
+        let rpc_error = |code: i64, message: &str| RpcError {
+            code,
+            message: message.to_owned(),
+        };
+
+        assert!(rpc_error(-22, "TX decode failed").is_permanent());
+        assert!(rpc_error(-26, "mandatory-script-verify-flag-failed (Signature must be zero for failed CHECK(MULTI)SIG operation)").is_permanent());
+        assert!(rpc_error(-26, "bad-txns-in-belowout").is_permanent());
+
+        assert!(!rpc_error(-26, "non-final").is_permanent());
+        assert!(!rpc_error(-26, "mempool full").is_permanent());
+        assert!(!rpc_error(-26, "min relay fee not met, 100 < 141").is_permanent());
+        assert!(!rpc_error(-25, "bad-txns-inputs-missingorspent").is_permanent());
+    }
+
     #[test]
     fn creating_two_lock_transactions_uses_different_utxos() {
         let mut wallet = new_test_wallet(&mut thread_rng(), Amount::from_sat(1000), 10).unwrap();
@@ -504,4 +1354,364 @@ mod tests {
         assert_eq!(utxos_in_transaction.len(), expected_num_utxos);
         assert_eq!(utxos_in_transaction, used_utxos);
     }
+
+    #[test]
+    fn released_utxos_are_spendable_again() {
+        let mut wallet = new_test_wallet(&mut thread_rng(), Amount::from_sat(1000), 10).unwrap();
+        let mut used_utxos = HashSet::new();
+
+        let failed_setup_lock_tx = wallet
+            .build_lock_tx(
+                Amount::from_sat(9000),
+                &mut used_utxos,
+                FeeRate::default_min_relay_fee(),
+            )
+            .unwrap();
+        let no_funds_left = wallet.build_lock_tx(
+            Amount::from_sat(2500),
+            &mut used_utxos,
+            FeeRate::default_min_relay_fee(),
+        );
+        assert!(no_funds_left.is_err());
+
+        let reserved = lock_tx_inputs(&failed_setup_lock_tx);
+        let released = release_utxos(&mut used_utxos, &reserved);
+
+        assert_eq!(released, 10);
+        assert!(used_utxos.is_empty());
+        wallet
+            .build_lock_tx(
+                Amount::from_sat(9000),
+                &mut used_utxos,
+                FeeRate::default_min_relay_fee(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn published_lock_transaction_drops_its_reservation() {
+        let mut wallet = new_test_wallet(&mut thread_rng(), Amount::from_sat(1000), 10).unwrap();
+        let mut used_utxos = HashSet::new();
+
+        let published = wallet
+            .build_lock_tx(
+                Amount::from_sat(4000),
+                &mut used_utxos,
+                FeeRate::default_min_relay_fee(),
+            )
+            .unwrap();
+        let pending = wallet
+            .build_lock_tx(
+                Amount::from_sat(4000),
+                &mut used_utxos,
+                FeeRate::default_min_relay_fee(),
+            )
+            .unwrap();
+        let (published_id, pending_id) = (OrderId::default(), OrderId::default());
+        let mut reserved_utxos = HashMap::from([
+            (published_id, lock_tx_inputs(&published)),
+            (pending_id, lock_tx_inputs(&pending)),
+        ]);
+        let used_before = used_utxos.clone();
+
+        let forgotten =
+            forget_published_reservations(&mut reserved_utxos, &published.global.unsigned_tx);
+
+        assert_eq!(forgotten, vec![published_id]);
+        assert_eq!(reserved_utxos.keys().collect::<Vec<_>>(), vec![&pending_id]);
+        assert_eq!(used_utxos, used_before);
+    }
+
+    #[test]
+    fn withdrawal_does_not_spend_utxos_reserved_for_lock() {
+        let mut wallet = new_test_wallet(&mut thread_rng(), Amount::from_sat(1000), 10).unwrap();
+        let mut used_utxos = HashSet::new();
+        let address = wallet.get_address(AddressIndex::New).unwrap().address;
+
+        let lock_tx = wallet
+            .build_lock_tx(
+                Amount::from_sat(2500),
+                &mut used_utxos,
+                FeeRate::default_min_relay_fee(),
+            )
+            .unwrap();
+        let withdraw_tx = build_withdraw_tx(
+            &wallet,
+            &address,
+            Amount::from_sat(5000),
+            FeeRate::default_min_relay_fee(),
+            &used_utxos,
+        )
+        .unwrap();
+
+        let reserved = lock_tx_inputs(&lock_tx);
+        assert!(withdraw_tx
+            .global
+            .unsigned_tx
+            .input
+            .iter()
+            .all(|input| !reserved.contains(&input.previous_output)));
+
+        let everything = build_withdraw_tx(
+            &wallet,
+            &address,
+            Amount::from_sat(9000),
+            FeeRate::default_min_relay_fee(),
+            &used_utxos,
+        );
+        assert!(everything.is_err());
+    }
+
+    #[test]
+    fn balance_excludes_utxos_reserved_for_lock() {
+        let mut wallet = new_test_wallet(&mut thread_rng(), Amount::from_sat(1000), 10).unwrap();
+        let mut used_utxos = HashSet::new();
+
+        wallet
+            .build_lock_tx(
+                Amount::from_sat(2500),
+                &mut used_utxos,
+                FeeRate::default_min_relay_fee(),
+            )
+            .unwrap();
+
+        let balance = available_balance(&wallet, &used_utxos).unwrap();
+
+        assert_eq!(balance, Amount::from_sat(7000));
+    }
+
+    #[test]
+    fn rescan_derives_known_addresses_again() {
+        let key = ExtendedPrivKey::new_master(bdk::bitcoin::Network::Regtest, &[0u8; 32]).unwrap();
+        let wallet = offline_wallet(key);
+        let known_addresses = (0..5)
+            .map(|_| wallet.get_address(AddressIndex::New).unwrap().address)
+            .collect::<Vec<_>>();
+        let last_index = wallet
+            .database()
+            .get_last_index(KeychainKind::External)
+            .unwrap();
+
+        let rescanned = offline_wallet(key);
+        restore_address_index(&rescanned, last_index).unwrap();
+
+        assert_eq!(
+            rescanned
+                .get_address(AddressIndex::LastUnused)
+                .unwrap()
+                .address,
+            known_addresses[4]
+        );
+        let next_address = rescanned.get_address(AddressIndex::New).unwrap().address;
+        assert!(!known_addresses.contains(&next_address));
+    }
+
+    #[test]
+    fn new_addresses_are_distinct_across_restarts() {
+        let key = ExtendedPrivKey::new_master(bdk::bitcoin::Network::Regtest, &[0u8; 32]).unwrap();
+        let path = std::env::temp_dir().join(format!("address-index-{}", OrderId::default()));
+
+        let wallet = offline_wallet(key);
+        let first = new_address(&wallet, &path).unwrap();
+        let second = new_address(&wallet, &path).unwrap();
+        assert_ne!(first, second);
+
+        let restarted = offline_wallet(key);
+        restore_address_index(&restarted, load_address_index(&path).unwrap()).unwrap();
+        let third = new_address(&restarted, &path).unwrap();
+        assert_ne!(third, first);
+        assert_ne!(third, second);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn bip21_uri_of_segwit_address_is_upper_case() {
+        let address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"
+            .parse::<Address>()
+            .unwrap();
+
+        let receive_address = ReceiveAddress::from(address);
+
+        assert_eq!(
+            receive_address.address,
+            "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"
+        );
+        assert_eq!(
+            receive_address.bip21,
+            "BITCOIN:BC1QAR0SRRR7XFKVY5L643LYDNW9RE59GTZZWF5MDQ"
+        );
+    }
+
+    #[test]
+    fn electrum_timeout_must_fit_into_u8_and_be_positive() {
+        assert_eq!(
+            parse_electrum_timeout("30").unwrap(),
+            Duration::from_secs(30)
+        );
+        assert!(parse_electrum_timeout("0").is_err());
+        assert!(parse_electrum_timeout("256").is_err());
+    }
+
+    #[test]
+    fn request_to_unresponsive_electrum_server_times_out() {
+        // Accepts connections but never answers
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", server.local_addr().unwrap());
+
+        let client = electrum_client(&url, Duration::from_secs(1), &ElectrumTls::WebPki).unwrap();
+
+        let started = Instant::now();
+        let result = client.block_headers_subscribe();
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn pinned_certificate_is_accepted() {
+        let response = request_through_proxy(ELECTRUM_CERTIFICATE).unwrap();
+
+        assert_eq!(&response, b"ping");
+    }
+
+    #[test]
+    fn certificate_other_than_pinned_is_rejected() {
+        let response = request_through_proxy(OTHER_CERTIFICATE);
+
+        assert!(response.is_err());
+    }
+
+    #[test]
+    fn pinned_certificate_is_not_used_for_other_server() {
+        let certificate = parse_pem_certificate(&mut ELECTRUM_CERTIFICATE.as_bytes()).unwrap();
+        let proxy = PinnedTlsProxy::spawn("ssl://127.0.0.1:50002", certificate).unwrap();
+
+        assert!(proxy.client_url("ssl://127.0.0.1:50002").is_ok());
+        assert!(proxy.client_url("ssl://127.0.0.2:50002").is_err());
+    }
+
+    const ELECTRUM_CERTIFICATE: &str = include_str!("../tests/fixtures/electrum.pem");
+    const ELECTRUM_KEY: &str = include_str!("../tests/fixtures/electrum.key");
+    const OTHER_CERTIFICATE: &str = include_str!("../tests/fixtures/other_electrum.pem");
+
+    /// Send a request to the echo server through a proxy pinning `certificate`.
+    fn request_through_proxy(certificate: &str) -> std::io::Result<[u8; 4]> {
+        let url = serve_tls_echo_once(ELECTRUM_CERTIFICATE, ELECTRUM_KEY);
+        let certificate = parse_pem_certificate(&mut certificate.as_bytes()).unwrap();
+        let proxy = PinnedTlsProxy::spawn(&url, certificate).unwrap();
+
+        let mut stream = TcpStream::connect(proxy.local_addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.write_all(b"ping")?;
+
+        let mut response = [0u8; 4];
+        stream.read_exact(&mut response)?;
+
+        Ok(response)
+    }
+
+    /// Accept a single TLS connection with the given certificate echoing the first four bytes,
+    /// returning its `ssl://` URL.
+    fn serve_tls_echo_once(certificate: &str, key: &str) -> String {
+        let certificates = rustls::internal::pemfile::certs(&mut certificate.as_bytes()).unwrap();
+        let key = rustls::internal::pemfile::pkcs8_private_keys(&mut key.as_bytes())
+            .unwrap()
+            .remove(0);
+
+        let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+        config.set_single_cert(certificates, key).unwrap();
+        let config = Arc::new(config);
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ssl://{}", server.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            let (stream, _) = server.accept().unwrap();
+            let mut stream = rustls::StreamOwned::new(rustls::ServerSession::new(&config), stream);
+
+            let mut request = [0u8; 4];
+            if stream.read_exact(&mut request).is_ok() {
+                let _ = stream.write_all(&request);
+                let _ = stream.flush();
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn transient_network_error_is_retried_until_it_succeeds() {
+        let mut backend = FlakyBackend::failing(2, transient_error);
+
+        let result = retry_with_backoff(3, Duration::ZERO, || backend.sync());
+
+        assert!(result.is_ok());
+        assert_eq!(backend.calls, 3);
+    }
+
+    #[test]
+    fn retrying_gives_up_after_all_attempts() {
+        let mut backend = FlakyBackend::failing(5, transient_error);
+
+        let result = retry_with_backoff(3, Duration::ZERO, || backend.sync());
+
+        assert!(result.is_err());
+        assert_eq!(backend.calls, 3);
+    }
+
+    #[test]
+    fn configuration_error_is_not_retried() {
+        let mut backend = FlakyBackend::failing(1, || {
+            bdk::Error::Electrum(electrum_client::Error::MissingDomain)
+        });
+
+        let result = retry_with_backoff(3, Duration::ZERO, || backend.sync());
+
+        assert!(result.is_err());
+        assert_eq!(backend.calls, 1);
+    }
+
+    /// A backend whose first syncs fail with the given error.
+    struct FlakyBackend {
+        failures: usize,
+        error: fn() -> bdk::Error,
+        calls: usize,
+    }
+
+    impl FlakyBackend {
+        fn failing(failures: usize, error: fn() -> bdk::Error) -> Self {
+            Self {
+                failures,
+                error,
+                calls: 0,
+            }
+        }
+
+        fn sync(&mut self) -> Result<(), bdk::Error> {
+            self.calls += 1;
+
+            if self.calls <= self.failures {
+                return Err((self.error)());
+            }
+
+            Ok(())
+        }
+    }
+
+    fn transient_error() -> bdk::Error {
+        bdk::Error::Electrum(electrum_client::Error::IOError(std::io::Error::from(
+            std::io::ErrorKind::ConnectionReset,
+        )))
+    }
+
+    fn offline_wallet(key: ExtendedPrivKey) -> bdk::Wallet<(), bdk::database::MemoryDatabase> {
+        bdk::Wallet::new_offline(
+            bdk::template::Bip84(key, KeychainKind::External),
+            Some(bdk::template::Bip84(key, KeychainKind::Internal)),
+            key.network,
+            bdk::database::MemoryDatabase::new(),
+        )
+        .unwrap()
+    }
 }
@@ -0,0 +1,394 @@
+//! Watch CFDs of third parties without holding any of their keys.
+//!
+//! A watched CFD is imported from the public part of its DLC, see [`WatchOnlyDlc`]. The monitor
+//! watches its transactions like those of our own CFDs, but none of them can be signed or
+//! published.
+//!
+//! Watched CFDs are persisted and watched again after a restart. What they reached so far is not
+//! persisted but reported again by the monitor and the oracle.
+
+use crate::db;
+use crate::model::cfd::OrderId;
+use crate::model::cfd::WatchOnlyDlc;
+use crate::model::BitMexPriceEventId;
+use crate::monitor;
+use crate::monitor::MonitorParams;
+use crate::oracle;
+use crate::send_async_safe::SendAsyncSafe;
+use anyhow::Result;
+use async_trait::async_trait;
+use bdk::bitcoin::Txid;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use xtra::prelude::MessageChannel;
+use xtra_productivity::xtra_productivity;
+
+/// Start watching the CFD of a third party.
+pub struct Import {
+    pub order_id: OrderId,
+    pub dlc: WatchOnlyDlc,
+}
+
+/// Get all watched CFDs.
+pub struct GetWatched;
+
+/// Fails if the CFD is only watched, none of its transactions can be signed.
+pub struct EnsureCanSign {
+    pub order_id: OrderId,
+}
+
+/// What the monitor and the oracle reported about a watched CFD so far.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WatchedCfd {
+    pub order_id: OrderId,
+    pub lock_txid: Txid,
+    pub commit_txid: Txid,
+    pub event_ids: Vec<BitMexPriceEventId>,
+    /// The monitoring targets in the order they were reached.
+    pub reached: Vec<Target>,
+    pub attested_price: Option<u64>,
+}
+
+/// A monitoring target of a watched CFD, see [`monitor::Event`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Target {
+    LockFinality,
+    CommitFinality,
+    CloseFinality,
+    CetTimelockExpired,
+    CetFinality,
+    RefundTimelockExpired,
+    RefundFinality,
+    RevokedTransactionFound,
+}
+
+impl From<&monitor::Event> for Target {
+    fn from(event: &monitor::Event) -> Self {
+        match event {
+            monitor::Event::LockFinality(_) => Target::LockFinality,
+            monitor::Event::CommitFinality(_) => Target::CommitFinality,
+            monitor::Event::CloseFinality(_) => Target::CloseFinality,
+            monitor::Event::CetTimelockExpired(_) => Target::CetTimelockExpired,
+            monitor::Event::CetFinality(_) => Target::CetFinality,
+            monitor::Event::RefundTimelockExpired(_) => Target::RefundTimelockExpired,
+            monitor::Event::RefundFinality(_) => Target::RefundFinality,
+            monitor::Event::RevokedTransactionFound(_) => Target::RevokedTransactionFound,
+        }
+    }
+}
+
+/// Receives all monitoring events, forwarding those of CFDs it does not watch.
+pub struct Actor {
+    db: SqlitePool,
+    cfds: HashMap<OrderId, WatchedCfd>,
+    start_monitoring: Box<dyn MessageChannel<monitor::StartMonitoring>>,
+    monitor_attestation: Box<dyn MessageChannel<oracle::MonitorAttestation>>,
+    forward_monitoring: Box<dyn MessageChannel<monitor::Event>>,
+}
+
+impl Actor {
+    pub fn new(
+        db: SqlitePool,
+        start_monitoring: &(impl MessageChannel<monitor::StartMonitoring> + 'static),
+        monitor_attestation: &(impl MessageChannel<oracle::MonitorAttestation> + 'static),
+        forward_monitoring: &(impl MessageChannel<monitor::Event> + 'static),
+    ) -> Self {
+        Self {
+            db,
+            cfds: HashMap::new(),
+            start_monitoring: start_monitoring.clone_channel(),
+            monitor_attestation: monitor_attestation.clone_channel(),
+            forward_monitoring: forward_monitoring.clone_channel(),
+        }
+    }
+
+    async fn watch_persisted(&mut self) -> Result<()> {
+        let mut conn = self.db.acquire().await?;
+
+        for (order_id, dlc) in db::load_watched_cfds(&mut conn).await? {
+            self.watch(order_id, dlc).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn watch(&mut self, order_id: OrderId, dlc: WatchOnlyDlc) -> Result<()> {
+        let cfd = WatchedCfd {
+            order_id,
+            lock_txid: dlc.lock.0,
+            commit_txid: dlc.commit.0,
+            event_ids: dlc.event_ids.clone(),
+            reached: Vec::new(),
+            attested_price: None,
+        };
+
+        // Not waiting for the monitor and the oracle, they might be busy sending us events
+        for event_id in cfd.event_ids.iter().copied() {
+            self.monitor_attestation
+                .send_async_safe(oracle::MonitorAttestation { event_id })
+                .await?;
+        }
+        self.start_monitoring
+            .send_async_safe(monitor::StartMonitoring {
+                id: order_id,
+                params: MonitorParams::new(dlc),
+            })
+            .await?;
+
+        tracing::info!(%order_id, "Watching CFD");
+        self.cfds.insert(order_id, cfd);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    async fn started(&mut self, _: &mut xtra::Context<Self>) {
+        if let Err(e) = self.watch_persisted().await {
+            tracing::error!("Failed to watch persisted CFDs: {:#}", e);
+        }
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle_import(&mut self, msg: Import) -> Result<()> {
+        let Import { order_id, dlc } = msg;
+
+        anyhow::ensure!(
+            !self.cfds.contains_key(&order_id),
+            "CFD {} is already watched",
+            order_id
+        );
+
+        // Watching one of our own CFDs would take over its monitoring and swallow its events
+        let mut conn = self.db.acquire().await?;
+        anyhow::ensure!(
+            !db::load_all_cfd_ids(&mut conn).await?.contains(&order_id),
+            "CFD {} is one of our own and monitored already",
+            order_id
+        );
+
+        db::insert_watched_cfd(order_id, &dlc, &mut conn).await?;
+
+        self.watch(order_id, dlc).await
+    }
+
+    fn handle_get_watched(&mut self, _: GetWatched) -> Vec<WatchedCfd> {
+        self.cfds.values().cloned().collect()
+    }
+
+    fn handle_ensure_can_sign(&mut self, msg: EnsureCanSign) -> Result<()> {
+        let EnsureCanSign { order_id } = msg;
+
+        anyhow::ensure!(
+            !self.cfds.contains_key(&order_id),
+            "CFD {} is watch-only, its transactions cannot be signed",
+            order_id
+        );
+
+        Ok(())
+    }
+}
+
+#[xtra_productivity(message_impl = false)]
+impl Actor {
+    async fn handle_monitor(&mut self, msg: monitor::Event) {
+        let cfd = match self.cfds.get_mut(&msg.order_id()) {
+            Some(cfd) => cfd,
+            None => {
+                if let Err(e) = self.forward_monitoring.send(msg).await {
+                    tracing::error!("Failed to forward monitoring event: {:#}", e);
+                }
+                return;
+            }
+        };
+
+        let target = Target::from(&msg);
+        tracing::info!(order_id = %cfd.order_id, ?target, "Watched CFD reached monitoring target");
+        cfd.reached.push(target);
+    }
+
+    fn handle_attestation(&mut self, msg: oracle::Attestation) {
+        for cfd in self
+            .cfds
+            .values_mut()
+            .filter(|cfd| cfd.event_ids.contains(&msg.id))
+        {
+            cfd.attested_price = Some(msg.price);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tests::setup_test_pool;
+    use crate::model::cfd::Cfd;
+    use crate::model::cfd::Dlc;
+    use xtra::Actor as _;
+
+    #[tokio::test]
+    async fn watch_only_cfd_reports_confirmations_but_rejects_signing() {
+        let (recorder, recorder_fut) = Recorder::default().create(None).run();
+        tokio::spawn(recorder_fut);
+        let (watch_only, fut) =
+            Actor::new(setup_test_pool().await, &recorder, &recorder, &recorder)
+                .create(None)
+                .run();
+        tokio::spawn(fut);
+
+        let watched = OrderId::default();
+        let own = OrderId::default();
+        let dlc = Dlc::dummy(0);
+        let event_id = dlc.settlement_event_id;
+        watch_only
+            .send(Import {
+                order_id: watched,
+                dlc: dlc.watch_only(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        for event in [
+            monitor::Event::LockFinality(watched),
+            monitor::Event::LockFinality(own),
+            monitor::Event::CommitFinality(watched),
+        ] {
+            watch_only.send(event).await.unwrap();
+        }
+        watch_only
+            .send(oracle::Attestation {
+                id: event_id,
+                price: 40_000,
+                scalars: Vec::new(),
+            })
+            .await
+            .unwrap();
+
+        let cfds = watch_only.send(GetWatched).await.unwrap();
+        assert_eq!(cfds.len(), 1);
+        assert_eq!(
+            cfds[0].reached,
+            vec![Target::LockFinality, Target::CommitFinality]
+        );
+        assert_eq!(cfds[0].attested_price, Some(40_000));
+        assert_eq!(
+            recorder.send(GetForwarded).await.unwrap(),
+            vec![monitor::Event::LockFinality(own)]
+        );
+
+        let watched_can_sign = watch_only
+            .send(EnsureCanSign { order_id: watched })
+            .await
+            .unwrap();
+        let own_can_sign = watch_only
+            .send(EnsureCanSign { order_id: own })
+            .await
+            .unwrap();
+        assert!(watched_can_sign.is_err());
+        assert!(own_can_sign.is_ok());
+    }
+
+    #[tokio::test]
+    async fn own_cfd_cannot_be_imported() {
+        let (recorder, recorder_fut) = Recorder::default().create(None).run();
+        tokio::spawn(recorder_fut);
+        let db = setup_test_pool().await;
+        let own = Cfd::dummy().insert(&mut db.acquire().await.unwrap()).await;
+        let (watch_only, fut) = Actor::new(db, &recorder, &recorder, &recorder)
+            .create(None)
+            .run();
+        tokio::spawn(fut);
+
+        let result = watch_only
+            .send(Import {
+                order_id: own.id(),
+                dlc: Dlc::dummy(0).watch_only(),
+            })
+            .await
+            .unwrap();
+
+        assert!(result.is_err());
+        assert!(watch_only.send(GetWatched).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn watched_cfd_is_watched_again_after_restart() {
+        let (recorder, recorder_fut) = Recorder::default().create(None).run();
+        tokio::spawn(recorder_fut);
+        let db = setup_test_pool().await;
+        let (watch_only, fut) = Actor::new(db.clone(), &recorder, &recorder, &recorder)
+            .create(None)
+            .run();
+        tokio::spawn(fut);
+        let watched = OrderId::default();
+        watch_only
+            .send(Import {
+                order_id: watched,
+                dlc: Dlc::dummy(0).watch_only(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (restarted, fut) = Actor::new(db, &recorder, &recorder, &recorder)
+            .create(None)
+            .run();
+        tokio::spawn(fut);
+
+        let cfds = restarted.send(GetWatched).await.unwrap();
+        assert_eq!(cfds.len(), 1);
+        assert_eq!(cfds[0].order_id, watched);
+        assert!(restarted
+            .send(EnsureCanSign { order_id: watched })
+            .await
+            .unwrap()
+            .is_err());
+    }
+
+    /// Stands in for the monitor, the oracle and the CFD actor.
+    #[derive(Default)]
+    struct Recorder {
+        forwarded: Vec<monitor::Event>,
+    }
+
+    struct GetForwarded;
+
+    impl xtra::Message for GetForwarded {
+        type Result = Vec<monitor::Event>;
+    }
+
+    impl xtra::Actor for Recorder {}
+
+    #[async_trait]
+    impl xtra::Handler<monitor::Event> for Recorder {
+        async fn handle(&mut self, msg: monitor::Event, _: &mut xtra::Context<Self>) {
+            self.forwarded.push(msg);
+        }
+    }
+
+    #[async_trait]
+    impl xtra::Handler<monitor::StartMonitoring> for Recorder {
+        async fn handle(&mut self, _: monitor::StartMonitoring, _: &mut xtra::Context<Self>) {}
+    }
+
+    #[async_trait]
+    impl xtra::Handler<oracle::MonitorAttestation> for Recorder {
+        async fn handle(&mut self, _: oracle::MonitorAttestation, _: &mut xtra::Context<Self>) {}
+    }
+
+    #[async_trait]
+    impl xtra::Handler<GetForwarded> for Recorder {
+        async fn handle(
+            &mut self,
+            _: GetForwarded,
+            _: &mut xtra::Context<Self>,
+        ) -> Vec<monitor::Event> {
+            self.forwarded.clone()
+        }
+    }
+}
@@ -1,6 +1,8 @@
+use crate::model::cfd::DlcFingerprint;
 use crate::model::cfd::Order;
 use crate::model::cfd::OrderId;
 use crate::model::BitMexPriceEventId;
+use crate::model::Leverage;
 use crate::model::Price;
 use crate::model::Timestamp;
 use crate::model::Usd;
@@ -29,6 +31,7 @@ use std::collections::HashMap;
 use std::fmt;
 use std::marker::PhantomData;
 use std::ops::RangeInclusive;
+use std::str;
 use tokio::net::TcpStream;
 use tokio_util::codec::Decoder;
 use tokio_util::codec::Encoder;
@@ -43,7 +46,40 @@ pub struct Version(semver::Version);
 
 impl Version {
     pub fn current() -> Self {
-        Self(semver::Version::new(1, 0, 0))
+        Self(semver::Version::new(2, 4, 0))
+    }
+
+    /// The oldest version of the counterparty we stay connected to.
+    ///
+    /// Defaults to [`Version::current`], a configured version must not be newer than that.
+    pub fn min_compatible(configured: Option<Version>) -> Result<Self> {
+        let current = Self::current();
+
+        match configured {
+            None => Ok(current),
+            Some(configured) if configured > current => bail!(
+                "Minimum compatible version {} is newer than our version {}",
+                configured,
+                current
+            ),
+            Some(configured) => Ok(configured),
+        }
+    }
+
+    /// Whether a counterparty on this version can talk to us.
+    ///
+    /// Only versions of our major version are accepted. Newer versions are accepted, it is up to
+    /// the newer side to reject us if we are too old.
+    pub fn is_compatible(&self, min_compatible: &Version) -> bool {
+        self.0.major == Self::current().0.major && self >= min_compatible
+    }
+}
+
+impl str::FromStr for Version {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.parse()?))
     }
 }
 
@@ -68,9 +104,11 @@ pub mod taker_to_maker {
             maker: Amount,
             price: Price,
         },
+        /// Accepting a counter-proposal of the maker is signalled by `Initiate`.
         Initiate {
             sig_taker: Signature,
         },
+        RejectCounter,
     }
 }
 
@@ -79,9 +117,19 @@ pub mod taker_to_maker {
 #[allow(clippy::large_enum_variant)]
 pub enum TakerToMaker {
     Hello(Version),
+    /// Sent after the `Hello`s are exchanged, the display name is sanitized by the maker.
+    Introduction {
+        display_name: Option<String>,
+    },
     TakeOrder {
         order_id: OrderId,
         quantity: Usd,
+        /// The fee rate to set up the contract with, the fee rate of the order if not given.
+        #[serde(default)]
+        fee_rate: Option<u32>,
+        /// The leverage to set up the contract with, the leverage of the order if not given.
+        #[serde(default)]
+        leverage: Option<Leverage>,
     },
     ProposeRollOver {
         order_id: OrderId,
@@ -110,6 +158,7 @@ impl fmt::Display for TakerToMaker {
             TakerToMaker::RollOverProtocol { .. } => write!(f, "RollOverProtocol"),
             TakerToMaker::Settlement { .. } => write!(f, "Settlement"),
             TakerToMaker::Hello(_) => write!(f, "Hello"),
+            TakerToMaker::Introduction { .. } => write!(f, "Introduction"),
         }
     }
 }
@@ -119,6 +168,10 @@ impl fmt::Display for TakerToMaker {
 #[allow(clippy::large_enum_variant)]
 pub enum MakerToTaker {
     Hello(Version),
+    /// Sent after the `Hello`s are exchanged, the display name is sanitized by the taker.
+    Introduction {
+        display_name: Option<String>,
+    },
     /// Periodically broadcasted message, indicating maker's presence
     Heartbeat,
     CurrentOrder(Option<Order>),
@@ -152,6 +205,14 @@ pub mod maker_to_taker {
     pub enum Settlement {
         Confirm,
         Reject,
+        /// Propose to settle at a different price than the taker proposed.
+        Counter {
+            #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+            taker: Amount,
+            #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+            maker: Amount,
+            price: Price,
+        },
     }
 }
 
@@ -159,6 +220,7 @@ impl fmt::Display for MakerToTaker {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             MakerToTaker::Hello(_) => write!(f, "Hello"),
+            MakerToTaker::Introduction { .. } => write!(f, "Introduction"),
             MakerToTaker::Heartbeat { .. } => write!(f, "Heartbeat"),
             MakerToTaker::CurrentOrder(_) => write!(f, "CurrentOrder"),
             MakerToTaker::ConfirmOrder(_) => write!(f, "ConfirmOrder"),
@@ -409,7 +471,9 @@ pub struct Msg2 {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Msg3;
+pub struct Msg3 {
+    pub dlc_fingerprint: DlcFingerprint,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", content = "payload")]
@@ -498,3 +562,38 @@ impl From<CfdTransactions> for RollOverMsg1 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u64, minor: u64, patch: u64) -> Version {
+        Version(semver::Version::new(major, minor, patch))
+    }
+
+    #[test]
+    fn current_version_is_compatible_by_default() {
+        let min_compatible = Version::min_compatible(None).unwrap();
+
+        assert!(Version::current().is_compatible(&min_compatible));
+    }
+
+    #[test]
+    fn minor_versions_within_window_are_compatible() {
+        let current = Version::current().0;
+        let min_compatible = version(current.major, current.minor - 1, 0);
+
+        assert!(version(current.major, current.minor - 1, 0).is_compatible(&min_compatible));
+        assert!(version(current.major, current.minor + 1, 0).is_compatible(&min_compatible));
+        assert!(!version(current.major, current.minor - 2, 0).is_compatible(&min_compatible));
+        assert!(!version(current.major + 1, 0, 0).is_compatible(&min_compatible));
+    }
+
+    #[test]
+    fn min_compatible_version_must_not_be_newer_than_ours() {
+        let current = Version::current().0;
+        let newer = version(current.major, current.minor + 1, 0);
+
+        assert!(Version::min_compatible(Some(newer)).is_err());
+    }
+}
@@ -1,4 +1,6 @@
 use crate::harness::dummy_new_order;
+use crate::harness::dummy_price;
+use crate::harness::dummy_quote;
 use crate::harness::flow::is_next_none;
 use crate::harness::flow::next;
 use crate::harness::flow::next_cfd;
@@ -8,21 +10,56 @@ use crate::harness::init_tracing;
 use crate::harness::maia::OliviaData;
 use crate::harness::mocks::oracle::dummy_wrong_attestation;
 use crate::harness::start_both;
+use crate::harness::FakeOracle;
 use crate::harness::Maker;
 use crate::harness::MakerConfig;
 use crate::harness::Taker;
 use crate::harness::TakerConfig;
+use bdk::bitcoin::Amount;
+use bdk::bitcoin::Transaction;
+use daemon::connection::ConnectionCloseReason;
 use daemon::connection::ConnectionStatus;
+use daemon::maker_cfd;
+use daemon::model::cfd::CfdEvent;
+use daemon::model::cfd::CloseKind;
+use daemon::model::cfd::CloseOption;
+use daemon::model::cfd::ExitLevels;
+use daemon::model::cfd::MinimumSize;
 use daemon::model::cfd::OrderId;
-use daemon::model::Identity;
+use daemon::model::cfd::SettlementRejectionPolicy;
+use daemon::model::cfd::SlippageBound;
+use daemon::model::DisplayName;
+use daemon::model::Leverage;
+use daemon::model::Position;
+use daemon::model::Price;
+use daemon::model::Role;
+use daemon::model::Timestamp;
 use daemon::model::Usd;
 use daemon::monitor::Event;
+use daemon::noise;
 use daemon::oracle;
+use daemon::oracle::UnknownEventPolicy;
+use daemon::price_feed;
+use daemon::process_manager::CetPolicy;
+use daemon::projection::CfdAction;
 use daemon::projection::CfdState;
+use daemon::projection::ConnectedTaker;
+use daemon::seed::IdentityPolicy;
+use daemon::taker_cfd;
+use daemon::wire::EncryptedJsonCodec;
+use daemon::wire::MakerToTaker;
+use daemon::wire::TakerToMaker;
+use daemon::wire::Version;
+use futures::SinkExt;
+use futures::StreamExt;
 use maia::secp256k1_zkp::schnorrsig;
 use rust_decimal_macros::dec;
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
+use tokio::net::TcpStream;
 use tokio::time::sleep;
+use tokio_util::codec::Framed;
 mod harness;
 
 /// Assert the next state of the single cfd present at both maker and taker
@@ -55,6 +92,116 @@ async fn taker_receives_order_from_maker_on_publication() {
     assert_eq!(published.unwrap(), received.unwrap());
 }
 
+#[tokio::test]
+async fn order_without_price_is_quoted_with_markup() {
+    let _guard = init_tracing();
+    let maker_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut maker = Maker::start(
+        &MakerConfig::default().with_markup("1%".parse().unwrap()),
+        maker_listener,
+    )
+    .await;
+    let mut taker = Taker::start(&TakerConfig::default(), maker.listen_addr, maker.identity).await;
+
+    assert!(is_next_none(taker.order_feed()).await.unwrap());
+
+    maker
+        .publish_order(maker_cfd::NewOrder {
+            price: None,
+            ..dummy_new_order()
+        })
+        .await;
+
+    let received = next_some(taker.order_feed()).await.unwrap();
+
+    // The harness quotes the market at the dummy price
+    assert_eq!(received.price, Price::new(dec!(50_500)).unwrap());
+}
+
+#[tokio::test]
+async fn order_without_price_is_refused_on_stale_quote() {
+    let _guard = init_tracing();
+    let maker_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let maker = Maker::start(&MakerConfig::default(), maker_listener).await;
+
+    let stale_age = price_feed::MAX_QUOTE_AGE.as_secs() as i64 + 60;
+    maker
+        .update_quote(price_feed::Quote {
+            timestamp: Timestamp::new(Timestamp::now().seconds() - stale_age),
+            ..dummy_quote()
+        })
+        .await;
+
+    let result = maker
+        .system
+        .cfd_actor_addr
+        .send(maker_cfd::NewOrder {
+            price: None,
+            ..dummy_new_order()
+        })
+        .await
+        .unwrap();
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn takers_receive_different_orders_if_overridden() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+    let mut other_taker =
+        Taker::start(&TakerConfig::default(), maker.listen_addr, maker.identity).await;
+
+    assert!(is_next_none(taker.order_feed()).await.unwrap());
+    assert!(is_next_none(other_taker.order_feed()).await.unwrap());
+
+    let overridden_price = Price::new(dec!(60_000)).unwrap();
+    maker
+        .override_order(
+            other_taker.id,
+            maker_cfd::NewOrder {
+                price: Some(overridden_price),
+                ..dummy_new_order()
+            },
+        )
+        .await;
+    maker.publish_order(dummy_new_order()).await;
+
+    let (published, received, other_received) = tokio::join!(
+        next_some(maker.order_feed()),
+        next_some(taker.order_feed()),
+        next_some(other_taker.order_feed())
+    );
+    let (published, received, other_received) = (
+        published.unwrap(),
+        received.unwrap(),
+        other_received.unwrap(),
+    );
+
+    assert_eq!(published, received);
+    assert_eq!(other_received.price, overridden_price);
+    assert_ne!(received.id, other_received.id);
+    assert_ne!(received.price, other_received.price);
+}
+
+#[tokio::test]
+async fn taker_receives_withdrawal_of_order_from_maker() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+    next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    maker.withdraw_order().await;
+
+    assert!(is_next_none(maker.order_feed()).await.unwrap());
+    assert!(is_next_none(taker.order_feed()).await.unwrap());
+}
+
 #[tokio::test]
 async fn taker_takes_order_and_maker_rejects() {
     let _guard = init_tracing();
@@ -63,122 +210,1256 @@ async fn taker_takes_order_and_maker_rejects() {
     // TODO: Why is this needed? For the cfd stream it is not needed
     is_next_none(taker.order_feed()).await.unwrap();
 
-    maker.publish_order(dummy_new_order()).await;
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_oracle_healthy().await;
+    maker.mocks.mock_oracle_announcement().await;
+    taker.take_order(received.clone(), Usd::new(dec!(10))).await;
+
+    assert_next_state!(CfdState::PendingSetup, maker, taker, received.id);
+
+    maker.reject_take_request(received.clone()).await;
+
+    assert_next_state!(CfdState::Rejected, maker, taker, received.id);
+}
+
+#[tokio::test]
+async fn maker_is_notified_when_order_is_taken() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_oracle_healthy().await;
+    maker.mocks.mock_oracle_announcement().await;
+    taker.take_order(received.clone(), Usd::new(dec!(10))).await;
+
+    let order_taken = next_some(maker.order_taken_feed()).await.unwrap();
+
+    assert_eq!(order_taken.order_id, received.id);
+    assert_eq!(order_taken.taker_id, taker.id);
+    assert_eq!(order_taken.quantity, Usd::new(dec!(10)));
+}
+
+#[tokio::test]
+async fn taker_takes_opposite_position_of_order() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+    assert_eq!(received.position, Position::Short);
+
+    taker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_oracle_healthy().await;
+    maker.mocks.mock_oracle_announcement().await;
+    taker.take_order(received.clone(), Usd::new(dec!(10))).await;
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.position, Position::Long);
+    assert_eq!(maker_cfd.position, Position::Short);
+}
+
+#[tokio::test]
+async fn maker_sets_up_cfd_with_leverage_chosen_by_taker() {
+    let _guard = init_tracing();
+    let maker_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut maker = Maker::start(
+        &MakerConfig::default().with_leverage_choices(vec![Leverage::new(5).unwrap()]),
+        maker_listener,
+    )
+    .await;
+    let mut taker = Taker::start(&TakerConfig::default(), maker.listen_addr, maker.identity).await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+    assert_eq!(
+        received.leverage_choices,
+        vec![Leverage::new(2).unwrap(), Leverage::new(5).unwrap()]
+    );
+
+    taker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_oracle_healthy().await;
+    maker.mocks.mock_oracle_announcement().await;
+    taker
+        .system
+        .cfd_actor_addr
+        .send(taker_cfd::TakeOffer {
+            order_id: received.id,
+            quantity: Usd::new(dec!(10)),
+            fee_rate: None,
+            slippage_bound: SlippageBound::default(),
+            leverage: Some(Leverage::new(5).unwrap()),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(maker_cfd.state, CfdState::PendingSetup);
+    assert_eq!(taker_cfd.leverage, Leverage::new(5).unwrap());
+    assert_eq!(maker_cfd.leverage, Leverage::new(5).unwrap());
+}
+
+#[tokio::test]
+async fn taker_refuses_to_take_order_if_oracle_is_unavailable() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_unavailable().await;
+    let result = taker
+        .system
+        .cfd_actor_addr
+        .send(taker_cfd::TakeOffer {
+            order_id: received.id,
+            quantity: Usd::new(dec!(10)),
+            fee_rate: None,
+            slippage_bound: SlippageBound::default(),
+            leverage: None,
+        })
+        .await
+        .unwrap();
+
+    assert!(result.is_err());
+    assert!(taker.cfd_feed().borrow().is_empty());
+}
+
+#[tokio::test]
+async fn taker_refuses_to_take_order_without_announcement() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_healthy().await;
+    taker.mocks.mock_oracle_announcement_unavailable().await;
+    let result = taker
+        .system
+        .cfd_actor_addr
+        .send(taker_cfd::TakeOffer {
+            order_id: received.id,
+            quantity: Usd::new(dec!(10)),
+            fee_rate: None,
+            slippage_bound: SlippageBound::default(),
+            leverage: None,
+        })
+        .await
+        .unwrap();
+
+    assert!(result.is_err());
+    assert!(taker.recorded_events(received.id).await.is_empty());
+}
+
+#[tokio::test]
+async fn taker_refuses_to_take_order_below_minimum_notional() {
+    let _guard = init_tracing();
+    let maker_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut maker = Maker::start(&MakerConfig::default(), maker_listener).await;
+    let mut taker = Taker::start(
+        &TakerConfig::default().with_minimum_size(MinimumSize {
+            notional: Some(Usd::new(dec!(10))),
+            margin: None,
+        }),
+        maker.listen_addr,
+        maker.identity,
+    )
+    .await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_healthy().await;
+    let result = taker
+        .system
+        .cfd_actor_addr
+        .send(taker_cfd::TakeOffer {
+            order_id: received.id,
+            quantity: Usd::new(dec!(9)),
+            fee_rate: None,
+            slippage_bound: SlippageBound::default(),
+            leverage: None,
+        })
+        .await
+        .unwrap();
+
+    assert!(result.is_err());
+    assert!(taker.cfd_feed().borrow().is_empty());
+}
+
+#[tokio::test]
+async fn taker_refuses_to_take_order_exceeding_max_price() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_healthy().await;
+    let result = taker
+        .system
+        .cfd_actor_addr
+        .send(taker_cfd::TakeOffer {
+            order_id: received.id,
+            quantity: Usd::new(dec!(10)),
+            fee_rate: None,
+            slippage_bound: SlippageBound {
+                max_price: Some(Price::new(dec!(49_999)).unwrap()),
+                min_price: None,
+            },
+            leverage: None,
+        })
+        .await
+        .unwrap();
+
+    assert!(result.is_err());
+    assert!(taker.cfd_feed().borrow().is_empty());
+}
+
+#[tokio::test]
+async fn taker_takes_order_within_max_price() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_oracle_healthy().await;
+    maker.mocks.mock_oracle_announcement().await;
+
+    taker
+        .system
+        .cfd_actor_addr
+        .send(taker_cfd::TakeOffer {
+            order_id: received.id,
+            quantity: Usd::new(dec!(10)),
+            fee_rate: None,
+            slippage_bound: SlippageBound {
+                max_price: Some(Price::new(dec!(50_000)).unwrap()),
+                min_price: None,
+            },
+            leverage: None,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_next_state!(CfdState::PendingSetup, maker, taker, received.id);
+}
+
+#[tokio::test]
+async fn taker_cancels_stuck_contract_setup() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_oracle_healthy().await;
+    maker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_wallet_release_utxos().await;
+
+    taker.take_order(received.clone(), Usd::new(dec!(5))).await;
+    assert_next_state!(CfdState::PendingSetup, maker, taker, received.id);
+
+    assert_eq!(
+        taker.system.running_protocols().await.unwrap(),
+        vec![taker_cfd::RunningProtocol {
+            order_id: received.id,
+            protocol: taker_cfd::Protocol::ContractSetup,
+        }]
+    );
+
+    let cancelled = taker.system.cancel_protocol(received.id).await.unwrap();
+
+    assert_eq!(cancelled, taker_cfd::Protocol::ContractSetup);
+    assert!(taker.system.running_protocols().await.unwrap().is_empty());
+    let taker_cfds = next(taker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfds[0].state, CfdState::SetupFailed);
+}
+
+#[tokio::test]
+async fn maker_refuses_to_publish_order_below_minimum_notional() {
+    let _guard = init_tracing();
+    let maker_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut maker = Maker::start(
+        &MakerConfig::default().with_minimum_size(MinimumSize {
+            notional: Some(Usd::new(dec!(10))),
+            margin: None,
+        }),
+        maker_listener,
+    )
+    .await;
+
+    // The dummy order has a minimum quantity of 5
+    let result = maker
+        .system
+        .cfd_actor_addr
+        .send(dummy_new_order())
+        .await
+        .unwrap();
+    assert!(result.is_err());
+
+    maker
+        .publish_order(maker_cfd::NewOrder {
+            min_quantity: Usd::new(dec!(10)),
+            ..dummy_new_order()
+        })
+        .await;
+    assert!(maker.order_feed().borrow().is_some());
+}
+
+#[tokio::test]
+async fn maker_refuses_to_publish_order_exceeding_max_cets() {
+    let _guard = init_tracing();
+    let maker_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut maker = Maker::start(&MakerConfig::default().with_max_cets(10), maker_listener).await;
+
+    let result = maker
+        .system
+        .cfd_actor_addr
+        .send(dummy_new_order())
+        .await
+        .unwrap();
+
+    assert!(result.is_err());
+    assert!(maker.order_feed().borrow().is_none());
+}
+
+#[tokio::test]
+async fn maker_rejects_take_exceeding_max_quantity_per_taker() {
+    let _guard = init_tracing();
+    let maker_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut maker = Maker::start(
+        &MakerConfig::default().with_max_quantity_per_taker(Usd::new(dec!(10))),
+        maker_listener,
+    )
+    .await;
+    let mut taker = Taker::start(&TakerConfig::default(), maker.listen_addr, maker.identity).await;
+    let mut other_taker =
+        Taker::start(&TakerConfig::default(), maker.listen_addr, maker.identity).await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    taker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_oracle_healthy().await;
+    other_taker.mocks.mock_oracle_announcement().await;
+    other_taker.mocks.mock_oracle_healthy().await;
+    maker.mocks.mock_oracle_announcement().await;
+
+    maker.publish_order(dummy_new_order()).await;
+    let (_, first_order) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker
+        .take_order(first_order.clone(), Usd::new(dec!(5)))
+        .await;
+    assert_next_state!(CfdState::PendingSetup, maker, taker, first_order.id);
+
+    // Taking an order removes it, wait for that before publishing the next one
+    assert!(is_next_none(maker.order_feed()).await.unwrap());
+    assert!(is_next_none(taker.order_feed()).await.unwrap());
+
+    maker.publish_order(dummy_new_order()).await;
+    let (_, second_order) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    // 5 + 10 exceeds the limit of 10 for this taker
+    taker
+        .take_order(second_order.clone(), Usd::new(dec!(10)))
+        .await;
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until the rejection arrives
+
+    let taker_cfds = taker.cfd_feed().borrow().clone();
+    let rejected = taker_cfds
+        .iter()
+        .find(|cfd| cfd.order_id == second_order.id)
+        .unwrap();
+    assert_eq!(rejected.state, CfdState::Rejected);
+
+    // The order is still available for other takers
+    other_taker
+        .take_order(second_order.clone(), Usd::new(dec!(10)))
+        .await;
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    let maker_cfds = maker.cfd_feed().borrow().clone();
+    assert_eq!(maker_cfds.len(), 2);
+    let accepted = maker_cfds
+        .iter()
+        .find(|cfd| cfd.order_id == second_order.id)
+        .unwrap();
+    assert_eq!(accepted.state, CfdState::PendingSetup);
+    assert_eq!(accepted.counterparty, other_taker.id);
+}
+
+#[tokio::test]
+#[ignore = "expensive, runs on CI"]
+async fn taker_takes_order_and_maker_accepts_and_contract_setup() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_oracle_healthy().await;
+    maker.mocks.mock_oracle_announcement().await;
+
+    taker.take_order(received.clone(), Usd::new(dec!(5))).await;
+    assert_next_state!(CfdState::PendingSetup, maker, taker, received.id);
+
+    maker.mocks.mock_party_params().await;
+    taker.mocks.mock_party_params().await;
+
+    maker.mocks.mock_monitor_oracle_attestation().await;
+    taker.mocks.mock_monitor_oracle_attestation().await;
+
+    maker.mocks.mock_oracle_monitor_attestation().await;
+    taker.mocks.mock_oracle_monitor_attestation().await;
+
+    maker.mocks.mock_monitor_start_monitoring().await;
+    taker.mocks.mock_monitor_start_monitoring().await;
+
+    maker.mocks.mock_wallet_sign_and_broadcast().await;
+    taker.mocks.mock_wallet_sign_and_broadcast().await;
+
+    maker.accept_take_request(received.clone()).await;
+    assert_next_state!(CfdState::PendingOpen, maker, taker, received.id);
+
+    deliver_event!(maker, taker, Event::LockFinality(received.id));
+    assert_next_state!(CfdState::Open, maker, taker, received.id);
+}
+
+#[tokio::test]
+#[ignore = "expensive, runs on CI"]
+async fn contract_setup_fails_if_taker_cannot_sign() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    is_next_none(taker.order_feed()).await.unwrap();
+
+    maker.publish_order(dummy_new_order()).await;
+
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+
+    taker.mocks.mock_oracle_announcement().await;
+    taker.mocks.mock_oracle_healthy().await;
+    maker.mocks.mock_oracle_announcement().await;
+
+    taker.take_order(received.clone(), Usd::new(dec!(5))).await;
+    assert_next_state!(CfdState::PendingSetup, maker, taker, received.id);
+
+    maker.mocks.mock_party_params().await;
+    taker.mocks.mock_party_params().await;
+
+    maker.mocks.mock_wallet_sign_and_broadcast().await;
+    taker.mocks.mock_wallet_sign_fails().await;
+    maker.mocks.mock_wallet_release_utxos().await;
+    taker.mocks.mock_wallet_release_utxos().await;
+
+    maker.accept_take_request(received.clone()).await;
+
+    let taker_cfds = next(taker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfds[0].state, CfdState::SetupFailed);
+    assert!(taker_cfds[0]
+        .setup_failure_reason
+        .as_deref()
+        .unwrap()
+        .contains("Failed to sign"));
+    assert_eq!(
+        names(&taker.recorded_events(received.id).await),
+        vec!["ContractSetupFailed"]
+    );
+
+    // The maker only gives up once it times out waiting for the signed lock transaction of the
+    // taker, which can take longer than the usual waiting time on the feeds
+    tokio::time::timeout(Duration::from_secs(90), maker.cfd_feed().changed())
+        .await
+        .expect("Maker did not fail the contract setup in time")
+        .unwrap();
+    let maker_cfds = maker.cfd_feed().borrow().clone();
+    assert_eq!(maker_cfds[0].state, CfdState::SetupFailed);
+    assert_eq!(
+        names(&maker.recorded_events(received.id).await),
+        vec!["ContractSetupFailed"]
+    );
+}
+
+#[tokio::test]
+async fn maker_and_taker_agree_on_signed_commit_tx_of_open_cfd() {
+    let _guard = init_tracing();
+    let (maker, taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    let maker_commit_tx = maker.system.commit_tx(order_id).await.unwrap();
+    let taker_commit_tx = taker.system.commit_tx(order_id).await.unwrap();
+
+    assert_eq!(maker_commit_tx.txid, maker_commit_tx.tx.txid());
+    assert_eq!(maker_commit_tx.txid, taker_commit_tx.txid);
+}
+
+#[tokio::test]
+async fn rebuilt_projection_matches_fresh_load() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    taker.propose_settlement(order_id).await;
+    let (_, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(maker_cfd.state, CfdState::IncomingSettlementProposal);
+
+    maker.rebuild_projection().await;
+
+    let rebuilt = next(maker.cfd_feed()).await.unwrap();
+    let fresh = maker.fresh_projection_cfds().await;
+
+    assert_eq!(rebuilt[0].state, CfdState::Open);
+    assert_eq!(
+        serde_json::to_value(rebuilt).unwrap(),
+        serde_json::to_value(fresh).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn collaboratively_close_an_open_cfd() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    taker.propose_settlement(order_id).await;
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::OutgoingSettlementProposal);
+    assert_eq!(maker_cfd.state, CfdState::IncomingSettlementProposal);
+    let proposal = taker_cfd.settlement_proposal.unwrap();
+
+    maker.mocks.mock_monitor_collaborative_settlement().await;
+    taker.mocks.mock_monitor_collaborative_settlement().await;
+
+    maker.accept_settlement_proposal(order_id).await;
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    assert_next_state!(CfdState::PendingClose, maker, taker, order_id);
+
+    deliver_event!(maker, taker, Event::CloseFinality(order_id));
+
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    assert_next_state!(CfdState::Closed, maker, taker, order_id);
+
+    let taker_receipt = taker.cfd_feed().borrow()[0].receipt.clone().unwrap();
+    let maker_receipt = maker.cfd_feed().borrow()[0].receipt.clone().unwrap();
+    assert_eq!(taker_receipt.spend_txid, maker_receipt.spend_txid);
+    assert_eq!(taker_receipt.closing_price, maker_receipt.closing_price);
+
+    let expected_events = vec![
+        "ContractSetupCompleted",
+        "LockConfirmed",
+        "CollaborativeSettlementCompleted",
+        "CollaborativeSettlementConfirmed",
+    ];
+    assert_eq!(
+        names(&taker.recorded_events(order_id).await),
+        expected_events
+    );
+    assert_eq!(
+        names(&maker.recorded_events(order_id).await),
+        expected_events
+    );
+
+    let (taker_tx, taker_amounts) = settlement_amounts(&taker.recorded_events(order_id).await);
+    let (maker_tx, maker_amounts) = settlement_amounts(&maker.recorded_events(order_id).await);
+    assert_eq!(taker_tx, maker_tx);
+    assert_eq!(taker_amounts, maker_amounts);
+
+    let (maker_amount, taker_amount) = taker_amounts;
+    assert_eq!(maker_amount, proposal.maker_payout);
+    assert_eq!(taker_amount, proposal.taker_payout);
+    assert_eq!(taker_receipt.payout, taker_amount);
+    assert_eq!(maker_receipt.payout, maker_amount);
+}
+
+#[tokio::test]
+async fn taker_accepts_settlement_counter_proposal_of_maker() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    taker.propose_settlement(order_id).await;
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::OutgoingSettlementProposal);
+    assert_eq!(maker_cfd.state, CfdState::IncomingSettlementProposal);
+
+    let counter_price = Price::new(dec!(49_000)).unwrap();
+    maker
+        .counter_settlement_proposal(order_id, counter_price)
+        .await;
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::IncomingSettlementProposal);
+    assert_eq!(maker_cfd.state, CfdState::OutgoingSettlementProposal);
+    assert!(taker_cfd.actions.contains(&CfdAction::AcceptSettlement));
+
+    let counter_proposal = taker_cfd.settlement_proposal.unwrap();
+    assert_eq!(counter_proposal.proposed_by, Role::Maker);
+    assert_eq!(counter_proposal.price, counter_price);
+
+    maker.mocks.mock_monitor_collaborative_settlement().await;
+    taker.mocks.mock_monitor_collaborative_settlement().await;
+
+    taker.accept_settlement_counter_proposal(order_id).await;
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    assert_next_state!(CfdState::PendingClose, maker, taker, order_id);
+
+    deliver_event!(maker, taker, Event::CloseFinality(order_id));
+
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    assert_next_state!(CfdState::Closed, maker, taker, order_id);
+
+    let taker_receipt = taker.cfd_feed().borrow()[0].receipt.clone().unwrap();
+    let maker_receipt = maker.cfd_feed().borrow()[0].receipt.clone().unwrap();
+    assert_eq!(taker_receipt.closing_price, counter_price);
+    assert_eq!(maker_receipt.closing_price, counter_price);
+
+    let (_, taker_amounts) = settlement_amounts(&taker.recorded_events(order_id).await);
+    let (_, maker_amounts) = settlement_amounts(&maker.recorded_events(order_id).await);
+    assert_eq!(taker_amounts, maker_amounts);
+}
+
+#[tokio::test]
+async fn taker_settles_all_eligible_cfds() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, open_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    maker.publish_order(dummy_new_order()).await;
+    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
+        .await
+        .unwrap();
+    taker.take_order(received.clone(), Usd::new(dec!(5))).await;
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until the maker sees the take request
+    maker.reject_take_request(received.clone()).await;
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    let attempts = taker.settle_all().await;
+
+    assert_eq!(attempts.len(), 1, "only the open CFD can be settled");
+    assert_eq!(attempts[0].order_id, open_id);
+    assert!(attempts[0].result.is_ok());
+
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    let taker_cfds = taker.cfd_feed().borrow().clone();
+    let state_of = |id| {
+        taker_cfds
+            .iter()
+            .find(|cfd| cfd.order_id == id)
+            .unwrap()
+            .state
+    };
+    assert_eq!(state_of(open_id), CfdState::OutgoingSettlementProposal);
+    assert_eq!(state_of(received.id), CfdState::Rejected);
+}
+
+fn names(events: &[CfdEvent]) -> Vec<String> {
+    events.iter().map(|event| event.to_json().0).collect()
+}
+
+/// The spend transaction and the amounts of maker and taker recorded for a collaborative
+/// settlement.
+fn settlement_amounts(events: &[CfdEvent]) -> (Transaction, (Amount, Amount)) {
+    events
+        .iter()
+        .find_map(|event| match event {
+            CfdEvent::CollaborativeSettlementCompleted {
+                spend_tx,
+                maker: Some(maker),
+                taker: Some(taker),
+                ..
+            } => Some((spend_tx.clone(), (*maker, *taker))),
+            _ => None,
+        })
+        .expect("collaborative settlement with amounts to be recorded")
+}
+
+#[tokio::test]
+async fn taker_proposes_settlement_once_take_profit_is_reached() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    let take_profit = Price::new(dec!(49_900)).unwrap();
+    taker
+        .system
+        .set_exit_levels(
+            order_id,
+            ExitLevels {
+                take_profit: Some(take_profit),
+                stop_loss: None,
+            },
+        )
+        .await
+        .unwrap();
+    let taker_cfds = next(taker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfds[0].take_profit, Some(take_profit));
+
+    // Every quote above the take profit reaches it
+    taker.update_quote(dummy_quote()).await;
+    taker.update_quote(dummy_quote()).await;
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::OutgoingSettlementProposal);
+    assert_eq!(maker_cfd.state, CfdState::IncomingSettlementProposal);
+    assert_eq!(
+        taker.system.running_protocols().await.unwrap(),
+        vec![taker_cfd::RunningProtocol {
+            order_id,
+            protocol: taker_cfd::Protocol::CollaborativeSettlement,
+        }]
+    );
+
+    maker.mocks.mock_monitor_collaborative_settlement().await;
+    taker.mocks.mock_monitor_collaborative_settlement().await;
+
+    maker.accept_settlement_proposal(order_id).await;
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    assert_next_state!(CfdState::PendingClose, maker, taker, order_id);
+
+    taker.update_quote(dummy_quote()).await;
+    sleep(Duration::from_secs(1)).await; // need to wait a bit until a proposal would be sent
+
+    assert!(
+        taker.system.running_protocols().await.unwrap().is_empty(),
+        "settled CFD should not be proposed to be settled again"
+    );
+}
+
+#[tokio::test]
+async fn taker_proposes_settlement_once_stop_loss_is_reached() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    taker
+        .system
+        .set_exit_levels(
+            order_id,
+            ExitLevels {
+                take_profit: None,
+                stop_loss: Some(Price::new(dec!(50_100)).unwrap()),
+            },
+        )
+        .await
+        .unwrap();
+    next(taker.cfd_feed()).await.unwrap();
+
+    // Every quote below the stop loss reaches it
+    taker.update_quote(dummy_quote()).await;
+    taker.update_quote(dummy_quote()).await;
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::OutgoingSettlementProposal);
+    assert_eq!(maker_cfd.state, CfdState::IncomingSettlementProposal);
+    assert_eq!(taker.system.running_protocols().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn maker_rejects_settlement_proposal_far_from_market_price() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    taker
+        .system
+        .cfd_actor_addr
+        .send(taker_cfd::ProposeSettlement {
+            order_id,
+            current_price: Price::new(dec!(100_000)).unwrap(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let taker_cfds = next(taker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfds[0].state, CfdState::OutgoingSettlementProposal);
+
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until the maker decided
+
+    let maker_cfds = maker.cfd_feed().borrow().clone();
+    assert_eq!(
+        maker_cfds[0].state,
+        CfdState::Open,
+        "proposal should never be presented to the maker"
+    );
+}
+
+#[tokio::test]
+async fn rejected_settlement_publishes_commit_transaction() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    taker.propose_settlement(order_id).await;
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::OutgoingSettlementProposal);
+    assert_eq!(maker_cfd.state, CfdState::IncomingSettlementProposal);
+
+    maker.reject_settlement_proposal(order_id).await;
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    assert_next_state!(CfdState::PendingCommit, maker, taker, order_id);
+
+    let expected_events = vec![
+        "ContractSetupCompleted",
+        "LockConfirmed",
+        "CollaborativeSettlementRejected",
+    ];
+    assert_eq!(
+        names(&taker.recorded_events(order_id).await),
+        expected_events
+    );
+    assert_eq!(
+        names(&maker.recorded_events(order_id).await),
+        expected_events
+    );
+}
+
+#[tokio::test]
+async fn rejected_settlement_keeps_cfd_open_if_configured() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) = start_from_open_cfd_state_with(
+        OliviaData::example_0().announcement(),
+        MakerConfig::default()
+            .with_settlement_rejection_policy(SettlementRejectionPolicy::KeepOpen),
+        TakerConfig::default()
+            .with_settlement_rejection_policy(SettlementRejectionPolicy::KeepOpen),
+    )
+    .await;
+
+    taker.propose_settlement(order_id).await;
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::OutgoingSettlementProposal);
+    assert_eq!(maker_cfd.state, CfdState::IncomingSettlementProposal);
+
+    maker.reject_settlement_proposal(order_id).await;
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    assert_next_state!(CfdState::Open, maker, taker, order_id);
+
+    let expected_events = vec!["ContractSetupCompleted", "LockConfirmed"];
+    assert_eq!(
+        names(&taker.recorded_events(order_id).await),
+        expected_events
+    );
+    assert_eq!(
+        names(&maker.recorded_events(order_id).await),
+        expected_events
+    );
+
+    // the CFD can still be settled collaboratively
+    taker.propose_settlement(order_id).await;
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::OutgoingSettlementProposal);
+    assert_eq!(maker_cfd.state, CfdState::IncomingSettlementProposal);
+}
+
+#[tokio::test]
+async fn transiently_failing_broadcast_is_retried() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) = start_from_open_cfd_state_with(
+        OliviaData::example_0().announcement(),
+        MakerConfig::default(),
+        TakerConfig::default().with_rebroadcast_interval(Duration::from_secs(1)),
+    )
+    .await;
+
+    let attempts = taker.mocks.mock_wallet_broadcast_fails_transiently(2).await;
+
+    taker.force_close(order_id).await;
+    sleep(Duration::from_secs(5)).await; // give the process manager time to rebroadcast
+
+    assert!(
+        attempts.load(Ordering::SeqCst) > 2,
+        "commit transaction should be broadcast again until it succeeds"
+    );
+
+    deliver_event!(maker, taker, Event::CommitFinality(order_id));
+    sleep(Duration::from_secs(1)).await; // need to wait a bit until the confirmation is processed
+
+    let attempts_until_confirmed = attempts.load(Ordering::SeqCst);
+    sleep(Duration::from_secs(3)).await;
+
+    assert_eq!(
+        attempts.load(Ordering::SeqCst),
+        attempts_until_confirmed,
+        "confirmed transaction should not be broadcast again"
+    );
+}
+
+#[tokio::test]
+async fn oracle_is_polled_at_configured_interval() {
+    let _guard = init_tracing();
+    let maker_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let maker = Maker::start(&MakerConfig::default(), maker_listener).await;
+    let mut taker = Taker::start(
+        &TakerConfig::default().with_oracle_sync_interval(Duration::from_millis(200)),
+        maker.listen_addr,
+        maker.identity,
+    )
+    .await;
 
-    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
-        .await
-        .unwrap();
+    // Only let time pass by advancing the clock, the oracle is polled exactly once per interval
+    tokio::time::pause();
+    advance_and_settle(Duration::ZERO).await; // a tick that was already due is not counted
+    let syncs = taker.mocks.mock_oracle_counts_syncs().await;
 
-    taker.mocks.mock_oracle_announcement().await;
-    maker.mocks.mock_oracle_announcement().await;
-    taker.take_order(received.clone(), Usd::new(dec!(10))).await;
+    for _ in 0..10 {
+        advance_and_settle(Duration::from_millis(200)).await;
+    }
 
-    assert_next_state!(CfdState::PendingSetup, maker, taker, received.id);
+    assert_eq!(syncs.load(Ordering::SeqCst), 10);
+}
 
-    maker.reject_take_request(received.clone()).await;
+/// Advance the paused clock and give the tasks woken up by it a chance to run.
+async fn advance_and_settle(duration: Duration) {
+    tokio::time::advance(duration).await;
 
-    assert_next_state!(CfdState::Rejected, maker, taker, received.id);
+    for _ in 0..10 {
+        tokio::task::yield_now().await;
+    }
 }
 
 #[tokio::test]
-#[ignore = "expensive, runs on CI"]
-async fn taker_takes_order_and_maker_accepts_and_contract_setup() {
+async fn force_close_an_open_cfd() {
     let _guard = init_tracing();
-    let (mut maker, mut taker) = start_both().await;
+    let oracle_data = OliviaData::example_0();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(oracle_data.announcement()).await;
 
-    is_next_none(taker.order_feed()).await.unwrap();
+    // Taker initiates force-closing
+    taker.force_close(order_id).await;
 
-    maker.publish_order(dummy_new_order()).await;
+    deliver_event!(maker, taker, Event::CommitFinality(order_id));
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    assert_next_state!(CfdState::OpenCommitted, maker, taker, order_id);
 
-    let (_, received) = next_order(maker.order_feed(), taker.order_feed())
-        .await
-        .unwrap();
+    // After CetTimelockExpired, we're only waiting for attestation
+    deliver_event!(maker, taker, Event::CetTimelockExpired(order_id));
 
-    taker.mocks.mock_oracle_announcement().await;
-    maker.mocks.mock_oracle_announcement().await;
+    // Delivering the wrong attestation does not move state to `PendingCet`
+    deliver_event!(maker, taker, dummy_wrong_attestation());
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    assert_next_state!(CfdState::OpenCommitted, maker, taker, order_id);
 
-    taker.take_order(received.clone(), Usd::new(dec!(5))).await;
-    assert_next_state!(CfdState::PendingSetup, maker, taker, received.id);
+    // Delivering correct attestation moves the state `PendingCet`
+    deliver_event!(maker, taker, oracle_data.attestation());
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    assert_next_state!(CfdState::PendingCet, maker, taker, order_id);
 
-    maker.mocks.mock_party_params().await;
-    taker.mocks.mock_party_params().await;
+    deliver_event!(maker, taker, Event::CetFinality(order_id));
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    assert_next_state!(CfdState::Closed, maker, taker, order_id);
+}
 
-    maker.mocks.mock_monitor_oracle_attestation().await;
-    taker.mocks.mock_monitor_oracle_attestation().await;
+#[tokio::test]
+async fn cet_is_published_automatically_if_attested_after_cet_timelock() {
+    let _guard = init_tracing();
+    let oracle_data = OliviaData::example_0();
+    let (mut maker, mut taker, order_id) = start_from_open_cfd_state_with(
+        oracle_data.announcement(),
+        MakerConfig::default().with_cet_policy(CetPolicy::LeaveToCounterparty),
+        TakerConfig::default(),
+    )
+    .await;
 
-    maker.mocks.mock_oracle_monitor_attestation().await;
-    taker.mocks.mock_oracle_monitor_attestation().await;
+    taker.force_close(order_id).await;
 
-    maker.mocks.mock_monitor_start_monitoring().await;
-    taker.mocks.mock_monitor_start_monitoring().await;
+    deliver_event!(maker, taker, Event::CommitFinality(order_id));
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    assert_next_state!(CfdState::OpenCommitted, maker, taker, order_id);
 
-    maker.mocks.mock_wallet_sign_and_broadcast().await;
-    taker.mocks.mock_wallet_sign_and_broadcast().await;
+    deliver_event!(maker, taker, Event::CetTimelockExpired(order_id));
 
-    maker.accept_take_request(received.clone()).await;
-    assert_next_state!(CfdState::PendingOpen, maker, taker, received.id);
+    let taker_broadcasts = taker.mocks.mock_wallet_records_broadcasts().await;
+    let maker_broadcasts = maker.mocks.mock_wallet_records_broadcasts().await;
 
-    deliver_event!(maker, taker, Event::LockFinality(received.id));
-    assert_next_state!(CfdState::Open, maker, taker, received.id);
+    deliver_event!(maker, taker, oracle_data.attestation());
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::PendingCet);
+    assert_eq!(maker_cfd.state, CfdState::CetReady);
+
+    let cet = taker
+        .recorded_events(order_id)
+        .await
+        .into_iter()
+        .find_map(|event| match event {
+            CfdEvent::OracleAttestedPostCetTimelock { cet, .. } => Some(cet),
+            _ => None,
+        })
+        .expect("CET to be decrypted");
+
+    assert_eq!(*taker_broadcasts.lock().unwrap(), vec![cet.txid()]);
+    assert!(
+        maker_broadcasts.lock().unwrap().is_empty(),
+        "maker leaves the CET to the taker"
+    );
 }
 
 #[tokio::test]
-async fn collaboratively_close_an_open_cfd() {
+async fn refund_a_committed_cfd_after_refund_timelock_expired() {
     let _guard = init_tracing();
     let (mut maker, mut taker, order_id) =
         start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
 
-    taker.propose_settlement(order_id).await;
+    taker.force_close(order_id).await;
 
+    deliver_event!(maker, taker, Event::CommitFinality(order_id));
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    assert_next_state!(CfdState::OpenCommitted, maker, taker, order_id);
+
+    // The oracle never attested, so once the refund timelock expired the refund can be published
+    deliver_event!(maker, taker, Event::RefundTimelockExpired(order_id));
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
     let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
-    assert_eq!(taker_cfd.state, CfdState::OutgoingSettlementProposal);
-    assert_eq!(maker_cfd.state, CfdState::IncomingSettlementProposal);
+    assert_eq!(taker_cfd.state, CfdState::OpenCommitted);
+    assert_eq!(maker_cfd.state, CfdState::OpenCommitted);
+    assert!(taker_cfd.actions.contains(&CfdAction::Refund));
+    assert!(maker_cfd.actions.contains(&CfdAction::Refund));
 
-    maker.mocks.mock_monitor_collaborative_settlement().await;
-    taker.mocks.mock_monitor_collaborative_settlement().await;
+    taker.refund(order_id).await;
 
-    maker.accept_settlement_proposal(order_id).await;
+    deliver_event!(maker, taker, Event::RefundFinality(order_id));
     sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    assert_next_state!(CfdState::Refunded, maker, taker, order_id);
+}
 
-    assert_next_state!(CfdState::PendingClose, maker, taker, order_id);
+#[tokio::test]
+async fn commit_and_settle_refunds_without_further_interaction() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
 
-    deliver_event!(maker, taker, Event::CloseFinality(order_id));
+    taker.commit_and_settle(order_id).await;
 
+    deliver_event!(maker, taker, Event::CommitFinality(order_id));
     sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    assert_next_state!(CfdState::OpenCommitted, maker, taker, order_id);
 
-    assert_next_state!(CfdState::Closed, maker, taker, order_id);
+    // The oracle never attested, the taker refunds without being asked to
+    deliver_event!(maker, taker, Event::RefundTimelockExpired(order_id));
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert_eq!(taker_cfd.state, CfdState::PendingRefund);
+    assert_eq!(maker_cfd.state, CfdState::OpenCommitted);
+
+    deliver_event!(maker, taker, Event::RefundFinality(order_id));
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    assert_next_state!(CfdState::Refunded, maker, taker, order_id);
+
+    assert_eq!(
+        names(&taker.recorded_events(order_id).await),
+        vec![
+            "ContractSetupCompleted",
+            "LockConfirmed",
+            "ManualCommitAndSettle",
+            "CommitConfirmed",
+            "RefundTimelockConfirmed",
+            "ManualRefund",
+            "RefundConfirmed",
+        ]
+    );
 }
 
 #[tokio::test]
-async fn force_close_an_open_cfd() {
+async fn paused_cfd_does_not_commit_on_attestation() {
     let _guard = init_tracing();
     let oracle_data = OliviaData::example_0();
     let (mut maker, mut taker, order_id) =
         start_from_open_cfd_state(oracle_data.announcement()).await;
 
-    // Taker initiates force-closing
+    taker.system.pause_monitoring(order_id).await.unwrap();
+    maker.system.pause_monitoring(order_id).await.unwrap();
+
+    let (taker_cfd, maker_cfd) = next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+    assert!(taker_cfd.monitoring_paused);
+    assert!(maker_cfd.monitoring_paused);
+
+    deliver_event!(maker, taker, oracle_data.attestation());
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both would transition
+
+    let taker_cfd = taker.cfd_feed().borrow()[0].clone();
+    let maker_cfd = maker.cfd_feed().borrow()[0].clone();
+    assert_eq!(
+        taker_cfd.state,
+        CfdState::Open,
+        "attestation should not trigger a commit"
+    );
+    assert_eq!(
+        maker_cfd.state,
+        CfdState::Open,
+        "attestation should not trigger a commit"
+    );
+}
+
+#[tokio::test]
+async fn monitoring_event_of_paused_cfd_is_handled_once_resumed() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    taker.system.pause_monitoring(order_id).await.unwrap();
+    maker.system.pause_monitoring(order_id).await.unwrap();
+    next_cfd(taker.cfd_feed(), maker.cfd_feed()).await.unwrap();
+
+    deliver_event!(maker, taker, Event::CommitFinality(order_id));
+    sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(taker.cfd_feed().borrow()[0].state, CfdState::Open);
+    assert_eq!(maker.cfd_feed().borrow()[0].state, CfdState::Open);
+
+    taker.system.resume_monitoring(order_id).await.unwrap();
+    maker.system.resume_monitoring(order_id).await.unwrap();
+    sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(taker.cfd_feed().borrow()[0].state, CfdState::OpenCommitted);
+    assert_eq!(maker.cfd_feed().borrow()[0].state, CfdState::OpenCommitted);
+}
+
+#[tokio::test]
+async fn close_options_follow_the_state_of_the_cfd() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    let taker_options = taker
+        .system
+        .close_options(order_id, Some(dummy_price()))
+        .await
+        .unwrap();
+    let taker_options_without_price = taker.system.close_options(order_id, None).await.unwrap();
+    let maker_options = maker
+        .system
+        .close_options(order_id, Some(dummy_price()))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        kinds(&taker_options),
+        vec![CloseKind::CollaborativeSettlement, CloseKind::Commit]
+    );
+    assert!(
+        taker_options[0].payout.is_some(),
+        "settlement payout is estimated given a price"
+    );
+    assert_eq!(
+        taker_options_without_price[0].payout, None,
+        "settlement payout is unknown without a price"
+    );
+    assert_eq!(
+        kinds(&maker_options),
+        vec![CloseKind::Commit],
+        "only the taker can propose a settlement"
+    );
+
     taker.force_close(order_id).await;
 
     deliver_event!(maker, taker, Event::CommitFinality(order_id));
     sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
     assert_next_state!(CfdState::OpenCommitted, maker, taker, order_id);
 
-    // After CetTimelockExpired, we're only waiting for attestation
-    deliver_event!(maker, taker, Event::CetTimelockExpired(order_id));
+    let taker_options = taker.system.close_options(order_id, None).await.unwrap();
+    assert!(taker_options.is_empty());
 
-    // Delivering the wrong attestation does not move state to `PendingCet`
-    deliver_event!(maker, taker, dummy_wrong_attestation());
+    deliver_event!(maker, taker, Event::RefundTimelockExpired(order_id));
     sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
-    assert_next_state!(CfdState::OpenCommitted, maker, taker, order_id);
 
-    // Delivering correct attestation moves the state `PendingCet`
-    deliver_event!(maker, taker, oracle_data.attestation());
-    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
-    assert_next_state!(CfdState::PendingCet, maker, taker, order_id);
+    let taker_options = taker.system.close_options(order_id, None).await.unwrap();
+    let maker_options = maker.system.close_options(order_id, None).await.unwrap();
 
-    deliver_event!(maker, taker, Event::CetFinality(order_id));
-    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
-    assert_next_state!(CfdState::Closed, maker, taker, order_id);
+    assert_eq!(kinds(&taker_options), vec![CloseKind::Refund]);
+    assert_eq!(kinds(&maker_options), vec![CloseKind::Refund]);
+    assert!(taker_options[0].payout.is_some());
+    assert!(maker_options[0].payout.is_some());
+}
+
+fn kinds(options: &[CloseOption]) -> Vec<CloseKind> {
+    options.iter().map(|option| option.kind).collect()
 }
 
 #[tokio::test]
@@ -200,7 +1481,7 @@ async fn taker_notices_lack_of_maker() {
     let mut taker = Taker::start(&taker_config, maker.listen_addr, maker.identity).await;
 
     assert_eq!(
-        ConnectionStatus::Online,
+        ConnectionStatus::Online { maker_name: None },
         next(taker.maker_status_feed()).await.unwrap()
     );
 
@@ -208,9 +1489,17 @@ async fn taker_notices_lack_of_maker() {
 
     sleep(taker_config.heartbeat_timeout).await;
 
-    assert_eq!(
-        ConnectionStatus::Offline { reason: None },
-        next(taker.maker_status_feed()).await.unwrap(),
+    // Depending on whether the maker's socket got closed before the heartbeat timed out
+    let status = next(taker.maker_status_feed()).await.unwrap();
+    assert!(
+        matches!(
+            status,
+            ConnectionStatus::Offline {
+                reason: Some(ConnectionCloseReason::RemoteClosed | ConnectionCloseReason::Timeout)
+            }
+        ),
+        "unexpected connection status {:?}",
+        status
     );
 
     let listener = tokio::net::TcpListener::bind(local_addr).await.unwrap();
@@ -220,7 +1509,7 @@ async fn taker_notices_lack_of_maker() {
     sleep(taker_config.heartbeat_timeout).await;
 
     assert_eq!(
-        ConnectionStatus::Online,
+        ConnectionStatus::Online { maker_name: None },
         next(taker.maker_status_feed()).await.unwrap(),
     );
 }
@@ -231,16 +1520,212 @@ async fn maker_notices_lack_of_taker() {
 
     let (mut maker, taker) = start_both().await;
     assert_eq!(
-        vec![taker.id],
+        vec![ConnectedTaker {
+            id: taker.id,
+            display_name: None
+        }],
         next(maker.connected_takers_feed()).await.unwrap()
     );
 
     std::mem::drop(taker);
 
     assert_eq!(
-        Vec::<Identity>::new(),
+        Vec::<ConnectedTaker>::new(),
+        next(maker.connected_takers_feed()).await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn display_names_are_exchanged_when_connecting() {
+    let _guard = init_tracing();
+
+    let maker_name = "Maker".parse::<DisplayName>().unwrap();
+    let taker_name = "Taker".parse::<DisplayName>().unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut maker = Maker::start(
+        &MakerConfig::default().with_display_name(maker_name.clone()),
+        listener,
+    )
+    .await;
+    let mut taker = Taker::start(
+        &TakerConfig::default().with_display_name(taker_name.clone()),
+        maker.listen_addr,
+        maker.identity,
+    )
+    .await;
+
+    assert_eq!(
+        vec![ConnectedTaker {
+            id: taker.id,
+            display_name: Some(taker_name)
+        }],
         next(maker.connected_takers_feed()).await.unwrap()
     );
+    assert_eq!(
+        ConnectionStatus::Online {
+            maker_name: Some(maker_name)
+        },
+        next(taker.maker_status_feed()).await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn maker_sees_new_identity_for_every_session_of_taker() {
+    let _guard = init_tracing();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut maker = Maker::start(&MakerConfig::default(), listener).await;
+    let config = TakerConfig::default().with_identity_policy(IdentityPolicy::PerSession);
+
+    let first_session = Taker::start(&config, maker.listen_addr, maker.identity).await;
+    next(maker.connected_takers_feed()).await.unwrap();
+    let second_session = Taker::start(&config, maker.listen_addr, maker.identity).await;
+
+    let connected_takers = next(maker.connected_takers_feed())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|taker| taker.id)
+        .collect::<HashSet<_>>();
+
+    assert_ne!(first_session.id, second_session.id);
+    assert_eq!(
+        connected_takers,
+        HashSet::from([first_session.id, second_session.id])
+    );
+}
+
+#[tokio::test]
+async fn maker_stays_connected_to_taker_one_minor_version_behind() {
+    let _guard = init_tracing();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = MakerConfig::default().with_min_compatible_version(one_minor_version_behind());
+    let maker = Maker::start(&config, listener).await;
+
+    let mut connection = connect_raw(&maker).await;
+    connection
+        .send(TakerToMaker::Hello(one_minor_version_behind()))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        connection.next().await,
+        Some(Ok(MakerToTaker::Hello(_)))
+    ));
+    assert!(matches!(
+        connection.next().await,
+        Some(Ok(MakerToTaker::Introduction { .. }))
+    ));
+}
+
+#[tokio::test]
+async fn maker_drops_taker_older_than_min_compatible_version() {
+    let _guard = init_tracing();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let maker = Maker::start(&MakerConfig::default(), listener).await;
+
+    let mut connection = connect_raw(&maker).await;
+    connection
+        .send(TakerToMaker::Hello(one_minor_version_behind()))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        connection.next().await,
+        Some(Ok(MakerToTaker::Hello(_)))
+    ));
+    assert!(connection.next().await.is_none());
+}
+
+fn one_minor_version_behind() -> Version {
+    let mut version = semver::Version::parse(&Version::current().to_string()).unwrap();
+    version.minor -= 1;
+
+    version.to_string().parse().unwrap()
+}
+
+/// Connects to the maker like a taker would, but leaves speaking the protocol to the test.
+async fn connect_raw(
+    maker: &Maker,
+) -> Framed<TcpStream, EncryptedJsonCodec<MakerToTaker, TakerToMaker>> {
+    let mut stream = TcpStream::connect(maker.listen_addr).await.unwrap();
+    let noise = noise::initiator_handshake(
+        &mut stream,
+        &x25519_dalek::StaticSecret::from([7u8; 32]),
+        &maker.identity.pk(),
+    )
+    .await
+    .unwrap();
+
+    Framed::new(stream, EncryptedJsonCodec::new(noise))
+}
+
+#[tokio::test]
+async fn triggered_rollover_check_skips_expired_cfd() {
+    let _guard = init_tracing();
+    // The settlement event of the example announcement lies in the past
+    let (_maker, taker, order_id) =
+        start_from_open_cfd_state(OliviaData::example_0().announcement()).await;
+
+    let started = taker.system.trigger_rollover_check().await.unwrap();
+
+    assert_eq!(started, 0);
+    sleep(Duration::from_millis(200)).await;
+    assert!(!taker
+        .recorded_events(order_id)
+        .await
+        .iter()
+        .any(|event| matches!(
+            event,
+            CfdEvent::RolloverCompleted { .. }
+                | CfdEvent::RolloverRejected
+                | CfdEvent::RolloverFailed
+        )));
+}
+
+#[tokio::test]
+async fn cfd_is_flagged_once_oracle_no_longer_serves_its_event() {
+    let _guard = init_tracing();
+    let oracle = FakeOracle::start(200).await;
+    let (_maker, mut taker, order_id) = start_from_open_cfd_state_with(
+        OliviaData::example_0().announcement(),
+        MakerConfig::default(),
+        TakerConfig::default().with_unknown_event_policy(UnknownEventPolicy::Warn {
+            oracle_url: oracle.url.clone(),
+            check_interval: Duration::from_millis(200),
+        }),
+    )
+    .await;
+    let oracle_event_unavailable = |taker: &mut Taker| {
+        taker
+            .cfd_feed()
+            .borrow()
+            .iter()
+            .find(|cfd| cfd.order_id == order_id)
+            .expect("CFD to be listed")
+            .oracle_event_unavailable
+    };
+
+    sleep(Duration::from_secs(1)).await;
+    assert!(!oracle_event_unavailable(&mut taker));
+
+    oracle.respond_with(500);
+    sleep(Duration::from_secs(1)).await;
+    assert!(
+        !oracle_event_unavailable(&mut taker),
+        "An oracle failing to answer does not tell the event is unavailable"
+    );
+
+    oracle.respond_with(404);
+    sleep(Duration::from_secs(1)).await;
+    assert!(oracle_event_unavailable(&mut taker));
+
+    oracle.respond_with(500);
+    sleep(Duration::from_secs(1)).await;
+    assert!(
+        oracle_event_unavailable(&mut taker),
+        "An oracle failing to answer does not tell the event is available again"
+    );
 }
 
 /// Hide the implementation detail of arriving at the Cfd open state.
@@ -248,15 +1733,24 @@ async fn maker_notices_lack_of_taker() {
 /// For convenience, returns also OrderId of the opened Cfd.
 /// `announcement` is used during Cfd's creation.
 async fn start_from_open_cfd_state(announcement: oracle::Announcement) -> (Maker, Taker, OrderId) {
+    start_from_open_cfd_state_with(announcement, MakerConfig::default(), TakerConfig::default())
+        .await
+}
+
+async fn start_from_open_cfd_state_with(
+    announcement: oracle::Announcement,
+    maker_config: MakerConfig,
+    taker_config: TakerConfig,
+) -> (Maker, Taker, OrderId) {
     let heartbeat_interval = Duration::from_secs(60);
     let maker_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
     let mut maker = Maker::start(
-        &MakerConfig::default().with_heartbeat_interval(heartbeat_interval),
+        &maker_config.with_heartbeat_interval(heartbeat_interval),
         maker_listener,
     )
     .await;
     let mut taker = Taker::start(
-        &TakerConfig::default().with_heartbeat_timeout(heartbeat_interval * 2),
+        &taker_config.with_heartbeat_timeout(heartbeat_interval * 2),
         maker.listen_addr,
         maker.identity,
     )
@@ -274,6 +1768,7 @@ async fn start_from_open_cfd_state(announcement: oracle::Announcement) -> (Maker
         .mocks
         .mock_oracle_announcement_with(announcement.clone())
         .await;
+    taker.mocks.mock_oracle_healthy().await;
     maker
         .mocks
         .mock_oracle_announcement_with(announcement)
@@ -2,6 +2,9 @@ use self::monitor::MonitorActor;
 use self::oracle::OracleActor;
 use self::wallet::WalletActor;
 use super::maia::OliviaData;
+use bdk::bitcoin::Txid;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::sync::MutexGuard;
@@ -35,6 +38,22 @@ impl Mocks {
         self.monitor().await.expect_sync().return_const(());
     }
 
+    /// Replaces the sync handler of the oracle mock with one counting how often it was called.
+    pub async fn mock_oracle_counts_syncs(&mut self) -> Arc<AtomicUsize> {
+        let syncs = Arc::new(AtomicUsize::new(0));
+
+        let mut oracle = self.oracle().await;
+        oracle.checkpoint();
+        oracle.expect_sync().returning({
+            let syncs = syncs.clone();
+            move |_| {
+                syncs.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        syncs
+    }
+
     // Helper function setting up a "happy path" wallet mock
     pub async fn mock_wallet_sign_and_broadcast(&mut self) {
         self.wallet()
@@ -47,6 +66,63 @@ impl Mocks {
             .returning(|broadcast_msg| Ok(broadcast_msg.tx.txid()));
     }
 
+    /// Replaces the wallet mock with one whose first `failures` broadcasts fail.
+    ///
+    /// Returns the number of broadcast attempts made so far.
+    pub async fn mock_wallet_broadcast_fails_transiently(
+        &mut self,
+        failures: usize,
+    ) -> Arc<AtomicUsize> {
+        let attempts = Arc::new(AtomicUsize::new(0));
+
+        let mut wallet = self.wallet().await;
+        wallet.checkpoint();
+        wallet.expect_sign().returning(|sign_msg| Ok(sign_msg.psbt));
+        wallet.expect_broadcast().returning({
+            let attempts = attempts.clone();
+            move |broadcast_msg| {
+                if attempts.fetch_add(1, Ordering::SeqCst) < failures {
+                    anyhow::bail!("Failed to broadcast")
+                }
+
+                Ok(broadcast_msg.tx.txid())
+            }
+        });
+
+        attempts
+    }
+
+    /// Replaces the wallet mock with one that records the txids of all broadcast transactions.
+    pub async fn mock_wallet_records_broadcasts(&mut self) -> Arc<std::sync::Mutex<Vec<Txid>>> {
+        let broadcasts = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let mut wallet = self.wallet().await;
+        wallet.checkpoint();
+        wallet.expect_sign().returning(|sign_msg| Ok(sign_msg.psbt));
+        wallet.expect_broadcast().returning({
+            let broadcasts = broadcasts.clone();
+            move |broadcast_msg| {
+                let txid = broadcast_msg.tx.txid();
+                broadcasts.lock().unwrap().push(txid);
+
+                Ok(txid)
+            }
+        });
+
+        broadcasts
+    }
+
+    pub async fn mock_wallet_sign_fails(&mut self) {
+        self.wallet()
+            .await
+            .expect_sign()
+            .returning(|_| Err(anyhow::anyhow!("Failed to sign")));
+    }
+
+    pub async fn mock_wallet_release_utxos(&mut self) {
+        self.wallet().await.expect_release_utxos().return_const(());
+    }
+
     pub async fn mock_oracle_announcement(&mut self) {
         self.mock_oracle_announcement_with(OliviaData::example_0().announcement())
             .await;
@@ -62,6 +138,30 @@ impl Mocks {
             .return_const(Ok(announcement));
     }
 
+    pub async fn mock_oracle_announcement_unavailable(&mut self) {
+        self.oracle()
+            .await
+            .expect_get_announcement()
+            .returning(|msg| Err(daemon::oracle::NoAnnouncement(msg.0)));
+    }
+
+    pub async fn mock_oracle_healthy(&mut self) {
+        self.oracle()
+            .await
+            .expect_get_health()
+            .return_const(Ok(daemon::oracle::Health {
+                last_reached: daemon::model::Timestamp::now(),
+                latency_ms: 100,
+            }));
+    }
+
+    pub async fn mock_oracle_unavailable(&mut self) {
+        self.oracle()
+            .await
+            .expect_get_health()
+            .return_const(Err(daemon::oracle::Unavailable::NeverReached));
+    }
+
     pub async fn mock_oracle_monitor_attestation(&mut self) {
         self.oracle()
             .await
@@ -33,6 +33,13 @@ impl OracleActor {
     async fn handle(&mut self, msg: oracle::Sync) {
         self.mock.lock().await.sync(msg)
     }
+
+    async fn handle(
+        &mut self,
+        msg: oracle::GetHealth,
+    ) -> Result<oracle::Health, oracle::Unavailable> {
+        self.mock.lock().await.get_health(msg)
+    }
 }
 
 #[automock]
@@ -51,6 +58,13 @@ pub trait Oracle {
     fn sync(&mut self, _msg: oracle::Sync) {
         unreachable!("mockall will reimplement this method")
     }
+
+    fn get_health(
+        &mut self,
+        _msg: oracle::GetHealth,
+    ) -> Result<oracle::Health, oracle::Unavailable> {
+        unreachable!("mockall will reimplement this method")
+    }
 }
 
 /// We do *not* depend on the current time in our tests, the valid combination of
@@ -44,6 +44,15 @@ impl WalletActor {
     async fn handle(&mut self, msg: wallet::Reinitialise) -> Result<()> {
         self.mock.lock().await.reinitialise(msg)
     }
+    async fn handle(&mut self, msg: wallet::Rescan) -> Result<()> {
+        self.mock.lock().await.rescan(msg)
+    }
+    async fn handle(&mut self, msg: wallet::NewAddress) -> Result<wallet::ReceiveAddress> {
+        self.mock.lock().await.new_address(msg)
+    }
+    async fn handle(&mut self, msg: wallet::ReleaseUtxos) {
+        self.mock.lock().await.release_utxos(msg)
+    }
 }
 
 #[automock]
@@ -67,6 +76,18 @@ pub trait Wallet {
     fn reinitialise(&mut self, _msg: wallet::Reinitialise) -> Result<()> {
         unreachable!("mockall will reimplement this method")
     }
+
+    fn rescan(&mut self, _msg: wallet::Rescan) -> Result<()> {
+        unreachable!("mockall will reimplement this method")
+    }
+
+    fn new_address(&mut self, _msg: wallet::NewAddress) -> Result<wallet::ReceiveAddress> {
+        unreachable!("mockall will reimplement this method")
+    }
+
+    fn release_utxos(&mut self, _msg: wallet::ReleaseUtxos) {
+        unreachable!("mockall will reimplement this method")
+    }
 }
 
 #[allow(dead_code)]
@@ -2,33 +2,66 @@ use crate::harness::mocks::oracle::OracleActor;
 use crate::harness::mocks::wallet::WalletActor;
 use crate::schnorrsig;
 use ::bdk::bitcoin::Network;
+use daemon::auto_commit::CommitPolicy;
+use daemon::auto_refund::RefundStrategy;
+use daemon::clock::SystemClock;
 use daemon::connection::connect;
 use daemon::connection::ConnectionStatus;
+use daemon::connection::MakerAddresses;
 use daemon::db;
 use daemon::maker_cfd;
 use daemon::maker_inc_connections;
 use daemon::model;
+use daemon::model::cfd::CfdEvent;
+use daemon::model::cfd::Markup;
+use daemon::model::cfd::MinimumSize;
 use daemon::model::cfd::OrderId;
 use daemon::model::cfd::Role;
+use daemon::model::cfd::SettlementPriceTolerance;
+use daemon::model::cfd::SettlementRejectionPolicy;
+use daemon::model::cfd::SlippageBound;
+use daemon::model::DisplayName;
 use daemon::model::Identity;
+use daemon::model::Leverage;
 use daemon::model::Price;
+use daemon::model::Timestamp;
 use daemon::model::Usd;
+use daemon::oracle;
+use daemon::oracle::SettlementEventSelection;
+use daemon::oracle::UnknownEventPolicy;
+use daemon::price_feed;
+use daemon::price_feed::PricingMode;
+use daemon::process_manager::CetPolicy;
+use daemon::process_manager::REBROADCAST_INTERVAL;
 use daemon::projection;
 use daemon::projection::Cfd;
 use daemon::projection::CfdOrder;
+use daemon::projection::ConnectedTaker;
+use daemon::projection::Explorer;
 use daemon::projection::Feeds;
+use daemon::projection::OrderTaken;
+use daemon::seed::IdentityPolicy;
 use daemon::seed::Seed;
 use daemon::taker_cfd;
+use daemon::wire::Version;
 use daemon::MakerActorSystem;
 use daemon::Tasks;
 use daemon::HEARTBEAT_INTERVAL;
+use daemon::MAX_CETS;
+use daemon::MAX_CONCURRENT_PROTOCOLS;
 use daemon::N_PAYOUTS;
 use daemon::SETTLEMENT_INTERVAL;
 use rust_decimal_macros::dec;
 use sqlx::SqlitePool;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
 use tokio::sync::watch;
 use tracing::subscriber::DefaultGuard;
@@ -63,6 +96,16 @@ pub struct MakerConfig {
     seed: Seed,
     pub heartbeat_interval: Duration,
     n_payouts: usize,
+    max_quantity_per_taker: Option<Usd>,
+    minimum_size: MinimumSize,
+    max_cets: usize,
+    settlement_price_tolerance: SettlementPriceTolerance,
+    markup: Markup,
+    leverage_choices: Vec<Leverage>,
+    settlement_rejection_policy: SettlementRejectionPolicy,
+    cet_policy: CetPolicy,
+    display_name: Option<DisplayName>,
+    min_compatible_version: Version,
 }
 
 impl MakerConfig {
@@ -72,6 +115,60 @@ impl MakerConfig {
             ..self
         }
     }
+
+    pub fn with_max_quantity_per_taker(self, max_quantity: Usd) -> Self {
+        Self {
+            max_quantity_per_taker: Some(max_quantity),
+            ..self
+        }
+    }
+
+    pub fn with_minimum_size(self, minimum_size: MinimumSize) -> Self {
+        Self {
+            minimum_size,
+            ..self
+        }
+    }
+
+    pub fn with_max_cets(self, max_cets: usize) -> Self {
+        Self { max_cets, ..self }
+    }
+
+    pub fn with_settlement_rejection_policy(self, policy: SettlementRejectionPolicy) -> Self {
+        Self {
+            settlement_rejection_policy: policy,
+            ..self
+        }
+    }
+
+    pub fn with_cet_policy(self, cet_policy: CetPolicy) -> Self {
+        Self { cet_policy, ..self }
+    }
+
+    pub fn with_markup(self, markup: Markup) -> Self {
+        Self { markup, ..self }
+    }
+
+    pub fn with_leverage_choices(self, leverage_choices: Vec<Leverage>) -> Self {
+        Self {
+            leverage_choices,
+            ..self
+        }
+    }
+
+    pub fn with_display_name(self, display_name: DisplayName) -> Self {
+        Self {
+            display_name: Some(display_name),
+            ..self
+        }
+    }
+
+    pub fn with_min_compatible_version(self, min_compatible_version: Version) -> Self {
+        Self {
+            min_compatible_version,
+            ..self
+        }
+    }
 }
 
 impl Default for MakerConfig {
@@ -81,6 +178,16 @@ impl Default for MakerConfig {
             seed: Seed::default(),
             heartbeat_interval: HEARTBEAT_INTERVAL_FOR_TEST,
             n_payouts: N_PAYOUTS_FOR_TEST,
+            max_quantity_per_taker: None,
+            minimum_size: MinimumSize::default(),
+            max_cets: MAX_CETS,
+            settlement_price_tolerance: SettlementPriceTolerance::from_percent(dec!(1)).unwrap(),
+            markup: Markup::default(),
+            leverage_choices: Vec::new(),
+            settlement_rejection_policy: SettlementRejectionPolicy::Commit,
+            cet_policy: CetPolicy::AutoPublish,
+            display_name: None,
+            min_compatible_version: Version::current(),
         }
     }
 }
@@ -91,6 +198,16 @@ pub struct TakerConfig {
     seed: Seed,
     pub heartbeat_timeout: Duration,
     n_payouts: usize,
+    minimum_size: MinimumSize,
+    max_cets: usize,
+    settlement_rejection_policy: SettlementRejectionPolicy,
+    cet_policy: CetPolicy,
+    rebroadcast_interval: Duration,
+    oracle_sync_interval: Duration,
+    unknown_event_policy: UnknownEventPolicy,
+    display_name: Option<DisplayName>,
+    identity_policy: IdentityPolicy,
+    identity_index_path: PathBuf,
 }
 
 impl TakerConfig {
@@ -100,6 +217,55 @@ impl TakerConfig {
             ..self
         }
     }
+
+    pub fn with_minimum_size(self, minimum_size: MinimumSize) -> Self {
+        Self {
+            minimum_size,
+            ..self
+        }
+    }
+
+    pub fn with_settlement_rejection_policy(self, policy: SettlementRejectionPolicy) -> Self {
+        Self {
+            settlement_rejection_policy: policy,
+            ..self
+        }
+    }
+
+    pub fn with_rebroadcast_interval(self, interval: Duration) -> Self {
+        Self {
+            rebroadcast_interval: interval,
+            ..self
+        }
+    }
+
+    pub fn with_oracle_sync_interval(self, interval: Duration) -> Self {
+        Self {
+            oracle_sync_interval: interval,
+            ..self
+        }
+    }
+
+    pub fn with_unknown_event_policy(self, policy: UnknownEventPolicy) -> Self {
+        Self {
+            unknown_event_policy: policy,
+            ..self
+        }
+    }
+
+    pub fn with_display_name(self, display_name: DisplayName) -> Self {
+        Self {
+            display_name: Some(display_name),
+            ..self
+        }
+    }
+
+    pub fn with_identity_policy(self, identity_policy: IdentityPolicy) -> Self {
+        Self {
+            identity_policy,
+            ..self
+        }
+    }
 }
 
 impl Default for TakerConfig {
@@ -109,6 +275,17 @@ impl Default for TakerConfig {
             seed: Seed::default(),
             heartbeat_timeout: HEARTBEAT_INTERVAL_FOR_TEST * 2,
             n_payouts: N_PAYOUTS_FOR_TEST,
+            minimum_size: MinimumSize::default(),
+            max_cets: MAX_CETS,
+            settlement_rejection_policy: SettlementRejectionPolicy::Commit,
+            cet_policy: CetPolicy::AutoPublish,
+            rebroadcast_interval: REBROADCAST_INTERVAL,
+            oracle_sync_interval: oracle::SYNC_INTERVAL,
+            unknown_event_policy: UnknownEventPolicy::Ignore,
+            display_name: None,
+            identity_policy: IdentityPolicy::Fixed,
+            identity_index_path: std::env::temp_dir()
+                .join(format!("taker-identity-index-{}", OrderId::default())),
         }
     }
 }
@@ -120,6 +297,7 @@ pub struct Maker {
     pub feeds: Feeds,
     pub listen_addr: SocketAddr,
     pub identity: model::Identity,
+    db: SqlitePool,
     _tasks: Tasks,
 }
 
@@ -132,10 +310,18 @@ impl Maker {
         &mut self.feeds.order
     }
 
-    pub fn connected_takers_feed(&mut self) -> &mut watch::Receiver<Vec<Identity>> {
+    pub fn connected_takers_feed(&mut self) -> &mut watch::Receiver<Vec<ConnectedTaker>> {
         &mut self.feeds.connected_takers
     }
 
+    pub fn order_taken_feed(&mut self) -> &mut watch::Receiver<Option<OrderTaken>> {
+        &mut self.feeds.order_taken
+    }
+
+    pub async fn recorded_events(&self, order_id: OrderId) -> Vec<CfdEvent> {
+        recorded_events(&self.db, order_id).await
+    }
+
     pub async fn start(config: &MakerConfig, listener: TcpListener) -> Self {
         let db = in_memory_db().await;
 
@@ -168,16 +354,49 @@ impl Maker {
                     channel2,
                     identity_sk,
                     config.heartbeat_interval,
+                    config.display_name.clone(),
+                    config.min_compatible_version.clone(),
                 )
             },
             settlement_interval,
+            SettlementEventSelection::AfterInterval,
             config.n_payouts,
+            config.max_quantity_per_taker,
+            config.minimum_size,
+            config.max_cets,
+            config.settlement_price_tolerance,
+            config.markup,
+            config.leverage_choices,
+            PricingMode::default_for(Role::Maker),
+            time::Duration::minutes(10),
+            None,
             projection_actor.clone(),
+            CommitPolicy::Immediate,
+            config.cet_policy,
+            config.settlement_rejection_policy,
+            RefundStrategy::Manual,
+            UnknownEventPolicy::Ignore,
+            REBROADCAST_INTERVAL,
+            Arc::new(SystemClock),
         )
         .await
         .unwrap();
 
-        let (proj_actor, feeds) = projection::Actor::new(db, Role::Maker, Network::Testnet);
+        // settlement proposals are validated against the latest quote, pretend the market is at
+        // the price of our orders
+        maker
+            .cfd_actor_addr
+            .send(projection::Update(dummy_quote()))
+            .await
+            .unwrap();
+
+        let (proj_actor, feeds) = projection::Actor::new(
+            db.clone(),
+            Role::Maker,
+            Explorer::mempool_space(Network::Testnet),
+            PricingMode::default_for(Role::Maker),
+            None,
+        );
         tasks.add(projection_context.run(proj_actor));
 
         let address = listener.local_addr().unwrap();
@@ -190,6 +409,7 @@ impl Maker {
             identity: model::Identity::new(identity_pk),
             listen_addr: address,
             mocks,
+            db,
             _tasks: tasks,
         }
     }
@@ -205,6 +425,46 @@ impl Maker {
             .unwrap();
     }
 
+    pub async fn update_quote(&self, quote: price_feed::Quote) {
+        self.system
+            .cfd_actor_addr
+            .send(projection::Update(quote))
+            .await
+            .unwrap();
+    }
+
+    pub async fn withdraw_order(&self) {
+        self.system.withdraw_order().await.unwrap();
+    }
+
+    pub async fn rebuild_projection(&self) {
+        self.system.rebuild_projection().await.unwrap();
+    }
+
+    /// Load the CFDs through a fresh projection of the maker's database.
+    pub async fn fresh_projection_cfds(&self) -> Vec<Cfd> {
+        let (proj_actor, mut feeds) = projection::Actor::new(
+            self.db.clone(),
+            Role::Maker,
+            Explorer::mempool_space(Network::Testnet),
+            PricingMode::default_for(Role::Maker),
+            None,
+        );
+        let (_address, fut) = proj_actor.create(None).run();
+
+        let mut tasks = Tasks::default();
+        tasks.add(fut);
+
+        flow::next(&mut feeds.cfds).await.unwrap()
+    }
+
+    pub async fn override_order(&self, taker_id: Identity, order: maker_cfd::NewOrder) {
+        self.system
+            .override_order(taker_id, Some(order))
+            .await
+            .unwrap();
+    }
+
     pub async fn reject_take_request(&self, order: CfdOrder) {
         self.system
             .cfd_actor_addr
@@ -231,6 +491,17 @@ impl Maker {
             .unwrap()
             .unwrap();
     }
+
+    pub async fn reject_settlement_proposal(&self, order_id: OrderId) {
+        self.system.reject_settlement(order_id).await.unwrap();
+    }
+
+    pub async fn counter_settlement_proposal(&self, order_id: OrderId, price: Price) {
+        self.system
+            .counter_settlement(order_id, price)
+            .await
+            .unwrap();
+    }
 }
 
 /// Taker Test Setup
@@ -239,6 +510,7 @@ pub struct Taker {
     pub system: daemon::TakerActorSystem<OracleActor, WalletActor>,
     pub mocks: mocks::Mocks,
     pub feeds: Feeds,
+    db: SqlitePool,
     _tasks: Tasks,
 }
 
@@ -255,12 +527,20 @@ impl Taker {
         &mut self.system.maker_online_status_feed_receiver
     }
 
+    pub async fn recorded_events(&self, order_id: OrderId) -> Vec<CfdEvent> {
+        recorded_events(&self.db, order_id).await
+    }
+
     pub async fn start(
         config: &TakerConfig,
         maker_address: SocketAddr,
         maker_identity: model::Identity,
     ) -> Self {
-        let (identity_pk, identity_sk) = config.seed.derive_identity();
+        let (identity_pk, identity_sk) = config
+            .seed
+            .session_identity(config.identity_policy, &config.identity_index_path)
+            .await
+            .unwrap();
 
         let db = in_memory_db().await;
 
@@ -284,22 +564,44 @@ impl Taker {
             |_| async { Ok(oracle) },
             |_| async { Ok(monitor) },
             config.n_payouts,
+            config.minimum_size,
+            config.max_cets,
             config.heartbeat_timeout,
             Duration::from_secs(10),
             projection_actor,
             maker_identity,
+            config.display_name.clone(),
+            Version::current(),
+            CommitPolicy::Immediate,
+            config.cet_policy,
+            config.settlement_rejection_policy,
+            PricingMode::default_for(Role::Taker),
+            RefundStrategy::Manual,
+            config.unknown_event_policy,
+            None,
+            MAX_CONCURRENT_PROTOCOLS,
+            config.rebroadcast_interval,
+            config.oracle_sync_interval,
+            Arc::new(SystemClock),
         )
         .await
         .unwrap();
 
-        let (proj_actor, feeds) = projection::Actor::new(db, Role::Taker, Network::Testnet);
+        let (proj_actor, feeds) = projection::Actor::new(
+            db.clone(),
+            Role::Taker,
+            Explorer::mempool_space(Network::Testnet),
+            PricingMode::default_for(Role::Taker),
+            None,
+        );
         tasks.add(projection_context.run(proj_actor));
 
         tasks.add(connect(
             taker.maker_online_status_feed_receiver.clone(),
             taker.connection_actor_addr.clone(),
             maker_identity,
-            vec![maker_address],
+            watch::channel(MakerAddresses::new(vec![maker_address])).0,
+            Arc::new(SystemClock),
         ));
 
         Self {
@@ -307,6 +609,7 @@ impl Taker {
             system: taker,
             feeds,
             mocks,
+            db,
             _tasks: tasks,
         }
     }
@@ -317,6 +620,9 @@ impl Taker {
             .send(taker_cfd::TakeOffer {
                 order_id: order.id,
                 quantity,
+                fee_rate: None,
+                slippage_bound: SlippageBound::default(),
+                leverage: None,
             })
             .await
             .unwrap()
@@ -335,6 +641,22 @@ impl Taker {
             .unwrap();
     }
 
+    pub async fn settle_all(&self) -> Vec<taker_cfd::SettlementAttempt> {
+        self.system.settle_all(dummy_price()).await.unwrap()
+    }
+
+    pub async fn accept_settlement_counter_proposal(&self, order_id: OrderId) {
+        self.system.accept_settlement(order_id).await.unwrap();
+    }
+
+    pub async fn update_quote(&self, quote: price_feed::Quote) {
+        self.system
+            .cfd_actor_addr
+            .send(projection::Update(quote))
+            .await
+            .unwrap();
+    }
+
     pub async fn force_close(&self, order_id: OrderId) {
         self.system
             .cfd_actor_addr
@@ -343,6 +665,24 @@ impl Taker {
             .unwrap()
             .unwrap();
     }
+
+    pub async fn commit_and_settle(&self, order_id: OrderId) {
+        self.system
+            .cfd_actor_addr
+            .send(taker_cfd::CommitAndSettle { order_id })
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    pub async fn refund(&self, order_id: OrderId) {
+        self.system
+            .cfd_actor_addr
+            .send(taker_cfd::Refund { order_id })
+            .await
+            .unwrap()
+            .unwrap();
+    }
 }
 
 /// Deliver monitor event to both actor systems
@@ -359,6 +699,55 @@ macro_rules! deliver_event {
     };
 }
 
+/// An HTTP server standing in for the oracle, answering every request with the status it was
+/// last told to respond with.
+pub struct FakeOracle {
+    pub url: reqwest::Url,
+    status: Arc<AtomicU16>,
+}
+
+impl FakeOracle {
+    pub async fn start(status: u16) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let status = Arc::new(AtomicU16::new(status));
+
+        let response_status = status.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let status = response_status.load(Ordering::SeqCst);
+
+                tokio::spawn(async move {
+                    let mut request = [0u8; 1024];
+                    let _ = stream.read(&mut request).await;
+                    let response = format!(
+                        "HTTP/1.1 {} Fake\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                        status
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        Self { url, status }
+    }
+
+    pub fn respond_with(&self, status: u16) {
+        self.status.store(status, Ordering::SeqCst);
+    }
+}
+
+/// The events recorded for the CFD with the given id, in the order they were recorded.
+async fn recorded_events(db: &SqlitePool, order_id: OrderId) -> Vec<CfdEvent> {
+    let mut conn = db.acquire().await.unwrap();
+    let (_, events) = db::load_cfd(order_id, &mut conn).await.unwrap();
+
+    events.into_iter().map(|event| event.event).collect()
+}
+
 async fn in_memory_db() -> SqlitePool {
     // Note: Every :memory: database is distinct from every other. So, opening two database
     // connections each with the filename ":memory:" will create two independent in-memory
@@ -374,9 +763,17 @@ pub fn dummy_price() -> Price {
     Price::new(dec!(50_000)).expect("to not fail")
 }
 
+pub fn dummy_quote() -> price_feed::Quote {
+    price_feed::Quote {
+        timestamp: Timestamp::now(),
+        bid: dummy_price(),
+        ask: dummy_price(),
+    }
+}
+
 pub fn dummy_new_order() -> maker_cfd::NewOrder {
     maker_cfd::NewOrder {
-        price: dummy_price(),
+        price: Some(dummy_price()),
         min_quantity: Usd::new(dec!(5)),
         max_quantity: Usd::new(dec!(100)),
         fee_rate: 1,
@@ -20,7 +20,7 @@ import React, { useEffect, useState } from "react";
 import { useAsync } from "react-async";
 import { useEventSource } from "react-sse-hooks";
 import { CfdTable } from "./components/cfdtables/CfdTable";
-import ConnectedTakers, { TakerId } from "./components/ConnectedTakers";
+import ConnectedTakers, { ConnectedTaker } from "./components/ConnectedTakers";
 import CurrencyInputField from "./components/CurrencyInputField";
 import CurrentPrice from "./components/CurrentPrice";
 import createErrorToast from "./components/ErrorToast";
@@ -42,7 +42,7 @@ export default function App() {
     const order = useLatestEvent<Order>(source, "order", intoOrder);
     const walletInfo = useLatestEvent<WalletInfo>(source, "wallet");
     const priceInfo = useLatestEvent<PriceInfo>(source, "quote");
-    const takersOrUndefined = useLatestEvent<TakerId[]>(source, "takers");
+    const takersOrUndefined = useLatestEvent<ConnectedTaker[]>(source, "takers");
     let takers = takersOrUndefined || [];
 
     const toast = useToast();
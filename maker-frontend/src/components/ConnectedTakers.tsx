@@ -1,12 +1,13 @@
 import { Heading, ListItem, UnorderedList, VStack } from "@chakra-ui/react";
 import React from "react";
 
-export interface TakerId {
+export interface ConnectedTaker {
     id: string;
+    display_name?: string;
 }
 
 interface Props {
-    takers: TakerId[];
+    takers: ConnectedTaker[];
 }
 
 const ConnectedTakers = ({ takers }: Props) => {
@@ -15,7 +16,11 @@ const ConnectedTakers = ({ takers }: Props) => {
             <Heading size={"sm"} padding={2}>{"Connected takers: " + takers.length}</Heading>
             <UnorderedList>
                 {takers.map((taker) => {
-                    return (<ListItem>{taker}</ListItem>);
+                    return (
+                        <ListItem key={taker.id}>
+                            {taker.display_name ? taker.display_name + " (" + taker.id + ")" : taker.id}
+                        </ListItem>
+                    );
                 })}
             </UnorderedList>
         </VStack>
@@ -6,6 +6,7 @@ export interface Order {
     min_quantity: number;
     max_quantity: number;
     leverage: number;
+    leverage_choices: number[];
     liquidation_price: number;
     creation_timestamp: number;
     settlement_time_interval_in_secs: number;
@@ -52,6 +53,18 @@ export interface Cfd {
     expiry_timestamp?: number;
 
     counterparty: string;
+    total_fees_btc: number;
+    oracle_event_unavailable: boolean;
+
+    settlement_proposal?: ProposedSettlement;
+}
+
+export interface ProposedSettlement {
+    proposed_by: "Maker" | "Taker";
+    price: number;
+    taker_payout: number;
+    maker_payout: number;
+    timestamp: number;
 }
 
 export interface CfdDetails {
@@ -97,10 +110,16 @@ export class State {
                 return "Refunded";
             case StateKey.SETUP_FAILED:
                 return "Setup Failed";
+            case StateKey.PENDING_CET_TIMELOCK:
+                return "Pending CET Timelock";
+            case StateKey.CET_READY:
+                return "CET Ready";
             case StateKey.PENDING_CET:
                 return "Pending CET";
             case StateKey.CLOSED:
                 return "Closed";
+            case StateKey.REVOKED:
+                return "Revoked commit-tx published";
         }
     }
 
@@ -115,11 +134,14 @@ export class State {
                 return green;
 
             case StateKey.REJECTED:
+            case StateKey.REVOKED:
                 return red;
 
             case StateKey.PENDING_COMMIT:
             case StateKey.OPEN_COMMITTED:
             case StateKey.PENDING_REFUND:
+            case StateKey.PENDING_CET_TIMELOCK:
+            case StateKey.CET_READY:
             case StateKey.PENDING_CET:
             case StateKey.PENDING_CLOSE:
                 return orange;
@@ -149,6 +171,8 @@ export class State {
             case StateKey.PENDING_REFUND:
             case StateKey.OUTGOING_SETTLEMENT_PROPOSAL:
             case StateKey.OUTGOING_ROLL_OVER_PROPOSAL:
+            case StateKey.PENDING_CET_TIMELOCK:
+            case StateKey.CET_READY:
             case StateKey.PENDING_CET:
             case StateKey.PENDING_CLOSE:
                 return StateGroupKey.OPEN;
@@ -163,6 +187,7 @@ export class State {
             case StateKey.REFUNDED:
             case StateKey.SETUP_FAILED:
             case StateKey.CLOSED:
+            case StateKey.REVOKED:
                 return StateGroupKey.CLOSED;
         }
     }
@@ -172,12 +197,15 @@ export enum Action {
     ACCEPT_ORDER = "acceptOrder",
     REJECT_ORDER = "rejectOrder",
     COMMIT = "commit",
+    COMMIT_AND_SETTLE = "commitAndSettle",
     SETTLE = "settle",
     ROLL_OVER = "rollOver",
     ACCEPT_SETTLEMENT = "acceptSettlement",
     REJECT_SETTLEMENT = "rejectSettlement",
     ACCEPT_ROLL_OVER = "acceptRollOver",
     REJECT_ROLL_OVER = "rejectRollOver",
+    REFUND = "refund",
+    ARCHIVE = "archive",
 }
 
 const enum StateKey {
@@ -187,6 +215,8 @@ const enum StateKey {
     OPEN = "Open",
     PENDING_CLOSE = "PendingClose",
     PENDING_COMMIT = "PendingCommit",
+    PENDING_CET_TIMELOCK = "PendingCetTimelock",
+    CET_READY = "CetReady",
     PENDING_CET = "PendingCet",
     OPEN_COMMITTED = "OpenCommitted",
     OUTGOING_SETTLEMENT_PROPOSAL = "OutgoingSettlementProposal",
@@ -197,6 +227,7 @@ const enum StateKey {
     REFUNDED = "Refunded",
     SETUP_FAILED = "SetupFailed",
     CLOSED = "Closed",
+    REVOKED = "Revoked",
 }
 
 export enum StateGroupKey {
@@ -219,6 +219,8 @@ function iconForAction(action: Action): any {
             return <CloseIcon />;
         case Action.COMMIT:
             return <WarningIcon />;
+        case Action.COMMIT_AND_SETTLE:
+            return <WarningIcon />;
         case Action.SETTLE:
             return <CheckCircleIcon />;
         case Action.ACCEPT_SETTLEMENT:
@@ -231,6 +233,10 @@ function iconForAction(action: Action): any {
             return <CheckIcon />;
         case Action.REJECT_ROLL_OVER:
             return <CloseIcon />;
+        case Action.REFUND:
+            return <WarningIcon />;
+        case Action.ARCHIVE:
+            return <CloseIcon />;
     }
 }
 
@@ -242,6 +248,8 @@ function colorSchemaForAction(action: Action): string {
             return "red";
         case Action.COMMIT:
             return "red";
+        case Action.COMMIT_AND_SETTLE:
+            return "red";
         case Action.SETTLE:
             return "green";
         case Action.ROLL_OVER:
@@ -254,6 +262,10 @@ function colorSchemaForAction(action: Action): string {
             return "green";
         case Action.REJECT_ROLL_OVER:
             return "red";
+        case Action.REFUND:
+            return "red";
+        case Action.ARCHIVE:
+            return "gray";
     }
 }
 
@@ -123,6 +123,8 @@ const CfdDetails = ({ cfd, connectedToMaker }: CfdDetailsProps) => {
             </Center>
             <VStack>
                 <Badge colorScheme={cfd.state.getColorScheme()}>{cfd.state.getLabel()}</Badge>
+                {cfd.oracle_event_unavailable
+                    && <Badge colorScheme="orange">Oracle event unavailable, refund only</Badge>}
                 <HStack>
                     <VStack>
                         <TxIcon tx={txLock} />
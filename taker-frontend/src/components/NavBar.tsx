@@ -39,6 +39,9 @@ export default function Nav({ walletInfo, connectedToMaker }: NavProps) {
     );
 
     let connectionMessage = connectedToMaker.online ? "Online" : "Offline";
+    if (connectedToMaker.maker_name) {
+        connectionMessage = connectionMessage + " (" + connectedToMaker.maker_name + ")";
+    }
     if (connectedToMaker.connection_close_reason) {
         switch (connectedToMaker.connection_close_reason) {
             case ConnectionCloseReason.MAKER_VERSION_OUTDATED:
@@ -47,6 +50,15 @@ export default function Nav({ walletInfo, connectedToMaker }: NavProps) {
             case ConnectionCloseReason.TAKER_VERSION_OUTDATED:
                 connectionMessage = connectionMessage + " - you are running an incompatible version, please upgrade!";
                 break;
+            case ConnectionCloseReason.TIMEOUT:
+                connectionMessage = connectionMessage + ": the maker stopped responding";
+                break;
+            case ConnectionCloseReason.NETWORK_ERROR:
+                connectionMessage = connectionMessage + ": the connection to the maker failed";
+                break;
+            case ConnectionCloseReason.REMOTE_CLOSED:
+                connectionMessage = connectionMessage + ": the maker closed the connection";
+                break;
         }
     }
 
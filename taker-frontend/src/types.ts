@@ -16,6 +16,7 @@ export interface Order {
     min_quantity: number;
     max_quantity: number;
     leverage: number;
+    leverage_choices: number[];
     liquidation_price: number;
     creation_timestamp: number;
     settlement_time_interval_in_secs: number;
@@ -54,6 +55,10 @@ export interface Cfd {
 
     profit_btc?: number;
     profit_percent?: number;
+    profit_usd?: string;
+
+    distance_to_liquidation_btc?: number;
+    distance_to_liquidation_percent?: number;
 
     state: State;
     state_transition_timestamp: number;
@@ -61,6 +66,36 @@ export interface Cfd {
     expiry_timestamp?: number;
 
     counterparty: string;
+
+    receipt?: SettlementReceipt;
+    total_fees_btc: number;
+
+    monitoring_paused: boolean;
+    oracle_event_unavailable: boolean;
+
+    setup_failure_reason?: string;
+
+    settlement_proposal?: ProposedSettlement;
+
+    take_profit?: number;
+    stop_loss?: number;
+}
+
+export interface ProposedSettlement {
+    proposed_by: "Maker" | "Taker";
+    price: number;
+    taker_payout: number;
+    maker_payout: number;
+    timestamp: number;
+}
+
+export interface SettlementReceipt {
+    order_id: string;
+    closing_price: number;
+    payout: number;
+    realized_profit_btc?: number;
+    spend_txid: string;
+    timestamp: number;
 }
 
 export interface CfdDetails {
@@ -114,10 +149,16 @@ export class State {
                 return "Refunded";
             case StateKey.SETUP_FAILED:
                 return "Setup Failed";
+            case StateKey.PENDING_CET_TIMELOCK:
+                return "Pending CET Timelock";
+            case StateKey.CET_READY:
+                return "CET Ready";
             case StateKey.PENDING_CET:
                 return "Pending CET";
             case StateKey.CLOSED:
                 return "Closed";
+            case StateKey.REVOKED:
+                return "Revoked commit-tx published";
         }
     }
 
@@ -132,11 +173,14 @@ export class State {
                 return green;
 
             case StateKey.REJECTED:
+            case StateKey.REVOKED:
                 return red;
 
             case StateKey.PENDING_COMMIT:
             case StateKey.OPEN_COMMITTED:
             case StateKey.MUST_REFUND:
+            case StateKey.PENDING_CET_TIMELOCK:
+            case StateKey.CET_READY:
             case StateKey.PENDING_CET:
             case StateKey.PENDING_CLOSE:
                 return orange;
@@ -166,6 +210,8 @@ export class State {
             case StateKey.MUST_REFUND:
             case StateKey.OUTGOING_SETTLEMENT_PROPOSAL:
             case StateKey.OUTGOING_ROLL_OVER_PROPOSAL:
+            case StateKey.PENDING_CET_TIMELOCK:
+            case StateKey.CET_READY:
             case StateKey.PENDING_CET:
             case StateKey.PENDING_CLOSE:
                 return StateGroupKey.OPEN;
@@ -180,6 +226,7 @@ export class State {
             case StateKey.REFUNDED:
             case StateKey.SETUP_FAILED:
             case StateKey.CLOSED:
+            case StateKey.REVOKED:
                 return StateGroupKey.CLOSED;
         }
     }
@@ -192,6 +239,8 @@ export const enum StateKey {
     OPEN = "Open",
     PENDING_CLOSE = "PendingClose",
     PENDING_COMMIT = "PendingCommit",
+    PENDING_CET_TIMELOCK = "PendingCetTimelock",
+    CET_READY = "CetReady",
     PENDING_CET = "PendingCet",
     OPEN_COMMITTED = "OpenCommitted",
     OUTGOING_SETTLEMENT_PROPOSAL = "OutgoingSettlementProposal",
@@ -202,6 +251,7 @@ export const enum StateKey {
     REFUNDED = "Refunded",
     SETUP_FAILED = "SetupFailed",
     CLOSED = "Closed",
+    REVOKED = "Revoked",
 }
 
 export enum StateGroupKey {
@@ -228,6 +278,7 @@ export interface MarginResponse {
 export interface CfdOrderRequestPayload {
     order_id: string;
     quantity: number;
+    leverage?: number;
 }
 
 export function intoOrder(key: string, value: any): any {
@@ -265,9 +316,13 @@ export interface WithdrawRequest {
 export interface ConnectionStatus {
     online: boolean;
     connection_close_reason?: ConnectionCloseReason;
+    maker_name?: string;
 }
 
 export const enum ConnectionCloseReason {
     MAKER_VERSION_OUTDATED = "MakerVersionOutdated",
     TAKER_VERSION_OUTDATED = "TakerVersionOutdated",
+    TIMEOUT = "Timeout",
+    NETWORK_ERROR = "NetworkError",
+    REMOTE_CLOSED = "RemoteClosed",
 }